@@ -75,6 +75,17 @@ impl AudioPreset {
         }
     }
 
+    /// Whether FEC packets are sent with this preset
+    ///
+    /// The lowest-latency presets skip FEC to save bandwidth and jitter
+    /// buffer depth (ADR-006).
+    pub fn fec_enabled(&self) -> bool {
+        !matches!(
+            self,
+            AudioPreset::ZeroLatency | AudioPreset::UltraLowLatency
+        )
+    }
+
     /// Get the preset name as a string (for serialization)
     pub fn name(&self) -> &'static str {
         match self {
@@ -532,6 +543,14 @@ mod tests {
         assert_eq!(config.signaling_server_url, None);
     }
 
+    #[test]
+    fn test_preset_fec_enabled() {
+        assert!(!AudioPreset::ZeroLatency.fec_enabled());
+        assert!(!AudioPreset::UltraLowLatency.fec_enabled());
+        assert!(AudioPreset::Balanced.fec_enabled());
+        assert!(AudioPreset::HighQuality.fec_enabled());
+    }
+
     #[test]
     fn test_config_validation_valid() {
         let config = AppConfig {
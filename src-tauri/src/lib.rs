@@ -36,6 +36,7 @@ pub fn run() {
             signaling::signaling_send_chat,
             signaling::signaling_get_chat_messages,
             signaling::signaling_poll_events,
+            signaling::signaling_update_peer_info,
             audio::audio_list_input_devices,
             audio::audio_list_output_devices,
             audio::audio_set_input_device,
@@ -57,6 +58,8 @@ pub fn run() {
             streaming::streaming_get_master_volume,
             streaming::streaming_set_peer_pan,
             streaming::streaming_get_peer_pan,
+            streaming::streaming_set_peer_mute,
+            streaming::streaming_set_metronome,
             config::config_load,
             config::config_save,
             config::config_get_server_url,
@@ -8,9 +8,14 @@ use std::sync::atomic::{AtomicU32, Ordering};
 use serde::Serialize;
 use tokio::sync::Mutex;
 
-use jamjam::network::{PeerInfo, RoomInfo, SignalingClient, SignalingConnection, SignalingMessage};
+use jamjam::network::{
+    gather_candidates, ConnectionState, PeerInfo, RoomInfo, SignalingClient, SignalingConnection,
+    SignalingMessage, SIGNALING_VERSION,
+};
 use uuid::Uuid;
 
+use crate::streaming::StreamingState;
+
 /// Connection ID counter
 static NEXT_CONN_ID: AtomicU32 = AtomicU32::new(1);
 
@@ -62,6 +67,8 @@ pub struct JoinResult {
     pub peer_id: String,
     pub invite_code: String,
     pub peers: Vec<PeerInfo>,
+    /// Peers must encrypt all audio in this room
+    pub require_encryption: bool,
 }
 
 /// Connect to a signaling server
@@ -121,6 +128,7 @@ pub async fn signaling_join_room(
     room_id: String,
     peer_name: String,
     state: tauri::State<'_, SignalingState>,
+    streaming: tauri::State<'_, StreamingState>,
 ) -> Result<JoinResult, String> {
     let mut connections = state.connections.lock().await;
     let conn = connections
@@ -131,15 +139,21 @@ pub async fn signaling_join_room(
         room_id: room_id.clone(),
         password: None,
         peer_name: peer_name.clone(),
+        signaling_version: SIGNALING_VERSION,
     })
     .await
     .map_err(|e| e.to_string())?;
 
-    match conn.recv().await.map_err(|e| e.to_string())? {
+    let response = conn.recv().await.map_err(|e| e.to_string())?;
+    // The streaming session follows room membership
+    streaming.handle_signaling_message(&response).await;
+
+    match response {
         SignalingMessage::RoomJoined {
             room_id,
             peer_id,
             peers,
+            require_encryption,
         } => {
             // Store room state for chat
             let peer_id_str = peer_id.to_string();
@@ -156,6 +170,7 @@ pub async fn signaling_join_room(
                 peer_id: peer_id_str,
                 invite_code: String::new(), // Not returned when joining existing room
                 peers,
+                require_encryption,
             })
         }
         SignalingMessage::Error { message } => Err(message),
@@ -163,11 +178,54 @@ pub async fn signaling_join_room(
     }
 }
 
+/// Publish the local streaming port to the room
+///
+/// Gathers address candidates for `port` (returned by `streaming_start`) so
+/// the other peers can open their streams to us.
+#[tauri::command]
+pub async fn signaling_update_peer_info(
+    conn_id: u32,
+    port: u16,
+    state: tauri::State<'_, SignalingState>,
+    streaming: tauri::State<'_, StreamingState>,
+) -> Result<(), String> {
+    let mut connections = state.connections.lock().await;
+    let conn = connections
+        .get_mut(&conn_id)
+        .ok_or("Connection not found")?;
+
+    publish_candidates(conn, port, &streaming).await
+}
+
+/// Gather candidates for `port`, publish them with the session's public key
+/// and use them for ICE checks
+///
+/// Called again after a network change so peers restart ICE against the
+/// new addresses.
+async fn publish_candidates(
+    conn: &mut SignalingConnection,
+    port: u16,
+    streaming: &StreamingState,
+) -> Result<(), String> {
+    let candidates = gather_candidates(port).await;
+    streaming.set_local_candidates(candidates.clone()).await;
+
+    conn.send(SignalingMessage::UpdatePeerInfo {
+        public_addr: candidates.first().map(|c| c.address),
+        local_addr: None,
+        candidates,
+        public_key: streaming.public_key().await,
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
 /// Leave the current room
 #[tauri::command]
 pub async fn signaling_leave_room(
     conn_id: u32,
     state: tauri::State<'_, SignalingState>,
+    streaming: tauri::State<'_, StreamingState>,
 ) -> Result<(), String> {
     let mut connections = state.connections.lock().await;
     let conn = connections
@@ -177,6 +235,7 @@ pub async fn signaling_leave_room(
     conn.send(SignalingMessage::LeaveRoom)
         .await
         .map_err(|e| e.to_string())?;
+    streaming.leave_room().await;
 
     Ok(())
 }
@@ -187,8 +246,11 @@ pub async fn signaling_create_room(
     conn_id: u32,
     room_name: String,
     peer_name: String,
+    require_encryption: Option<bool>,
     state: tauri::State<'_, SignalingState>,
+    streaming: tauri::State<'_, StreamingState>,
 ) -> Result<JoinResult, String> {
+    let require_encryption = require_encryption.unwrap_or(false);
     let mut connections = state.connections.lock().await;
     let conn = connections
         .get_mut(&conn_id)
@@ -198,11 +260,16 @@ pub async fn signaling_create_room(
         room_name,
         password: None,
         peer_name: peer_name.clone(),
+        require_encryption,
+        signaling_version: SIGNALING_VERSION,
     })
     .await
     .map_err(|e| e.to_string())?;
 
-    match conn.recv().await.map_err(|e| e.to_string())? {
+    let response = conn.recv().await.map_err(|e| e.to_string())?;
+    streaming.handle_signaling_message(&response).await;
+
+    match response {
         SignalingMessage::RoomCreated {
             room_id,
             peer_id,
@@ -223,6 +290,7 @@ pub async fn signaling_create_room(
                 peer_id: peer_id_str,
                 invite_code,
                 peers: vec![],
+                require_encryption,
             })
         }
         SignalingMessage::Error { message } => Err(message),
@@ -322,6 +390,19 @@ pub enum SignalingEvent {
     PeerUpdated { peer: PeerInfo },
     /// A chat message was received
     ChatMessageReceived { message: ChatMessage },
+    /// The audio stream to a peer changed state (e.g. reconnecting)
+    PeerConnectionState {
+        peer_id: String,
+        state: ConnectionState,
+    },
+    /// Adaptive quality changed the audio sent to a peer
+    PeerQualityChanged {
+        peer_id: String,
+        /// congestion, packet loss, jitter or recovery
+        reason: String,
+        /// Old and new settings with the conditions behind the change
+        description: String,
+    },
 }
 
 /// Poll for signaling events (peer join/leave, chat messages)
@@ -330,6 +411,7 @@ pub enum SignalingEvent {
 pub async fn signaling_poll_events(
     conn_id: u32,
     state: tauri::State<'_, SignalingState>,
+    streaming: tauri::State<'_, StreamingState>,
 ) -> Result<Vec<SignalingEvent>, String> {
     use tokio::time::{timeout, Duration};
 
@@ -346,6 +428,9 @@ pub async fn signaling_poll_events(
     loop {
         match timeout(Duration::from_millis(50), conn.recv()).await {
             Ok(Ok(msg)) => {
+                // Open, re-target and close peer streams as the room changes
+                streaming.handle_signaling_message(&msg).await;
+
                 match msg {
                     SignalingMessage::PeerJoined { peer } => {
                         // Add system message for join
@@ -439,5 +524,29 @@ pub async fn signaling_poll_events(
         }
     }
 
+    // Report stream state changes; a reconnecting peer usually means our
+    // network changed, so re-gather and republish our candidates
+    let mut republish = false;
+    for (peer_id, peer_state) in streaming.take_peer_state_events() {
+        republish |= peer_state == ConnectionState::Reconnecting;
+        events.push(SignalingEvent::PeerConnectionState {
+            peer_id: peer_id.to_string(),
+            state: peer_state,
+        });
+    }
+    for (peer_id, decision) in streaming.take_quality_events() {
+        events.push(SignalingEvent::PeerQualityChanged {
+            peer_id: peer_id.to_string(),
+            reason: decision.reason.to_string(),
+            description: decision.to_string(),
+        });
+    }
+    let port = streaming.local_port().await;
+    if republish && port != 0 {
+        if let Err(e) = publish_candidates(conn, port, &streaming).await {
+            eprintln!("Failed to republish candidates: {}", e);
+        }
+    }
+
     Ok(events)
 }
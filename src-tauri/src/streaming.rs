@@ -1,9 +1,10 @@
 //! Audio streaming IPC commands for Tauri
 //!
 //! Manages P2P audio streaming with a dedicated audio thread to handle
-//! the non-Send+Sync AudioEngine.
+//! the non-Send+Sync AudioEngine. Audio is streamed to every peer in the
+//! room through one `Session` (full mesh).
 
-use std::net::SocketAddr;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::mpsc as std_mpsc;
 use std::sync::{Arc, RwLock};
@@ -11,9 +12,19 @@ use std::thread;
 
 use serde::Serialize;
 use tokio::sync::Mutex;
+use uuid::Uuid;
 
-use jamjam::audio::{AudioConfig, AudioEngine, DeviceId};
-use jamjam::network::{Connection, ConnectionStats, LatencyBreakdown, LocalLatencyInfo};
+use jamjam::audio::{
+    AudioConfig, AudioEngine, CodecConfig, DeviceId, MetronomeConfig, SyncedMetronome, Tempo,
+};
+use jamjam::network::{
+    AddressCandidate, ConnectionState, EncryptionMode, FecConfig, JitterBufferMode,
+    LatencyBreakdown, LocalLatencyInfo, PeerInfo, PeerMix, PeerStats, QualityConfig,
+    QualityDecision, Session, SessionConfig, SignalingMessage, MAX_PEERS_PER_ROOM,
+};
+use jamjam::protocol::ControlMessage;
+
+use crate::config::ConfigState;
 
 /// Audio sample rate used for latency calculations
 /// Target: < 2ms one-way app latency (see CLAUDE.md requirements)
@@ -164,20 +175,32 @@ pub struct StreamingState {
     input_level: Arc<AtomicU32>,
     /// Current output audio level (0-100, for master meter)
     output_level: Arc<AtomicU32>,
-    /// Remote address currently connected to
-    remote_addr: Mutex<Option<String>>,
-    /// Connection statistics (updated by audio thread)
-    stats: Arc<RwLock<Option<ConnectionStats>>>,
+    /// Local UDP port of the last session, reused so the address published
+    /// to the room stays valid when streaming restarts
+    local_port: Mutex<u16>,
+    /// Encryption public key of the current (or last) session, published to
+    /// the room so peers can check the key we offer them
+    public_key: Mutex<Option<[u8; 32]>>,
+    /// Peers in the current room (streamed to when streaming starts)
+    room_peers: Mutex<HashMap<Uuid, PeerInfo>>,
+    /// Per-peer statistics (updated by audio thread)
+    stats: Arc<RwLock<Vec<(PeerInfo, PeerStats)>>>,
     /// Current buffer size (frame_size) for latency calculations
     buffer_size: Mutex<u32>,
     /// Buffer underrun count (audio glitches due to CPU/scheduling)
     underrun_count: Arc<AtomicU64>,
-    /// Peer (received) audio volume (0-200, 100 = unity gain)
-    peer_volume: Arc<AtomicU32>,
+    /// Per-peer volume, pan and mute (kept across restarts)
+    peer_mix: Arc<RwLock<HashMap<Uuid, PeerMix>>>,
     /// Master output volume (0-200, 100 = unity gain)
     master_volume: Arc<AtomicU32>,
-    /// Peer (received) audio pan (-100 = full left, 0 = center, 100 = full right)
-    peer_pan: Arc<std::sync::atomic::AtomicI32>,
+    /// Peer state transitions not yet picked up by the event poller
+    peer_state_events: Arc<RwLock<Vec<(Uuid, ConnectionState)>>>,
+    /// Quality controller decisions not yet picked up by the event poller
+    quality_events: Arc<RwLock<Vec<(Uuid, QualityDecision)>>>,
+    /// Peers that muted their microphone (from their control messages)
+    muted_peers: Arc<RwLock<HashSet<Uuid>>>,
+    /// Metronome shared with the room (None if not streaming)
+    metronome: RwLock<Option<Arc<SyncedMetronome>>>,
 }
 
 impl StreamingState {
@@ -188,13 +211,99 @@ impl StreamingState {
             is_muted: Arc::new(AtomicBool::new(false)),
             input_level: Arc::new(AtomicU32::new(0)),
             output_level: Arc::new(AtomicU32::new(0)),
-            remote_addr: Mutex::new(None),
-            stats: Arc::new(RwLock::new(None)),
+            local_port: Mutex::new(0),
+            public_key: Mutex::new(None),
+            room_peers: Mutex::new(HashMap::new()),
+            stats: Arc::new(RwLock::new(Vec::new())),
             buffer_size: Mutex::new(64), // Default: 64 samples
             underrun_count: Arc::new(AtomicU64::new(0)),
-            peer_volume: Arc::new(AtomicU32::new(100)), // 100 = unity gain
+            peer_mix: Arc::new(RwLock::new(HashMap::new())),
             master_volume: Arc::new(AtomicU32::new(100)), // 100 = unity gain
-            peer_pan: Arc::new(std::sync::atomic::AtomicI32::new(0)), // 0 = center
+            peer_state_events: Arc::new(RwLock::new(Vec::new())),
+            quality_events: Arc::new(RwLock::new(Vec::new())),
+            muted_peers: Arc::new(RwLock::new(HashSet::new())),
+            metronome: RwLock::new(None),
+        }
+    }
+
+    /// Follow room membership from the signaling server
+    ///
+    /// Remembers the room's peers for the next `streaming_start` and forwards
+    /// joins, leaves and updates to the running session.
+    pub async fn handle_signaling_message(&self, message: &SignalingMessage) {
+        {
+            let mut room_peers = self.room_peers.lock().await;
+            match message {
+                SignalingMessage::RoomJoined { peers, .. } => {
+                    room_peers.clear();
+                    room_peers.extend(peers.iter().map(|p| (p.id, p.clone())));
+                }
+                SignalingMessage::RoomCreated { .. } => room_peers.clear(),
+                SignalingMessage::PeerJoined { peer } | SignalingMessage::PeerUpdated { peer } => {
+                    room_peers.insert(peer.id, peer.clone());
+                }
+                SignalingMessage::PeerLeft { peer_id } => {
+                    room_peers.remove(peer_id);
+                    if let Ok(mut mix) = self.peer_mix.write() {
+                        mix.remove(peer_id);
+                    }
+                }
+                _ => return,
+            }
+        }
+
+        if self.is_active.load(Ordering::SeqCst) {
+            let tx = self.cmd_tx.lock().await;
+            if let Some(ref sender) = *tx {
+                let _ = sender.send(StreamingCommand::Signaling(message.clone()));
+            }
+        }
+    }
+
+    /// Use re-gathered local candidates in the running session's ICE checks
+    pub async fn set_local_candidates(&self, candidates: Vec<AddressCandidate>) {
+        if self.is_active.load(Ordering::SeqCst) {
+            let tx = self.cmd_tx.lock().await;
+            if let Some(ref sender) = *tx {
+                let _ = sender.send(StreamingCommand::SetLocalCandidates(candidates));
+            }
+        }
+    }
+
+    /// Take the peer state transitions since the last call
+    pub fn take_peer_state_events(&self) -> Vec<(Uuid, ConnectionState)> {
+        self.peer_state_events
+            .write()
+            .map(|mut events| std::mem::take(&mut *events))
+            .unwrap_or_default()
+    }
+
+    /// Take the quality controller decisions since the last call
+    pub fn take_quality_events(&self) -> Vec<(Uuid, QualityDecision)> {
+        self.quality_events
+            .write()
+            .map(|mut events| std::mem::take(&mut *events))
+            .unwrap_or_default()
+    }
+
+    /// Local UDP port of the current (or last) session
+    pub async fn local_port(&self) -> u16 {
+        *self.local_port.lock().await
+    }
+
+    /// Encryption public key of the current (or last) session
+    pub async fn public_key(&self) -> Option<[u8; 32]> {
+        *self.public_key.lock().await
+    }
+
+    /// Forget the room's peers and their mix settings after leaving
+    pub async fn leave_room(&self) {
+        self.room_peers.lock().await.clear();
+        if let Ok(mut mix) = self.peer_mix.write() {
+            mix.clear();
+        }
+        if let Ok(mut muted) = self.muted_peers.write() {
+            muted.clear();
         }
     }
 
@@ -217,9 +326,12 @@ enum StreamingCommand {
     SetInputDevice(Option<String>),
     SetOutputDevice(Option<String>),
     SetMute(bool),
-    SetPeerVolume(f32),
+    SetPeerMix(Uuid, PeerMix),
     SetMasterVolume(f32),
-    SetPeerPan(i32),
+    /// Room membership change to apply to the session
+    Signaling(SignalingMessage),
+    /// Local candidates re-gathered after a network change
+    SetLocalCandidates(Vec<AddressCandidate>),
 }
 
 /// Network statistics for IPC
@@ -229,8 +341,14 @@ pub struct NetworkStats {
     pub rtt_ms: f32,
     /// Jitter in milliseconds
     pub jitter_ms: f32,
-    /// Packet loss percentage (0-100)
+    /// Packet loss percentage over recent packets (0-100)
     pub packet_loss_percent: f32,
+    /// Packet loss percentage since connecting (0-100)
+    pub total_packet_loss_percent: f32,
+    /// Audio packets lost in transit
+    pub packets_lost: u64,
+    /// Longest run of consecutive lost packets
+    pub max_burst_length: u32,
     /// Connection uptime in seconds
     pub uptime_seconds: u64,
     /// Total packets sent
@@ -241,6 +359,39 @@ pub struct NetworkStats {
     pub bytes_sent: u64,
     /// Total bytes received
     pub bytes_received: u64,
+    /// Whether audio is end-to-end encrypted
+    pub encrypted: bool,
+    /// Lost packets recovered by FEC
+    pub packets_recovered: u64,
+}
+
+/// Per-peer streaming status for IPC
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerStreamStatus {
+    pub peer_id: String,
+    pub name: String,
+    /// Peer volume (0-200, 100 = unity gain)
+    pub volume: u32,
+    /// Peer pan (-100 = full left, 0 = center, 100 = full right)
+    pub pan: i32,
+    /// Whether the peer is muted in the mix
+    pub muted: bool,
+    /// Round-trip time in milliseconds
+    pub rtt_ms: f32,
+    /// Packet loss percentage over recent packets (0-100)
+    pub packet_loss_percent: f32,
+    /// Whether audio is end-to-end encrypted
+    pub encrypted: bool,
+    /// Stream state (connecting, connected, reconnecting or failed)
+    pub state: ConnectionState,
+    /// Whether the peer muted their microphone
+    pub remote_muted: bool,
+    /// One-way network delay in milliseconds (half the best round trip)
+    pub one_way_ms: Option<f32>,
+    /// Peer clock minus local clock in milliseconds
+    pub clock_offset_ms: Option<f32>,
+    /// Settings chosen by the quality controller (None if adaptive quality is off)
+    pub quality: Option<String>,
 }
 
 /// Audio quality metrics for IPC
@@ -276,46 +427,83 @@ pub struct DetailedLatency {
     pub roundtrip_total_ms: f32,
 }
 
+/// Shared metronome status for IPC
+#[derive(Debug, Clone, Serialize)]
+pub struct MetronomeStatus {
+    /// Whether the click is running
+    pub running: bool,
+    pub bpm: u32,
+    pub beats_per_measure: u32,
+    /// Current beat within the measure (0-indexed)
+    pub current_beat: u32,
+    /// Whether we set the tempo (otherwise we follow a peer)
+    pub is_owner: bool,
+}
+
 /// Streaming status for IPC
 #[derive(Debug, Clone, Serialize)]
 pub struct StreamingStatus {
     pub is_active: bool,
-    pub remote_addr: Option<String>,
+    /// Local UDP port peers reach us at
+    pub local_port: Option<u16>,
+    /// Peers in the session
+    pub peers: Vec<PeerStreamStatus>,
     /// Whether microphone is muted
     pub is_muted: bool,
     /// Current input audio level (0-100)
     pub input_level: u32,
     /// Current output audio level (0-100, for master meter)
     pub output_level: u32,
-    /// Network statistics
+    /// Network statistics (worst peer for RTT, jitter and loss; totals otherwise)
     pub network: Option<NetworkStats>,
     /// Detailed latency breakdown
     pub latency: Option<DetailedLatency>,
     /// Audio quality metrics
     pub audio_quality: Option<AudioQuality>,
+    /// Shared metronome (None if not streaming)
+    pub metronome: Option<MetronomeStatus>,
 }
 
-/// Start audio streaming to a remote peer
+/// Start audio streaming to every peer in the current room
+///
+/// Returns the local UDP port, to be published to the room with
+/// `signaling_update_peer_info`.
 #[tauri::command]
 pub async fn streaming_start(
-    remote_addr: String,
     input_device_id: Option<String>,
     output_device_id: Option<String>,
     buffer_size: u32,
+    require_encryption: Option<bool>,
+    adaptive_quality: Option<bool>,
     state: tauri::State<'_, StreamingState>,
-) -> Result<(), String> {
+    config_state: tauri::State<'_, ConfigState>,
+) -> Result<u16, String> {
+    let encryption = if require_encryption.unwrap_or(false) {
+        EncryptionMode::Required
+    } else {
+        EncryptionMode::Preferred
+    };
+    let fec = if config_state.get()?.preset.fec_enabled() {
+        FecConfig::default()
+    } else {
+        FecConfig::disabled()
+    };
+    let quality = adaptive_quality
+        .unwrap_or(false)
+        .then(QualityConfig::default);
+
     // Check if already streaming
     if state.is_active.load(Ordering::SeqCst) {
         return Err("Streaming already active".to_string());
     }
 
-    // Parse remote address
-    let addr: SocketAddr = remote_addr
-        .parse()
-        .map_err(|e| format!("Invalid address: {}", e))?;
+    let room_peers: Vec<PeerInfo> = state.room_peers.lock().await.values().cloned().collect();
+    let local_port = *state.local_port.lock().await;
 
     // Create channel for commands to audio thread
     let (cmd_tx, cmd_rx) = std_mpsc::channel::<StreamingCommand>();
+    // The audio thread reports the session port and key once it is running
+    let (ready_tx, ready_rx) = tokio::sync::oneshot::channel::<Result<(u16, [u8; 32]), String>>();
 
     // Store command sender
     {
@@ -323,12 +511,6 @@ pub async fn streaming_start(
         *tx = Some(cmd_tx);
     }
 
-    // Store remote address
-    {
-        let mut addr_lock = state.remote_addr.lock().await;
-        *addr_lock = Some(remote_addr.clone());
-    }
-
     // Store buffer size for latency display
     {
         let mut bs = state.buffer_size.lock().await;
@@ -341,19 +523,25 @@ pub async fn streaming_start(
     let output_level = state.output_level.clone();
     let shared_stats = state.stats.clone();
     let underrun_count = state.underrun_count.clone();
-    let peer_volume = state.peer_volume.clone();
+    let peer_mix = state.peer_mix.clone();
     let master_volume = state.master_volume.clone();
-    let peer_pan = state.peer_pan.clone();
+    let peer_state_events = state.peer_state_events.clone();
+    let quality_events = state.quality_events.clone();
+    let muted_peers = state.muted_peers.clone();
+    // A fresh metronome per session follows whoever owns the room's tempo
+    let metronome = Arc::new(SyncedMetronome::new(
+        MetronomeConfig::default(),
+        AUDIO_SAMPLE_RATE,
+    ));
+    if let Ok(mut current) = state.metronome.write() {
+        *current = Some(metronome.clone());
+    }
 
     // Reset state on new connection
     state.is_muted.store(false, Ordering::SeqCst);
     state.input_level.store(0, Ordering::SeqCst);
     state.output_level.store(0, Ordering::SeqCst);
     state.underrun_count.store(0, Ordering::SeqCst);
-    // Reset volumes to unity gain and pan to center
-    state.peer_volume.store(100, Ordering::SeqCst);
-    state.master_volume.store(100, Ordering::SeqCst);
-    state.peer_pan.store(0, Ordering::SeqCst);
 
     // Mark as active BEFORE spawning thread to avoid race condition
     state.is_active.store(true, Ordering::SeqCst);
@@ -370,35 +558,64 @@ pub async fn streaming_start(
             .expect("Failed to create tokio runtime");
 
         rt.block_on(async move {
+            let mut ready_tx = Some(ready_tx);
             if let Err(e) = run_audio_streaming(
-                addr,
+                local_port,
+                room_peers,
                 input_device_id,
                 output_device_id,
                 buffer_size,
+                encryption,
+                fec,
+                quality,
                 cmd_rx,
+                &mut ready_tx,
                 &is_active,
                 &is_muted,
                 &input_level,
                 &output_level,
                 &shared_stats,
                 &underrun_count,
-                &peer_volume,
+                &peer_mix,
                 &master_volume,
-                &peer_pan,
+                peer_state_events,
+                quality_events,
+                muted_peers,
+                metronome,
             )
             .await
             {
                 eprintln!("Audio streaming error: {}", e);
+                if let Some(ready_tx) = ready_tx.take() {
+                    let _ = ready_tx.send(Err(e));
+                }
             }
             is_active.store(false, Ordering::SeqCst);
             // Clear stats on disconnect
             if let Ok(mut stats) = shared_stats.write() {
-                *stats = None;
+                stats.clear();
             }
         });
     });
 
-    Ok(())
+    let (port, public_key) = match ready_rx.await {
+        Ok(Ok(ready)) => ready,
+        Ok(Err(e)) => {
+            state.is_active.store(false, Ordering::SeqCst);
+            *state.cmd_tx.lock().await = None;
+            return Err(e);
+        }
+        Err(_) => {
+            state.is_active.store(false, Ordering::SeqCst);
+            *state.cmd_tx.lock().await = None;
+            return Err("Audio thread exited".to_string());
+        }
+    };
+
+    *state.local_port.lock().await = port;
+    *state.public_key.lock().await = Some(public_key);
+
+    Ok(port)
 }
 
 /// Stop audio streaming
@@ -421,13 +638,12 @@ pub async fn streaming_stop(state: tauri::State<'_, StreamingState>) -> Result<(
         let mut tx = state.cmd_tx.lock().await;
         *tx = None;
     }
-    {
-        let mut addr = state.remote_addr.lock().await;
-        *addr = None;
+    if let Ok(mut metronome) = state.metronome.write() {
+        *metronome = None;
     }
     {
         if let Ok(mut stats) = state.stats.write() {
-            *stats = None;
+            stats.clear();
         }
     }
 
@@ -448,25 +664,19 @@ pub async fn streaming_status(
     let is_muted = state.is_muted.load(Ordering::SeqCst);
     let input_level = state.input_level.load(Ordering::SeqCst);
     let output_level = state.output_level.load(Ordering::SeqCst);
-    let remote_addr = state.remote_addr.lock().await.clone();
-    let stats = state.stats.read().ok().and_then(|s| s.clone());
+    let local_port = if is_active {
+        Some(*state.local_port.lock().await)
+    } else {
+        None
+    };
+    let peer_stats = state.stats.read().map(|s| s.clone()).unwrap_or_default();
+    let peers = peer_stream_status(&peer_stats, &state.peer_mix, &state.muted_peers);
 
-    let (network, latency) = if let Some(ref s) = stats {
+    let (network, latency) = if let Some(ref s) = aggregate_network_stats(&peer_stats) {
         let local_info = state.local_latency_info();
         let breakdown = LatencyBreakdown::calculate(&local_info, None, s.rtt_ms, s.jitter_ms);
         let frame_size = state.buffer_size.lock().await;
 
-        let network = NetworkStats {
-            rtt_ms: s.rtt_ms,
-            jitter_ms: s.jitter_ms,
-            packet_loss_percent: s.packet_loss_rate * 100.0,
-            uptime_seconds: s.uptime_seconds,
-            packets_sent: s.packets_sent,
-            packets_received: s.packets_received,
-            bytes_sent: s.bytes_sent,
-            bytes_received: s.bytes_received,
-        };
-
         let upstream = vec![
             LatencyComponent {
                 name: "Capture buffer".to_string(),
@@ -519,7 +729,7 @@ pub async fn streaming_status(
             roundtrip_total_ms: breakdown.roundtrip_total_ms,
         };
 
-        (Some(network), Some(latency))
+        (Some(s.clone()), Some(latency))
     } else {
         (None, None)
     };
@@ -533,18 +743,99 @@ pub async fn streaming_status(
         None
     };
 
+    let metronome = state.metronome.read().ok().and_then(|m| m.clone());
+    let metronome = metronome.map(|metronome| {
+        let tempo = metronome.tempo();
+        MetronomeStatus {
+            running: metronome.is_running(),
+            bpm: tempo.bpm,
+            beats_per_measure: tempo.beats_per_measure,
+            current_beat: metronome.state().current_beat,
+            is_owner: metronome.is_owner(),
+        }
+    });
+
     Ok(StreamingStatus {
         is_active,
-        remote_addr,
+        local_port,
+        peers,
         is_muted,
         input_level,
         output_level,
         network,
         latency,
         audio_quality,
+        metronome,
     })
 }
 
+/// Combine per-peer statistics into the session-wide network view
+///
+/// RTT, jitter and loss come from the worst peer, since that peer limits
+/// the jam; counters are summed.
+fn aggregate_network_stats(peers: &[(PeerInfo, PeerStats)]) -> Option<NetworkStats> {
+    if peers.is_empty() {
+        return None;
+    }
+    let worst = |f: fn(&PeerStats) -> f32| peers.iter().map(|(_, s)| f(s)).fold(0.0, f32::max);
+    let total = |f: fn(&PeerStats) -> u64| peers.iter().map(|(_, s)| f(s)).sum();
+
+    Some(NetworkStats {
+        rtt_ms: worst(|s| s.rtt_ms),
+        jitter_ms: worst(|s| s.jitter_ms),
+        packet_loss_percent: worst(|s| s.loss.recent_loss_rate) * 100.0,
+        total_packet_loss_percent: worst(|s| s.loss.loss_rate) * 100.0,
+        packets_lost: total(|s| s.loss.packets_lost),
+        max_burst_length: peers
+            .iter()
+            .map(|(_, s)| s.loss.max_burst_length)
+            .max()
+            .unwrap_or(0),
+        uptime_seconds: peers
+            .iter()
+            .map(|(_, s)| s.uptime_seconds)
+            .max()
+            .unwrap_or(0),
+        packets_sent: total(|s| s.packets_sent),
+        packets_received: total(|s| s.packets_received as u64),
+        bytes_sent: total(|s| s.bytes_sent),
+        bytes_received: total(|s| s.bytes_received),
+        encrypted: peers.iter().all(|(_, s)| s.encrypted),
+        packets_recovered: total(|s| s.packets_recovered),
+    })
+}
+
+/// Per-peer status with the mix settings chosen in the UI
+fn peer_stream_status(
+    peers: &[(PeerInfo, PeerStats)],
+    peer_mix: &RwLock<HashMap<Uuid, PeerMix>>,
+    muted_peers: &RwLock<HashSet<Uuid>>,
+) -> Vec<PeerStreamStatus> {
+    let mix = peer_mix.read().map(|m| m.clone()).unwrap_or_default();
+    let muted_peers = muted_peers.read().map(|m| m.clone()).unwrap_or_default();
+    peers
+        .iter()
+        .map(|(info, stats)| {
+            let mix = mix.get(&info.id).copied().unwrap_or_default();
+            PeerStreamStatus {
+                peer_id: info.id.to_string(),
+                name: info.name.clone(),
+                volume: (mix.volume * 100.0).round() as u32,
+                pan: (mix.pan * 100.0).round() as i32,
+                muted: mix.muted,
+                rtt_ms: stats.rtt_ms,
+                packet_loss_percent: stats.loss.recent_loss_rate * 100.0,
+                encrypted: stats.encrypted,
+                state: stats.state,
+                remote_muted: muted_peers.contains(&info.id),
+                one_way_ms: stats.clock.map(|c| c.one_way_ms()),
+                clock_offset_ms: stats.clock.map(|c| c.offset_us as f32 / 1000.0),
+                quality: stats.quality.map(|q| q.to_string()),
+            }
+        })
+        .collect()
+}
+
 /// Set input device during streaming
 #[tauri::command]
 pub async fn streaming_set_input_device(
@@ -619,33 +910,57 @@ pub async fn streaming_get_input_level(
     Ok(state.input_level.load(Ordering::SeqCst))
 }
 
-/// Set peer (received audio) volume
-/// Volume is 0-200 where 100 = unity gain (1.0x), 200 = 2.0x
-#[tauri::command]
-pub async fn streaming_set_peer_volume(
-    volume: u32,
-    state: tauri::State<'_, StreamingState>,
+/// Update a peer's mix settings and apply them to the running session
+async fn update_peer_mix(
+    state: &StreamingState,
+    peer_id: &str,
+    update: impl FnOnce(&mut PeerMix),
 ) -> Result<(), String> {
-    let clamped = volume.min(200);
-    state.peer_volume.store(clamped, Ordering::SeqCst);
+    let peer_id = Uuid::parse_str(peer_id).map_err(|e| format!("Invalid peer ID: {}", e))?;
+    let mix = {
+        let mut peer_mix = state.peer_mix.write().map_err(|e| e.to_string())?;
+        let mix = peer_mix.entry(peer_id).or_default();
+        update(mix);
+        *mix
+    };
 
     // If streaming, also send command to audio thread
     if state.is_active.load(Ordering::SeqCst) {
         let tx = state.cmd_tx.lock().await;
         if let Some(ref sender) = *tx {
-            let _ = sender.send(StreamingCommand::SetPeerVolume(clamped as f32 / 100.0));
+            let _ = sender.send(StreamingCommand::SetPeerMix(peer_id, mix));
         }
     }
 
     Ok(())
 }
 
-/// Get peer volume (0-200, 100 = unity)
+/// Get a peer's mix settings (defaults if never changed)
+fn stored_peer_mix(state: &StreamingState, peer_id: &str) -> Result<PeerMix, String> {
+    let peer_id = Uuid::parse_str(peer_id).map_err(|e| format!("Invalid peer ID: {}", e))?;
+    let peer_mix = state.peer_mix.read().map_err(|e| e.to_string())?;
+    Ok(peer_mix.get(&peer_id).copied().unwrap_or_default())
+}
+
+/// Set a peer's (received audio) volume
+/// Volume is 0-200 where 100 = unity gain (1.0x), 200 = 2.0x
+#[tauri::command]
+pub async fn streaming_set_peer_volume(
+    peer_id: String,
+    volume: u32,
+    state: tauri::State<'_, StreamingState>,
+) -> Result<(), String> {
+    let clamped = volume.min(200);
+    update_peer_mix(&state, &peer_id, |mix| mix.volume = clamped as f32 / 100.0).await
+}
+
+/// Get a peer's volume (0-200, 100 = unity)
 #[tauri::command]
 pub async fn streaming_get_peer_volume(
+    peer_id: String,
     state: tauri::State<'_, StreamingState>,
 ) -> Result<u32, String> {
-    Ok(state.peer_volume.load(Ordering::SeqCst))
+    Ok((stored_peer_mix(&state, &peer_id)?.volume * 100.0).round() as u32)
 }
 
 /// Set master output volume
@@ -677,51 +992,100 @@ pub async fn streaming_get_master_volume(
     Ok(state.master_volume.load(Ordering::SeqCst))
 }
 
-/// Set peer (received audio) pan
+/// Set a peer's (received audio) pan
 /// Pan is -100 (full left) to 100 (full right), 0 = center
 #[tauri::command]
 pub async fn streaming_set_peer_pan(
+    peer_id: String,
     pan: i32,
     state: tauri::State<'_, StreamingState>,
 ) -> Result<(), String> {
     let clamped = pan.clamp(-100, 100);
-    state.peer_pan.store(clamped, Ordering::SeqCst);
-
-    // If streaming, also send command to audio thread
-    if state.is_active.load(Ordering::SeqCst) {
-        let tx = state.cmd_tx.lock().await;
-        if let Some(ref sender) = *tx {
-            let _ = sender.send(StreamingCommand::SetPeerPan(clamped));
-        }
-    }
-
-    Ok(())
+    update_peer_mix(&state, &peer_id, |mix| mix.pan = clamped as f32 / 100.0).await
 }
 
-/// Get peer pan (-100 to 100, 0 = center)
+/// Get a peer's pan (-100 to 100, 0 = center)
 #[tauri::command]
 pub async fn streaming_get_peer_pan(
+    peer_id: String,
     state: tauri::State<'_, StreamingState>,
 ) -> Result<i32, String> {
-    Ok(state.peer_pan.load(Ordering::SeqCst))
+    Ok((stored_peer_mix(&state, &peer_id)?.pan * 100.0).round() as i32)
+}
+
+/// Mute or unmute a peer in the mix
+#[tauri::command]
+pub async fn streaming_set_peer_mute(
+    peer_id: String,
+    muted: bool,
+    state: tauri::State<'_, StreamingState>,
+) -> Result<(), String> {
+    update_peer_mix(&state, &peer_id, |mix| mix.muted = muted).await
+}
+
+/// Start, retempo or stop the metronome for everyone in the room
+///
+/// A running metronome changes tempo and time signature on the next bar.
+#[tauri::command]
+pub async fn streaming_set_metronome(
+    enabled: bool,
+    bpm: u32,
+    beats_per_measure: u32,
+    state: tauri::State<'_, StreamingState>,
+) -> Result<(), String> {
+    let metronome = state
+        .metronome
+        .read()
+        .map_err(|e| e.to_string())?
+        .clone()
+        .ok_or_else(|| "Streaming not active".to_string())?;
+
+    let tempo = Tempo {
+        bpm,
+        beats_per_measure,
+        beat_value: 4,
+    };
+    match (enabled, metronome.is_running()) {
+        (false, _) => metronome.stop(),
+        (true, false) => metronome.start(tempo),
+        (true, true) => metronome.set_tempo(tempo),
+    }
+    Ok(())
+}
+
+/// Apply stored mix settings to a peer in the session
+async fn apply_peer_mix(session: &Session, peer_id: Uuid, mix: PeerMix) {
+    // The peer may not be in the session yet (no address); the settings are
+    // applied again when it joins
+    let _ = session.set_peer_volume(peer_id, mix.volume).await;
+    let _ = session.set_peer_pan(peer_id, mix.pan).await;
+    let _ = session.set_peer_muted(peer_id, mix.muted).await;
 }
 
 /// Run audio streaming in the audio thread
 async fn run_audio_streaming(
-    remote_addr: SocketAddr,
+    local_port: u16,
+    room_peers: Vec<PeerInfo>,
     input_device_id: Option<String>,
     output_device_id: Option<String>,
     buffer_size: u32,
+    encryption: EncryptionMode,
+    fec: FecConfig,
+    quality: Option<QualityConfig>,
     cmd_rx: std_mpsc::Receiver<StreamingCommand>,
+    ready_tx: &mut Option<tokio::sync::oneshot::Sender<Result<(u16, [u8; 32]), String>>>,
     is_active: &AtomicBool,
     is_muted: &AtomicBool,
     input_level: &AtomicU32,
     output_level: &AtomicU32,
-    shared_stats: &RwLock<Option<ConnectionStats>>,
+    shared_stats: &RwLock<Vec<(PeerInfo, PeerStats)>>,
     underrun_count: &AtomicU64,
-    peer_volume: &AtomicU32,
+    peer_mix: &RwLock<HashMap<Uuid, PeerMix>>,
     master_volume: &AtomicU32,
-    peer_pan: &std::sync::atomic::AtomicI32,
+    peer_state_events: Arc<RwLock<Vec<(Uuid, ConnectionState)>>>,
+    quality_events: Arc<RwLock<Vec<(Uuid, QualityDecision)>>>,
+    muted_peers: Arc<RwLock<HashSet<Uuid>>>,
+    metronome: Arc<SyncedMetronome>,
 ) -> Result<(), String> {
     // Capture config: mono (for network transmission)
     let capture_config = AudioConfig {
@@ -737,10 +1101,30 @@ async fn run_audio_streaming(
         frame_size: buffer_size,
     };
 
-    // Create connection
-    let mut connection = Connection::new("0.0.0.0:0")
-        .await
-        .map_err(|e| format!("Failed to create connection: {}", e))?;
+    // One session socket for every peer in the room. The mixer pans each
+    // peer into a stereo mix; no jitter buffering, as before the mesh.
+    let session_config = |local_port: u16| SessionConfig {
+        local_port,
+        max_peers: MAX_PEERS_PER_ROOM,
+        encryption,
+        codec: CodecConfig {
+            sample_rate: AUDIO_SAMPLE_RATE,
+            channels: 1,
+            frame_size: buffer_size,
+            ..Default::default()
+        },
+        jitter_buffer: JitterBufferMode::Passthrough,
+        mix_channels: 2,
+        fec,
+        quality: quality.clone(),
+        ..Default::default()
+    };
+    let session = match Session::new(session_config(local_port)).await {
+        // The previous port may have been taken in the meantime
+        Err(_) if local_port != 0 => Session::new(session_config(0)).await,
+        result => result,
+    };
+    let mut session = session.map_err(|e| format!("Failed to create session: {}", e))?;
 
     // Create separate audio engines for capture (mono) and playback (stereo)
     let mut capture_engine = AudioEngine::new(capture_config);
@@ -782,28 +1166,65 @@ async fn run_audio_streaming(
         .start_playback(output_id.as_ref())
         .map_err(|e| format!("Failed to start playback: {}", e))?;
 
-    // Set up audio receive callback BEFORE connect
-    connection.set_audio_callback(move |data, _timestamp| {
-        // Convert bytes back to f32 samples
-        let samples: Vec<f32> = data
-            .chunks_exact(4)
-            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-            .collect();
-
-        let _ = tx_playback.try_send(samples);
+    // Mixed stereo audio from all peers, with per-peer volume and pan applied
+    session.set_mixed_audio_callback(move |samples, _timestamp| {
+        let _ = tx_playback.try_send(samples.to_vec());
     });
+    // Reconnects are reported to the UI through the signaling event poller
+    session.set_peer_state_callback(move |peer_id, peer_state| {
+        if let Ok(mut events) = peer_state_events.write() {
+            events.push((peer_id, peer_state));
+        }
+    });
+    // So are quality changes, with the conditions that caused them
+    session.set_quality_callback(move |peer_id, decision| {
+        if let Ok(mut events) = quality_events.write() {
+            events.push((peer_id, decision));
+        }
+    });
+    // Peers tell us over the control channel when they mute themselves
+    session.set_control_callback(move |peer_id, message| {
+        if let ControlMessage::MuteState { muted } = message {
+            if let Ok(mut muted_peers) = muted_peers.write() {
+                if muted {
+                    muted_peers.insert(peer_id);
+                } else {
+                    muted_peers.remove(&peer_id);
+                }
+            }
+        }
+    });
+    // The click is mixed into the stereo output
+    session.set_metronome(metronome);
+    session.start();
+
+    // Stream to everyone already in the room
+    for peer in room_peers {
+        let peer_id = peer.id;
+        if let Err(e) = session
+            .handle_signaling_message(&SignalingMessage::PeerJoined { peer })
+            .await
+        {
+            eprintln!("Failed to add peer {}: {}", peer_id, e);
+        }
+    }
+    let stored_mix = peer_mix.read().map(|m| m.clone()).unwrap_or_default();
+    for (peer_id, mix) in stored_mix {
+        apply_peer_mix(&session, peer_id, mix).await;
+    }
 
-    // Connect to remote peer
-    connection
-        .connect(remote_addr)
-        .await
-        .map_err(|e| format!("Failed to connect: {}", e))?;
-
-    println!("Connected to {}. Streaming active.", remote_addr);
+    let local_port = session.local_addr().port();
+    if let Some(ready_tx) = ready_tx.take() {
+        let _ = ready_tx.send(Ok((local_port, session.public_key())));
+    }
+    println!(
+        "Session listening on port {}. Streaming active.",
+        local_port
+    );
 
-    // Wrap connection for shared access
-    let connection_arc = Arc::new(tokio::sync::Mutex::new(connection));
-    let connection_for_send = connection_arc.clone();
+    // Share the session with the send task
+    let session = Arc::new(session);
+    let session_for_send = session.clone();
 
     // Create muted state flag for send task
     let is_muted_for_send = Arc::new(AtomicBool::new(is_muted.load(Ordering::SeqCst)));
@@ -816,11 +1237,8 @@ async fn run_audio_streaming(
             if is_muted_send_ref.load(Ordering::SeqCst) {
                 continue;
             }
-            let conn = connection_for_send.lock().await;
-            if conn.is_connected() {
-                if let Err(e) = conn.send_audio(&samples, timestamp).await {
-                    eprintln!("Failed to send audio: {}", e);
-                }
+            if let Err(e) = session_for_send.broadcast_audio(&samples, timestamp).await {
+                eprintln!("Failed to send audio: {}", e);
             }
         }
     });
@@ -857,18 +1275,45 @@ async fn run_audio_streaming(
             Ok(StreamingCommand::SetMute(muted)) => {
                 println!("Setting mute state to: {}", muted);
                 is_muted_for_send.store(muted, Ordering::SeqCst);
+                session
+                    .broadcast_control(ControlMessage::MuteState { muted })
+                    .await;
             }
-            Ok(StreamingCommand::SetPeerVolume(vol)) => {
-                println!("Setting peer volume to: {}", vol);
-                peer_volume.store((vol * 100.0) as u32, Ordering::SeqCst);
+            Ok(StreamingCommand::SetPeerMix(peer_id, mix)) => {
+                println!("Setting mix of peer {} to: {:?}", peer_id, mix);
+                apply_peer_mix(&session, peer_id, mix).await;
             }
             Ok(StreamingCommand::SetMasterVolume(vol)) => {
                 println!("Setting master volume to: {}", vol);
                 master_volume.store((vol * 100.0) as u32, Ordering::SeqCst);
             }
-            Ok(StreamingCommand::SetPeerPan(pan)) => {
-                println!("Setting peer pan to: {}", pan);
-                peer_pan.store(pan, Ordering::SeqCst);
+            Ok(StreamingCommand::Signaling(message)) => {
+                if let Err(e) = session.handle_signaling_message(&message).await {
+                    eprintln!("Failed to update session peers: {}", e);
+                }
+                // Re-apply mix settings made before the peer was reachable
+                if let SignalingMessage::PeerJoined { peer }
+                | SignalingMessage::PeerUpdated { peer } = &message
+                {
+                    let mix = peer_mix.read().ok().and_then(|m| m.get(&peer.id).copied());
+                    if let Some(mix) = mix {
+                        apply_peer_mix(&session, peer.id, mix).await;
+                    }
+                }
+                // Let a newcomer know we are muted (ignored until the peer
+                // has an address and is in the session)
+                if let SignalingMessage::PeerJoined { peer }
+                | SignalingMessage::PeerUpdated { peer } = &message
+                {
+                    if is_muted_for_send.load(Ordering::SeqCst) {
+                        let muted = ControlMessage::MuteState { muted: true };
+                        let _ = session.send_control(peer.id, muted).await;
+                    }
+                }
+            }
+            Ok(StreamingCommand::SetLocalCandidates(candidates)) => {
+                println!("Using {} re-gathered local candidates", candidates.len());
+                session.set_local_candidates(candidates);
             }
             Err(std_mpsc::TryRecvError::Disconnected) => {
                 println!("Command channel disconnected");
@@ -888,13 +1333,16 @@ async fn run_audio_streaming(
         stats_update_counter += 1;
         if stats_update_counter >= 10 {
             stats_update_counter = 0;
-            // Update connection stats
-            if let Ok(conn) = connection_arc.try_lock() {
-                let conn_stats = conn.stats();
-                if let Ok(mut stats) = shared_stats.write() {
-                    *stats = Some(conn_stats);
+            // Update per-peer stats
+            let mut peer_stats = Vec::new();
+            for peer in session.peers().await {
+                if let Some(stats) = session.peer_stats(peer.id).await {
+                    peer_stats.push((peer, stats));
                 }
             }
+            if let Ok(mut stats) = shared_stats.write() {
+                *stats = peer_stats;
+            }
             // Update shared input level from capture callback
             input_level.store(
                 input_level_for_capture.load(Ordering::SeqCst),
@@ -906,38 +1354,22 @@ async fn run_audio_streaming(
 
         // Process received audio with timeout
         tokio::select! {
-            Some(samples) = rx_playback.recv() => {
-                // Apply peer volume and master volume
-                let peer_vol = peer_volume.load(Ordering::Relaxed) as f32 / 100.0;
+            Some(mut samples) = rx_playback.recv() => {
+                // Peer volume and pan are applied by the session mixer
                 let master_vol = master_volume.load(Ordering::Relaxed) as f32 / 100.0;
-                let combined_vol = peer_vol * master_vol;
-
-                // Get pan value and calculate constant power pan gains
-                // Pan: -100 = full left, 0 = center, 100 = full right
-                let pan = peer_pan.load(Ordering::Relaxed);
-                let pan_normalized = (pan + 100) as f32 / 200.0; // 0.0 to 1.0
-                // Constant power panning: at center (0.5), both channels get ~0.707
-                let angle = pan_normalized * std::f32::consts::FRAC_PI_2;
-                let left_gain = angle.cos();
-                let right_gain = angle.sin();
-
-                // Convert mono to stereo with pan and volume applied
-                let mut stereo_samples = Vec::with_capacity(samples.len() * 2);
-                for mono_sample in samples.iter() {
-                    let scaled = mono_sample * combined_vol;
-                    stereo_samples.push(scaled * left_gain);  // Left channel
-                    stereo_samples.push(scaled * right_gain); // Right channel
+                for sample in samples.iter_mut() {
+                    *sample *= master_vol;
                 }
 
                 // Calculate RMS for output level metering (from stereo mix)
-                if !stereo_samples.is_empty() {
-                    let sum_sq: f32 = stereo_samples.iter().map(|s| s * s).sum();
-                    let rms = (sum_sq / stereo_samples.len() as f32).sqrt();
+                if !samples.is_empty() {
+                    let sum_sq: f32 = samples.iter().map(|s| s * s).sum();
+                    let rms = (sum_sq / samples.len() as f32).sqrt();
                     let level = ((rms * 100.0).min(100.0)).round() as u32;
                     output_level.store(level, Ordering::Relaxed);
                 }
 
-                playback_engine.enqueue_playback(&stereo_samples);
+                playback_engine.enqueue_playback(&samples);
             }
             _ = tokio::time::sleep(tokio::time::Duration::from_millis(10)) => {
                 // Timeout, check commands again
@@ -947,11 +1379,8 @@ async fn run_audio_streaming(
 
     // Cleanup
     send_task.abort();
-
-    {
-        let mut conn = connection_arc.lock().await;
-        conn.disconnect();
-    }
+    // Dropping the last reference stops the session
+    drop(session);
 
     capture_engine.stop_capture();
     playback_engine.stop_playback();
@@ -6,7 +6,7 @@
 use thiserror::Error;
 
 /// Codec type enumeration
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum CodecType {
     /// Raw PCM (no compression, for LAN mode)
     #[default]
@@ -33,6 +33,14 @@ impl CodecType {
         }
     }
 
+    /// Codec types that can be used in the current build
+    pub fn available() -> Vec<Self> {
+        [CodecType::Pcm, CodecType::Opus]
+            .into_iter()
+            .filter(|codec| codec.is_available())
+            .collect()
+    }
+
     /// Check if this codec type is available in the current build
     pub fn is_available(&self) -> bool {
         match self {
@@ -135,6 +143,13 @@ pub trait AudioCodec: Send + Sync {
 
     /// Get number of channels
     fn channels(&self) -> u16;
+
+    /// Change the encoder bitrate in bits per second
+    ///
+    /// Codecs without a bitrate setting ignore it.
+    fn set_bitrate(&mut self, _bitrate: u32) -> Result<(), CodecError> {
+        Ok(())
+    }
 }
 
 /// PCM codec (passthrough, no compression)
@@ -204,6 +219,9 @@ impl AudioCodec for PcmCodec {
 mod opus_impl {
     use super::*;
 
+    /// Largest Opus frame (120 ms @ 48kHz) in samples per channel
+    const MAX_OPUS_FRAME_SIZE: usize = 5760;
+
     /// Opus codec wrapper
     ///
     /// Uses the opus crate for encoding/decoding.
@@ -286,14 +304,16 @@ mod opus_impl {
         }
 
         fn decode(&mut self, data: &[u8]) -> Result<Vec<f32>, CodecError> {
-            let total_samples = self.frame_size as usize * self.channels as usize;
-            let mut output = vec![0.0f32; total_samples];
+            // The sender may use a different frame size than ours
+            let max_samples = MAX_OPUS_FRAME_SIZE.max(self.frame_size as usize);
+            let mut output = vec![0.0f32; max_samples * self.channels as usize];
 
-            let _decoded = self
+            let decoded = self
                 .decoder
                 .decode_float(Some(data), &mut output, false)
                 .map_err(|e| CodecError::DecodeFailed(format!("Opus decode failed: {}", e)))?;
 
+            output.truncate(decoded * self.channels as usize);
             Ok(output)
         }
 
@@ -321,6 +341,12 @@ mod opus_impl {
         fn channels(&self) -> u16 {
             self.channels
         }
+
+        fn set_bitrate(&mut self, bitrate: u32) -> Result<(), CodecError> {
+            self.encoder
+                .set_bitrate(opus::Bitrate::Bits(bitrate as i32))
+                .map_err(|e| CodecError::EncodeFailed(format!("Set bitrate failed: {}", e)))
+        }
     }
 }
 
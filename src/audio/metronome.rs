@@ -1,10 +1,22 @@
 //! Metronome for tempo synchronization
 //!
 //! Generates click sounds at a specified BPM that can be shared across peers.
+//! `SyncedMetronome` keeps the metronomes of a session on one timeline: the
+//! peer that last started or changed it owns the tempo and sends
+//! `MetronomeSync` messages, the others follow with their position advanced by
+//! the one-way latency so every player hears the downbeat together.
 
 use std::f32::consts::PI;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Lowest and highest supported tempo
+const BPM_RANGE: (u32, u32) = (20, 300);
+
+/// Largest phase correction applied from a single sync; larger offsets must
+/// be confirmed by the next sync (a retransmitted sync arrives late)
+const SYNC_TOLERANCE: Duration = Duration::from_millis(20);
 
 /// Metronome configuration
 #[derive(Debug, Clone)]
@@ -36,6 +48,35 @@ impl Default for MetronomeConfig {
     }
 }
 
+/// Tempo and time signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tempo {
+    /// Beats per minute (clamped to 20-300)
+    pub bpm: u32,
+    /// Time signature numerator
+    pub beats_per_measure: u32,
+    /// Time signature denominator
+    pub beat_value: u32,
+}
+
+impl Tempo {
+    fn clamped(self) -> Self {
+        Self {
+            bpm: self.bpm.clamp(BPM_RANGE.0, BPM_RANGE.1),
+            beats_per_measure: self.beats_per_measure.max(1),
+            beat_value: self.beat_value.max(1),
+        }
+    }
+}
+
+/// Tempo change taking effect at the start of a measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TempoChange {
+    pub tempo: Tempo,
+    /// Measure the new tempo starts at
+    pub measure: u32,
+}
+
 /// Metronome state that can be synchronized across peers
 #[derive(Debug, Clone, Copy)]
 pub struct MetronomeState {
@@ -49,34 +90,105 @@ pub struct MetronomeState {
     pub total_samples: u64,
 }
 
+/// Position and tempo of a metronome
+#[derive(Debug, Clone)]
+struct Timeline {
+    tempo: Tempo,
+    samples_per_beat: u32,
+    pending: Option<TempoChange>,
+    current_beat: u32,
+    measure: u32,
+    sample_position: u64,
+    total_samples: u64,
+}
+
+impl Timeline {
+    fn new(tempo: Tempo, sample_rate: u32) -> Self {
+        let tempo = tempo.clamped();
+        Self {
+            tempo,
+            samples_per_beat: samples_per_beat(tempo.bpm, sample_rate),
+            pending: None,
+            current_beat: 0,
+            measure: 0,
+            sample_position: 0,
+            total_samples: 0,
+        }
+    }
+
+    fn set_tempo(&mut self, tempo: Tempo, sample_rate: u32) {
+        self.tempo = tempo.clamped();
+        self.samples_per_beat = samples_per_beat(self.tempo.bpm, sample_rate);
+        self.current_beat = self.current_beat.min(self.tempo.beats_per_measure - 1);
+    }
+
+    /// Move to the next beat, applying a scheduled tempo change on its measure
+    fn next_beat(&mut self, sample_rate: u32) {
+        self.sample_position = 0;
+        self.current_beat += 1;
+        if self.current_beat < self.tempo.beats_per_measure {
+            return;
+        }
+        self.current_beat = 0;
+        self.measure = self.measure.wrapping_add(1);
+        if let Some(change) = self.pending {
+            if change.measure == self.measure {
+                self.pending = None;
+                self.set_tempo(change.tempo, sample_rate);
+            }
+        }
+    }
+
+    /// Advance by `samples` without generating audio
+    fn skip(&mut self, mut samples: u64, sample_rate: u32) {
+        self.total_samples += samples;
+        while samples > 0 {
+            let left = self.samples_per_beat as u64 - self.sample_position;
+            if samples < left {
+                self.sample_position += samples;
+                return;
+            }
+            samples -= left;
+            self.next_beat(sample_rate);
+        }
+    }
+
+    /// Samples since the start of `measure`, assuming the current tempo
+    fn samples_since(&self, measure: u32) -> i64 {
+        let measures = self.measure.wrapping_sub(measure) as i32 as i64;
+        let beats = measures * self.tempo.beats_per_measure as i64 + self.current_beat as i64;
+        beats * self.samples_per_beat as i64 + self.sample_position as i64
+    }
+}
+
+fn samples_per_beat(bpm: u32, sample_rate: u32) -> u32 {
+    (sample_rate * 60) / bpm
+}
+
 /// Metronome for generating synchronized clicks
 pub struct Metronome {
     config: MetronomeConfig,
     sample_rate: u32,
     running: Arc<AtomicBool>,
-    current_beat: AtomicU32,
-    current_measure: AtomicU32,
-    sample_position: Arc<std::sync::atomic::AtomicU64>,
-    total_samples: Arc<std::sync::atomic::AtomicU64>,
-    samples_per_beat: u32,
+    timeline: Mutex<Timeline>,
     click_duration_samples: u32,
 }
 
 impl Metronome {
     /// Create a new metronome
     pub fn new(config: MetronomeConfig, sample_rate: u32) -> Self {
-        let samples_per_beat = (sample_rate * 60) / config.bpm;
+        let tempo = Tempo {
+            bpm: config.bpm,
+            beats_per_measure: config.beats_per_measure,
+            beat_value: config.beat_value,
+        };
         let click_duration_samples = sample_rate / 20; // 50ms click
 
         Self {
             config,
             sample_rate,
             running: Arc::new(AtomicBool::new(false)),
-            current_beat: AtomicU32::new(0),
-            current_measure: AtomicU32::new(0),
-            sample_position: Arc::new(std::sync::atomic::AtomicU64::new(0)),
-            total_samples: Arc::new(std::sync::atomic::AtomicU64::new(0)),
-            samples_per_beat,
+            timeline: Mutex::new(Timeline::new(tempo, sample_rate)),
             click_duration_samples,
         }
     }
@@ -94,10 +206,12 @@ impl Metronome {
 
     /// Reset the metronome to the beginning
     pub fn reset(&self) {
-        self.current_beat.store(0, Ordering::SeqCst);
-        self.current_measure.store(0, Ordering::SeqCst);
-        self.sample_position.store(0, Ordering::SeqCst);
-        self.total_samples.store(0, Ordering::SeqCst);
+        let mut timeline = self.timeline.lock().unwrap();
+        timeline.current_beat = 0;
+        timeline.measure = 0;
+        timeline.sample_position = 0;
+        timeline.total_samples = 0;
+        timeline.pending = None;
     }
 
     /// Check if metronome is running
@@ -107,13 +221,45 @@ impl Metronome {
 
     /// Set BPM
     pub fn set_bpm(&mut self, bpm: u32) {
-        self.config.bpm = bpm.clamp(20, 300);
-        self.samples_per_beat = (self.sample_rate * 60) / self.config.bpm;
+        let mut timeline = self.timeline.lock().unwrap();
+        let tempo = Tempo {
+            bpm,
+            ..timeline.tempo
+        };
+        timeline.set_tempo(tempo, self.sample_rate);
+        self.config.bpm = timeline.tempo.bpm;
     }
 
     /// Get current BPM
     pub fn bpm(&self) -> u32 {
-        self.config.bpm
+        self.tempo().bpm
+    }
+
+    /// Get the current tempo and time signature
+    pub fn tempo(&self) -> Tempo {
+        self.timeline.lock().unwrap().tempo
+    }
+
+    /// Change tempo and time signature at the start of the next measure
+    ///
+    /// A stopped metronome changes immediately.
+    pub fn schedule_tempo(&self, tempo: Tempo) -> Option<TempoChange> {
+        let mut timeline = self.timeline.lock().unwrap();
+        if !self.is_running() {
+            timeline.set_tempo(tempo, self.sample_rate);
+            return None;
+        }
+        let change = TempoChange {
+            tempo: tempo.clamped(),
+            measure: timeline.measure.wrapping_add(1),
+        };
+        timeline.pending = Some(change);
+        Some(change)
+    }
+
+    /// Tempo change waiting for its measure
+    pub fn pending_tempo(&self) -> Option<TempoChange> {
+        self.timeline.lock().unwrap().pending
     }
 
     /// Set volume
@@ -123,23 +269,30 @@ impl Metronome {
 
     /// Get current state
     pub fn state(&self) -> MetronomeState {
+        let timeline = self.timeline.lock().unwrap();
         MetronomeState {
-            current_beat: self.current_beat.load(Ordering::SeqCst),
-            measure: self.current_measure.load(Ordering::SeqCst),
-            sample_position: self.sample_position.load(Ordering::SeqCst),
-            total_samples: self.total_samples.load(Ordering::SeqCst),
+            current_beat: timeline.current_beat,
+            measure: timeline.measure,
+            sample_position: timeline.sample_position,
+            total_samples: timeline.total_samples,
         }
     }
 
     /// Synchronize to a remote state
     pub fn sync_to(&self, state: MetronomeState) {
-        self.current_beat
-            .store(state.current_beat, Ordering::SeqCst);
-        self.current_measure.store(state.measure, Ordering::SeqCst);
-        self.sample_position
-            .store(state.sample_position, Ordering::SeqCst);
-        self.total_samples
-            .store(state.total_samples, Ordering::SeqCst);
+        let mut timeline = self.timeline.lock().unwrap();
+        timeline.current_beat = state.current_beat;
+        timeline.measure = state.measure;
+        timeline.sample_position = state.sample_position;
+        timeline.total_samples = state.total_samples;
+    }
+
+    /// Advance by `samples` without generating audio
+    pub fn skip(&self, samples: u64) {
+        self.timeline
+            .lock()
+            .unwrap()
+            .skip(samples, self.sample_rate);
     }
 
     /// Generate audio samples for the metronome
@@ -150,16 +303,14 @@ impl Metronome {
         }
 
         let mut output = vec![0.0; num_samples];
-        let mut sample_pos = self.sample_position.load(Ordering::SeqCst);
-        let mut current_beat = self.current_beat.load(Ordering::SeqCst);
-        let mut current_measure = self.current_measure.load(Ordering::SeqCst);
+        let mut timeline = self.timeline.lock().unwrap();
 
         for out_sample in output.iter_mut() {
-            let pos_in_beat = (sample_pos % self.samples_per_beat as u64) as u32;
+            let pos_in_beat = timeline.sample_position as u32;
 
             // Generate click at the start of each beat
             if pos_in_beat < self.click_duration_samples {
-                let is_downbeat = current_beat == 0;
+                let is_downbeat = timeline.current_beat == 0;
                 let freq = if is_downbeat {
                     self.config.downbeat_freq
                 } else {
@@ -173,27 +324,13 @@ impl Metronome {
                 *out_sample = sample;
             }
 
-            // Advance position
-            sample_pos += 1;
-
-            // Check for beat transition
-            #[allow(clippy::manual_is_multiple_of)]
-            if sample_pos % self.samples_per_beat as u64 == 0 {
-                current_beat += 1;
-                if current_beat >= self.config.beats_per_measure {
-                    current_beat = 0;
-                    current_measure += 1;
-                }
+            // Advance position, checking for beat transition
+            timeline.sample_position += 1;
+            if timeline.sample_position >= timeline.samples_per_beat as u64 {
+                timeline.next_beat(self.sample_rate);
             }
         }
-
-        // Update state
-        self.sample_position.store(sample_pos, Ordering::SeqCst);
-        self.current_beat.store(current_beat, Ordering::SeqCst);
-        self.current_measure
-            .store(current_measure, Ordering::SeqCst);
-        self.total_samples
-            .fetch_add(num_samples as u64, Ordering::SeqCst);
+        timeline.total_samples += num_samples as u64;
 
         output
     }
@@ -209,57 +346,325 @@ impl Metronome {
             *sample += click_samples[i];
         }
     }
+
+    fn timeline(&self) -> Timeline {
+        self.timeline.lock().unwrap().clone()
+    }
+
+    fn set_timeline(&self, timeline: Timeline) {
+        *self.timeline.lock().unwrap() = timeline;
+    }
 }
 
 /// Message for metronome synchronization across network
-#[derive(Debug, Clone, Copy)]
+///
+/// Binary format (38 bytes, 54 with a tempo change):
+/// - epoch: 4 bytes, owner_token: 4 bytes
+/// - running: 1 byte
+/// - bpm, beats_per_measure, beat_value, current_beat, measure: 4 bytes each
+/// - sample_position: 8 bytes
+/// - has_next: 1 byte, then bpm, beats_per_measure, beat_value and measure of
+///   the change (4 bytes each)
+///
+/// All integers are big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MetronomeSync {
+    /// Ownership claim; the highest `(epoch, owner_token)` owns the tempo
+    pub epoch: u32,
+    /// Random tie-breaker between peers claiming the same epoch
+    pub owner_token: u32,
+    pub running: bool,
     pub bpm: u32,
     pub beats_per_measure: u32,
+    pub beat_value: u32,
     pub current_beat: u32,
     pub measure: u32,
+    /// Sample position within the current beat
     pub sample_position: u64,
+    /// Tempo change scheduled by the owner
+    pub next: Option<TempoChange>,
 }
 
 impl MetronomeSync {
+    /// Size without a tempo change
+    pub const MIN_SIZE: usize = 38;
+
     /// Create from a metronome instance
     pub fn from_metronome(metro: &Metronome) -> Self {
-        let state = metro.state();
+        let timeline = metro.timeline();
         Self {
-            bpm: metro.config.bpm,
-            beats_per_measure: metro.config.beats_per_measure,
-            current_beat: state.current_beat,
-            measure: state.measure,
-            sample_position: state.sample_position,
+            epoch: 0,
+            owner_token: 0,
+            running: metro.is_running(),
+            bpm: timeline.tempo.bpm,
+            beats_per_measure: timeline.tempo.beats_per_measure,
+            beat_value: timeline.tempo.beat_value,
+            current_beat: timeline.current_beat,
+            measure: timeline.measure,
+            sample_position: timeline.sample_position,
+            next: timeline.pending,
+        }
+    }
+
+    /// Tempo the owner is playing at
+    pub fn tempo(&self) -> Tempo {
+        Tempo {
+            bpm: self.bpm,
+            beats_per_measure: self.beats_per_measure,
+            beat_value: self.beat_value,
         }
     }
 
     /// Serialize to bytes
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(24);
+        let mut bytes = Vec::with_capacity(Self::MIN_SIZE + 16);
+        bytes.extend_from_slice(&self.epoch.to_be_bytes());
+        bytes.extend_from_slice(&self.owner_token.to_be_bytes());
+        bytes.push(self.running as u8);
         bytes.extend_from_slice(&self.bpm.to_be_bytes());
         bytes.extend_from_slice(&self.beats_per_measure.to_be_bytes());
+        bytes.extend_from_slice(&self.beat_value.to_be_bytes());
         bytes.extend_from_slice(&self.current_beat.to_be_bytes());
         bytes.extend_from_slice(&self.measure.to_be_bytes());
         bytes.extend_from_slice(&self.sample_position.to_be_bytes());
+        match self.next {
+            Some(change) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&change.tempo.bpm.to_be_bytes());
+                bytes.extend_from_slice(&change.tempo.beats_per_measure.to_be_bytes());
+                bytes.extend_from_slice(&change.tempo.beat_value.to_be_bytes());
+                bytes.extend_from_slice(&change.measure.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
         bytes
     }
 
     /// Deserialize from bytes
     pub fn from_bytes(data: &[u8]) -> Option<Self> {
-        if data.len() < 24 {
+        if data.len() < Self::MIN_SIZE {
             return None;
         }
+        let u32_at =
+            |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let next = match data[37] {
+            0 => None,
+            _ if data.len() >= Self::MIN_SIZE + 16 => Some(TempoChange {
+                tempo: Tempo {
+                    bpm: u32_at(38),
+                    beats_per_measure: u32_at(42),
+                    beat_value: u32_at(46),
+                },
+                measure: u32_at(50),
+            }),
+            _ => return None,
+        };
         Some(Self {
-            bpm: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
-            beats_per_measure: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
-            current_beat: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
-            measure: u32::from_be_bytes([data[12], data[13], data[14], data[15]]),
+            epoch: u32_at(0),
+            owner_token: u32_at(4),
+            running: data[8] != 0,
+            bpm: u32_at(9),
+            beats_per_measure: u32_at(13),
+            beat_value: u32_at(17),
+            current_beat: u32_at(21),
+            measure: u32_at(25),
             sample_position: u64::from_be_bytes([
-                data[16], data[17], data[18], data[19], data[20], data[21], data[22], data[23],
+                data[29], data[30], data[31], data[32], data[33], data[34], data[35], data[36],
             ]),
+            next,
+        })
+    }
+
+    fn timeline(&self, sample_rate: u32) -> Timeline {
+        let mut timeline = Timeline::new(self.tempo(), sample_rate);
+        timeline.pending = self.next;
+        timeline.current_beat = self.current_beat.min(timeline.tempo.beats_per_measure - 1);
+        timeline.measure = self.measure;
+        timeline.sample_position = self
+            .sample_position
+            .min(timeline.samples_per_beat as u64 - 1);
+        timeline
+    }
+}
+
+/// Ownership and sync bookkeeping of a `SyncedMetronome`
+struct SyncState {
+    epoch: u32,
+    owner_token: u32,
+    /// We own the tempo and send syncs
+    owner: bool,
+    /// A sync should go out (owner only)
+    sync_due: bool,
+    /// Phase offset of the last rejected sync (samples)
+    outlier: Option<i64>,
+}
+
+/// Metronome shared by the peers of a session
+///
+/// Starting, stopping or changing the tempo takes ownership: the owner sends
+/// a `MetronomeSync` right away and at every downbeat. Followers adopt the
+/// owner's tempo and position, advanced by the one-way latency to the owner.
+/// Tempo changes are scheduled on the owner's next measure, so they apply on
+/// the same bar for everyone. Between syncs each metronome runs on its own
+/// sample clock; small phase differences are corrected at each sync, large
+/// ones only once a second sync confirms them.
+pub struct SyncedMetronome {
+    metronome: Metronome,
+    sync: Mutex<SyncState>,
+}
+
+impl SyncedMetronome {
+    /// Create a stopped metronome that follows any peer's tempo
+    pub fn new(config: MetronomeConfig, sample_rate: u32) -> Self {
+        Self {
+            metronome: Metronome::new(config, sample_rate),
+            sync: Mutex::new(SyncState {
+                epoch: 0,
+                owner_token: 0,
+                owner: false,
+                sync_due: false,
+                outlier: None,
+            }),
+        }
+    }
+
+    /// Start the click from the first beat and take ownership of the tempo
+    pub fn start(&self, tempo: Tempo) {
+        self.metronome.stop();
+        self.metronome.schedule_tempo(tempo);
+        self.metronome.start();
+        self.take_ownership();
+    }
+
+    /// Stop the click for everyone
+    pub fn stop(&self) {
+        self.metronome.stop();
+        self.take_ownership();
+    }
+
+    /// Change the tempo for everyone at the start of the next measure
+    pub fn set_tempo(&self, tempo: Tempo) {
+        self.metronome.schedule_tempo(tempo);
+        self.take_ownership();
+    }
+
+    /// Whether we own the tempo
+    pub fn is_owner(&self) -> bool {
+        self.sync.lock().unwrap().owner
+    }
+
+    /// Check if the click is running
+    pub fn is_running(&self) -> bool {
+        self.metronome.is_running()
+    }
+
+    /// Current tempo and time signature
+    pub fn tempo(&self) -> Tempo {
+        self.metronome.tempo()
+    }
+
+    /// Tempo change waiting for its measure
+    pub fn pending_tempo(&self) -> Option<TempoChange> {
+        self.metronome.pending_tempo()
+    }
+
+    /// Current position
+    pub fn state(&self) -> MetronomeState {
+        self.metronome.state()
+    }
+
+    /// Mix the click into an interleaved buffer
+    pub fn mix_into(&self, buffer: &mut [f32], channels: u16) {
+        if !self.metronome.is_running() {
+            return;
+        }
+        let channels = channels.max(1) as usize;
+        let measure = self.metronome.state().measure;
+        let clicks = self.metronome.generate(buffer.len() / channels);
+        for (frame, click) in buffer.chunks_mut(channels).zip(clicks) {
+            for sample in frame {
+                *sample += click;
+            }
+        }
+
+        // The owner re-syncs everyone at each downbeat
+        if self.metronome.state().measure != measure {
+            let mut sync = self.sync.lock().unwrap();
+            if sync.owner {
+                sync.sync_due = true;
+            }
+        }
+    }
+
+    /// Take the sync to send to every peer, if one is due
+    pub fn take_sync(&self) -> Option<MetronomeSync> {
+        let mut sync = self.sync.lock().unwrap();
+        if !sync.owner || !std::mem::take(&mut sync.sync_due) {
+            return None;
+        }
+        Some(MetronomeSync {
+            epoch: sync.epoch,
+            owner_token: sync.owner_token,
+            ..MetronomeSync::from_metronome(&self.metronome)
         })
     }
+
+    /// Follow a sync from the owner, sent `one_way` ago
+    pub fn handle_sync(&self, remote: &MetronomeSync, one_way: Duration) {
+        let mut sync = self.sync.lock().unwrap();
+        let claim = (remote.epoch, remote.owner_token);
+        let ours = (sync.epoch, sync.owner_token);
+        if claim < ours || (claim == ours && sync.owner) {
+            return;
+        }
+
+        let sample_rate = self.metronome.sample_rate;
+        let mut target = remote.timeline(sample_rate);
+        target.skip(duration_samples(one_way, sample_rate), sample_rate);
+
+        let current = self.metronome.timeline();
+        let same_timeline = claim == ours
+            && remote.running == self.metronome.is_running()
+            && current.tempo == target.tempo
+            && current.pending == target.pending;
+        if same_timeline {
+            let offset =
+                current.samples_since(target.measure) - target.samples_since(target.measure);
+            let tolerance = duration_samples(SYNC_TOLERANCE, sample_rate) as i64;
+            let confirmed = sync
+                .outlier
+                .is_some_and(|previous| (previous - offset).abs() <= tolerance);
+            if offset.abs() > tolerance && !confirmed {
+                // Possibly a late (retransmitted) sync; wait for the next one
+                sync.outlier = Some(offset);
+                return;
+            }
+        }
+
+        sync.epoch = remote.epoch;
+        sync.owner_token = remote.owner_token;
+        sync.owner = false;
+        sync.sync_due = false;
+        sync.outlier = None;
+        target.total_samples = current.total_samples;
+        self.metronome.set_timeline(target);
+        self.metronome
+            .running
+            .store(remote.running, Ordering::SeqCst);
+    }
+
+    fn take_ownership(&self) {
+        let mut sync = self.sync.lock().unwrap();
+        sync.epoch = sync.epoch.wrapping_add(1);
+        sync.owner_token = rand::random();
+        sync.owner = true;
+        sync.sync_due = true;
+        sync.outlier = None;
+    }
+}
+
+fn duration_samples(duration: Duration, sample_rate: u32) -> u64 {
+    (duration.as_secs_f64() * sample_rate as f64).round() as u64
 }
 
 #[cfg(test)]
@@ -326,4 +731,144 @@ mod tests {
         metro.set_bpm(400);
         assert_eq!(metro.bpm(), 300);
     }
+
+    fn tempo(bpm: u32, beats_per_measure: u32) -> Tempo {
+        Tempo {
+            bpm,
+            beats_per_measure,
+            beat_value: 4,
+        }
+    }
+
+    #[test]
+    fn test_tempo_change_on_next_measure() {
+        // 120 BPM at 48 kHz: 24000 samples per beat
+        let metro = Metronome::new(MetronomeConfig::default(), 48000);
+        metro.start();
+        metro.skip(24000 + 100);
+
+        let change = metro.schedule_tempo(tempo(90, 3)).unwrap();
+        assert_eq!(change.measure, 1);
+        metro.skip(2 * 24000);
+        assert_eq!(metro.bpm(), 120, "Change waits for the downbeat");
+
+        metro.skip(24000);
+        let state = metro.state();
+        assert_eq!((state.measure, state.current_beat), (1, 0));
+        assert_eq!(metro.tempo(), tempo(90, 3));
+        assert_eq!(metro.pending_tempo(), None);
+
+        // Three beats of 32000 samples make the new measure
+        metro.skip(3 * 32000);
+        assert_eq!(metro.state().measure, 2);
+    }
+
+    #[test]
+    fn test_sync_message_roundtrip_with_change() {
+        let sync = MetronomeSync {
+            epoch: 3,
+            owner_token: 0xDEADBEEF,
+            running: true,
+            bpm: 100,
+            beats_per_measure: 4,
+            beat_value: 4,
+            current_beat: 2,
+            measure: 17,
+            sample_position: 1234,
+            next: Some(TempoChange {
+                tempo: tempo(140, 7),
+                measure: 18,
+            }),
+        };
+        assert_eq!(MetronomeSync::from_bytes(&sync.to_bytes()), Some(sync));
+
+        let without = MetronomeSync { next: None, ..sync };
+        assert_eq!(without.to_bytes().len(), MetronomeSync::MIN_SIZE);
+        assert_eq!(
+            MetronomeSync::from_bytes(&without.to_bytes()),
+            Some(without)
+        );
+        assert_eq!(MetronomeSync::from_bytes(&[0; 10]), None);
+    }
+
+    #[test]
+    fn test_follower_compensates_one_way_latency() {
+        let owner = SyncedMetronome::new(MetronomeConfig::default(), 48000);
+        let follower = SyncedMetronome::new(MetronomeConfig::default(), 48000);
+        owner.start(tempo(120, 4));
+        let mut buf = vec![0.0; 1000];
+        owner.mix_into(&mut buf, 1);
+
+        let sync = owner.take_sync().unwrap();
+        assert!(owner.take_sync().is_none(), "Sync is sent once");
+        follower.handle_sync(&sync, Duration::from_millis(10));
+
+        assert!(follower.is_running());
+        assert!(!follower.is_owner());
+        // 1000 samples played by the owner + 10 ms (480 samples) in flight
+        assert_eq!(follower.state().sample_position, 1480);
+    }
+
+    #[test]
+    fn test_follower_rejects_late_sync_until_confirmed() {
+        let owner = SyncedMetronome::new(MetronomeConfig::default(), 48000);
+        let follower = SyncedMetronome::new(MetronomeConfig::default(), 48000);
+        owner.start(tempo(120, 4));
+        follower.handle_sync(&owner.take_sync().unwrap(), Duration::ZERO);
+
+        // Both play 1000 samples, then a stale sync from the start arrives
+        let stale = {
+            let sync = owner.sync.lock().unwrap();
+            MetronomeSync {
+                epoch: sync.epoch,
+                owner_token: sync.owner_token,
+                ..MetronomeSync::from_metronome(&owner.metronome)
+            }
+        };
+        let mut buf = vec![0.0; 1000];
+        owner.mix_into(&mut buf, 1);
+        follower.mix_into(&mut buf, 1);
+        follower.handle_sync(&stale, Duration::ZERO);
+        assert_eq!(follower.state().sample_position, 1000);
+
+        // A second sync with the same offset is a real phase difference
+        follower.handle_sync(&stale, Duration::ZERO);
+        assert_eq!(follower.state().sample_position, 0);
+    }
+
+    #[test]
+    fn test_ownership_moves_to_latest_claim() {
+        let a = SyncedMetronome::new(MetronomeConfig::default(), 48000);
+        let b = SyncedMetronome::new(MetronomeConfig::default(), 48000);
+        a.start(tempo(120, 4));
+        b.handle_sync(&a.take_sync().unwrap(), Duration::ZERO);
+
+        // B changes the tempo: B owns it now and A follows on the next measure
+        b.set_tempo(tempo(150, 4));
+        assert!(b.is_owner());
+        a.handle_sync(&b.take_sync().unwrap(), Duration::ZERO);
+        assert!(!a.is_owner());
+        assert_eq!(a.pending_tempo().map(|c| c.tempo), Some(tempo(150, 4)));
+
+        // A stops the click for everyone
+        a.stop();
+        b.handle_sync(&a.take_sync().unwrap(), Duration::ZERO);
+        assert!(!b.is_running());
+    }
+
+    #[test]
+    fn test_owner_syncs_every_measure() {
+        let owner = SyncedMetronome::new(MetronomeConfig::default(), 48000);
+        owner.start(tempo(120, 4));
+        owner.take_sync().unwrap();
+
+        let mut buf = vec![0.0; 48000];
+        owner.mix_into(&mut buf, 1);
+        assert!(owner.take_sync().is_none());
+        owner.mix_into(&mut buf, 1);
+        assert!(
+            owner.take_sync().is_some(),
+            "Downbeat of the second measure"
+        );
+    }
 }
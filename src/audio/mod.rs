@@ -11,6 +11,7 @@ mod metronome;
 mod plc;
 mod plugin;
 mod recording;
+mod time_stretch;
 
 pub use codec::{
     create_codec, AudioCodec, CodecConfig, CodecError, CodecType, OpusCodec, PcmCodec,
@@ -25,10 +26,13 @@ pub use engine::{
     SharedPlaybackProducer,
 };
 pub use error::AudioError;
-pub use metronome::{Metronome, MetronomeConfig, MetronomeState, MetronomeSync};
-pub use plc::PcmPlc;
+pub use metronome::{
+    Metronome, MetronomeConfig, MetronomeState, MetronomeSync, SyncedMetronome, Tempo, TempoChange,
+};
+pub use plc::{create_plc, PacketLossConcealment, PcmPlc, PitchPlc, PlcType};
 pub use plugin::{
     AudioPlugin, ClapPlugin, ClapPluginLoader, PluginFormat, PluginHost, PluginInfo,
     PluginParameter, PluginScanner,
 };
 pub use recording::{Recorder, RecordingInfo};
+pub use time_stretch::TimeStretcher;
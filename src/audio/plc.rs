@@ -1,7 +1,102 @@
 //! Packet Loss Concealment for PCM audio
 //!
-//! Implements "front frame repeat + fadeout" strategy for concealing
-//! lost packets in uncompressed PCM audio streams.
+//! Two strategies for concealing lost packets in uncompressed PCM audio
+//! streams behind a common trait:
+//! - `PcmPlc`: "front frame repeat + fadeout"
+//! - `PitchPlc`: pitch-based waveform substitution, which repeats the last
+//!   pitch period of the signal instead of the whole last frame and
+//!   crossfades back into the next received frame
+
+use std::f32::consts::PI;
+
+/// Shortest pitch period searched by `PitchPlc` (milliseconds, 400 Hz)
+const MIN_PITCH_MS: f32 = 2.5;
+
+/// Longest pitch period searched by `PitchPlc` (milliseconds, 50 Hz)
+const MAX_PITCH_MS: f32 = 20.0;
+
+/// Audio compared when searching the pitch period (milliseconds)
+const PITCH_WINDOW_MS: f32 = 10.0;
+
+/// Fraction of the best autocorrelation a shorter pitch period candidate
+/// must reach
+const PITCH_PEAK_RATIO: f32 = 0.9;
+
+/// Length of the smoothing at seams of the substituted waveform and of the
+/// crossfade back into received audio (milliseconds)
+const OVERLAP_MS: f32 = 2.5;
+
+/// Sample rate assumed by `PitchPlc::new`
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// Packet loss concealment strategy
+///
+/// All implementations must be Send + Sync for use across threads.
+pub trait PacketLossConcealment: Send + Sync {
+    /// Store a successfully received frame
+    ///
+    /// Call this for every successfully decoded frame to update the PLC state.
+    fn store_frame(&mut self, samples: &[f32]);
+
+    /// Store a successfully received frame, smoothing the transition from
+    /// concealment into it
+    ///
+    /// Strategies without a transition just store the frame.
+    fn receive_frame(&mut self, samples: &mut [f32]) {
+        self.store_frame(samples);
+    }
+
+    /// Generate concealment audio for a lost frame
+    ///
+    /// The frame is sized like the last stored frame.
+    fn generate_concealment(&mut self) -> Vec<f32>;
+
+    /// Reset PLC state
+    ///
+    /// Call this when reconnecting or starting a new stream.
+    fn reset(&mut self);
+
+    /// Get number of consecutive losses
+    fn consecutive_losses(&self) -> u32;
+
+    /// Check if currently in concealment mode
+    fn is_concealing(&self) -> bool {
+        self.consecutive_losses() > 0
+    }
+}
+
+/// Packet loss concealment strategy selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlcType {
+    /// Repeat the last frame with fadeout (`PcmPlc`)
+    #[default]
+    Repeat,
+    /// Pitch-based waveform substitution (`PitchPlc`)
+    Pitch,
+}
+
+/// Create a packet loss concealment instance
+///
+/// # Arguments
+/// * `plc_type` - Concealment strategy
+/// * `frame_size` - Number of samples per channel per frame
+/// * `channels` - Number of audio channels
+/// * `sample_rate` - Sample rate in Hz
+pub fn create_plc(
+    plc_type: PlcType,
+    frame_size: u32,
+    channels: u16,
+    sample_rate: u32,
+) -> Box<dyn PacketLossConcealment> {
+    match plc_type {
+        PlcType::Repeat => Box::new(PcmPlc::new(frame_size, channels)),
+        PlcType::Pitch => Box::new(PitchPlc::with_sample_rate(
+            frame_size,
+            channels,
+            sample_rate,
+        )),
+    }
+}
 
 /// PCM Packet Loss Concealment
 ///
@@ -83,9 +178,9 @@ impl PcmPlc {
         self.consecutive_losses += 1;
 
         // After too many consecutive losses, output silence
+        // (sized like the last frame, which may differ from `frame_size`)
         if self.consecutive_losses > self.max_losses_before_silence {
-            let total_samples = self.frame_size as usize * self.channels as usize;
-            return vec![0.0; total_samples];
+            return vec![0.0; self.last_frame.len()];
         }
 
         // Calculate fadeout gain: factor^consecutive_losses
@@ -115,6 +210,284 @@ impl PcmPlc {
     }
 }
 
+impl PacketLossConcealment for PcmPlc {
+    fn store_frame(&mut self, samples: &[f32]) {
+        PcmPlc::store_frame(self, samples)
+    }
+
+    fn generate_concealment(&mut self) -> Vec<f32> {
+        PcmPlc::generate_concealment(self)
+    }
+
+    fn reset(&mut self) {
+        PcmPlc::reset(self)
+    }
+
+    fn consecutive_losses(&self) -> u32 {
+        PcmPlc::consecutive_losses(self)
+    }
+
+    fn is_concealing(&self) -> bool {
+        PcmPlc::is_concealing(self)
+    }
+}
+
+/// Pitch-based waveform substitution PLC
+///
+/// Strategy:
+/// - 1st loss: Estimate the pitch period of the last received audio by
+///   normalized autocorrelation and keep repeating that period, starting
+///   seamlessly from the last played sample
+/// - 2nd+ loss: Fade out (factor^(n-1)), ramped across each frame
+/// - After too many consecutive losses: Fade to silence
+/// - Next received frame: Crossfade from the substituted waveform into it
+///
+/// Seams where the copied waveform jumps back by a period are smoothed by a
+/// decaying offset, so a period that does not repeat exactly (noise,
+/// inharmonic sounds) does not click.
+pub struct PitchPlc {
+    /// Recently played audio, interleaved, oldest first
+    history: Vec<f32>,
+    /// Samples of the last stored frame (size of concealment frames)
+    last_len: usize,
+    /// Number of consecutive lost frames
+    consecutive_losses: u32,
+    /// Frame size in samples per channel
+    frame_size: u32,
+    /// Number of channels
+    channels: u16,
+    /// Maximum consecutive losses before silence
+    max_losses_before_silence: u32,
+    /// Fadeout factor per consecutive loss
+    fadeout_factor: f32,
+    /// Pitch period search range in samples per channel
+    min_period: usize,
+    max_period: usize,
+    /// Audio compared in the pitch search in samples per channel
+    window: usize,
+    /// Seam smoothing and crossfade length in samples per channel
+    overlap: usize,
+    /// Period being repeated in samples per channel (0 while not concealing)
+    period: usize,
+    /// Next history sample (per channel) to copy
+    position: usize,
+    /// Offset bridging the last seam per channel, decaying to zero
+    offset: Vec<f32>,
+    /// Samples per channel until the offset has decayed
+    offset_left: usize,
+    /// Last substituted sample per channel, before gain
+    last: Vec<f32>,
+    /// Gain reached at the end of the last concealment frame
+    gain: f32,
+}
+
+impl PitchPlc {
+    /// Create a new pitch PLC instance for 48 kHz audio
+    ///
+    /// # Arguments
+    /// * `frame_size` - Number of samples per channel per frame
+    /// * `channels` - Number of audio channels
+    pub fn new(frame_size: u32, channels: u16) -> Self {
+        Self::with_sample_rate(frame_size, channels, DEFAULT_SAMPLE_RATE)
+    }
+
+    /// Create a new pitch PLC instance
+    ///
+    /// # Arguments
+    /// * `frame_size` - Number of samples per channel per frame
+    /// * `channels` - Number of audio channels
+    /// * `sample_rate` - Sample rate in Hz (sets the pitch search range)
+    pub fn with_sample_rate(frame_size: u32, channels: u16, sample_rate: u32) -> Self {
+        let samples = |ms: f32| ((sample_rate as f32 * ms / 1000.0) as usize).max(1);
+        let channels = channels.max(1);
+        let max_period = samples(MAX_PITCH_MS);
+        let window = samples(PITCH_WINDOW_MS);
+        Self {
+            history: vec![0.0; (max_period + window) * channels as usize],
+            last_len: frame_size as usize * channels as usize,
+            consecutive_losses: 0,
+            frame_size,
+            channels,
+            max_losses_before_silence: 5,
+            fadeout_factor: 0.85,
+            min_period: samples(MIN_PITCH_MS).min(max_period),
+            max_period,
+            window,
+            overlap: samples(OVERLAP_MS),
+            period: 0,
+            position: 0,
+            offset: vec![0.0; channels as usize],
+            offset_left: 0,
+            last: vec![0.0; channels as usize],
+            gain: 1.0,
+        }
+    }
+
+    /// Pitch period currently repeated in samples per channel (None while
+    /// not concealing)
+    pub fn period(&self) -> Option<usize> {
+        (self.period > 0).then_some(self.period)
+    }
+
+    fn history_len(&self) -> usize {
+        self.history.len() / self.channels as usize
+    }
+
+    /// Pitch period of the latest audio
+    ///
+    /// The shortest lag at a peak of the normalized autocorrelation that
+    /// comes close to the highest one; multiples of the period correlate
+    /// just as well.
+    fn estimate_period(&self) -> usize {
+        let channels = self.channels as usize;
+        let mono: Vec<f32> = self
+            .history
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum())
+            .collect();
+        let recent = &mono[mono.len() - self.window..];
+
+        let similarities: Vec<f32> = (self.min_period..=self.max_period)
+            .map(|lag| {
+                let end = mono.len() - lag;
+                let earlier = &mono[end - self.window..end];
+                let mut cross = 0.0;
+                let mut energy = (0.0, 0.0);
+                for (&a, &b) in recent.iter().zip(earlier) {
+                    cross += a * b;
+                    energy.0 += a * a;
+                    energy.1 += b * b;
+                }
+                let norm = (energy.0 * energy.1).sqrt();
+                if norm > 0.0 {
+                    cross / norm
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        let best = similarities.iter().copied().fold(f32::MIN, f32::max);
+        let peak = (0..similarities.len()).find(|&i| {
+            let similarity = similarities[i];
+            similarity >= best * PITCH_PEAK_RATIO
+                && i.checked_sub(1)
+                    .is_none_or(|j| similarities[j] <= similarity)
+                && similarities
+                    .get(i + 1)
+                    .is_none_or(|&next| next <= similarity)
+        });
+        self.min_period + peak.unwrap_or(similarities.len() - 1)
+    }
+
+    /// Jump back to the start of the repeated period, bridging the step
+    /// between the last substituted sample and the one preceding the jump
+    /// target
+    fn seam(&mut self) {
+        let channels = self.channels as usize;
+        self.position = self.history_len() - self.period;
+        let before = &self.history[(self.position - 1) * channels..self.position * channels];
+        for ((offset, &last), &before) in self.offset.iter_mut().zip(&self.last).zip(before) {
+            *offset = last - before;
+        }
+        self.offset_left = self.overlap;
+    }
+
+    /// Next substituted sample per channel, before gain
+    fn substitute(&mut self, output: &mut Vec<f32>) {
+        let channels = self.channels as usize;
+        let bridge = self.offset_left as f32 / self.overlap as f32;
+        let frame = &self.history[self.position * channels..(self.position + 1) * channels];
+        for ((last, &sample), &offset) in self.last.iter_mut().zip(frame).zip(&self.offset) {
+            *last = sample + offset * bridge;
+            output.push(*last);
+        }
+        self.offset_left = self.offset_left.saturating_sub(1);
+        self.position += 1;
+        if self.position == self.history_len() {
+            self.seam();
+        }
+    }
+}
+
+impl PacketLossConcealment for PitchPlc {
+    fn store_frame(&mut self, samples: &[f32]) {
+        let len = self.history.len();
+        if samples.len() >= len {
+            self.history
+                .copy_from_slice(&samples[samples.len() - len..]);
+        } else {
+            self.history.drain(..samples.len());
+            self.history.extend_from_slice(samples);
+        }
+        self.last_len = samples.len();
+        self.consecutive_losses = 0;
+        self.period = 0;
+        self.gain = 1.0;
+    }
+
+    /// Crossfades from the continued substitution into the frame over the
+    /// overlap length
+    fn receive_frame(&mut self, samples: &mut [f32]) {
+        if self.period > 0 {
+            let channels = self.channels as usize;
+            let frames = (samples.len() / channels).min(self.overlap);
+            let mut substituted = Vec::with_capacity(frames * channels);
+            for _ in 0..frames {
+                self.substitute(&mut substituted);
+            }
+            for (i, (sample, &concealed)) in samples.iter_mut().zip(&substituted).enumerate() {
+                let fade = 0.5 - 0.5 * (PI * ((i / channels) as f32 + 0.5) / frames as f32).cos();
+                *sample = *sample * fade + concealed * self.gain * (1.0 - fade);
+            }
+        }
+        self.store_frame(samples);
+    }
+
+    /// Repeats the last pitch period, fading out on consecutive losses
+    fn generate_concealment(&mut self) -> Vec<f32> {
+        let channels = self.channels as usize;
+        self.consecutive_losses += 1;
+        if self.period == 0 {
+            self.period = self.estimate_period();
+            let end = self.history.len();
+            self.last.copy_from_slice(&self.history[end - channels..]);
+            self.seam();
+        }
+
+        let target = if self.consecutive_losses > self.max_losses_before_silence {
+            0.0
+        } else {
+            self.fadeout_factor.powi(self.consecutive_losses as i32 - 1)
+        };
+        let frames = self.last_len / channels;
+        let mut output = Vec::with_capacity(self.last_len);
+        for i in 0..frames {
+            let gain = self.gain + (target - self.gain) * (i + 1) as f32 / frames as f32;
+            let start = output.len();
+            self.substitute(&mut output);
+            for sample in &mut output[start..] {
+                *sample *= gain;
+            }
+        }
+        self.gain = target;
+        output
+    }
+
+    fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.last_len = self.frame_size as usize * self.channels as usize;
+        self.consecutive_losses = 0;
+        self.period = 0;
+        self.offset_left = 0;
+        self.gain = 1.0;
+    }
+
+    fn consecutive_losses(&self) -> u32 {
+        self.consecutive_losses
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -220,4 +593,93 @@ mod tests {
         assert_eq!(plc.consecutive_losses(), 0);
         assert!(plc.last_frame.iter().all(|&s| s == 0.0));
     }
+
+    /// Sine with a period of 200 samples (240 Hz at 48 kHz)
+    fn tone(start: usize, len: usize) -> Vec<f32> {
+        (start..start + len)
+            .map(|n| (n as f32 * 2.0 * PI / 200.0).sin() * 0.5)
+            .collect()
+    }
+
+    fn max_step(samples: &[f32]) -> f32 {
+        samples
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_pitch_plc_continues_waveform() {
+        let mut plc = PitchPlc::new(128, 1);
+        for i in 0..8 {
+            plc.store_frame(&tone(i * 128, 128));
+        }
+
+        let concealed = plc.generate_concealment();
+        assert_eq!(plc.period(), Some(200));
+        assert_eq!(concealed.len(), 128);
+        for (a, b) in concealed.iter().zip(tone(8 * 128, 128)) {
+            assert!((a - b).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_pitch_plc_crossfades_into_received_audio() {
+        let mut plc = PitchPlc::new(128, 1);
+        let mut played = Vec::new();
+        for i in 0..8 {
+            let frame = tone(i * 128, 128);
+            plc.store_frame(&frame);
+            played.extend(frame);
+        }
+        played.extend(plc.generate_concealment());
+
+        // The received audio does not continue the concealed waveform
+        let mut received: Vec<f32> = tone(9 * 128, 128).iter().map(|s| -s).collect();
+        let original = received.clone();
+        plc.receive_frame(&mut received);
+        played.extend(&received);
+
+        assert!(!plc.is_concealing());
+        assert!(plc.period().is_none());
+        assert!(max_step(&played) < 0.05);
+        // Only the overlap (2.5 ms) is crossfaded
+        assert_eq!(received[120..], original[120..]);
+    }
+
+    #[test]
+    fn test_pitch_plc_fades_to_silence() {
+        let mut plc = PitchPlc::with_sample_rate(80, 2, 8000);
+        let frame: Vec<f32> = (0..160).map(|i| ((i / 2) as f32 * 0.3).sin()).collect();
+        plc.store_frame(&frame);
+
+        let mut played = frame.clone();
+        for _ in 0..6 {
+            let concealed = plc.generate_concealment();
+            assert_eq!(concealed.len(), 160);
+            played.extend(concealed);
+        }
+        assert_eq!(plc.consecutive_losses(), 6);
+        assert!(plc.generate_concealment().iter().all(|&s| s == 0.0));
+
+        // The fadeout is ramped, without steps between frames
+        let left: Vec<f32> = played.iter().step_by(2).copied().collect();
+        assert!(max_step(&left) < 0.35);
+
+        plc.reset();
+        assert!(!plc.is_concealing());
+        assert!(plc.generate_concealment().iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_create_plc() {
+        let mut plc = create_plc(PlcType::Pitch, 120, 1, 48000);
+        plc.store_frame(&[0.5; 120]);
+        assert_eq!(plc.generate_concealment().len(), 120);
+        assert!(plc.is_concealing());
+
+        let mut plc = create_plc(PlcType::default(), 120, 1, 48000);
+        plc.store_frame(&[1.0; 120]);
+        assert!((plc.generate_concealment()[0] - 0.85).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,208 @@
+//! Time-scale modification for playout adaptation
+//!
+//! Implements WSOLA (waveform similarity overlap-add) on single frames: to
+//! lengthen a frame, a segment of it is played twice; to shorten it, a
+//! segment is skipped. The segment length is the lag at which the waveform
+//! best matches itself (for tonal audio a multiple of the pitch period), and
+//! the seam is crossfaded, so the tempo changes for a moment while the pitch
+//! stays and no discontinuity is heard.
+
+use std::f32::consts::PI;
+
+/// Shortest segment inserted or removed (milliseconds)
+const MIN_SEGMENT_MS: f32 = 2.5;
+
+/// Longest segment inserted or removed (milliseconds, covers a 50 Hz period)
+const MAX_SEGMENT_MS: f32 = 20.0;
+
+/// WSOLA time stretcher for interleaved audio frames
+pub struct TimeStretcher {
+    /// Number of channels
+    channels: u16,
+    /// Shortest segment in samples per channel
+    min_segment: usize,
+    /// Longest segment in samples per channel
+    max_segment: usize,
+}
+
+impl TimeStretcher {
+    /// Create a time stretcher
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Sample rate in Hz
+    /// * `channels` - Number of audio channels
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let samples = |ms: f32| ((sample_rate as f32 * ms / 1000.0) as usize).max(1);
+        Self {
+            channels: channels.max(1),
+            min_segment: samples(MIN_SEGMENT_MS),
+            max_segment: samples(MAX_SEGMENT_MS),
+        }
+    }
+
+    /// Shortest change a single `stretch` makes, in samples per channel
+    pub fn min_change(&self) -> usize {
+        self.min_segment
+    }
+
+    /// Lengthen (`change` > 0) or shorten (`change` < 0) a frame
+    ///
+    /// The frame changes by one segment of at least `min_change` and at
+    /// most half the frame's samples per channel; segments up to `|change|`
+    /// are preferred, so larger changes take several frames. Returns None if
+    /// the frame is too short to hold two segments or `change` is zero.
+    pub fn stretch(&self, samples: &[f32], change: i64) -> Option<Vec<f32>> {
+        let channels = self.channels as usize;
+        let frames = samples.len() / channels;
+        let longest = (frames / 2)
+            .min(self.max_segment)
+            .min((change.unsigned_abs() as usize).max(self.min_segment));
+        if change == 0 || longest < self.min_segment {
+            return None;
+        }
+
+        let mono: Vec<f32> = samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum())
+            .collect();
+        let segment = self.best_segment(&mono, longest);
+        let window = fade_in(segment);
+        let split = segment * channels;
+        let (head, rest) = samples.split_at(split);
+
+        // Crossfade from `from` into `to`, one segment long
+        let crossfade = |from: &[f32], to: &[f32]| -> Vec<f32> {
+            from.chunks_exact(channels)
+                .zip(to.chunks_exact(channels))
+                .zip(&window)
+                .flat_map(|((from, to), &w)| {
+                    from.iter()
+                        .zip(to)
+                        .map(move |(&a, &b)| a * (1.0 - w) + b * w)
+                })
+                .collect()
+        };
+
+        let mut output = Vec::with_capacity(samples.len() + split);
+        if change > 0 {
+            // Play the first segment, then fade from its continuation back
+            // into it: the first segment is heard twice
+            output.extend_from_slice(head);
+            output.extend(crossfade(&rest[..split], head));
+            output.extend_from_slice(rest);
+        } else {
+            // Fade from the first segment into the second, skipping one
+            output.extend(crossfade(head, &rest[..split]));
+            output.extend_from_slice(&rest[split..]);
+        }
+        Some(output)
+    }
+
+    /// Segment length up to `longest` at which the frame best repeats itself
+    ///
+    /// Maximizes the normalized correlation between the first segment and
+    /// the one following it; ties go to the longer segment.
+    fn best_segment(&self, mono: &[f32], longest: usize) -> usize {
+        let mut best = (longest, f32::MIN);
+        for segment in (self.min_segment..=longest).rev() {
+            let (first, second) = (&mono[..segment], &mono[segment..2 * segment]);
+            let mut cross = 0.0;
+            let mut energy = (0.0, 0.0);
+            for (&a, &b) in first.iter().zip(second) {
+                cross += a * b;
+                energy.0 += a * a;
+                energy.1 += b * b;
+            }
+            let norm = (energy.0 * energy.1).sqrt();
+            let similarity = if norm > 0.0 { cross / norm } else { 0.0 };
+            if similarity > best.1 {
+                best = (segment, similarity);
+            }
+        }
+        best.0
+    }
+}
+
+/// Raised-cosine fade-in over `len` samples
+fn fade_in(len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| 0.5 - 0.5 * (PI * (i as f32 + 0.5) / len as f32).cos())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sine with a period of 32 samples
+    fn sine(start: usize, len: usize) -> Vec<f32> {
+        (start..start + len)
+            .map(|n| (n as f32 * 2.0 * PI / 32.0).sin() * 0.5)
+            .collect()
+    }
+
+    fn max_step(samples: &[f32]) -> f32 {
+        samples
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_expand_repeats_a_pitch_period() {
+        let stretcher = TimeStretcher::new(8000, 1);
+        let frame = sine(0, 80);
+        let stretched = stretcher.stretch(&frame, 80).unwrap();
+
+        // Segments 20..=40 samples long; the sine repeats after 32
+        assert_eq!(stretched.len(), 80 + 32);
+        for (a, b) in stretched.iter().zip(sine(0, 80 + 32)) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_compress_skips_a_pitch_period() {
+        let stretcher = TimeStretcher::new(8000, 1);
+        let frame = sine(0, 80);
+        let compressed = stretcher.stretch(&frame, -80).unwrap();
+        assert_eq!(compressed.len(), 80 - 32);
+
+        // Continues seamlessly from the previous frame into the next
+        let mut stream = sine(0, 80);
+        stream.extend(&compressed);
+        stream.extend(sine(80, 80));
+        assert!(max_step(&stream) < 0.1);
+    }
+
+    #[test]
+    fn test_no_click_on_noise() {
+        let stretcher = TimeStretcher::new(8000, 2);
+        // Stereo noise-like signal under a low tone, nothing repeats exactly
+        let frame: Vec<f32> = (0..160)
+            .map(|n| ((n * 7919 % 61) as f32 / 61.0 - 0.5) * 0.02 + (n as f32 * 0.01).sin() * 0.5)
+            .collect();
+        for change in [30, -30] {
+            let stretched = stretcher.stretch(&frame, change).unwrap();
+            let frames = stretched.len() as i64 / 2;
+            assert!((20..=40).contains(&(frames - 80).abs()));
+            assert_eq!((frames - 80).signum(), change.signum());
+            assert_eq!(stretched[stretched.len() - 2..], frame[frame.len() - 2..]);
+            for channel in 0..2 {
+                let samples: Vec<f32> =
+                    stretched.iter().skip(channel).step_by(2).copied().collect();
+                assert!(max_step(&samples) < 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn test_short_frames_left_alone() {
+        let stretcher = TimeStretcher::new(48000, 1);
+        assert_eq!(stretcher.min_change(), 120);
+        // Two segments of 2.5 ms do not fit in a 2.5 ms frame
+        assert!(stretcher.stretch(&sine(0, 120), 120).is_none());
+        assert!(stretcher.stretch(&sine(0, 480), 0).is_none());
+        assert_eq!(stretcher.stretch(&sine(0, 480), 1).unwrap().len(), 600);
+    }
+}
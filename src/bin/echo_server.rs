@@ -10,6 +10,9 @@
 //!   cargo run --bin echo-server -- --port 5000 --delay 3000 \
 //!     --signaling-url ws://localhost:8080
 //!
+//! With a relay server (fallback for peers that cannot hole punch):
+//!   cargo run --bin echo-server -- --port 5000 --relay-port 3479
+//!
 //! Environment variables:
 //!   ECHO_DELAY_MS - Delay in milliseconds (default: 3000)
 //!   RUST_LOG - Log level (default: info)
@@ -23,7 +26,10 @@ use clap::Parser;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn, Level};
 
-use jamjam::network::{SignalingClient, SignalingConnection, SignalingMessage, UdpTransport};
+use jamjam::network::{
+    local_clock_us, RelayServer, SignalingClient, SignalingConnection, SignalingMessage,
+    UdpTransport, SIGNALING_VERSION,
+};
 use jamjam::protocol::{LatencyPing, LatencyPong, Packet, PacketType};
 
 /// Echo server for jamjam P2P audio testing
@@ -55,6 +61,10 @@ struct Args {
     #[arg(long)]
     public_addr: Option<SocketAddr>,
 
+    /// Also run a relay server on this port
+    #[arg(long, env = "RELAY_PORT")]
+    relay_port: Option<u16>,
+
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
@@ -156,6 +166,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Echo server listening on {}", transport.local_addr());
 
+    // Start relay server if requested
+    let relay_handle = match args.relay_port {
+        Some(relay_port) => {
+            let relay = RelayServer::bind(&format!("{}:{}", args.host, relay_port)).await?;
+            info!("Relay server listening on {}", relay.local_addr());
+            Some(tokio::spawn(async move {
+                if let Err(e) = relay.run().await {
+                    error!("Relay server stopped: {}", e);
+                }
+            }))
+        }
+        None => None,
+    };
+
     // Connect to signaling server if URL provided
     let signaling_conn: Arc<Mutex<Option<SignalingConnection>>> = Arc::new(Mutex::new(None));
     let mut my_peer_id: Option<String> = None;
@@ -320,10 +344,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             PacketType::LatencyPing => {
                                 // Respond with pong for RTT measurement
                                 if let Some(ping) = LatencyPing::from_bytes(&packet.payload) {
-                                    let pong = LatencyPong {
-                                        original_sent_time_us: ping.sent_time_us,
-                                        ping_sequence: ping.ping_sequence,
-                                    };
+                                    let now = local_clock_us();
+                                    let pong = LatencyPong::reply(&ping, now, now);
                                     let pong_packet = Packet::latency_pong(packet.sequence, &pong);
                                     if let Err(e) = transport.send_to(&pong_packet, sender).await {
                                         warn!("Failed to send latency pong to {}: {}", sender, e);
@@ -360,6 +382,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     sender_handle.abort();
+    if let Some(relay_handle) = relay_handle {
+        relay_handle.abort();
+    }
 
     // Leave room on shutdown
     {
@@ -397,6 +422,8 @@ async fn setup_signaling(
         room_name: full_room_name,
         password: None,
         peer_name: "Echo Bot".to_string(),
+        require_encryption: false,
+        signaling_version: SIGNALING_VERSION,
     })
     .await?;
 
@@ -418,6 +445,7 @@ async fn setup_signaling(
                 candidates: vec![],
                 public_addr: Some(udp_addr),
                 local_addr: Some(udp_addr),
+                public_key: None,
             })
             .await?;
 
@@ -0,0 +1,181 @@
+//! Impairment proxy for testing jamjam on a bad network
+//!
+//! Forwards UDP between two peers while adding loss, delay, jitter,
+//! reordering, duplication and a bandwidth cap. Peer A connects to the A side
+//! port and peer B to the B side port.
+//!
+//! Run with:
+//!   cargo run --bin impairment-proxy -- \
+//!     --peer-a 127.0.0.1:5000 --peer-b 127.0.0.1:5001 \
+//!     --a-port 6000 --b-port 6001 --loss 0.02 --delay 20 --jitter 5
+//!
+//! Bursty (Gilbert-Elliott) loss with a mean burst of 4 packets:
+//!   cargo run --bin impairment-proxy -- ... --burst-to-bad 0.01 --burst-to-good 0.25
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use clap::Parser;
+use tracing::{info, Level};
+
+use jamjam::network::{Direction, ImpairmentConfig, ImpairmentProxy, LossModel};
+
+/// Impairment proxy for jamjam network testing
+#[derive(Parser, Debug)]
+#[command(name = "impairment-proxy")]
+#[command(about = "UDP proxy that simulates a bad network between two jamjam peers")]
+struct Args {
+    /// Address of peer A
+    #[arg(long)]
+    peer_a: SocketAddr,
+
+    /// Address of peer B
+    #[arg(long)]
+    peer_b: SocketAddr,
+
+    /// Port peer A sends to
+    #[arg(long, default_value = "6000")]
+    a_port: u16,
+
+    /// Port peer B sends to
+    #[arg(long, default_value = "6001")]
+    b_port: u16,
+
+    /// Host to bind to
+    #[arg(long, default_value = "0.0.0.0")]
+    host: String,
+
+    /// Packet loss probability (0.0 - 1.0; loss in the good state with bursts)
+    #[arg(long, default_value = "0.0")]
+    loss: f64,
+
+    /// Per-packet probability of entering a loss burst (enables bursty loss)
+    #[arg(long, requires = "burst_to_good")]
+    burst_to_bad: Option<f64>,
+
+    /// Per-packet probability of leaving a loss burst
+    #[arg(long, requires = "burst_to_bad")]
+    burst_to_good: Option<f64>,
+
+    /// One-way delay in milliseconds
+    #[arg(long, default_value = "0")]
+    delay: u64,
+
+    /// Maximum extra random delay in milliseconds
+    #[arg(long, default_value = "0")]
+    jitter: u64,
+
+    /// Probability that a packet is held back so later ones overtake it
+    #[arg(long, default_value = "0.0")]
+    reorder: f64,
+
+    /// Extra delay for held back packets in milliseconds
+    #[arg(long, default_value = "10")]
+    reorder_delay: u64,
+
+    /// Probability that a packet is delivered twice
+    #[arg(long, default_value = "0.0")]
+    duplicate: f64,
+
+    /// Link rate in kbit/s (unlimited if omitted)
+    #[arg(long)]
+    bandwidth_kbps: Option<u64>,
+
+    /// RNG seed
+    #[arg(long, default_value = "0")]
+    seed: u64,
+
+    /// Seconds between statistics lines (0 disables them)
+    #[arg(long, default_value = "5")]
+    stats_interval: u64,
+
+    /// Enable verbose logging
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+impl Args {
+    fn impairment_config(&self) -> ImpairmentConfig {
+        let loss = match (self.burst_to_bad, self.burst_to_good) {
+            (Some(to_bad), Some(to_good)) => LossModel::GilbertElliott {
+                to_bad,
+                to_good,
+                loss_good: self.loss,
+                loss_bad: 1.0,
+            },
+            _ if self.loss > 0.0 => LossModel::Random {
+                probability: self.loss,
+            },
+            _ => LossModel::None,
+        };
+
+        ImpairmentConfig {
+            loss,
+            delay: Duration::from_millis(self.delay),
+            jitter: Duration::from_millis(self.jitter),
+            reorder_probability: self.reorder,
+            reorder_delay: Duration::from_millis(self.reorder_delay),
+            duplicate_probability: self.duplicate,
+            bandwidth_bps: self.bandwidth_kbps.map(|kbps| kbps * 1000),
+            seed: self.seed,
+            ..Default::default()
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let level = if args.verbose {
+        Level::DEBUG
+    } else {
+        Level::INFO
+    };
+
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .compact()
+        .init();
+
+    let config = args.impairment_config();
+    info!("Impairment: {:?}", config);
+
+    let proxy = ImpairmentProxy::bind(
+        &format!("{}:{}", args.host, args.a_port),
+        &format!("{}:{}", args.host, args.b_port),
+        args.peer_a,
+        args.peer_b,
+        config,
+    )
+    .await?;
+    info!(
+        "Peer A ({}) -> {}, peer B ({}) -> {}",
+        args.peer_a,
+        proxy.a_side_addr(),
+        args.peer_b,
+        proxy.b_side_addr()
+    );
+
+    if args.stats_interval == 0 {
+        proxy.run().await?;
+        return Ok(());
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(args.stats_interval));
+    let run = proxy.run();
+    tokio::pin!(run);
+    loop {
+        tokio::select! {
+            result = &mut run => return Ok(result?),
+            _ = interval.tick() => {
+                info!(
+                    "A->B {:?} | B->A {:?}",
+                    proxy.stats(Direction::AToB),
+                    proxy.stats(Direction::BToA)
+                );
+            }
+        }
+    }
+}
@@ -24,8 +24,8 @@ use tracing::{error, info, warn, Level};
 use uuid::Uuid;
 
 use jamjam::network::{
-    generate_invite_code, is_invite_code_format, PeerInfo, RoomInfo, SignalingMessage,
-    SignalingServer, MAX_PEERS_PER_ROOM,
+    generate_invite_code, is_invite_code_format, signaling_version_error, PeerInfo, RoomInfo,
+    SignalingMessage, SignalingServer, MAX_PEERS_PER_ROOM,
 };
 
 /// Signaling server for jamjam P2P audio sessions
@@ -164,6 +164,8 @@ struct RoomState {
     broadcast_tx: broadcast::Sender<SignalingMessage>,
     /// 6-character invite code for easy room sharing
     invite_code: String,
+    /// Peers must encrypt all audio in this room
+    require_encryption: bool,
 }
 
 /// Run the signaling server with TLS support
@@ -332,7 +334,12 @@ async fn process_message(
             room_name,
             password,
             peer_name,
+            require_encryption,
+            signaling_version,
         } => {
+            if let Some(error) = signaling_version_error(signaling_version) {
+                return Some(error);
+            }
             let room_id = Uuid::new_v4().to_string()[..8].to_string();
             let peer_id = Uuid::new_v4();
             let (tx, rx) = broadcast::channel(100);
@@ -357,6 +364,8 @@ async fn process_message(
                 candidates: vec![],
                 public_addr: None,
                 local_addr: None,
+                streams: vec![],
+                public_key: None,
             };
 
             let mut peers = HashMap::new();
@@ -369,6 +378,7 @@ async fn process_message(
                 peers,
                 broadcast_tx: tx,
                 invite_code: invite_code.clone(),
+                require_encryption,
             };
 
             rooms.write().await.insert(room_id.clone(), room);
@@ -392,7 +402,11 @@ async fn process_message(
             room_id,
             password,
             peer_name,
+            signaling_version,
         } => {
+            if let Some(error) = signaling_version_error(signaling_version) {
+                return Some(error);
+            }
             let mut rooms_guard = rooms.write().await;
 
             // Look up room by ID or invite code
@@ -432,6 +446,8 @@ async fn process_message(
                         candidates: vec![],
                         public_addr: None,
                         local_addr: None,
+                        streams: vec![],
+                        public_key: None,
                     };
 
                     let peers: Vec<PeerInfo> = room.peers.values().cloned().collect();
@@ -458,6 +474,7 @@ async fn process_message(
                         room_id: actual_room_id,
                         peer_id,
                         peers,
+                        require_encryption: room.require_encryption,
                     })
                 }
                 None => Some(SignalingMessage::Error {
@@ -489,6 +506,7 @@ async fn process_message(
             candidates,
             public_addr,
             local_addr,
+            public_key,
         } => {
             if let (Some(room_id), Some(peer_id)) =
                 (current_room.as_ref(), current_peer_id.as_ref())
@@ -503,6 +521,27 @@ async fn process_message(
                         // Also update legacy fields for backward compatibility
                         peer.public_addr = public_addr;
                         peer.local_addr = local_addr;
+                        if public_key.is_some() {
+                            peer.public_key = public_key;
+                        }
+
+                        let _ = room
+                            .broadcast_tx
+                            .send(SignalingMessage::PeerUpdated { peer: peer.clone() });
+                    }
+                }
+            }
+            None
+        }
+
+        SignalingMessage::UpdateStreams { streams } => {
+            if let (Some(room_id), Some(peer_id)) =
+                (current_room.as_ref(), current_peer_id.as_ref())
+            {
+                let mut rooms_guard = rooms.write().await;
+                if let Some(room) = rooms_guard.get_mut(room_id) {
+                    if let Some(peer) = room.peers.get_mut(peer_id) {
+                        peer.streams = streams;
 
                         let _ = room
                             .broadcast_tx
@@ -524,6 +563,7 @@ async fn process_message(
                     max_peers: MAX_PEERS_PER_ROOM,
                     has_password: room.password.is_some(),
                     invite_code: room.invite_code.clone(),
+                    requires_encryption: room.require_encryption,
                 })
                 .collect();
 
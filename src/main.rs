@@ -1,18 +1,26 @@
 //! jamjam - Low-latency P2P audio communication for musicians
 
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tracing::{info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use jamjam::audio::{list_input_devices, list_output_devices, AudioConfig, AudioEngine, DeviceId};
+use jamjam::audio::{
+    list_input_devices, list_output_devices, AudioConfig, AudioEngine, CodecConfig, CodecType,
+    DeviceId, MetronomeConfig, Recorder, SyncedMetronome, Tempo,
+};
 use jamjam::network::{
-    candidates_to_addrs, gather_candidates, Connection, ConnectionStats, LatencyBreakdown,
-    LocalLatencyInfo, PeerLatencyInfo, SignalingClient, SignalingConnection, SignalingMessage,
+    gather_candidates, replay, CaptureReader, Connection, ConnectionState, ConnectionStats,
+    EncryptionMode, FecConfig, JitterBufferMode, LatencyBreakdown, LocalLatencyInfo,
+    PeerLatencyInfo, PeerStats, QualityConfig, ReceivePipelineConfig, RedundancyConfig,
+    ReplayConfig, ReplayInterval, Session, SessionConfig, SignalingClient, SignalingConnection,
+    SignalingMessage, MAX_PEERS_PER_ROOM, SIGNALING_VERSION,
 };
 
 #[derive(Parser)]
@@ -79,6 +87,46 @@ enum Commands {
         /// Output device name (use 'devices list' to see available devices)
         #[arg(long)]
         output_device: Option<String>,
+
+        /// Route received audio through a jitter buffer with packet loss concealment
+        #[arg(long, value_enum)]
+        jitter_buffer: Option<JitterBufferArg>,
+
+        /// End-to-end encryption policy
+        #[arg(long, value_enum, default_value = "preferred")]
+        encryption: EncryptionArg,
+
+        /// Audio packets per FEC packet (0 disables FEC for the lowest latency)
+        #[arg(long, default_value = "4")]
+        fec_group_size: usize,
+
+        /// Reed-Solomon FEC packets per group, to recover bursts of this many losses (0 uses XOR)
+        #[arg(long, default_value = "0")]
+        fec_parity: u8,
+
+        /// Previous frames carried in each audio packet, recovered without delay (0-2)
+        #[arg(long, default_value = "0", value_parser = clap::value_parser!(u8).range(0..=2))]
+        redundancy: u8,
+
+        /// Carry previous frames as 16-bit PCM or low-bitrate Opus
+        #[arg(long)]
+        redundancy_reduced: bool,
+
+        /// Preferred audio codec (Opus is used if either peer prefers it and both support it)
+        #[arg(long, value_enum, default_value = "pcm")]
+        codec: CodecArg,
+
+        /// Relay server (IP:PORT) to fall back to if the peer cannot be reached directly
+        #[arg(long)]
+        relay: Option<String>,
+
+        /// Adapt bitrate, frame size, FEC and codec to packet loss, jitter and RTT
+        #[arg(long)]
+        adaptive: bool,
+
+        /// Write received packets to a capture file for `jamjam replay`
+        #[arg(long)]
+        capture: Option<PathBuf>,
     },
 
     /// List rooms on signaling server
@@ -129,6 +177,76 @@ enum Commands {
         /// Skip audio (chat only mode)
         #[arg(long)]
         chat_only: bool,
+
+        /// Route received audio through a jitter buffer with packet loss concealment
+        #[arg(long, value_enum)]
+        jitter_buffer: Option<JitterBufferArg>,
+
+        /// End-to-end encryption policy (rooms that require encryption force "required")
+        #[arg(long, value_enum, default_value = "preferred")]
+        encryption: EncryptionArg,
+
+        /// Audio packets per FEC packet (0 disables FEC for the lowest latency)
+        #[arg(long, default_value = "4")]
+        fec_group_size: usize,
+
+        /// Reed-Solomon FEC packets per group, to recover bursts of this many losses (0 uses XOR)
+        #[arg(long, default_value = "0")]
+        fec_parity: u8,
+
+        /// Previous frames carried in each audio packet, recovered without delay (0-2)
+        #[arg(long, default_value = "0", value_parser = clap::value_parser!(u8).range(0..=2))]
+        redundancy: u8,
+
+        /// Carry previous frames as 16-bit PCM or low-bitrate Opus
+        #[arg(long)]
+        redundancy_reduced: bool,
+
+        /// Preferred audio codec (Opus is used if either peer prefers it and both support it)
+        #[arg(long, value_enum, default_value = "pcm")]
+        codec: CodecArg,
+
+        /// Adapt bitrate, frame size, FEC and codec to packet loss, jitter and RTT
+        #[arg(long)]
+        adaptive: bool,
+
+        /// Write received packets to a capture file for `jamjam replay`
+        #[arg(long)]
+        capture: Option<PathBuf>,
+    },
+
+    /// Replay a packet capture offline through the jitter buffer, decoder and PLC
+    Replay {
+        /// Capture file written with --capture
+        capture: PathBuf,
+
+        /// WAV file to render the received audio to
+        #[arg(short, long, default_value = "replay.wav")]
+        output: PathBuf,
+
+        /// Also write the stats timeline to a CSV file
+        #[arg(long)]
+        timeline: Option<PathBuf>,
+
+        /// Replay audio from this source (IP:PORT, defaults to the first peer heard)
+        #[arg(long)]
+        source: Option<SocketAddr>,
+
+        /// Sample rate in Hz
+        #[arg(long, default_value = "48000")]
+        sample_rate: u32,
+
+        /// Frame size in samples
+        #[arg(long, default_value = "128")]
+        frame_size: u32,
+
+        /// Jitter buffer mode to replay through
+        #[arg(long, value_enum, default_value = "adaptive")]
+        jitter_buffer: JitterBufferArg,
+
+        /// Timeline interval in milliseconds
+        #[arg(long, default_value = "1000")]
+        interval: u64,
     },
 }
 
@@ -138,6 +256,290 @@ enum DevicesAction {
     List,
 }
 
+/// Jitter buffer mode for the receive pipeline
+#[derive(Clone, Copy, ValueEnum)]
+enum JitterBufferArg {
+    /// Adjust delay automatically based on packet loss
+    Adaptive,
+    /// Constant delay
+    Fixed,
+    /// No buffering, conceal gaps only
+    Passthrough,
+}
+
+impl From<JitterBufferArg> for JitterBufferMode {
+    fn from(arg: JitterBufferArg) -> Self {
+        match arg {
+            JitterBufferArg::Adaptive => JitterBufferMode::Adaptive,
+            JitterBufferArg::Fixed => JitterBufferMode::Fixed,
+            JitterBufferArg::Passthrough => JitterBufferMode::Passthrough,
+        }
+    }
+}
+
+/// Preferred audio codec
+#[derive(Clone, Copy, ValueEnum)]
+enum CodecArg {
+    /// Uncompressed, lowest latency (LAN)
+    Pcm,
+    /// Compressed, for limited bandwidth (internet, tethering)
+    Opus,
+}
+
+impl CodecArg {
+    /// Codec configuration for the given audio settings
+    fn codec_config(self, config: &AudioConfig) -> CodecConfig {
+        CodecConfig {
+            codec_type: match self {
+                CodecArg::Pcm => CodecType::Pcm,
+                CodecArg::Opus => CodecType::Opus,
+            },
+            sample_rate: config.sample_rate,
+            channels: config.channels,
+            frame_size: config.frame_size,
+            ..Default::default()
+        }
+    }
+}
+
+/// End-to-end encryption policy
+#[derive(Clone, Copy, ValueEnum)]
+enum EncryptionArg {
+    /// Send everything in cleartext
+    Disabled,
+    /// Encrypt when the peer supports it
+    Preferred,
+    /// Refuse to exchange audio without encryption
+    Required,
+}
+
+impl From<EncryptionArg> for EncryptionMode {
+    fn from(arg: EncryptionArg) -> Self {
+        match arg {
+            EncryptionArg::Disabled => EncryptionMode::Disabled,
+            EncryptionArg::Preferred => EncryptionMode::Preferred,
+            EncryptionArg::Required => EncryptionMode::Required,
+        }
+    }
+}
+
+/// FEC policy from the `--fec-group-size` and `--fec-parity` options
+fn fec_config(group_size: usize, parity: u8) -> FecConfig {
+    if group_size == 0 {
+        FecConfig::disabled()
+    } else if parity == 0 {
+        FecConfig::with_group_size(group_size)
+    } else {
+        FecConfig::reed_solomon(group_size, parity)
+    }
+}
+
+/// Redundant audio policy from the `--redundancy` and `--redundancy-reduced`
+/// options
+fn redundancy_config(depth: u8, reduced: bool) -> RedundancyConfig {
+    if reduced {
+        RedundancyConfig::reduced(depth as usize)
+    } else {
+        RedundancyConfig::with_depth(depth as usize)
+    }
+}
+
+/// Time to wait for the peer's public key when encryption is required
+const KEY_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wait for the key exchange if the connection requires encryption
+async fn wait_for_required_encryption(connection: &Connection) -> Result<()> {
+    if connection.encryption_mode() == EncryptionMode::Required {
+        println!("Waiting for end-to-end encryption...");
+        connection
+            .wait_for_encryption(KEY_EXCHANGE_TIMEOUT)
+            .await
+            .map_err(|e| anyhow::anyhow!("Peer did not complete encryption: {}", e))?;
+        println!("End-to-end encryption established.");
+    }
+    Ok(())
+}
+
+/// Set up the audio receive path on a connection
+///
+/// With a jitter buffer mode, received packets go through the receive pipeline
+/// (reordering, decoding, PLC). Otherwise raw PCM payloads are played in
+/// arrival order.
+fn configure_audio_receive(
+    connection: &mut Connection,
+    jitter_buffer: Option<JitterBufferArg>,
+    config: &AudioConfig,
+    tx_playback: tokio::sync::mpsc::Sender<Vec<f32>>,
+) -> Result<()> {
+    match jitter_buffer {
+        Some(mode) => {
+            let codec = CodecConfig {
+                sample_rate: config.sample_rate,
+                channels: config.channels,
+                frame_size: config.frame_size,
+                ..Default::default()
+            };
+            connection.set_receive_pipeline(ReceivePipelineConfig::new(mode.into(), codec))?;
+            connection.set_decoded_audio_callback(move |samples, _timestamp| {
+                let _ = tx_playback.try_send(samples.to_vec());
+            });
+        }
+        None => {
+            connection.set_audio_callback(move |data, _timestamp| {
+                // Convert bytes back to f32 samples
+                let samples: Vec<f32> = data
+                    .chunks_exact(4)
+                    .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                    .collect();
+
+                let _ = tx_playback.try_send(samples);
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Print where a packet capture was written
+fn report_capture(result: Result<u64, jamjam::network::NetworkError>, path: &Path) {
+    match result {
+        Ok(packets) => println!(
+            "Captured {} packets to {} (inspect with `jamjam replay`)",
+            packets,
+            path.display()
+        ),
+        Err(e) => warn!("Failed to finish capture {}: {}", path.display(), e),
+    }
+}
+
+/// Print a replay timeline, one line per interval
+fn print_replay_timeline(timeline: &[ReplayInterval]) {
+    println!(
+        "\n{:>8}  {:>5}  {:>5}  {:>5}  {:>7}  {:>9}  {:>9}  {:>8}",
+        "time", "recv", "lost", "fec", "decoded", "concealed", "underruns", "jb ms"
+    );
+    for interval in timeline {
+        let marker = if interval.frames_concealed > 0 || interval.underruns > 0 {
+            "  <-"
+        } else {
+            ""
+        };
+        println!(
+            "{:>7.1}s  {:>5}  {:>5}  {:>5}  {:>7}  {:>9}  {:>9}  {:>8.2}{}",
+            interval.start_ms as f64 / 1000.0,
+            interval.packets_received,
+            interval.packets_lost,
+            interval.packets_recovered,
+            interval.frames_decoded,
+            interval.frames_concealed,
+            interval.underruns,
+            interval.jitter_buffer_delay_ms,
+            marker
+        );
+    }
+}
+
+/// Write a replay timeline as CSV
+fn write_replay_timeline(path: &Path, timeline: &[ReplayInterval]) -> Result<()> {
+    let mut csv = String::from(
+        "start_ms,packets_received,packets_lost,packets_recovered,frames_decoded,frames_concealed,underruns,jitter_buffer_delay_ms\n",
+    );
+    for interval in timeline {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{:.2}\n",
+            interval.start_ms,
+            interval.packets_received,
+            interval.packets_lost,
+            interval.packets_recovered,
+            interval.frames_decoded,
+            interval.frames_concealed,
+            interval.underruns,
+            interval.jitter_buffer_delay_ms
+        ));
+    }
+    std::fs::write(path, csv)?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_replay(
+    capture: &Path,
+    output: &Path,
+    timeline: Option<&Path>,
+    source: Option<SocketAddr>,
+    sample_rate: u32,
+    frame_size: u32,
+    jitter_buffer: JitterBufferArg,
+    interval_ms: u64,
+) -> Result<()> {
+    let codec = CodecConfig {
+        sample_rate,
+        channels: 1,
+        frame_size,
+        ..Default::default()
+    };
+    let mut config = ReplayConfig::new(ReceivePipelineConfig::new(jitter_buffer.into(), codec));
+    config.source = source;
+    config.interval = Duration::from_millis(interval_ms);
+
+    let result = replay(CaptureReader::open(capture)?, &config)?;
+    let Some(source) = result.source else {
+        anyhow::bail!("No audio in {}", capture.display());
+    };
+
+    let mut recorder = Recorder::new(sample_rate, 1, 16);
+    recorder.start(output)?;
+    recorder.write_samples(&result.samples)?;
+    let recording = recorder.stop()?;
+
+    let sent = result.packets_received + result.loss.packets_lost;
+    println!("Replayed audio from {}", source);
+    println!(
+        "  Packets:   {} received, {} lost ({:.1} %), {} recovered by FEC",
+        result.packets_received,
+        result.loss.packets_lost,
+        result.loss.packets_lost as f64 * 100.0 / sent.max(1) as f64,
+        result.packets_recovered
+    );
+    println!(
+        "  Frames:    {} decoded ({} from redundancy), {} concealed, {} underruns",
+        result.pipeline.frames_decoded,
+        result.pipeline.frames_from_redundancy,
+        result.pipeline.frames_concealed,
+        result.underruns
+    );
+    println!(
+        "  Late:      {} packets arrived after their playout deadline",
+        result.pipeline.jitter_buffer.late_arrivals
+    );
+    let buffer = &result.pipeline.jitter_buffer;
+    if buffer.stretched_ms > 0.0 || buffer.compressed_ms > 0.0 {
+        println!(
+            "  Stretch:   {:.1} ms added, {:.1} ms removed to change the buffer delay",
+            buffer.stretched_ms, buffer.compressed_ms
+        );
+    }
+    if let Some(drift) = result.pipeline.drift_ppm {
+        println!(
+            "  Drift:     {:+.1} ppm (sender clock against playout)",
+            drift
+        );
+    }
+    print_replay_timeline(&result.timeline);
+
+    println!(
+        "\nRendered {:.1} s of audio to {}",
+        recording.duration_secs,
+        output.display()
+    );
+    if let Some(path) = timeline {
+        write_replay_timeline(path, &result.timeline)?;
+        println!("Timeline written to {}", path.display());
+    }
+
+    Ok(())
+}
+
 fn setup_logging(verbose: bool) {
     let level = if verbose { Level::DEBUG } else { Level::INFO };
 
@@ -177,15 +579,44 @@ fn list_devices() {
     }
 }
 
+/// Display name of a codec
+fn codec_name(codec: CodecType) -> &'static str {
+    match codec {
+        CodecType::Pcm => "PCM",
+        CodecType::Opus => "Opus",
+    }
+}
+
 /// Print session statistics with latency breakdown
 fn print_session_stats(
     stats: &ConnectionStats,
     local_info: &LocalLatencyInfo,
     peer_info: Option<&PeerLatencyInfo>,
     peer_name: Option<&str>,
+    fec: &FecConfig,
 ) {
-    let breakdown =
+    let mut breakdown =
         LatencyBreakdown::calculate(local_info, peer_info, stats.rtt_ms, stats.jitter_ms);
+    if let Some(clock) = &stats.clock {
+        breakdown = breakdown.with_clock(clock);
+    }
+    // What was actually sent: the peer may not use (Reed-Solomon) FEC, and
+    // the quality controller moves the group size
+    let mut fec = *fec;
+    if let Some(capabilities) = &stats.capabilities {
+        fec.enabled &= capabilities.fec;
+        fec.scheme = capabilities.fec_scheme(fec.scheme);
+    }
+    if let Some(quality) = &stats.quality {
+        fec.enabled = quality.fec_group_size.is_some();
+        fec.group_size = quality.fec_group_size.unwrap_or(fec.group_size);
+    }
+    breakdown = breakdown.with_fec(&fec, local_info.capture_buffer_ms);
+    let network_source = if stats.clock.is_some() {
+        "best RTT/2"
+    } else {
+        "RTT/2"
+    };
     let peer_label = peer_name.unwrap_or("Peer");
 
     println!("\n═══════════════════════════════════════════════════════════════");
@@ -196,8 +627,47 @@ fn print_session_stats(
     println!("\n Network:");
     println!("   RTT:          {:>7.2} ms", stats.rtt_ms);
     println!("   Jitter:       {:>7.2} ms", stats.jitter_ms);
-    println!("   Packet Loss:  {:>7.1} %", stats.packet_loss_rate * 100.0);
+    println!(
+        "   Packet Loss:  {:>7.1} %  (recent {:.1} %)",
+        stats.packet_loss_rate * 100.0,
+        stats.recent_packet_loss_rate * 100.0
+    );
+    println!(
+        "   Lost:         {:>7}     (bursts {}, avg {:.1}, max {})",
+        stats.packets_lost, stats.loss_bursts, stats.mean_burst_length, stats.max_burst_length
+    );
+    println!("   Reordered:    {:>7}", stats.packets_reordered);
+    println!("   Duplicated:   {:>7}", stats.packets_duplicated);
     println!("   Uptime:       {:>7} sec", stats.uptime_seconds);
+    println!(
+        "   Encryption:   {:>7}",
+        if stats.encrypted { "on" } else { "off" }
+    );
+    println!("   Codec:        {:>7}", codec_name(stats.send_codec));
+    if let Some(quality) = &stats.quality {
+        println!("   Quality:      {}", quality);
+    }
+    if let Some(clock) = &stats.clock {
+        println!(
+            "   Clock offset: {:>7.2} ms  (drift {:+.1} ppm)",
+            clock.offset_us as f64 / 1000.0,
+            clock.drift_ppm
+        );
+    }
+
+    // How our audio arrives, from the peer's receiver reports
+    if let Some(reception) = &stats.remote_reception {
+        println!("\n Your Audio at {}:", peer_label);
+        println!(
+            "   Packet Loss:  {:>7.1} %  (lost {})",
+            reception.fraction_lost * 100.0,
+            reception.packets_lost
+        );
+        println!("   Jitter:       {:>7.2} ms", reception.jitter_ms);
+        if let Some(delay) = reception.jitter_buffer_delay_ms {
+            println!("   Jitter buf:   {:>7.2} ms", delay);
+        }
+    }
 
     // Latency breakdown
     println!("\n Latency Breakdown:");
@@ -213,8 +683,8 @@ fn print_session_stats(
         local_info.codec, breakdown.upstream.encode_ms
     );
     println!(
-        "     Network:           {:>6.2} ms  (RTT/2)",
-        breakdown.upstream.network_ms
+        "     Network:           {:>6.2} ms  ({})",
+        breakdown.upstream.network_ms, network_source
     );
 
     if breakdown.has_peer_info() {
@@ -254,8 +724,8 @@ fn print_session_stats(
         println!("     [{}] (info not available)", peer_label);
     }
     println!(
-        "     Network:           {:>6.2} ms  (RTT/2)",
-        breakdown.downstream.network_ms
+        "     Network:           {:>6.2} ms  ({})",
+        breakdown.downstream.network_ms, network_source
     );
     println!(
         "     Jitter buffer:     {:>6.2} ms",
@@ -290,16 +760,189 @@ fn print_session_stats(
         breakdown.roundtrip_total_ms
     );
 
+    // FEC trade-off (not part of the totals)
+    if let Some(fec) = &breakdown.fec {
+        println!("\n FEC (Your Audio):");
+        println!(
+            "   Scheme:            {} ({} per {} packets, +{:.0} % packets)",
+            fec.scheme,
+            fec.parity_count,
+            fec.group_size,
+            fec.packet_overhead * 100.0
+        );
+        println!(
+            "   Recovers:          {} lost per group, consecutive or not",
+            fec.parity_count
+        );
+        println!(
+            "   Recovery wait:     {:>7.2} ms  (peer's jitter buffer must cover it)",
+            fec.recovery_delay_ms
+        );
+    }
+
     // Packet stats
     println!("\n Packets:");
     println!("   Sent:     {:>10}", stats.packets_sent);
     println!("   Received: {:>10}", stats.packets_received);
     println!("   Bytes sent:     {:>10}", stats.bytes_sent);
     println!("   Bytes received: {:>10}", stats.bytes_received);
+    if stats.packets_rejected > 0 {
+        println!("   Rejected: {:>10}", stats.packets_rejected);
+    }
+    if stats.packets_rejected_source > 0 {
+        println!("   Wrong source:   {:>10}", stats.packets_rejected_source);
+    }
+    if stats.address_migrations > 0 {
+        println!("   Peer moved:     {:>10}", stats.address_migrations);
+    }
+    if stats.fec_packets_sent > 0 || stats.fec_packets_received > 0 {
+        println!("   FEC sent:       {:>10}", stats.fec_packets_sent);
+        println!("   FEC received:   {:>10}", stats.fec_packets_received);
+        println!("   Recovered:      {:>10}", stats.packets_recovered);
+    }
+
+    println!("\n═══════════════════════════════════════════════════════════════\n");
+}
+
+/// Print per-peer statistics for a mesh session
+fn print_mesh_stats(peers: &[(String, PeerStats)]) {
+    println!("\n═══════════════════════════════════════════════════════════════");
+    println!(" Session Statistics");
+    println!("═══════════════════════════════════════════════════════════════");
+
+    if peers.is_empty() {
+        println!("\n No peers connected.");
+    }
+    for (name, stats) in peers {
+        println!("\n {}:", name);
+        println!("   RTT:           {:>7.2} ms", stats.rtt_ms);
+        if let Some(clock) = &stats.clock {
+            println!(
+                "   Clock offset:  {:>7.2} ms  (drift {:+.1} ppm)",
+                clock.offset_us as f64 / 1000.0,
+                clock.drift_ppm
+            );
+        }
+        println!(
+            "   Sent:          {:>7} packets ({} bytes)",
+            stats.packets_sent, stats.bytes_sent
+        );
+        println!("   Received:      {:>7} packets", stats.packets_received);
+        println!(
+            "   Packet Loss:   {:>7.1} %  (recent {:.1} %)",
+            stats.loss.loss_rate * 100.0,
+            stats.loss.recent_loss_rate * 100.0
+        );
+        println!("   Recovered:     {:>7}", stats.packets_recovered);
+        println!(
+            "   Redundancy:    {:>7}",
+            stats.receive.frames_from_redundancy
+        );
+        println!("   Concealed:     {:>7}", stats.receive.frames_concealed);
+        println!(
+            "   Jitter buffer: {:>7.2} ms",
+            stats.receive.jitter_buffer_delay_ms
+        );
+        if let Some(drift) = stats.receive.drift_ppm {
+            println!("   Audio drift:   {:>+7.1} ppm", drift);
+        }
+        println!(
+            "   Encryption:    {:>7}",
+            if stats.encrypted { "on" } else { "off" }
+        );
+        println!("   Codec:         {:>7}", codec_name(stats.send_codec));
+        if let Some(quality) = &stats.quality {
+            println!("   Quality:       {}", quality);
+        }
+        if let Some(reception) = &stats.remote_reception {
+            println!(
+                "   At peer:       {:>7.1} %  loss (lost {}), jitter {:.2} ms",
+                reception.fraction_lost * 100.0,
+                reception.packets_lost,
+                reception.jitter_ms
+            );
+        }
+    }
 
     println!("\n═══════════════════════════════════════════════════════════════\n");
 }
 
+/// Apply a `/metronome <bpm> [beats]` or `/metronome off` chat command
+///
+/// Starts the shared click, or changes its tempo on the next bar when it is
+/// already running.
+fn handle_metronome_command(metronome: &SyncedMetronome, line: &str) -> Result<String> {
+    let usage = || anyhow::anyhow!("Usage: /metronome <bpm> [beats per bar] | /metronome off");
+    let mut parts = line.split_whitespace().skip(1);
+    let arg = parts.next().ok_or_else(usage)?;
+    if arg.eq_ignore_ascii_case("off") {
+        metronome.stop();
+        return Ok("Metronome stopped".to_string());
+    }
+
+    let bpm: u32 = arg.parse().map_err(|_| usage())?;
+    let beats_per_measure = match parts.next() {
+        Some(beats) => beats.parse().map_err(|_| usage())?,
+        None => metronome.tempo().beats_per_measure,
+    };
+    let tempo = Tempo {
+        bpm,
+        beats_per_measure,
+        beat_value: 4,
+    };
+    if metronome.is_running() {
+        metronome.set_tempo(tempo);
+        Ok(format!("Metronome changes to {} BPM on the next bar", bpm))
+    } else {
+        metronome.start(tempo);
+        let tempo = metronome.tempo();
+        Ok(format!(
+            "Metronome started at {} BPM, {}/{}",
+            tempo.bpm, tempo.beats_per_measure, tempo.beat_value
+        ))
+    }
+}
+
+/// Apply a `/volume`, `/mute` or `/unmute` chat command to a peer's mix
+///
+/// Peers are matched by name (case-insensitive) or by ID prefix.
+async fn handle_mix_command(session: &Session, line: &str) -> Result<String> {
+    let mut parts = line.split_whitespace();
+    let command = parts.next().unwrap_or_default();
+    let target = parts
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Usage: {} <name|id> ...", command))?;
+    let peer = session
+        .peers()
+        .await
+        .into_iter()
+        .find(|p| p.name.eq_ignore_ascii_case(target) || p.id.to_string().starts_with(target))
+        .ok_or_else(|| anyhow::anyhow!("No peer named {}", target))?;
+
+    match command {
+        "/volume" => {
+            let percent: u32 = parts
+                .next()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| anyhow::anyhow!("Usage: /volume <name|id> <0-200>"))?;
+            let percent = percent.min(200);
+            session
+                .set_peer_volume(peer.id, percent as f32 / 100.0)
+                .await?;
+            Ok(format!("{} volume {}%", peer.name, percent))
+        }
+        "/mute" => {
+            session.set_peer_muted(peer.id, true).await?;
+            Ok(format!("{} muted", peer.name))
+        }
+        "/unmute" => {
+            session.set_peer_muted(peer.id, false).await?;
+            Ok(format!("{} unmuted", peer.name))
+        }
+        _ => anyhow::bail!("Unknown command: {}", command),
+    }
+}
+
 async fn run_host(
     port: u16,
     sample_rate: u32,
@@ -351,17 +994,34 @@ async fn run_host(
     let peer_info = connection.peer_latency_info();
     let local_info = LocalLatencyInfo::from_audio_config(frame_size, sample_rate, "pcm");
 
-    print_session_stats(&stats, &local_info, peer_info.as_ref(), None);
+    print_session_stats(
+        &stats,
+        &local_info,
+        peer_info.as_ref(),
+        None,
+        &connection.fec_config(),
+    );
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_join(
     address: String,
     sample_rate: u32,
     frame_size: u32,
     input_device: Option<String>,
     output_device: Option<String>,
+    jitter_buffer: Option<JitterBufferArg>,
+    encryption: EncryptionArg,
+    fec_group_size: usize,
+    fec_parity: u8,
+    redundancy: u8,
+    redundancy_reduced: bool,
+    codec: CodecArg,
+    relay: Option<String>,
+    adaptive: bool,
+    capture: Option<PathBuf>,
 ) -> Result<()> {
     let config = AudioConfig {
         sample_rate,
@@ -374,6 +1034,18 @@ async fn run_join(
 
     let remote_addr: std::net::SocketAddr = address.parse()?;
     let mut connection = Connection::new("0.0.0.0:0").await?;
+    connection.set_encryption_mode(encryption.into());
+    connection.set_fec_config(fec_config(fec_group_size, fec_parity));
+    connection.set_redundancy_config(redundancy_config(redundancy, redundancy_reduced));
+    connection.set_codec_config(codec.codec_config(&config));
+    if let Some(path) = &capture {
+        connection.start_capture(path)?;
+    }
+
+    if let Some(relay) = relay {
+        let relayed_addr = connection.allocate_relay(relay.parse()?).await?;
+        println!("Relayed address: {}", relayed_addr);
+    }
 
     let mut audio_engine = AudioEngine::new(config.clone());
 
@@ -395,20 +1067,31 @@ async fn run_join(
 
     // Set up audio receive callback BEFORE connect
     // (connect starts receive loop which clones the callback)
-    connection.set_audio_callback(move |data, _timestamp| {
-        // Convert bytes back to f32 samples
-        let samples: Vec<f32> = data
-            .chunks_exact(4)
-            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-            .collect();
-
-        let _ = tx_playback.try_send(samples);
+    configure_audio_receive(&mut connection, jitter_buffer, &config, tx_playback)?;
+
+    // Report reconnects; the stream resumes on its own when the peer is heard again
+    let (tx_state, mut rx_state) = tokio::sync::mpsc::unbounded_channel();
+    connection.set_state_callback(move |state| {
+        let _ = tx_state.send(state);
     });
+    let (tx_quality, mut rx_quality) = tokio::sync::mpsc::unbounded_channel();
+    if adaptive {
+        connection.set_quality_config(QualityConfig::default());
+        connection.set_quality_callback(move |decision| {
+            let _ = tx_quality.send(decision);
+        });
+    }
 
-    // Connect to remote (starts receive loop)
-    connection.connect(remote_addr).await?;
+    // Connect to remote (starts receive loop), through the relay if the
+    // peer does not answer directly
+    connection.connect_with_candidates(&[remote_addr]).await?;
+    wait_for_required_encryption(&connection).await?;
 
-    println!("\nConnected to {}. Session active.", address);
+    if connection.is_relayed() {
+        println!("\nConnected to {} via relay. Session active.", address);
+    } else {
+        println!("\nConnected to {}. Session active.", address);
+    }
     println!("Press Ctrl+C to stop.\n");
 
     // Spawn task to send captured audio
@@ -419,7 +1102,8 @@ async fn run_join(
         let mut packet_count = 0u64;
         while let Some((samples, timestamp)) = rx_capture.recv().await {
             let conn = connection_for_send.lock().await;
-            if conn.is_connected() {
+            // Keep sending while reconnecting so the peer hears us as soon as the path is back
+            if conn.state().can_transmit() {
                 if let Err(e) = conn.send_audio(&samples, timestamp).await {
                     tracing::warn!("Failed to send audio: {}", e);
                 } else {
@@ -447,28 +1131,75 @@ async fn run_join(
                     tracing::debug!("Received {} audio packets for playback", received_count);
                 }
             }
+            Some(state) = rx_state.recv() => {
+                match state {
+                    ConnectionState::Reconnecting => println!("⚠️  Connection lost, reconnecting..."),
+                    ConnectionState::Connected => println!("✅ Reconnected to {}", address),
+                    ConnectionState::Failed => {
+                        println!("❌ Could not reconnect to {}", address);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            Some(decision) = rx_quality.recv() => {
+                println!("🎚️  Audio quality: {}", decision);
+            }
         }
     }
 
     send_task.abort();
 
-    let (stats, peer_info) = {
+    let (stats, peer_info, pipeline_stats, fec) = {
         let mut conn = connection_arc.lock().await;
         let stats = conn.stats();
         let peer_info = conn.peer_latency_info();
+        let pipeline_stats = conn.receive_pipeline_stats();
         conn.disconnect();
-        (stats, peer_info)
+        if let Some(path) = &capture {
+            report_capture(conn.stop_capture(), path);
+        }
+        (stats, peer_info, pipeline_stats, conn.fec_config())
     };
 
     audio_engine.stop_capture();
     audio_engine.stop_playback();
 
-    let local_info = LocalLatencyInfo::from_audio_config(frame_size, sample_rate, "pcm");
-    print_session_stats(&stats, &local_info, peer_info.as_ref(), None);
+    let mut local_info = LocalLatencyInfo::from_audio_config(frame_size, sample_rate, "pcm");
+    if let Some(pipeline_stats) = pipeline_stats {
+        local_info.set_jitter_buffer_ms(pipeline_stats.jitter_buffer_delay_ms);
+    }
+    print_session_stats(&stats, &local_info, peer_info.as_ref(), None, &fec);
 
     Ok(())
 }
 
+/// Gather candidates again and publish them to the room
+///
+/// Peers restart ICE against the new candidates and so do we.
+async fn republish_candidates(
+    session: Arc<Session>,
+    signaling: Arc<tokio::sync::Mutex<SignalingConnection>>,
+) {
+    let local_addr = session.local_addr();
+    let candidates = gather_candidates(local_addr.port()).await;
+    info!("Re-gathered {} candidates", candidates.len());
+    session.set_local_candidates(candidates.clone());
+
+    let mut conn = signaling.lock().await;
+    if let Err(e) = conn
+        .send(SignalingMessage::UpdatePeerInfo {
+            public_addr: candidates.first().map(|c| c.address),
+            local_addr: Some(local_addr),
+            candidates,
+            public_key: Some(session.public_key()),
+        })
+        .await
+    {
+        warn!("Failed to republish candidates: {}", e);
+    }
+}
+
 /// Process signaling events and print them to stdout
 fn handle_signaling_event(msg: &SignalingMessage) {
     match msg {
@@ -540,9 +1271,19 @@ async fn run_rooms(server: String) -> Result<()> {
                     } else {
                         ""
                     };
+                    let encryption_str = if room.requires_encryption {
+                        " (encrypted)"
+                    } else {
+                        ""
+                    };
                     println!(
-                        "  {} - {} ({}/{} peers){}",
-                        room.id, room.name, room.peer_count, room.max_peers, password_str
+                        "  {} - {} ({}/{} peers){}{}",
+                        room.id,
+                        room.name,
+                        room.peer_count,
+                        room.max_peers,
+                        password_str,
+                        encryption_str
                     );
                 }
             }
@@ -570,6 +1311,15 @@ async fn run_join_room(
     message: Option<String>,
     timeout_secs: u64,
     chat_only: bool,
+    jitter_buffer: Option<JitterBufferArg>,
+    encryption: EncryptionArg,
+    fec_group_size: usize,
+    fec_parity: u8,
+    redundancy: u8,
+    redundancy_reduced: bool,
+    codec: CodecArg,
+    adaptive: bool,
+    capture: Option<PathBuf>,
 ) -> Result<()> {
     let config = AudioConfig {
         sample_rate,
@@ -588,26 +1338,35 @@ async fn run_join_room(
         room_id: room_id.clone(),
         password: None,
         peer_name: peer_name.clone(),
+        signaling_version: SIGNALING_VERSION,
     })
     .await?;
 
-    let (my_peer_id, peers) = match conn.recv().await? {
+    let joined = conn.recv().await?;
+    let (my_peer_id, encryption_mode) = match &joined {
         SignalingMessage::RoomJoined {
             room_id: joined_room_id,
             peer_id,
             peers,
+            require_encryption,
         } => {
             info!("Joined room {} as peer {}", joined_room_id, peer_id);
             println!("\nJoined room: {}", joined_room_id);
             println!("Your peer ID: {}", peer_id);
+            let encryption_mode = if *require_encryption {
+                println!("Room requires end-to-end encryption.");
+                EncryptionMode::Required
+            } else {
+                encryption.into()
+            };
             println!("\nPeers in room ({}):", peers.len());
-            for peer in &peers {
+            for peer in peers {
                 println!(
                     "  - {} (id: {}, addr: {:?})",
                     peer.name, peer.id, peer.public_addr
                 );
             }
-            (peer_id, peers)
+            (*peer_id, encryption_mode)
         }
         SignalingMessage::Error { message } => {
             anyhow::bail!("Failed to join room: {}", message);
@@ -617,40 +1376,26 @@ async fn run_join_room(
         }
     };
 
-    // Find a peer with address(es) to connect to
-    // Prefer peers with candidates, fall back to legacy public_addr
-    // Skip peer search if chat_only mode is enabled
-    let target_peer = if chat_only {
-        None
-    } else {
-        peers
-            .iter()
-            .find(|p| !p.candidates.is_empty() || p.public_addr.is_some())
-    };
-
-    if let Some(peer) = target_peer {
-        let peer_display_name = peer.name.clone();
-
-        // Collect remote addresses to try (prefer candidates, fall back to legacy)
-        let remote_addrs: Vec<std::net::SocketAddr> = if !peer.candidates.is_empty() {
-            candidates_to_addrs(&peer.candidates)
-        } else if let Some(addr) = peer.public_addr {
-            vec![addr]
-        } else {
-            vec![]
-        };
-
-        println!(
-            "\nConnecting to peer {} ({} address candidates)...",
-            peer.name,
-            remote_addrs.len()
-        );
-        for (i, addr) in remote_addrs.iter().enumerate() {
-            info!("  Candidate {}: {}", i, addr);
+    // A one-shot message (--message) needs no audio either
+    if !chat_only && message.is_none() {
+        // Full mesh: one session socket, one stream per peer in the room
+        let mut session = Session::new(SessionConfig {
+            max_peers: MAX_PEERS_PER_ROOM,
+            encryption: encryption_mode,
+            codec: codec.codec_config(&config),
+            jitter_buffer: jitter_buffer
+                .map(Into::into)
+                .unwrap_or(JitterBufferMode::Passthrough),
+            fec: fec_config(fec_group_size, fec_parity),
+            redundancy: redundancy_config(redundancy, redundancy_reduced),
+            quality: adaptive.then(QualityConfig::default),
+            ..Default::default()
+        })
+        .await?;
+        if let Some(path) = &capture {
+            session.start_capture(path)?;
         }
-
-        let mut connection = Connection::new("0.0.0.0:0").await?;
-        let local_addr = connection.local_addr();
+        let local_addr = session.local_addr();
         info!("Local UDP socket: {}", local_addr);
 
         // Gather our local candidates
@@ -666,6 +1411,7 @@ async fn run_join_room(
             candidates: candidates.clone(),
             public_addr: candidates.first().map(|c| c.address),
             local_addr: Some(local_addr),
+            public_key: Some(session.public_key()),
         })
         .await?;
 
@@ -685,42 +1431,53 @@ async fn run_join_room(
 
         audio_engine.start_playback(output_id.as_ref())?;
 
-        // Set up audio receive callback BEFORE connect
-        // (connect starts receive loop which clones the callback)
-        connection.set_audio_callback(move |data, _timestamp| {
-            let samples: Vec<f32> = data
-                .chunks_exact(4)
-                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-                .collect();
-            let _ = tx_playback.try_send(samples);
+        // Set up the callbacks BEFORE start
+        // (start spawns the mixer and ICE loop which clone the callbacks)
+        session.set_mixed_audio_callback(move |samples, _timestamp| {
+            let _ = tx_playback.try_send(samples.to_vec());
         });
-
-        // Connect to remote peer using candidates (Happy Eyeballs style)
-        connection.connect_with_candidates(&remote_addrs).await?;
-
-        println!("\nConnected to peer. Session active.");
+        let (tx_state, mut rx_state) = tokio::sync::mpsc::unbounded_channel();
+        session.set_peer_state_callback(move |peer_id, state| {
+            let _ = tx_state.send((peer_id, state));
+        });
+        let (tx_quality, mut rx_quality) = tokio::sync::mpsc::unbounded_channel();
+        session.set_quality_callback(move |peer_id, decision| {
+            let _ = tx_quality.send((peer_id, decision));
+        });
+        // Shared click, started by whoever types /metronome first
+        let metronome = Arc::new(SyncedMetronome::new(
+            MetronomeConfig::default(),
+            config.sample_rate,
+        ));
+        session.set_metronome(metronome.clone());
+        session.set_local_candidates(candidates);
+        session.start();
+
+        // Connect to everyone already in the room
+        session.handle_signaling_message(&joined).await?;
+
+        println!("\nSession active. Peers are added as they join the room.");
         println!("Audio config: {:?}", config);
         println!("\n💬 Chat enabled. Type a message and press Enter to send.");
+        println!("   /volume <name> <0-200>, /mute <name> and /unmute <name> adjust a peer's mix.");
+        println!("   /metronome <bpm> [beats] and /metronome off control everyone's click.");
         println!("Press Ctrl+C to stop.\n");
         print!("chat> ");
         let _ = std::io::Write::flush(&mut std::io::stdout());
 
-        let connection_arc = Arc::new(tokio::sync::Mutex::new(connection));
-        let connection_for_send = connection_arc.clone();
+        let session = Arc::new(session);
+        let session_for_send = session.clone();
 
         // Spawn task to send captured audio
         let send_task = tokio::spawn(async move {
             let mut packet_count = 0u64;
             while let Some((samples, timestamp)) = rx_capture.recv().await {
-                let conn = connection_for_send.lock().await;
-                if conn.is_connected() {
-                    if let Err(e) = conn.send_audio(&samples, timestamp).await {
-                        warn!("Failed to send audio: {}", e);
-                    } else {
-                        packet_count += 1;
-                        if packet_count.is_multiple_of(100) {
-                            tracing::debug!("Sent {} audio packets", packet_count);
-                        }
+                if let Err(e) = session_for_send.broadcast_audio(&samples, timestamp).await {
+                    warn!("Failed to send audio: {}", e);
+                } else {
+                    packet_count += 1;
+                    if packet_count.is_multiple_of(100) {
+                        tracing::debug!("Sent {} audio frames", packet_count);
                     }
                 }
             }
@@ -766,7 +1523,10 @@ async fn run_join_room(
         let my_peer_id_for_chat = my_peer_id.to_string();
         let peer_name_for_chat = peer_name.clone();
 
-        // Process received audio and chat on main thread using select
+        // Re-gathers candidates after a network change, one at a time
+        let mut republish_task: Option<tokio::task::JoinHandle<()>> = None;
+
+        // Process mixed audio and chat on main thread using select
         let mut received_count = 0u64;
         loop {
             tokio::select! {
@@ -778,7 +1538,7 @@ async fn run_join_room(
                     audio_engine.enqueue_playback(&samples);
                     received_count += 1;
                     if received_count.is_multiple_of(100) {
-                        tracing::debug!("Received {} audio packets for playback", received_count);
+                        tracing::debug!("Played {} mixed frames", received_count);
                     }
                 }
                 Some(msg) = rx_signaling.recv() => {
@@ -788,13 +1548,65 @@ async fn run_join_room(
                             continue;
                         }
                     }
+                    if let Err(e) = session.handle_signaling_message(&msg).await {
+                        warn!("Failed to update mesh: {}", e);
+                    }
                     handle_signaling_event(&msg);
                 }
+                Some((peer_id, state)) = rx_state.recv() => {
+                    let name = session
+                        .peers()
+                        .await
+                        .into_iter()
+                        .find(|p| p.id == peer_id)
+                        .map(|p| p.name)
+                        .unwrap_or_else(|| peer_id.to_string());
+                    match state {
+                        ConnectionState::Connected => println!("\n🔊 Audio connected to {}", name),
+                        ConnectionState::Reconnecting => {
+                            println!("\n⚠️  Lost {}, reconnecting...", name);
+                            // Our address may have changed; publish fresh candidates
+                            // so the peer restarts ICE against them
+                            if republish_task.as_ref().is_none_or(|t| t.is_finished()) {
+                                republish_task = Some(tokio::spawn(republish_candidates(
+                                    session.clone(),
+                                    signaling_conn_arc.clone(),
+                                )));
+                            }
+                        }
+                        ConnectionState::Failed => println!("\n❌ Could not reconnect to {}", name),
+                        _ => continue,
+                    }
+                    print!("chat> ");
+                    let _ = std::io::Write::flush(&mut std::io::stdout());
+                }
+                Some((peer_id, decision)) = rx_quality.recv() => {
+                    let name = session
+                        .peers()
+                        .await
+                        .into_iter()
+                        .find(|p| p.id == peer_id)
+                        .map(|p| p.name)
+                        .unwrap_or_else(|| peer_id.to_string());
+                    println!("\n🎚️  Audio quality to {}: {}", name, decision);
+                    print!("chat> ");
+                    let _ = std::io::Write::flush(&mut std::io::stdout());
+                }
                 line_result = stdin_reader.next_line() => {
                     match line_result {
                         Ok(Some(line)) => {
                             let line = line.trim();
-                            if !line.is_empty() {
+                            if line.starts_with("/metronome") {
+                                match handle_metronome_command(&metronome, line) {
+                                    Ok(reply) => println!("🥁 {}", reply),
+                                    Err(e) => println!("⚠️  {}", e),
+                                }
+                            } else if line.starts_with('/') {
+                                match handle_mix_command(&session, line).await {
+                                    Ok(reply) => println!("🎚  {}", reply),
+                                    Err(e) => println!("⚠️  {}", e),
+                                }
+                            } else if !line.is_empty() {
                                 let mut conn_guard = signaling_conn_arc.lock().await;
                                 if let Err(e) = send_chat_message(
                                     &mut conn_guard,
@@ -824,25 +1636,25 @@ async fn run_join_room(
 
         send_task.abort();
         signaling_recv_task.abort();
+        if let Some(task) = republish_task {
+            task.abort();
+        }
 
-        let (stats, peer_latency_info) = {
-            let mut conn = connection_arc.lock().await;
-            let stats = conn.stats();
-            let peer_latency_info = conn.peer_latency_info();
-            conn.disconnect();
-            (stats, peer_latency_info)
-        };
+        let mut peer_stats = Vec::new();
+        for peer in session.peers().await {
+            if let Some(stats) = session.peer_stats(peer.id).await {
+                peer_stats.push((peer.name, stats));
+            }
+        }
+        if let Some(path) = &capture {
+            report_capture(session.stop_capture(), path);
+        }
+        drop(session);
 
         audio_engine.stop_capture();
         audio_engine.stop_playback();
 
-        let local_info = LocalLatencyInfo::from_audio_config(frame_size, sample_rate, "pcm");
-        print_session_stats(
-            &stats,
-            &local_info,
-            peer_latency_info.as_ref(),
-            Some(&peer_display_name),
-        );
+        print_mesh_stats(&peer_stats);
 
         // Leave room
         {
@@ -855,8 +1667,6 @@ async fn run_join_room(
         // Chat-only mode (no audio connection)
         if chat_only {
             println!("\n📝 Chat-only mode (no audio connection)");
-        } else {
-            println!("\nNo peers with addresses found in room. Waiting for peers...");
         }
 
         // Channel for signaling events
@@ -1044,6 +1854,16 @@ async fn main() -> Result<()> {
             frame_size,
             input_device,
             output_device,
+            jitter_buffer,
+            encryption,
+            fec_group_size,
+            fec_parity,
+            redundancy,
+            redundancy_reduced,
+            codec,
+            relay,
+            adaptive,
+            capture,
         } => {
             run_join(
                 address,
@@ -1051,6 +1871,16 @@ async fn main() -> Result<()> {
                 frame_size,
                 input_device,
                 output_device,
+                jitter_buffer,
+                encryption,
+                fec_group_size,
+                fec_parity,
+                redundancy,
+                redundancy_reduced,
+                codec,
+                relay,
+                adaptive,
+                capture,
             )
             .await?;
         }
@@ -1068,6 +1898,15 @@ async fn main() -> Result<()> {
             message,
             timeout,
             chat_only,
+            jitter_buffer,
+            encryption,
+            fec_group_size,
+            fec_parity,
+            redundancy,
+            redundancy_reduced,
+            codec,
+            adaptive,
+            capture,
         } => {
             run_join_room(
                 server,
@@ -1080,9 +1919,39 @@ async fn main() -> Result<()> {
                 message,
                 timeout,
                 chat_only,
+                jitter_buffer,
+                encryption,
+                fec_group_size,
+                fec_parity,
+                redundancy,
+                redundancy_reduced,
+                codec,
+                adaptive,
+                capture,
             )
             .await?;
         }
+        Commands::Replay {
+            capture,
+            output,
+            timeline,
+            source,
+            sample_rate,
+            frame_size,
+            jitter_buffer,
+            interval,
+        } => {
+            run_replay(
+                &capture,
+                &output,
+                timeline.as_deref(),
+                source,
+                sample_rate,
+                frame_size,
+                jitter_buffer,
+                interval,
+            )?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,387 @@
+//! Per-peer protocol version and capability negotiation
+//!
+//! Right after connecting, peers exchange `PacketType::Hello` packets with the
+//! range of protocol versions they speak and what they support: codecs, FEC,
+//! encryption, additional streams and the largest frame they accept. Both
+//! sides then use the newest common version and the common subset of the
+//! capabilities.
+//!
+//! Hellos are sent in cleartext like the key exchange, so a peer that cannot
+//! meet our encryption policy gets a clear error rather than silence. Peers
+//! predating the handshake never answer; until a hello arrives the peer is
+//! assumed to speak version 1 without additional streams or Reed-Solomon FEC.
+//!
+//! A peer is incompatible if there is no common version or codec, one side
+//! requires encryption the other cannot do, or our frames are larger than the
+//! peer accepts.
+
+use tracing::{debug, warn};
+
+use crate::audio::CodecType;
+use crate::protocol::{Capabilities, HelloPayload, Packet, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+use super::encryption::EncryptionMode;
+use super::error::NetworkError;
+use super::fec::FecScheme;
+
+/// Largest audio frame we accept, in samples per channel (120 ms at 48 kHz,
+/// the longest Opus frame)
+pub const MAX_FRAME_SIZE: u32 = 5760;
+
+/// What both sides of a connection support
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiatedCapabilities {
+    /// Protocol version in use
+    pub version: u8,
+    /// Codecs both sides can decode
+    pub codecs: Vec<CodecType>,
+    /// Both sides use FEC
+    pub fec: bool,
+    /// Both sides recover from Reed-Solomon FEC
+    pub reed_solomon: bool,
+    /// Both sides play redundant audio
+    pub redundancy: bool,
+    /// Both sides answer path challenges
+    pub path_validation: bool,
+    /// Both sides can encrypt
+    pub encryption: bool,
+    /// Additional streams that may be sent to the peer
+    pub max_streams: u8,
+    /// Largest audio frame either side sends, in samples per channel
+    pub max_frame_size: u32,
+}
+
+impl NegotiatedCapabilities {
+    /// Assumed capabilities of a peer that has not sent a hello
+    fn assumed(local: &Capabilities) -> Self {
+        Self {
+            version: MIN_PROTOCOL_VERSION,
+            codecs: local.codecs.clone(),
+            fec: local.fec,
+            // Older peers would take Reed-Solomon packets for XOR
+            reed_solomon: false,
+            // Older peers would play the redundant frames as audio
+            redundancy: false,
+            // Older peers cannot parse path challenges
+            path_validation: false,
+            encryption: local.encryption,
+            max_streams: 0,
+            max_frame_size: local.max_frame_size,
+        }
+    }
+
+    /// Scheme to protect audio to the peer with: `wanted`, unless that is
+    /// Reed-Solomon and the peer only recovers from XOR
+    pub fn fec_scheme(&self, wanted: FecScheme) -> FecScheme {
+        match wanted {
+            FecScheme::ReedSolomon { .. } if !self.reed_solomon => FecScheme::Xor,
+            scheme => scheme,
+        }
+    }
+}
+
+/// Our capabilities with the given encryption policy
+///
+/// `max_streams` is the number of additional streams we play; a `Connection`
+/// plays none. Path challenges are only answered by a `Connection`, which
+/// turns `path_validation` on.
+pub(crate) fn local_capabilities(encryption: EncryptionMode, max_streams: u8) -> Capabilities {
+    Capabilities {
+        codecs: CodecType::available(),
+        fec: true,
+        reed_solomon: true,
+        redundancy: true,
+        path_validation: false,
+        encryption: encryption.is_enabled(),
+        requires_encryption: encryption == EncryptionMode::Required,
+        max_streams,
+        max_frame_size: MAX_FRAME_SIZE,
+    }
+}
+
+/// Find the common subset of our and the peer's capabilities
+///
+/// `frame_size` is the frame size we start sending with.
+pub fn negotiate(
+    local: &Capabilities,
+    frame_size: u32,
+    peer: &HelloPayload,
+) -> Result<NegotiatedCapabilities, NetworkError> {
+    let version = PROTOCOL_VERSION.min(peer.max_version);
+    if version < MIN_PROTOCOL_VERSION.max(peer.min_version) {
+        return Err(incompatible(format!(
+            "peer speaks protocol versions {}-{}, we speak {}-{}",
+            peer.min_version, peer.max_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        )));
+    }
+
+    let remote = &peer.capabilities;
+    let codecs: Vec<CodecType> = local
+        .codecs
+        .iter()
+        .copied()
+        .filter(|codec| remote.codecs.contains(codec))
+        .collect();
+    if codecs.is_empty() {
+        return Err(incompatible(format!(
+            "no common codec (peer decodes {:?}, we decode {:?})",
+            remote.codecs, local.codecs
+        )));
+    }
+
+    if local.requires_encryption && !remote.encryption {
+        return Err(incompatible(
+            "encryption is required here but the peer cannot encrypt".to_string(),
+        ));
+    }
+    if remote.requires_encryption && !local.encryption {
+        return Err(incompatible(
+            "the peer requires encryption but it is disabled here".to_string(),
+        ));
+    }
+
+    let max_frame_size = local.max_frame_size.min(remote.max_frame_size);
+    if frame_size > max_frame_size {
+        return Err(incompatible(format!(
+            "the peer accepts frames up to {} samples, we send {}",
+            remote.max_frame_size, frame_size
+        )));
+    }
+
+    Ok(NegotiatedCapabilities {
+        version,
+        codecs,
+        fec: local.fec && remote.fec,
+        reed_solomon: local.reed_solomon && remote.reed_solomon,
+        redundancy: local.redundancy && remote.redundancy,
+        path_validation: local.path_validation && remote.path_validation,
+        encryption: local.encryption && remote.encryption,
+        max_streams: local.max_streams.min(remote.max_streams),
+        max_frame_size,
+    })
+}
+
+fn incompatible(reason: String) -> NetworkError {
+    NetworkError::IncompatiblePeer(reason)
+}
+
+/// Version and capability negotiation with one peer
+pub(crate) struct CapabilityNegotiation {
+    local: Capabilities,
+    /// Frame size we start sending with
+    frame_size: u32,
+    /// Hello received from the peer
+    peer_hello: Option<HelloPayload>,
+    /// Outcome of the last hello (Err holds the reason the peer is
+    /// incompatible)
+    result: Option<Result<NegotiatedCapabilities, String>>,
+}
+
+impl CapabilityNegotiation {
+    /// Create negotiation state with our capabilities
+    pub fn new(local: Capabilities, frame_size: u32) -> Self {
+        Self {
+            local,
+            frame_size,
+            peer_hello: None,
+            result: None,
+        }
+    }
+
+    /// Check if the peer's hello is still missing
+    pub fn needs_hello(&self) -> bool {
+        self.peer_hello.is_none()
+    }
+
+    /// Build our hello packet
+    pub fn hello_packet(&self, sequence: u32, ack: bool) -> Packet {
+        Packet::hello(
+            sequence,
+            &HelloPayload {
+                min_version: MIN_PROTOCOL_VERSION,
+                max_version: PROTOCOL_VERSION,
+                capabilities: self.local.clone(),
+                ack,
+            },
+        )
+    }
+
+    /// Handle the peer's hello
+    ///
+    /// Returns true if the peer has not seen our hello yet and we should
+    /// reply (also when it is incompatible, so it can tell its user why).
+    pub fn handle_hello(&mut self, hello: HelloPayload) -> bool {
+        let reply = !hello.ack;
+        let hello = HelloPayload {
+            ack: false,
+            ..hello
+        };
+        if self.peer_hello.as_ref() == Some(&hello) {
+            return reply;
+        }
+
+        let result = negotiate(&self.local, self.frame_size, &hello).map_err(|e| e.to_string());
+        match &result {
+            Ok(negotiated) => debug!("Negotiated capabilities: {:?}", negotiated),
+            Err(reason) => warn!("{}", reason),
+        }
+        self.peer_hello = Some(hello);
+        self.result = Some(result);
+        reply
+    }
+
+    /// Capabilities in use (assumed until the peer's hello arrives)
+    pub fn negotiated(&self) -> NegotiatedCapabilities {
+        match &self.result {
+            Some(Ok(negotiated)) => negotiated.clone(),
+            _ => NegotiatedCapabilities::assumed(&self.local),
+        }
+    }
+
+    /// Capabilities agreed with the peer (None before its hello or if it is
+    /// incompatible)
+    pub fn agreed(&self) -> Option<NegotiatedCapabilities> {
+        self.result.as_ref()?.as_ref().ok().cloned()
+    }
+
+    /// Why the peer is incompatible, if it is
+    pub fn incompatibility(&self) -> Option<NetworkError> {
+        match &self.result {
+            Some(Err(reason)) => Some(NetworkError::IncompatiblePeer(reason.clone())),
+            _ => None,
+        }
+    }
+
+    /// Check if the peer's hello showed it cannot talk to us
+    pub fn is_incompatible(&self) -> bool {
+        matches!(self.result, Some(Err(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(capabilities: Capabilities) -> HelloPayload {
+        HelloPayload {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            capabilities,
+            ack: false,
+        }
+    }
+
+    #[test]
+    fn test_common_subset() {
+        let local = local_capabilities(EncryptionMode::Preferred, 8);
+        let peer = Capabilities {
+            codecs: vec![CodecType::Pcm],
+            fec: false,
+            reed_solomon: false,
+            redundancy: false,
+            path_validation: false,
+            encryption: true,
+            requires_encryption: false,
+            max_streams: 2,
+            max_frame_size: 960,
+        };
+
+        let negotiated = negotiate(&local, 480, &hello(peer)).unwrap();
+        assert_eq!(negotiated.version, PROTOCOL_VERSION);
+        assert_eq!(negotiated.codecs, vec![CodecType::Pcm]);
+        assert!(!negotiated.fec);
+        assert!(!negotiated.reed_solomon);
+        assert!(!negotiated.redundancy);
+        assert!(!negotiated.path_validation);
+        assert_eq!(
+            negotiated.fec_scheme(FecScheme::ReedSolomon { parity: 2 }),
+            FecScheme::Xor
+        );
+        assert!(negotiated.encryption);
+        assert_eq!(negotiated.max_streams, 2);
+        assert_eq!(negotiated.max_frame_size, 960);
+    }
+
+    #[test]
+    fn test_no_common_version() {
+        let local = local_capabilities(EncryptionMode::Preferred, 0);
+        let mut newer = hello(local.clone());
+        newer.min_version = PROTOCOL_VERSION + 1;
+        newer.max_version = PROTOCOL_VERSION + 2;
+
+        let error = negotiate(&local, 480, &newer).unwrap_err();
+        assert!(matches!(error, NetworkError::IncompatiblePeer(_)));
+        assert!(error.to_string().contains("protocol versions"));
+    }
+
+    #[test]
+    fn test_newer_peer_uses_our_version() {
+        let local = local_capabilities(EncryptionMode::Preferred, 0);
+        let mut newer = hello(local.clone());
+        newer.max_version = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            negotiate(&local, 480, &newer).unwrap().version,
+            PROTOCOL_VERSION
+        );
+    }
+
+    #[test]
+    fn test_required_encryption_needs_peer_support() {
+        let local = local_capabilities(EncryptionMode::Required, 0);
+        let peer = local_capabilities(EncryptionMode::Disabled, 0);
+        let error = negotiate(&local, 480, &hello(peer.clone())).unwrap_err();
+        assert!(error.to_string().contains("encryption is required"));
+
+        let error = negotiate(&peer, 480, &hello(local)).unwrap_err();
+        assert!(error.to_string().contains("peer requires encryption"));
+    }
+
+    #[test]
+    fn test_frame_size_limit() {
+        let local = local_capabilities(EncryptionMode::Preferred, 0);
+        let mut peer = local.clone();
+        peer.max_frame_size = 240;
+        assert!(negotiate(&local, 480, &hello(peer.clone())).is_err());
+        assert_eq!(
+            negotiate(&local, 240, &hello(peer)).unwrap().max_frame_size,
+            240
+        );
+    }
+
+    #[test]
+    fn test_assumed_until_hello() {
+        let local = local_capabilities(EncryptionMode::Preferred, 4);
+        let mut negotiation = CapabilityNegotiation::new(local.clone(), 480);
+        assert!(negotiation.needs_hello());
+        assert_eq!(negotiation.negotiated().max_streams, 0);
+        assert!(!negotiation.negotiated().reed_solomon);
+        assert!(!negotiation.negotiated().redundancy);
+        assert!(negotiation.agreed().is_none());
+
+        assert!(negotiation.handle_hello(hello(local.clone())));
+        assert!(!negotiation.needs_hello());
+        assert_eq!(negotiation.negotiated().max_streams, 4);
+        assert!(negotiation.agreed().is_some());
+        let rs = FecScheme::ReedSolomon { parity: 2 };
+        assert_eq!(negotiation.negotiated().fec_scheme(rs), rs);
+
+        let mut acked = hello(local);
+        acked.ack = true;
+        assert!(!negotiation.handle_hello(acked));
+    }
+
+    #[test]
+    fn test_incompatible_peer_still_gets_reply() {
+        let mut negotiation =
+            CapabilityNegotiation::new(local_capabilities(EncryptionMode::Required, 0), 480);
+        let peer = local_capabilities(EncryptionMode::Disabled, 0);
+        assert!(negotiation.handle_hello(hello(peer)));
+        assert!(negotiation.is_incompatible());
+        assert!(matches!(
+            negotiation.incompatibility(),
+            Some(NetworkError::IncompatiblePeer(_))
+        ));
+        // Falls back to what is assumed of a peer without a hello
+        assert_eq!(negotiation.negotiated().max_streams, 0);
+    }
+}
@@ -0,0 +1,291 @@
+//! Packet capture files
+//!
+//! A `Connection` or `Session` can record every packet it receives, after
+//! decryption, together with its arrival time and source address, so that a
+//! glitch reported by a user can be replayed offline (see `replay`).
+//!
+//! File format (big-endian):
+//!
+//! ```text
+//! "JJCP" | version (1)
+//! then per packet:
+//! arrival_us (8) | family (1: 4 or 6) | ip (4 or 16) | port (2) | length (4) | packet bytes
+//! ```
+//!
+//! `arrival_us` is `local_clock_us` at arrival, and the packet bytes are the
+//! jamjam header and payload as produced by `Packet::to_bytes`.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tracing::{info, warn};
+
+use crate::protocol::Packet;
+
+use super::error::NetworkError;
+
+/// Magic bytes at the start of a capture file
+pub const CAPTURE_MAGIC: &[u8; 4] = b"JJCP";
+
+/// Capture file format version
+pub const CAPTURE_VERSION: u8 = 1;
+
+/// Largest packet accepted when reading a capture
+const MAX_CAPTURED_PACKET: usize = 65_535;
+
+/// A received packet read back from a capture file
+#[derive(Debug, Clone)]
+pub struct CapturedPacket {
+    /// Arrival time (`local_clock_us` of the capturing process)
+    pub arrival_us: u64,
+    /// Address the packet came from
+    pub source: SocketAddr,
+    /// The packet (decrypted)
+    pub packet: Packet,
+}
+
+/// Writes received packets to a capture file
+pub struct PacketCapture {
+    writer: BufWriter<File>,
+    packets_written: u64,
+}
+
+impl PacketCapture {
+    /// Create a capture file (truncates an existing file)
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, NetworkError> {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(CAPTURE_MAGIC)?;
+        writer.write_all(&[CAPTURE_VERSION])?;
+        info!("Capturing received packets to {}", path.as_ref().display());
+
+        Ok(Self {
+            writer,
+            packets_written: 0,
+        })
+    }
+
+    /// Append a received packet
+    pub fn record(
+        &mut self,
+        arrival_us: u64,
+        source: SocketAddr,
+        packet: &Packet,
+    ) -> Result<(), NetworkError> {
+        let bytes = packet.to_bytes();
+        self.writer.write_all(&arrival_us.to_be_bytes())?;
+        match source.ip() {
+            IpAddr::V4(ip) => {
+                self.writer.write_all(&[4])?;
+                self.writer.write_all(&ip.octets())?;
+            }
+            IpAddr::V6(ip) => {
+                self.writer.write_all(&[6])?;
+                self.writer.write_all(&ip.octets())?;
+            }
+        }
+        self.writer.write_all(&source.port().to_be_bytes())?;
+        self.writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.packets_written += 1;
+        Ok(())
+    }
+
+    /// Number of packets written so far
+    pub fn packets_written(&self) -> u64 {
+        self.packets_written
+    }
+
+    /// Flush buffered packets to disk (returns packets written)
+    pub fn finish(mut self) -> Result<u64, NetworkError> {
+        self.writer.flush()?;
+        Ok(self.packets_written)
+    }
+}
+
+/// Capture shared between an endpoint and its receive loop
+pub(crate) type SharedCapture = Arc<Mutex<Option<PacketCapture>>>;
+
+/// Record a packet if a capture is running
+///
+/// A capture that fails to write is stopped rather than retried per packet.
+pub(crate) fn capture_packet(
+    capture: &SharedCapture,
+    arrival_us: u64,
+    source: SocketAddr,
+    packet: &Packet,
+) {
+    let mut capture = capture.lock();
+    let Some(writer) = capture.as_mut() else {
+        return;
+    };
+    if let Err(e) = writer.record(arrival_us, source, packet) {
+        warn!("Stopping packet capture: {}", e);
+        *capture = None;
+    }
+}
+
+/// Reads packets back from a capture file
+///
+/// A packet cut short at the end of the file (the capturing process died
+/// mid-write) ends the capture without an error.
+pub struct CaptureReader {
+    reader: BufReader<File>,
+}
+
+impl CaptureReader {
+    /// Open a capture file and check its header
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, NetworkError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 5];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| invalid("missing header"))?;
+        if &header[..4] != CAPTURE_MAGIC {
+            return Err(invalid("not a jamjam capture"));
+        }
+        if header[4] != CAPTURE_VERSION {
+            return Err(invalid(&format!("unsupported version {}", header[4])));
+        }
+        Ok(Self { reader })
+    }
+
+    /// Read the next packet (None at the end of the capture)
+    fn read_packet(&mut self) -> Result<Option<CapturedPacket>, NetworkError> {
+        let mut arrival = [0u8; 8];
+        match self.reader.read_exact(&mut arrival) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut family = [0u8; 1];
+        self.reader.read_exact(&mut family)?;
+        let ip = match family[0] {
+            4 => {
+                let mut octets = [0u8; 4];
+                self.reader.read_exact(&mut octets)?;
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            6 => {
+                let mut octets = [0u8; 16];
+                self.reader.read_exact(&mut octets)?;
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            other => return Err(invalid(&format!("bad address family {}", other))),
+        };
+
+        let mut port = [0u8; 2];
+        self.reader.read_exact(&mut port)?;
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_CAPTURED_PACKET {
+            return Err(invalid(&format!("packet of {} bytes", len)));
+        }
+        let mut bytes = vec![0u8; len];
+        self.reader.read_exact(&mut bytes)?;
+        let packet = Packet::from_bytes(&bytes).ok_or(NetworkError::InvalidPacket)?;
+
+        Ok(Some(CapturedPacket {
+            arrival_us: u64::from_be_bytes(arrival),
+            source: SocketAddr::new(ip, u16::from_be_bytes(port)),
+            packet,
+        }))
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = Result<CapturedPacket, NetworkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_packet() {
+            Ok(packet) => packet.map(Ok),
+            Err(NetworkError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                warn!("Capture ends with a truncated packet");
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+fn invalid(reason: &str) -> NetworkError {
+    NetworkError::InvalidCapture(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("jamjam-{}-{}.jjcap", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_capture_roundtrip() {
+        let path = temp_path("roundtrip");
+        let v4: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:6000".parse().unwrap();
+
+        let mut capture = PacketCapture::create(&path).unwrap();
+        capture
+            .record(100, v4, &Packet::audio(7, 896, vec![1, 2, 3]))
+            .unwrap();
+        capture.record(250, v6, &Packet::keep_alive(8)).unwrap();
+        assert_eq!(capture.finish().unwrap(), 2);
+
+        let packets: Vec<CapturedPacket> = CaptureReader::open(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].arrival_us, 100);
+        assert_eq!(packets[0].source, v4);
+        assert_eq!(packets[0].packet.sequence, 7);
+        assert_eq!(packets[0].packet.timestamp, 896);
+        assert_eq!(packets[0].packet.payload, vec![1, 2, 3]);
+        assert_eq!(packets[1].arrival_us, 250);
+        assert_eq!(packets[1].source, v6);
+        assert_eq!(packets[1].packet.sequence, 8);
+    }
+
+    #[test]
+    fn test_truncated_capture_ends_cleanly() {
+        let path = temp_path("truncated");
+        let source: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+
+        let mut capture = PacketCapture::create(&path).unwrap();
+        capture
+            .record(1, source, &Packet::audio(0, 0, vec![0; 16]))
+            .unwrap();
+        capture
+            .record(2, source, &Packet::audio(1, 4, vec![0; 16]))
+            .unwrap();
+        capture.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+
+        let packets: Vec<CapturedPacket> = CaptureReader::open(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(packets.len(), 1);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = temp_path("other");
+        std::fs::write(&path, b"RIFF....WAVE").unwrap();
+        let result = CaptureReader::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(NetworkError::InvalidCapture(_))));
+    }
+}
@@ -0,0 +1,336 @@
+//! Peer clock offset and drift estimation
+//!
+//! Built on the latency ping exchange, NTP style: a ping leaves at `t1`
+//! (our clock), arrives at `t2` and its pong leaves at `t3` (peer clock), and
+//! the pong arrives at `t4` (our clock). Assuming a symmetric path, the peer
+//! clock is ahead of ours by `((t2 - t1) + (t3 - t4)) / 2`, with an error of
+//! at most half the round-trip delay `(t4 - t1) - (t3 - t2)`.
+//!
+//! Queuing only ever adds delay, so the sample with the lowest delay among
+//! the last few gives the offset. The slope of those offsets over time is the
+//! drift between the two clocks.
+//!
+//! With the offset known, each peer's sample timestamps are mapped onto our
+//! clock, which serves as the session timeline.
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Exchanges the offset is picked from (lowest delay wins)
+const CLOCK_FILTER_SIZE: usize = 8;
+
+/// Picked offsets kept for the drift estimate
+const CLOCK_HISTORY_SIZE: usize = 64;
+
+/// Time the picked offsets must span before drift is estimated (µs)
+const MIN_DRIFT_SPAN_US: u64 = 5_000_000;
+
+/// Audio packets per window of the stream anchor's minimum
+const STREAM_ANCHOR_WINDOW: u32 = 500;
+
+/// Microseconds on this process's monotonic clock
+///
+/// Latency pings and pongs are stamped with it, and it is the timeline peer
+/// streams are mapped onto.
+pub fn local_clock_us() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+/// Estimated relation between a peer's clock and ours
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClockEstimate {
+    /// Peer clock minus our clock at `measured_at_us` (µs)
+    pub offset_us: i64,
+    /// How much faster the peer clock runs than ours (parts per million)
+    pub drift_ppm: f64,
+    /// Round-trip delay of the exchange the offset comes from (µs); the
+    /// offset is off by at most half of it
+    pub delay_us: u64,
+    /// Our clock when the offset was measured (µs)
+    pub measured_at_us: u64,
+}
+
+impl ClockEstimate {
+    /// Peer clock minus our clock at `local_us`, following the drift
+    pub fn offset_at(&self, local_us: u64) -> f64 {
+        let elapsed = local_us as f64 - self.measured_at_us as f64;
+        self.offset_us as f64 + elapsed * self.drift_ppm / 1e6
+    }
+
+    /// Convert a time on our clock to the peer's clock
+    pub fn to_remote_us(&self, local_us: u64) -> u64 {
+        (local_us as f64 + self.offset_at(local_us)).max(0.0) as u64
+    }
+
+    /// Convert a time on the peer's clock to ours
+    pub fn to_local_us(&self, remote_us: u64) -> u64 {
+        // The drift over the offset itself is negligible
+        let approx = remote_us as f64 - self.offset_us as f64;
+        (remote_us as f64 - self.offset_at(approx.max(0.0) as u64)).max(0.0) as u64
+    }
+
+    /// One-way delay to the peer (ms), half the best round trip
+    pub fn one_way_ms(&self) -> f32 {
+        self.delay_us as f32 / 2000.0
+    }
+}
+
+/// One ping exchange
+#[derive(Debug, Clone, Copy)]
+struct ClockSample {
+    offset_us: i64,
+    delay_us: u64,
+    /// Our clock when the pong arrived
+    local_us: u64,
+}
+
+/// Clock offset and drift estimator for one peer
+#[derive(Debug, Default)]
+pub(crate) struct ClockSync {
+    /// Latest exchanges
+    filter: VecDeque<ClockSample>,
+    /// Offsets picked by the filter, oldest first
+    history: VecDeque<ClockSample>,
+    estimate: Option<ClockEstimate>,
+}
+
+impl ClockSync {
+    /// Add an exchange: ping sent at `t1`, received at `t2`, pong sent at
+    /// `t3` and received at `t4`
+    pub(crate) fn process(&mut self, t1: u64, t2: u64, t3: u64, t4: u64) {
+        let (t1, t2, t3, t4) = (t1 as i64, t2 as i64, t3 as i64, t4 as i64);
+        let delay = (t4 - t1) - (t3 - t2);
+        if delay < 0 || t3 < t2 {
+            return;
+        }
+        if self.filter.len() >= CLOCK_FILTER_SIZE {
+            self.filter.pop_front();
+        }
+        self.filter.push_back(ClockSample {
+            offset_us: ((t2 - t1) + (t3 - t4)) / 2,
+            delay_us: delay as u64,
+            local_us: t4 as u64,
+        });
+
+        let Some(best) = self.filter.iter().min_by_key(|s| s.delay_us).copied() else {
+            return;
+        };
+        // The same exchange stays the best for a while; record it once
+        if self
+            .history
+            .back()
+            .is_none_or(|last| best.local_us > last.local_us)
+        {
+            if self.history.len() >= CLOCK_HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(best);
+        }
+
+        self.estimate = Some(ClockEstimate {
+            offset_us: best.offset_us,
+            drift_ppm: self.drift_ppm(),
+            delay_us: best.delay_us,
+            measured_at_us: best.local_us,
+        });
+    }
+
+    /// Current estimate (None before the first pong)
+    pub(crate) fn estimate(&self) -> Option<ClockEstimate> {
+        self.estimate
+    }
+
+    /// Least-squares slope of the picked offsets over time
+    fn drift_ppm(&self) -> f64 {
+        let (Some(first), Some(last)) = (self.history.front(), self.history.back()) else {
+            return 0.0;
+        };
+        if last.local_us - first.local_us < MIN_DRIFT_SPAN_US {
+            return 0.0;
+        }
+        let n = self.history.len() as f64;
+        let points = || {
+            self.history.iter().map(|s| {
+                (
+                    (s.local_us - first.local_us) as f64,
+                    (s.offset_us - first.offset_us) as f64,
+                )
+            })
+        };
+        let mean_x = points().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points().map(|(_, y)| y).sum::<f64>() / n;
+        let (cov, var) = points().fold((0.0, 0.0), |(cov, var), (x, y)| {
+            let dx = x - mean_x;
+            (cov + dx * (y - mean_y), var + dx * dx)
+        });
+        if var == 0.0 {
+            0.0
+        } else {
+            cov / var * 1e6
+        }
+    }
+}
+
+/// Maps a peer's sample timestamps onto our clock
+///
+/// Each audio packet was sent about one one-way delay before it arrived, so
+/// it puts sample 0 of the peer's stream at some time on our clock. Packets
+/// delayed by queuing put it later; the earliest over a recent window is the
+/// anchor.
+#[derive(Debug)]
+pub(crate) struct StreamClock {
+    sample_rate: u32,
+    /// Last timestamp, extended past u32 wraparound
+    last_timestamp: Option<i64>,
+    /// Earliest origin in the current and previous windows (µs)
+    window_min: f64,
+    previous_min: f64,
+    window_packets: u32,
+}
+
+impl StreamClock {
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            last_timestamp: None,
+            window_min: f64::INFINITY,
+            previous_min: f64::INFINITY,
+            window_packets: 0,
+        }
+    }
+
+    /// Record an audio packet that arrived at `arrival_us` after about
+    /// `one_way_us` in flight
+    pub(crate) fn on_packet(&mut self, timestamp: u32, arrival_us: u64, one_way_us: u64) {
+        let timestamp = self.extend(timestamp);
+        self.last_timestamp = Some(self.last_timestamp.map_or(timestamp, |t| t.max(timestamp)));
+
+        let origin = arrival_us as f64 - one_way_us as f64 - self.samples_to_us(timestamp);
+        self.window_min = self.window_min.min(origin);
+        self.window_packets += 1;
+        if self.window_packets >= STREAM_ANCHOR_WINDOW {
+            self.previous_min = self.window_min;
+            self.window_min = f64::INFINITY;
+            self.window_packets = 0;
+        }
+    }
+
+    /// Our clock when the sample at `timestamp` was sent (µs)
+    pub(crate) fn to_local_us(&self, timestamp: u32) -> Option<u64> {
+        let origin = self.window_min.min(self.previous_min);
+        if !origin.is_finite() {
+            return None;
+        }
+        let at = origin + self.samples_to_us(self.extend(timestamp));
+        Some(at.max(0.0) as u64)
+    }
+
+    fn extend(&self, timestamp: u32) -> i64 {
+        match self.last_timestamp {
+            Some(last) => last + timestamp.wrapping_sub(last as u32) as i32 as i64,
+            None => timestamp as i64,
+        }
+    }
+
+    fn samples_to_us(&self, samples: i64) -> f64 {
+        samples as f64 * 1e6 / self.sample_rate as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exchange with a peer whose clock is `offset` ahead and runs `ppm`
+    /// fast, over a path with the given delays each way
+    fn exchange(sync: &mut ClockSync, t1: u64, offset: i64, ppm: f64, up: u64, down: u64) {
+        let remote = |local: u64| (local as f64 * (1.0 + ppm / 1e6)) as i64 + offset;
+        let t2 = remote(t1 + up) as u64;
+        let t3 = t2 + 50;
+        let t4 = t1 + up + 50 + down;
+        sync.process(t1, t2, t3, t4);
+    }
+
+    #[test]
+    fn test_offset_from_symmetric_path() {
+        let mut sync = ClockSync::default();
+        assert!(sync.estimate().is_none());
+        exchange(&mut sync, 1_000_000, 250_000, 0.0, 2_000, 2_000);
+
+        let estimate = sync.estimate().unwrap();
+        assert_eq!(estimate.offset_us, 250_000);
+        assert_eq!(estimate.delay_us, 4_000);
+        assert_eq!(estimate.to_remote_us(2_000_000), 2_250_000);
+        assert_eq!(estimate.to_local_us(2_250_000), 2_000_000);
+    }
+
+    #[test]
+    fn test_lowest_delay_exchange_wins() {
+        let mut sync = ClockSync::default();
+        // Queuing on the way back skews the offset of slow exchanges
+        exchange(&mut sync, 1_000_000, 10_000, 0.0, 1_000, 9_000);
+        exchange(&mut sync, 1_500_000, 10_000, 0.0, 1_000, 1_000);
+        exchange(&mut sync, 2_000_000, 10_000, 0.0, 1_000, 15_000);
+
+        let estimate = sync.estimate().unwrap();
+        assert_eq!(estimate.offset_us, 10_000);
+        assert_eq!(estimate.delay_us, 2_000);
+        assert!((estimate.one_way_ms() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_drift_estimate() {
+        let mut sync = ClockSync::default();
+        for i in 0..40u64 {
+            // Every other exchange is queued
+            let extra = if i % 2 == 0 { 0 } else { 3_000 };
+            exchange(&mut sync, i * 500_000, -40_000, 100.0, 1_000, 1_000 + extra);
+        }
+
+        let estimate = sync.estimate().unwrap();
+        assert!(
+            (estimate.drift_ppm - 100.0).abs() < 5.0,
+            "drift {} ppm",
+            estimate.drift_ppm
+        );
+        // Mapping a minute ahead stays within a millisecond
+        let later = 80_000_000;
+        let expected = (later as f64 * 1.0001) as i64 - 40_000;
+        assert!((estimate.to_remote_us(later) as i64 - expected).abs() < 1_000);
+    }
+
+    #[test]
+    fn test_invalid_exchange_ignored() {
+        let mut sync = ClockSync::default();
+        // Pong "arrived" before the ping was answered
+        sync.process(1_000, 5_000, 5_100, 900);
+        assert!(sync.estimate().is_none());
+    }
+
+    #[test]
+    fn test_stream_clock_anchors_on_fastest_packet() {
+        // 48 kHz, 480-sample packets (10 ms), 2 ms one-way
+        let mut stream = StreamClock::new(48000);
+        assert_eq!(stream.to_local_us(0), None);
+        for i in 0..10u64 {
+            let queued = if i == 3 { 0 } else { 700 };
+            let arrival = 1_000_000 + i * 10_000 + 2_000 + queued;
+            stream.on_packet((i * 480) as u32, arrival, 2_000);
+        }
+
+        assert_eq!(stream.to_local_us(0), Some(1_000_000));
+        assert_eq!(stream.to_local_us(48000), Some(2_000_000));
+    }
+
+    #[test]
+    fn test_stream_clock_timestamp_wraparound() {
+        let mut stream = StreamClock::new(48000);
+        let start = u32::MAX - 479;
+        stream.on_packet(start, 1_000_000, 0);
+        stream.on_packet(start.wrapping_add(480), 1_010_000, 0);
+
+        assert_eq!(stream.to_local_us(start.wrapping_add(960)), Some(1_020_000));
+    }
+}
@@ -0,0 +1,332 @@
+//! Per-peer audio codec negotiation
+//!
+//! Peers exchange `PacketType::CodecOffer` packets listing the codecs they can
+//! decode and the codec they would like to use. Audio is sent as Opus if
+//! either side prefers it and both can decode it, otherwise as PCM, which
+//! every build supports. Until the peer's offer arrives, PCM is used.
+//!
+//! The codec of every audio packet is carried in the header flags, so the
+//! receiver always decodes according to the packet rather than the
+//! negotiation state.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use tracing::{debug, warn};
+
+use crate::audio::{create_codec, AudioCodec, CodecConfig, CodecError, CodecType};
+use crate::protocol::{CodecOfferPayload, Packet};
+
+use super::quality::QualitySettings;
+
+/// Codec negotiation and encoder state for one peer
+pub(crate) struct CodecNegotiation {
+    /// Local codec preference and audio parameters
+    config: CodecConfig,
+    /// Offer received from the peer
+    peer_offer: Option<CodecOfferPayload>,
+    /// Encoder for the negotiated codec
+    encoder: Option<Box<dyn AudioCodec>>,
+    /// Encoders for additional streams, by stream ID
+    stream_encoders: HashMap<u8, Box<dyn AudioCodec>>,
+    /// Codecs that failed to encode with the local parameters
+    failed: Vec<CodecType>,
+}
+
+impl CodecNegotiation {
+    /// Create negotiation state with the local codec preference
+    pub fn new(config: CodecConfig) -> Self {
+        Self {
+            config,
+            peer_offer: None,
+            encoder: None,
+            stream_encoders: HashMap::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Check if the peer's offer is still missing
+    pub fn needs_offer(&self) -> bool {
+        self.peer_offer.is_none()
+    }
+
+    /// Build our codec offer packet
+    pub fn offer_packet(&self, sequence: u32, ack: bool) -> Packet {
+        let offer = CodecOfferPayload {
+            supported: CodecType::available(),
+            preferred: self.config.codec_type,
+            ack,
+        };
+        Packet::codec_offer(sequence, &offer)
+    }
+
+    /// Handle the peer's codec offer
+    ///
+    /// Returns true if the peer has not seen our offer yet and we should reply.
+    pub fn handle_offer(&mut self, offer: CodecOfferPayload) -> bool {
+        let reply = !offer.ack;
+        if self.peer_offer.as_ref() != Some(&offer) {
+            let previous = self.send_codec();
+            self.peer_offer = Some(CodecOfferPayload {
+                ack: false,
+                ..offer
+            });
+            if self.send_codec() != previous {
+                debug!("Negotiated audio codec: {:?}", self.send_codec());
+            }
+        }
+        reply
+    }
+
+    /// Codec used for audio sent to this peer
+    pub fn send_codec(&self) -> CodecType {
+        let Some(peer) = &self.peer_offer else {
+            return CodecType::Pcm;
+        };
+        let opus_wanted =
+            self.config.codec_type == CodecType::Opus || peer.preferred == CodecType::Opus;
+        let opus_usable = CodecType::Opus.is_available()
+            && peer.supported.contains(&CodecType::Opus)
+            && !self.failed.contains(&CodecType::Opus);
+        if opus_wanted && opus_usable {
+            CodecType::Opus
+        } else {
+            CodecType::Pcm
+        }
+    }
+
+    /// Follow the quality controller's codec preference, bitrate and frame
+    /// size
+    ///
+    /// The bitrate is changed on the running encoder; a new frame size needs
+    /// a new encoder.
+    pub fn apply_quality(&mut self, settings: &QualitySettings) {
+        if settings.frame_size != self.config.frame_size {
+            self.encoder = None;
+            self.stream_encoders.clear();
+        } else if settings.bitrate != self.config.bitrate {
+            if let Some(encoder) = self.encoder.as_mut() {
+                if let Err(e) = encoder.set_bitrate(settings.bitrate) {
+                    warn!("{}, restarting the encoder", e);
+                    self.encoder = None;
+                }
+            }
+        }
+        self.config.codec_type = settings.codec;
+        self.config.bitrate = settings.bitrate;
+        self.config.frame_size = settings.frame_size;
+    }
+
+    /// Encode a frame with the negotiated codec
+    ///
+    /// Falls back to PCM for good if the negotiated codec cannot encode with
+    /// the local parameters (e.g. an unsupported Opus frame size).
+    pub fn encode(&mut self, samples: &[f32]) -> (CodecType, Vec<u8>) {
+        let codec = self.send_codec();
+        if codec != CodecType::Pcm {
+            match self.encode_with(codec, samples) {
+                Ok(payload) => return (codec, payload),
+                Err(e) => {
+                    warn!("{:?} encoding failed, falling back to PCM: {}", codec, e);
+                    self.failed.push(codec);
+                    self.encoder = None;
+                }
+            }
+        }
+        (CodecType::Pcm, encode_pcm(samples))
+    }
+
+    /// Encode a frame of an additional stream with the negotiated codec
+    ///
+    /// Each stream has its own encoder with the stream's channel count.
+    pub fn encode_stream(
+        &mut self,
+        stream_id: u8,
+        channels: u16,
+        samples: &[f32],
+    ) -> (CodecType, Vec<u8>) {
+        let codec = self.send_codec();
+        if codec != CodecType::Pcm {
+            match self.encode_stream_with(codec, stream_id, channels, samples) {
+                Ok(payload) => return (codec, payload),
+                Err(e) => {
+                    warn!(
+                        "{:?} encoding of stream {} failed, falling back to PCM: {}",
+                        codec, stream_id, e
+                    );
+                    self.failed.push(codec);
+                    self.encoder = None;
+                    self.stream_encoders.clear();
+                }
+            }
+        }
+        (CodecType::Pcm, encode_pcm(samples))
+    }
+
+    fn encode_stream_with(
+        &mut self,
+        codec: CodecType,
+        stream_id: u8,
+        channels: u16,
+        samples: &[f32],
+    ) -> Result<Vec<u8>, CodecError> {
+        if self.stream_encoders.get(&stream_id).map(|e| e.codec_type()) != Some(codec) {
+            let encoder = create_codec(&CodecConfig {
+                codec_type: codec,
+                channels,
+                ..self.config.clone()
+            })?;
+            self.stream_encoders.insert(stream_id, encoder);
+        }
+        self.stream_encoders
+            .get_mut(&stream_id)
+            .expect("encoder was just created")
+            .encode(samples)
+    }
+
+    fn encode_with(&mut self, codec: CodecType, samples: &[f32]) -> Result<Vec<u8>, CodecError> {
+        if self.encoder.as_ref().map(|e| e.codec_type()) != Some(codec) {
+            self.encoder = Some(create_codec(&CodecConfig {
+                codec_type: codec,
+                ..self.config.clone()
+            })?);
+        }
+        self.encoder
+            .as_mut()
+            .expect("encoder was just created")
+            .encode(samples)
+    }
+}
+
+/// Decoders for every codec seen on an incoming stream
+pub(crate) struct AudioDecoders {
+    config: CodecConfig,
+    decoders: HashMap<CodecType, Box<dyn AudioCodec>>,
+}
+
+impl AudioDecoders {
+    /// Create decoders using the local audio parameters
+    ///
+    /// # Errors
+    /// Returns error if the configured codec is not available in this build
+    pub fn new(config: CodecConfig) -> Result<Self, CodecError> {
+        let mut decoders = HashMap::new();
+        decoders.insert(config.codec_type, create_codec(&config)?);
+        Ok(Self { config, decoders })
+    }
+
+    /// Decode a payload according to its codec
+    pub fn decode(&mut self, codec: CodecType, payload: &[u8]) -> Result<Vec<f32>, CodecError> {
+        let decoder = match self.decoders.entry(codec) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(create_codec(&CodecConfig {
+                codec_type: codec,
+                ..self.config.clone()
+            })?),
+        };
+        decoder.decode(payload)
+    }
+
+    /// Conceal a lost frame of `frame_size` samples per channel with the
+    /// given codec's PLC
+    pub fn decode_plc(&mut self, codec: CodecType, frame_size: u32) -> Option<Vec<f32>> {
+        self.decoders
+            .get_mut(&codec)?
+            .decode_plc(frame_size as usize)
+            .ok()
+    }
+}
+
+/// Serialize samples as little-endian f32 (PCM wire format)
+pub(crate) fn encode_pcm(samples: &[f32]) -> Vec<u8> {
+    samples.iter().flat_map(|&s| s.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(preferred: CodecType, supported: Vec<CodecType>) -> CodecOfferPayload {
+        CodecOfferPayload {
+            supported,
+            preferred,
+            ack: false,
+        }
+    }
+
+    #[test]
+    fn test_pcm_until_peer_offer() {
+        let negotiation = CodecNegotiation::new(CodecConfig {
+            codec_type: CodecType::Opus,
+            ..Default::default()
+        });
+        assert!(negotiation.needs_offer());
+        assert_eq!(negotiation.send_codec(), CodecType::Pcm);
+    }
+
+    #[test]
+    fn test_opus_requires_peer_support() {
+        let mut negotiation = CodecNegotiation::new(CodecConfig {
+            codec_type: CodecType::Opus,
+            ..Default::default()
+        });
+        assert!(negotiation.handle_offer(offer(CodecType::Pcm, vec![CodecType::Pcm])));
+        assert!(!negotiation.needs_offer());
+        assert_eq!(negotiation.send_codec(), CodecType::Pcm);
+    }
+
+    #[test]
+    fn test_peer_preference_selects_opus() {
+        let mut negotiation = CodecNegotiation::new(CodecConfig::default());
+        negotiation.handle_offer(offer(
+            CodecType::Opus,
+            vec![CodecType::Pcm, CodecType::Opus],
+        ));
+
+        let expected = if CodecType::Opus.is_available() {
+            CodecType::Opus
+        } else {
+            CodecType::Pcm
+        };
+        assert_eq!(negotiation.send_codec(), expected);
+    }
+
+    #[test]
+    fn test_ack_suppresses_reply() {
+        let mut negotiation = CodecNegotiation::new(CodecConfig::default());
+        let mut acked = offer(CodecType::Pcm, vec![CodecType::Pcm]);
+        acked.ack = true;
+        assert!(!negotiation.handle_offer(acked));
+    }
+
+    #[test]
+    fn test_quality_settings_change_preference() {
+        let mut negotiation = CodecNegotiation::new(CodecConfig::default());
+        negotiation.handle_offer(offer(CodecType::Pcm, vec![CodecType::Pcm, CodecType::Opus]));
+        assert_eq!(negotiation.send_codec(), CodecType::Pcm);
+
+        negotiation.apply_quality(&QualitySettings {
+            codec: CodecType::Opus,
+            bitrate: 64_000,
+            frame_size: 240,
+            fec_group_size: None,
+        });
+        let expected = if CodecType::Opus.is_available() {
+            CodecType::Opus
+        } else {
+            CodecType::Pcm
+        };
+        assert_eq!(negotiation.send_codec(), expected);
+        assert_eq!(negotiation.encode(&[0.0; 240]).0, expected);
+    }
+
+    #[test]
+    fn test_pcm_encode_decode() {
+        let mut negotiation = CodecNegotiation::new(CodecConfig::default());
+        let (codec, payload) = negotiation.encode(&[0.5, -0.5]);
+        assert_eq!(codec, CodecType::Pcm);
+
+        let mut decoders = AudioDecoders::new(CodecConfig::default()).unwrap();
+        assert_eq!(decoders.decode(codec, &payload).unwrap(), vec![0.5, -0.5]);
+    }
+}
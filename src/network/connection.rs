@@ -2,32 +2,77 @@
 
 use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
 use tokio::time::interval;
 use tracing::{debug, info, trace, warn};
 
-use crate::protocol::{LatencyInfoMessage, LatencyPing, LatencyPong, Packet, PacketType};
-
+use crate::audio::{CodecConfig, CodecType};
+use crate::protocol::{
+    Capabilities, CodecOfferPayload, ControlMessage, HelloPayload, KeyExchangePayload,
+    LatencyInfoMessage, LatencyPing, LatencyPong, Packet, PacketType, PathValidationPayload,
+    ReceiverReport,
+};
+
+use super::capabilities::{local_capabilities, CapabilityNegotiation, NegotiatedCapabilities};
+use super::capture::{capture_packet, PacketCapture, SharedCapture};
+use super::clock::{local_clock_us, ClockEstimate, ClockSync};
+use super::codec_negotiation::{encode_pcm, AudioDecoders, CodecNegotiation};
+use super::control::{ControlChannel, ControlConfig, ControlStats};
+use super::encryption::{send_packet, EncryptedTransport, EncryptionMode, KeyExchangeState};
 use super::error::NetworkError;
-use super::transport::UdpTransport;
+use super::fec::{FecConfig, FecPacket, FecStreamDecoder, FecStreamEncoder, RecoveredAudio};
+use super::ice::{self, IceAgent, IceConfig, IceState};
+use super::path_validation::PathValidator;
+use super::quality::{
+    Packetizer, QualityConfig, QualityController, QualityDecision, QualitySettings,
+};
+use super::receive_pipeline::{ReceivePipeline, ReceivePipelineConfig, ReceivePipelineStats};
+use super::receiver_report::{ReceptionReporter, RemoteReceptionStats};
+use super::redundancy::{split_redundancy, RedundancyConfig, RedundancyEncoder};
+use super::sequence_tracker::SequenceTracker;
+use super::signaling::AddressCandidate;
+use super::transport::{StunDatagram, UdpTransport};
 
 /// Number of RTT samples to keep for averaging
 const RTT_SAMPLE_COUNT: usize = 10;
 
+/// Time allowed for ICE checks to nominate a pair
+const ICE_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Maximum pending pings before discarding old ones
 const MAX_PENDING_PINGS: usize = 10;
 
+/// How often unacknowledged control messages are checked for retransmission
+pub(crate) const CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
 /// Connection statistics
 #[derive(Debug, Clone, Default)]
 pub struct ConnectionStats {
     /// Round-trip time in milliseconds
     pub rtt_ms: f32,
-    /// Packet loss rate (0.0 - 1.0)
+    /// Audio packet loss rate since connecting (0.0 - 1.0)
     pub packet_loss_rate: f32,
+    /// Audio packet loss rate over the last `LOSS_WINDOW_PACKETS` sequence
+    /// numbers (0.0 - 1.0)
+    pub recent_packet_loss_rate: f32,
+    /// Audio packets lost in transit (before FEC recovery)
+    pub packets_lost: u64,
+    /// Audio packets that arrived out of order
+    pub packets_reordered: u64,
+    /// Audio packets received more than once
+    pub packets_duplicated: u64,
+    /// Number of audio loss bursts (runs of consecutive lost packets)
+    pub loss_bursts: u64,
+    /// Average loss burst length in packets
+    pub mean_burst_length: f32,
+    /// Longest loss burst in packets
+    pub max_burst_length: u32,
     /// Jitter in milliseconds (RTT variation)
     pub jitter_ms: f32,
     /// Total bytes sent
@@ -40,23 +85,52 @@ pub struct ConnectionStats {
     pub packets_received: u64,
     /// Connection uptime in seconds
     pub uptime_seconds: u64,
+    /// Whether packets are end-to-end encrypted
+    pub encrypted: bool,
+    /// Codec negotiated for outgoing audio
+    pub send_codec: CodecType,
+    /// Packets dropped because they failed decryption, were unencrypted
+    /// while encryption is required, or offered a key other than the peer's
+    pub packets_rejected: u64,
+    /// Packets dropped because they came from an address other than the
+    /// peer's
+    pub packets_rejected_source: u64,
+    /// Times the peer moved to a new address after answering a path
+    /// challenge
+    pub address_migrations: u64,
+    /// FEC packets sent
+    pub fec_packets_sent: u64,
+    /// FEC packets received
+    pub fec_packets_received: u64,
+    /// Lost audio packets rebuilt from FEC in time for playout
+    pub packets_recovered: u64,
+    /// Peer clock offset and drift (None before the first pong)
+    pub clock: Option<ClockEstimate>,
+    /// Settings chosen by the quality controller (None if it is disabled)
+    pub quality: Option<QualitySettings>,
+    /// How our audio arrives at the peer (None before its first receiver
+    /// report)
+    pub remote_reception: Option<RemoteReceptionStats>,
+    /// Protocol version and capabilities agreed with the peer (None before
+    /// its hello)
+    pub capabilities: Option<NegotiatedCapabilities>,
 }
 
 /// RTT measurement state
 #[derive(Debug)]
-struct RttMeasurement {
+pub(crate) struct RttMeasurement {
     /// Current smoothed RTT estimate (ms)
-    rtt_ms: f32,
+    pub(crate) rtt_ms: f32,
     /// RTT jitter / variation (ms)
-    jitter_ms: f32,
+    pub(crate) jitter_ms: f32,
     /// Pending ping sequences with sent timestamps (monotonic instant)
     pending_pings: HashMap<u32, Instant>,
     /// Recent RTT samples for averaging
     rtt_samples: VecDeque<f32>,
     /// Next ping sequence number
     next_ping_seq: u32,
-    /// Monotonic time reference for timestamps
-    time_reference: Instant,
+    /// Peer clock offset and drift from the same exchanges
+    pub(crate) clock: ClockSync,
 }
 
 impl Default for RttMeasurement {
@@ -67,19 +141,14 @@ impl Default for RttMeasurement {
             pending_pings: HashMap::new(),
             rtt_samples: VecDeque::with_capacity(RTT_SAMPLE_COUNT),
             next_ping_seq: 0,
-            time_reference: Instant::now(),
+            clock: ClockSync::default(),
         }
     }
 }
 
 impl RttMeasurement {
-    /// Get current time in microseconds since reference
-    fn now_us(&self) -> u64 {
-        self.time_reference.elapsed().as_micros() as u64
-    }
-
     /// Create a ping message and record the send time
-    fn create_ping(&mut self) -> LatencyPing {
+    pub(crate) fn create_ping(&mut self) -> LatencyPing {
         let seq = self.next_ping_seq;
         self.next_ping_seq = self.next_ping_seq.wrapping_add(1);
 
@@ -94,15 +163,21 @@ impl RttMeasurement {
         self.pending_pings.insert(seq, Instant::now());
 
         LatencyPing {
-            sent_time_us: self.now_us(),
+            sent_time_us: local_clock_us(),
             ping_sequence: seq,
         }
     }
 
-    /// Process a pong response and update RTT statistics
-    fn process_pong(&mut self, pong: &LatencyPong) {
+    /// Process a pong response and update RTT statistics and the clock estimate
+    pub(crate) fn process_pong(&mut self, pong: &LatencyPong) {
         if let Some(sent_time) = self.pending_pings.remove(&pong.ping_sequence) {
             let rtt = sent_time.elapsed().as_secs_f32() * 1000.0; // Convert to ms
+            self.clock.process(
+                pong.original_sent_time_us,
+                pong.receive_time_us,
+                pong.send_time_us,
+                local_clock_us(),
+            );
 
             // Update RTT samples
             if self.rtt_samples.len() >= RTT_SAMPLE_COUNT {
@@ -186,7 +261,8 @@ impl From<LatencyInfoMessage> for PeerLatencyInfo {
 /// Connected --> Disconnected: disconnect()
 /// Failed --> Disconnected: reset()
 /// ```
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
 #[repr(u8)]
 pub enum ConnectionState {
     /// Not connected
@@ -240,28 +316,214 @@ impl ConnectionState {
     }
 }
 
+/// Liveness detection and reconnect timing
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectConfig {
+    /// Silence from the peer after which a connection starts reconnecting
+    pub liveness_timeout: Duration,
+    /// Time spent reconnecting before the connection fails
+    pub reconnect_timeout: Duration,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            liveness_timeout: Duration::from_secs(5),
+            reconnect_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Callback for connection state transitions
+pub type StateCallback = Box<dyn Fn(ConnectionState) + Send + Sync + 'static>;
+
+/// Connection state shared with the connection's tasks
+struct SharedState {
+    state: AtomicU8,
+    callback: RwLock<Option<Arc<StateCallback>>>,
+}
+
+impl SharedState {
+    fn new() -> Self {
+        Self {
+            state: AtomicU8::new(ConnectionState::Disconnected as u8),
+            callback: RwLock::new(None),
+        }
+    }
+
+    fn get(&self) -> ConnectionState {
+        ConnectionState::from_u8(self.state.load(Ordering::SeqCst))
+    }
+
+    /// Store a state, notifying the callback if it changed
+    fn set(&self, state: ConnectionState) {
+        let previous = ConnectionState::from_u8(self.state.swap(state as u8, Ordering::SeqCst));
+        if previous != state {
+            let callback = self.callback.read().clone();
+            if let Some(callback) = callback {
+                callback(state);
+            }
+        }
+    }
+
+    fn set_callback(&self, callback: Arc<StateCallback>) {
+        *self.callback.write() = Some(callback);
+    }
+}
+
+/// Our capabilities as a connection: no additional streams, and path
+/// challenges are answered so the peer can follow us to a new address
+fn connection_capabilities(encryption: EncryptionMode) -> Capabilities {
+    Capabilities {
+        path_validation: true,
+        ..local_capabilities(encryption, 0)
+    }
+}
+
+/// Run an ICE agent until it selects a pair, fails or times out
+///
+/// With `read_socket` the transport is read here to drive STUN demux (before
+/// the receive loop runs); otherwise the receive loop does it.
+async fn run_checks(
+    transport: &UdpTransport,
+    agent: &mut IceAgent,
+    stun_rx: &mut tokio::sync::mpsc::UnboundedReceiver<StunDatagram>,
+    check_interval: Duration,
+    read_socket: bool,
+) -> Option<(SocketAddr, bool)> {
+    let checks = async {
+        let mut tick = interval(check_interval);
+        loop {
+            let transmits = tokio::select! {
+                result = transport.recv_raw(), if read_socket => {
+                    if let Err(e) = result {
+                        debug!("Receive error during ICE checks: {}", e);
+                    }
+                    Vec::new()
+                }
+                Some(datagram) = stun_rx.recv() => {
+                    ice::handle_datagram(agent, &datagram, Instant::now())
+                }
+                _ = tick.tick() => agent.poll(Instant::now()),
+            };
+            ice::send_transmits(transport, transmits).await;
+
+            if let Some(selected) = agent.selected() {
+                return Some(selected);
+            }
+            if agent.state() == IceState::Failed {
+                return None;
+            }
+        }
+    };
+    tokio::time::timeout(ICE_TIMEOUT, checks)
+        .await
+        .ok()
+        .flatten()
+}
+
 /// Callback for received audio data
 pub type AudioCallback = Box<dyn Fn(&[u8], u32) + Send + Sync + 'static>;
 
+/// Callback for decoded audio frames from the receive pipeline
+pub type DecodedAudioCallback = Box<dyn Fn(&[f32], u32) + Send + Sync + 'static>;
+
 /// Callback for received peer latency info
 pub type LatencyInfoCallback = Box<dyn Fn(PeerLatencyInfo) + Send + Sync + 'static>;
 
+/// Callback for control messages from the peer, in the order they were sent
+pub type ControlCallback = Box<dyn Fn(ControlMessage) + Send + Sync + 'static>;
+
+/// Callback for quality controller decisions
+pub type QualityCallback = Box<dyn Fn(QualityDecision) + Send + Sync + 'static>;
+
 /// A P2P connection to a remote peer
 pub struct Connection {
     transport: Arc<UdpTransport>,
-    remote_addr: SocketAddr,
-    state: Arc<AtomicU8>,
+    /// Remote address, shared with the loops so an ICE restart can move it
+    remote_addr: Arc<RwLock<SocketAddr>>,
+    /// Candidates the peer may answer from after `connect_with_candidates`
+    /// fell back without a response; the first packet from the peer settles
+    /// the address and empties this
+    unconfirmed_candidates: Arc<Mutex<Vec<SocketAddr>>>,
+    state: Arc<SharedState>,
     /// Last error message that caused connection failure (if any)
     last_error: Arc<std::sync::Mutex<Option<String>>>,
-    sequence: AtomicU32,
+    /// Header sequence for every packet other than audio and FEC, shared with
+    /// the loops: one counter per key keeps nonces unique
+    sequence: Arc<AtomicU32>,
+    /// Sequence counter for audio packets only (gaps indicate loss)
+    audio_sequence: AtomicU32,
     packets_sent: Arc<AtomicU64>,
     packets_received: Arc<AtomicU64>,
+    packets_rejected: Arc<AtomicU64>,
+    packets_rejected_source: Arc<AtomicU64>,
+    address_migrations: Arc<AtomicU64>,
     bytes_sent: Arc<AtomicU64>,
     bytes_received: Arc<AtomicU64>,
     last_received: Arc<std::sync::Mutex<Instant>>,
+    /// Encryption policy for this connection
+    encryption_mode: EncryptionMode,
+    /// Key exchange state (reset on every connect)
+    key_exchange: Arc<Mutex<KeyExchangeState>>,
+    /// Preferred codec and audio parameters for outgoing audio
+    codec_config: CodecConfig,
+    /// Codec negotiation and encoder (reset on every connect)
+    codec: Arc<Mutex<CodecNegotiation>>,
+    /// Protocol version and capability negotiation (reset on every connect)
+    capabilities: Arc<Mutex<CapabilityNegotiation>>,
+    /// FEC policy for outgoing audio
+    fec_config: FecConfig,
+    /// FEC generator (also serializes audio sequence allocation)
+    fec_encoder: Mutex<FecStreamEncoder>,
+    /// FEC recovery state (reset on every connect)
+    fec_decoder: Arc<Mutex<FecStreamDecoder>>,
+    fec_packets_sent: Arc<AtomicU64>,
+    fec_packets_received: Arc<AtomicU64>,
+    packets_recovered: Arc<AtomicU64>,
+    /// Redundant audio policy for outgoing audio
+    redundancy_config: RedundancyConfig,
+    /// Adds copies of previous frames to outgoing audio packets
+    redundancy: Mutex<RedundancyEncoder>,
+    /// Bounds of the quality controller (None keeps quality fixed)
+    quality_config: Option<QualityConfig>,
+    /// Adapts codec, frame size and FEC to the network (reset on every connect)
+    quality: Arc<Mutex<Option<QualityController>>>,
+    /// Collects captured audio into packets of the adapted frame size
+    packetizer: Mutex<Packetizer>,
+    /// Callback for quality decisions
+    quality_callback: Option<Arc<QualityCallback>>,
+    /// Audio sequence tracking for loss statistics (reset on every connect)
+    sequence_tracker: Arc<Mutex<SequenceTracker>>,
+    /// Jitter and receiver reports for the peer's audio (reset on every
+    /// connect)
+    reception: Arc<Mutex<ReceptionReporter>>,
+    /// Latest receiver report from the peer on our audio
+    remote_reception: Arc<RwLock<Option<RemoteReceptionStats>>>,
     audio_callback: Option<Arc<AudioCallback>>,
+    /// Jitter buffer / decoder / PLC pipeline (opt-in)
+    receive_pipeline: Option<Arc<Mutex<ReceivePipeline>>>,
+    /// Callback for decoded frames from the receive pipeline
+    decoded_audio_callback: Option<Arc<DecodedAudioCallback>>,
+    /// Capture file for received packets (opt-in)
+    capture: SharedCapture,
     receive_handle: Option<tokio::task::JoinHandle<()>>,
     keepalive_handle: Option<tokio::task::JoinHandle<()>>,
+    playout_handle: Option<tokio::task::JoinHandle<()>>,
+    /// ICE agent answering checks and refreshing consent after `connect_ice`
+    ice_handle: Option<tokio::task::JoinHandle<()>>,
+    /// Retransmits unacknowledged control messages
+    control_handle: Option<tokio::task::JoinHandle<()>>,
+    /// Reliable control channel (reset on every connect)
+    control: Arc<Mutex<ControlChannel>>,
+    /// Retransmission policy of the control channel
+    control_config: ControlConfig,
+    /// Callback for received control messages
+    control_callback: Option<Arc<ControlCallback>>,
+    /// Timing of ICE connectivity checks
+    ice_config: IceConfig,
+    /// Liveness detection and reconnect timing
+    reconnect_config: ReconnectConfig,
     /// RTT measurement state
     rtt_measurement: Arc<RwLock<RttMeasurement>>,
     /// Peer latency information (received from remote peer)
@@ -279,18 +541,62 @@ impl Connection {
 
         Ok(Self {
             transport: Arc::new(transport),
-            remote_addr: "0.0.0.0:0".parse().unwrap(),
-            state: Arc::new(AtomicU8::new(ConnectionState::Disconnected as u8)),
+            remote_addr: Arc::new(RwLock::new("0.0.0.0:0".parse().unwrap())),
+            unconfirmed_candidates: Arc::new(Mutex::new(Vec::new())),
+            state: Arc::new(SharedState::new()),
             last_error: Arc::new(std::sync::Mutex::new(None)),
-            sequence: AtomicU32::new(0),
+            sequence: Arc::new(AtomicU32::new(0)),
+            audio_sequence: AtomicU32::new(0),
             packets_sent: Arc::new(AtomicU64::new(0)),
             packets_received: Arc::new(AtomicU64::new(0)),
+            packets_rejected: Arc::new(AtomicU64::new(0)),
+            packets_rejected_source: Arc::new(AtomicU64::new(0)),
+            address_migrations: Arc::new(AtomicU64::new(0)),
             bytes_sent: Arc::new(AtomicU64::new(0)),
             bytes_received: Arc::new(AtomicU64::new(0)),
             last_received: Arc::new(std::sync::Mutex::new(Instant::now())),
+            encryption_mode: EncryptionMode::default(),
+            key_exchange: Arc::new(Mutex::new(KeyExchangeState::new(EncryptionMode::default()))),
+            codec_config: CodecConfig::default(),
+            codec: Arc::new(Mutex::new(CodecNegotiation::new(CodecConfig::default()))),
+            capabilities: Arc::new(Mutex::new(CapabilityNegotiation::new(
+                connection_capabilities(EncryptionMode::default()),
+                CodecConfig::default().frame_size,
+            ))),
+            fec_config: FecConfig::default(),
+            fec_encoder: Mutex::new(FecStreamEncoder::new(FecConfig::default().group_size)),
+            fec_decoder: Arc::new(Mutex::new(FecStreamDecoder::new())),
+            fec_packets_sent: Arc::new(AtomicU64::new(0)),
+            fec_packets_received: Arc::new(AtomicU64::new(0)),
+            sequence_tracker: Arc::new(Mutex::new(SequenceTracker::new())),
+            reception: Arc::new(Mutex::new(ReceptionReporter::new(
+                CodecConfig::default().sample_rate,
+            ))),
+            remote_reception: Arc::new(RwLock::new(None)),
+            packets_recovered: Arc::new(AtomicU64::new(0)),
+            redundancy_config: RedundancyConfig::default(),
+            redundancy: Mutex::new(RedundancyEncoder::new(
+                RedundancyConfig::default(),
+                CodecConfig::default().sample_rate,
+            )),
+            quality_config: None,
+            quality: Arc::new(Mutex::new(None)),
+            packetizer: Mutex::new(Packetizer::default()),
+            quality_callback: None,
             audio_callback: None,
+            receive_pipeline: None,
+            decoded_audio_callback: None,
+            capture: Arc::new(Mutex::new(None)),
             receive_handle: None,
             keepalive_handle: None,
+            playout_handle: None,
+            ice_handle: None,
+            control_handle: None,
+            control: Arc::new(Mutex::new(ControlChannel::new(ControlConfig::default()))),
+            control_config: ControlConfig::default(),
+            control_callback: None,
+            ice_config: IceConfig::default(),
+            reconnect_config: ReconnectConfig::default(),
             rtt_measurement: Arc::new(RwLock::new(RttMeasurement::default())),
             peer_latency_info: Arc::new(RwLock::new(None)),
             latency_info_callback: None,
@@ -303,13 +609,41 @@ impl Connection {
         self.transport.local_addr()
     }
 
+    /// Get the remote peer's address (the selected candidate once connected)
+    pub fn remote_addr(&self) -> SocketAddr {
+        *self.remote_addr.read()
+    }
+
+    /// Allocate a relayed address on a relay server
+    ///
+    /// Must be called before connecting. The returned address should be
+    /// signaled to the peer as a relay candidate; `connect_with_candidates`
+    /// also falls back to probing through the relay when no candidate answers
+    /// directly.
+    pub async fn allocate_relay(&mut self, server: SocketAddr) -> Result<SocketAddr, NetworkError> {
+        if self.is_connected() {
+            return Err(NetworkError::AlreadyConnected);
+        }
+        self.transport.allocate_relay(server).await
+    }
+
+    /// Get the relayed address, if a relay allocation is held
+    pub fn relayed_addr(&self) -> Option<SocketAddr> {
+        self.transport.relayed_addr()
+    }
+
+    /// Check if traffic to the peer goes through the relay
+    pub fn is_relayed(&self) -> bool {
+        self.transport.is_relayed(self.remote_addr())
+    }
+
     /// Connect to a remote peer
     pub async fn connect(&mut self, remote_addr: SocketAddr) -> Result<(), NetworkError> {
         if self.is_connected() {
             return Err(NetworkError::AlreadyConnected);
         }
 
-        self.remote_addr = remote_addr;
+        *self.remote_addr.write() = remote_addr;
         self.set_state(ConnectionState::Connecting);
         info!("Connecting to {}", remote_addr);
 
@@ -317,14 +651,7 @@ impl Connection {
         let packet = Packet::keep_alive(self.next_sequence());
         self.transport.send_to(&packet, remote_addr).await?;
 
-        // Record connection start time
-        if let Ok(mut start) = self.connection_start.lock() {
-            *start = Some(Instant::now());
-        }
-
-        self.set_state(ConnectionState::Connected);
-        self.start_receive_loop();
-        self.start_keepalive_loop();
+        self.start_connected();
 
         info!("Connected to {}", remote_addr);
         Ok(())
@@ -334,6 +661,8 @@ impl Connection {
     ///
     /// This function tries multiple candidates in parallel and uses the first one that responds.
     /// Candidates are tried in order of priority, with a small delay between starting each attempt.
+    /// If none responds and a relay is allocated, the candidates are probed again through the
+    /// relay before falling back to the first candidate.
     pub async fn connect_with_candidates(
         &mut self,
         candidates: &[SocketAddr],
@@ -346,14 +675,246 @@ impl Connection {
             return Err(NetworkError::NoCandidates);
         }
 
-        // If only one candidate, use simple connect
-        if candidates.len() == 1 {
+        // If only one candidate and no relay to fall back to, use simple connect
+        if candidates.len() == 1 && self.transport.relayed_addr().is_none() {
             return self.connect(candidates[0]).await;
         }
 
         self.set_state(ConnectionState::CheckingConnectivity);
         info!("Checking connectivity with {} candidates", candidates.len());
 
+        let mut timeout = self.probe_candidates(candidates).await;
+
+        // Direct probes failed: try again through our relay, if any
+        if timeout.is_err() && self.transport.relayed_addr().is_some() {
+            info!("No direct response, probing candidates through the relay");
+            for &addr in candidates {
+                self.transport.set_relayed(addr, true);
+            }
+            timeout = self.probe_candidates(candidates).await;
+            if timeout.is_err() {
+                for &addr in candidates {
+                    self.transport.set_relayed(addr, false);
+                }
+            }
+        }
+
+        match timeout {
+            Ok(Some(selected_addr)) => {
+                info!(
+                    "Selected candidate: {} (first to respond{})",
+                    selected_addr,
+                    if self.transport.is_relayed(selected_addr) {
+                        ", relayed"
+                    } else {
+                        ""
+                    }
+                );
+                for &addr in candidates {
+                    if addr != selected_addr {
+                        self.transport.set_relayed(addr, false);
+                    }
+                }
+                *self.remote_addr.write() = selected_addr;
+                self.start_connected();
+
+                Ok(())
+            }
+            Ok(None) => {
+                self.set_state(ConnectionState::Failed);
+                Err(NetworkError::ConnectionFailed(
+                    "No candidate responded".to_string(),
+                ))
+            }
+            Err(_) => {
+                // Timeout - try fallback to first candidate
+                warn!("No candidate responded in time, falling back to first candidate");
+                self.set_state(ConnectionState::Connecting);
+                *self.remote_addr.write() = candidates[0];
+                // Nothing validated the path: the peer may still answer from
+                // another of its candidates
+                *self.unconfirmed_candidates.lock() = candidates.to_vec();
+                self.start_connected();
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Connect to a remote peer with ICE connectivity checks
+    ///
+    /// Both peers call this at about the same time with their own candidates
+    /// and the peer's (from signaling). Candidate pairs are checked with STUN
+    /// binding requests on the media socket, and the nominated pair becomes
+    /// the remote address. A relay allocation (see `allocate_relay`) adds
+    /// relayed pairs. Once connected, checks keep being answered and consent
+    /// is refreshed; the connection fails if consent expires.
+    pub async fn connect_ice(
+        &mut self,
+        local_candidates: &[AddressCandidate],
+        remote_candidates: &[AddressCandidate],
+    ) -> Result<(), NetworkError> {
+        if self.is_connected() {
+            return Err(NetworkError::AlreadyConnected);
+        }
+
+        if remote_candidates.is_empty() {
+            return Err(NetworkError::NoCandidates);
+        }
+
+        self.set_state(ConnectionState::CheckingConnectivity);
+        info!(
+            "Running ICE checks with {} remote candidates",
+            remote_candidates.len()
+        );
+
+        let mut agent = IceAgent::new(
+            ice::local_candidates(&self.transport, local_candidates),
+            self.ice_config.clone(),
+        );
+        agent.add_remote_candidates(remote_candidates);
+        let mut stun_rx = self.transport.stun_messages();
+
+        // The receive loop is not running yet, so read the socket here
+        let selected = run_checks(
+            &self.transport,
+            &mut agent,
+            &mut stun_rx,
+            self.ice_config.check_interval,
+            true,
+        )
+        .await;
+
+        let Some((remote_addr, relayed)) = selected else {
+            let error = NetworkError::ConnectionFailed("ICE checks failed".to_string());
+            self.set_failed(&error);
+            return Err(error);
+        };
+
+        info!(
+            "ICE selected {}{} as {:?}",
+            remote_addr,
+            if relayed { " (relayed)" } else { "" },
+            agent.role()
+        );
+        self.transport.set_relayed(remote_addr, relayed);
+        *self.remote_addr.write() = remote_addr;
+        self.start_connected();
+        self.start_ice_loop(agent, stun_rx);
+
+        Ok(())
+    }
+
+    /// Restart ICE checks on a live connection after a network change
+    ///
+    /// Call this with freshly gathered local candidates and the peer's latest
+    /// candidates (e.g. from `PeerUpdated`) while `Reconnecting`, or when the
+    /// peer reports new candidates. The peer must restart too. The stream
+    /// (keys, codec, sequence numbers, receive pipeline) carries on over the
+    /// newly nominated pair. On failure the state is left unchanged, so the
+    /// restart can be retried until the reconnect timeout.
+    pub async fn restart_ice(
+        &mut self,
+        local_candidates: &[AddressCandidate],
+        remote_candidates: &[AddressCandidate],
+    ) -> Result<(), NetworkError> {
+        if !self.state().can_transmit() {
+            return Err(NetworkError::NotConnected);
+        }
+
+        if remote_candidates.is_empty() {
+            return Err(NetworkError::NoCandidates);
+        }
+
+        info!(
+            "Restarting ICE with {} remote candidates",
+            remote_candidates.len()
+        );
+        if let Some(handle) = self.ice_handle.take() {
+            handle.abort();
+        }
+
+        let mut agent = IceAgent::new(
+            ice::local_candidates(&self.transport, local_candidates),
+            self.ice_config.clone(),
+        );
+        agent.add_remote_candidates(remote_candidates);
+        let mut stun_rx = self.transport.stun_messages();
+
+        // The receive loop reads the socket and hands us STUN messages
+        let selected = run_checks(
+            &self.transport,
+            &mut agent,
+            &mut stun_rx,
+            self.ice_config.check_interval,
+            false,
+        )
+        .await;
+
+        let Some((remote_addr, relayed)) = selected else {
+            return Err(NetworkError::ConnectionFailed(
+                "ICE restart failed".to_string(),
+            ));
+        };
+
+        let previous = self.remote_addr();
+        if previous != remote_addr {
+            info!("ICE restart moved {} to {}", previous, remote_addr);
+        }
+        self.transport.set_relayed(remote_addr, relayed);
+        *self.remote_addr.write() = remote_addr;
+        self.unconfirmed_candidates.lock().clear();
+        self.start_ice_loop(agent, stun_rx);
+
+        Ok(())
+    }
+
+    /// Set ICE check timing (used by `connect_ice` and `restart_ice`)
+    pub fn set_ice_config(&mut self, config: IceConfig) {
+        self.ice_config = config;
+    }
+
+    /// Set liveness detection and reconnect timing
+    pub fn set_reconnect_config(&mut self, config: ReconnectConfig) {
+        self.reconnect_config = config;
+    }
+
+    /// Set callback for connection state transitions
+    ///
+    /// Called from the connection's tasks on every change, including
+    /// `Connected` -> `Reconnecting` -> `Connected` when the peer goes silent
+    /// and comes back.
+    pub fn set_state_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(ConnectionState) + Send + Sync + 'static,
+    {
+        self.state.set_callback(Arc::new(Box::new(callback)));
+    }
+
+    /// Mark the connection established and start its loops
+    fn start_connected(&mut self) {
+        // Record connection start time
+        if let Ok(mut start) = self.connection_start.lock() {
+            *start = Some(Instant::now());
+        }
+        *self.last_received.lock().unwrap() = Instant::now();
+
+        self.set_state(ConnectionState::Connected);
+        self.start_receive_loop();
+        self.start_keepalive_loop();
+        self.start_playout_loop();
+        self.start_control_loop();
+    }
+
+    /// Probe all candidates and wait for the first to answer
+    ///
+    /// Probes are keep-alives; a keep-alive from a candidate is answered so a
+    /// peer probing us at the same time selects us too. Returns `Err` on
+    /// timeout.
+    async fn probe_candidates(
+        &self,
+        candidates: &[SocketAddr],
+    ) -> Result<Option<SocketAddr>, tokio::time::error::Elapsed> {
         // Try candidates with Happy Eyeballs approach:
         // - Send probes to all candidates
         // - Use the first one that responds
@@ -377,7 +938,7 @@ impl Connection {
 
         // Wait for first response
         let transport = self.transport.clone();
-        let timeout = tokio::time::timeout(PROBE_TIMEOUT, async {
+        tokio::time::timeout(PROBE_TIMEOUT, async {
             loop {
                 match transport.recv_raw().await {
                     Ok((buf, from_addr)) => {
@@ -385,6 +946,10 @@ impl Connection {
                         if candidates.contains(&from_addr) {
                             if let Some(packet) = Packet::from_bytes(&buf) {
                                 if matches!(packet.packet_type, PacketType::KeepAlive) {
+                                    let reply = Packet::keep_alive(self.next_sequence());
+                                    if let Err(e) = transport.send_to(&reply, from_addr).await {
+                                        debug!("Failed to answer probe from {}: {}", from_addr, e);
+                                    }
                                     return Some(from_addr);
                                 }
                             }
@@ -397,48 +962,7 @@ impl Connection {
                 }
             }
         })
-        .await;
-
-        match timeout {
-            Ok(Some(selected_addr)) => {
-                info!("Selected candidate: {} (first to respond)", selected_addr);
-                self.remote_addr = selected_addr;
-
-                // Record connection start time
-                if let Ok(mut start) = self.connection_start.lock() {
-                    *start = Some(Instant::now());
-                }
-
-                self.set_state(ConnectionState::Connected);
-                self.start_receive_loop();
-                self.start_keepalive_loop();
-
-                Ok(())
-            }
-            Ok(None) => {
-                self.set_state(ConnectionState::Failed);
-                Err(NetworkError::ConnectionFailed(
-                    "No candidate responded".to_string(),
-                ))
-            }
-            Err(_) => {
-                // Timeout - try fallback to first candidate
-                warn!("No candidate responded in time, falling back to first candidate");
-                self.set_state(ConnectionState::Connecting);
-                self.remote_addr = candidates[0];
-
-                // Record connection start time
-                if let Ok(mut start) = self.connection_start.lock() {
-                    *start = Some(Instant::now());
-                }
-
-                self.set_state(ConnectionState::Connected);
-                self.start_receive_loop();
-                self.start_keepalive_loop();
-
-                Ok(())
-            }
-        }
+        .await
     }
 
     /// Disconnect from the remote peer
@@ -451,8 +975,17 @@ impl Connection {
         if let Some(handle) = self.keepalive_handle.take() {
             handle.abort();
         }
+        if let Some(handle) = self.playout_handle.take() {
+            handle.abort();
+        }
+        if let Some(handle) = self.ice_handle.take() {
+            handle.abort();
+        }
+        if let Some(handle) = self.control_handle.take() {
+            handle.abort();
+        }
 
-        info!("Disconnected from {}", self.remote_addr);
+        info!("Disconnected from {}", self.remote_addr());
     }
 
     /// Check if connected
@@ -462,7 +995,7 @@ impl Connection {
 
     /// Get current connection state
     pub fn state(&self) -> ConnectionState {
-        ConnectionState::from_u8(self.state.load(Ordering::SeqCst))
+        self.state.get()
     }
 
     /// Set connection state
@@ -473,7 +1006,7 @@ impl Connection {
                 *err = None;
             }
         }
-        self.state.store(state as u8, Ordering::SeqCst);
+        self.state.set(state);
     }
 
     /// Set connection to failed state with error information
@@ -482,8 +1015,7 @@ impl Connection {
         if let Ok(mut err) = self.last_error.lock() {
             *err = Some(error.to_string());
         }
-        self.state
-            .store(ConnectionState::Failed as u8, Ordering::SeqCst);
+        self.state.set(ConnectionState::Failed);
     }
 
     /// Get the last error message that caused connection failure
@@ -494,6 +1026,9 @@ impl Connection {
     }
 
     /// Set callback for received audio data
+    ///
+    /// The payload is always PCM (little-endian f32); compressed packets are
+    /// decoded first.
     pub fn set_audio_callback<F>(&mut self, callback: F)
     where
         F: Fn(&[u8], u32) + Send + Sync + 'static,
@@ -501,82 +1036,424 @@ impl Connection {
         self.audio_callback = Some(Arc::new(Box::new(callback)));
     }
 
-    /// Set callback for received peer latency info
-    pub fn set_latency_info_callback<F>(&mut self, callback: F)
+    /// Enable the receive pipeline (jitter buffer, decoder and PLC)
+    ///
+    /// When enabled, received audio packets are no longer passed to the raw
+    /// audio callback. Instead they are reordered in the jitter buffer, decoded,
+    /// and handed to the decoded audio callback on the playout clock. Lost
+    /// frames are filled by packet loss concealment. In passthrough mode frames
+    /// are delivered as soon as they arrive.
+    ///
+    /// Must be called before connecting.
+    pub fn set_receive_pipeline(
+        &mut self,
+        config: ReceivePipelineConfig,
+    ) -> Result<(), NetworkError> {
+        let pipeline = ReceivePipeline::new(config)?;
+        self.receive_pipeline = Some(Arc::new(Mutex::new(pipeline)));
+        Ok(())
+    }
+
+    /// Set callback for decoded audio frames from the receive pipeline
+    pub fn set_decoded_audio_callback<F>(&mut self, callback: F)
     where
-        F: Fn(PeerLatencyInfo) + Send + Sync + 'static,
+        F: Fn(&[f32], u32) + Send + Sync + 'static,
     {
-        self.latency_info_callback = Some(Arc::new(Box::new(callback)));
+        self.decoded_audio_callback = Some(Arc::new(Box::new(callback)));
     }
 
-    /// Send latency info to the remote peer
-    pub async fn send_latency_info(&self, info: &LatencyInfoMessage) -> Result<(), NetworkError> {
-        if !self.state().can_transmit() {
-            return Err(NetworkError::NotConnected);
-        }
-
-        let packet = Packet::latency_info(self.next_sequence(), info);
-        self.transport.send_to(&packet, self.remote_addr).await?;
+    /// Get receive pipeline statistics (if the pipeline is enabled)
+    pub fn receive_pipeline_stats(&self) -> Option<ReceivePipelineStats> {
+        self.receive_pipeline.as_ref().map(|p| p.lock().stats())
+    }
 
-        debug!("Sent latency info to {}", self.remote_addr);
+    /// Write every received packet to a capture file for offline replay
+    ///
+    /// Packets are recorded after decryption, with their arrival time and
+    /// source. Replaces any capture in progress.
+    pub fn start_capture<P: AsRef<Path>>(&self, path: P) -> Result<(), NetworkError> {
+        let capture = PacketCapture::create(path)?;
+        *self.capture.lock() = Some(capture);
         Ok(())
     }
 
-    /// Get the current RTT estimate in milliseconds
-    pub fn rtt_ms(&self) -> f32 {
-        self.rtt_measurement.read().rtt_ms
+    /// Stop capturing and flush the capture file (returns packets captured)
+    pub fn stop_capture(&self) -> Result<u64, NetworkError> {
+        match self.capture.lock().take() {
+            Some(capture) => capture.finish(),
+            None => Ok(0),
+        }
     }
 
-    /// Get the current jitter estimate in milliseconds
-    pub fn jitter_ms(&self) -> f32 {
-        self.rtt_measurement.read().jitter_ms
+    /// Set the encryption policy
+    ///
+    /// Keys are exchanged with `PacketType::KeyExchange` packets right after
+    /// connecting. In required mode media cannot be sent until the exchange
+    /// completes and unencrypted packets from the peer are dropped.
+    ///
+    /// Must be called before connecting.
+    pub fn set_encryption_mode(&mut self, mode: EncryptionMode) {
+        self.encryption_mode = mode;
     }
 
-    /// Get the peer's latency information (if received)
-    pub fn peer_latency_info(&self) -> Option<PeerLatencyInfo> {
-        self.peer_latency_info.read().clone()
+    /// Get the encryption policy
+    pub fn encryption_mode(&self) -> EncryptionMode {
+        self.encryption_mode
     }
 
-    /// Send audio data to the remote peer
-    pub async fn send_audio(&self, data: &[f32], timestamp: u32) -> Result<(), NetworkError> {
-        if !self.state().can_transmit() {
-            return Err(NetworkError::NotConnected);
-        }
+    /// Check if the key exchange has completed and packets are encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.key_exchange.lock().is_established()
+    }
 
-        // Convert f32 samples to bytes (little-endian)
-        let bytes: Vec<u8> = data.iter().flat_map(|&s| s.to_le_bytes()).collect();
+    /// Wait until the key exchange with the peer has completed
+    pub async fn wait_for_encryption(&self, timeout: Duration) -> Result<(), NetworkError> {
+        if !self.encryption_mode.is_enabled() {
+            return Err(NetworkError::KeyExchangeFailed(
+                "Encryption is disabled".to_string(),
+            ));
+        }
 
-        let packet = Packet::audio(self.next_sequence(), timestamp, bytes);
-        let packet_bytes = packet.to_bytes();
-        let len = packet_bytes.len() as u64;
+        let deadline = Instant::now() + timeout;
+        while !self.is_encrypted() {
+            if Instant::now() >= deadline {
+                return Err(NetworkError::KeyExchangeFailed(
+                    "Timed out waiting for peer key".to_string(),
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(())
+    }
 
-        self.transport.send_to(&packet, self.remote_addr).await?;
+    /// Set the preferred codec and audio parameters
+    ///
+    /// The codec actually used is negotiated with the peer right after
+    /// connecting: Opus is used if either side prefers it and both support
+    /// it, PCM otherwise. Received audio is decoded according to the codec
+    /// in each packet's header.
+    ///
+    /// Must be called before connecting.
+    pub fn set_codec_config(&mut self, config: CodecConfig) {
+        *self.redundancy.lock() =
+            RedundancyEncoder::new(self.redundancy_config, config.sample_rate);
+        self.codec_config = config;
+    }
 
-        self.packets_sent.fetch_add(1, Ordering::Relaxed);
-        self.bytes_sent.fetch_add(len, Ordering::Relaxed);
+    /// Get the preferred codec configuration
+    pub fn codec_config(&self) -> &CodecConfig {
+        &self.codec_config
+    }
 
-        Ok(())
+    /// Codec currently used for outgoing audio
+    pub fn send_codec(&self) -> CodecType {
+        self.codec.lock().send_codec()
     }
 
-    /// Get connection statistics
-    pub fn stats(&self) -> ConnectionStats {
-        let rtt = self.rtt_measurement.read();
-        let uptime = self
-            .connection_start
-            .lock()
-            .ok()
-            .and_then(|start| start.map(|s| s.elapsed().as_secs()))
-            .unwrap_or(0);
+    /// Protocol version and capabilities agreed with the peer
+    ///
+    /// None before the peer's hello arrives (a peer predating the handshake
+    /// never sends one) or if the peer is incompatible, in which case the
+    /// connection fails and `last_error` says why.
+    pub fn capabilities(&self) -> Option<NegotiatedCapabilities> {
+        self.capabilities.lock().agreed()
+    }
+
+    /// Set the FEC policy for outgoing audio
+    ///
+    /// When enabled, one XOR FEC packet is sent after every `group_size`
+    /// audio packets. Incoming FEC packets are always used for recovery,
+    /// regardless of this setting.
+    pub fn set_fec_config(&mut self, config: FecConfig) {
+        self.fec_config = config;
+        // Keep the encoder: its FEC header sequences must not restart while
+        // the session keys live
+        self.fec_encoder.lock().set_group_size(config.group_size);
+    }
+
+    /// Get the FEC policy
+    pub fn fec_config(&self) -> FecConfig {
+        self.fec_config
+    }
+
+    /// Set the redundant audio policy for outgoing audio
+    ///
+    /// When enabled, every audio packet also carries copies of the previous
+    /// `depth` frames, which the peer plays in place of lost frames without
+    /// waiting for a FEC group. Only peers that announce redundancy in their
+    /// hello get it. Incoming redundant frames are always used when a
+    /// receive pipeline is set.
+    pub fn set_redundancy_config(&mut self, config: RedundancyConfig) {
+        self.redundancy_config = config;
+        *self.redundancy.lock() = RedundancyEncoder::new(config, self.codec_config.sample_rate);
+    }
+
+    /// Get the redundant audio policy
+    pub fn redundancy_config(&self) -> RedundancyConfig {
+        self.redundancy_config
+    }
+
+    /// Adapt outgoing audio to the network within the given bounds
+    ///
+    /// Once a second, loss and jitter of the peer's stream and the round
+    /// trip decide on the Opus bitrate, frame size, FEC strength and a
+    /// fallback from PCM to Opus, starting from the codec and FEC policy.
+    /// Without it, quality stays as configured.
+    ///
+    /// Must be called before connecting.
+    pub fn set_quality_config(&mut self, config: QualityConfig) {
+        self.quality_config = Some(config);
+    }
+
+    /// Set callback for quality controller decisions
+    ///
+    /// Must be called before connecting.
+    pub fn set_quality_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(QualityDecision) + Send + Sync + 'static,
+    {
+        self.quality_callback = Some(Arc::new(Box::new(callback)));
+    }
+
+    /// Settings currently chosen by the quality controller (if enabled)
+    pub fn quality_settings(&self) -> Option<QualitySettings> {
+        self.quality
+            .lock()
+            .as_ref()
+            .map(QualityController::settings)
+    }
+
+    /// Set callback for received peer latency info
+    pub fn set_latency_info_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(PeerLatencyInfo) + Send + Sync + 'static,
+    {
+        self.latency_info_callback = Some(Arc::new(Box::new(callback)));
+    }
+
+    /// Send latency info to the remote peer
+    pub async fn send_latency_info(&self, info: &LatencyInfoMessage) -> Result<(), NetworkError> {
+        if !self.state().can_transmit() {
+            return Err(NetworkError::NotConnected);
+        }
+
+        let secure = self.key_exchange.lock().outbound()?;
+        let packet = Packet::latency_info(self.next_sequence(), info);
+        send_packet(
+            &self.transport,
+            secure.as_deref(),
+            &packet,
+            self.remote_addr(),
+        )
+        .await?;
+
+        debug!("Sent latency info to {}", self.remote_addr());
+        Ok(())
+    }
+
+    /// Set the control channel's retransmission policy
+    ///
+    /// Takes effect on the next connect.
+    pub fn set_control_config(&mut self, config: ControlConfig) {
+        self.control_config = config;
+    }
+
+    /// Set callback for control messages from the peer
+    ///
+    /// Must be called before connecting. Messages are delivered once each, in
+    /// the order the peer sent them.
+    pub fn set_control_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(ControlMessage) + Send + Sync + 'static,
+    {
+        self.control_callback = Some(Arc::new(Box::new(callback)));
+    }
+
+    /// Send a control message to the peer
+    ///
+    /// The message is retransmitted until the peer acknowledges it, so an
+    /// `Ok` only means it was queued and sent once.
+    pub async fn send_control(&self, message: ControlMessage) -> Result<(), NetworkError> {
+        if !self.state().can_transmit() {
+            return Err(NetworkError::NotConnected);
+        }
+
+        let secure = self.key_exchange.lock().outbound()?;
+        let packet = self.control.lock().send(message, Instant::now());
+        send_packet(
+            &self.transport,
+            secure.as_deref(),
+            &packet,
+            self.remote_addr(),
+        )
+        .await
+    }
+
+    /// Get control channel statistics
+    pub fn control_stats(&self) -> ControlStats {
+        self.control.lock().stats()
+    }
+
+    /// Get the current RTT estimate in milliseconds
+    pub fn rtt_ms(&self) -> f32 {
+        self.rtt_measurement.read().rtt_ms
+    }
+
+    /// Get the current jitter estimate in milliseconds
+    pub fn jitter_ms(&self) -> f32 {
+        self.rtt_measurement.read().jitter_ms
+    }
+
+    /// Get the peer's clock offset and drift (None before the first pong)
+    pub fn clock_estimate(&self) -> Option<ClockEstimate> {
+        self.rtt_measurement.read().clock.estimate()
+    }
+
+    /// Get the peer's latency information (if received)
+    pub fn peer_latency_info(&self) -> Option<PeerLatencyInfo> {
+        self.peer_latency_info.read().clone()
+    }
+
+    /// Send audio data to the remote peer
+    pub async fn send_audio(&self, data: &[f32], timestamp: u32) -> Result<(), NetworkError> {
+        if !self.state().can_transmit() {
+            return Err(NetworkError::NotConnected);
+        }
+
+        let secure = self.key_exchange.lock().outbound()?;
+        // FEC only goes to peers that use it, Reed-Solomon only to those
+        // that recover from it
+        let negotiated = self.capabilities.lock().negotiated();
+        let fec = negotiated.fec;
+        let redundant = negotiated.redundancy;
+        self.fec_encoder
+            .lock()
+            .set_scheme(negotiated.fec_scheme(self.fec_config.scheme));
+        let Some(settings) = self.quality_settings() else {
+            let fec_group = (self.fec_config.enabled && fec).then_some(self.fec_config.group_size);
+            return self
+                .send_frame(secure.as_deref(), data, timestamp, fec_group, redundant)
+                .await;
+        };
+
+        let packets = self.packetizer.lock().push(
+            data,
+            timestamp,
+            settings.frame_size,
+            self.codec_config.channels,
+        );
+        for (frame, timestamp) in packets {
+            self.send_frame(
+                secure.as_deref(),
+                &frame,
+                timestamp,
+                settings.fec_group_size.filter(|_| fec),
+                redundant,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Encode and send one audio packet, followed by the FEC packets when a
+    /// group of `fec_group` packets completes
+    ///
+    /// With `redundant`, the packet also carries copies of previous frames.
+    async fn send_frame(
+        &self,
+        secure: Option<&EncryptedTransport>,
+        data: &[f32],
+        timestamp: u32,
+        fec_group: Option<usize>,
+        redundant: bool,
+    ) -> Result<(), NetworkError> {
+        let (codec, bytes) = self.codec.lock().encode(data);
+
+        let (sequence, fec) = if let Some(group_size) = fec_group {
+            // Hold the encoder while allocating so groups stay consecutive
+            let mut encoder = self.fec_encoder.lock();
+            encoder.set_group_size(group_size);
+            let sequence = self.audio_sequence.fetch_add(1, Ordering::Relaxed);
+            let fec = encoder.add_packet_all(sequence, timestamp, codec, &bytes);
+            let fec = fec
+                .into_iter()
+                .map(|fec| Packet::fec(encoder.next_sequence(), fec.to_bytes()))
+                .collect();
+            (sequence, fec)
+        } else {
+            (
+                self.audio_sequence.fetch_add(1, Ordering::Relaxed),
+                Vec::new(),
+            )
+        };
+
+        let mut packet = Packet::audio(sequence, timestamp, bytes);
+        packet.flags.has_fec = fec_group.is_some();
+        packet.flags.codec = codec;
+        if redundant {
+            self.redundancy
+                .lock()
+                .protect(&mut packet, data, self.codec_config.channels);
+        }
+        let packet_bytes = packet.to_bytes();
+        let len = packet_bytes.len() as u64;
+
+        send_packet(&self.transport, secure, &packet, self.remote_addr()).await?;
+
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(len, Ordering::Relaxed);
+
+        for fec_packet in fec {
+            let len = (fec_packet.header_len() + fec_packet.payload.len()) as u64;
+            send_packet(&self.transport, secure, &fec_packet, self.remote_addr()).await?;
+
+            self.packets_sent.fetch_add(1, Ordering::Relaxed);
+            self.fec_packets_sent.fetch_add(1, Ordering::Relaxed);
+            self.bytes_sent.fetch_add(len, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    /// Get connection statistics
+    pub fn stats(&self) -> ConnectionStats {
+        let rtt = self.rtt_measurement.read();
+        let loss = self.sequence_tracker.lock().stats();
+        let uptime = self
+            .connection_start
+            .lock()
+            .ok()
+            .and_then(|start| start.map(|s| s.elapsed().as_secs()))
+            .unwrap_or(0);
 
         ConnectionStats {
             rtt_ms: rtt.rtt_ms,
-            packet_loss_rate: 0.0, // TODO: Implement packet loss tracking
+            packet_loss_rate: loss.loss_rate,
+            recent_packet_loss_rate: loss.recent_loss_rate,
+            packets_lost: loss.packets_lost,
+            packets_reordered: loss.packets_reordered,
+            packets_duplicated: loss.packets_duplicated,
+            loss_bursts: loss.loss_bursts,
+            mean_burst_length: loss.mean_burst_length,
+            max_burst_length: loss.max_burst_length,
             jitter_ms: rtt.jitter_ms,
             bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
             bytes_received: self.bytes_received.load(Ordering::Relaxed),
             packets_sent: self.packets_sent.load(Ordering::Relaxed),
             packets_received: self.packets_received.load(Ordering::Relaxed),
             uptime_seconds: uptime,
+            encrypted: self.is_encrypted(),
+            send_codec: self.send_codec(),
+            packets_rejected: self.packets_rejected.load(Ordering::Relaxed),
+            packets_rejected_source: self.packets_rejected_source.load(Ordering::Relaxed),
+            address_migrations: self.address_migrations.load(Ordering::Relaxed),
+            fec_packets_sent: self.fec_packets_sent.load(Ordering::Relaxed),
+            fec_packets_received: self.fec_packets_received.load(Ordering::Relaxed),
+            packets_recovered: self.packets_recovered.load(Ordering::Relaxed),
+            clock: rtt.clock.estimate(),
+            quality: self.quality_settings(),
+            remote_reception: *self.remote_reception.read(),
+            capabilities: self.capabilities(),
         }
     }
 
@@ -585,35 +1462,219 @@ impl Connection {
     }
 
     fn start_receive_loop(&mut self) {
+        // Fresh ephemeral keys for every connection attempt
+        *self.key_exchange.lock() = KeyExchangeState::new(self.encryption_mode);
+        *self.fec_decoder.lock() = FecStreamDecoder::new();
+        self.sequence_tracker.lock().reset();
+        *self.reception.lock() = ReceptionReporter::new(self.codec_config.sample_rate);
+        *self.remote_reception.write() = None;
+        *self.codec.lock() = CodecNegotiation::new(self.codec_config.clone());
+        *self.capabilities.lock() = CapabilityNegotiation::new(
+            connection_capabilities(self.encryption_mode),
+            self.codec_config.frame_size,
+        );
+        *self.packetizer.lock() = Packetizer::default();
+        *self.quality.lock() = self.quality_config.clone().map(|config| {
+            let controller = QualityController::new(config, &self.codec_config, &self.fec_config);
+            self.codec.lock().apply_quality(&controller.settings());
+            controller
+        });
+        *self.control.lock() = ControlChannel::new(self.control_config.clone());
+
         let transport = self.transport.clone();
         let state = self.state.clone();
+        let last_error = self.last_error.clone();
         let last_received = self.last_received.clone();
         let packets_received = self.packets_received.clone();
+        let packets_rejected = self.packets_rejected.clone();
+        let packets_rejected_source = self.packets_rejected_source.clone();
+        let address_migrations = self.address_migrations.clone();
+        let key_exchange = self.key_exchange.clone();
+        let fec_decoder = self.fec_decoder.clone();
+        let sequence_tracker = self.sequence_tracker.clone();
+        let reception = self.reception.clone();
+        let remote_reception = self.remote_reception.clone();
+        let fec_packets_received = self.fec_packets_received.clone();
+        let packets_recovered = self.packets_recovered.clone();
         let bytes_received = self.bytes_received.clone();
-        let audio_callback = self.audio_callback.clone();
+        let capture = self.capture.clone();
+        let codec = self.codec.clone();
+        let capabilities = self.capabilities.clone();
+        let quality = self.quality.clone();
+        let mut playout = AudioPlayout {
+            receive_pipeline: self.receive_pipeline.clone(),
+            audio_callback: self.audio_callback.clone(),
+            decoded_audio_callback: self.decoded_audio_callback.clone(),
+            decoders: AudioDecoders::new(CodecConfig {
+                codec_type: CodecType::Pcm,
+                ..self.codec_config.clone()
+            })
+            .expect("PCM is always available"),
+        };
         let rtt_measurement = self.rtt_measurement.clone();
         let peer_latency_info = self.peer_latency_info.clone();
         let latency_info_callback = self.latency_info_callback.clone();
-        let remote_addr = self.remote_addr;
-        let sequence = Arc::new(AtomicU32::new(1_000_000)); // Separate sequence for pong responses
+        let control = self.control.clone();
+        let control_callback = self.control_callback.clone();
+        let remote = self.remote_addr.clone();
+        let unconfirmed_candidates = self.unconfirmed_candidates.clone();
+        let sequence = self.sequence.clone();
 
         let handle = tokio::spawn(async move {
             let (mut rx, _recv_handle) = transport.clone().start_receive_loop();
+            let mut path_validator = PathValidator::new();
 
-            while let Some((packet, _addr)) = rx.recv().await {
-                let current_state = ConnectionState::from_u8(state.load(Ordering::SeqCst));
+            while let Some((packet, addr)) = rx.recv().await {
+                let received_at_us = local_clock_us();
+                let current_state = state.get();
                 if !current_state.can_transmit() {
                     break;
                 }
+                let mut remote_addr = *remote.read();
+
+                let wire_len = (packet.header_len() + packet.payload.len()) as u64;
+                let encrypted = packet.flags.encrypted;
+                let mut packet = match key_exchange.lock().open(packet) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        packets_rejected.fetch_add(1, Ordering::Relaxed);
+                        trace!("Dropped packet: {}", e);
+                        continue;
+                    }
+                };
+
+                // After a fallback nothing told us which candidate the peer
+                // answers from: the first packet from any of them settles it
+                {
+                    let mut unconfirmed = unconfirmed_candidates.lock();
+                    if !unconfirmed.is_empty()
+                        && (addr == remote_addr || unconfirmed.contains(&addr))
+                    {
+                        unconfirmed.clear();
+                        if addr != remote_addr {
+                            info!("Peer answered from {} instead of {}", addr, remote_addr);
+                            *remote.write() = addr;
+                            remote_addr = addr;
+                        }
+                    }
+                }
+
+                if addr != remote_addr {
+                    // Only packets sealed with the session keys can move the
+                    // peer. Without them (before the key exchange or with
+                    // encryption disabled) anyone can forge a packet that
+                    // looks like the peer's, so the address stays put.
+                    let now = Instant::now();
+                    let validated = encrypted
+                        && packet.packet_type == PacketType::PathResponse
+                        && PathValidationPayload::from_bytes(&packet.payload).is_some_and(
+                            |response| path_validator.validate(addr, response.cookie, now),
+                        );
+                    if !validated {
+                        packets_rejected_source.fetch_add(1, Ordering::Relaxed);
+                        if let Some(dropped) = path_validator.reject(now) {
+                            warn!(
+                                "Dropped {} packets from unexpected addresses (latest from {}, peer is at {})",
+                                dropped, addr, remote_addr
+                            );
+                        }
+
+                        // A sealed packet means the peer may have moved:
+                        // challenge the new address if the peer can answer.
+                        // The challenge is sealed too, so only the peer can
+                        // read the cookie it has to echo.
+                        let answers = capabilities.lock().negotiated().path_validation;
+                        let Some(cookie) = (encrypted && answers)
+                            .then(|| path_validator.challenge(addr, now))
+                            .flatten()
+                        else {
+                            continue;
+                        };
+                        let challenge = Packet::path_challenge(
+                            sequence.fetch_add(1, Ordering::Relaxed),
+                            &PathValidationPayload { cookie },
+                        );
+                        let secure = key_exchange.lock().outbound();
+                        if let Ok(secure) = secure {
+                            debug!("Challenging {} for the peer at {}", addr, remote_addr);
+                            if let Err(e) =
+                                send_packet(&transport, secure.as_deref(), &challenge, addr).await
+                            {
+                                warn!("Failed to send path challenge: {}", e);
+                            }
+                        }
+                        continue;
+                    }
+
+                    info!("Peer moved from {} to {}", remote_addr, addr);
+                    *remote.write() = addr;
+                    remote_addr = addr;
+                    address_migrations.fetch_add(1, Ordering::Relaxed);
+                }
+
+                capture_packet(&capture, received_at_us, addr, &packet);
 
                 *last_received.lock().unwrap() = Instant::now();
                 packets_received.fetch_add(1, Ordering::Relaxed);
-                bytes_received.fetch_add(packet.payload.len() as u64 + 12, Ordering::Relaxed);
+                if current_state == ConnectionState::Reconnecting {
+                    info!("Connection to {} resumed", remote_addr);
+                    state.set(ConnectionState::Connected);
+                }
+                bytes_received.fetch_add(wire_len, Ordering::Relaxed);
+
+                if packet.stream.is_some() {
+                    // Additional streams are only played by a Session
+                    trace!("Ignoring stream {} from {}", packet.stream_id(), addr);
+                    continue;
+                }
 
                 match packet.packet_type {
                     PacketType::Audio => {
-                        if let Some(ref callback) = audio_callback {
-                            callback(&packet.payload, packet.timestamp);
+                        sequence_tracker.lock().record(packet.sequence);
+                        reception.lock().on_packet(packet.timestamp, received_at_us);
+                        let Some(redundant) = split_redundancy(&mut packet) else {
+                            trace!("Dropped malformed redundant audio seq={}", packet.sequence);
+                            continue;
+                        };
+                        playout.deliver_redundant(redundant);
+                        let recovered = {
+                            let mut fec_decoder = fec_decoder.lock();
+                            if fec_decoder.contains(packet.sequence) {
+                                // Already rebuilt from FEC
+                                continue;
+                            }
+                            fec_decoder.add_packet_all(
+                                packet.sequence,
+                                packet.timestamp,
+                                packet.flags.codec,
+                                &packet.payload,
+                            )
+                        };
+
+                        let audio = RecoveredAudio {
+                            sequence: packet.sequence,
+                            timestamp: packet.timestamp,
+                            codec: packet.flags.codec,
+                            payload: packet.payload,
+                        };
+                        playout.deliver(audio, false);
+                        for recovered in recovered {
+                            if playout.deliver(recovered, true) {
+                                packets_recovered.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                    PacketType::Fec => {
+                        fec_packets_received.fetch_add(1, Ordering::Relaxed);
+                        let Some(fec) = FecPacket::from_bytes(&packet.payload) else {
+                            continue;
+                        };
+                        let recovered = fec_decoder.lock().add_fec_all(fec);
+                        for recovered in recovered {
+                            trace!("Recovered audio seq={} from FEC", recovered.sequence);
+                            if playout.deliver(recovered, true) {
+                                packets_recovered.fetch_add(1, Ordering::Relaxed);
+                            }
                         }
                     }
                     PacketType::KeepAlive => {
@@ -622,16 +1683,23 @@ impl Connection {
                     PacketType::LatencyPing => {
                         // Respond with pong
                         if let Some(ping) = LatencyPing::from_bytes(&packet.payload) {
-                            let pong = LatencyPong {
-                                original_sent_time_us: ping.sent_time_us,
-                                ping_sequence: ping.ping_sequence,
-                            };
+                            let pong = LatencyPong::reply(&ping, received_at_us, local_clock_us());
                             let pong_packet = Packet::latency_pong(
                                 sequence.fetch_add(1, Ordering::Relaxed),
                                 &pong,
                             );
-                            if let Err(e) = transport.send_to(&pong_packet, remote_addr).await {
-                                warn!("Failed to send latency pong: {}", e);
+                            let secure = key_exchange.lock().outbound();
+                            if let Ok(secure) = secure {
+                                if let Err(e) = send_packet(
+                                    &transport,
+                                    secure.as_deref(),
+                                    &pong_packet,
+                                    remote_addr,
+                                )
+                                .await
+                                {
+                                    warn!("Failed to send latency pong: {}", e);
+                                }
                             }
                             trace!("Responded to latency ping seq={}", ping.ping_sequence);
                         }
@@ -642,6 +1710,12 @@ impl Connection {
                             rtt_measurement.write().process_pong(&pong);
                         }
                     }
+                    PacketType::ReceiverReport => {
+                        if let Some(report) = ReceiverReport::from_bytes(&packet.payload) {
+                            *remote_reception.write() =
+                                Some(RemoteReceptionStats::from_report(&report, received_at_us));
+                        }
+                    }
                     PacketType::LatencyInfo => {
                         // Store peer's latency info
                         if let Some(info) = LatencyInfoMessage::from_bytes(&packet.payload) {
@@ -658,6 +1732,135 @@ impl Connection {
                             *peer_latency_info.write() = Some(peer_info);
                         }
                     }
+                    PacketType::CodecOffer => {
+                        let Some(offer) = CodecOfferPayload::from_bytes(&packet.payload) else {
+                            continue;
+                        };
+                        let reply = {
+                            let mut codec = codec.lock();
+                            codec.handle_offer(offer).then(|| {
+                                codec.offer_packet(sequence.fetch_add(1, Ordering::Relaxed), true)
+                            })
+                        };
+                        let secure = key_exchange.lock().outbound();
+                        if let (Some(reply), Ok(secure)) = (reply, secure) {
+                            if let Err(e) =
+                                send_packet(&transport, secure.as_deref(), &reply, remote_addr)
+                                    .await
+                            {
+                                warn!("Failed to send codec offer: {}", e);
+                            }
+                        }
+                    }
+                    PacketType::Hello => {
+                        let Some(hello) = HelloPayload::from_bytes(&packet.payload) else {
+                            continue;
+                        };
+                        let (reply, negotiated, incompatibility) = {
+                            let mut capabilities = capabilities.lock();
+                            let reply = capabilities.handle_hello(hello).then(|| {
+                                capabilities
+                                    .hello_packet(sequence.fetch_add(1, Ordering::Relaxed), true)
+                            });
+                            (reply, capabilities.agreed(), capabilities.incompatibility())
+                        };
+                        // Hellos go out in cleartext until the session keys
+                        // are established, sealed after that
+                        let secure = key_exchange.lock().outbound().ok().flatten();
+                        if let Some(reply) = reply {
+                            if let Err(e) =
+                                send_packet(&transport, secure.as_deref(), &reply, remote_addr)
+                                    .await
+                            {
+                                warn!("Failed to send hello: {}", e);
+                            }
+                        }
+                        if let Some(e) = incompatibility {
+                            warn!("Cannot talk to {}: {}", remote_addr, e);
+                            if let Ok(mut err) = last_error.lock() {
+                                *err = Some(e.to_string());
+                            }
+                            state.set(ConnectionState::Failed);
+                            break;
+                        }
+                        // Keep frames within what the peer accepts
+                        let limited = negotiated.and_then(|negotiated| {
+                            quality
+                                .lock()
+                                .as_mut()?
+                                .limit_frame_size(negotiated.max_frame_size)
+                        });
+                        if let Some(settings) = limited {
+                            codec.lock().apply_quality(&settings);
+                        }
+                    }
+                    PacketType::KeyExchange => {
+                        let Some(payload) = KeyExchangePayload::from_bytes(&packet.payload) else {
+                            continue;
+                        };
+                        let result = {
+                            let mut key_exchange = key_exchange.lock();
+                            let was_established = key_exchange.is_established();
+                            key_exchange
+                                .handle_key_exchange(&transport, &payload)
+                                .map(|reply| (reply, !was_established))
+                        };
+                        match result {
+                            Ok((reply, newly_established)) => {
+                                if newly_established {
+                                    info!("End-to-end encryption established with {}", remote_addr);
+                                }
+                                if let Some(reply) = reply {
+                                    if let Err(e) = transport.send_to(&reply, remote_addr).await {
+                                        warn!("Failed to send key exchange: {}", e);
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                // Anyone can send a cleartext key exchange,
+                                // so a bad one is dropped, not fatal
+                                packets_rejected.fetch_add(1, Ordering::Relaxed);
+                                debug!("Key exchange from {} rejected: {}", remote_addr, e);
+                            }
+                        }
+                    }
+                    PacketType::PathChallenge => {
+                        // Answer to the address the peer knows us by; the
+                        // response shows the peer where we are now
+                        let Some(challenge) = PathValidationPayload::from_bytes(&packet.payload)
+                        else {
+                            continue;
+                        };
+                        let response = Packet::path_response(
+                            sequence.fetch_add(1, Ordering::Relaxed),
+                            &challenge,
+                        );
+                        let secure = key_exchange.lock().outbound();
+                        if let Ok(secure) = secure {
+                            if let Err(e) =
+                                send_packet(&transport, secure.as_deref(), &response, remote_addr)
+                                    .await
+                            {
+                                warn!("Failed to answer path challenge: {}", e);
+                            }
+                        }
+                    }
+                    PacketType::Control => {
+                        let (ack, messages) = control.lock().handle_packet(&packet);
+                        let secure = key_exchange.lock().outbound();
+                        if let (Some(ack), Ok(secure)) = (ack, secure) {
+                            if let Err(e) =
+                                send_packet(&transport, secure.as_deref(), &ack, remote_addr).await
+                            {
+                                warn!("Failed to acknowledge control message: {}", e);
+                            }
+                        }
+                        if let Some(ref callback) = control_callback {
+                            for message in messages {
+                                callback(message);
+                            }
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -669,40 +1872,374 @@ impl Connection {
     fn start_keepalive_loop(&mut self) {
         let transport = self.transport.clone();
         let state = self.state.clone();
-        let remote_addr = self.remote_addr;
-        let sequence = AtomicU32::new(0);
+        let last_error = self.last_error.clone();
+        let remote = self.remote_addr.clone();
+        let last_received = self.last_received.clone();
+        let reconnect_config = self.reconnect_config;
+        let sequence = self.sequence.clone();
         let rtt_measurement = self.rtt_measurement.clone();
+        let key_exchange = self.key_exchange.clone();
+        let codec = self.codec.clone();
+        let capabilities = self.capabilities.clone();
+        let quality = self.quality.clone();
+        let quality_callback = self.quality_callback.clone();
+        let sequence_tracker = self.sequence_tracker.clone();
+        let reception = self.reception.clone();
+        let receive_pipeline = self.receive_pipeline.clone();
 
         let handle = tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(1));
+            let mut reconnecting_since: Option<Instant> = None;
 
             loop {
                 interval.tick().await;
 
-                let current_state = ConnectionState::from_u8(state.load(Ordering::SeqCst));
+                let current_state = state.get();
                 if !current_state.can_transmit() {
                     break;
                 }
+                let remote_addr = *remote.read();
+
+                // Liveness: nothing heard from the peer for a while
+                let silence = last_received.lock().unwrap().elapsed();
+                if current_state == ConnectionState::Connected {
+                    reconnecting_since = None;
+                    if silence >= reconnect_config.liveness_timeout {
+                        warn!(
+                            "Nothing received from {} for {:?}, reconnecting",
+                            remote_addr, silence
+                        );
+                        reconnecting_since = Some(Instant::now());
+                        state.set(ConnectionState::Reconnecting);
+                    }
+                } else if reconnecting_since
+                    .get_or_insert_with(Instant::now)
+                    .elapsed()
+                    >= reconnect_config.reconnect_timeout
+                {
+                    warn!("Reconnecting to {} timed out", remote_addr);
+                    if let Ok(mut err) = last_error.lock() {
+                        *err = Some("Reconnect timed out".to_string());
+                    }
+                    state.set(ConnectionState::Failed);
+                    break;
+                }
 
                 // Send keep-alive
                 let packet = Packet::keep_alive(sequence.fetch_add(1, Ordering::Relaxed));
                 if let Err(e) = transport.send_to(&packet, remote_addr).await {
                     warn!("Failed to send keep-alive: {}", e);
                 }
+                if let Err(e) = transport.refresh_relay().await {
+                    warn!("Failed to refresh relay allocation: {}", e);
+                }
+
+                // (Re)send our versions and capabilities until the peer's
+                // arrive. The peer only accepts them in cleartext until our
+                // key establishes the session keys, so they go first, and
+                // sealed once the keys are established.
+                let hello = {
+                    let capabilities = capabilities.lock();
+                    capabilities.needs_hello().then(|| {
+                        capabilities.hello_packet(sequence.fetch_add(1, Ordering::Relaxed), false)
+                    })
+                };
+                if let Some(hello) = hello {
+                    let sealed = key_exchange.lock().outbound().ok().flatten();
+                    if let Err(e) =
+                        send_packet(&transport, sealed.as_deref(), &hello, remote_addr).await
+                    {
+                        warn!("Failed to send hello: {}", e);
+                    }
+                }
+
+                // (Re)send our public key until the peer's key arrives
+                let (hello, secure) = {
+                    let key_exchange = key_exchange.lock();
+                    let hello = key_exchange
+                        .needs_key_exchange()
+                        .then(|| key_exchange.key_exchange_packet(false));
+                    (hello, key_exchange.outbound())
+                };
+                if let Some(hello) = hello {
+                    if let Err(e) = transport.send_to(&hello, remote_addr).await {
+                        warn!("Failed to send key exchange: {}", e);
+                    }
+                }
+
+                // Media-plane messages wait until encryption allows them
+                let Ok(secure) = secure else {
+                    continue;
+                };
+
+                // (Re)send our codec offer until the peer's offer arrives
+                let offer = {
+                    let codec = codec.lock();
+                    codec.needs_offer().then(|| {
+                        codec.offer_packet(sequence.fetch_add(1, Ordering::Relaxed), false)
+                    })
+                };
+                if let Some(offer) = offer {
+                    if let Err(e) =
+                        send_packet(&transport, secure.as_deref(), &offer, remote_addr).await
+                    {
+                        warn!("Failed to send codec offer: {}", e);
+                    }
+                }
 
                 // Send latency ping for RTT measurement
                 let ping = rtt_measurement.write().create_ping();
                 let ping_packet =
                     Packet::latency_ping(sequence.fetch_add(1, Ordering::Relaxed), &ping);
-                if let Err(e) = transport.send_to(&ping_packet, remote_addr).await {
+                if let Err(e) =
+                    send_packet(&transport, secure.as_deref(), &ping_packet, remote_addr).await
+                {
                     warn!("Failed to send latency ping: {}", e);
                 }
                 trace!("Sent latency ping seq={}", ping.ping_sequence);
+
+                // Tell the peer how its audio arrives
+                let report = {
+                    let delay = receive_pipeline
+                        .as_ref()
+                        .map(|pipeline| pipeline.lock().stats().jitter_buffer_delay_ms);
+                    reception
+                        .lock()
+                        .poll(Instant::now(), &sequence_tracker.lock(), delay)
+                };
+                if let Some(report) = report {
+                    let report_packet =
+                        Packet::receiver_report(sequence.fetch_add(1, Ordering::Relaxed), &report);
+                    if let Err(e) =
+                        send_packet(&transport, secure.as_deref(), &report_packet, remote_addr)
+                            .await
+                    {
+                        warn!("Failed to send receiver report: {}", e);
+                    }
+                }
+
+                // Adapt outgoing audio to the last interval's conditions
+                let decision = quality.lock().as_mut().and_then(|controller| {
+                    let loss = sequence_tracker.lock().stats();
+                    let jitter_buffer = receive_pipeline
+                        .as_ref()
+                        .map(|pipeline| pipeline.lock().stats().jitter_buffer);
+                    let rtt_ms = rtt_measurement.read().rtt_ms;
+                    controller.poll(Instant::now(), &loss, jitter_buffer.as_ref(), rtt_ms)
+                });
+                if let Some(decision) = decision {
+                    debug!("Audio quality changed: {}", decision);
+                    codec.lock().apply_quality(&decision.settings);
+                    if let Some(callback) = &quality_callback {
+                        callback(decision);
+                    }
+                }
             }
         });
 
         self.keepalive_handle = Some(handle);
     }
+
+    /// Keep the ICE agent answering checks and refreshing consent
+    ///
+    /// Lost consent starts reconnecting, like silence does. A pair
+    /// re-nominated by the peer is not followed; the connection keeps its
+    /// remote address until `restart_ice`.
+    fn start_ice_loop(
+        &mut self,
+        mut agent: IceAgent,
+        mut stun_rx: tokio::sync::mpsc::UnboundedReceiver<StunDatagram>,
+    ) {
+        let transport = self.transport.clone();
+        let state = self.state.clone();
+        let check_interval = self.ice_config.check_interval;
+
+        let handle = tokio::spawn(async move {
+            let mut tick = interval(check_interval);
+            let mut consent = true;
+            loop {
+                let transmits = tokio::select! {
+                    datagram = stun_rx.recv() => match datagram {
+                        Some(datagram) => ice::handle_datagram(&mut agent, &datagram, Instant::now()),
+                        None => break,
+                    },
+                    _ = tick.tick() => agent.poll(Instant::now()),
+                };
+                ice::send_transmits(&transport, transmits).await;
+
+                let has_consent = agent.state() != IceState::Disconnected;
+                if consent && !has_consent {
+                    warn!("ICE consent expired");
+                    if state.get() == ConnectionState::Connected {
+                        state.set(ConnectionState::Reconnecting);
+                    }
+                }
+                consent = has_consent;
+            }
+        });
+
+        self.ice_handle = Some(handle);
+    }
+
+    /// Start the playout clock that pulls frames from the receive pipeline
+    ///
+    /// Does nothing if the pipeline is disabled or in passthrough mode.
+    fn start_playout_loop(&mut self) {
+        let pipeline = match self.receive_pipeline.clone() {
+            Some(pipeline) => pipeline,
+            None => return,
+        };
+        let (frame_duration, samples_per_second) = {
+            let guard = pipeline.lock();
+            let config = guard.config();
+            if config.is_passthrough() {
+                return;
+            }
+            let codec = &config.codec;
+            (
+                config.frame_duration(),
+                (codec.sample_rate as u64 * codec.channels as u64).max(1),
+            )
+        };
+        let state = self.state.clone();
+        let callback = self.decoded_audio_callback.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut interval = interval(frame_duration);
+            // Audio handed out beyond the ticks so far; a peer sending larger
+            // frames than ours covers several ticks with each
+            let mut ahead = Duration::ZERO;
+
+            loop {
+                interval.tick().await;
+
+                let current_state = state.get();
+                if !current_state.can_transmit() {
+                    break;
+                }
+
+                if ahead >= frame_duration {
+                    ahead -= frame_duration;
+                    continue;
+                }
+                let frame = pipeline.lock().pop_frame();
+                if let Some(frame) = &frame {
+                    let nanos = frame.samples.len() as u64 * 1_000_000_000 / samples_per_second;
+                    ahead = (ahead + Duration::from_nanos(nanos)).saturating_sub(frame_duration);
+                }
+                if let (Some(frame), Some(callback)) = (frame, &callback) {
+                    callback(&frame.samples, frame.timestamp);
+                }
+            }
+        });
+
+        self.playout_handle = Some(handle);
+    }
+
+    /// Retransmit control messages until the peer acknowledges them
+    fn start_control_loop(&mut self) {
+        let transport = self.transport.clone();
+        let state = self.state.clone();
+        let remote = self.remote_addr.clone();
+        let key_exchange = self.key_exchange.clone();
+        let control = self.control.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut interval = interval(CONTROL_POLL_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                if !state.get().can_transmit() {
+                    break;
+                }
+
+                let packets = control.lock().poll(Instant::now());
+                if packets.is_empty() {
+                    continue;
+                }
+                let Ok(secure) = key_exchange.lock().outbound() else {
+                    continue;
+                };
+                let remote_addr = *remote.read();
+                for packet in packets {
+                    trace!("Retransmitting control message {}", packet.sequence);
+                    if let Err(e) =
+                        send_packet(&transport, secure.as_deref(), &packet, remote_addr).await
+                    {
+                        warn!("Failed to retransmit control message: {}", e);
+                    }
+                }
+            }
+        });
+
+        self.control_handle = Some(handle);
+    }
+}
+
+/// Playout path for received and recovered audio in the receive loop
+struct AudioPlayout {
+    receive_pipeline: Option<Arc<Mutex<ReceivePipeline>>>,
+    audio_callback: Option<Arc<AudioCallback>>,
+    decoded_audio_callback: Option<Arc<DecodedAudioCallback>>,
+    /// Decoders for compressed payloads handed to the raw audio callback
+    decoders: AudioDecoders,
+}
+
+impl AudioPlayout {
+    /// Keep redundant copies of previous frames for lost slots
+    ///
+    /// Only the receive pipeline uses them; the raw audio callback gets the
+    /// packets themselves and FEC recoveries.
+    fn deliver_redundant(&mut self, frames: Vec<RecoveredAudio>) {
+        let Some(pipeline) = &self.receive_pipeline else {
+            return;
+        };
+        let mut pipeline = pipeline.lock();
+        for frame in frames {
+            pipeline.insert_redundant(frame.sequence, frame.timestamp, frame.codec, frame.payload);
+        }
+    }
+
+    /// Hand a received or recovered audio packet to the playout path
+    ///
+    /// Recovered packets that missed their playout deadline are dropped and
+    /// `false` is returned.
+    fn deliver(&mut self, audio: RecoveredAudio, recovered: bool) -> bool {
+        let Some(pipeline) = &self.receive_pipeline else {
+            let Some(callback) = &self.audio_callback else {
+                return true;
+            };
+            if audio.codec == CodecType::Pcm {
+                callback(&audio.payload, audio.timestamp);
+            } else {
+                // The raw callback always receives PCM
+                match self.decoders.decode(audio.codec, &audio.payload) {
+                    Ok(samples) => callback(&encode_pcm(&samples), audio.timestamp),
+                    Err(e) => warn!("Failed to decode {:?} audio: {}", audio.codec, e),
+                }
+            }
+            return true;
+        };
+
+        let mut pipeline = pipeline.lock();
+        if recovered && pipeline.is_late(audio.sequence) {
+            return false;
+        }
+        pipeline.insert_with_codec(audio.sequence, audio.timestamp, audio.codec, audio.payload);
+
+        // Passthrough mode delivers frames on arrival
+        if pipeline.config().is_passthrough() {
+            let frames = pipeline.drain_ready();
+            drop(pipeline);
+            if let Some(callback) = &self.decoded_audio_callback {
+                for frame in &frames {
+                    callback(&frame.samples, frame.timestamp);
+                }
+            }
+        }
+        true
+    }
 }
 
 impl Drop for Connection {
@@ -791,4 +2328,844 @@ mod tests {
 
         assert!(conn1.is_connected());
     }
+
+    #[tokio::test]
+    async fn test_receive_pipeline_delivers_decoded_audio() {
+        use crate::audio::CodecConfig;
+        use crate::network::JitterBufferMode;
+
+        let mut sender = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+
+        let codec = CodecConfig {
+            frame_size: 4,
+            ..Default::default()
+        };
+        receiver
+            .set_receive_pipeline(ReceivePipelineConfig::new(
+                JitterBufferMode::Passthrough,
+                codec,
+            ))
+            .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_decoded_audio_callback(move |samples, timestamp| {
+            let _ = tx.send((samples.to_vec(), timestamp));
+        });
+
+        receiver.connect(sender.local_addr()).await.unwrap();
+        sender.connect(receiver.local_addr()).await.unwrap();
+
+        sender.send_audio(&[0.25; 4], 0).await.unwrap();
+
+        let (samples, timestamp) = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("Timed out waiting for decoded audio")
+            .unwrap();
+        assert_eq!(timestamp, 0);
+        assert_eq!(samples, vec![0.25; 4]);
+
+        let stats = receiver.receive_pipeline_stats().unwrap();
+        assert_eq!(stats.frames_decoded, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fec_packets_sent_per_group() {
+        let mut sender = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        sender.set_fec_config(FecConfig::with_group_size(2));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_audio_callback(move |_, timestamp| {
+            let _ = tx.send(timestamp);
+        });
+
+        receiver.connect(sender.local_addr()).await.unwrap();
+        sender.connect(receiver.local_addr()).await.unwrap();
+
+        for i in 0..4 {
+            sender.send_audio(&[0.1; 4], i * 4).await.unwrap();
+        }
+        for _ in 0..4 {
+            tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for audio")
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(sender.stats().fec_packets_sent, 2);
+        assert_eq!(receiver.stats().fec_packets_received, 2);
+        assert_eq!(receiver.stats().packets_recovered, 0);
+
+        // Disabled FEC sends audio only
+        sender.set_fec_config(FecConfig::disabled());
+        sender.send_audio(&[0.1; 4], 16).await.unwrap();
+        sender.send_audio(&[0.1; 4], 20).await.unwrap();
+        assert_eq!(sender.stats().fec_packets_sent, 2);
+    }
+
+    #[tokio::test]
+    async fn test_reed_solomon_fec_after_hello() {
+        let mut sender = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        sender.set_fec_config(FecConfig::reed_solomon(2, 3));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_audio_callback(move |_, timestamp| {
+            let _ = tx.send(timestamp);
+        });
+
+        receiver.connect(sender.local_addr()).await.unwrap();
+        sender.connect(receiver.local_addr()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while sender.capabilities().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Hellos were not exchanged");
+        assert!(sender.capabilities().unwrap().reed_solomon);
+
+        for i in 0..4 {
+            sender.send_audio(&[0.1; 4], i * 4).await.unwrap();
+        }
+        for _ in 0..4 {
+            tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for audio")
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Three FEC packets per group of two
+        assert_eq!(sender.stats().fec_packets_sent, 6);
+        assert_eq!(receiver.stats().fec_packets_received, 6);
+        assert_eq!(receiver.stats().packets_recovered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_fec_recovers_lost_audio() {
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        let sender = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_audio_callback(move |data, timestamp| {
+            let _ = tx.send((data.to_vec(), timestamp));
+        });
+        receiver.connect(sender.local_addr()).await.unwrap();
+
+        let mut encoder = FecStreamEncoder::new(3);
+        let payloads = [vec![1u8; 8], vec![2u8; 8], vec![3u8; 8]];
+        let mut fec = None;
+        for (i, payload) in payloads.iter().enumerate() {
+            let timestamp = i as u32 * 2;
+            fec = encoder.add_packet(i as u32, timestamp, CodecType::Pcm, payload);
+            // Packet 1 is lost on the wire
+            if i != 1 {
+                let packet = Packet::audio(i as u32, timestamp, payload.clone());
+                sender
+                    .send_to(&packet, receiver.local_addr())
+                    .await
+                    .unwrap();
+            }
+        }
+        let fec = fec.unwrap();
+        let fec_packet = Packet::fec(encoder.next_sequence(), fec.to_bytes());
+        sender
+            .send_to(&fec_packet, receiver.local_addr())
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let (data, timestamp) = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for audio")
+                .unwrap();
+            received.push((timestamp, data));
+        }
+        received.sort();
+        assert_eq!(received[1], (2, payloads[1].clone()));
+        assert_eq!(receiver.stats().packets_recovered, 1);
+        assert_eq!(receiver.stats().fec_packets_received, 1);
+        // Loss is counted on the wire, before FEC recovery
+        assert_eq!(receiver.stats().packets_lost, 1);
+    }
+
+    #[tokio::test]
+    async fn test_redundancy_fills_lost_audio() {
+        use crate::network::JitterBufferMode;
+
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        let sender = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let codec = CodecConfig {
+            frame_size: 4,
+            ..Default::default()
+        };
+        receiver
+            .set_receive_pipeline(ReceivePipelineConfig::new(
+                JitterBufferMode::Passthrough,
+                codec,
+            ))
+            .unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_decoded_audio_callback(move |samples, timestamp| {
+            let _ = tx.send((samples.to_vec(), timestamp));
+        });
+        receiver.connect(sender.local_addr()).await.unwrap();
+
+        let mut redundancy = RedundancyEncoder::new(RedundancyConfig::with_depth(1), 48000);
+        for i in 0..3u32 {
+            let samples = [0.1 * (i + 1) as f32; 4];
+            let mut packet = Packet::audio(i, i * 4, encode_pcm(&samples));
+            redundancy.protect(&mut packet, &samples, 1);
+            // Packet 1 is lost on the wire
+            if i != 1 {
+                sender
+                    .send_to(&packet, receiver.local_addr())
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let mut frames = Vec::new();
+        for _ in 0..3 {
+            let frame = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for decoded audio")
+                .unwrap();
+            frames.push(frame);
+        }
+        // The lost frame plays from the copy in packet 2, before packet 2
+        assert_eq!(frames[1], (vec![0.2; 4], 4));
+        assert_eq!(frames[2], (vec![0.3; 4], 8));
+
+        let stats = receiver.receive_pipeline_stats().unwrap();
+        assert_eq!(stats.frames_from_redundancy, 1);
+        assert_eq!(stats.frames_concealed, 0);
+        assert_eq!(receiver.stats().packets_lost, 1);
+    }
+
+    #[tokio::test]
+    async fn test_redundancy_sent_after_hello() {
+        use crate::network::JitterBufferMode;
+        use crate::protocol::{RedundancyPayload, HEADER_SIZE};
+
+        let mut sender = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        sender.set_fec_config(FecConfig::disabled());
+        sender.set_redundancy_config(RedundancyConfig::reduced(2));
+        let codec = CodecConfig {
+            frame_size: 4,
+            ..Default::default()
+        };
+        receiver
+            .set_receive_pipeline(ReceivePipelineConfig::new(
+                JitterBufferMode::Passthrough,
+                codec,
+            ))
+            .unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_decoded_audio_callback(move |samples, _| {
+            let _ = tx.send(samples.to_vec());
+        });
+
+        receiver.connect(sender.local_addr()).await.unwrap();
+        sender.connect(receiver.local_addr()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while sender.capabilities().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Hellos were not exchanged");
+        assert!(sender.capabilities().unwrap().redundancy);
+
+        for i in 0..3 {
+            sender.send_audio(&[0.5; 4], i * 4).await.unwrap();
+        }
+        for _ in 0..3 {
+            let samples = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for decoded audio")
+                .unwrap();
+            // Only the packet's own frame is played
+            assert_eq!(samples, vec![0.5; 4]);
+        }
+
+        // Each packet carries 16-bit copies of up to two frames before it
+        let copy = RedundancyPayload::FRAME_HEADER_SIZE + 4 * 2;
+        let frame = HEADER_SIZE + 1 + 4 * 4;
+        assert_eq!(sender.stats().bytes_sent, (3 * frame + 3 * copy) as u64);
+        assert_eq!(
+            receiver
+                .receive_pipeline_stats()
+                .unwrap()
+                .frames_from_redundancy,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_packet_loss_stats() {
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        let sender = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        receiver.connect(sender.local_addr()).await.unwrap();
+
+        // 1 arrives late, 1 is duplicated, 3 and 4 are lost
+        for sequence in [0u32, 2, 1, 1, 5] {
+            let packet = Packet::audio(sequence, sequence * 2, vec![0u8; 8]);
+            sender
+                .send_to(&packet, receiver.local_addr())
+                .await
+                .unwrap();
+        }
+
+        let stats = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let stats = receiver.stats();
+                if stats.packets_received == 5 {
+                    return stats;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Timed out waiting for audio");
+
+        assert_eq!(stats.packets_lost, 2);
+        assert_eq!(stats.packets_reordered, 1);
+        assert_eq!(stats.packets_duplicated, 1);
+        assert_eq!(stats.loss_bursts, 2);
+        assert_eq!(stats.max_burst_length, 2);
+        assert!(stats.packet_loss_rate > 0.0);
+        assert!(stats.recent_packet_loss_rate > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_codec_negotiation() {
+        let mut sender = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        sender.set_codec_config(CodecConfig {
+            codec_type: CodecType::Opus,
+            ..Default::default()
+        });
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_audio_callback(move |data, _| {
+            let _ = tx.send(data.to_vec());
+        });
+
+        receiver.connect(sender.local_addr()).await.unwrap();
+        sender.connect(receiver.local_addr()).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while sender.codec.lock().needs_offer() || receiver.codec.lock().needs_offer() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Codec offers were not exchanged");
+
+        // Opus only if this build can encode and decode it
+        let expected = if CodecType::Opus.is_available() {
+            CodecType::Opus
+        } else {
+            CodecType::Pcm
+        };
+        assert_eq!(sender.send_codec(), expected);
+        assert_eq!(receiver.send_codec(), expected);
+
+        // PCM is always delivered unchanged to the raw callback
+        if expected == CodecType::Pcm {
+            sender.send_audio(&[0.5; 4], 0).await.unwrap();
+            let data = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for audio")
+                .unwrap();
+            assert_eq!(data, encode_pcm(&[0.5; 4]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_audio_roundtrip() {
+        let mut sender = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        sender.set_encryption_mode(EncryptionMode::Required);
+        receiver.set_encryption_mode(EncryptionMode::Required);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        receiver.set_audio_callback(move |data, timestamp| {
+            let _ = tx.send((data.to_vec(), timestamp));
+        });
+
+        // Media cannot be sent before the key exchange
+        assert!(sender.send_audio(&[0.5; 4], 0).await.is_err());
+
+        receiver.connect(sender.local_addr()).await.unwrap();
+        sender.connect(receiver.local_addr()).await.unwrap();
+
+        sender
+            .wait_for_encryption(Duration::from_secs(3))
+            .await
+            .unwrap();
+        receiver
+            .wait_for_encryption(Duration::from_secs(3))
+            .await
+            .unwrap();
+
+        sender.send_audio(&[0.5; 4], 480).await.unwrap();
+
+        let (data, timestamp) = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("Timed out waiting for audio")
+            .unwrap();
+        assert_eq!(timestamp, 480);
+        let expected: Vec<u8> = [0.5f32; 4].iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(data, expected);
+        assert!(receiver.stats().encrypted);
+    }
+
+    #[tokio::test]
+    async fn test_capability_handshake() {
+        let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+        a.connect(b.local_addr()).await.unwrap();
+        b.connect(a.local_addr()).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while a.capabilities().is_none() || b.capabilities().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Hellos were not exchanged");
+
+        let negotiated = a.capabilities().unwrap();
+        assert_eq!(negotiated.version, crate::protocol::PROTOCOL_VERSION);
+        assert!(negotiated.codecs.contains(&CodecType::Pcm));
+        assert!(negotiated.fec);
+        // Connections only play the main stream
+        assert_eq!(negotiated.max_streams, 0);
+        assert_eq!(b.stats().capabilities, Some(negotiated));
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_peer_fails() {
+        let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+        a.set_encryption_mode(EncryptionMode::Required);
+        b.set_encryption_mode(EncryptionMode::Disabled);
+        a.connect(b.local_addr()).await.unwrap();
+        b.connect(a.local_addr()).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while a.state() != ConnectionState::Failed || b.state() != ConnectionState::Failed {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Incompatible peers kept their connection");
+
+        assert!(a.last_error().unwrap().contains("encryption is required"));
+        assert!(b.last_error().unwrap().contains("peer requires encryption"));
+        assert!(a.capabilities().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_required_encryption_rejects_cleartext() {
+        let mut sender = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        sender.set_encryption_mode(EncryptionMode::Disabled);
+        receiver.set_encryption_mode(EncryptionMode::Required);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+        receiver.set_audio_callback(move |data, _| {
+            let _ = tx.send(data.to_vec());
+        });
+
+        receiver.connect(sender.local_addr()).await.unwrap();
+        sender.connect(receiver.local_addr()).await.unwrap();
+
+        sender.send_audio(&[0.5; 4], 0).await.unwrap();
+
+        let result = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await;
+        assert!(result.is_err(), "Cleartext audio must not be delivered");
+        assert!(receiver.stats().packets_rejected >= 1);
+        assert!(!receiver.is_encrypted());
+    }
+
+    #[tokio::test]
+    async fn test_packets_from_other_addresses_dropped() {
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        let peer = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let spoofer = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+        receiver.set_audio_callback(move |data, _| {
+            let _ = tx.send(data.to_vec());
+        });
+        receiver.connect(peer.local_addr()).await.unwrap();
+
+        for sequence in 0..3 {
+            let packet = Packet::audio(sequence, 0, vec![sequence as u8]);
+            spoofer
+                .send_to(&packet, receiver.local_addr())
+                .await
+                .unwrap();
+        }
+        peer.send_to(&Packet::audio(3, 0, vec![3]), receiver.local_addr())
+            .await
+            .unwrap();
+
+        let data = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("Timed out waiting for audio")
+            .unwrap();
+        assert_eq!(data, vec![3]);
+        let stats = receiver.stats();
+        assert_eq!(stats.packets_rejected_source, 3);
+        assert_eq!(stats.packets_received, 1);
+        assert_eq!(stats.address_migrations, 0);
+        assert_eq!(receiver.remote_addr(), peer.local_addr());
+    }
+
+    #[tokio::test]
+    async fn test_fallback_candidate_follows_peer_answer() {
+        let mut receiver = Connection::new("127.0.0.1:0").await.unwrap();
+        receiver.set_encryption_mode(EncryptionMode::Disabled);
+        // Neither candidate answers the probes
+        let silent = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let peer = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        receiver
+            .connect_with_candidates(&[silent.local_addr(), peer.local_addr()])
+            .await
+            .unwrap();
+        assert_eq!(receiver.remote_addr(), silent.local_addr());
+
+        // The peer turns up at its other candidate
+        peer.send_to(&Packet::audio(0, 0, vec![0]), receiver.local_addr())
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while receiver.stats().packets_received == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Timed out waiting for the peer");
+        assert_eq!(receiver.remote_addr(), peer.local_addr());
+
+        // From then on the address is held like any other
+        silent
+            .send_to(&Packet::audio(1, 0, vec![1]), receiver.local_addr())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let stats = receiver.stats();
+        assert_eq!(stats.packets_rejected_source, 1);
+        assert_eq!(stats.packets_received, 1);
+        assert_eq!(receiver.remote_addr(), peer.local_addr());
+    }
+
+    /// NAT in front of `b` whose mapping changes when the returned flag is
+    /// set
+    ///
+    /// Returns the address `b` sends to and the address `a` first sees `b` at.
+    async fn rebinding_nat(
+        a: SocketAddr,
+        b: SocketAddr,
+    ) -> (SocketAddr, SocketAddr, Arc<std::sync::atomic::AtomicBool>) {
+        use tokio::net::UdpSocket;
+
+        let inside = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut mappings = Vec::new();
+        for _ in 0..2 {
+            mappings.push(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()));
+        }
+        let rebound = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let inside_addr = inside.local_addr().unwrap();
+        let first = mappings[0].local_addr().unwrap();
+
+        // b to a through the current mapping
+        let (socket, outside, switch) = (inside.clone(), mappings.clone(), rebound.clone());
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            while let Ok((len, _)) = socket.recv_from(&mut buf).await {
+                let mapping = &outside[switch.load(Ordering::Relaxed) as usize];
+                let _ = mapping.send_to(&buf[..len], a).await;
+            }
+        });
+        // a to b through either mapping
+        for mapping in mappings {
+            let inside = inside.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 2048];
+                while let Ok((len, _)) = mapping.recv_from(&mut buf).await {
+                    let _ = inside.send_to(&buf[..len], b).await;
+                }
+            });
+        }
+        (inside_addr, first, rebound)
+    }
+
+    #[tokio::test]
+    async fn test_peer_migrates_after_path_challenge() {
+        let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+        let (nat, first, rebound) = rebinding_nat(a.local_addr(), b.local_addr()).await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+        a.set_audio_callback(move |data, _| {
+            let _ = tx.send(data.to_vec());
+        });
+        a.connect(first).await.unwrap();
+        b.connect(nat).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !a.is_encrypted() || !b.is_encrypted() || a.capabilities().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Handshake did not complete");
+
+        // b's sealed audio arrives from the new mapping and earns it a
+        // challenge, which b answers from there
+        rebound.store(true, Ordering::Relaxed);
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while a.remote_addr() == first {
+                b.send_audio(&[0.5; 4], 0).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("Peer did not move to its new address");
+        assert_eq!(a.stats().address_migrations, 1);
+        assert!(a.stats().packets_rejected_source >= 1);
+
+        while rx.try_recv().is_ok() {}
+        b.send_audio(&[0.25; 4], 0).await.unwrap();
+        let data = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("Timed out waiting for audio")
+            .unwrap();
+        assert_eq!(data, encode_pcm(&[0.25; 4]));
+    }
+
+    #[tokio::test]
+    async fn test_cleartext_hello_after_handshake_ignored() {
+        let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+        let peer = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+        let mut peer_keys = KeyExchangeState::new(EncryptionMode::Required);
+        let peer_capabilities = CapabilityNegotiation::new(
+            connection_capabilities(EncryptionMode::Required),
+            CodecConfig::default().frame_size,
+        );
+        a.connect(peer.local_addr()).await.unwrap();
+
+        // The peer's hello and key arrive before the keys are established
+        for packet in [
+            peer_capabilities.hello_packet(0, true),
+            peer_keys.key_exchange_packet(false),
+        ] {
+            peer.send_to(&packet, a.local_addr()).await.unwrap();
+        }
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !peer_keys.is_established() {
+                let (packet, _) = peer.recv_from().await.unwrap();
+                if let Some(payload) = KeyExchangePayload::from_bytes(&packet.payload)
+                    .filter(|_| packet.packet_type == PacketType::KeyExchange)
+                {
+                    peer_keys.handle_key_exchange(&peer, &payload).unwrap();
+                }
+            }
+        })
+        .await
+        .expect("Key exchange did not complete");
+        assert!(a.is_encrypted());
+        let negotiated = a.capabilities().expect("Hello was not received");
+
+        // A forged hello from the peer's address asking for a version `a`
+        // does not speak would fail the connection
+        let forged = Packet::hello(
+            1,
+            &HelloPayload {
+                min_version: u8::MAX,
+                max_version: u8::MAX,
+                capabilities: connection_capabilities(EncryptionMode::Disabled),
+                ack: true,
+            },
+        );
+        let rejected = a.stats().packets_rejected;
+        peer.send_to(&forged, a.local_addr()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert_eq!(a.stats().packets_rejected, rejected + 1);
+        assert_eq!(a.state(), ConnectionState::Connected);
+        assert_eq!(a.capabilities(), Some(negotiated));
+        assert!(a.last_error().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_key_exchange_does_not_break_connection() {
+        let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+        let peer = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+        let mut peer_keys = KeyExchangeState::new(EncryptionMode::Preferred);
+        a.connect(peer.local_addr()).await.unwrap();
+
+        peer.send_to(&peer_keys.key_exchange_packet(false), a.local_addr())
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !peer_keys.is_established() {
+                let (packet, _) = peer.recv_from().await.unwrap();
+                if let Some(payload) = KeyExchangePayload::from_bytes(&packet.payload)
+                    .filter(|_| packet.packet_type == PacketType::KeyExchange)
+                {
+                    peer_keys.handle_key_exchange(&peer, &payload).unwrap();
+                }
+            }
+        })
+        .await
+        .expect("Key exchange did not complete");
+        assert!(a.is_encrypted());
+
+        // Anyone can send a key exchange from the peer's address
+        let other = KeyExchangeState::new(EncryptionMode::Preferred);
+        let rejected = a.stats().packets_rejected;
+        peer.send_to(&other.key_exchange_packet(false), a.local_addr())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(a.stats().packets_rejected, rejected + 1);
+        assert_eq!(a.state(), ConnectionState::Connected);
+        assert!(a.last_error().is_none());
+
+        // The peer's sealed audio still gets through
+        let received = a.stats().packets_received;
+        let sealed = peer_keys
+            .outbound()
+            .unwrap()
+            .unwrap()
+            .encrypt(&Packet::audio(0, 0, vec![0; 8]))
+            .unwrap();
+        peer.send_to(&sealed, a.local_addr()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while a.stats().packets_received == received {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Sealed audio was not accepted");
+        assert_eq!(a.stats().packets_rejected, rejected + 1);
+    }
+
+    #[tokio::test]
+    async fn test_sealed_packets_never_share_a_nonce() {
+        let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+        let peer = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+        let mut peer_keys = KeyExchangeState::new(EncryptionMode::Preferred);
+        a.connect(peer.local_addr()).await.unwrap();
+
+        peer.send_to(&peer_keys.key_exchange_packet(false), a.local_addr())
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !peer_keys.is_established() {
+                let (packet, _) = peer.recv_from().await.unwrap();
+                if let Some(payload) = KeyExchangePayload::from_bytes(&packet.payload)
+                    .filter(|_| packet.packet_type == PacketType::KeyExchange)
+                {
+                    peer_keys.handle_key_exchange(&peer, &payload).unwrap();
+                }
+            }
+        })
+        .await
+        .expect("Key exchange did not complete");
+        let secure = peer_keys.outbound().unwrap().unwrap();
+
+        // Make the receive loop answer while the keep-alive loop sends its own
+        let mut rtt = RttMeasurement::default();
+        let mut requests = vec![
+            CapabilityNegotiation::new(
+                connection_capabilities(EncryptionMode::Preferred),
+                CodecConfig::default().frame_size,
+            )
+            .hello_packet(0, false),
+            CodecNegotiation::new(CodecConfig::default()).offer_packet(1, false),
+        ];
+        requests.extend((2..8).map(|seq| Packet::latency_ping(seq, &rtt.create_ping())));
+        for packet in &requests {
+            peer.send_to(&secure.encrypt(packet).unwrap(), a.local_addr())
+                .await
+                .unwrap();
+        }
+
+        // The nonce is derived from the header's type, stream and sequence
+        let mut nonces = std::collections::HashSet::new();
+        let mut types = Vec::new();
+        let _ = tokio::time::timeout(Duration::from_millis(2500), async {
+            loop {
+                let (packet, _) = peer.recv_from().await.unwrap();
+                if !packet.flags.encrypted {
+                    continue;
+                }
+                let nonce = (
+                    packet.packet_type as u8,
+                    packet.stream_id(),
+                    packet.sequence,
+                );
+                assert!(nonces.insert(nonce), "Nonce reused by {:?}", nonce);
+                types.push(packet.packet_type);
+            }
+        })
+        .await;
+        assert!(types.contains(&PacketType::LatencyPong));
+        assert!(types.contains(&PacketType::LatencyPing));
+    }
+
+    #[tokio::test]
+    async fn test_third_party_cannot_take_over_unencrypted_connection() {
+        let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+        let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+        a.set_encryption_mode(EncryptionMode::Disabled);
+        b.set_encryption_mode(EncryptionMode::Disabled);
+        a.connect(b.local_addr()).await.unwrap();
+        b.connect(a.local_addr()).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while a.capabilities().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Hellos were not exchanged");
+        assert!(a.capabilities().unwrap().path_validation);
+
+        // The attacker claims to be the peer from its own address
+        let attacker = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let hello = CapabilityNegotiation::new(
+            connection_capabilities(EncryptionMode::Disabled),
+            CodecConfig::default().frame_size,
+        )
+        .hello_packet(0, false);
+        let packets = [
+            hello,
+            Packet::audio(0, 0, vec![0; 8]),
+            Packet::path_response(1, &PathValidationPayload { cookie: 0 }),
+        ];
+        for packet in &packets {
+            attacker.send_to(packet, a.local_addr()).await.unwrap();
+        }
+
+        let challenge =
+            tokio::time::timeout(Duration::from_millis(500), attacker.recv_from()).await;
+        assert!(challenge.is_err(), "The attacker must not be challenged");
+        let stats = a.stats();
+        assert_eq!(stats.packets_rejected_source, packets.len() as u64);
+        assert_eq!(stats.address_migrations, 0);
+        assert_eq!(a.remote_addr(), b.local_addr());
+    }
 }
@@ -0,0 +1,454 @@
+//! Reliable, ordered control channel over `PacketType::Control`
+//!
+//! Control messages (mute state, stream configuration, ...) share the media
+//! socket with audio instead of going through the signaling server. Each
+//! message gets a sequence number and is retransmitted with exponential
+//! backoff until the peer acknowledges it. The receiver acknowledges every
+//! message, drops duplicates and delivers messages in sequence order, holding
+//! back those that arrive early.
+//!
+//! Message sequences only live in the payload. The packet header carries a
+//! separate transmission counter, new for every packet including
+//! retransmissions and acks, because the encryption nonce is derived from
+//! the header sequence: a retransmission with a newer floor must not be
+//! sealed under the nonce of the first transmission.
+//!
+//! A message that is never acknowledged is abandoned after `max_attempts`.
+//! Every packet carries the sender's retransmit floor (the oldest sequence it
+//! still retransmits), so the receiver skips abandoned messages instead of
+//! waiting for them forever. The first sequence is random: a receiver syncs
+//! to the floor of the first message it sees and resyncs when the floor jumps
+//! outside its window (the peer restarted its channel).
+//!
+//! The channel does no I/O: feed it received control packets and poll it for
+//! retransmissions.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use crate::protocol::{ControlMessage, ControlPayload, Packet};
+
+/// Messages the receiver holds back for in-order delivery
+pub const CONTROL_RECEIVE_WINDOW: u32 = 256;
+
+/// Retransmission policy of the control channel
+#[derive(Debug, Clone)]
+pub struct ControlConfig {
+    /// Retransmission timeout after the first transmission (doubles with
+    /// every retransmission)
+    pub retransmit_timeout: Duration,
+    /// Upper bound of the retransmission timeout
+    pub max_retransmit_timeout: Duration,
+    /// Transmissions of a message before it is abandoned
+    pub max_attempts: u32,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            retransmit_timeout: Duration::from_millis(200),
+            max_retransmit_timeout: Duration::from_secs(2),
+            max_attempts: 10,
+        }
+    }
+}
+
+/// Control channel statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControlStats {
+    /// Messages queued for sending
+    pub messages_sent: u64,
+    /// Messages delivered to us in order
+    pub messages_delivered: u64,
+    /// Retransmitted messages
+    pub retransmissions: u64,
+    /// Messages given up on without an acknowledgement
+    pub messages_abandoned: u64,
+    /// Messages waiting for an acknowledgement
+    pub in_flight: usize,
+}
+
+/// A sent message waiting for its acknowledgement
+struct Pending {
+    sequence: u32,
+    message: ControlMessage,
+    attempts: u32,
+    next_send: Instant,
+}
+
+/// Sender and receiver state of one peer's control channel
+pub(crate) struct ControlChannel {
+    config: ControlConfig,
+    next_sequence: u32,
+    /// Header sequence of the next packet sent
+    next_transmission: u32,
+    /// Unacknowledged messages in sequence order
+    pending: VecDeque<Pending>,
+    /// Next sequence to deliver (`None` until the first message)
+    expected: Option<u32>,
+    /// Messages received ahead of `expected` (`None` for unknown types)
+    early: HashMap<u32, Option<ControlMessage>>,
+    stats: ControlStats,
+}
+
+impl ControlChannel {
+    pub fn new(config: ControlConfig) -> Self {
+        Self {
+            config,
+            next_sequence: rand::random(),
+            next_transmission: 0,
+            pending: VecDeque::new(),
+            expected: None,
+            early: HashMap::new(),
+            stats: ControlStats::default(),
+        }
+    }
+
+    /// Queue a message and return its first transmission
+    pub fn send(&mut self, message: ControlMessage, now: Instant) -> Packet {
+        let sequence = self.next_sequence;
+        self.next_sequence = sequence.wrapping_add(1);
+        self.stats.messages_sent += 1;
+
+        let payload = ControlPayload::Message {
+            sequence,
+            message: Some(message.clone()),
+        };
+        self.pending.push_back(Pending {
+            sequence,
+            message,
+            attempts: 1,
+            next_send: now + self.retransmit_timeout(1),
+        });
+        self.transmit(&payload)
+    }
+
+    /// Retransmit messages whose timer expired
+    ///
+    /// Messages that reached `max_attempts` are abandoned instead.
+    pub fn poll(&mut self, now: Instant) -> Vec<Packet> {
+        let max_attempts = self.config.max_attempts;
+        let before = self.pending.len();
+        self.pending.retain(|p| {
+            let abandon = p.next_send <= now && p.attempts >= max_attempts;
+            if abandon {
+                warn!(
+                    "Control message {} abandoned after {} attempts",
+                    p.sequence, p.attempts
+                );
+            }
+            !abandon
+        });
+        self.stats.messages_abandoned += (before - self.pending.len()) as u64;
+
+        let mut packets = Vec::new();
+        for i in 0..self.pending.len() {
+            if self.pending[i].next_send > now {
+                continue;
+            }
+            let attempts = self.pending[i].attempts + 1;
+            let timeout = self.retransmit_timeout(attempts);
+            let pending = &mut self.pending[i];
+            pending.attempts = attempts;
+            pending.next_send = now + timeout;
+            let payload = ControlPayload::Message {
+                sequence: pending.sequence,
+                message: Some(pending.message.clone()),
+            };
+            packets.push(self.transmit(&payload));
+        }
+        self.stats.retransmissions += packets.len() as u64;
+        packets
+    }
+
+    /// Handle a received control packet
+    ///
+    /// Returns the acknowledgement to send back (for messages) and the
+    /// messages that are now deliverable in order.
+    pub fn handle_packet(&mut self, packet: &Packet) -> (Option<Packet>, Vec<ControlMessage>) {
+        match ControlPayload::from_bytes(&packet.payload) {
+            Some(ControlPayload::Ack { sequence }) => {
+                self.pending.retain(|p| p.sequence != sequence);
+                (None, Vec::new())
+            }
+            Some(ControlPayload::Message { sequence, message }) => {
+                self.receive(sequence, packet.timestamp, message)
+            }
+            None => (None, Vec::new()),
+        }
+    }
+
+    /// Control channel statistics
+    pub fn stats(&self) -> ControlStats {
+        ControlStats {
+            in_flight: self.pending.len(),
+            ..self.stats
+        }
+    }
+
+    fn receive(
+        &mut self,
+        sequence: u32,
+        floor: u32,
+        message: Option<ControlMessage>,
+    ) -> (Option<Packet>, Vec<ControlMessage>) {
+        let mut delivered = Vec::new();
+        let expected = self.sync(floor, &mut delivered);
+
+        let offset = sequence.wrapping_sub(expected) as i32;
+        if offset >= CONTROL_RECEIVE_WINDOW as i32 {
+            // Too far ahead to hold; the sender retransmits it
+            debug!("Control message {} outside the receive window", sequence);
+            return (None, delivered);
+        }
+
+        let ack = self.transmit(&ControlPayload::Ack { sequence });
+        if offset < 0 || self.early.contains_key(&sequence) {
+            // Duplicate: acknowledge again in case our ack was lost
+            return (Some(ack), delivered);
+        }
+
+        self.early.insert(sequence, message);
+        self.deliver_ready(&mut delivered);
+        (Some(ack), delivered)
+    }
+
+    /// Follow the sender's retransmit floor, returning the next expected
+    /// sequence
+    ///
+    /// Messages below the floor that did arrive are delivered; the rest were
+    /// abandoned by the sender and are skipped.
+    fn sync(&mut self, floor: u32, delivered: &mut Vec<ControlMessage>) -> u32 {
+        let Some(expected) = self.expected else {
+            self.expected = Some(floor);
+            return floor;
+        };
+
+        let ahead = floor.wrapping_sub(expected) as i32;
+        if ahead.unsigned_abs() > CONTROL_RECEIVE_WINDOW {
+            debug!("Control channel resynced at {}", floor);
+            self.early.clear();
+            self.expected = Some(floor);
+            return floor;
+        }
+
+        if ahead > 0 {
+            let mut next = expected;
+            while next != floor {
+                if let Some(Some(message)) = self.early.remove(&next) {
+                    self.stats.messages_delivered += 1;
+                    delivered.push(message);
+                }
+                next = next.wrapping_add(1);
+            }
+            self.expected = Some(floor);
+            self.deliver_ready(delivered);
+        }
+        self.expected.unwrap_or(floor)
+    }
+
+    /// Deliver held messages from `expected` on until the first gap
+    fn deliver_ready(&mut self, delivered: &mut Vec<ControlMessage>) {
+        let Some(mut next) = self.expected else {
+            return;
+        };
+        while let Some(message) = self.early.remove(&next) {
+            if let Some(message) = message {
+                self.stats.messages_delivered += 1;
+                delivered.push(message);
+            }
+            next = next.wrapping_add(1);
+        }
+        self.expected = Some(next);
+    }
+
+    /// Packet for one transmission of `payload`, under a header sequence of
+    /// its own
+    fn transmit(&mut self, payload: &ControlPayload) -> Packet {
+        let transmission = self.next_transmission;
+        self.next_transmission = transmission.wrapping_add(1);
+        Packet::control(transmission, self.floor(), payload)
+    }
+
+    /// Oldest sequence still retransmitted (the next one if none is pending)
+    fn floor(&self) -> u32 {
+        self.pending
+            .front()
+            .map_or(self.next_sequence, |p| p.sequence)
+    }
+
+    fn retransmit_timeout(&self, attempts: u32) -> Duration {
+        let factor = 1u32 << attempts.saturating_sub(1).min(16);
+        self.config
+            .retransmit_timeout
+            .saturating_mul(factor)
+            .min(self.config.max_retransmit_timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(kind: u16) -> ControlMessage {
+        ControlMessage::Custom {
+            kind,
+            data: Vec::new(),
+        }
+    }
+
+    fn channel() -> ControlChannel {
+        ControlChannel::new(ControlConfig::default())
+    }
+
+    /// Message sequence or acknowledged sequence carried by `packet`
+    fn sequence_of(packet: &Packet) -> u32 {
+        match ControlPayload::from_bytes(&packet.payload).unwrap() {
+            ControlPayload::Message { sequence, .. } | ControlPayload::Ack { sequence } => sequence,
+        }
+    }
+
+    #[test]
+    fn test_delivers_in_order_and_ack_stops_retransmission() {
+        let now = Instant::now();
+        let mut a = channel();
+        let mut b = channel();
+
+        let packet = a.send(message(1), now);
+        let (ack, delivered) = b.handle_packet(&packet);
+        assert_eq!(delivered, vec![message(1)]);
+        assert_eq!(a.stats().in_flight, 1);
+
+        a.handle_packet(&ack.unwrap());
+        assert_eq!(a.stats().in_flight, 0);
+        assert!(a.poll(now + Duration::from_secs(5)).is_empty());
+        assert_eq!(b.stats().messages_delivered, 1);
+    }
+
+    #[test]
+    fn test_holds_early_messages_and_drops_duplicates() {
+        let now = Instant::now();
+        let mut a = channel();
+        let mut b = channel();
+
+        let packets: Vec<Packet> = (0..3).map(|i| a.send(message(i), now)).collect();
+
+        // Sync on the first message, then receive the third before the second
+        assert_eq!(b.handle_packet(&packets[0]).1, vec![message(0)]);
+        let (ack, delivered) = b.handle_packet(&packets[2]);
+        assert!(ack.is_some());
+        assert!(delivered.is_empty());
+        assert_eq!(b.handle_packet(&packets[1]).1, vec![message(1), message(2)]);
+
+        // A duplicate is acknowledged again but not delivered
+        let (ack, delivered) = b.handle_packet(&packets[1]);
+        assert_eq!(sequence_of(&ack.unwrap()), sequence_of(&packets[1]));
+        assert!(delivered.is_empty());
+    }
+
+    #[test]
+    fn test_retransmits_with_backoff() {
+        let now = Instant::now();
+        let mut a = channel();
+        let packet = a.send(message(1), now);
+
+        assert!(a.poll(now + Duration::from_millis(199)).is_empty());
+        let resent = a.poll(now + Duration::from_millis(200));
+        assert_eq!(resent.len(), 1);
+        assert_eq!(resent[0].payload, packet.payload);
+
+        // Second retransmission after twice the timeout
+        assert!(a.poll(now + Duration::from_millis(599)).is_empty());
+        assert_eq!(a.poll(now + Duration::from_millis(600)).len(), 1);
+        assert_eq!(a.stats().retransmissions, 2);
+    }
+
+    #[test]
+    fn test_receiver_skips_abandoned_message() {
+        let config = ControlConfig {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let mut now = Instant::now();
+        let mut a = ControlChannel::new(config);
+        let mut b = channel();
+
+        let first = a.send(message(0), now);
+        b.handle_packet(&first);
+        // Acknowledge nothing: the first message is sent twice, then abandoned
+        let _lost = a.send(message(1), now);
+        now += Duration::from_secs(1);
+        assert_eq!(a.poll(now).len(), 2);
+        now += Duration::from_secs(1);
+        assert!(a.poll(now).is_empty());
+        assert_eq!(a.stats().messages_abandoned, 2);
+
+        let next = a.send(message(2), now);
+        assert_eq!(next.timestamp, sequence_of(&next));
+        assert_eq!(b.handle_packet(&next).1, vec![message(2)]);
+    }
+
+    #[test]
+    fn test_unknown_message_keeps_its_place() {
+        let now = Instant::now();
+        let mut a = channel();
+        let mut b = channel();
+
+        let known = a.send(message(0), now);
+        let mut unknown = a.send(message(1), now);
+        unknown.payload.truncate(5);
+        unknown.payload.push(42);
+        let last = a.send(message(2), now);
+
+        b.handle_packet(&known);
+        assert!(b.handle_packet(&last).1.is_empty());
+        let (ack, delivered) = b.handle_packet(&unknown);
+        assert!(ack.is_some());
+        assert_eq!(delivered, vec![message(2)]);
+    }
+
+    #[test]
+    fn test_resyncs_after_peer_restart() {
+        let now = Instant::now();
+        let mut a = channel();
+        let mut b = channel();
+        b.handle_packet(&a.send(message(0), now));
+
+        let mut restarted = channel();
+        restarted.next_sequence = a.next_sequence.wrapping_add(CONTROL_RECEIVE_WINDOW * 4);
+        let packet = restarted.send(message(1), now);
+        assert_eq!(b.handle_packet(&packet).1, vec![message(1)]);
+    }
+
+    #[test]
+    fn test_every_transmission_has_its_own_sequence() {
+        let mut now = Instant::now();
+        let mut a = channel();
+        let mut b = channel();
+
+        // One message sent three times while the floor moves under it, and
+        // acks for the peer's messages, all from the same channel
+        let mut sent = vec![a.send(message(0), now), a.send(message(1), now)];
+        let (ack, _) = a.handle_packet(&b.send(message(9), now));
+        sent.push(ack.unwrap());
+        a.handle_packet(&b.handle_packet(&sent[0]).0.unwrap());
+        for _ in 0..2 {
+            now += Duration::from_secs(1);
+            sent.extend(a.poll(now));
+        }
+        let retransmissions: Vec<&Packet> = sent
+            .iter()
+            .filter(|p| sequence_of(p) == sequence_of(&sent[1]))
+            .collect();
+        assert_eq!(retransmissions.len(), 3);
+        assert_ne!(retransmissions[0].timestamp, retransmissions[1].timestamp);
+
+        // The nonce is derived from the header sequence and type: distinct
+        // header sequences never share one, whatever the header says
+        for (i, packet) in sent.iter().enumerate() {
+            assert!(sent[i + 1..].iter().all(|p| p.sequence != packet.sequence));
+        }
+    }
+}
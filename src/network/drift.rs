@@ -0,0 +1,484 @@
+//! Clock drift compensation
+//!
+//! Two sound cards running at a nominal 48 kHz never agree exactly; tens of
+//! ppm apart, the jitter buffer of a long session slowly fills up or runs
+//! dry. The receive pipeline estimates the drift by comparing the sender's
+//! sample timestamps with the local playout position, and plays the stream
+//! through an adaptive resampler whose ratio follows the drift, plus a small
+//! correction that pulls the buffer fill back to its target. No frame is
+//! dropped or repeated; the pitch moves by a few cents at most.
+//!
+//! Like the clock offset filter, the estimate follows the earliest arrivals:
+//! queuing only ever delays packets, so the highest timestamp-minus-playout
+//! value of each window is the one least disturbed by jitter, and the slope
+//! of those values over playout time is the drift.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+/// Local playout covered by one window of the drift estimate (seconds)
+const DRIFT_WINDOW_SECS: f64 = 1.0;
+
+/// Windows kept for the drift estimate
+const DRIFT_HISTORY_WINDOWS: usize = 60;
+
+/// Playout the windows must span before drift is estimated (seconds)
+const MIN_DRIFT_SPAN_SECS: f64 = 10.0;
+
+/// Timestamp jump taken as a restart of the remote stream (seconds)
+const RESTART_THRESHOLD_SECS: f64 = 1.0;
+
+/// Largest drift believed (ppm); real sound cards stay well within it
+const MAX_DRIFT_PPM: f64 = 1000.0;
+
+/// Largest resampling ratio deviation including the fill correction (ppm)
+const MAX_RATIO_DEVIATION_PPM: f64 = 2000.0;
+
+/// Time over which a buffer fill error is corrected (seconds)
+const FILL_CORRECTION_SECS: f64 = 20.0;
+
+/// Half the number of resampler filter taps
+const HALF_TAPS: usize = 16;
+
+/// Filter phases in the coefficient table (interpolated linearly)
+const FILTER_PHASES: usize = 256;
+
+/// Playout position and timestamp-minus-playout value of one packet
+#[derive(Debug, Clone, Copy)]
+struct DriftPoint {
+    position: f64,
+    offset: f64,
+}
+
+/// Earliest arrival of the current window
+#[derive(Debug, Clone, Copy)]
+struct DriftWindow {
+    start: f64,
+    best: DriftPoint,
+}
+
+/// Line fitted through the windows' earliest arrivals
+#[derive(Debug, Clone, Copy)]
+struct DriftLine {
+    through: DriftPoint,
+    slope: f64,
+}
+
+impl DriftLine {
+    fn offset_at(&self, position: f64) -> f64 {
+        self.through.offset + (position - self.through.position) * self.slope
+    }
+}
+
+/// Drift estimator for one received stream
+///
+/// Positions are in samples per channel of local playout, so the estimate is
+/// the drift between the sender's sample clock and our playout clock.
+#[derive(Debug)]
+pub(crate) struct DriftEstimator {
+    sample_rate: f64,
+    /// Newest timestamp seen and its unwrapped value
+    newest: Option<(u32, i64)>,
+    window: Option<DriftWindow>,
+    /// Earliest arrival of each finished window, oldest first
+    history: VecDeque<DriftPoint>,
+    line: Option<DriftLine>,
+    /// Played timestamp minus playout position when playout started
+    start: Option<DriftPoint>,
+    /// Lead of the earliest arrivals over the playout when it started
+    start_lead: Option<f64>,
+}
+
+impl DriftEstimator {
+    /// Create an estimator for a stream at `sample_rate`
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1) as f64,
+            newest: None,
+            window: None,
+            history: VecDeque::new(),
+            line: None,
+            start: None,
+            start_lead: None,
+        }
+    }
+
+    /// Add a packet with `timestamp` that arrived at local playout `position`
+    pub(crate) fn on_packet(&mut self, timestamp: u32, position: f64) {
+        let mut point = self.point(timestamp, position);
+        let restart_threshold = RESTART_THRESHOLD_SECS * self.sample_rate;
+        if self
+            .latest()
+            .is_some_and(|latest| (point.offset - latest.offset).abs() > restart_threshold)
+        {
+            self.reset();
+            point = self.point(timestamp, position);
+        }
+
+        let window_len = DRIFT_WINDOW_SECS * self.sample_rate;
+        if let Some(window) = self
+            .window
+            .as_mut()
+            .filter(|window| point.position < window.start + window_len)
+        {
+            if point.offset > window.best.offset {
+                window.best = point;
+            }
+            return;
+        }
+
+        if let Some(done) = self.window.take() {
+            if self.history.len() >= DRIFT_HISTORY_WINDOWS {
+                self.history.pop_front();
+            }
+            self.history.push_back(done.best);
+            self.line = self.fit();
+            if let (Some(line), Some(start), None) = (self.line, self.start, self.start_lead) {
+                self.start_lead = Some(line.offset_at(start.position) - start.offset);
+            }
+        }
+        self.window = Some(DriftWindow {
+            start: point.position,
+            best: point,
+        });
+    }
+
+    /// Note that the sample with `timestamp` is played at `position`
+    ///
+    /// The first call after a (re)start anchors the lead held by
+    /// `lead_error`.
+    pub(crate) fn on_played(&mut self, timestamp: u32, position: f64) {
+        if self.start.is_none() {
+            self.start = self.unwrap(timestamp).map(|unwrapped| DriftPoint {
+                position,
+                offset: unwrapped as f64 - position,
+            });
+        }
+    }
+
+    /// How much faster the sender's clock runs than our playout (ppm), None
+    /// until enough playout has been observed
+    pub(crate) fn drift_ppm(&self) -> Option<f64> {
+        self.line
+            .map(|line| (line.slope * 1e6).clamp(-MAX_DRIFT_PPM, MAX_DRIFT_PPM))
+    }
+
+    /// How many samples further the earliest arrivals are ahead of the
+    /// sample with `timestamp`, played at `position`, than when playout
+    /// started
+    ///
+    /// Unlike the jitter buffer depth this follows the buffer fill without
+    /// jitter or the granularity of whole frames. None until drift is
+    /// estimated.
+    pub(crate) fn lead_error(&self, timestamp: u32, position: f64) -> Option<f64> {
+        let line = self.line?;
+        let played = self.unwrap(timestamp)? as f64 - position;
+        Some(line.offset_at(position) - played - self.start_lead?)
+    }
+
+    /// Forget everything (e.g. when the remote stream restarts)
+    pub(crate) fn reset(&mut self) {
+        self.newest = None;
+        self.window = None;
+        self.history.clear();
+        self.line = None;
+        self.start = None;
+        self.start_lead = None;
+    }
+
+    /// Timestamp extended to 64 bits around the newest one
+    fn unwrap(&self, timestamp: u32) -> Option<i64> {
+        self.newest
+            .map(|(last, value)| value + timestamp.wrapping_sub(last) as i32 as i64)
+    }
+
+    fn point(&mut self, timestamp: u32, position: f64) -> DriftPoint {
+        let unwrapped = self.unwrap(timestamp).unwrap_or(timestamp as i64);
+        if self.newest.is_none_or(|(_, value)| unwrapped > value) {
+            self.newest = Some((timestamp, unwrapped));
+        }
+        DriftPoint {
+            position,
+            offset: unwrapped as f64 - position,
+        }
+    }
+
+    fn latest(&self) -> Option<DriftPoint> {
+        self.window
+            .map(|window| window.best)
+            .or_else(|| self.history.back().copied())
+    }
+
+    /// Least-squares line through the windows' earliest arrivals
+    fn fit(&self) -> Option<DriftLine> {
+        let (first, last) = (self.history.front()?, self.history.back()?);
+        if last.position - first.position < MIN_DRIFT_SPAN_SECS * self.sample_rate {
+            return None;
+        }
+
+        let n = self.history.len() as f64;
+        let points = || {
+            self.history
+                .iter()
+                .map(|p| (p.position - first.position, p.offset - first.offset))
+        };
+        let mean_x = points().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points().map(|(_, y)| y).sum::<f64>() / n;
+        let (num, den) = points().fold((0.0, 0.0), |(num, den), (x, y)| {
+            (
+                num + (x - mean_x) * (y - mean_y),
+                den + (x - mean_x) * (x - mean_x),
+            )
+        });
+        if den <= 0.0 {
+            return None;
+        }
+        Some(DriftLine {
+            through: DriftPoint {
+                position: first.position + mean_x,
+                offset: first.offset + mean_y,
+            },
+            slope: num / den,
+        })
+    }
+}
+
+/// Resampling ratio (input samples per output sample) for a drift and a
+/// buffer fill error in samples per channel
+///
+/// A fuller buffer than the target is played slightly faster until the
+/// error is gone after about `FILL_CORRECTION_SECS`.
+pub(crate) fn resample_ratio(drift_ppm: f64, fill_error: f64, sample_rate: u32) -> f64 {
+    let correction = fill_error / (FILL_CORRECTION_SECS * sample_rate.max(1) as f64) * 1e6;
+    let ppm = (drift_ppm + correction).clamp(-MAX_RATIO_DEVIATION_PPM, MAX_RATIO_DEVIATION_PPM);
+    1.0 + ppm / 1e6
+}
+
+/// Windowed-sinc coefficients, one row of `2 * HALF_TAPS` taps per phase
+/// from 0 to 1 inclusive, each row normalized to unity gain
+fn filter_table() -> &'static [f32] {
+    static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let taps = 2 * HALF_TAPS;
+        let mut table = Vec::with_capacity((FILTER_PHASES + 1) * taps);
+        for phase in 0..=FILTER_PHASES {
+            let frac = phase as f64 / FILTER_PHASES as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|tap| windowed_sinc(tap as f64 - (HALF_TAPS - 1) as f64 - frac))
+                .collect();
+            let sum: f64 = row.iter().sum();
+            table.extend(row.iter().map(|c| (c / sum) as f32));
+        }
+        table
+    })
+}
+
+/// Sinc with a Blackman window reaching zero at `±HALF_TAPS`
+fn windowed_sinc(distance: f64) -> f64 {
+    if distance == 0.0 {
+        return 1.0;
+    }
+    let x = PI * distance;
+    let w = x / HALF_TAPS as f64;
+    let window = 0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+    x.sin() / x * window
+}
+
+/// Streaming resampler with a ratio that may change every call
+///
+/// Interpolates with a 32-tap windowed-sinc filter, so at a ratio of exactly
+/// 1 the input passes through unchanged. The filter looks `HALF_TAPS` samples
+/// ahead, which the caller provides by pushing input before it is needed.
+pub(crate) struct AdaptiveResampler {
+    channels: usize,
+    /// Interleaved input, starting `HALF_TAPS - 1` samples before the read
+    /// position
+    input: Vec<f32>,
+    /// Read position in samples per channel from the start of `input`
+    position: f64,
+}
+
+impl AdaptiveResampler {
+    /// Samples per channel of already played audio the filter looks back on
+    pub(crate) const HISTORY: usize = HALF_TAPS - 1;
+
+    /// Samples per channel the filter reads beyond the read position
+    pub(crate) const LOOKAHEAD: usize = HALF_TAPS;
+
+    /// Create a resampler continuing from `history`, the last played
+    /// interleaved samples (at most `HISTORY` per channel are used)
+    pub(crate) fn new(channels: usize, history: &[f32]) -> Self {
+        let channels = channels.max(1);
+        let len = Self::HISTORY * channels;
+        let mut input = vec![0.0; len];
+        let tail = &history[history.len().saturating_sub(len)..];
+        let tail = &tail[tail.len() % channels..];
+        input[len - tail.len()..].copy_from_slice(tail);
+        Self {
+            channels,
+            input,
+            position: Self::HISTORY as f64,
+        }
+    }
+
+    /// Append interleaved input samples
+    pub(crate) fn push(&mut self, samples: &[f32]) {
+        self.input.extend_from_slice(samples);
+    }
+
+    /// Input samples per channel not yet played, including the lookahead
+    pub(crate) fn buffered(&self) -> f64 {
+        (self.input.len() / self.channels) as f64 - self.position
+    }
+
+    /// Check if enough input is buffered for `frames` output samples per
+    /// channel at `ratio`
+    pub(crate) fn can_process(&self, frames: usize, ratio: f64) -> bool {
+        let last = self.position + frames.saturating_sub(1) as f64 * ratio;
+        (last.floor() as usize + HALF_TAPS) < self.input.len() / self.channels
+    }
+
+    /// Produce `frames` interleaved output samples per channel, reading the
+    /// input `ratio` samples per output sample
+    ///
+    /// The caller checks `can_process` first.
+    pub(crate) fn process(&mut self, frames: usize, ratio: f64) -> Vec<f32> {
+        let table = filter_table();
+        let taps = 2 * HALF_TAPS;
+        let mut coefficients = [0.0f32; 2 * HALF_TAPS];
+        let mut output = Vec::with_capacity(frames * self.channels);
+
+        for _ in 0..frames {
+            let base = self.position.floor();
+            let phase = (self.position - base) * FILTER_PHASES as f64;
+            let row = phase.floor() as usize;
+            let blend = (phase - row as f64) as f32;
+            let lower = &table[row * taps..(row + 1) * taps];
+            let upper = &table[(row + 1) * taps..(row + 2) * taps];
+            for ((c, a), b) in coefficients.iter_mut().zip(lower).zip(upper) {
+                *c = a + (b - a) * blend;
+            }
+
+            let first = base as usize + 1 - HALF_TAPS;
+            for channel in 0..self.channels {
+                let sum = coefficients
+                    .iter()
+                    .enumerate()
+                    .map(|(tap, c)| c * self.input[(first + tap) * self.channels + channel])
+                    .sum();
+                output.push(sum);
+            }
+            self.position += ratio;
+        }
+
+        let consumed = (self.position.floor() as usize).saturating_sub(Self::HISTORY);
+        self.input.drain(..consumed * self.channels);
+        self.position -= consumed as f64;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packets of 480 samples from a sender running `drift_ppm` fast,
+    /// arriving with up to 3 ms of jitter
+    fn estimate(drift_ppm: f64, seconds: u32) -> Option<f64> {
+        let mut estimator = DriftEstimator::new(48000);
+        let rate = 1.0 + drift_ppm / 1e6;
+        for i in 0..seconds * 100 {
+            let timestamp = (i * 480).wrapping_add(u32::MAX - 48000);
+            let sent = i as f64 * 480.0 / rate;
+            let jitter = ((i * 7919) % 31) as f64 / 30.0 * 144.0;
+            estimator.on_packet(timestamp, sent + jitter);
+        }
+        estimator.drift_ppm()
+    }
+
+    #[test]
+    fn test_estimates_drift() {
+        assert_eq!(estimate(100.0, 5), None);
+
+        for drift in [-250.0, 0.0, 40.0, 100.0] {
+            let estimated = estimate(drift, 40).unwrap();
+            assert!(
+                (estimated - drift).abs() < 2.0,
+                "{} ppm estimated as {}",
+                drift,
+                estimated
+            );
+        }
+    }
+
+    #[test]
+    fn test_restart_discards_estimate() {
+        let mut estimator = DriftEstimator::new(48000);
+        for i in 0..1500u32 {
+            estimator.on_packet(i * 480, i as f64 * 480.0);
+        }
+        assert!(estimator.drift_ppm().is_some());
+
+        estimator.on_packet(7_000_000, 1500.0 * 480.0);
+        assert_eq!(estimator.drift_ppm(), None);
+    }
+
+    #[test]
+    fn test_unit_ratio_passes_input_through() {
+        let input: Vec<f32> = (0..200).map(|i| ((i * 37) % 101) as f32 / 100.0).collect();
+        let mut resampler = AdaptiveResampler::new(2, &[]);
+        let mut output = Vec::new();
+        for chunk in input.chunks(20) {
+            resampler.push(chunk);
+            while resampler.can_process(4, 1.0) {
+                output.extend(resampler.process(4, 1.0));
+            }
+        }
+        // Everything but the lookahead comes out unchanged
+        assert_eq!(output.len(), input.len() - 2 * HALF_TAPS);
+        for (out, expected) in output.iter().zip(&input) {
+            assert!((out - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_resampled_sine() {
+        let ratio = 1.0015;
+        let frequency = 1000.0 / 48000.0;
+        let sine = |t: f64| (2.0 * PI * frequency * t).sin() as f32;
+
+        let history: Vec<f32> = (-20..0).map(|t| sine(t as f64)).collect();
+        let mut resampler = AdaptiveResampler::new(1, &history);
+        let mut pushed = 0;
+        let mut output = Vec::new();
+        while output.len() < 4800 {
+            while !resampler.can_process(480, ratio) {
+                resampler.push(
+                    &(pushed..pushed + 480)
+                        .map(|t| sine(t as f64))
+                        .collect::<Vec<_>>(),
+                );
+                pushed += 480;
+            }
+            output.extend(resampler.process(480, ratio));
+        }
+
+        for (n, &sample) in output.iter().enumerate() {
+            let expected = sine(n as f64 * ratio);
+            assert!((sample - expected).abs() < 1e-3, "sample {}", n);
+        }
+        // The input consumed follows the ratio
+        let consumed = pushed as f64 - resampler.buffered();
+        assert!((consumed - 4800.0 * ratio).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ratio_follows_drift_and_fill() {
+        assert_eq!(resample_ratio(0.0, 0.0, 48000), 1.0);
+        assert!((resample_ratio(50.0, 0.0, 48000) - 1.000_05).abs() < 1e-12);
+        // A frame too many is worked off in about 20 s
+        assert!((resample_ratio(0.0, 480.0, 48000) - 1.0005).abs() < 1e-12);
+        assert!((resample_ratio(900.0, 48000.0, 48000) - 1.002).abs() < 1e-12);
+    }
+}
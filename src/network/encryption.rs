@@ -2,18 +2,31 @@
 //!
 //! Uses X25519 for key exchange and AES-256-GCM for symmetric encryption.
 //! The nonce is derived from the packet sequence number to avoid nonce reuse.
+//!
+//! Peers exchange public keys with `PacketType::KeyExchange` packets. Each
+//! direction uses its own key; the peer with the larger public key takes the
+//! initiator role. Each exchange also carries a random nonce that salts the
+//! key derivation, so a key pair used for several exchanges never yields the
+//! same session keys twice.
+//!
+//! The in-band exchange alone does not authenticate the peer: whoever sits on
+//! the path can hand each side its own key. A peer that published its public
+//! key through signaling (`PeerInfo::public_key`) is held to it. Key exchange
+//! packets are cleartext and anyone can spoof them, so one that does not
+//! match, or that changes the key after the session keys are derived, is
+//! dropped rather than failing the peer.
 
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
 use aes_gcm::{Aes256Gcm, Key, Nonce};
 use hkdf::Hkdf;
 use rand::rngs::OsRng;
 use sha2::Sha256;
-use x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
 
-use crate::protocol::Packet;
+use crate::protocol::{KeyExchangePayload, Packet, PacketType};
 
 use super::error::NetworkError;
 use super::transport::UdpTransport;
@@ -26,15 +39,19 @@ const TAG_SIZE: usize = 16;
 const NONCE_SIZE: usize = 12;
 
 /// Encryption key pair for ECDH key exchange
+///
+/// One key pair can be used with several peers (a `Session` publishes a
+/// single public key through signaling); each pair of peers still derives
+/// its own shared secret.
 pub struct KeyPair {
-    secret: EphemeralSecret,
+    secret: StaticSecret,
     public: PublicKey,
 }
 
 impl KeyPair {
     /// Generate a new random key pair
     pub fn generate() -> Self {
-        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let secret = StaticSecret::random_from_rng(OsRng);
         let public = PublicKey::from(&secret);
         Self { secret, public }
     }
@@ -45,7 +62,7 @@ impl KeyPair {
     }
 
     /// Derive a shared secret from the peer's public key
-    pub fn derive_shared_secret(self, peer_public: &[u8; 32]) -> SharedSecret {
+    pub fn derive_shared_secret(&self, peer_public: &[u8; 32]) -> SharedSecret {
         let peer_key = PublicKey::from(*peer_public);
         self.secret.diffie_hellman(&peer_key)
     }
@@ -61,8 +78,17 @@ pub struct EncryptionContext {
 impl EncryptionContext {
     /// Create a new encryption context from a shared secret
     pub fn from_shared_secret(shared_secret: &[u8], is_initiator: bool) -> Self {
+        Self::from_salted_secret(shared_secret, None, is_initiator)
+    }
+
+    /// Create a new encryption context from a shared secret and an HKDF salt
+    pub fn from_salted_secret(
+        shared_secret: &[u8],
+        salt: Option<&[u8]>,
+        is_initiator: bool,
+    ) -> Self {
         // Use HKDF to derive the encryption key
-        let hk = Hkdf::<Sha256>::new(None, shared_secret);
+        let hk = Hkdf::<Sha256>::new(salt, shared_secret);
         let mut key_bytes = [0u8; 32];
         let info = if is_initiator {
             b"jamjam-session-key-initiator"
@@ -113,6 +139,66 @@ impl EncryptionContext {
             .map_err(|_| NetworkError::EncryptionError("Decryption failed".to_string()))
     }
 
+    /// Encrypt a whole packet
+    ///
+    /// The payload is encrypted and the header (with the encrypted flag set)
+    /// is authenticated as associated data, so sequence and timestamp cannot
+    /// be altered in transit. The packet type and stream ID are mixed into
+    /// the nonce because each packet type and stream has its own sequence
+    /// space.
+    pub fn encrypt_packet(&self, packet: &Packet) -> Result<Packet, NetworkError> {
+        let mut encrypted_packet = Packet {
+            payload: Vec::new(),
+            ..packet.clone()
+        };
+        encrypted_packet.flags.encrypted = true;
+
+        let nonce = self.derive_packet_nonce(packet);
+        let header = encrypted_packet.header_bytes();
+        encrypted_packet.payload = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &packet.payload,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| NetworkError::EncryptionError("Encryption failed".to_string()))?;
+
+        Ok(encrypted_packet)
+    }
+
+    /// Decrypt a packet produced by [`EncryptionContext::encrypt_packet`]
+    pub fn decrypt_packet(&self, packet: &Packet) -> Result<Packet, NetworkError> {
+        if !packet.flags.encrypted {
+            return Err(NetworkError::EncryptionError(
+                "Packet is not encrypted".to_string(),
+            ));
+        }
+
+        let nonce = self.derive_packet_nonce(packet);
+        let header = packet.header_bytes();
+        let payload = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &packet.payload,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| NetworkError::EncryptionError("Decryption failed".to_string()))?;
+
+        let mut decrypted_packet = Packet {
+            payload,
+            ..packet.clone()
+        };
+        decrypted_packet.flags.encrypted = false;
+
+        Ok(decrypted_packet)
+    }
+
     /// Derive a nonce from sequence number
     /// Nonce format: [4 bytes prefix][4 bytes sequence][4 bytes zero padding]
     fn derive_nonce(&self, sequence: u32) -> [u8; NONCE_SIZE] {
@@ -122,72 +208,321 @@ impl EncryptionContext {
         // Last 4 bytes remain zero
         nonce
     }
+
+    /// Derive a nonce from packet type, stream and sequence number
+    /// Nonce format: [4 bytes prefix][4 bytes sequence][1 byte type][1 byte stream][2 bytes zero padding]
+    fn derive_packet_nonce(&self, packet: &Packet) -> [u8; NONCE_SIZE] {
+        let mut nonce = self.derive_nonce(packet.sequence);
+        nonce[8] = packet.packet_type as u8;
+        nonce[9] = packet.stream_id();
+        nonce
+    }
 }
 
 /// Encrypted transport wrapper
+///
+/// Holds one encryption context per direction.
 pub struct EncryptedTransport {
     inner: Arc<UdpTransport>,
-    encryption: EncryptionContext,
+    send: EncryptionContext,
+    recv: EncryptionContext,
 }
 
 impl EncryptedTransport {
     /// Create a new encrypted transport
-    pub fn new(transport: Arc<UdpTransport>, encryption: EncryptionContext) -> Self {
+    pub fn new(
+        transport: Arc<UdpTransport>,
+        send: EncryptionContext,
+        recv: EncryptionContext,
+    ) -> Self {
         Self {
             inner: transport,
-            encryption,
+            send,
+            recv,
         }
     }
 
+    /// Create an encrypted transport from an ECDH shared secret
+    ///
+    /// Both peers must derive the same secret and take opposite roles.
+    pub fn from_shared_secret(
+        transport: Arc<UdpTransport>,
+        shared_secret: &[u8],
+        is_initiator: bool,
+    ) -> Self {
+        Self::from_salted_secret(transport, shared_secret, None, is_initiator)
+    }
+
+    /// Create an encrypted transport from an ECDH shared secret and an HKDF
+    /// salt both peers agree on
+    pub fn from_salted_secret(
+        transport: Arc<UdpTransport>,
+        shared_secret: &[u8],
+        salt: Option<&[u8]>,
+        is_initiator: bool,
+    ) -> Self {
+        Self::new(
+            transport,
+            EncryptionContext::from_salted_secret(shared_secret, salt, is_initiator),
+            EncryptionContext::from_salted_secret(shared_secret, salt, !is_initiator),
+        )
+    }
+
     /// Get the local address
     pub fn local_addr(&self) -> SocketAddr {
         self.inner.local_addr()
     }
 
+    /// Encrypt an outgoing packet
+    pub fn encrypt(&self, packet: &Packet) -> Result<Packet, NetworkError> {
+        self.send.encrypt_packet(packet)
+    }
+
+    /// Decrypt an incoming packet
+    pub fn decrypt(&self, packet: &Packet) -> Result<Packet, NetworkError> {
+        self.recv.decrypt_packet(packet)
+    }
+
     /// Send an encrypted packet
     pub async fn send_to(&self, packet: &Packet, addr: SocketAddr) -> Result<(), NetworkError> {
-        // Encrypt the payload
-        let encrypted_payload = self.encryption.encrypt(packet.sequence, &packet.payload)?;
-
-        // Create a new packet with encrypted payload and encrypted flag set
-        let mut flags = packet.flags;
-        flags.encrypted = true;
-
-        let encrypted_packet = Packet {
-            version: packet.version,
-            packet_type: packet.packet_type,
-            sequence: packet.sequence,
-            timestamp: packet.timestamp,
-            flags,
-            payload: encrypted_payload,
-        };
-
+        let encrypted_packet = self.encrypt(packet)?;
         self.inner.send_to(&encrypted_packet, addr).await
     }
 
     /// Receive and decrypt a packet
     pub async fn recv_from(&self) -> Result<(Packet, SocketAddr), NetworkError> {
         let (encrypted_packet, addr) = self.inner.recv_from().await?;
+        let packet = self.decrypt(&encrypted_packet)?;
+        Ok((packet, addr))
+    }
+}
 
-        // Decrypt the payload
-        let decrypted_payload = self
-            .encryption
-            .decrypt(encrypted_packet.sequence, &encrypted_packet.payload)?;
-
-        // Create decrypted packet with encrypted flag cleared
-        let mut flags = encrypted_packet.flags;
-        flags.encrypted = false;
-
-        let packet = Packet {
-            version: encrypted_packet.version,
-            packet_type: encrypted_packet.packet_type,
-            sequence: encrypted_packet.sequence,
-            timestamp: encrypted_packet.timestamp,
-            flags,
-            payload: decrypted_payload,
-        };
+/// Encryption policy for a connection or session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncryptionMode {
+    /// Never exchange keys; all packets are sent in cleartext
+    Disabled,
+    /// Exchange keys and encrypt once both sides agree; accept cleartext
+    /// from peers that do not support encryption
+    #[default]
+    Preferred,
+    /// Exchange keys, refuse to send media before the exchange completes,
+    /// and reject unencrypted media packets
+    Required,
+}
 
-        Ok((packet, addr))
+impl EncryptionMode {
+    /// Check if key exchange should be performed
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, Self::Disabled)
+    }
+}
+
+/// Key exchange state with a single peer
+pub(crate) struct KeyExchangeState {
+    mode: EncryptionMode,
+    keypair: Arc<KeyPair>,
+    local_public: [u8; 32],
+    /// Random value salting the session keys of this exchange
+    local_nonce: [u8; 16],
+    /// Public key the peer published through signaling
+    expected_public: Option<[u8; 32]>,
+    peer_public: Option<[u8; 32]>,
+    peer_nonce: Option<[u8; 16]>,
+    secure: Option<Arc<EncryptedTransport>>,
+}
+
+impl KeyExchangeState {
+    /// Create a new state with a fresh ephemeral key pair
+    pub(crate) fn new(mode: EncryptionMode) -> Self {
+        Self::with_keypair(mode, Arc::new(KeyPair::generate()))
+    }
+
+    /// Create a new state using `keypair`, e.g. one published through
+    /// signaling
+    pub(crate) fn with_keypair(mode: EncryptionMode, keypair: Arc<KeyPair>) -> Self {
+        Self {
+            mode,
+            local_public: keypair.public_key_bytes(),
+            keypair,
+            local_nonce: rand::random(),
+            expected_public: None,
+            peer_public: None,
+            peer_nonce: None,
+            secure: None,
+        }
+    }
+
+    /// Hold the peer to the public key it published through signaling
+    ///
+    /// Only key exchanges offering this key derive session keys from now on.
+    /// Returns an error, and keeps the session keys, if they were already
+    /// derived from another key.
+    pub(crate) fn expect_peer_key(&mut self, public_key: [u8; 32]) -> Result<(), NetworkError> {
+        self.expected_public = Some(public_key);
+        match self.peer_public {
+            Some(known) if known != public_key => Err(NetworkError::KeyExchangeFailed(
+                "Session keys derived from another key than the published one".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check if session keys have been derived
+    pub(crate) fn is_established(&self) -> bool {
+        self.secure.is_some()
+    }
+
+    /// Check if our public key still needs to be (re)sent
+    pub(crate) fn needs_key_exchange(&self) -> bool {
+        self.mode.is_enabled() && !self.is_established()
+    }
+
+    /// Build a key exchange packet carrying our public key
+    pub(crate) fn key_exchange_packet(&self, ack: bool) -> Packet {
+        Packet::key_exchange(
+            0,
+            &KeyExchangePayload {
+                public_key: self.local_public,
+                ack,
+                nonce: Some(self.local_nonce),
+            },
+        )
+    }
+
+    /// Handle a key exchange packet from the peer
+    ///
+    /// Derives the session keys on the first valid public key, salted with
+    /// both nonces when the peer sends one. Returns the packet to send back,
+    /// if the peer has not yet acknowledged our key.
+    ///
+    /// A key other than the one the peer published, or a key or nonce that
+    /// changes after the session keys were derived, is rejected and changes
+    /// nothing: the packet is cleartext and may be spoofed. A peer that
+    /// published its key must send a nonce, since its key is reused across
+    /// exchanges.
+    pub(crate) fn handle_key_exchange(
+        &mut self,
+        transport: &Arc<UdpTransport>,
+        payload: &KeyExchangePayload,
+    ) -> Result<Option<Packet>, NetworkError> {
+        if !self.mode.is_enabled() {
+            return Ok(None);
+        }
+        if self
+            .expected_public
+            .is_some_and(|expected| expected != payload.public_key)
+        {
+            return Err(NetworkError::KeyExchangeFailed(
+                "Peer public key does not match signaling".to_string(),
+            ));
+        }
+        if self.expected_public.is_some() && payload.nonce.is_none() {
+            return Err(NetworkError::KeyExchangeFailed(
+                "Peer sent no key exchange nonce".to_string(),
+            ));
+        }
+
+        match self.peer_public {
+            Some(known) if known != payload.public_key || self.peer_nonce != payload.nonce => {
+                return Err(NetworkError::KeyExchangeFailed(
+                    "Peer public key changed".to_string(),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                let shared = self.keypair.derive_shared_secret(&payload.public_key);
+                if !shared.was_contributory() {
+                    return Err(NetworkError::KeyExchangeFailed(
+                        "Non-contributory public key".to_string(),
+                    ));
+                }
+
+                let is_initiator = self.local_public > payload.public_key;
+                let salt = payload.nonce.map(|peer_nonce| {
+                    let (first, second) = if is_initiator {
+                        (self.local_nonce, peer_nonce)
+                    } else {
+                        (peer_nonce, self.local_nonce)
+                    };
+                    [first, second].concat()
+                });
+                self.secure = Some(Arc::new(EncryptedTransport::from_salted_secret(
+                    transport.clone(),
+                    shared.as_bytes(),
+                    salt.as_deref(),
+                    is_initiator,
+                )));
+                self.peer_public = Some(payload.public_key);
+                self.peer_nonce = payload.nonce;
+            }
+        }
+
+        Ok((!payload.ack).then(|| self.key_exchange_packet(true)))
+    }
+
+    /// Validate and decrypt an incoming packet
+    ///
+    /// Encrypted packets are decrypted with the session keys. Cleartext
+    /// packets are rejected in required mode, except for the keep-alive, key
+    /// exchange and hello packets needed to set up the session. Once the
+    /// session keys are established hellos are sealed, and a cleartext one
+    /// (which anyone could forge to fail or downgrade the connection) is
+    /// rejected in any mode.
+    pub(crate) fn open(&self, packet: Packet) -> Result<Packet, NetworkError> {
+        if packet.flags.encrypted {
+            return match &self.secure {
+                Some(secure) => secure.decrypt(&packet),
+                None => Err(NetworkError::EncryptionError(
+                    "Encrypted packet before key exchange".to_string(),
+                )),
+            };
+        }
+
+        if packet.packet_type == PacketType::Hello && self.is_established() {
+            return Err(NetworkError::EncryptionError(
+                "Unencrypted hello after key exchange".to_string(),
+            ));
+        }
+
+        let is_handshake = matches!(
+            packet.packet_type,
+            PacketType::KeepAlive | PacketType::KeyExchange | PacketType::Hello
+        );
+        if self.mode == EncryptionMode::Required && !is_handshake {
+            return Err(NetworkError::EncryptionError(
+                "Unencrypted packet rejected".to_string(),
+            ));
+        }
+
+        Ok(packet)
+    }
+
+    /// Get the transport to use for an outgoing media packet
+    ///
+    /// Returns `None` when the packet should go out in cleartext. In required
+    /// mode this fails until the key exchange has completed.
+    pub(crate) fn outbound(&self) -> Result<Option<Arc<EncryptedTransport>>, NetworkError> {
+        match &self.secure {
+            Some(secure) => Ok(Some(secure.clone())),
+            None if self.mode == EncryptionMode::Required => Err(NetworkError::EncryptionError(
+                "Key exchange not complete".to_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Send a packet through the encrypted transport if given, otherwise in cleartext
+pub(crate) async fn send_packet(
+    transport: &UdpTransport,
+    secure: Option<&EncryptedTransport>,
+    packet: &Packet,
+    addr: SocketAddr,
+) -> Result<(), NetworkError> {
+    match secure {
+        Some(secure) => secure.send_to(packet, addr).await,
+        None => transport.send_to(packet, addr).await,
     }
 }
 
@@ -207,6 +542,7 @@ impl KeyExchangeMessage {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::StreamHeader;
 
     #[test]
     fn test_key_exchange() {
@@ -319,6 +655,271 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_packet_encryption_roundtrip() {
+        let alice = KeyPair::generate();
+        let bob = KeyPair::generate();
+        let alice_public = alice.public_key_bytes();
+        let bob_public = bob.public_key_bytes();
+        let alice_shared = alice.derive_shared_secret(&bob_public);
+        let bob_shared = bob.derive_shared_secret(&alice_public);
+
+        // Alice's send direction is Bob's receive direction
+        let alice_send = EncryptionContext::from_shared_secret(alice_shared.as_bytes(), true);
+        let bob_recv = EncryptionContext::from_shared_secret(bob_shared.as_bytes(), true);
+
+        let packet = Packet::audio(7, 960, vec![1, 2, 3, 4]);
+        let encrypted = alice_send.encrypt_packet(&packet).unwrap();
+        assert!(encrypted.flags.encrypted);
+        assert_ne!(encrypted.payload, packet.payload);
+
+        let decrypted = bob_recv.decrypt_packet(&encrypted).unwrap();
+        assert!(!decrypted.flags.encrypted);
+        assert_eq!(decrypted.payload, packet.payload);
+        assert_eq!(decrypted.timestamp, 960);
+    }
+
+    #[test]
+    fn test_packet_header_is_authenticated() {
+        let ctx = EncryptionContext::from_shared_secret(&[0x42u8; 32], true);
+        let mut encrypted = ctx
+            .encrypt_packet(&Packet::audio(1, 100, vec![9; 8]))
+            .unwrap();
+
+        // Changing the timestamp must invalidate the tag
+        encrypted.timestamp = 200;
+        assert!(ctx.decrypt_packet(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_packet_type_separates_nonces() {
+        let ctx = EncryptionContext::from_shared_secret(&[0x42u8; 32], true);
+        let audio = ctx
+            .encrypt_packet(&Packet::audio(1, 0, vec![0; 12]))
+            .unwrap();
+        let mut info = Packet::audio(1, 0, vec![0; 12]);
+        info.packet_type = PacketType::LatencyInfo;
+        let info = ctx.encrypt_packet(&info).unwrap();
+
+        // Same sequence and plaintext, different packet type
+        assert_ne!(audio.payload, info.payload);
+    }
+
+    #[test]
+    fn test_stream_separates_nonces() {
+        let ctx = EncryptionContext::from_shared_secret(&[0x42u8; 32], true);
+        let main = ctx
+            .encrypt_packet(&Packet::audio(1, 0, vec![0; 12]))
+            .unwrap();
+        let mut guitar = ctx
+            .encrypt_packet(&Packet::audio(1, 0, vec![0; 12]).with_stream(1, 1))
+            .unwrap();
+
+        // Same sequence and plaintext, different stream
+        assert_ne!(main.payload, guitar.payload);
+
+        // The stream extension is authenticated
+        guitar.stream = Some(StreamHeader {
+            stream_id: 1,
+            channels: 2,
+        });
+        assert!(ctx.decrypt_packet(&guitar).is_err());
+    }
+
+    #[test]
+    fn test_fec_group_packets_use_distinct_nonces() {
+        use crate::audio::CodecType;
+        use crate::network::fec::{FecScheme, FecStreamEncoder};
+
+        let ctx = EncryptionContext::from_shared_secret(&[0x42u8; 32], true);
+        let mut encoder = FecStreamEncoder::with_scheme(4, FecScheme::ReedSolomon { parity: 3 });
+        let mut nonces = Vec::new();
+        for group in 0..2u32 {
+            let mut fec = Vec::new();
+            for i in 0..4 {
+                let sequence = group * 4 + i;
+                fec = encoder.add_packet_all(sequence, sequence * 100, CodecType::Pcm, &[0; 8]);
+            }
+            assert_eq!(fec.len(), 3);
+            for fec in fec {
+                let packet = Packet::fec(encoder.next_sequence(), fec.to_bytes());
+                let sealed = ctx.encrypt_packet(&packet).unwrap();
+                nonces.push(ctx.derive_packet_nonce(&sealed));
+            }
+        }
+
+        // Every parity packet of every group is sealed under its own nonce
+        for (i, nonce) in nonces.iter().enumerate() {
+            assert!(!nonces[i + 1..].contains(nonce));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_exchange_state_handshake() {
+        let transport = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+
+        let mut alice = KeyExchangeState::new(EncryptionMode::Required);
+        let mut bob = KeyExchangeState::new(EncryptionMode::Required);
+        assert!(alice.needs_key_exchange());
+        assert!(matches!(
+            alice.outbound(),
+            Err(NetworkError::EncryptionError(_))
+        ));
+
+        // Alice -> Bob (no ack), Bob replies with ack, Alice does not reply
+        let hello = alice.key_exchange_packet(false);
+        let payload = KeyExchangePayload::from_bytes(&hello.payload).unwrap();
+        let reply = bob
+            .handle_key_exchange(&transport, &payload)
+            .unwrap()
+            .expect("Bob should reply");
+        let payload = KeyExchangePayload::from_bytes(&reply.payload).unwrap();
+        assert!(payload.ack);
+        assert!(alice
+            .handle_key_exchange(&transport, &payload)
+            .unwrap()
+            .is_none());
+
+        assert!(alice.is_established());
+        assert!(bob.is_established());
+
+        let sealed = alice
+            .outbound()
+            .unwrap()
+            .unwrap()
+            .encrypt(&Packet::audio(0, 0, vec![5; 4]))
+            .unwrap();
+        let opened = bob.open(sealed).unwrap();
+        assert_eq!(opened.payload, vec![5; 4]);
+
+        // Cleartext media is rejected, keep-alives are not
+        assert!(bob.open(Packet::audio(1, 0, vec![5; 4])).is_err());
+        assert!(bob.open(Packet::keep_alive(0)).is_ok());
+
+        // Hellos must be sealed now
+        let mut hello = Packet::keep_alive(1);
+        hello.packet_type = PacketType::Hello;
+        let sealed = alice.outbound().unwrap().unwrap().encrypt(&hello).unwrap();
+        assert!(bob.open(sealed).is_ok());
+        assert!(bob.open(hello).is_err());
+    }
+
+    #[test]
+    fn test_key_exchange_state_preferred_accepts_cleartext() {
+        let state = KeyExchangeState::new(EncryptionMode::Preferred);
+        assert!(state.outbound().unwrap().is_none());
+        assert!(state.open(Packet::audio(1, 0, vec![5; 4])).is_ok());
+    }
+
+    /// Run the exchange from `from` to `to`, then `to`'s reply back
+    fn exchange(
+        transport: &Arc<UdpTransport>,
+        from: &mut KeyExchangeState,
+        to: &mut KeyExchangeState,
+    ) -> Result<(), NetworkError> {
+        let payload = KeyExchangePayload::from_bytes(&from.key_exchange_packet(false).payload)
+            .expect("valid payload");
+        if let Some(reply) = to.handle_key_exchange(transport, &payload)? {
+            let payload = KeyExchangePayload::from_bytes(&reply.payload).expect("valid payload");
+            from.handle_key_exchange(transport, &payload)?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_key_exchange_held_to_signaled_key() {
+        let transport = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+        let bob_key = Arc::new(KeyPair::generate());
+
+        // Someone else's key is rejected and does not stop bob's
+        let mut alice = KeyExchangeState::new(EncryptionMode::Preferred);
+        alice.expect_peer_key(bob_key.public_key_bytes()).unwrap();
+        let mut mallory = KeyExchangeState::new(EncryptionMode::Preferred);
+        assert!(matches!(
+            exchange(&transport, &mut mallory, &mut alice),
+            Err(NetworkError::KeyExchangeFailed(_))
+        ));
+        assert!(!alice.is_established());
+
+        let mut bob = KeyExchangeState::with_keypair(EncryptionMode::Preferred, bob_key.clone());
+        exchange(&transport, &mut bob, &mut alice).unwrap();
+        assert!(alice.is_established());
+        let sealed = bob
+            .outbound()
+            .unwrap()
+            .unwrap()
+            .encrypt(&Packet::audio(0, 0, vec![5; 4]))
+            .unwrap();
+        assert_eq!(alice.open(sealed).unwrap().payload, vec![5; 4]);
+
+        // Learning the published key after deriving from another one is
+        // reported, but the session keys stay
+        let mut alice = KeyExchangeState::new(EncryptionMode::Preferred);
+        let mut mallory = KeyExchangeState::new(EncryptionMode::Preferred);
+        exchange(&transport, &mut mallory, &mut alice).unwrap();
+        assert!(alice.expect_peer_key(bob_key.public_key_bytes()).is_err());
+        assert!(alice.is_established());
+    }
+
+    #[tokio::test]
+    async fn test_changed_peer_key_rejected() {
+        let transport = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+        let mut alice = KeyExchangeState::new(EncryptionMode::Preferred);
+        let mut bob = KeyExchangeState::new(EncryptionMode::Preferred);
+        exchange(&transport, &mut bob, &mut alice).unwrap();
+        // Resent packets of the same exchange are fine
+        exchange(&transport, &mut bob, &mut alice).unwrap();
+
+        // A spoofed key exchange changes nothing
+        let mut mallory = KeyExchangeState::new(EncryptionMode::Preferred);
+        assert!(exchange(&transport, &mut mallory, &mut alice).is_err());
+        let mut renonced =
+            KeyExchangePayload::from_bytes(&bob.key_exchange_packet(true).payload).unwrap();
+        renonced.nonce = Some([0; 16]);
+        assert!(alice.handle_key_exchange(&transport, &renonced).is_err());
+        let sealed = bob
+            .outbound()
+            .unwrap()
+            .unwrap()
+            .encrypt(&Packet::audio(0, 0, vec![5; 4]))
+            .unwrap();
+        assert_eq!(alice.open(sealed).unwrap().payload, vec![5; 4]);
+
+        // Older peers send no nonce and derive unsalted keys, unless they
+        // published a key
+        let mut legacy = KeyExchangePayload::from_bytes(&bob.key_exchange_packet(true).payload)
+            .expect("valid payload");
+        legacy.nonce = None;
+        let mut alice = KeyExchangeState::new(EncryptionMode::Preferred);
+        alice.handle_key_exchange(&transport, &legacy).unwrap();
+        assert!(alice.is_established());
+        let mut alice = KeyExchangeState::new(EncryptionMode::Preferred);
+        alice.expect_peer_key(legacy.public_key).unwrap();
+        assert!(alice.handle_key_exchange(&transport, &legacy).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reused_key_pairs_derive_fresh_session_keys() {
+        let transport = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+        let alice_key = Arc::new(KeyPair::generate());
+        let bob_key = Arc::new(KeyPair::generate());
+        let packet = Packet::audio(0, 0, vec![5; 4]);
+
+        let mut sealed = Vec::new();
+        for _ in 0..2 {
+            let mut alice =
+                KeyExchangeState::with_keypair(EncryptionMode::Preferred, alice_key.clone());
+            let mut bob =
+                KeyExchangeState::with_keypair(EncryptionMode::Preferred, bob_key.clone());
+            exchange(&transport, &mut alice, &mut bob).unwrap();
+            let packet = alice.outbound().unwrap().unwrap().encrypt(&packet).unwrap();
+            assert_eq!(bob.open(packet.clone()).unwrap().payload, vec![5; 4]);
+            sealed.push(packet);
+        }
+        // Same key pairs and sequence, yet a different key and nonce
+        assert_ne!(sealed[0].payload, sealed[1].payload);
+    }
+
     #[test]
     fn test_tampered_ciphertext_fails() {
         let shared_secret = [0x42u8; 32];
@@ -32,6 +32,9 @@ pub enum NetworkError {
     #[error("STUN failed: {0}")]
     StunFailed(String),
 
+    #[error("Relay failed: {0}")]
+    RelayFailed(String),
+
     #[error("Signaling error: {0}")]
     SignalingError(String),
 
@@ -52,4 +55,16 @@ pub enum NetworkError {
 
     #[error("Connection failed: {0}")]
     ConnectionFailed(String),
+
+    #[error("Incompatible peer: {0}")]
+    IncompatiblePeer(String),
+
+    #[error("Unknown audio stream: {0}")]
+    UnknownStream(u8),
+
+    #[error("Invalid capture file: {0}")]
+    InvalidCapture(String),
+
+    #[error("Codec error: {0}")]
+    Codec(#[from] crate::audio::CodecError),
 }
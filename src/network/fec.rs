@@ -1,16 +1,129 @@
 //! Forward Error Correction (FEC) for packet loss recovery
 //!
-//! Implements XOR-based FEC to recover lost packets without retransmission.
-//! For every N data packets, generates 1 FEC packet that can recover any single lost packet.
+//! Recovers lost packets without retransmission. Two schemes share the same
+//! `FecPacket` framing, told apart by a scheme ID:
+//!
+//! - XOR: for every N data packets, 1 FEC packet that can recover any single
+//!   lost packet
+//! - Reed-Solomon: for every N data packets, M FEC packets that together can
+//!   recover any M lost packets, so bursts of consecutive losses survive
+//!
+//! On a live audio stream, `FecStreamEncoder` and `FecStreamDecoder` key each
+//! group by the sequence number of its first audio packet, so the receiver
+//! needs no prior knowledge of the sender's group size or scheme.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::audio::CodecType;
 
-use std::collections::HashMap;
+use super::reed_solomon::{ReedSolomon, MAX_SHARDS};
 
 /// FEC group size (number of data packets per FEC packet)
 pub const FEC_GROUP_SIZE: usize = 4;
 
+/// Number of received audio packets kept for recovery
+const STREAM_HISTORY_SIZE: usize = 64;
+
+/// Maximum FEC groups still waiting for data packets
+const MAX_PENDING_GROUPS: usize = 16;
+
+/// Scheme ID of XOR parity (the former reserved byte, so peers predating
+/// Reed-Solomon send it)
+const SCHEME_XOR: u8 = 0;
+
+/// Scheme ID of Reed-Solomon parity
+const SCHEME_REED_SOLOMON: u8 = 1;
+
+/// How FEC packets are computed from a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FecScheme {
+    /// One XOR packet per group, recovering a single loss
+    #[default]
+    Xor,
+    /// `parity` Reed-Solomon packets per group, recovering any `parity`
+    /// losses in the group
+    ReedSolomon { parity: u8 },
+}
+
+impl FecScheme {
+    /// FEC packets sent per group
+    pub fn parity_count(self) -> usize {
+        match self {
+            FecScheme::Xor => 1,
+            FecScheme::ReedSolomon { parity } => parity as usize,
+        }
+    }
+
+    /// Display name
+    pub fn name(self) -> &'static str {
+        match self {
+            FecScheme::Xor => "XOR",
+            FecScheme::ReedSolomon { .. } => "Reed-Solomon",
+        }
+    }
+}
+
+/// FEC policy for a connection
+///
+/// See ADR-006: FEC trades extra packets per group for loss recovery, and
+/// can be turned off when every millisecond counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FecConfig {
+    /// Send FEC packets for outgoing audio
+    pub enabled: bool,
+    /// Number of audio packets protected by each group's FEC packets
+    pub group_size: usize,
+    /// How FEC packets are computed (peers that cannot decode Reed-Solomon
+    /// get XOR)
+    pub scheme: FecScheme,
+}
+
+impl FecConfig {
+    /// FEC disabled (zero-latency preset)
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    /// XOR FEC enabled with a custom group size
+    pub fn with_group_size(group_size: usize) -> Self {
+        Self {
+            enabled: true,
+            group_size: group_size.clamp(2, u8::MAX as usize),
+            scheme: FecScheme::Xor,
+        }
+    }
+
+    /// Reed-Solomon FEC with `parity` FEC packets per `group_size` audio
+    /// packets
+    pub fn reed_solomon(group_size: usize, parity: u8) -> Self {
+        let group_size = group_size.clamp(2, MAX_SHARDS / 2);
+        Self {
+            enabled: true,
+            group_size,
+            scheme: FecScheme::ReedSolomon {
+                parity: parity.clamp(1, (MAX_SHARDS - group_size).min(u8::MAX as usize) as u8),
+            },
+        }
+    }
+}
+
+impl Default for FecConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            group_size: FEC_GROUP_SIZE,
+            scheme: FecScheme::Xor,
+        }
+    }
+}
+
 /// FEC packet generator
 pub struct FecEncoder {
     group_size: usize,
+    scheme: FecScheme,
     current_group: Vec<Vec<u8>>,
     group_sequence: u32,
 }
@@ -21,10 +134,16 @@ impl FecEncoder {
         Self::with_group_size(FEC_GROUP_SIZE)
     }
 
-    /// Create a new FEC encoder with custom group size
+    /// Create a new XOR FEC encoder with custom group size
     pub fn with_group_size(group_size: usize) -> Self {
+        Self::with_scheme(group_size, FecScheme::Xor)
+    }
+
+    /// Create a new FEC encoder with custom group size and scheme
+    pub fn with_scheme(group_size: usize, scheme: FecScheme) -> Self {
         Self {
             group_size,
+            scheme,
             current_group: Vec::with_capacity(group_size),
             group_sequence: 0,
         }
@@ -32,21 +151,64 @@ impl FecEncoder {
 
     /// Add a data packet and optionally return an FEC packet
     /// Returns (should_send_fec, fec_data) when the group is complete
+    ///
+    /// Reed-Solomon groups have several FEC packets; this returns the first
+    /// one only, `add_packet_all` returns them all.
     pub fn add_packet(&mut self, data: &[u8]) -> Option<FecPacket> {
+        self.add_packet_all(data).into_iter().next()
+    }
+
+    /// Add a data packet
+    /// Returns all of the group's FEC packets when the group is complete
+    pub fn add_packet_all(&mut self, data: &[u8]) -> Vec<FecPacket> {
         self.current_group.push(data.to_vec());
 
         if self.current_group.len() >= self.group_size {
             let fec = self.generate_fec();
             self.current_group.clear();
             self.group_sequence += 1;
-            Some(fec)
+            fec
         } else {
-            None
+            Vec::new()
         }
     }
 
-    /// Generate FEC packet for the current group
-    fn generate_fec(&self) -> FecPacket {
+    /// Generate FEC packets for the current group
+    fn generate_fec(&self) -> Vec<FecPacket> {
+        let lengths: Vec<u16> = self.current_group.iter().map(|p| p.len() as u16).collect();
+        let packet = |scheme, parity_index, fec_data| FecPacket {
+            group_sequence: self.group_sequence,
+            packet_count: self.current_group.len() as u8,
+            scheme,
+            parity_index,
+            packet_lengths: lengths.clone(),
+            fec_data,
+        };
+
+        // Groups too large for the requested parity get as much as fits
+        let code = match self.scheme {
+            FecScheme::Xor => None,
+            FecScheme::ReedSolomon { parity } => {
+                let fits = MAX_SHARDS.saturating_sub(self.current_group.len());
+                ReedSolomon::new(self.current_group.len(), (parity as usize).min(fits))
+            }
+        };
+        let Some(code) = code else {
+            return vec![packet(FecScheme::Xor, 0, self.xor_parity())];
+        };
+
+        let scheme = FecScheme::ReedSolomon {
+            parity: code.parity_shards() as u8,
+        };
+        code.encode(&self.current_group)
+            .into_iter()
+            .enumerate()
+            .map(|(index, fec_data)| packet(scheme, index as u8, fec_data))
+            .collect()
+    }
+
+    /// XOR of all packets in the current group
+    fn xor_parity(&self) -> Vec<u8> {
         // Find the maximum packet length
         let max_len = self
             .current_group
@@ -55,23 +217,13 @@ impl FecEncoder {
             .max()
             .unwrap_or(0);
 
-        // XOR all packets together
         let mut fec_data = vec![0u8; max_len];
-        let mut lengths = Vec::with_capacity(self.group_size);
-
         for packet in &self.current_group {
-            lengths.push(packet.len() as u16);
             for (i, &byte) in packet.iter().enumerate() {
                 fec_data[i] ^= byte;
             }
         }
-
-        FecPacket {
-            group_sequence: self.group_sequence,
-            packet_count: self.current_group.len() as u8,
-            packet_lengths: lengths,
-            fec_data,
-        }
+        fec_data
     }
 
     /// Get current group sequence
@@ -93,22 +245,36 @@ pub struct FecPacket {
     pub group_sequence: u32,
     /// Number of data packets in this group
     pub packet_count: u8,
+    /// How `fec_data` was computed
+    pub scheme: FecScheme,
+    /// Index of this packet among the group's FEC packets (0 for XOR)
+    pub parity_index: u8,
     /// Original lengths of each packet
     pub packet_lengths: Vec<u16>,
-    /// XOR'd FEC data
+    /// FEC data (XOR or Reed-Solomon parity)
     pub fec_data: Vec<u8>,
 }
 
 impl FecPacket {
     /// Serialize FEC packet to bytes
+    ///
+    /// Layout: group sequence (4), packet count (1), scheme ID (1), then for
+    /// Reed-Solomon parity index (1) and parity count (1), packet lengths
+    /// (2 each) and the FEC data. XOR packets keep the original layout.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(8 + self.packet_lengths.len() * 2 + self.fec_data.len());
 
         bytes.extend_from_slice(&self.group_sequence.to_be_bytes());
         bytes.push(self.packet_count);
 
-        // Reserved byte for alignment
-        bytes.push(0);
+        match self.scheme {
+            FecScheme::Xor => bytes.push(SCHEME_XOR),
+            FecScheme::ReedSolomon { parity } => {
+                bytes.push(SCHEME_REED_SOLOMON);
+                bytes.push(self.parity_index);
+                bytes.push(parity);
+            }
+        }
 
         // Packet lengths
         for &len in &self.packet_lengths {
@@ -122,6 +288,9 @@ impl FecPacket {
     }
 
     /// Deserialize FEC packet from bytes
+    ///
+    /// Returns None for unknown schemes and inconsistent Reed-Solomon
+    /// parameters.
     pub fn from_bytes(data: &[u8]) -> Option<Self> {
         if data.len() < 6 {
             return None;
@@ -129,25 +298,40 @@ impl FecPacket {
 
         let group_sequence = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
         let packet_count = data[4];
-        // data[5] is reserved
+
+        let (scheme, parity_index, header_len) = match data[5] {
+            SCHEME_XOR => (FecScheme::Xor, 0, 6),
+            SCHEME_REED_SOLOMON if data.len() >= 8 => {
+                let (parity_index, parity) = (data[6], data[7]);
+                if parity_index >= parity
+                    || ReedSolomon::new(packet_count as usize, parity as usize).is_none()
+                {
+                    return None;
+                }
+                (FecScheme::ReedSolomon { parity }, parity_index, 8)
+            }
+            _ => return None,
+        };
 
         let lengths_size = packet_count as usize * 2;
-        if data.len() < 6 + lengths_size {
+        if data.len() < header_len + lengths_size {
             return None;
         }
 
         let mut packet_lengths = Vec::with_capacity(packet_count as usize);
         for i in 0..packet_count as usize {
-            let offset = 6 + i * 2;
+            let offset = header_len + i * 2;
             let len = u16::from_be_bytes([data[offset], data[offset + 1]]);
             packet_lengths.push(len);
         }
 
-        let fec_data = data[6 + lengths_size..].to_vec();
+        let fec_data = data[header_len + lengths_size..].to_vec();
 
         Some(Self {
             group_sequence,
             packet_count,
+            scheme,
+            parity_index,
             packet_lengths,
             fec_data,
         })
@@ -164,9 +348,11 @@ pub struct FecDecoder {
     max_groups: usize,
 }
 
+#[derive(Default)]
 struct GroupState {
     packets: HashMap<usize, Vec<u8>>,
-    fec: Option<FecPacket>,
+    /// FEC packets by parity index
+    fec: BTreeMap<u8, FecPacket>,
     recovered: bool,
 }
 
@@ -187,102 +373,134 @@ impl FecDecoder {
 
     /// Add a received data packet
     /// Returns recovered packet if possible
+    ///
+    /// A Reed-Solomon group can recover several packets at once; this returns
+    /// the first one only, `add_packet_all` returns them all.
     pub fn add_packet(
         &mut self,
         group_sequence: u32,
         packet_index: usize,
         data: &[u8],
     ) -> Option<RecoveredPacket> {
-        let group = self
-            .groups
-            .entry(group_sequence)
-            .or_insert_with(|| GroupState {
-                packets: HashMap::new(),
-                fec: None,
-                recovered: false,
-            });
+        self.add_packet_all(group_sequence, packet_index, data)
+            .into_iter()
+            .next()
+    }
+
+    /// Add a received data packet
+    /// Returns the packets that can now be recovered
+    pub fn add_packet_all(
+        &mut self,
+        group_sequence: u32,
+        packet_index: usize,
+        data: &[u8],
+    ) -> Vec<RecoveredPacket> {
+        let group = self.groups.entry(group_sequence).or_default();
 
         group.packets.insert(packet_index, data.to_vec());
 
-        // Try to recover if we have FEC and are missing exactly one packet
+        // Try to recover if we have enough FEC for the missing packets
         self.try_recover(group_sequence)
     }
 
     /// Add a received FEC packet
     /// Returns recovered packet if possible
+    ///
+    /// A Reed-Solomon group can recover several packets at once; this returns
+    /// the first one only, `add_fec_all` returns them all.
     pub fn add_fec(&mut self, fec: FecPacket) -> Option<RecoveredPacket> {
+        self.add_fec_all(fec).into_iter().next()
+    }
+
+    /// Add a received FEC packet
+    /// Returns the packets that can now be recovered
+    pub fn add_fec_all(&mut self, fec: FecPacket) -> Vec<RecoveredPacket> {
         let group_sequence = fec.group_sequence;
 
-        let group = self
-            .groups
-            .entry(group_sequence)
-            .or_insert_with(|| GroupState {
-                packets: HashMap::new(),
-                fec: None,
-                recovered: false,
-            });
+        let group = self.groups.entry(group_sequence).or_default();
 
-        group.fec = Some(fec);
+        // All FEC packets of a group must agree on how it was protected
+        let consistent = group.fec.values().next().is_none_or(|first| {
+            first.scheme == fec.scheme && first.packet_count == fec.packet_count
+        });
+        if !consistent {
+            return Vec::new();
+        }
+        group.fec.insert(fec.parity_index, fec);
+
+        // Groups without losses never recover, so bound memory here too
+        self.cleanup();
 
         // Try to recover
         self.try_recover(group_sequence)
     }
 
-    /// Try to recover a lost packet in a group
-    fn try_recover(&mut self, group_sequence: u32) -> Option<RecoveredPacket> {
-        let group = self.groups.get_mut(&group_sequence)?;
+    /// Try to recover lost packets in a group
+    fn try_recover(&mut self, group_sequence: u32) -> Vec<RecoveredPacket> {
+        let Some(group) = self.groups.get_mut(&group_sequence) else {
+            return Vec::new();
+        };
 
         // Already recovered or no FEC
-        if group.recovered || group.fec.is_none() {
-            return None;
+        let Some(first) = group.fec.values().next() else {
+            return Vec::new();
+        };
+        if group.recovered {
+            return Vec::new();
         }
 
-        let fec = group.fec.as_ref().unwrap();
-        let expected_count = fec.packet_count as usize;
-
-        // Check if we're missing exactly one packet
-        if group.packets.len() + 1 != expected_count {
-            return None;
-        }
-
-        // Find the missing packet index
-        let mut missing_index = None;
-        for i in 0..expected_count {
-            if !group.packets.contains_key(&i) {
-                missing_index = Some(i);
-                break;
-            }
+        let expected_count = first.packet_count as usize;
+        let missing: Vec<usize> = (0..expected_count)
+            .filter(|i| !group.packets.contains_key(i))
+            .collect();
+        if missing.is_empty() || missing.len() > group.fec.len() {
+            return Vec::new();
         }
 
-        let missing_index = missing_index?;
-
-        // Recover the missing packet by XORing all received packets with FEC data
-        let recovered_len = fec.packet_lengths.get(missing_index).copied()? as usize;
-        let mut recovered = fec.fec_data.clone();
-
-        for (&idx, packet) in &group.packets {
-            if idx != missing_index {
-                for (i, &byte) in packet.iter().enumerate() {
-                    if i < recovered.len() {
-                        recovered[i] ^= byte;
-                    }
+        let recovered = match first.scheme {
+            FecScheme::Xor => vec![(missing[0], xor_recover(first, &group.packets, missing[0]))],
+            FecScheme::ReedSolomon { parity } => {
+                let Some(code) = ReedSolomon::new(expected_count, parity as usize) else {
+                    return Vec::new();
+                };
+                let mut data: Vec<Option<Vec<u8>>> = (0..expected_count)
+                    .map(|i| group.packets.get(&i).cloned())
+                    .collect();
+                let parity: Vec<(usize, &[u8])> = group
+                    .fec
+                    .values()
+                    .map(|fec| (fec.parity_index as usize, fec.fec_data.as_slice()))
+                    .collect();
+                if !code.reconstruct(&mut data, &parity) {
+                    return Vec::new();
                 }
+                missing
+                    .iter()
+                    .filter_map(|&index| Some((index, data[index].take()?)))
+                    .collect()
             }
-        }
-
-        // Truncate to original length
-        recovered.truncate(recovered_len);
+        };
+
+        // Truncate to original lengths
+        let lengths = &first.packet_lengths;
+        let recovered: Vec<RecoveredPacket> = recovered
+            .into_iter()
+            .filter_map(|(packet_index, mut data)| {
+                data.truncate(lengths.get(packet_index).copied()? as usize);
+                Some(RecoveredPacket {
+                    group_sequence,
+                    packet_index,
+                    data,
+                })
+            })
+            .collect();
 
         group.recovered = true;
 
         // Cleanup old groups
         self.cleanup();
 
-        Some(RecoveredPacket {
-            group_sequence,
-            packet_index: missing_index,
-            data: recovered,
-        })
+        recovered
     }
 
     /// Remove old groups to prevent memory growth
@@ -296,6 +514,23 @@ impl FecDecoder {
     }
 }
 
+/// Recover the one missing packet by XORing all received packets with the
+/// FEC data
+fn xor_recover(fec: &FecPacket, packets: &HashMap<usize, Vec<u8>>, missing: usize) -> Vec<u8> {
+    let mut recovered = fec.fec_data.clone();
+
+    for (&idx, packet) in packets {
+        if idx != missing {
+            for (i, &byte) in packet.iter().enumerate() {
+                if i < recovered.len() {
+                    recovered[i] ^= byte;
+                }
+            }
+        }
+    }
+    recovered
+}
+
 impl Default for FecDecoder {
     fn default() -> Self {
         Self::new()
@@ -313,10 +548,322 @@ pub struct RecoveredPacket {
     pub data: Vec<u8>,
 }
 
+/// An audio packet rebuilt from FEC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveredAudio {
+    /// Audio sequence number of the lost packet
+    pub sequence: u32,
+    /// Timestamp of the lost packet
+    pub timestamp: u32,
+    /// Codec of the payload
+    pub codec: CodecType,
+    /// Audio payload of the lost packet
+    pub payload: Vec<u8>,
+}
+
+/// Protected unit for an audio packet: timestamp, codec, then payload
+fn protected_audio(timestamp: u32, codec: CodecType, payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(5 + payload.len());
+    data.extend_from_slice(&timestamp.to_be_bytes());
+    data.push(codec.to_flags());
+    data.extend_from_slice(payload);
+    data
+}
+
+/// FEC generator for a live audio stream
+///
+/// The emitted `FecPacket::group_sequence` is the audio sequence number of
+/// the first packet in the group. FEC packets are sent with header sequences
+/// of their own from `next_sequence`: a group may have several parity
+/// packets, and each must be sealed under its own nonce.
+pub struct FecStreamEncoder {
+    encoder: FecEncoder,
+    group_size: usize,
+    scheme: FecScheme,
+    /// Sequence of the first packet in the current group
+    base_sequence: u32,
+    /// Packets added to the current group
+    pending: usize,
+    /// Header sequence of the next FEC packet sent
+    fec_sequence: u32,
+}
+
+impl FecStreamEncoder {
+    /// Create a new XOR stream encoder
+    pub fn new(group_size: usize) -> Self {
+        Self::with_scheme(group_size, FecScheme::Xor)
+    }
+
+    /// Create a new stream encoder with the given scheme
+    pub fn with_scheme(group_size: usize, scheme: FecScheme) -> Self {
+        Self {
+            encoder: FecEncoder::with_scheme(group_size, scheme),
+            group_size,
+            scheme,
+            base_sequence: 0,
+            pending: 0,
+            fec_sequence: 0,
+        }
+    }
+
+    /// Header sequence for the next FEC packet sent
+    ///
+    /// Consecutive across groups, group size and scheme changes, so no two
+    /// FEC packets of the stream share a nonce.
+    pub fn next_sequence(&mut self) -> u32 {
+        let sequence = self.fec_sequence;
+        self.fec_sequence = sequence.wrapping_add(1);
+        sequence
+    }
+
+    /// Change the group size, starting a new group if it differs
+    pub fn set_group_size(&mut self, group_size: usize) {
+        if group_size != self.group_size {
+            self.group_size = group_size;
+            self.restart();
+        }
+    }
+
+    /// Change the scheme, starting a new group if it differs
+    pub fn set_scheme(&mut self, scheme: FecScheme) {
+        if scheme != self.scheme {
+            self.scheme = scheme;
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.encoder = FecEncoder::with_scheme(self.group_size, self.scheme);
+        self.pending = 0;
+    }
+
+    /// Add an outgoing audio packet and return an FEC packet when a group completes
+    ///
+    /// Sequences must be consecutive; a gap starts a new group. Reed-Solomon
+    /// groups have several FEC packets; this returns the first one only,
+    /// `add_packet_all` returns them all.
+    pub fn add_packet(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: &[u8],
+    ) -> Option<FecPacket> {
+        self.add_packet_all(sequence, timestamp, codec, payload)
+            .into_iter()
+            .next()
+    }
+
+    /// Add an outgoing audio packet and return the FEC packets when a group
+    /// completes
+    ///
+    /// Sequences must be consecutive; a gap starts a new group.
+    pub fn add_packet_all(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: &[u8],
+    ) -> Vec<FecPacket> {
+        let expected = self.base_sequence.wrapping_add(self.pending as u32);
+        if self.pending > 0 && sequence != expected {
+            self.restart();
+        }
+        if self.pending == 0 {
+            self.base_sequence = sequence;
+        }
+        self.pending += 1;
+
+        let mut fec = self
+            .encoder
+            .add_packet_all(&protected_audio(timestamp, codec, payload));
+        if fec.is_empty() {
+            return fec;
+        }
+        for packet in &mut fec {
+            packet.group_sequence = self.base_sequence;
+        }
+        self.pending = 0;
+        fec
+    }
+}
+
+/// FEC recovery for a live audio stream
+pub struct FecStreamDecoder {
+    decoder: FecDecoder,
+    /// Recently received (or recovered) packets by sequence
+    history: BTreeMap<u32, Vec<u8>>,
+    /// FEC groups seen so far: (first sequence, packet count)
+    groups: VecDeque<(u32, usize)>,
+    packets_recovered: u64,
+}
+
+impl FecStreamDecoder {
+    /// Create a new stream decoder
+    pub fn new() -> Self {
+        Self {
+            decoder: FecDecoder::new(),
+            history: BTreeMap::new(),
+            groups: VecDeque::with_capacity(MAX_PENDING_GROUPS),
+            packets_recovered: 0,
+        }
+    }
+
+    /// Add a received audio packet
+    ///
+    /// Returns a lost packet of the same group if it can now be recovered.
+    /// Reed-Solomon groups can recover several; this returns the first one
+    /// only, `add_packet_all` returns them all.
+    pub fn add_packet(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: &[u8],
+    ) -> Option<RecoveredAudio> {
+        self.add_packet_all(sequence, timestamp, codec, payload)
+            .into_iter()
+            .next()
+    }
+
+    /// Add a received audio packet
+    ///
+    /// Returns lost packets of the same group that can now be recovered.
+    pub fn add_packet_all(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: &[u8],
+    ) -> Vec<RecoveredAudio> {
+        if self.history.contains_key(&sequence) {
+            return Vec::new();
+        }
+        let data = protected_audio(timestamp, codec, payload);
+
+        let group = self.groups.iter().copied().find(|&(base, count)| {
+            let index = sequence.wrapping_sub(base) as usize;
+            index < count
+        });
+        let recovered = group.map_or_else(Vec::new, |(base, _)| {
+            let index = sequence.wrapping_sub(base) as usize;
+            self.decoder.add_packet_all(base, index, &data)
+        });
+
+        self.remember(sequence, data);
+        self.finish_recovery(recovered)
+    }
+
+    /// Add a received FEC packet
+    ///
+    /// Returns the lost packet of its group if it can be recovered.
+    /// Reed-Solomon groups can recover several; this returns the first one
+    /// only, `add_fec_all` returns them all.
+    pub fn add_fec(&mut self, fec: FecPacket) -> Option<RecoveredAudio> {
+        self.add_fec_all(fec).into_iter().next()
+    }
+
+    /// Add a received FEC packet
+    ///
+    /// Returns the lost packets of its group that can be recovered.
+    pub fn add_fec_all(&mut self, fec: FecPacket) -> Vec<RecoveredAudio> {
+        let base = fec.group_sequence;
+        let count = fec.packet_count as usize;
+        if count == 0 {
+            return Vec::new();
+        }
+        // Further FEC packets of a known group go straight to the decoder
+        if self.groups.iter().any(|&(b, _)| b == base) {
+            let recovered = self.decoder.add_fec_all(fec);
+            return self.finish_recovery(recovered);
+        }
+
+        if self.groups.len() >= MAX_PENDING_GROUPS {
+            self.groups.pop_front();
+        }
+        self.groups.push_back((base, count));
+
+        let mut recovered = self.decoder.add_fec_all(fec);
+        for index in 0..count {
+            if !recovered.is_empty() {
+                break;
+            }
+            let sequence = base.wrapping_add(index as u32);
+            if let Some(data) = self.history.get(&sequence) {
+                recovered = self.decoder.add_packet_all(base, index, data);
+            }
+        }
+
+        self.finish_recovery(recovered)
+    }
+
+    /// Check if a packet has already been received or recovered
+    pub fn contains(&self, sequence: u32) -> bool {
+        self.history.contains_key(&sequence)
+    }
+
+    /// Number of packets recovered so far
+    pub fn packets_recovered(&self) -> u64 {
+        self.packets_recovered
+    }
+
+    fn remember(&mut self, sequence: u32, data: Vec<u8>) {
+        self.history.insert(sequence, data);
+        while self.history.len() > STREAM_HISTORY_SIZE {
+            self.history.pop_first();
+        }
+    }
+
+    /// Turn recovered packets we have not seen into audio, in sequence order
+    fn finish_recovery(&mut self, mut recovered: Vec<RecoveredPacket>) -> Vec<RecoveredAudio> {
+        recovered.sort_by_key(|r| r.packet_index);
+        recovered
+            .into_iter()
+            .filter_map(|r| self.finish_one(r))
+            .collect()
+    }
+
+    fn finish_one(&mut self, recovered: RecoveredPacket) -> Option<RecoveredAudio> {
+        let sequence = recovered
+            .group_sequence
+            .wrapping_add(recovered.packet_index as u32);
+        if recovered.data.len() < 5 || self.history.contains_key(&sequence) {
+            return None;
+        }
+
+        let timestamp = u32::from_be_bytes([
+            recovered.data[0],
+            recovered.data[1],
+            recovered.data[2],
+            recovered.data[3],
+        ]);
+        let codec = CodecType::from_flags(recovered.data[4]);
+        let payload = recovered.data[5..].to_vec();
+        self.remember(sequence, recovered.data);
+        self.packets_recovered += 1;
+
+        Some(RecoveredAudio {
+            sequence,
+            timestamp,
+            codec,
+            payload,
+        })
+    }
+}
+
+impl Default for FecStreamDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const PCM: CodecType = CodecType::Pcm;
+    const OPUS: CodecType = CodecType::Opus;
+
     #[test]
     fn test_fec_encoder_basic() {
         let mut encoder = FecEncoder::with_group_size(4);
@@ -421,4 +968,175 @@ mod tests {
         assert_eq!(recovered.packet_index, 1);
         assert_eq!(recovered.data, packets[1]);
     }
+
+    #[test]
+    fn test_fec_stream_recovers_lost_audio() {
+        let mut encoder = FecStreamEncoder::new(3);
+        let mut decoder = FecStreamDecoder::new();
+
+        // Groups start at the first sequence, not at a multiple of the group size
+        assert!(encoder.add_packet(10, 4800, PCM, &[1, 2, 3]).is_none());
+        assert!(encoder.add_packet(11, 4900, OPUS, &[4, 5]).is_none());
+        let fec = encoder.add_packet(12, 5000, PCM, &[6, 7, 8, 9]).unwrap();
+        assert_eq!(fec.group_sequence, 10);
+
+        // Lose the middle packet
+        assert!(decoder.add_packet(10, 4800, PCM, &[1, 2, 3]).is_none());
+        assert!(decoder.add_packet(12, 5000, PCM, &[6, 7, 8, 9]).is_none());
+
+        let recovered = decoder.add_fec(fec).unwrap();
+        assert_eq!(
+            recovered,
+            RecoveredAudio {
+                sequence: 11,
+                timestamp: 4900,
+                codec: OPUS,
+                payload: vec![4, 5],
+            }
+        );
+        assert_eq!(decoder.packets_recovered(), 1);
+
+        // The original arriving late is recognized as already delivered
+        assert!(decoder.contains(11));
+    }
+
+    #[test]
+    fn test_fec_stream_recovers_when_fec_arrives_first() {
+        let mut encoder = FecStreamEncoder::new(2);
+        let mut decoder = FecStreamDecoder::new();
+
+        encoder.add_packet(0, 0, PCM, &[1, 1]);
+        let fec = encoder.add_packet(1, 100, PCM, &[2, 2]).unwrap();
+
+        // Packet 0 is lost, FEC overtakes packet 1
+        assert!(decoder.add_fec(fec).is_none());
+        let recovered = decoder.add_packet(1, 100, PCM, &[2, 2]).unwrap();
+        assert_eq!(recovered.sequence, 0);
+        assert_eq!(recovered.payload, vec![1, 1]);
+    }
+
+    #[test]
+    fn test_fec_stream_encoder_restarts_group_on_gap() {
+        let mut encoder = FecStreamEncoder::new(2);
+
+        encoder.add_packet(0, 0, PCM, &[1]);
+        assert!(encoder.add_packet(5, 0, PCM, &[2]).is_none());
+        let fec = encoder.add_packet(6, 0, PCM, &[3]).unwrap();
+        assert_eq!(fec.group_sequence, 5);
+    }
+
+    #[test]
+    fn test_reed_solomon_packet_roundtrip() {
+        let mut encoder = FecEncoder::with_scheme(3, FecScheme::ReedSolomon { parity: 2 });
+        encoder.add_packet(&[1, 2]);
+        encoder.add_packet(&[3]);
+        let fec = encoder.add_packet_all(&[4, 5, 6]);
+        assert_eq!(fec.len(), 2);
+
+        let parsed = FecPacket::from_bytes(&fec[1].to_bytes()).unwrap();
+        assert_eq!(parsed.scheme, FecScheme::ReedSolomon { parity: 2 });
+        assert_eq!(parsed.parity_index, 1);
+        assert_eq!(parsed.packet_lengths, vec![2, 1, 3]);
+        assert_eq!(parsed.fec_data, fec[1].fec_data);
+
+        // XOR keeps the layout of peers predating the scheme ID
+        let mut xor = FecEncoder::with_group_size(2);
+        xor.add_packet(&[1]);
+        let bytes = xor.add_packet(&[2]).unwrap().to_bytes();
+        assert_eq!(bytes[5], 0);
+        assert_eq!(bytes.len(), 6 + 2 * 2 + 1);
+
+        // Unknown schemes are ignored
+        let mut unknown = fec[0].to_bytes();
+        unknown[5] = 9;
+        assert!(FecPacket::from_bytes(&unknown).is_none());
+    }
+
+    #[test]
+    fn test_reed_solomon_recovers_burst() {
+        let mut encoder = FecEncoder::with_scheme(4, FecScheme::ReedSolomon { parity: 2 });
+        let packets = vec![vec![1, 2, 3, 4], vec![5, 6], vec![7, 8, 9], vec![10]];
+        let mut fec = Vec::new();
+        for packet in &packets {
+            fec = encoder.add_packet_all(packet);
+        }
+
+        // Two consecutive packets lost: beyond XOR
+        let mut decoder = FecDecoder::new();
+        decoder.add_packet(0, 0, &packets[0]);
+        decoder.add_packet(0, 3, &packets[3]);
+        let second = fec.pop().unwrap();
+        assert!(decoder.add_fec_all(fec.pop().unwrap()).is_empty());
+
+        let mut recovered = decoder.add_fec_all(second);
+        recovered.sort_by_key(|r| r.packet_index);
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].packet_index, 1);
+        assert_eq!(recovered[0].data, packets[1]);
+        assert_eq!(recovered[1].packet_index, 2);
+        assert_eq!(recovered[1].data, packets[2]);
+    }
+
+    #[test]
+    fn test_fec_stream_recovers_burst_with_reed_solomon() {
+        let mut encoder = FecStreamEncoder::with_scheme(4, FecScheme::ReedSolomon { parity: 2 });
+        let mut decoder = FecStreamDecoder::new();
+
+        let mut fec = Vec::new();
+        for i in 0..4u32 {
+            fec = encoder.add_packet_all(20 + i, i * 100, PCM, &[i as u8; 3]);
+        }
+        assert_eq!(fec.len(), 2);
+        // Both carry the group in their payload; header sequences differ
+        assert!(fec.iter().all(|f| f.group_sequence == 20));
+        assert_ne!(encoder.next_sequence(), encoder.next_sequence());
+
+        // Packets 21 and 22 lost in a burst, the first FEC packet overtakes 23
+        assert!(decoder.add_packet(20, 0, PCM, &[0; 3]).is_none());
+        assert!(decoder.add_fec(fec[0].clone()).is_none());
+        assert!(decoder.add_packet(23, 300, PCM, &[3; 3]).is_none());
+
+        let recovered = decoder.add_fec_all(fec[1].clone());
+        let sequences: Vec<u32> = recovered.iter().map(|r| r.sequence).collect();
+        assert_eq!(sequences, vec![21, 22]);
+        assert_eq!(recovered[1].timestamp, 200);
+        assert_eq!(recovered[1].payload, vec![2; 3]);
+        assert_eq!(decoder.packets_recovered(), 2);
+    }
+
+    #[test]
+    fn test_fec_stream_encoder_restarts_group_on_scheme_change() {
+        let mut encoder = FecStreamEncoder::new(2);
+
+        encoder.add_packet(0, 0, PCM, &[1]);
+        encoder.set_scheme(FecScheme::ReedSolomon { parity: 3 });
+        assert!(encoder.add_packet(1, 0, PCM, &[2]).is_none());
+        let fec = encoder.add_packet_all(2, 0, PCM, &[3]);
+        assert_eq!(fec.len(), 3);
+        assert_eq!(fec[0].group_sequence, 1);
+    }
+
+    #[test]
+    fn test_fec_stream_encoder_header_sequences_unique() {
+        let mut encoder = FecStreamEncoder::with_scheme(2, FecScheme::ReedSolomon { parity: 3 });
+        let mut sequences = Vec::new();
+        for i in 0..6u32 {
+            for _ in encoder.add_packet_all(i, 0, PCM, &[i as u8]) {
+                sequences.push(encoder.next_sequence());
+            }
+            if i == 3 {
+                encoder.set_group_size(1);
+            }
+        }
+        assert_eq!(sequences, (0..sequences.len() as u32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_reed_solomon_config_limits() {
+        let config = FecConfig::reed_solomon(4, 0);
+        assert_eq!(config.scheme, FecScheme::ReedSolomon { parity: 1 });
+        let config = FecConfig::reed_solomon(1000, 255);
+        assert_eq!(config.group_size, 128);
+        assert_eq!(config.scheme.parity_count(), 128);
+    }
 }
@@ -0,0 +1,913 @@
+//! ICE-style connectivity checks (RFC 8445, simplified)
+//!
+//! Each agent pairs its local candidates with the peer's, orders the pairs by
+//! priority and sends STUN binding requests over the media socket. A request
+//! from the peer triggers a check in the reverse direction, so both sides
+//! converge on the same working pairs. The controlling agent nominates the
+//! best working pair with USE-CANDIDATE and re-nominates when a better pair
+//! starts working later (e.g. a LAN candidate signaled mid-session). Role
+//! conflicts (both sides controlling or both controlled) are resolved with the
+//! tie-breakers. Once a pair is selected, consent to keep sending is refreshed
+//! with periodic checks (RFC 7675).
+//!
+//! The agent does no I/O: feed it received binding messages and poll it for
+//! messages to send.
+//!
+//! Simplifications: one component, no frozen pairs, and a single local base
+//! (the transport socket), so host and server reflexive local candidates
+//! collapse into one direct path. A relay allocation adds a relayed path.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use super::signaling::{AddressCandidate, CandidateType};
+use super::stun::{BindingMessage, StunClass, ROLE_CONFLICT};
+use super::transport::{StunDatagram, UdpTransport};
+
+/// Type preference of peer reflexive candidates (RFC 8445)
+const PEER_REFLEXIVE_TYPE_PREF: u32 = 110;
+
+/// ICE agent role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IceRole {
+    /// Nominates the pair to use
+    Controlling,
+    /// Follows the peer's nomination
+    Controlled,
+}
+
+/// Connectivity state of an ICE agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IceState {
+    /// Checks are running, no pair selected yet
+    Checking,
+    /// A nominated pair is in use
+    Connected,
+    /// Every pair failed
+    Failed,
+    /// Consent on the selected pair expired; checks restart
+    Disconnected,
+}
+
+/// Timing of connectivity checks
+#[derive(Debug, Clone)]
+pub struct IceConfig {
+    /// Pacing between new checks (Ta)
+    pub check_interval: Duration,
+    /// Retransmission timeout of an unanswered check
+    pub retransmit_timeout: Duration,
+    /// Requests sent for one check before the pair fails
+    pub max_attempts: u32,
+    /// How long the controlling agent waits for a higher priority pair
+    /// before nominating a working one
+    pub nomination_delay: Duration,
+    /// Interval of consent freshness checks on the selected pair
+    pub consent_interval: Duration,
+    /// Consent expires after this long without a response
+    pub consent_timeout: Duration,
+}
+
+impl Default for IceConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_millis(20),
+            retransmit_timeout: Duration::from_millis(100),
+            max_attempts: 7,
+            nomination_delay: Duration::from_millis(200),
+            consent_interval: Duration::from_secs(5),
+            consent_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A binding message to send
+#[derive(Debug, Clone)]
+pub(crate) struct Transmit {
+    pub to: SocketAddr,
+    /// Send through our relay allocation
+    pub relayed: bool,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PairState {
+    Waiting,
+    InProgress,
+    Succeeded,
+    Failed,
+}
+
+/// Outstanding connectivity check
+struct Check {
+    request: BindingMessage,
+    sent_at: Instant,
+    attempts: u32,
+}
+
+struct CandidatePair {
+    remote: AddressCandidate,
+    /// Local side is our relay allocation
+    relayed: bool,
+    local_priority: u32,
+    state: PairState,
+    check: Option<Check>,
+    /// Controlled side: nominate as soon as our check succeeds
+    nominate_on_success: bool,
+    succeeded_at: Option<Instant>,
+}
+
+/// Consent freshness on the selected pair
+struct Consent {
+    last_sent: Instant,
+    last_response: Instant,
+    transaction_id: Option<[u8; 12]>,
+}
+
+/// Connectivity check state machine for one peer
+pub(crate) struct IceAgent {
+    config: IceConfig,
+    role: IceRole,
+    tiebreaker: u64,
+    /// (local, remote) username fragments
+    usernames: Option<(String, String)>,
+    local_candidates: Vec<AddressCandidate>,
+    /// Pairs are only appended, so indexes stay valid
+    pairs: Vec<CandidatePair>,
+    triggered: VecDeque<usize>,
+    last_check: Option<Instant>,
+    selected: Option<usize>,
+    nominating: Option<usize>,
+    consent: Option<Consent>,
+    state: IceState,
+}
+
+impl IceAgent {
+    /// Create a controlling agent for the given local candidates
+    ///
+    /// Both peers may start as controlling; the role conflict is resolved on
+    /// the first checks.
+    pub fn new(local_candidates: Vec<AddressCandidate>, config: IceConfig) -> Self {
+        Self {
+            config,
+            role: IceRole::Controlling,
+            tiebreaker: rand::random(),
+            usernames: None,
+            local_candidates,
+            pairs: Vec::new(),
+            triggered: VecDeque::new(),
+            last_check: None,
+            selected: None,
+            nominating: None,
+            consent: None,
+            state: IceState::Checking,
+        }
+    }
+
+    /// Identify both ends in USERNAME; requests for other usernames are ignored
+    pub fn set_usernames(&mut self, local: String, remote: String) {
+        self.usernames = Some((local, remote));
+    }
+
+    /// Current role
+    pub fn role(&self) -> IceRole {
+        self.role
+    }
+
+    /// Current connectivity state
+    pub fn state(&self) -> IceState {
+        self.state
+    }
+
+    /// Selected remote address and whether it is reached through the relay
+    pub fn selected(&self) -> Option<(SocketAddr, bool)> {
+        self.selected
+            .map(|i| (self.pairs[i].remote.address, self.pairs[i].relayed))
+    }
+
+    /// Check if a response belongs to one of our checks
+    pub fn owns_transaction(&self, transaction_id: &[u8; 12]) -> bool {
+        self.pairs.iter().any(|p| {
+            p.check
+                .as_ref()
+                .is_some_and(|c| &c.request.transaction_id == transaction_id)
+        }) || self
+            .consent
+            .as_ref()
+            .is_some_and(|c| c.transaction_id.as_ref() == Some(transaction_id))
+    }
+
+    /// Check if a remote address is one of the peer's candidates
+    pub fn has_remote(&self, addr: SocketAddr) -> bool {
+        self.pairs.iter().any(|p| p.remote.address == addr)
+    }
+
+    /// Add the peer's candidates (known ones are skipped)
+    pub fn add_remote_candidates(&mut self, candidates: &[AddressCandidate]) {
+        for candidate in candidates {
+            for relayed in [false, true] {
+                self.add_pair(candidate.clone(), relayed);
+            }
+        }
+    }
+
+    /// Pair a remote candidate with our direct or relayed path
+    fn add_pair(&mut self, remote: AddressCandidate, relayed: bool) -> Option<usize> {
+        if let Some(i) = self.find_pair(remote.address, relayed) {
+            return Some(i);
+        }
+        let local_priority = self.local_priority(remote.address, relayed)?;
+        debug!(
+            "ICE pair {} ({:?}{})",
+            remote.address,
+            remote.candidate_type,
+            if relayed { ", relayed" } else { "" }
+        );
+        self.pairs.push(CandidatePair {
+            remote,
+            relayed,
+            local_priority,
+            state: PairState::Waiting,
+            check: None,
+            nominate_on_success: false,
+            succeeded_at: None,
+        });
+        if self.state == IceState::Failed {
+            self.state = IceState::Checking;
+        }
+        Some(self.pairs.len() - 1)
+    }
+
+    fn find_pair(&self, addr: SocketAddr, relayed: bool) -> Option<usize> {
+        self.pairs
+            .iter()
+            .position(|p| p.remote.address == addr && p.relayed == relayed)
+    }
+
+    /// Priority of our best local candidate for a path to `remote`
+    fn local_priority(&self, remote: SocketAddr, relayed: bool) -> Option<u32> {
+        self.local_candidates
+            .iter()
+            .filter(|c| (c.candidate_type == CandidateType::Relay) == relayed)
+            .filter(|c| c.address.is_ipv4() == remote.is_ipv4())
+            .map(|c| c.priority)
+            .max()
+    }
+
+    /// Pair priority (RFC 8445 section 6.1.2.3)
+    fn pair_priority(&self, pair: &CandidatePair) -> u64 {
+        let (g, d) = match self.role {
+            IceRole::Controlling => (pair.local_priority, pair.remote.priority),
+            IceRole::Controlled => (pair.remote.priority, pair.local_priority),
+        };
+        let (g, d) = (g as u64, d as u64);
+        (g.min(d) << 32) + 2 * g.max(d) + u64::from(g > d)
+    }
+
+    /// Run timers and return the messages to send
+    pub fn poll(&mut self, now: Instant) -> Vec<Transmit> {
+        let mut out = Vec::new();
+        self.retransmit(now, &mut out);
+        self.nominate(now, &mut out);
+        self.next_check(now, &mut out);
+        self.refresh_consent(now, &mut out);
+        self.update_state();
+        out
+    }
+
+    fn retransmit(&mut self, now: Instant, out: &mut Vec<Transmit>) {
+        for i in 0..self.pairs.len() {
+            let pair = &mut self.pairs[i];
+            let Some(check) = pair.check.as_mut() else {
+                continue;
+            };
+            if now < check.sent_at + self.config.retransmit_timeout {
+                continue;
+            }
+            if check.attempts >= self.config.max_attempts {
+                debug!("ICE check to {} failed", pair.remote.address);
+                pair.check = None;
+                pair.state = PairState::Failed;
+                if self.nominating == Some(i) {
+                    self.nominating = None;
+                }
+                continue;
+            }
+            check.attempts += 1;
+            check.sent_at = now;
+            out.push(Transmit {
+                to: pair.remote.address,
+                relayed: pair.relayed,
+                data: check.request.to_bytes(),
+            });
+        }
+    }
+
+    /// Controlling side: nominate the best working pair once no better pair
+    /// is pending (or the nomination delay has passed)
+    fn nominate(&mut self, now: Instant, out: &mut Vec<Transmit>) {
+        if self.role != IceRole::Controlling || self.nominating.is_some() {
+            return;
+        }
+        let Some(best) = self.best_pair(|p| p.state == PairState::Succeeded) else {
+            return;
+        };
+        if self.selected == Some(best) {
+            return;
+        }
+        let best_priority = self.pair_priority(&self.pairs[best]);
+        if let Some(selected) = self.selected {
+            if self.pair_priority(&self.pairs[selected]) >= best_priority {
+                return;
+            }
+        }
+        let better_pending = self.pairs.iter().any(|p| {
+            matches!(p.state, PairState::Waiting | PairState::InProgress)
+                && self.pair_priority(p) > best_priority
+        });
+        let waited = self.pairs[best]
+            .succeeded_at
+            .is_some_and(|t| now >= t + self.config.nomination_delay);
+        if better_pending && !waited {
+            return;
+        }
+
+        debug!("ICE nominating {}", self.pairs[best].remote.address);
+        self.nominating = Some(best);
+        out.push(self.send_check(best, true, now));
+    }
+
+    /// Send the next triggered or ordinary check, paced by the check interval
+    fn next_check(&mut self, now: Instant, out: &mut Vec<Transmit>) {
+        if self
+            .last_check
+            .is_some_and(|t| now < t + self.config.check_interval)
+        {
+            return;
+        }
+        let next = loop {
+            match self.triggered.pop_front() {
+                Some(i) if self.pairs[i].check.is_none() => break Some(i),
+                Some(_) => continue,
+                None => {
+                    break self.best_pair(|p| p.state == PairState::Waiting && p.check.is_none());
+                }
+            }
+        };
+        if let Some(i) = next {
+            self.last_check = Some(now);
+            out.push(self.send_check(i, false, now));
+        }
+    }
+
+    fn refresh_consent(&mut self, now: Instant, out: &mut Vec<Transmit>) {
+        let (Some(selected), Some(consent)) = (self.selected, self.consent.as_ref()) else {
+            return;
+        };
+        if now >= consent.last_response + self.config.consent_timeout {
+            let pair = &self.pairs[selected];
+            warn!(
+                "ICE consent to send to {} expired, restarting checks",
+                pair.remote.address
+            );
+            self.selected = None;
+            self.nominating = None;
+            self.consent = None;
+            self.triggered.clear();
+            for pair in &mut self.pairs {
+                pair.state = PairState::Waiting;
+                pair.check = None;
+                pair.nominate_on_success = false;
+                pair.succeeded_at = None;
+            }
+            self.state = IceState::Disconnected;
+            return;
+        }
+        if now < consent.last_sent + self.config.consent_interval {
+            return;
+        }
+        let request = self.request(selected, false);
+        if let Some(consent) = self.consent.as_mut() {
+            consent.last_sent = now;
+            consent.transaction_id = Some(request.transaction_id);
+        }
+        let pair = &self.pairs[selected];
+        out.push(Transmit {
+            to: pair.remote.address,
+            relayed: pair.relayed,
+            data: request.to_bytes(),
+        });
+    }
+
+    fn update_state(&mut self) {
+        if self.selected.is_some() {
+            self.state = IceState::Connected;
+        } else if !self.pairs.is_empty() && self.pairs.iter().all(|p| p.state == PairState::Failed)
+        {
+            self.state = IceState::Failed;
+        } else if self.state != IceState::Disconnected {
+            self.state = IceState::Checking;
+        }
+    }
+
+    fn best_pair(&self, filter: impl Fn(&CandidatePair) -> bool) -> Option<usize> {
+        (0..self.pairs.len())
+            .filter(|&i| filter(&self.pairs[i]))
+            .max_by_key(|&i| self.pair_priority(&self.pairs[i]))
+    }
+
+    /// Build a binding request for a pair
+    fn request(&self, index: usize, use_candidate: bool) -> BindingMessage {
+        let pair = &self.pairs[index];
+        let mut request = BindingMessage::request();
+        request.username = self
+            .usernames
+            .as_ref()
+            .map(|(local, remote)| format!("{}:{}", remote, local));
+        // Priority the peer assigns if it learns us as peer reflexive
+        request.priority =
+            Some((PEER_REFLEXIVE_TYPE_PREF << 24) | (pair.local_priority & 0x00FF_FFFF));
+        request.use_candidate = use_candidate;
+        match self.role {
+            IceRole::Controlling => request.controlling = Some(self.tiebreaker),
+            IceRole::Controlled => request.controlled = Some(self.tiebreaker),
+        }
+        request
+    }
+
+    fn send_check(&mut self, index: usize, use_candidate: bool, now: Instant) -> Transmit {
+        let request = self.request(index, use_candidate);
+        let data = request.to_bytes();
+        let pair = &mut self.pairs[index];
+        pair.state = PairState::InProgress;
+        pair.check = Some(Check {
+            request,
+            sent_at: now,
+            attempts: 1,
+        });
+        Transmit {
+            to: pair.remote.address,
+            relayed: pair.relayed,
+            data,
+        }
+    }
+
+    /// Handle a binding message from the peer
+    pub fn handle_message(
+        &mut self,
+        message: &BindingMessage,
+        from: SocketAddr,
+        relayed: bool,
+        now: Instant,
+    ) -> Vec<Transmit> {
+        let out = match message.class {
+            StunClass::Request => self.handle_request(message, from, relayed, now),
+            StunClass::Success => {
+                self.handle_success(message, now);
+                Vec::new()
+            }
+            StunClass::Error(code) => {
+                self.handle_error(message, code);
+                Vec::new()
+            }
+        };
+        self.update_state();
+        out
+    }
+
+    fn handle_request(
+        &mut self,
+        request: &BindingMessage,
+        from: SocketAddr,
+        relayed: bool,
+        now: Instant,
+    ) -> Vec<Transmit> {
+        if let (Some((local, remote)), Some(username)) = (&self.usernames, &request.username) {
+            if *username != format!("{}:{}", local, remote) {
+                debug!("Ignoring ICE check for {} from {}", username, from);
+                return Vec::new();
+            }
+        }
+
+        let respond = |class| Transmit {
+            to: from,
+            relayed,
+            data: {
+                let mut response = BindingMessage::response(request, class);
+                if class == StunClass::Success {
+                    response.mapped_address = Some(from);
+                }
+                response.to_bytes()
+            },
+        };
+
+        // Role conflict (RFC 8445 section 7.3.1.1)
+        match (self.role, request.controlling, request.controlled) {
+            (IceRole::Controlling, Some(theirs), _) => {
+                if self.tiebreaker >= theirs {
+                    return vec![respond(StunClass::Error(ROLE_CONFLICT))];
+                }
+                self.switch_role(IceRole::Controlled);
+            }
+            (IceRole::Controlled, _, Some(theirs)) => {
+                if self.tiebreaker < theirs {
+                    return vec![respond(StunClass::Error(ROLE_CONFLICT))];
+                }
+                self.switch_role(IceRole::Controlling);
+            }
+            _ => {}
+        }
+
+        let index = self.find_pair(from, relayed).or_else(|| {
+            let priority = request.priority.unwrap_or(PEER_REFLEXIVE_TYPE_PREF << 24);
+            debug!("ICE learned peer reflexive candidate {}", from);
+            self.add_pair(AddressCandidate::peer_reflexive(from, priority), relayed)
+        });
+
+        if let Some(index) = index {
+            let pair = &mut self.pairs[index];
+            // Triggered check in the reverse direction
+            if pair.state != PairState::Succeeded
+                && pair.check.is_none()
+                && !self.triggered.contains(&index)
+            {
+                pair.state = PairState::Waiting;
+                self.triggered.push_back(index);
+            }
+            if self.role == IceRole::Controlled && request.use_candidate {
+                if pair.state == PairState::Succeeded {
+                    self.select(index, now);
+                } else {
+                    pair.nominate_on_success = true;
+                }
+            }
+        }
+
+        vec![respond(StunClass::Success)]
+    }
+
+    fn handle_success(&mut self, response: &BindingMessage, now: Instant) {
+        if let Some(consent) = self.consent.as_mut() {
+            if consent.transaction_id == Some(response.transaction_id) {
+                consent.last_response = now;
+                return;
+            }
+        }
+        let Some(index) = self.pair_for_transaction(&response.transaction_id) else {
+            return;
+        };
+        let pair = &mut self.pairs[index];
+        let check = pair.check.take().expect("pair has the transaction");
+        pair.state = PairState::Succeeded;
+        pair.succeeded_at = Some(now);
+        debug!("ICE check to {} succeeded", pair.remote.address);
+
+        let nominated = if check.request.use_candidate {
+            self.role == IceRole::Controlling
+        } else {
+            pair.nominate_on_success && self.role == IceRole::Controlled
+        };
+        if check.request.use_candidate {
+            self.nominating = None;
+        }
+        if nominated {
+            self.select(index, now);
+        }
+        // Any response on the selected path proves consent
+        if self.selected == Some(index) {
+            if let Some(consent) = self.consent.as_mut() {
+                consent.last_response = now;
+            }
+        }
+    }
+
+    fn handle_error(&mut self, response: &BindingMessage, code: u16) {
+        let Some(index) = self.pair_for_transaction(&response.transaction_id) else {
+            return;
+        };
+        let pair = &mut self.pairs[index];
+        let check = pair.check.take().expect("pair has the transaction");
+        if self.nominating == Some(index) {
+            self.nominating = None;
+        }
+        if code != ROLE_CONFLICT {
+            debug!(
+                "ICE check to {} failed: error {}",
+                pair.remote.address, code
+            );
+            pair.state = PairState::Failed;
+            return;
+        }
+        // Take the role opposite to the one we claimed, then retry
+        let role = if check.request.controlling.is_some() {
+            IceRole::Controlled
+        } else {
+            IceRole::Controlling
+        };
+        pair.state = PairState::Waiting;
+        self.triggered.push_back(index);
+        self.switch_role(role);
+    }
+
+    fn pair_for_transaction(&self, transaction_id: &[u8; 12]) -> Option<usize> {
+        self.pairs.iter().position(|p| {
+            p.check
+                .as_ref()
+                .is_some_and(|c| &c.request.transaction_id == transaction_id)
+        })
+    }
+
+    fn switch_role(&mut self, role: IceRole) {
+        if self.role != role {
+            debug!("ICE role conflict: switching to {:?}", role);
+            self.role = role;
+            self.nominating = None;
+        }
+    }
+
+    fn select(&mut self, index: usize, now: Instant) {
+        if self.selected == Some(index) {
+            return;
+        }
+        let pair = &self.pairs[index];
+        info!(
+            "ICE selected {} ({:?}{})",
+            pair.remote.address,
+            pair.remote.candidate_type,
+            if pair.relayed { ", relayed" } else { "" }
+        );
+        self.selected = Some(index);
+        self.consent = Some(Consent {
+            last_sent: self.consent.as_ref().map_or(now, |c| c.last_sent),
+            last_response: now,
+            transaction_id: None,
+        });
+        self.state = IceState::Connected;
+    }
+}
+
+/// Local candidates for an agent on `transport`
+///
+/// Adds a host candidate for the socket and a relay candidate for its relay
+/// allocation when the signaled candidates lack them.
+pub(crate) fn local_candidates(
+    transport: &UdpTransport,
+    signaled: &[AddressCandidate],
+) -> Vec<AddressCandidate> {
+    let mut candidates = signaled.to_vec();
+    if !candidates
+        .iter()
+        .any(|c| c.candidate_type != CandidateType::Relay)
+    {
+        candidates.push(AddressCandidate::host(transport.local_addr()));
+    }
+    if let Some(relayed_addr) = transport.relayed_addr() {
+        if !candidates
+            .iter()
+            .any(|c| c.candidate_type == CandidateType::Relay)
+        {
+            candidates.push(AddressCandidate::relay(relayed_addr));
+        }
+    }
+    candidates
+}
+
+/// Parse a STUN datagram and pass it to the agent
+pub(crate) fn handle_datagram(
+    agent: &mut IceAgent,
+    datagram: &StunDatagram,
+    now: Instant,
+) -> Vec<Transmit> {
+    match BindingMessage::from_bytes(&datagram.data) {
+        Some(message) => agent.handle_message(&message, datagram.from, datagram.relayed, now),
+        None => Vec::new(),
+    }
+}
+
+/// Send an agent's messages
+pub(crate) async fn send_transmits(transport: &UdpTransport, transmits: Vec<Transmit>) {
+    for transmit in transmits {
+        if let Err(e) = transport
+            .send_raw(&transmit.data, transmit.to, transmit.relayed)
+            .await
+        {
+            debug!("Failed to send ICE check to {}: {}", transmit.to, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory network between two agents
+    struct Link {
+        agents: [IceAgent; 2],
+        /// Addresses each agent answers on (first one is its source address)
+        addrs: [Vec<SocketAddr>; 2],
+        now: Instant,
+        connected: bool,
+    }
+
+    impl Link {
+        fn new(agents: [IceAgent; 2], addrs: [Vec<SocketAddr>; 2]) -> Self {
+            Self {
+                agents,
+                addrs,
+                now: Instant::now(),
+                connected: true,
+            }
+        }
+
+        fn deliver(&mut self, from: usize, transmits: Vec<Transmit>) {
+            let mut queue: VecDeque<(usize, Transmit)> =
+                transmits.into_iter().map(|t| (from, t)).collect();
+            while let Some((sender, transmit)) = queue.pop_front() {
+                let receiver = 1 - sender;
+                if !self.connected || !self.addrs[receiver].contains(&transmit.to) {
+                    continue;
+                }
+                let message = BindingMessage::from_bytes(&transmit.data).unwrap();
+                let source = self.addrs[sender][0];
+                let replies =
+                    self.agents[receiver].handle_message(&message, source, false, self.now);
+                queue.extend(replies.into_iter().map(|t| (receiver, t)));
+            }
+        }
+
+        fn run(&mut self, duration: Duration) {
+            let end = self.now + duration;
+            while self.now < end {
+                for i in 0..2 {
+                    let out = self.agents[i].poll(self.now);
+                    self.deliver(i, out);
+                }
+                self.now += Duration::from_millis(10);
+            }
+        }
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn agent(local: &str) -> IceAgent {
+        IceAgent::new(
+            vec![AddressCandidate::host(addr(local))],
+            IceConfig::default(),
+        )
+    }
+
+    #[test]
+    fn test_pair_priority_prefers_host() {
+        let mut agent = agent("192.168.1.10:5000");
+        agent.add_remote_candidates(&[
+            AddressCandidate::relay(addr("203.0.113.9:40000")),
+            AddressCandidate::server_reflexive(addr("203.0.113.5:5000")),
+            AddressCandidate::host(addr("192.168.1.20:5000")),
+            // No local IPv6 candidate: not paired
+            AddressCandidate::host(addr("[2001:db8::1]:5000")),
+        ]);
+        assert_eq!(agent.pairs.len(), 3);
+
+        let best = agent.best_pair(|_| true).unwrap();
+        assert_eq!(agent.pairs[best].remote.address, addr("192.168.1.20:5000"));
+        let priorities: Vec<u64> = agent.pairs.iter().map(|p| agent.pair_priority(p)).collect();
+        assert!(priorities[2] > priorities[1] && priorities[1] > priorities[0]);
+    }
+
+    #[test]
+    fn test_both_controlling_converge_on_working_pair() {
+        let a_addr = addr("10.0.0.1:5000");
+        let b_addr = addr("10.0.0.2:5000");
+        let mut a = agent("10.0.0.1:5000");
+        let mut b = agent("10.0.0.2:5000");
+        a.add_remote_candidates(&[
+            AddressCandidate::host(addr("10.0.0.99:5000")),
+            AddressCandidate::server_reflexive(b_addr),
+        ]);
+        b.add_remote_candidates(&[
+            AddressCandidate::host(addr("10.0.0.98:5000")),
+            AddressCandidate::server_reflexive(a_addr),
+        ]);
+
+        let mut link = Link::new([a, b], [vec![a_addr], vec![b_addr]]);
+        link.run(Duration::from_secs(2));
+
+        let [a, b] = &link.agents;
+        assert_eq!(a.state(), IceState::Connected);
+        assert_eq!(b.state(), IceState::Connected);
+        assert_eq!(a.selected(), Some((b_addr, false)));
+        assert_eq!(b.selected(), Some((a_addr, false)));
+        assert_ne!(a.role(), b.role(), "Role conflict must be resolved");
+    }
+
+    #[test]
+    fn test_peer_reflexive_candidate_learned() {
+        let a_addr = addr("10.0.0.1:5000");
+        let b_addr = addr("10.0.0.2:5000");
+        let mut a = agent("10.0.0.1:5000");
+        // B has not received A's candidates
+        let b = agent("10.0.0.2:5000");
+        a.add_remote_candidates(&[AddressCandidate::host(b_addr)]);
+
+        let mut link = Link::new([a, b], [vec![a_addr], vec![b_addr]]);
+        link.run(Duration::from_secs(2));
+
+        let [a, b] = &link.agents;
+        assert_eq!(a.selected(), Some((b_addr, false)));
+        assert_eq!(b.selected(), Some((a_addr, false)));
+        assert_eq!(
+            b.pairs[0].remote.candidate_type,
+            CandidateType::PeerReflexive
+        );
+    }
+
+    #[test]
+    fn test_renomination_when_better_pair_appears() {
+        let a_addr = addr("10.0.0.1:5000");
+        let b_public = addr("203.0.113.2:6000");
+        let b_lan = addr("10.0.0.2:5000");
+        let mut a = agent("10.0.0.1:5000");
+        let mut b = agent("10.0.0.2:5000");
+        // A wins the role conflict and nominates
+        a.tiebreaker = u64::MAX;
+        b.tiebreaker = 0;
+        a.add_remote_candidates(&[AddressCandidate::server_reflexive(b_public)]);
+        b.add_remote_candidates(&[AddressCandidate::host(a_addr)]);
+
+        // B is reachable on both addresses and seen from its public one
+        let mut link = Link::new([a, b], [vec![a_addr], vec![b_public, b_lan]]);
+        link.run(Duration::from_secs(1));
+        assert_eq!(link.agents[0].role(), IceRole::Controlling);
+        assert_eq!(link.agents[0].selected(), Some((b_public, false)));
+
+        // B's LAN address is signaled mid-session
+        link.agents[0].add_remote_candidates(&[AddressCandidate::host(b_lan)]);
+        link.run(Duration::from_secs(1));
+        assert_eq!(link.agents[0].selected(), Some((b_lan, false)));
+        assert_eq!(link.agents[1].selected(), Some((a_addr, false)));
+    }
+
+    #[test]
+    fn test_checks_fail_without_peer() {
+        let mut a = agent("10.0.0.1:5000");
+        a.add_remote_candidates(&[AddressCandidate::host(addr("10.0.0.2:5000"))]);
+        let b = agent("10.0.0.2:5000");
+
+        let mut link = Link::new([a, b], [vec![addr("10.0.0.1:5000")], vec![]]);
+        link.run(Duration::from_secs(2));
+        assert_eq!(link.agents[0].state(), IceState::Failed);
+        assert_eq!(link.agents[0].selected(), None);
+    }
+
+    #[test]
+    fn test_consent_expires_when_peer_goes_silent() {
+        let config = IceConfig {
+            consent_interval: Duration::from_millis(100),
+            consent_timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let a_addr = addr("10.0.0.1:5000");
+        let b_addr = addr("10.0.0.2:5000");
+        let mut a = IceAgent::new(vec![AddressCandidate::host(a_addr)], config.clone());
+        let mut b = IceAgent::new(vec![AddressCandidate::host(b_addr)], config);
+        a.add_remote_candidates(&[AddressCandidate::host(b_addr)]);
+        b.add_remote_candidates(&[AddressCandidate::host(a_addr)]);
+
+        let mut link = Link::new([a, b], [vec![a_addr], vec![b_addr]]);
+        link.run(Duration::from_secs(1));
+        assert_eq!(link.agents[0].state(), IceState::Connected);
+
+        // Consent keeps the pair alive while the peer answers
+        link.run(Duration::from_secs(1));
+        assert_eq!(link.agents[0].state(), IceState::Connected);
+
+        link.connected = false;
+        link.run(Duration::from_millis(700));
+        assert_eq!(link.agents[0].state(), IceState::Disconnected);
+        assert_eq!(link.agents[0].selected(), None);
+
+        // Checks restart and recover once the peer is back
+        link.connected = true;
+        link.run(Duration::from_secs(2));
+        assert_eq!(link.agents[0].state(), IceState::Connected);
+    }
+
+    #[test]
+    fn test_mismatched_username_ignored() {
+        let mut a = agent("10.0.0.1:5000");
+        a.set_usernames("alice".to_string(), "bob".to_string());
+
+        let mut request = BindingMessage::request();
+        request.username = Some("alice:mallory".to_string());
+        let out = a.handle_message(&request, addr("10.0.0.3:5000"), false, Instant::now());
+        assert!(out.is_empty());
+
+        request.username = Some("alice:bob".to_string());
+        let out = a.handle_message(&request, addr("10.0.0.2:5000"), false, Instant::now());
+        assert_eq!(out.len(), 1);
+    }
+}
@@ -0,0 +1,569 @@
+//! Network impairment simulator
+//!
+//! `ImpairmentProxy` sits between two UDP endpoints and forwards their
+//! datagrams through one `Impairment` per direction, which drops, delays,
+//! reorders, duplicates and rate-limits them the way a bad network would (in
+//! the spirit of Linux netem). Pointing two peers at the proxy instead of at
+//! each other exercises the jitter buffer, FEC and PLC on a single machine.
+//!
+//! An `Impairment` draws from an RNG seeded by `ImpairmentConfig::seed`, so the
+//! same sequence of datagrams always meets the same fate. Through the proxy the
+//! order in which datagrams arrive still depends on the OS scheduler; only the
+//! model itself is exactly reproducible.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+use tracing::{info, trace, warn};
+
+use super::error::NetworkError;
+
+/// Packet loss model
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LossModel {
+    /// No loss
+    #[default]
+    None,
+    /// Independent loss with a fixed probability (0.0 - 1.0)
+    Random { probability: f64 },
+    /// Two-state Gilbert-Elliott model for bursty loss
+    ///
+    /// Before each packet the channel moves from the good to the bad state
+    /// with probability `to_bad`, and back with probability `to_good`. A packet
+    /// is then lost with `loss_good` or `loss_bad` depending on the state.
+    /// With `loss_bad` at 1.0 the mean burst length is `1 / to_good` packets.
+    GilbertElliott {
+        to_bad: f64,
+        to_good: f64,
+        loss_good: f64,
+        loss_bad: f64,
+    },
+}
+
+/// Impairment applied to one direction of traffic
+#[derive(Debug, Clone)]
+pub struct ImpairmentConfig {
+    /// Packet loss model
+    pub loss: LossModel,
+    /// Fixed one-way delay
+    pub delay: Duration,
+    /// Maximum extra delay, drawn uniformly per packet
+    pub jitter: Duration,
+    /// Probability that a packet is held back by `reorder_delay` (0.0 - 1.0)
+    pub reorder_probability: f64,
+    /// Extra delay for held back packets, letting later packets overtake them
+    pub reorder_delay: Duration,
+    /// Probability that a packet is delivered twice (0.0 - 1.0)
+    pub duplicate_probability: f64,
+    /// Link rate in bits per second (None for unlimited)
+    pub bandwidth_bps: Option<u64>,
+    /// Longest a packet queues for a rate-limited link before being dropped
+    pub max_queue_delay: Duration,
+    /// RNG seed
+    pub seed: u64,
+}
+
+impl Default for ImpairmentConfig {
+    fn default() -> Self {
+        Self {
+            loss: LossModel::None,
+            delay: Duration::ZERO,
+            jitter: Duration::ZERO,
+            reorder_probability: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            duplicate_probability: 0.0,
+            bandwidth_bps: None,
+            max_queue_delay: Duration::from_millis(200),
+            seed: 0,
+        }
+    }
+}
+
+/// Counters for one direction of impaired traffic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImpairmentStats {
+    /// Datagrams offered to the link
+    pub packets_in: u64,
+    /// Datagrams delivered (duplicates included)
+    pub packets_out: u64,
+    /// Datagrams dropped by the loss model
+    pub packets_lost: u64,
+    /// Datagrams dropped because the rate-limited link queue was full
+    pub queue_drops: u64,
+    /// Datagrams delivered twice
+    pub packets_duplicated: u64,
+    /// Datagrams held back so later ones overtake them
+    pub packets_reordered: u64,
+}
+
+/// Deterministic model of an impaired one-way link
+pub struct Impairment {
+    config: ImpairmentConfig,
+    rng: StdRng,
+    /// Gilbert-Elliott channel is in the bad state
+    bad_state: bool,
+    /// When the rate-limited link finishes sending what is queued
+    link_free_at: Duration,
+    stats: ImpairmentStats,
+}
+
+impl Impairment {
+    /// Create a link model
+    pub fn new(config: ImpairmentConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            bad_state: false,
+            link_free_at: Duration::ZERO,
+            stats: ImpairmentStats::default(),
+        }
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &ImpairmentConfig {
+        &self.config
+    }
+
+    /// Get the counters
+    pub fn stats(&self) -> ImpairmentStats {
+        self.stats
+    }
+
+    /// Decide the fate of a `len` byte datagram offered at `now`
+    ///
+    /// `now` is measured from the start of the simulation and must not go
+    /// backwards. Returns the delivery time of each copy: none if the datagram
+    /// is dropped, two if it is duplicated.
+    pub fn process(&mut self, now: Duration, len: usize) -> Vec<Duration> {
+        self.stats.packets_in += 1;
+        if self.is_lost() {
+            self.stats.packets_lost += 1;
+            return Vec::new();
+        }
+
+        let copies = if self.chance(self.config.duplicate_probability) {
+            self.stats.packets_duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        let mut deliveries = Vec::with_capacity(copies);
+        for _ in 0..copies {
+            let Some(departure) = self.transmit(now, len) else {
+                self.stats.queue_drops += 1;
+                continue;
+            };
+            let mut at = departure + self.config.delay + self.jitter();
+            if self.chance(self.config.reorder_probability) {
+                self.stats.packets_reordered += 1;
+                at += self.config.reorder_delay;
+            }
+            deliveries.push(at);
+        }
+
+        self.stats.packets_out += deliveries.len() as u64;
+        deliveries
+    }
+
+    fn is_lost(&mut self) -> bool {
+        match self.config.loss {
+            LossModel::None => false,
+            LossModel::Random { probability } => self.chance(probability),
+            LossModel::GilbertElliott {
+                to_bad,
+                to_good,
+                loss_good,
+                loss_bad,
+            } => {
+                let transition = if self.bad_state { to_good } else { to_bad };
+                if self.chance(transition) {
+                    self.bad_state = !self.bad_state;
+                }
+                self.chance(if self.bad_state { loss_bad } else { loss_good })
+            }
+        }
+    }
+
+    /// Queue a datagram on the link (returns when it has been sent)
+    ///
+    /// Returns `None` if it would wait longer than `max_queue_delay`.
+    fn transmit(&mut self, now: Duration, len: usize) -> Option<Duration> {
+        let Some(bps) = self.config.bandwidth_bps else {
+            return Some(now);
+        };
+
+        let start = self.link_free_at.max(now);
+        if start - now > self.config.max_queue_delay {
+            return None;
+        }
+        let bits = len as u64 * 8;
+        self.link_free_at = start + Duration::from_nanos(bits * 1_000_000_000 / bps.max(1));
+        Some(self.link_free_at)
+    }
+
+    fn jitter(&mut self) -> Duration {
+        let max = self.config.jitter.as_nanos() as u64;
+        if max == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.rng.gen_range(0..=max))
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.gen::<f64>() < probability
+    }
+}
+
+/// Direction of traffic through an `ImpairmentProxy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    /// From peer A to peer B
+    AToB,
+    /// From peer B to peer A
+    BToA,
+}
+
+/// Datagram waiting for its delivery time: (due, arrival order, direction, data)
+type Scheduled = Reverse<(Duration, u64, Direction, Vec<u8>)>;
+
+/// UDP proxy that impairs traffic between two peers
+///
+/// Peer A sends to `a_side_addr` to reach peer B, and peer B sends to
+/// `b_side_addr` to reach peer A. Each peer sees the other as the proxy
+/// address it sends to. Datagrams from any other sender are ignored.
+pub struct ImpairmentProxy {
+    a_socket: UdpSocket,
+    b_socket: UdpSocket,
+    peer_a: SocketAddr,
+    peer_b: SocketAddr,
+    a_to_b: Mutex<Impairment>,
+    b_to_a: Mutex<Impairment>,
+}
+
+impl ImpairmentProxy {
+    /// Bind the proxy's two sockets
+    ///
+    /// Both directions get `config`; B to A draws from `seed + 1` so the two
+    /// directions are not impaired in lockstep.
+    pub async fn bind(
+        a_side: &str,
+        b_side: &str,
+        peer_a: SocketAddr,
+        peer_b: SocketAddr,
+        config: ImpairmentConfig,
+    ) -> Result<Self, NetworkError> {
+        let a_socket = UdpSocket::bind(a_side.parse::<SocketAddr>()?).await?;
+        let b_socket = UdpSocket::bind(b_side.parse::<SocketAddr>()?).await?;
+        info!(
+            "Impairment proxy {} <-> {} via {} / {}",
+            peer_a,
+            peer_b,
+            a_socket.local_addr()?,
+            b_socket.local_addr()?
+        );
+
+        let reverse = ImpairmentConfig {
+            seed: config.seed.wrapping_add(1),
+            ..config.clone()
+        };
+        Ok(Self {
+            a_socket,
+            b_socket,
+            peer_a,
+            peer_b,
+            a_to_b: Mutex::new(Impairment::new(config)),
+            b_to_a: Mutex::new(Impairment::new(reverse)),
+        })
+    }
+
+    /// Get the address peer A sends to in order to reach peer B
+    pub fn a_side_addr(&self) -> SocketAddr {
+        self.a_socket
+            .local_addr()
+            .expect("bound socket has a local address")
+    }
+
+    /// Get the address peer B sends to in order to reach peer A
+    pub fn b_side_addr(&self) -> SocketAddr {
+        self.b_socket
+            .local_addr()
+            .expect("bound socket has a local address")
+    }
+
+    /// Replace the impairment for one direction (takes effect immediately)
+    pub fn set_config(&self, direction: Direction, config: ImpairmentConfig) {
+        *self.impairment(direction).lock() = Impairment::new(config);
+    }
+
+    /// Get the counters for one direction
+    pub fn stats(&self, direction: Direction) -> ImpairmentStats {
+        self.impairment(direction).lock().stats()
+    }
+
+    /// Forward datagrams until a socket fails
+    pub async fn run(&self) -> Result<(), NetworkError> {
+        let start = Instant::now();
+        let mut queue: BinaryHeap<Scheduled> = BinaryHeap::new();
+        let mut arrivals = 0u64;
+        let mut buf_a = vec![0u8; 2048];
+        let mut buf_b = vec![0u8; 2048];
+
+        loop {
+            let next_due = queue.peek().map(|Reverse((at, ..))| start + *at);
+
+            tokio::select! {
+                result = self.a_socket.recv_from(&mut buf_a) => {
+                    let (len, from) = result?;
+                    if from == self.peer_a {
+                        let now = start.elapsed();
+                        for at in self.a_to_b.lock().process(now, len) {
+                            queue.push(Reverse((at, arrivals, Direction::AToB, buf_a[..len].to_vec())));
+                            arrivals += 1;
+                        }
+                    }
+                }
+                result = self.b_socket.recv_from(&mut buf_b) => {
+                    let (len, from) = result?;
+                    if from == self.peer_b {
+                        let now = start.elapsed();
+                        for at in self.b_to_a.lock().process(now, len) {
+                            queue.push(Reverse((at, arrivals, Direction::BToA, buf_b[..len].to_vec())));
+                            arrivals += 1;
+                        }
+                    }
+                }
+                _ = tokio::time::sleep_until(next_due.unwrap_or(start)), if next_due.is_some() => {
+                    let now = start.elapsed();
+                    while queue.peek().is_some_and(|Reverse((at, ..))| *at <= now) {
+                        let Some(Reverse((_, _, direction, data))) = queue.pop() else {
+                            break;
+                        };
+                        self.deliver(direction, &data).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, direction: Direction, data: &[u8]) {
+        // Each peer hears the other from the proxy address it sends to
+        let (socket, to) = match direction {
+            Direction::AToB => (&self.b_socket, self.peer_b),
+            Direction::BToA => (&self.a_socket, self.peer_a),
+        };
+        match socket.send_to(data, to).await {
+            Ok(_) => trace!("Delivered {} bytes to {}", data.len(), to),
+            Err(e) => warn!("Failed to deliver to {}: {}", to, e),
+        }
+    }
+
+    fn impairment(&self, direction: Direction) -> &Mutex<Impairment> {
+        match direction {
+            Direction::AToB => &self.a_to_b,
+            Direction::BToA => &self.b_to_a,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(3);
+
+    /// Offer `count` datagrams one frame apart and collect their fates
+    fn run_model(config: ImpairmentConfig, count: u32) -> (Vec<Vec<Duration>>, ImpairmentStats) {
+        let mut impairment = Impairment::new(config);
+        let fates = (0..count)
+            .map(|i| impairment.process(FRAME * i, 200))
+            .collect();
+        (fates, impairment.stats())
+    }
+
+    #[test]
+    fn test_unimpaired_link_delivers_immediately() {
+        let (fates, stats) = run_model(ImpairmentConfig::default(), 100);
+        for (i, fate) in fates.iter().enumerate() {
+            assert_eq!(fate, &vec![FRAME * i as u32]);
+        }
+        assert_eq!(stats.packets_in, 100);
+        assert_eq!(stats.packets_out, 100);
+    }
+
+    #[test]
+    fn test_same_seed_same_fates() {
+        let config = ImpairmentConfig {
+            loss: LossModel::Random { probability: 0.1 },
+            jitter: Duration::from_millis(5),
+            reorder_probability: 0.05,
+            duplicate_probability: 0.05,
+            seed: 42,
+            ..Default::default()
+        };
+        let (first, _) = run_model(config.clone(), 1000);
+        let (second, _) = run_model(config.clone(), 1000);
+        assert_eq!(first, second);
+
+        let (other, _) = run_model(ImpairmentConfig { seed: 43, ..config }, 1000);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_random_loss_rate() {
+        let config = ImpairmentConfig {
+            loss: LossModel::Random { probability: 0.1 },
+            seed: 7,
+            ..Default::default()
+        };
+        let (fates, stats) = run_model(config, 10_000);
+        let lost = fates.iter().filter(|fate| fate.is_empty()).count() as u64;
+        assert_eq!(lost, stats.packets_lost);
+        assert!((800..1200).contains(&lost), "lost {}", lost);
+    }
+
+    #[test]
+    fn test_gilbert_elliott_loss_is_bursty() {
+        let config = ImpairmentConfig {
+            loss: LossModel::GilbertElliott {
+                to_bad: 0.02,
+                to_good: 0.25,
+                loss_good: 0.0,
+                loss_bad: 1.0,
+            },
+            seed: 7,
+            ..Default::default()
+        };
+        let (fates, stats) = run_model(config, 10_000);
+
+        let mut bursts = 0;
+        let mut previous_lost = false;
+        for fate in &fates {
+            let lost = fate.is_empty();
+            if lost && !previous_lost {
+                bursts += 1;
+            }
+            previous_lost = lost;
+        }
+        let mean_burst = stats.packets_lost as f64 / bursts as f64;
+        assert!(
+            (3.0..5.5).contains(&mean_burst),
+            "mean burst {}",
+            mean_burst
+        );
+    }
+
+    #[test]
+    fn test_delay_and_jitter_bounds() {
+        let config = ImpairmentConfig {
+            delay: Duration::from_millis(20),
+            jitter: Duration::from_millis(10),
+            seed: 1,
+            ..Default::default()
+        };
+        let (fates, _) = run_model(config, 500);
+        for (i, fate) in fates.iter().enumerate() {
+            let sent = FRAME * i as u32;
+            let latency = fate[0] - sent;
+            assert!(latency >= Duration::from_millis(20));
+            assert!(latency <= Duration::from_millis(30));
+        }
+    }
+
+    #[test]
+    fn test_reorder_lets_later_packets_overtake() {
+        let config = ImpairmentConfig {
+            reorder_probability: 0.2,
+            reorder_delay: Duration::from_millis(10),
+            seed: 3,
+            ..Default::default()
+        };
+        let (fates, stats) = run_model(config, 1000);
+        let arrivals: Vec<Duration> = fates.iter().map(|fate| fate[0]).collect();
+        let overtaken = arrivals.windows(2).filter(|w| w[1] < w[0]).count();
+        assert!(stats.packets_reordered > 0);
+        assert!(overtaken > 0);
+    }
+
+    #[test]
+    fn test_duplicates_are_delivered_twice() {
+        let config = ImpairmentConfig {
+            duplicate_probability: 0.1,
+            seed: 5,
+            ..Default::default()
+        };
+        let (fates, stats) = run_model(config, 1000);
+        let doubled = fates.iter().filter(|fate| fate.len() == 2).count() as u64;
+        assert_eq!(doubled, stats.packets_duplicated);
+        assert_eq!(stats.packets_out, 1000 + doubled);
+        assert!(doubled > 50);
+    }
+
+    #[test]
+    fn test_bandwidth_cap_queues_then_drops() {
+        // 200 byte datagrams every 3 ms need ~533 kbps; the link has 256 kbps
+        let config = ImpairmentConfig {
+            bandwidth_bps: Some(256_000),
+            max_queue_delay: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let (fates, stats) = run_model(config, 1000);
+
+        // 1600 bits at 256 kbps takes 6.25 ms on the wire
+        assert_eq!(fates[0], vec![Duration::from_micros(6250)]);
+        assert!(stats.queue_drops > 0);
+        for (i, fate) in fates.iter().enumerate() {
+            if let Some(at) = fate.first() {
+                let queued = *at - FRAME * i as u32;
+                assert!(queued <= Duration::from_millis(50) + Duration::from_micros(6250));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_forwards_both_ways() {
+        let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = ImpairmentConfig {
+            delay: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let proxy = Arc::new(
+            ImpairmentProxy::bind(
+                "127.0.0.1:0",
+                "127.0.0.1:0",
+                a.local_addr().unwrap(),
+                b.local_addr().unwrap(),
+                config,
+            )
+            .await
+            .unwrap(),
+        );
+        let runner = proxy.clone();
+        let handle = tokio::spawn(async move { runner.run().await });
+
+        let mut buf = [0u8; 16];
+        a.send_to(b"ping", proxy.a_side_addr()).await.unwrap();
+        let (len, from) = b.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"ping");
+        assert_eq!(from, proxy.b_side_addr());
+
+        b.send_to(b"pong", proxy.b_side_addr()).await.unwrap();
+        let (len, from) = a.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"pong");
+        assert_eq!(from, proxy.a_side_addr());
+
+        assert_eq!(proxy.stats(Direction::AToB).packets_out, 1);
+        assert_eq!(proxy.stats(Direction::BToA).packets_out, 1);
+        handle.abort();
+    }
+}
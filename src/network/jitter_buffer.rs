@@ -6,6 +6,8 @@
 use std::collections::BTreeMap;
 use std::time::Instant;
 
+use crate::audio::CodecType;
+
 /// Jitter buffer operating mode
 ///
 /// Determines how the buffer adjusts its delay based on network conditions.
@@ -131,6 +133,8 @@ struct BufferedPacket {
     sequence: u32,
     /// Timestamp in samples
     timestamp: u32,
+    /// Codec of the payload
+    codec: CodecType,
     /// Encoded audio payload
     payload: Vec<u8>,
     /// Time when packet was received (for future jitter statistics)
@@ -145,6 +149,7 @@ pub enum JitterBufferResult {
     Packet {
         sequence: u32,
         timestamp: u32,
+        codec: CodecType,
         payload: Vec<u8>,
     },
     /// Packet was lost (not received in time)
@@ -182,6 +187,13 @@ pub struct JitterBuffer {
     playing: bool,
     /// Timestamp of first packet (for sync)
     first_timestamp: Option<u32>,
+    /// Delay changes not yet played out by time-stretching (ms, positive to
+    /// grow the buffer)
+    pending_stretch_ms: f32,
+    /// Audio added by time-stretching (ms)
+    stretched_ms: f32,
+    /// Audio removed by time-stretching (ms)
+    compressed_ms: f32,
 }
 
 impl JitterBuffer {
@@ -208,6 +220,9 @@ impl JitterBuffer {
             late_arrivals: 0,
             playing: false,
             first_timestamp: None,
+            pending_stretch_ms: 0.0,
+            stretched_ms: 0.0,
+            compressed_ms: 0.0,
             config,
         }
     }
@@ -217,13 +232,24 @@ impl JitterBuffer {
         self.mode
     }
 
-    /// Insert a received packet into the buffer
+    /// Insert a received PCM packet into the buffer
     ///
     /// # Arguments
     /// * `sequence` - Packet sequence number
     /// * `timestamp` - Timestamp in samples
     /// * `payload` - Encoded audio data
     pub fn insert(&mut self, sequence: u32, timestamp: u32, payload: Vec<u8>) {
+        self.insert_with_codec(sequence, timestamp, CodecType::Pcm, payload);
+    }
+
+    /// Insert a received packet encoded with the given codec
+    pub fn insert_with_codec(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: Vec<u8>,
+    ) {
         // Initialize first timestamp for sync
         if self.first_timestamp.is_none() {
             self.first_timestamp = Some(timestamp);
@@ -243,18 +269,19 @@ impl JitterBuffer {
                     }
                 }
             }
-        } else if let Some(next_seq) = self.next_play_sequence {
-            // Check if this is a late arrival (only when playing)
-            let diff = self.sequence_diff(sequence, next_seq);
-            if diff < 0 {
-                // Packet arrived after its play deadline
-                self.late_arrivals += 1;
-            }
+        } else if self.is_late(sequence) {
+            // Packet arrived after its play deadline. It can never be played,
+            // and kept in the buffer it would make `pop` walk the whole
+            // sequence space around to it, so it is dropped here
+            self.late_arrivals += 1;
+            self.packets_inserted += 1;
+            return;
         }
 
         let packet = BufferedPacket {
             sequence,
             timestamp,
+            codec,
             payload,
             received_at: Instant::now(),
         };
@@ -295,6 +322,7 @@ impl JitterBuffer {
             JitterBufferResult::Packet {
                 sequence: packet.sequence,
                 timestamp: packet.timestamp,
+                codec: packet.codec,
                 payload: packet.payload,
             }
         } else {
@@ -306,6 +334,14 @@ impl JitterBuffer {
         }
     }
 
+    /// Check if a packet would arrive after its playout deadline
+    pub fn is_late(&self, sequence: u32) -> bool {
+        self.playing
+            && self
+                .next_play_sequence
+                .is_some_and(|next| self.sequence_diff(sequence, next) < 0)
+    }
+
     /// Peek at the next packet without removing it
     pub fn peek(&self) -> Option<u32> {
         self.next_play_sequence
@@ -336,6 +372,9 @@ impl JitterBuffer {
             current_depth: self.depth(),
             current_delay_frames: self.current_delay_frames,
             jitter_estimate_ms: self.jitter_estimate_ms,
+            stretched_ms: self.stretched_ms,
+            compressed_ms: self.compressed_ms,
+            pending_stretch_ms: self.pending_stretch_ms,
         }
     }
 
@@ -353,6 +392,10 @@ impl JitterBuffer {
     ///
     /// Call this periodically (e.g., every 100ms) to adjust buffer size.
     /// Only has effect in Adaptive mode; Fixed and Passthrough modes ignore this.
+    ///
+    /// Once playing, a delay change only takes effect as the playout is
+    /// stretched or compressed by the same amount (see `pending_stretch_ms`);
+    /// the buffer then fills up or drains by itself.
     pub fn adapt(&mut self) {
         // Only adapt in Adaptive mode
         if self.mode != JitterBufferMode::Adaptive {
@@ -372,15 +415,45 @@ impl JitterBuffer {
             // More than 5% loss - increase buffer
             if self.current_delay_frames < self.config.max_delay_frames {
                 self.current_delay_frames += 1;
+                if self.playing {
+                    self.pending_stretch_ms += self.config.frame_duration_ms;
+                }
             }
         } else if loss_rate < 0.01 && self.depth() > self.current_delay_frames {
             // Low loss and buffer is full - can decrease
             if self.current_delay_frames > self.config.min_delay_frames {
                 self.current_delay_frames -= 1;
+                if self.playing {
+                    self.pending_stretch_ms -= self.config.frame_duration_ms;
+                }
             }
         }
     }
 
+    /// Playout time still to be added (positive) or removed (negative) by
+    /// time-stretching to reach the current delay, in milliseconds
+    pub fn pending_stretch_ms(&self) -> f32 {
+        self.pending_stretch_ms
+    }
+
+    /// Record playout time added (positive) or removed (negative) by
+    /// time-stretching, in milliseconds
+    ///
+    /// A step may overshoot the pending amount; the rest is then dropped.
+    pub fn record_stretch(&mut self, ms: f32) {
+        if ms > 0.0 {
+            self.stretched_ms += ms;
+        } else {
+            self.compressed_ms -= ms;
+        }
+        let remaining = self.pending_stretch_ms - ms;
+        self.pending_stretch_ms = if remaining * self.pending_stretch_ms > 0.0 {
+            remaining
+        } else {
+            0.0
+        };
+    }
+
     /// Reset the buffer
     pub fn reset(&mut self) {
         self.packets.clear();
@@ -394,6 +467,9 @@ impl JitterBuffer {
         self.late_arrivals = 0;
         self.playing = false;
         self.first_timestamp = None;
+        self.pending_stretch_ms = 0.0;
+        self.stretched_ms = 0.0;
+        self.compressed_ms = 0.0;
     }
 
     /// Calculate signed difference between sequence numbers
@@ -430,6 +506,13 @@ pub struct JitterBufferStats {
     pub current_depth: u32,
     pub current_delay_frames: u32,
     pub jitter_estimate_ms: f32,
+    /// Playout time added by time-stretching to grow the buffer (ms)
+    pub stretched_ms: f32,
+    /// Playout time removed by time-stretching to shrink the buffer (ms)
+    pub compressed_ms: f32,
+    /// Delay change still to be played out by time-stretching (ms, positive
+    /// to grow the buffer)
+    pub pending_stretch_ms: f32,
 }
 
 #[cfg(test)]
@@ -538,6 +621,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_late_packet_dropped_on_insert() {
+        let mut jb = JitterBuffer::with_config(JitterBufferConfig::passthrough(10.0));
+
+        jb.insert(0, 0, vec![0]);
+        jb.insert(2, 960, vec![2]);
+        assert!(matches!(
+            jb.pop(),
+            JitterBufferResult::Packet { sequence: 0, .. }
+        ));
+        assert!(matches!(jb.pop(), JitterBufferResult::Lost { sequence: 1 }));
+        assert!(matches!(
+            jb.pop(),
+            JitterBufferResult::Packet { sequence: 2, .. }
+        ));
+
+        // Packet 1 shows up after its slot was concealed
+        jb.insert(1, 480, vec![1]);
+        assert!(jb.is_empty());
+        assert_eq!(jb.stats().late_arrivals, 1);
+    }
+
     #[test]
     fn test_stats() {
         let config = JitterBufferConfig {
@@ -682,4 +787,46 @@ mod tests {
             "Delay should increase on loss"
         );
     }
+
+    #[test]
+    fn test_delay_change_schedules_time_stretch() {
+        let config = JitterBufferConfig::adaptive(1, 5, 2, 10.0);
+        let mut buffer = JitterBuffer::with_config(config.clone());
+
+        // Not playing yet: the start threshold changes, nothing to stretch
+        for i in 0..6 {
+            buffer.insert(i, i * 480, vec![0; 10]);
+        }
+        buffer.adapt();
+        assert_eq!(buffer.stats().current_delay_frames, 1);
+        assert_eq!(buffer.pending_stretch_ms(), 0.0);
+
+        // Playing with a full buffer: shrink by one frame
+        let mut buffer = JitterBuffer::with_config(config);
+        for i in 0..6 {
+            buffer.insert(i, i * 480, vec![0; 10]);
+        }
+        buffer.pop();
+        buffer.adapt();
+        assert_eq!(buffer.pending_stretch_ms(), -10.0);
+
+        buffer.record_stretch(-4.0);
+        assert_eq!(buffer.pending_stretch_ms(), -6.0);
+        // The last step overshoots
+        buffer.record_stretch(-7.0);
+        assert_eq!(buffer.pending_stretch_ms(), 0.0);
+
+        // Losses grow the buffer again
+        for _ in 0..6 {
+            buffer.pop();
+        }
+        buffer.adapt();
+        assert_eq!(buffer.pending_stretch_ms(), 10.0);
+        buffer.record_stretch(10.0);
+
+        let stats = buffer.stats();
+        assert_eq!(stats.pending_stretch_ms, 0.0);
+        assert_eq!(stats.compressed_ms, 11.0);
+        assert_eq!(stats.stretched_ms, 10.0);
+    }
 }
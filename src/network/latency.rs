@@ -5,7 +5,9 @@
 
 use serde::{Deserialize, Serialize};
 
+use super::clock::ClockEstimate;
 use super::connection::PeerLatencyInfo;
+use super::fec::FecConfig;
 
 /// Local audio configuration latency info (calculated from config)
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -61,12 +63,17 @@ impl LocalLatencyInfo {
 pub struct NetworkLatencyInfo {
     /// Round-trip time in ms
     pub rtt_ms: f32,
-    /// One-way latency estimate (RTT/2) in ms
+    /// One-way latency estimate in ms (RTT/2, or half the best round trip
+    /// once the peer's clock is estimated)
     pub one_way_ms: f32,
     /// Jitter (variation in packet arrival) in ms
     pub jitter_ms: f32,
     /// Packet loss rate (0.0-1.0)
     pub packet_loss_rate: f32,
+    /// Peer clock minus local clock in ms (None if not estimated)
+    pub clock_offset_ms: Option<f32>,
+    /// Peer clock drift in parts per million (None if not estimated)
+    pub clock_drift_ppm: Option<f32>,
 }
 
 impl NetworkLatencyInfo {
@@ -77,6 +84,8 @@ impl NetworkLatencyInfo {
             one_way_ms: rtt_ms / 2.0,
             jitter_ms,
             packet_loss_rate,
+            clock_offset_ms: None,
+            clock_drift_ppm: None,
         }
     }
 }
@@ -139,6 +148,48 @@ impl DownstreamLatency {
     }
 }
 
+/// What FEC costs and what it buys
+///
+/// The recovery delay is not part of the totals: a lost packet is rebuilt
+/// only when the group's FEC packets arrive, which helps only if the jitter
+/// buffer holds audio at least that long. Reed-Solomon spends bandwidth
+/// rather than delay to survive bursts: with the same group size it recovers
+/// `parity_count` losses at the same delay as XOR.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FecLatency {
+    /// Scheme name ("XOR" or "Reed-Solomon")
+    pub scheme: String,
+    /// Audio packets per group
+    pub group_size: u32,
+    /// FEC packets per group (also the losses per group that are recovered,
+    /// consecutive or not)
+    pub parity_count: u32,
+    /// Wait for the FEC packets to rebuild the first packet of a group in ms
+    /// (they follow the group's last packet)
+    pub recovery_delay_ms: f32,
+    /// Extra packets sent per audio packet (parity_count / group_size)
+    pub packet_overhead: f32,
+}
+
+impl FecLatency {
+    /// Trade-off of an FEC policy with frames of `frame_ms` (None if FEC is
+    /// disabled)
+    pub fn from_config(config: &FecConfig, frame_ms: f32) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let group_size = config.group_size.max(1) as u32;
+        let parity_count = config.scheme.parity_count() as u32;
+        Some(Self {
+            scheme: config.scheme.name().to_string(),
+            group_size,
+            parity_count,
+            recovery_delay_ms: (group_size - 1) as f32 * frame_ms,
+            packet_overhead: parity_count as f32 / group_size as f32,
+        })
+    }
+}
+
 /// Complete latency breakdown for a peer connection
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LatencyBreakdown {
@@ -154,6 +205,9 @@ pub struct LatencyBreakdown {
     pub downstream: DownstreamLatency,
     /// Network stats
     pub network: NetworkLatencyInfo,
+    /// FEC trade-off for our outgoing audio (None if FEC is off or unknown)
+    #[serde(default)]
+    pub fec: Option<FecLatency>,
 }
 
 impl LatencyBreakdown {
@@ -208,9 +262,36 @@ impl LatencyBreakdown {
             upstream,
             downstream,
             network: NetworkLatencyInfo::from_measurements(rtt_ms, jitter_ms, 0.0),
+            fec: None,
         }
     }
 
+    /// Describe the FEC policy protecting our audio, sent in frames of
+    /// `frame_ms`
+    pub fn with_fec(mut self, config: &FecConfig, frame_ms: f32) -> Self {
+        self.fec = FecLatency::from_config(config, frame_ms);
+        self
+    }
+
+    /// Use the peer's clock estimate for the network legs
+    ///
+    /// The smoothed RTT includes queuing spikes and the peer's reply time;
+    /// the clock estimate's best round trip does not, so half of it is the
+    /// one-way delay of an unloaded path. Paths are assumed symmetric.
+    pub fn with_clock(mut self, clock: &ClockEstimate) -> Self {
+        let one_way_ms = clock.one_way_ms();
+        self.upstream.network_ms = one_way_ms;
+        self.downstream.network_ms = one_way_ms;
+        self.network.one_way_ms = one_way_ms;
+        self.network.clock_offset_ms = Some(clock.offset_us as f32 / 1000.0);
+        self.network.clock_drift_ppm = Some(clock.drift_ppm as f32);
+
+        self.upstream_total_ms = self.upstream.total();
+        self.downstream_total_ms = self.downstream.total();
+        self.roundtrip_total_ms = self.upstream_total_ms + self.downstream_total_ms;
+        self
+    }
+
     /// Check if peer info is available (non-zero values)
     pub fn has_peer_info(&self) -> bool {
         self.upstream.peer_playback_buffer_ms > 0.0 || self.downstream.peer_capture_buffer_ms > 0.0
@@ -280,4 +361,58 @@ mod tests {
         // Network latency should still be calculated
         assert!((breakdown.network.one_way_ms - 5.0).abs() < 0.1);
     }
+
+    #[test]
+    fn test_latency_breakdown_with_clock() {
+        let local = LocalLatencyInfo::from_audio_config(128, 48000, "pcm");
+        let clock = ClockEstimate {
+            offset_us: -1500,
+            drift_ppm: 20.0,
+            delay_us: 6000,
+            measured_at_us: 0,
+        };
+        let breakdown = LatencyBreakdown::calculate(&local, None, 10.0, 0.5).with_clock(&clock);
+
+        assert!((breakdown.network.one_way_ms - 3.0).abs() < 1e-6);
+        assert!((breakdown.upstream.network_ms - 3.0).abs() < 1e-6);
+        assert_eq!(breakdown.network.clock_offset_ms, Some(-1.5));
+        // 2.67 capture + 3.0 network
+        assert!((breakdown.upstream_total_ms - 5.67).abs() < 0.1);
+        assert_eq!(breakdown.network.rtt_ms, 10.0);
+    }
+
+    #[test]
+    fn test_latency_breakdown_with_fec() {
+        let local = LocalLatencyInfo::from_audio_config(128, 48000, "pcm");
+        let frame_ms = local.capture_buffer_ms;
+
+        let breakdown = LatencyBreakdown::calculate(&local, None, 10.0, 0.5);
+        assert!(breakdown.fec.is_none());
+
+        let xor = breakdown
+            .clone()
+            .with_fec(&FecConfig::with_group_size(4), frame_ms)
+            .fec
+            .unwrap();
+        assert_eq!(xor.scheme, "XOR");
+        assert_eq!(xor.parity_count, 1);
+        assert!((xor.packet_overhead - 0.25).abs() < 1e-6);
+
+        // Same delay, more bandwidth, survives a 2-packet burst
+        let rs = breakdown
+            .clone()
+            .with_fec(&FecConfig::reed_solomon(4, 2), frame_ms)
+            .fec
+            .unwrap();
+        assert_eq!(rs.scheme, "Reed-Solomon");
+        assert_eq!(rs.parity_count, 2);
+        assert!((rs.packet_overhead - 0.5).abs() < 1e-6);
+        assert!((rs.recovery_delay_ms - xor.recovery_delay_ms).abs() < 1e-6);
+        // 3 frames of 2.67 ms
+        assert!((rs.recovery_delay_ms - 8.0).abs() < 0.1);
+
+        // FEC does not change the totals
+        let totals = breakdown.with_fec(&FecConfig::reed_solomon(4, 2), frame_ms);
+        assert!((totals.upstream_total_ms - 2.67 - 5.0).abs() < 0.1);
+    }
 }
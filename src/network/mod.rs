@@ -1,35 +1,83 @@
 //! Network module for P2P communication
 //!
-//! Handles UDP transport, NAT traversal, signaling, FEC, encryption, and connection management.
+//! Handles UDP transport, NAT traversal, relaying, signaling, FEC, encryption, and connection
+//! management, plus a network impairment simulator for testing.
 
+mod capabilities;
+mod capture;
+mod clock;
+mod codec_negotiation;
 mod connection;
+mod control;
+mod drift;
 mod encryption;
 mod error;
 mod fec;
+mod ice;
+mod impairment;
 mod jitter_buffer;
 mod latency;
+mod path_validation;
+mod quality;
+mod receive_pipeline;
+mod receiver_report;
+mod redundancy;
+mod reed_solomon;
+mod relay;
+mod replay;
 mod sequence_tracker;
 mod session;
 mod signaling;
 mod stun;
 mod transport;
 
-pub use connection::{Connection, ConnectionState, ConnectionStats, PeerLatencyInfo};
-pub use encryption::{EncryptedTransport, EncryptionContext, KeyExchangeMessage, KeyPair};
+pub use capabilities::{negotiate, NegotiatedCapabilities, MAX_FRAME_SIZE};
+pub use capture::{CaptureReader, CapturedPacket, PacketCapture, CAPTURE_MAGIC, CAPTURE_VERSION};
+pub use clock::{local_clock_us, ClockEstimate};
+pub use connection::{
+    Connection, ConnectionState, ConnectionStats, ControlCallback, PeerLatencyInfo,
+    QualityCallback, ReconnectConfig, StateCallback,
+};
+pub use control::{ControlConfig, ControlStats, CONTROL_RECEIVE_WINDOW};
+pub use encryption::{
+    EncryptedTransport, EncryptionContext, EncryptionMode, KeyExchangeMessage, KeyPair,
+};
 pub use error::NetworkError;
-pub use fec::{FecDecoder, FecEncoder, FecPacket, RecoveredPacket, FEC_GROUP_SIZE};
+pub use fec::{
+    FecConfig, FecDecoder, FecEncoder, FecPacket, FecScheme, FecStreamDecoder, FecStreamEncoder,
+    RecoveredAudio, RecoveredPacket, FEC_GROUP_SIZE,
+};
+pub use ice::{IceConfig, IceRole, IceState};
+pub use impairment::{
+    Direction, Impairment, ImpairmentConfig, ImpairmentProxy, ImpairmentStats, LossModel,
+};
 pub use jitter_buffer::{
     JitterBuffer, JitterBufferConfig, JitterBufferMode, JitterBufferResult, JitterBufferStats,
 };
 pub use latency::{
-    DownstreamLatency, LatencyBreakdown, LocalLatencyInfo, NetworkLatencyInfo, UpstreamLatency,
+    DownstreamLatency, FecLatency, LatencyBreakdown, LocalLatencyInfo, NetworkLatencyInfo,
+    UpstreamLatency,
+};
+pub use quality::{
+    NetworkConditions, QualityConfig, QualityDecision, QualityReason, QualitySettings,
+};
+pub use receive_pipeline::{
+    PlayoutFrame, ReceivePipeline, ReceivePipelineConfig, ReceivePipelineStats,
+};
+pub use receiver_report::RemoteReceptionStats;
+pub use redundancy::{RedundancyConfig, MAX_REDUNDANCY_DEPTH, REDUCED_OPUS_BITRATE};
+pub use relay::{RelayServer, RELAY_ALLOCATION_LIFETIME};
+pub use replay::{replay, ReplayConfig, ReplayInterval, ReplayOutput};
+pub use sequence_tracker::{LossStats, SequenceTracker, LOSS_WINDOW_PACKETS};
+pub use session::{
+    PeerControlCallback, PeerMix, PeerQualityCallback, PeerStateCallback, PeerStats, Session,
+    SessionConfig, StreamAudioCallback, StreamStats,
 };
-pub use sequence_tracker::SequenceTracker;
-pub use session::{Session, SessionConfig};
 pub use signaling::{
     candidates_to_addrs, gather_candidates, generate_invite_code, is_invite_code_format,
-    AddressCandidate, CandidateType, PeerInfo, RoomInfo, SignalingClient, SignalingConnection,
-    SignalingMessage, SignalingServer, MAX_PEERS_PER_ROOM,
+    signaling_version_error, AddressCandidate, CandidateType, PeerInfo, RoomInfo, SignalingClient,
+    SignalingConnection, SignalingMessage, SignalingServer, StreamInfo, MAX_PEERS_PER_ROOM,
+    MIN_SIGNALING_VERSION, SIGNALING_VERSION,
 };
 pub use stun::{StunClient, StunResult, DEFAULT_STUN_SERVERS};
 pub use transport::UdpTransport;
@@ -0,0 +1,190 @@
+//! Source address validation for a connection
+//!
+//! A `Connection` only accepts packets from the peer's nominated address;
+//! anything else is dropped and counted, so a spoofed source cannot inject
+//! audio or control messages. The peer may still move, e.g. when its NAT
+//! binding changes. A packet from a new address that decrypts with the
+//! session keys gets a random cookie sent to that address in a sealed
+//! `PacketType::PathChallenge`. Only the peer can read the cookie and echo
+//! it in a sealed `PacketType::PathResponse` from that address, and only then
+//! does the new address replace the old one. Replaying captured packets from
+//! a spoofed source earns it nothing but challenges it cannot read, which
+//! are rate limited.
+//!
+//! Without session keys (before the key exchange completes, or with
+//! encryption disabled) nothing proves a packet comes from the peer, so the
+//! peer never moves: a peer whose address changes then has to reconnect.
+//! An attacker on the path between the peers can still relay the peer's
+//! sealed response from an address of its own and so become the path; it
+//! can read nothing and could drop the traffic anyway.
+//!
+//! Dropped packets are logged at most once per `LOG_INTERVAL`, so a flood
+//! from a stray or hostile source cannot fill the log.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Shortest time between challenges to the same address
+const CHALLENGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time a challenge can be answered (resent challenges keep their cookie)
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most addresses challenged at the same time
+const MAX_CHALLENGES: usize = 4;
+
+/// Shortest time between log lines about dropped packets
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Challenge outstanding for one address
+struct Challenge {
+    cookie: u64,
+    /// When the cookie was first sent
+    created: Instant,
+    /// When the cookie was last sent
+    sent: Instant,
+}
+
+/// Challenges to new peer addresses and rate limiting of drop logs
+pub(crate) struct PathValidator {
+    challenges: HashMap<SocketAddr, Challenge>,
+    /// Drops not reported in a log line yet
+    unlogged: u64,
+    last_log: Option<Instant>,
+}
+
+impl PathValidator {
+    /// Create a validator with no challenges outstanding
+    pub fn new() -> Self {
+        Self {
+            challenges: HashMap::new(),
+            unlogged: 0,
+            last_log: None,
+        }
+    }
+
+    /// Note a packet dropped for its source address
+    ///
+    /// Returns the number of drops to report when a log line is due: on the
+    /// first drop and then at most once per `LOG_INTERVAL`.
+    pub fn reject(&mut self, now: Instant) -> Option<u64> {
+        self.unlogged += 1;
+        if self
+            .last_log
+            .is_some_and(|last| now.duration_since(last) < LOG_INTERVAL)
+        {
+            return None;
+        }
+        self.last_log = Some(now);
+        Some(std::mem::take(&mut self.unlogged))
+    }
+
+    /// Cookie to challenge `addr` with
+    ///
+    /// An address already being challenged gets the same cookie again, at
+    /// most once per `CHALLENGE_INTERVAL`. Returns None while that interval
+    /// runs or when `MAX_CHALLENGES` other addresses are being challenged.
+    pub fn challenge(&mut self, addr: SocketAddr, now: Instant) -> Option<u64> {
+        self.expire(now);
+        if let Some(challenge) = self.challenges.get_mut(&addr) {
+            if now.duration_since(challenge.sent) < CHALLENGE_INTERVAL {
+                return None;
+            }
+            challenge.sent = now;
+            return Some(challenge.cookie);
+        }
+        if self.challenges.len() >= MAX_CHALLENGES {
+            return None;
+        }
+        let cookie = rand::random();
+        self.challenges.insert(
+            addr,
+            Challenge {
+                cookie,
+                created: now,
+                sent: now,
+            },
+        );
+        Some(cookie)
+    }
+
+    /// Check a path response from `addr`
+    ///
+    /// Returns true if it echoes the cookie of the challenge outstanding for
+    /// `addr`; the address is then validated and all challenges are dropped.
+    pub fn validate(&mut self, addr: SocketAddr, cookie: u64, now: Instant) -> bool {
+        self.expire(now);
+        let valid = self
+            .challenges
+            .get(&addr)
+            .is_some_and(|challenge| challenge.cookie == cookie);
+        if valid {
+            self.challenges.clear();
+        }
+        valid
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.challenges
+            .retain(|_, challenge| now.duration_since(challenge.created) < CHALLENGE_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([192, 0, 2, 1], port))
+    }
+
+    #[test]
+    fn test_response_must_echo_cookie_from_challenged_address() {
+        let mut validator = PathValidator::new();
+        let now = Instant::now();
+        let cookie = validator.challenge(addr(1), now).unwrap();
+
+        assert!(!validator.validate(addr(1), cookie.wrapping_add(1), now));
+        assert!(!validator.validate(addr(2), cookie, now));
+        assert!(validator.validate(addr(1), cookie, now));
+        // A validated challenge cannot be answered again
+        assert!(!validator.validate(addr(1), cookie, now));
+    }
+
+    #[test]
+    fn test_challenges_rate_limited_and_expire() {
+        let mut validator = PathValidator::new();
+        let start = Instant::now();
+        let cookie = validator.challenge(addr(1), start).unwrap();
+        assert_eq!(
+            validator.challenge(addr(1), start + CHALLENGE_INTERVAL / 2),
+            None
+        );
+        assert_eq!(
+            validator.challenge(addr(1), start + CHALLENGE_INTERVAL),
+            Some(cookie)
+        );
+
+        for port in 2..=MAX_CHALLENGES as u16 {
+            assert!(validator.challenge(addr(port), start).is_some());
+        }
+        assert_eq!(validator.challenge(addr(99), start), None);
+
+        // Expired challenges make room and can no longer be answered
+        let later = start + CHALLENGE_TIMEOUT;
+        assert!(!validator.validate(addr(1), cookie, later));
+        assert!(validator.challenge(addr(99), later).is_some());
+    }
+
+    #[test]
+    fn test_drop_log_rate_limited() {
+        let mut validator = PathValidator::new();
+        let start = Instant::now();
+        assert_eq!(validator.reject(start), Some(1));
+        for _ in 0..9 {
+            assert_eq!(validator.reject(start + LOG_INTERVAL / 2), None);
+        }
+        assert_eq!(validator.reject(start + LOG_INTERVAL), Some(10));
+    }
+}
@@ -0,0 +1,671 @@
+//! Adaptive audio quality from observed network conditions
+//!
+//! Every outgoing stream gets a controller that looks at the network once
+//! per interval and moves one step at a time within the configured bounds:
+//!
+//! - A round trip rising above the lowest seen means queues are building up
+//!   (congestion): PCM falls back to Opus, then the Opus bitrate is cut by a
+//!   quarter, then frames grow.
+//! - Packet loss, counting packets that missed the jitter buffer, makes FEC
+//!   stronger (smaller groups), then cuts the bitrate.
+//! - Jitter doubles the frame size, halving the packet rate.
+//!
+//! After several clean intervals in a row the steps are undone one at a
+//! time, the bitrate growing by a fixed step up to the maximum. Each change
+//! is reported as a `QualityDecision` carrying the conditions behind it.
+//!
+//! Conditions are measured on the peer's stream to us, assuming the path is
+//! about as good both ways. The codec is still negotiated with the peer, so
+//! falling back to Opus only takes effect if the peer can decode it.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::audio::{CodecConfig, CodecType};
+
+use super::fec::FecConfig;
+use super::jitter_buffer::JitterBufferStats;
+use super::sequence_tracker::LossStats;
+
+/// Bitrate added per recovery step (bits/s)
+const BITRATE_STEP: u32 = 16_000;
+
+/// Bounds and thresholds of the quality controller
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityConfig {
+    /// Lowest Opus bitrate (bits/s)
+    pub min_bitrate: u32,
+    /// Highest Opus bitrate (bits/s)
+    pub max_bitrate: u32,
+    /// Largest frame size in samples per channel
+    ///
+    /// Frames start at the codec's frame size and double. If Opus can encode
+    /// the starting size, sizes it cannot encode are skipped.
+    pub max_frame_size: u32,
+    /// Smallest FEC group (strongest protection)
+    pub min_fec_group_size: usize,
+    /// Turn FEC on under loss even if the FEC policy has it off
+    pub enable_fec: bool,
+    /// Switch PCM to Opus when the network cannot carry PCM
+    pub allow_opus_fallback: bool,
+    /// Loss rate (0.0 - 1.0) that calls for a step down
+    pub high_loss_rate: f32,
+    /// Loss rate below which an interval counts as clean
+    pub low_loss_rate: f32,
+    /// Jitter buffer jitter estimate (ms) that makes frames grow
+    pub high_jitter_ms: f32,
+    /// Round trip above the lowest seen that signals congestion (ms)
+    pub queuing_delay_ms: f32,
+    /// Time between decisions
+    pub interval: Duration,
+    /// Clean intervals in a row before a step is undone
+    pub recovery_intervals: u32,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            min_bitrate: 24_000,
+            max_bitrate: 128_000,
+            max_frame_size: 480, // 10ms @ 48kHz
+            min_fec_group_size: 2,
+            enable_fec: true,
+            allow_opus_fallback: true,
+            high_loss_rate: 0.03,
+            low_loss_rate: 0.005,
+            high_jitter_ms: 15.0,
+            queuing_delay_ms: 40.0,
+            interval: Duration::from_secs(1),
+            recovery_intervals: 5,
+        }
+    }
+}
+
+/// Encoding parameters of an outgoing stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualitySettings {
+    /// Preferred codec (still negotiated with the peer)
+    pub codec: CodecType,
+    /// Opus bitrate (bits/s)
+    pub bitrate: u32,
+    /// Samples per channel in each packet
+    pub frame_size: u32,
+    /// Audio packets per FEC packet (None = FEC off)
+    pub fec_group_size: Option<usize>,
+}
+
+impl fmt::Display for QualitySettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.codec {
+            CodecType::Pcm => write!(f, "PCM")?,
+            CodecType::Opus => write!(f, "Opus {} kbps", self.bitrate / 1000)?,
+        }
+        write!(f, ", {}-sample frames", self.frame_size)?;
+        match self.fec_group_size {
+            Some(group) => write!(f, ", FEC 1/{}", group),
+            None => write!(f, ", FEC off"),
+        }
+    }
+}
+
+/// Network conditions over one interval
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// Recent packet loss rate of the peer's stream (0.0 - 1.0)
+    pub loss_rate: f32,
+    /// Share of packets that reached the jitter buffer too late (0.0 - 1.0)
+    pub late_rate: f32,
+    /// Jitter buffer jitter estimate (ms)
+    pub jitter_ms: f32,
+    /// Smoothed round-trip time (ms, 0 before the first pong)
+    pub rtt_ms: f32,
+    /// Lowest round trip seen so far (ms)
+    pub base_rtt_ms: f32,
+}
+
+/// Why the quality controller changed the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityReason {
+    /// The round trip grew: queues are building up on the path
+    Congestion,
+    /// Packets were lost or arrived too late to play
+    PacketLoss,
+    /// Packet arrival times varied too much
+    Jitter,
+    /// Conditions have been clean for a while
+    Recovery,
+}
+
+impl fmt::Display for QualityReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QualityReason::Congestion => "congestion",
+            QualityReason::PacketLoss => "packet loss",
+            QualityReason::Jitter => "jitter",
+            QualityReason::Recovery => "recovery",
+        })
+    }
+}
+
+/// A change made by the quality controller
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityDecision {
+    /// Settings before the change
+    pub previous: QualitySettings,
+    /// Settings after the change
+    pub settings: QualitySettings,
+    pub reason: QualityReason,
+    /// Conditions the decision was based on
+    pub conditions: NetworkConditions,
+}
+
+impl fmt::Display for QualityDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.conditions;
+        write!(
+            f,
+            "{} -> {} ({}: loss {:.1}%, late {:.1}%, jitter {:.1}ms, RTT {:.1}ms)",
+            self.previous,
+            self.settings,
+            self.reason,
+            c.loss_rate * 100.0,
+            c.late_rate * 100.0,
+            c.jitter_ms,
+            c.rtt_ms
+        )
+    }
+}
+
+/// Quality controller for one outgoing stream
+#[derive(Debug)]
+pub(crate) struct QualityController {
+    config: QualityConfig,
+    sample_rate: u32,
+    /// Settings before any adaptation, restored by recovery
+    initial: QualitySettings,
+    settings: QualitySettings,
+    /// Weakest FEC group used
+    max_fec_group_size: usize,
+    base_rtt_ms: f32,
+    /// Jitter buffer (inserted, late) counters at the last decision
+    last_jitter_buffer: Option<(u64, u64)>,
+    clean_intervals: u32,
+    last_poll: Option<Instant>,
+}
+
+impl QualityController {
+    /// Create a controller starting from the configured codec and FEC policy
+    pub(crate) fn new(config: QualityConfig, codec: &CodecConfig, fec: &FecConfig) -> Self {
+        let initial = QualitySettings {
+            codec: codec.codec_type,
+            bitrate: codec
+                .bitrate
+                .min(config.max_bitrate)
+                .max(config.min_bitrate),
+            frame_size: codec.frame_size,
+            fec_group_size: fec.enabled.then_some(fec.group_size),
+        };
+        Self {
+            max_fec_group_size: fec.group_size.max(config.min_fec_group_size),
+            config,
+            sample_rate: codec.sample_rate,
+            initial,
+            settings: initial,
+            base_rtt_ms: f32::INFINITY,
+            last_jitter_buffer: None,
+            clean_intervals: 0,
+            last_poll: None,
+        }
+    }
+
+    /// Current settings
+    pub(crate) fn settings(&self) -> QualitySettings {
+        self.settings
+    }
+
+    /// Keep frames within the largest size the peer accepts
+    ///
+    /// Returns the new settings if frames had already grown past it.
+    pub(crate) fn limit_frame_size(&mut self, max_frame_size: u32) -> Option<QualitySettings> {
+        self.config.max_frame_size = self.config.max_frame_size.min(max_frame_size);
+        if self.settings.frame_size <= max_frame_size {
+            return None;
+        }
+        self.settings.frame_size = self.initial.frame_size;
+        Some(self.settings)
+    }
+
+    /// Decide on new settings if an interval has passed since the last call
+    ///
+    /// Returns the decision if the settings changed.
+    pub(crate) fn poll(
+        &mut self,
+        now: Instant,
+        loss: &LossStats,
+        jitter_buffer: Option<&JitterBufferStats>,
+        rtt_ms: f32,
+    ) -> Option<QualityDecision> {
+        if self
+            .last_poll
+            .is_some_and(|at| now.duration_since(at) < self.config.interval)
+        {
+            return None;
+        }
+        self.last_poll = Some(now);
+
+        let mut late_rate = 0.0;
+        if let Some(stats) = jitter_buffer {
+            let counters = (stats.packets_inserted, stats.late_arrivals);
+            if let Some((inserted, late)) = self.last_jitter_buffer.replace(counters) {
+                // Late packets are inserted too
+                let arrived = counters.0.saturating_sub(inserted);
+                if arrived > 0 {
+                    late_rate = counters.1.saturating_sub(late) as f32 / arrived as f32;
+                }
+            }
+        }
+        if rtt_ms > 0.0 {
+            self.base_rtt_ms = self.base_rtt_ms.min(rtt_ms);
+        }
+
+        self.update(NetworkConditions {
+            loss_rate: loss.recent_loss_rate,
+            late_rate,
+            jitter_ms: jitter_buffer.map_or(0.0, |stats| stats.jitter_estimate_ms),
+            rtt_ms,
+            base_rtt_ms: if self.base_rtt_ms.is_finite() {
+                self.base_rtt_ms
+            } else {
+                0.0
+            },
+        })
+    }
+
+    /// Take one step based on an interval's conditions
+    fn update(&mut self, conditions: NetworkConditions) -> Option<QualityDecision> {
+        let config = &self.config;
+        let loss = conditions.loss_rate.max(conditions.late_rate);
+        let congested = conditions.rtt_ms > 0.0
+            && conditions.rtt_ms > conditions.base_rtt_ms + config.queuing_delay_ms;
+
+        let (settings, reason) = if congested {
+            self.clean_intervals = 0;
+            let settings = self
+                .fall_back_to_opus()
+                .or_else(|| self.lower_bitrate())
+                .or_else(|| self.grow_frames())?;
+            (settings, QualityReason::Congestion)
+        } else if loss >= config.high_loss_rate {
+            self.clean_intervals = 0;
+            let settings = self
+                .strengthen_fec()
+                .or_else(|| self.lower_bitrate())
+                .or_else(|| self.fall_back_to_opus())?;
+            (settings, QualityReason::PacketLoss)
+        } else if conditions.jitter_ms >= config.high_jitter_ms {
+            self.clean_intervals = 0;
+            (self.grow_frames()?, QualityReason::Jitter)
+        } else if loss < config.low_loss_rate && conditions.jitter_ms < config.high_jitter_ms / 2.0
+        {
+            self.clean_intervals += 1;
+            if self.clean_intervals < config.recovery_intervals {
+                return None;
+            }
+            self.clean_intervals = 0;
+            (self.recover()?, QualityReason::Recovery)
+        } else {
+            self.clean_intervals = 0;
+            return None;
+        };
+
+        let previous = std::mem::replace(&mut self.settings, settings);
+        Some(QualityDecision {
+            previous,
+            settings,
+            reason,
+            conditions,
+        })
+    }
+
+    fn fall_back_to_opus(&self) -> Option<QualitySettings> {
+        let usable = self.config.allow_opus_fallback
+            && CodecType::Opus.is_available()
+            && is_opus_frame_size(self.settings.frame_size, self.sample_rate);
+        (usable && self.settings.codec == CodecType::Pcm).then_some(QualitySettings {
+            codec: CodecType::Opus,
+            ..self.settings
+        })
+    }
+
+    fn lower_bitrate(&self) -> Option<QualitySettings> {
+        let bitrate = (self.settings.bitrate / 4 * 3).max(self.config.min_bitrate);
+        (self.settings.codec == CodecType::Opus && bitrate < self.settings.bitrate).then_some(
+            QualitySettings {
+                bitrate,
+                ..self.settings
+            },
+        )
+    }
+
+    fn strengthen_fec(&self) -> Option<QualitySettings> {
+        let min = self.config.min_fec_group_size;
+        let group = match self.settings.fec_group_size {
+            None if self.config.enable_fec => self.max_fec_group_size,
+            Some(group) if group > min => (group / 2).max(min),
+            _ => return None,
+        };
+        Some(QualitySettings {
+            fec_group_size: Some(group),
+            ..self.settings
+        })
+    }
+
+    fn grow_frames(&self) -> Option<QualitySettings> {
+        let mut frame_size = self.settings.frame_size * 2;
+        while frame_size <= self.config.max_frame_size {
+            if self.is_usable_frame_size(frame_size) {
+                return Some(QualitySettings {
+                    frame_size,
+                    ..self.settings
+                });
+            }
+            frame_size *= 2;
+        }
+        None
+    }
+
+    /// Undo one step: frames first (they cost latency), then bitrate, FEC
+    /// and the codec
+    fn recover(&self) -> Option<QualitySettings> {
+        let current = self.settings;
+        let initial = self.initial;
+        let mut next = current;
+
+        if current.frame_size > initial.frame_size {
+            next.frame_size = self.shrink_frames();
+        } else if current.codec == CodecType::Opus && current.bitrate < self.config.max_bitrate {
+            next.bitrate = (current.bitrate + BITRATE_STEP).min(self.config.max_bitrate);
+        } else if current.fec_group_size != initial.fec_group_size {
+            next.fec_group_size = match current.fec_group_size {
+                Some(group) if group < self.max_fec_group_size => {
+                    Some((group * 2).min(self.max_fec_group_size))
+                }
+                _ => initial.fec_group_size,
+            };
+        } else if current.codec != initial.codec {
+            next.codec = initial.codec;
+        }
+
+        (next != current).then_some(next)
+    }
+
+    fn shrink_frames(&self) -> u32 {
+        let mut frame_size = self.settings.frame_size / 2;
+        while frame_size > self.initial.frame_size && !self.is_usable_frame_size(frame_size) {
+            frame_size /= 2;
+        }
+        frame_size.max(self.initial.frame_size)
+    }
+
+    /// Check if frames of this size can be sent with the stream's codecs
+    ///
+    /// If Opus cannot encode the starting size, the stream stays on PCM,
+    /// which takes any size.
+    fn is_usable_frame_size(&self, frame_size: u32) -> bool {
+        is_opus_frame_size(frame_size, self.sample_rate)
+            || !is_opus_frame_size(self.initial.frame_size, self.sample_rate)
+    }
+}
+
+/// Check if Opus can encode frames of this size (2.5 to 60 ms)
+fn is_opus_frame_size(frame_size: u32, sample_rate: u32) -> bool {
+    // In units of 0.5 ms
+    let half_ms = frame_size as u64 * 2000;
+    half_ms.is_multiple_of(sample_rate as u64)
+        && matches!(half_ms / sample_rate as u64, 5 | 10 | 20 | 40 | 80 | 120)
+}
+
+/// Cuts captured audio into packets of the adapted frame size
+#[derive(Debug, Default)]
+pub(crate) struct Packetizer {
+    samples: Vec<f32>,
+    /// Timestamp of the first buffered sample
+    timestamp: u32,
+}
+
+impl Packetizer {
+    /// Add captured samples and return the packets of `frame_size` samples
+    /// per channel that are now complete, with their timestamps
+    pub(crate) fn push(
+        &mut self,
+        data: &[f32],
+        timestamp: u32,
+        frame_size: u32,
+        channels: u16,
+    ) -> Vec<(Vec<f32>, u32)> {
+        let packet_len = (frame_size as usize * channels as usize).max(1);
+        if self.samples.is_empty() {
+            if data.len() == packet_len {
+                return vec![(data.to_vec(), timestamp)];
+            }
+            self.timestamp = timestamp;
+        }
+        self.samples.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        while self.samples.len() >= packet_len {
+            let rest = self.samples.split_off(packet_len);
+            packets.push((std::mem::replace(&mut self.samples, rest), self.timestamp));
+            self.timestamp = self.timestamp.wrapping_add(frame_size);
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(codec: CodecType) -> QualityController {
+        let codec = CodecConfig {
+            codec_type: codec,
+            ..Default::default()
+        };
+        QualityController::new(QualityConfig::default(), &codec, &FecConfig::disabled())
+    }
+
+    fn conditions(loss_rate: f32, jitter_ms: f32, rtt_ms: f32) -> NetworkConditions {
+        NetworkConditions {
+            loss_rate,
+            late_rate: 0.0,
+            jitter_ms,
+            rtt_ms,
+            base_rtt_ms: 20.0,
+        }
+    }
+
+    fn clean(controller: &mut QualityController) -> Option<QualityDecision> {
+        (0..QualityConfig::default().recovery_intervals)
+            .filter_map(|_| controller.update(conditions(0.0, 0.0, 20.0)))
+            .last()
+    }
+
+    #[test]
+    fn test_loss_strengthens_fec_then_lowers_bitrate() {
+        let mut controller = controller(CodecType::Opus);
+        assert_eq!(controller.settings().bitrate, 128_000);
+
+        let decision = controller.update(conditions(0.05, 0.0, 20.0)).unwrap();
+        assert_eq!(decision.reason, QualityReason::PacketLoss);
+        assert_eq!(decision.previous.fec_group_size, None);
+        assert_eq!(decision.settings.fec_group_size, Some(4));
+
+        controller.update(conditions(0.05, 0.0, 20.0)).unwrap();
+        assert_eq!(controller.settings().fec_group_size, Some(2));
+
+        let decision = controller.update(conditions(0.05, 0.0, 20.0)).unwrap();
+        assert_eq!(decision.settings.bitrate, 96_000);
+    }
+
+    #[test]
+    fn test_congestion_lowers_bitrate_to_minimum() {
+        let mut controller = controller(CodecType::Opus);
+        while let Some(decision) = controller.update(conditions(0.0, 0.0, 200.0)) {
+            if decision.settings.frame_size != decision.previous.frame_size {
+                break;
+            }
+            assert_eq!(decision.reason, QualityReason::Congestion);
+            assert!(decision.settings.bitrate < decision.previous.bitrate);
+        }
+        assert_eq!(controller.settings().bitrate, 24_000);
+        // Out of bitrate: frames grow to cut per-packet overhead
+        assert_eq!(controller.settings().frame_size, 240);
+    }
+
+    #[test]
+    fn test_jitter_grows_frames_within_bounds() {
+        let mut controller = controller(CodecType::Pcm);
+        let sizes: Vec<u32> = std::iter::from_fn(|| controller.update(conditions(0.0, 30.0, 20.0)))
+            .map(|decision| decision.settings.frame_size)
+            .collect();
+        assert_eq!(sizes, vec![240, 480]);
+    }
+
+    #[test]
+    fn test_recovery_undoes_steps_after_clean_intervals() {
+        let mut controller = controller(CodecType::Opus);
+        controller.update(conditions(0.0, 30.0, 20.0)).unwrap();
+        controller.update(conditions(0.05, 0.0, 20.0)).unwrap();
+        controller.update(conditions(0.0, 0.0, 200.0)).unwrap();
+        assert_eq!(controller.settings().bitrate, 96_000);
+
+        // Not clean enough: no recovery
+        assert!(controller.update(conditions(0.01, 0.0, 20.0)).is_none());
+        for _ in 1..QualityConfig::default().recovery_intervals {
+            assert!(controller.update(conditions(0.0, 0.0, 20.0)).is_none());
+        }
+        let decision = controller.update(conditions(0.0, 0.0, 20.0)).unwrap();
+        assert_eq!(decision.reason, QualityReason::Recovery);
+        assert_eq!(decision.settings.frame_size, 120);
+
+        assert_eq!(clean(&mut controller).unwrap().settings.bitrate, 112_000);
+        assert_eq!(clean(&mut controller).unwrap().settings.bitrate, 128_000);
+        assert_eq!(
+            clean(&mut controller).unwrap().settings.fec_group_size,
+            None
+        );
+        assert!(clean(&mut controller).is_none());
+    }
+
+    #[test]
+    fn test_late_packets_count_as_loss() {
+        let mut controller = controller(CodecType::Opus);
+        let mut stats = JitterBufferStats {
+            packets_inserted: 0,
+            packets_played: 0,
+            packets_lost: 0,
+            late_arrivals: 0,
+            current_depth: 0,
+            current_delay_frames: 0,
+            jitter_estimate_ms: 1.0,
+            stretched_ms: 0.0,
+            compressed_ms: 0.0,
+            pending_stretch_ms: 0.0,
+        };
+        let loss = LossStats::default();
+        let start = Instant::now();
+        assert!(controller.poll(start, &loss, Some(&stats), 20.0).is_none());
+
+        stats.packets_inserted = 100;
+        stats.late_arrivals = 10;
+        // Too soon for another decision
+        let soon = start + Duration::from_millis(100);
+        assert!(controller.poll(soon, &loss, Some(&stats), 20.0).is_none());
+
+        let later = start + Duration::from_secs(1);
+        let decision = controller.poll(later, &loss, Some(&stats), 20.0).unwrap();
+        assert_eq!(decision.reason, QualityReason::PacketLoss);
+        assert!((decision.conditions.late_rate - 0.1).abs() < 1e-6);
+        assert_eq!(decision.conditions.base_rtt_ms, 20.0);
+    }
+
+    #[test]
+    fn test_opus_fallback_needs_opus() {
+        let mut controller = controller(CodecType::Pcm);
+        let decision = controller.update(conditions(0.0, 0.0, 200.0));
+        if CodecType::Opus.is_available() {
+            assert_eq!(decision.unwrap().settings.codec, CodecType::Opus);
+        } else {
+            // PCM has no bitrate to lower
+            assert_eq!(decision.unwrap().settings.frame_size, 240);
+        }
+    }
+
+    #[test]
+    fn test_pcm_frame_sizes_double_freely() {
+        // 128 samples is not an Opus frame size, so the stream stays on PCM
+        let codec = CodecConfig {
+            frame_size: 128,
+            ..Default::default()
+        };
+        let config = QualityConfig {
+            max_frame_size: 512,
+            ..Default::default()
+        };
+        let mut controller = QualityController::new(config, &codec, &FecConfig::disabled());
+        let decision = controller.update(conditions(0.0, 0.0, 200.0)).unwrap();
+        assert_eq!(decision.settings.codec, CodecType::Pcm);
+        assert_eq!(decision.settings.frame_size, 256);
+        controller.update(conditions(0.0, 30.0, 20.0)).unwrap();
+        assert_eq!(controller.settings().frame_size, 512);
+    }
+
+    #[test]
+    fn test_peer_frame_size_limit() {
+        let codec = CodecConfig {
+            frame_size: 128,
+            ..Default::default()
+        };
+        let config = QualityConfig {
+            max_frame_size: 1024,
+            ..Default::default()
+        };
+        let mut controller = QualityController::new(config, &codec, &FecConfig::disabled());
+        controller.update(conditions(0.0, 30.0, 20.0)).unwrap();
+        controller.update(conditions(0.0, 30.0, 20.0)).unwrap();
+        assert_eq!(controller.settings().frame_size, 512);
+
+        // Frames past the peer's limit go back to the start
+        let settings = controller.limit_frame_size(256).unwrap();
+        assert_eq!(settings.frame_size, 128);
+        controller.update(conditions(0.0, 30.0, 20.0)).unwrap();
+        assert!(controller.update(conditions(0.0, 30.0, 20.0)).is_none());
+        assert_eq!(controller.settings().frame_size, 256);
+    }
+
+    #[test]
+    fn test_opus_frame_sizes() {
+        assert!(is_opus_frame_size(120, 48000));
+        assert!(is_opus_frame_size(960, 48000));
+        assert!(is_opus_frame_size(2880, 48000));
+        assert!(!is_opus_frame_size(128, 48000));
+        assert!(!is_opus_frame_size(3840, 48000));
+        assert!(is_opus_frame_size(441, 44100));
+    }
+
+    #[test]
+    fn test_packetizer_collects_and_splits() {
+        let mut packetizer = Packetizer::default();
+        // Stereo, 2-sample capture frames into 4-sample packets
+        assert!(packetizer.push(&[1.0; 4], 100, 4, 2).is_empty());
+        let packets = packetizer.push(&[2.0; 4], 102, 4, 2);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].1, 100);
+        assert_eq!(packets[0].0, vec![1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0]);
+
+        // Larger captures are split
+        let packets = packetizer.push(&[3.0; 12], 104, 2, 2);
+        let timestamps: Vec<u32> = packets.iter().map(|(_, ts)| *ts).collect();
+        assert_eq!(timestamps, vec![104, 106, 108]);
+    }
+}
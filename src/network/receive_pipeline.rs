@@ -0,0 +1,1002 @@
+//! Receive pipeline for incoming audio packets
+//!
+//! Combines the jitter buffer, the audio decoder and packet loss concealment
+//! into a single playout path: packets are inserted as they arrive and frames
+//! are pulled on the local playout clock.
+//!
+//! Redundant copies of frames carried by later packets are kept aside and
+//! played in the slot of a lost frame instead of concealment.
+//!
+//! Once the drift between the sender's sample clock and the playout clock is
+//! known, frames are played through an adaptive resampler that keeps the
+//! jitter buffer at its target depth (see `drift`).
+//!
+//! When the adaptive jitter buffer changes its delay during playout, decoded
+//! frames are time-stretched until the buffer has grown or shrunk by the
+//! change; frames keep being handed out at the sender's frame size.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::audio::{
+    create_plc, CodecConfig, CodecError, CodecType, PacketLossConcealment, PlcType, TimeStretcher,
+};
+
+use super::clock::local_clock_us;
+use super::codec_negotiation::AudioDecoders;
+use super::drift::{resample_ratio, AdaptiveResampler, DriftEstimator};
+
+use super::jitter_buffer::{
+    JitterBuffer, JitterBufferConfig, JitterBufferMode, JitterBufferResult, JitterBufferStats,
+};
+
+/// Number of played frames between jitter buffer adaptation steps
+const ADAPT_INTERVAL_FRAMES: u64 = 40;
+
+/// Maximum concealment frames emitted for a single gap in passthrough mode
+///
+/// A large sequence jump (e.g. after the sender paused) would otherwise
+/// produce a burst of concealment frames at once.
+const MAX_PASSTHROUGH_CONCEALMENT: u32 = 4;
+
+/// Maximum redundant frames kept for slots not yet played
+const MAX_REDUNDANT_FRAMES: usize = 64;
+
+/// Maximum frames pulled from the jitter buffer for one resampled or
+/// stretched frame
+const MAX_FRAME_PULLS: usize = 4;
+
+/// Configuration for the receive pipeline
+#[derive(Debug, Clone)]
+pub struct ReceivePipelineConfig {
+    /// Jitter buffer configuration (Adaptive / Fixed / Passthrough)
+    pub jitter_buffer: JitterBufferConfig,
+    /// Codec configuration used to decode received payloads
+    pub codec: CodecConfig,
+    /// Resample the stream to compensate clock drift between the sender and
+    /// the local playout (applies to frames pulled with `pop_frame`)
+    pub drift_compensation: bool,
+    /// Time-stretch the playout when the jitter buffer delay changes, so the
+    /// buffer grows or shrinks without waiting for or skipping whole frames
+    pub time_stretch: bool,
+    /// Concealment of lost PCM frames (Opus uses its own)
+    pub plc: PlcType,
+}
+
+impl ReceivePipelineConfig {
+    /// Create a configuration for the given codec with a jitter buffer whose
+    /// frame duration matches the codec frame size
+    pub fn new(mode: JitterBufferMode, codec: CodecConfig) -> Self {
+        let frame_duration_ms = codec.frame_size as f32 / codec.sample_rate as f32 * 1000.0;
+        let jitter_buffer = match mode {
+            JitterBufferMode::Adaptive => JitterBufferConfig {
+                frame_duration_ms,
+                ..Default::default()
+            },
+            JitterBufferMode::Fixed => JitterBufferConfig::fixed(2, frame_duration_ms),
+            JitterBufferMode::Passthrough => JitterBufferConfig::passthrough(frame_duration_ms),
+        };
+
+        Self {
+            jitter_buffer,
+            codec,
+            drift_compensation: true,
+            time_stretch: true,
+            plc: PlcType::default(),
+        }
+    }
+
+    /// Duration of a single audio frame
+    pub fn frame_duration(&self) -> Duration {
+        let sample_rate = self.codec.sample_rate.max(1) as u64;
+        Duration::from_nanos(self.codec.frame_size as u64 * 1_000_000_000 / sample_rate)
+    }
+
+    /// Whether frames are delivered on arrival instead of on the playout clock
+    pub fn is_passthrough(&self) -> bool {
+        self.jitter_buffer.mode == JitterBufferMode::Passthrough
+    }
+}
+
+impl Default for ReceivePipelineConfig {
+    fn default() -> Self {
+        Self::new(JitterBufferMode::Adaptive, CodecConfig::default())
+    }
+}
+
+/// A decoded frame ready for playback
+#[derive(Debug, Clone)]
+pub struct PlayoutFrame {
+    /// Interleaved f32 samples
+    pub samples: Vec<f32>,
+    /// Timestamp in samples
+    pub timestamp: u32,
+    /// True if the frame was generated by packet loss concealment
+    pub concealed: bool,
+}
+
+/// Receive pipeline statistics
+#[derive(Debug, Clone)]
+pub struct ReceivePipelineStats {
+    /// Jitter buffer statistics
+    pub jitter_buffer: JitterBufferStats,
+    /// Current jitter buffer delay in milliseconds
+    pub jitter_buffer_delay_ms: f32,
+    /// Frames decoded from received packets
+    pub frames_decoded: u64,
+    /// Frames generated by packet loss concealment
+    pub frames_concealed: u64,
+    /// Payloads that failed to decode (concealed instead)
+    pub decode_errors: u64,
+    /// Lost frames played from a redundant copy instead of concealed (also
+    /// counted in `frames_decoded`)
+    pub frames_from_redundancy: u64,
+    /// How much faster the sender's sample clock runs than the local playout
+    /// (ppm), None until enough playout has been observed
+    pub drift_ppm: Option<f64>,
+    /// Input samples played per output sample (1.0 while not resampling)
+    pub resample_ratio: f64,
+}
+
+/// Redundant copy of a frame waiting for its slot
+struct RedundantCopy {
+    timestamp: u32,
+    codec: CodecType,
+    payload: Vec<u8>,
+}
+
+/// Jitter buffer + decoder + PLC for a single incoming audio stream
+pub struct ReceivePipeline {
+    config: ReceivePipelineConfig,
+    jitter_buffer: JitterBuffer,
+    decoders: AudioDecoders,
+    /// Redundant copies by sequence, played if their own packet is lost
+    redundant: HashMap<u32, RedundantCopy>,
+    /// Codec of the last decoded packet (used for concealment)
+    last_codec: CodecType,
+    /// Samples per channel of the last decoded packet
+    ///
+    /// The sender's frame size may differ from the local one; concealment
+    /// follows the sender so the stream timeline stays intact.
+    last_frame_size: u32,
+    plc: Box<dyn PacketLossConcealment>,
+    /// Timestamp of the last frame handed out (for concealed frames)
+    last_timestamp: Option<u32>,
+    frames_decoded: u64,
+    frames_concealed: u64,
+    decode_errors: u64,
+    frames_from_redundancy: u64,
+    frames_since_adapt: u64,
+    /// Drift of the sender's timestamps against the local playout
+    drift: DriftEstimator,
+    /// Resampler compensating the drift, engaged once it is estimated
+    resampler: Option<AdaptiveResampler>,
+    resample_ratio: f64,
+    /// Last played samples, to start the resampler from
+    played_tail: Vec<f32>,
+    /// Samples per channel played so far
+    played: f64,
+    /// Local clock when the last frame was pulled (µs)
+    last_pop_us: Option<u64>,
+    /// Jitter buffer delay when playout started
+    start_delay_frames: u32,
+    /// Stretches decoded frames to play out jitter buffer delay changes
+    stretcher: TimeStretcher,
+    /// Decoded samples left over from stretched frames, played next
+    stretched: Vec<f32>,
+}
+
+impl ReceivePipeline {
+    /// Create a new receive pipeline
+    ///
+    /// # Errors
+    /// Returns error if the configured codec is not available in this build
+    pub fn new(config: ReceivePipelineConfig) -> Result<Self, CodecError> {
+        let decoders = AudioDecoders::new(config.codec.clone())?;
+        let last_codec = config.codec.codec_type;
+        let last_frame_size = config.codec.frame_size;
+        let plc = create_plc(
+            config.plc,
+            config.codec.frame_size,
+            config.codec.channels,
+            config.codec.sample_rate,
+        );
+        let jitter_buffer = JitterBuffer::with_config(config.jitter_buffer.clone());
+        let stretcher = TimeStretcher::new(config.codec.sample_rate, config.codec.channels);
+
+        Ok(Self {
+            jitter_buffer,
+            decoders,
+            redundant: HashMap::new(),
+            last_codec,
+            last_frame_size,
+            plc,
+            last_timestamp: None,
+            frames_decoded: 0,
+            frames_concealed: 0,
+            decode_errors: 0,
+            frames_from_redundancy: 0,
+            frames_since_adapt: 0,
+            drift: DriftEstimator::new(config.codec.sample_rate),
+            resampler: None,
+            resample_ratio: 1.0,
+            played_tail: Vec::new(),
+            played: 0.0,
+            last_pop_us: None,
+            start_delay_frames: 0,
+            stretcher,
+            stretched: Vec::new(),
+            config,
+        })
+    }
+
+    /// Get the pipeline configuration
+    pub fn config(&self) -> &ReceivePipelineConfig {
+        &self.config
+    }
+
+    /// Insert a received audio packet encoded with the configured codec
+    pub fn insert(&mut self, sequence: u32, timestamp: u32, payload: Vec<u8>) {
+        let codec = self.config.codec.codec_type;
+        self.insert_with_codec(sequence, timestamp, codec, payload);
+    }
+
+    /// Insert a received audio packet encoded with the given codec
+    pub fn insert_with_codec(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: Vec<u8>,
+    ) {
+        self.insert_at(sequence, timestamp, codec, payload, local_clock_us());
+    }
+
+    /// Insert a received audio packet that arrived at `local_us` on the clock
+    /// `pop_frame_at` is called with
+    pub fn insert_at(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: Vec<u8>,
+        local_us: u64,
+    ) {
+        if let Some(position) = self.playout_position(local_us) {
+            self.drift.on_packet(timestamp, position);
+        }
+        self.jitter_buffer
+            .insert_with_codec(sequence, timestamp, codec, payload);
+    }
+
+    /// Keep a redundant copy of a frame for its slot
+    ///
+    /// The copy is played only if the frame's own packet is lost; it is
+    /// dropped once playout moves past the frame.
+    pub fn insert_redundant(
+        &mut self,
+        sequence: u32,
+        timestamp: u32,
+        codec: CodecType,
+        payload: Vec<u8>,
+    ) {
+        if self.is_late(sequence) || self.redundant.len() >= MAX_REDUNDANT_FRAMES {
+            return;
+        }
+        self.redundant.entry(sequence).or_insert(RedundantCopy {
+            timestamp,
+            codec,
+            payload,
+        });
+    }
+
+    /// Check if a packet would arrive after its playout deadline
+    pub fn is_late(&self, sequence: u32) -> bool {
+        self.jitter_buffer.is_late(sequence)
+    }
+
+    /// Pull the next frame for playback
+    ///
+    /// Call this once per frame on the playout clock. Returns `None` while the
+    /// jitter buffer is still filling up (underrun).
+    pub fn pop_frame(&mut self) -> Option<PlayoutFrame> {
+        self.pop_frame_at(local_clock_us())
+    }
+
+    /// Pull the next frame for playback at `local_us`
+    ///
+    /// Like `pop_frame`, for callers running the playout clock themselves
+    /// (e.g. offline replay). Packets must then be inserted with `insert_at`
+    /// on the same clock.
+    pub fn pop_frame_at(&mut self, local_us: u64) -> Option<PlayoutFrame> {
+        // The resampler reads ahead into the next frame, which must be there
+        if self.config.drift_compensation
+            && self.resampler.is_none()
+            && self.drift.drift_ppm().is_some()
+            && self.jitter_buffer.depth() >= 2
+        {
+            self.engage_resampler();
+        }
+        let frame = if self.resampler.is_some() {
+            self.next_resampled()?
+        } else {
+            self.next_stretched()?
+        };
+        self.drop_played_copies();
+        self.on_played(&frame, local_us);
+
+        self.frames_since_adapt += 1;
+        if self.frames_since_adapt >= ADAPT_INTERVAL_FRAMES {
+            self.frames_since_adapt = 0;
+            self.jitter_buffer.adapt();
+        }
+
+        Some(frame)
+    }
+
+    /// Pull every frame that can be played immediately
+    ///
+    /// Used in passthrough mode where frames are delivered on arrival rather
+    /// than on the playout clock. Gaps are concealed, but at most
+    /// `MAX_PASSTHROUGH_CONCEALMENT` frames are emitted per gap.
+    pub fn drain_ready(&mut self) -> Vec<PlayoutFrame> {
+        let mut frames = Vec::new();
+        let mut gap = 0;
+
+        while !self.jitter_buffer.is_empty() {
+            match self.jitter_buffer.pop() {
+                JitterBufferResult::Packet {
+                    timestamp,
+                    codec,
+                    payload,
+                    ..
+                } => {
+                    gap = 0;
+                    frames.push(self.decode(timestamp, codec, &payload));
+                }
+                JitterBufferResult::Lost { sequence } => {
+                    if self.redundant.contains_key(&sequence) {
+                        gap = 0;
+                        frames.push(self.fill_lost(sequence));
+                    } else {
+                        gap += 1;
+                        if gap <= MAX_PASSTHROUGH_CONCEALMENT {
+                            frames.push(self.conceal());
+                        }
+                    }
+                }
+                JitterBufferResult::Underrun => break,
+            }
+        }
+        self.drop_played_copies();
+
+        frames
+    }
+
+    /// Get pipeline statistics
+    pub fn stats(&self) -> ReceivePipelineStats {
+        ReceivePipelineStats {
+            jitter_buffer: self.jitter_buffer.stats(),
+            jitter_buffer_delay_ms: self.jitter_buffer.current_delay_ms(),
+            frames_decoded: self.frames_decoded,
+            frames_concealed: self.frames_concealed,
+            decode_errors: self.decode_errors,
+            frames_from_redundancy: self.frames_from_redundancy,
+            drift_ppm: self.drift.drift_ppm(),
+            resample_ratio: self.resample_ratio,
+        }
+    }
+
+    /// Current jitter buffer delay in milliseconds
+    pub fn jitter_buffer_delay_ms(&self) -> f32 {
+        self.jitter_buffer.current_delay_ms()
+    }
+
+    /// Reset the pipeline (e.g. when the remote stream restarts)
+    pub fn reset(&mut self) {
+        self.jitter_buffer.reset();
+        self.redundant.clear();
+        self.plc.reset();
+        self.last_timestamp = None;
+        self.frames_since_adapt = 0;
+        self.drift.reset();
+        self.resampler = None;
+        self.resample_ratio = 1.0;
+        self.played_tail.clear();
+        self.played = 0.0;
+        self.last_pop_us = None;
+        self.start_delay_frames = 0;
+        self.stretched.clear();
+    }
+
+    fn channels(&self) -> usize {
+        self.config.codec.channels.max(1) as usize
+    }
+
+    /// Decode (or conceal) the next frame from the jitter buffer
+    ///
+    /// Decoded frames are time-stretched while a delay change of the jitter
+    /// buffer is pending, so they may be longer or shorter than sent.
+    fn next_frame(&mut self) -> Option<PlayoutFrame> {
+        match self.jitter_buffer.pop() {
+            JitterBufferResult::Packet {
+                timestamp,
+                codec,
+                payload,
+                ..
+            } => {
+                let mut frame = self.decode(timestamp, codec, &payload);
+                self.time_stretch(&mut frame);
+                Some(frame)
+            }
+            JitterBufferResult::Lost { sequence } => Some(self.fill_lost(sequence)),
+            JitterBufferResult::Underrun => None,
+        }
+    }
+
+    /// Next frame at the sender's frame size, made up from stretched frames
+    ///
+    /// Frames that were not stretched are passed through as they are.
+    fn next_stretched(&mut self) -> Option<PlayoutFrame> {
+        let mut concealed = false;
+        if self.stretched.is_empty() {
+            let frame = self.next_frame()?;
+            if frame.samples.len() == self.last_frame_size as usize * self.channels() {
+                return Some(frame);
+            }
+            concealed = frame.concealed;
+            self.stretched = frame.samples;
+        }
+
+        for _ in 0..MAX_FRAME_PULLS {
+            if self.stretched.len() >= self.last_frame_size as usize * self.channels() {
+                break;
+            }
+            let Some(frame) = self.next_frame() else {
+                break;
+            };
+            concealed |= frame.concealed;
+            self.stretched.extend_from_slice(&frame.samples);
+        }
+        let len = self.last_frame_size as usize * self.channels();
+        if self.stretched.len() < len {
+            return None;
+        }
+
+        // Timestamp of the sample the frame starts at
+        let timestamp = self
+            .last_timestamp
+            .unwrap_or(0)
+            .wrapping_add(self.last_frame_size)
+            .wrapping_sub((self.stretched.len() / self.channels()) as u32);
+        let samples = self.stretched.drain(..len).collect();
+        Some(PlayoutFrame {
+            samples,
+            timestamp,
+            concealed,
+        })
+    }
+
+    /// Stretch or compress a decoded frame towards the pending jitter buffer
+    /// delay change
+    fn time_stretch(&mut self, frame: &mut PlayoutFrame) {
+        let pending_ms = self.jitter_buffer.pending_stretch_ms();
+        if !self.config.time_stretch || pending_ms == 0.0 {
+            return;
+        }
+        let sample_rate = self.config.codec.sample_rate as f32;
+        let change = (pending_ms * sample_rate / 1000.0).round() as i64;
+        let Some(samples) = self.stretcher.stretch(&frame.samples, change) else {
+            return;
+        };
+        let frames = samples.len() as f32 - frame.samples.len() as f32;
+        self.jitter_buffer
+            .record_stretch(frames / self.channels() as f32 * 1000.0 / sample_rate);
+        frame.samples = samples;
+    }
+
+    /// Next frame played through the drift compensating resampler
+    ///
+    /// Has the sender's frame size; the jitter buffer is read a little faster
+    /// or slower than one frame per frame played.
+    fn next_resampled(&mut self) -> Option<PlayoutFrame> {
+        let mut resampler = self.resampler.take()?;
+        let frames = self.last_frame_size as usize;
+        // Timestamp of the input sample the frame starts at
+        let timestamp = self
+            .last_timestamp
+            .unwrap_or(0)
+            .wrapping_add(self.last_frame_size)
+            .wrapping_sub(resampler.buffered() as u32);
+        let ratio = self.drift_ratio(timestamp);
+
+        let mut concealed = false;
+        for _ in 0..MAX_FRAME_PULLS {
+            if resampler.can_process(frames, ratio) {
+                break;
+            }
+            let Some(frame) = self.next_frame() else {
+                break;
+            };
+            concealed |= frame.concealed;
+            resampler.push(&frame.samples);
+        }
+        if !resampler.can_process(frames, ratio) {
+            self.resampler = Some(resampler);
+            return None;
+        }
+
+        let samples = resampler.process(frames, ratio);
+        self.resampler = Some(resampler);
+        self.resample_ratio = ratio;
+
+        Some(PlayoutFrame {
+            samples,
+            timestamp,
+            concealed,
+        })
+    }
+
+    /// Start resampling, continuing seamlessly from the last played samples
+    fn engage_resampler(&mut self) {
+        let mut resampler = AdaptiveResampler::new(self.channels(), &self.played_tail);
+        resampler.push(&std::mem::take(&mut self.stretched));
+        self.played_tail = Vec::new();
+        self.resampler = Some(resampler);
+    }
+
+    /// Resampling ratio for the estimated drift, playing `timestamp` next
+    ///
+    /// The lead of the received audio over the playout is held where it was
+    /// when playout started, plus the samples the resampler reads ahead and
+    /// any change of the jitter buffer delay since.
+    fn drift_ratio(&self, timestamp: u32) -> f64 {
+        let delay_change =
+            self.jitter_buffer.stats().current_delay_frames as f64 - self.start_delay_frames as f64;
+        let error = self
+            .drift
+            .lead_error(timestamp, self.played)
+            .map_or(0.0, |error| {
+                error
+                    - AdaptiveResampler::LOOKAHEAD as f64
+                    - delay_change * self.last_frame_size as f64
+            });
+        resample_ratio(
+            self.drift.drift_ppm().unwrap_or(0.0),
+            error,
+            self.config.codec.sample_rate,
+        )
+    }
+
+    /// Follow the local playout after a frame was played at `local_us`
+    fn on_played(&mut self, frame: &PlayoutFrame, local_us: u64) {
+        if self.last_pop_us.is_none() {
+            self.start_delay_frames = self.jitter_buffer.stats().current_delay_frames;
+        }
+        self.drift.on_played(frame.timestamp, self.played);
+        self.played += (frame.samples.len() / self.channels()) as f64;
+        self.last_pop_us = Some(local_us);
+
+        if self.config.drift_compensation && self.resampler.is_none() {
+            let keep = AdaptiveResampler::HISTORY * self.channels();
+            self.played_tail.extend_from_slice(&frame.samples);
+            let excess = self.played_tail.len().saturating_sub(keep);
+            self.played_tail.drain(..excess);
+        }
+    }
+
+    /// Local playout position at `local_us` in samples per channel (None
+    /// before the first frame was played)
+    ///
+    /// Interpolated from the time the last frame was pulled, up to one frame.
+    fn playout_position(&self, local_us: u64) -> Option<f64> {
+        let last_pop_us = self.last_pop_us?;
+        let elapsed = local_us.saturating_sub(last_pop_us) as f64
+            * self.config.codec.sample_rate as f64
+            / 1e6;
+        Some(self.played + elapsed.min(self.last_frame_size as f64))
+    }
+
+    fn decode(&mut self, timestamp: u32, codec: CodecType, payload: &[u8]) -> PlayoutFrame {
+        match self.decoders.decode(codec, payload) {
+            Ok(mut samples) => {
+                self.last_codec = codec;
+                self.last_frame_size = (samples.len() / self.channels()) as u32;
+                self.plc.receive_frame(&mut samples);
+                self.frames_decoded += 1;
+                self.last_timestamp = Some(timestamp);
+                PlayoutFrame {
+                    samples,
+                    timestamp,
+                    concealed: false,
+                }
+            }
+            Err(e) => {
+                warn!("Failed to decode audio payload: {}", e);
+                self.decode_errors += 1;
+                self.conceal()
+            }
+        }
+    }
+
+    /// Play a lost frame from its redundant copy, or conceal it
+    fn fill_lost(&mut self, sequence: u32) -> PlayoutFrame {
+        match self.redundant.remove(&sequence) {
+            Some(copy) => {
+                self.frames_from_redundancy += 1;
+                self.decode(copy.timestamp, copy.codec, &copy.payload)
+            }
+            None => self.conceal(),
+        }
+    }
+
+    /// Forget redundant copies of frames playout has moved past
+    fn drop_played_copies(&mut self) {
+        let jitter_buffer = &self.jitter_buffer;
+        self.redundant
+            .retain(|&sequence, _| !jitter_buffer.is_late(sequence));
+    }
+
+    fn conceal(&mut self) -> PlayoutFrame {
+        let frame_size = self.last_frame_size;
+        let samples = match self.last_codec {
+            CodecType::Opus => self
+                .decoders
+                .decode_plc(CodecType::Opus, frame_size)
+                .unwrap_or_else(|| self.plc.generate_concealment()),
+            CodecType::Pcm => self.plc.generate_concealment(),
+        };
+
+        let timestamp = self
+            .last_timestamp
+            .map(|ts| ts.wrapping_add(frame_size))
+            .unwrap_or(0);
+        self.last_timestamp = Some(timestamp);
+        self.frames_concealed += 1;
+
+        PlayoutFrame {
+            samples,
+            timestamp,
+            concealed: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::codec_negotiation::encode_pcm;
+    use std::f32::consts::PI;
+
+    fn pcm_payload(value: f32, len: usize) -> Vec<u8> {
+        (0..len).flat_map(|_| value.to_le_bytes()).collect()
+    }
+
+    fn test_config(mode: JitterBufferMode) -> ReceivePipelineConfig {
+        let codec = CodecConfig {
+            frame_size: 4,
+            ..Default::default()
+        };
+        let mut config = ReceivePipelineConfig::new(mode, codec);
+        config.jitter_buffer.min_delay_frames = 1;
+        config.jitter_buffer.initial_delay_frames = 1;
+        config
+    }
+
+    #[test]
+    fn test_frame_duration() {
+        let codec = CodecConfig {
+            frame_size: 480,
+            sample_rate: 48000,
+            ..Default::default()
+        };
+        let config = ReceivePipelineConfig::new(JitterBufferMode::Adaptive, codec);
+        assert_eq!(config.frame_duration(), Duration::from_millis(10));
+        assert!((config.jitter_buffer.frame_duration_ms - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_underrun_before_playout() {
+        let mut config = test_config(JitterBufferMode::Adaptive);
+        config.jitter_buffer.initial_delay_frames = 3;
+        let mut pipeline = ReceivePipeline::new(config).unwrap();
+
+        pipeline.insert(0, 0, pcm_payload(0.5, 4));
+        assert!(pipeline.pop_frame().is_none());
+    }
+
+    #[test]
+    fn test_reordered_packets_play_in_order() {
+        let mut pipeline = ReceivePipeline::new(test_config(JitterBufferMode::Fixed)).unwrap();
+
+        pipeline.insert(1, 4, pcm_payload(0.2, 4));
+        pipeline.insert(0, 0, pcm_payload(0.1, 4));
+
+        let first = pipeline.pop_frame().unwrap();
+        let second = pipeline.pop_frame().unwrap();
+        assert_eq!(first.timestamp, 0);
+        assert!((first.samples[0] - 0.1).abs() < 1e-6);
+        assert_eq!(second.timestamp, 4);
+        assert!((second.samples[0] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_lost_packet_is_concealed() {
+        let mut pipeline = ReceivePipeline::new(test_config(JitterBufferMode::Fixed)).unwrap();
+
+        pipeline.insert(0, 0, pcm_payload(1.0, 4));
+        pipeline.insert(2, 8, pcm_payload(1.0, 4));
+
+        let good = pipeline.pop_frame().unwrap();
+        assert!(!good.concealed);
+
+        let concealed = pipeline.pop_frame().unwrap();
+        assert!(concealed.concealed);
+        assert_eq!(concealed.timestamp, 4);
+        assert_eq!(concealed.samples.len(), 4);
+        // PcmPlc repeats the last frame with fadeout
+        assert!((concealed.samples[0] - 0.85).abs() < 1e-6);
+
+        let stats = pipeline.stats();
+        assert_eq!(stats.frames_decoded, 1);
+        assert_eq!(stats.frames_concealed, 1);
+    }
+
+    #[test]
+    fn test_concealment_follows_sender_frame_size() {
+        let mut pipeline = ReceivePipeline::new(test_config(JitterBufferMode::Fixed)).unwrap();
+
+        // Sender uses 8-sample frames while the local frame size is 4
+        pipeline.insert(0, 0, pcm_payload(1.0, 8));
+        pipeline.insert(2, 16, pcm_payload(1.0, 8));
+
+        pipeline.pop_frame().unwrap();
+        let concealed = pipeline.pop_frame().unwrap();
+        assert!(concealed.concealed);
+        assert_eq!(concealed.samples.len(), 8);
+        assert_eq!(concealed.timestamp, 8);
+    }
+
+    #[test]
+    fn test_invalid_payload_is_concealed() {
+        let mut pipeline = ReceivePipeline::new(test_config(JitterBufferMode::Fixed)).unwrap();
+
+        pipeline.insert(0, 0, vec![1, 2, 3]); // Not a multiple of 4
+        let frame = pipeline.pop_frame().unwrap();
+        assert!(frame.concealed);
+        assert_eq!(pipeline.stats().decode_errors, 1);
+    }
+
+    #[test]
+    fn test_lost_packet_played_from_redundancy() {
+        let mut pipeline = ReceivePipeline::new(test_config(JitterBufferMode::Fixed)).unwrap();
+
+        pipeline.insert(0, 0, pcm_payload(1.0, 4));
+        // Packet 2 arrives carrying copies of frames 0 and 1; 1 is lost
+        pipeline.insert_redundant(0, 0, CodecType::Pcm, pcm_payload(1.0, 4));
+        pipeline.insert_redundant(1, 4, CodecType::Pcm, pcm_payload(0.3, 4));
+        pipeline.insert(2, 8, pcm_payload(0.5, 4));
+
+        assert!((pipeline.pop_frame().unwrap().samples[0] - 1.0).abs() < 1e-6);
+        let filled = pipeline.pop_frame().unwrap();
+        assert!(!filled.concealed);
+        assert_eq!(filled.timestamp, 4);
+        assert!((filled.samples[0] - 0.3).abs() < 1e-6);
+        assert!((pipeline.pop_frame().unwrap().samples[0] - 0.5).abs() < 1e-6);
+
+        let stats = pipeline.stats();
+        assert_eq!(stats.frames_decoded, 3);
+        assert_eq!(stats.frames_from_redundancy, 1);
+        assert_eq!(stats.frames_concealed, 0);
+        assert!(pipeline.redundant.is_empty());
+
+        // Copies of frames already played are not kept
+        pipeline.insert_redundant(1, 4, CodecType::Pcm, pcm_payload(0.3, 4));
+        assert!(pipeline.redundant.is_empty());
+    }
+
+    #[test]
+    fn test_passthrough_fills_gap_from_redundancy() {
+        let mut pipeline =
+            ReceivePipeline::new(test_config(JitterBufferMode::Passthrough)).unwrap();
+
+        pipeline.insert(0, 0, pcm_payload(0.5, 4));
+        assert_eq!(pipeline.drain_ready().len(), 1);
+
+        pipeline.insert_redundant(1, 4, CodecType::Pcm, pcm_payload(0.7, 4));
+        pipeline.insert(2, 8, pcm_payload(0.5, 4));
+        let frames = pipeline.drain_ready();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|f| !f.concealed));
+        assert!((frames[0].samples[0] - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_passthrough_drain_limits_concealment() {
+        let mut pipeline =
+            ReceivePipeline::new(test_config(JitterBufferMode::Passthrough)).unwrap();
+
+        pipeline.insert(0, 0, pcm_payload(0.5, 4));
+        assert_eq!(pipeline.drain_ready().len(), 1);
+
+        // Large gap: only a bounded number of concealment frames are emitted
+        pipeline.insert(100, 400, pcm_payload(0.5, 4));
+        let frames = pipeline.drain_ready();
+        let concealed = frames.iter().filter(|f| f.concealed).count();
+        assert!(concealed <= MAX_PASSTHROUGH_CONCEALMENT as usize);
+        assert!(!frames.last().unwrap().concealed);
+    }
+
+    #[test]
+    fn test_passthrough_drops_reordered_late_packet() {
+        let mut pipeline =
+            ReceivePipeline::new(test_config(JitterBufferMode::Passthrough)).unwrap();
+
+        pipeline.insert(0, 0, pcm_payload(0.5, 4));
+        pipeline.insert(2, 8, pcm_payload(0.5, 4));
+        assert_eq!(pipeline.drain_ready().len(), 3);
+
+        // Packet 1 arrives after its slot was concealed: nothing to play, and
+        // the buffer is not walked around the sequence space to reach it
+        pipeline.insert(1, 4, pcm_payload(0.5, 4));
+        assert!(pipeline.drain_ready().is_empty());
+        assert_eq!(pipeline.stats().frames_concealed, 1);
+        assert_eq!(pipeline.stats().jitter_buffer.late_arrivals, 1);
+    }
+
+    /// Play `seconds` of a sine from a sender running `drift_ppm` fast, in
+    /// 10 ms frames arriving with up to 3 ms of jitter
+    fn play_drifting(
+        drift_ppm: f64,
+        drift_compensation: bool,
+        seconds: u64,
+    ) -> (ReceivePipeline, Vec<PlayoutFrame>) {
+        let codec = CodecConfig {
+            sample_rate: 8000,
+            frame_size: 80,
+            ..Default::default()
+        };
+        let mut config = ReceivePipelineConfig::new(JitterBufferMode::Fixed, codec);
+        config.drift_compensation = drift_compensation;
+        let mut pipeline = ReceivePipeline::new(config).unwrap();
+
+        let rate = 1.0 + drift_ppm / 1e6;
+        let mut sequence = 0u32;
+        let mut frames = Vec::new();
+        for tick in 0..seconds * 100 {
+            let now_us = tick * 10_000;
+            loop {
+                let jitter = (sequence as u64 * 7919 % 31) * 100;
+                let arrival = (sequence as f64 * 10_000.0 / rate) as u64 + 2_000 + jitter;
+                if arrival > now_us {
+                    break;
+                }
+                let samples: Vec<f32> = (0..80)
+                    .map(|n| ((sequence * 80 + n) as f32 * 0.05).sin() * 0.5)
+                    .collect();
+                let timestamp = sequence * 80;
+                pipeline.insert_at(
+                    sequence,
+                    timestamp,
+                    CodecType::Pcm,
+                    encode_pcm(&samples),
+                    arrival,
+                );
+                sequence += 1;
+            }
+            frames.extend(pipeline.pop_frame_at(now_us));
+        }
+        (pipeline, frames)
+    }
+
+    #[test]
+    fn test_drift_compensation_holds_depth() {
+        for drift in [200.0, -200.0] {
+            let (pipeline, frames) = play_drifting(drift, true, 120);
+            let stats = pipeline.stats();
+
+            let estimated = stats.drift_ppm.unwrap();
+            assert!((estimated - drift).abs() < 5.0, "{} ppm", estimated);
+            assert!((stats.resample_ratio - 1.0) * drift > 0.0);
+            assert_eq!(stats.frames_concealed, 0);
+            // One frame is read ahead into the resampler
+            assert!(stats.jitter_buffer.current_depth <= 2);
+
+            // Nothing dropped or repeated: the sine stays continuous
+            let samples: Vec<f32> = frames.iter().flat_map(|f| f.samples.clone()).collect();
+            assert!(frames.iter().all(|f| f.samples.len() == 80));
+            assert!(samples.windows(2).all(|w| (w[1] - w[0]).abs() < 0.03));
+        }
+    }
+
+    #[test]
+    fn test_uncompensated_drift_overflows_buffer() {
+        let (pipeline, _) = play_drifting(200.0, false, 120);
+        let stats = pipeline.stats();
+        assert!(stats.drift_ppm.is_some());
+        assert_eq!(stats.resample_ratio, 1.0);
+        // The surplus is dropped from the full buffer and concealed
+        assert!(stats.frames_concealed > 0);
+    }
+
+    /// Play `frames` 10 ms frames of a 250 Hz sine through an adaptive
+    /// jitter buffer, one packet arriving per frame played after `prefill`
+    /// packets arrived at once
+    fn play_adaptive(
+        prefill: u32,
+        lost: impl Fn(u32) -> bool,
+        time_stretch: bool,
+        frames: u32,
+    ) -> (ReceivePipeline, Vec<PlayoutFrame>) {
+        let codec = CodecConfig {
+            sample_rate: 8000,
+            frame_size: 80,
+            ..Default::default()
+        };
+        let mut config = ReceivePipelineConfig::new(JitterBufferMode::Adaptive, codec);
+        config.drift_compensation = false;
+        config.time_stretch = time_stretch;
+        let mut pipeline = ReceivePipeline::new(config).unwrap();
+
+        let insert = |pipeline: &mut ReceivePipeline, sequence: u32| {
+            if !lost(sequence) {
+                let samples: Vec<f32> = (0..80)
+                    .map(|n| ((sequence * 80 + n) as f32 * PI / 16.0).sin() * 0.5)
+                    .collect();
+                pipeline.insert(sequence, sequence * 80, encode_pcm(&samples));
+            }
+        };
+        for sequence in 0..prefill {
+            insert(&mut pipeline, sequence);
+        }
+        let played = (0..frames)
+            .filter_map(|i| {
+                insert(&mut pipeline, prefill + i);
+                pipeline.pop_frame()
+            })
+            .collect();
+        (pipeline, played)
+    }
+
+    #[test]
+    fn test_delay_decrease_compresses_playout() {
+        let (pipeline, frames) = play_adaptive(6, |_| false, true, 200);
+        let stats = pipeline.stats().jitter_buffer;
+
+        assert_eq!(stats.current_delay_frames, 1);
+        assert!((10.0..12.5).contains(&stats.compressed_ms));
+        assert_eq!(stats.pending_stretch_ms, 0.0);
+        // The buffered audio shrank by what was compressed
+        let buffered = stats.current_depth as usize * 80 + pipeline.stretched.len();
+        assert_eq!(
+            buffered,
+            6 * 80 - (stats.compressed_ms * 8.0).round() as usize
+        );
+
+        // Without a click, and at the sender's frame size throughout
+        let samples: Vec<f32> = frames.iter().flat_map(|f| f.samples.clone()).collect();
+        assert!(frames.iter().all(|f| f.samples.len() == 80 && !f.concealed));
+        assert!(samples.windows(2).all(|w| (w[1] - w[0]).abs() < 0.1));
+    }
+
+    #[test]
+    fn test_delay_increase_stretches_playout() {
+        let lost = |sequence: u32| sequence % 10 == 5;
+        let (stretched, frames) = play_adaptive(2, lost, true, 200);
+        let (plain, _) = play_adaptive(2, lost, false, 200);
+
+        let stats = stretched.stats().jitter_buffer;
+        assert_eq!(stats.current_delay_frames, 7);
+        assert!(stats.stretched_ms >= 40.0);
+        assert_eq!(stats.compressed_ms, 0.0);
+        assert_eq!(plain.stats().jitter_buffer.stretched_ms, 0.0);
+        // The buffer grew by the delay changes played out so far
+        assert_eq!(
+            stats.current_depth,
+            plain.stats().jitter_buffer.current_depth + 4
+        );
+        assert!(frames.iter().all(|f| f.samples.len() == 80));
+    }
+}
@@ -0,0 +1,261 @@
+//! RTCP-style receiver reports
+//!
+//! Only the receiving side of an audio stream sees its loss and jitter. Once
+//! a second it sends the sender a `ReceiverReport` (RFC 3550 §6.4.1): the
+//! highest sequence number received, cumulative and fraction lost,
+//! interarrival jitter and its jitter buffer delay. The sender keeps the
+//! latest one as "how my audio arrives at them".
+//!
+//! Interarrival jitter follows RFC 3550 §A.8: the difference in relative
+//! transit time of consecutive packets, smoothed with a gain of 1/16. It is
+//! computed in microseconds rather than timestamp units so it does not
+//! depend on the stream's sample rate.
+
+use std::time::{Duration, Instant};
+
+use crate::protocol::ReceiverReport;
+
+use super::sequence_tracker::SequenceTracker;
+
+/// How often receiver reports are sent
+pub(crate) const RECEIVER_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Allowance for the timers reports are polled from firing a little early
+const REPORT_TIMER_SLACK: Duration = Duration::from_millis(50);
+
+/// How our audio arrives at the peer, from its latest receiver report
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RemoteReceptionStats {
+    /// Highest audio sequence number the peer has received
+    pub highest_sequence: u32,
+    /// Audio packets the peer has lost since the stream started
+    pub packets_lost: u32,
+    /// Fraction of audio packets lost in the report's interval (0.0 - 1.0)
+    pub fraction_lost: f32,
+    /// Interarrival jitter at the peer (ms)
+    pub jitter_ms: f32,
+    /// Peer's jitter buffer delay (ms, None if it has no jitter buffer)
+    pub jitter_buffer_delay_ms: Option<f32>,
+    /// When the report arrived, in `local_clock_us` microseconds
+    pub received_at_us: u64,
+}
+
+impl RemoteReceptionStats {
+    /// Stats from a report that arrived at `received_at_us`
+    pub(crate) fn from_report(report: &ReceiverReport, received_at_us: u64) -> Self {
+        Self {
+            highest_sequence: report.highest_sequence,
+            packets_lost: report.cumulative_lost,
+            fraction_lost: report.fraction_lost as f32 / 256.0,
+            jitter_ms: report.jitter_us as f32 / 1000.0,
+            jitter_buffer_delay_ms: report
+                .jitter_buffer_delay_us
+                .map(|delay| delay as f32 / 1000.0),
+            received_at_us,
+        }
+    }
+}
+
+/// Receiving side of one audio stream: tracks interarrival jitter and builds
+/// the periodic reports
+pub(crate) struct ReceptionReporter {
+    sample_rate: u32,
+    /// Interarrival jitter estimate (µs)
+    jitter_us: f64,
+    /// Arrival time and timestamp of the previous audio packet
+    last_arrival: Option<(u64, u32)>,
+    /// Packets expected and lost when the previous report was built
+    expected_prior: u64,
+    lost_prior: u64,
+    last_report: Option<Instant>,
+}
+
+impl ReceptionReporter {
+    /// Create a reporter for a stream with `sample_rate` timestamps
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            jitter_us: 0.0,
+            last_arrival: None,
+            expected_prior: 0,
+            lost_prior: 0,
+            last_report: None,
+        }
+    }
+
+    /// Update the jitter estimate with an audio packet that arrived at
+    /// `arrival_us`
+    pub(crate) fn on_packet(&mut self, timestamp: u32, arrival_us: u64) {
+        if let Some((last_us, last_timestamp)) = self.last_arrival {
+            let arrival_delta = arrival_us as f64 - last_us as f64;
+            let samples = timestamp.wrapping_sub(last_timestamp) as i32 as f64;
+            let send_delta = samples * 1e6 / self.sample_rate as f64;
+            let d = (arrival_delta - send_delta).abs();
+            self.jitter_us += (d - self.jitter_us) / 16.0;
+        }
+        self.last_arrival = Some((arrival_us, timestamp));
+    }
+
+    /// Build a report if one is due and audio has arrived
+    pub(crate) fn poll(
+        &mut self,
+        now: Instant,
+        tracker: &SequenceTracker,
+        jitter_buffer_delay_ms: Option<f32>,
+    ) -> Option<ReceiverReport> {
+        if tracker.packets_received() == 0
+            || self.last_report.is_some_and(|at| {
+                now.duration_since(at) + REPORT_TIMER_SLACK < RECEIVER_REPORT_INTERVAL
+            })
+        {
+            return None;
+        }
+        self.last_report = Some(now);
+        Some(self.report(tracker, jitter_buffer_delay_ms))
+    }
+
+    /// Build a report covering the interval since the previous one
+    fn report(
+        &mut self,
+        tracker: &SequenceTracker,
+        jitter_buffer_delay_ms: Option<f32>,
+    ) -> ReceiverReport {
+        let stats = tracker.stats();
+        let lost = stats.packets_lost;
+        let expected = tracker
+            .packets_received()
+            .saturating_sub(stats.packets_duplicated)
+            + lost;
+
+        // Late arrivals can make the interval's loss negative
+        let expected_interval = expected.saturating_sub(self.expected_prior);
+        let lost_interval = lost.saturating_sub(self.lost_prior);
+        self.expected_prior = expected;
+        self.lost_prior = lost;
+        let fraction_lost = (lost_interval * 256)
+            .checked_div(expected_interval)
+            .map_or(0, |fraction| fraction.min(255) as u8);
+
+        ReceiverReport {
+            highest_sequence: tracker.highest_sequence(),
+            cumulative_lost: lost.min(u32::MAX as u64) as u32,
+            fraction_lost,
+            jitter_us: self.jitter_us as u32,
+            jitter_buffer_delay_us: jitter_buffer_delay_ms.map(|delay| (delay * 1000.0) as u32),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_report_before_audio() {
+        let mut reporter = ReceptionReporter::new(48_000);
+        let tracker = SequenceTracker::new();
+        assert_eq!(reporter.poll(Instant::now(), &tracker, None), None);
+    }
+
+    #[test]
+    fn test_fraction_lost_covers_interval() {
+        let mut reporter = ReceptionReporter::new(48_000);
+        let mut tracker = SequenceTracker::new();
+        let start = Instant::now();
+
+        // 4 of the first 100 lost
+        for sequence in 0..100 {
+            if sequence % 25 != 1 {
+                tracker.record(sequence);
+            }
+        }
+        let report = reporter.poll(start, &tracker, Some(10.0)).unwrap();
+        assert_eq!(report.highest_sequence, 99);
+        assert_eq!(report.cumulative_lost, 4);
+        assert_eq!(report.fraction_lost, (4 * 256 / 100) as u8);
+        assert_eq!(report.jitter_buffer_delay_us, Some(10_000));
+
+        // Not due yet
+        assert_eq!(
+            reporter.poll(start + Duration::from_millis(500), &tracker, None),
+            None
+        );
+
+        // A clean interval
+        for sequence in 100..200 {
+            tracker.record(sequence);
+        }
+        let report = reporter
+            .poll(start + RECEIVER_REPORT_INTERVAL, &tracker, None)
+            .unwrap();
+        assert_eq!(report.highest_sequence, 199);
+        assert_eq!(report.cumulative_lost, 4);
+        assert_eq!(report.fraction_lost, 0);
+        assert_eq!(report.jitter_buffer_delay_us, None);
+    }
+
+    #[test]
+    fn test_late_arrival_does_not_underflow() {
+        let mut reporter = ReceptionReporter::new(48_000);
+        let mut tracker = SequenceTracker::new();
+        let start = Instant::now();
+
+        tracker.record(0);
+        tracker.record(2);
+        assert_eq!(
+            reporter
+                .poll(start, &tracker, None)
+                .unwrap()
+                .cumulative_lost,
+            1
+        );
+
+        // 1 arrives late, taking back the loss
+        tracker.record(1);
+        let report = reporter
+            .poll(start + RECEIVER_REPORT_INTERVAL, &tracker, None)
+            .unwrap();
+        assert_eq!(report.cumulative_lost, 0);
+        assert_eq!(report.fraction_lost, 0);
+    }
+
+    #[test]
+    fn test_interarrival_jitter() {
+        // 48 kHz, 480-sample frames every 10 ms
+        let mut reporter = ReceptionReporter::new(48_000);
+        for i in 0..200u32 {
+            reporter.on_packet(i.wrapping_mul(480), i as u64 * 10_000);
+        }
+        assert!(reporter.jitter_us < 1.0);
+
+        // Alternating 2 ms early/late: every transit difference is 4 ms
+        let mut reporter = ReceptionReporter::new(48_000);
+        for i in 0..200u32 {
+            let wobble = if i % 2 == 0 { 0 } else { 4_000 };
+            reporter.on_packet(i * 480, i as u64 * 10_000 + wobble);
+        }
+        assert!((reporter.jitter_us - 4_000.0).abs() < 10.0);
+
+        // Timestamps wrap around
+        let mut reporter = ReceptionReporter::new(48_000);
+        reporter.on_packet(u32::MAX - 479, 0);
+        reporter.on_packet(0, 10_000);
+        assert!(reporter.jitter_us < 1.0);
+    }
+
+    #[test]
+    fn test_remote_stats_from_report() {
+        let report = ReceiverReport {
+            highest_sequence: 10,
+            cumulative_lost: 3,
+            fraction_lost: 128,
+            jitter_us: 2_500,
+            jitter_buffer_delay_us: Some(12_000),
+        };
+        let stats = RemoteReceptionStats::from_report(&report, 42);
+        assert_eq!(stats.fraction_lost, 0.5);
+        assert_eq!(stats.jitter_ms, 2.5);
+        assert_eq!(stats.jitter_buffer_delay_ms, Some(12.0));
+        assert_eq!(stats.received_at_us, 42);
+    }
+}
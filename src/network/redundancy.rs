@@ -0,0 +1,343 @@
+//! Redundant audio encoding (low-delay FEC)
+//!
+//! Each audio packet also carries copies of the previous one or two frames
+//! (after RFC 2198), optionally at reduced quality: 16-bit instead of f32
+//! PCM, or Opus at a lower bitrate. When a packet is lost, the copy of its
+//! frame arrives with the next packet and the receive pipeline plays it in
+//! the lost frame's slot instead of concealing. Unlike XOR or Reed-Solomon
+//! FEC nothing waits for a group to complete, so recovery adds no delay; the
+//! price is sending every frame up to three times.
+//!
+//! Redundancy is only sent to peers that announce it in their hello, since
+//! older peers would play the whole payload as audio.
+
+use std::collections::VecDeque;
+
+use tracing::warn;
+
+use crate::audio::{create_codec, AudioCodec, CodecConfig, CodecError, CodecType};
+use crate::protocol::{Packet, RedundancyPayload, RedundantEncoding, RedundantFrame};
+
+use super::codec_negotiation::encode_pcm;
+use super::fec::RecoveredAudio;
+
+/// Most previous frames carried in an audio packet
+pub const MAX_REDUNDANCY_DEPTH: usize = 2;
+
+/// Bitrate of reduced-quality Opus copies in bits per second
+pub const REDUCED_OPUS_BITRATE: u32 = 24_000;
+
+/// Redundant audio policy for outgoing audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RedundancyConfig {
+    /// Previous frames carried in each audio packet (0 disables redundancy)
+    pub depth: usize,
+    /// Send the copies as 16-bit PCM or low-bitrate Opus instead of
+    /// repeating the original frames
+    pub reduced: bool,
+}
+
+impl RedundancyConfig {
+    /// No redundant audio
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Carry full-quality copies of the previous `depth` frames (at most
+    /// `MAX_REDUNDANCY_DEPTH`)
+    pub fn with_depth(depth: usize) -> Self {
+        Self {
+            depth: depth.min(MAX_REDUNDANCY_DEPTH),
+            reduced: false,
+        }
+    }
+
+    /// Carry reduced-quality copies of the previous `depth` frames
+    pub fn reduced(depth: usize) -> Self {
+        Self {
+            reduced: true,
+            ..Self::with_depth(depth)
+        }
+    }
+
+    /// Check if audio packets carry previous frames
+    pub fn is_enabled(&self) -> bool {
+        self.depth > 0
+    }
+}
+
+/// Copy of a sent frame kept for the following packets
+struct SentFrame {
+    sequence: u32,
+    timestamp: u32,
+    encoding: RedundantEncoding,
+    data: Vec<u8>,
+}
+
+/// Adds copies of previous frames to outgoing audio packets of one stream
+pub(crate) struct RedundancyEncoder {
+    config: RedundancyConfig,
+    sample_rate: u32,
+    /// Copies of the last frames, oldest first
+    history: VecDeque<SentFrame>,
+    /// Low-bitrate encoder for reduced Opus copies, with its channels and
+    /// frame size
+    opus: Option<(u16, usize, Box<dyn AudioCodec>)>,
+    /// Reduced Opus copies failed to encode; the original frames are repeated
+    opus_failed: bool,
+}
+
+impl RedundancyEncoder {
+    /// Create an encoder for audio at `sample_rate`
+    pub fn new(config: RedundancyConfig, sample_rate: u32) -> Self {
+        Self {
+            config,
+            sample_rate,
+            history: VecDeque::new(),
+            opus: None,
+            opus_failed: false,
+        }
+    }
+
+    /// Turn an encoded audio packet into a redundant one
+    ///
+    /// The packet's payload becomes a `RedundancyPayload` with the copies of
+    /// the previous frames, and a copy of its own frame (encoded from
+    /// `samples` if reduced) is kept for the next packets. Does nothing when
+    /// redundancy is disabled.
+    pub fn protect(&mut self, packet: &mut Packet, samples: &[f32], channels: u16) {
+        if !self.config.is_enabled() {
+            return;
+        }
+
+        let frames = self
+            .history
+            .iter()
+            .filter_map(|sent| {
+                let distance = packet.sequence.wrapping_sub(sent.sequence);
+                let offset = packet.timestamp.wrapping_sub(sent.timestamp);
+                Some(RedundantFrame {
+                    distance: u8::try_from(distance).ok().filter(|&d| d > 0)?,
+                    timestamp_offset: u16::try_from(offset).ok()?,
+                    encoding: sent.encoding,
+                    data: sent.data.clone(),
+                })
+            })
+            .collect();
+
+        let (encoding, data) = self.copy(packet.flags.codec, &packet.payload, samples, channels);
+        self.history.push_back(SentFrame {
+            sequence: packet.sequence,
+            timestamp: packet.timestamp,
+            encoding,
+            data,
+        });
+        while self.history.len() > self.config.depth {
+            self.history.pop_front();
+        }
+
+        packet.payload = RedundancyPayload {
+            frames,
+            primary: std::mem::take(&mut packet.payload),
+        }
+        .to_bytes();
+        packet.flags.redundant = true;
+    }
+
+    /// Copy of a frame to send with the following packets
+    fn copy(
+        &mut self,
+        codec: CodecType,
+        primary: &[u8],
+        samples: &[f32],
+        channels: u16,
+    ) -> (RedundantEncoding, Vec<u8>) {
+        match (codec, self.config.reduced) {
+            (CodecType::Pcm, false) => (RedundantEncoding::Pcm, primary.to_vec()),
+            (CodecType::Pcm, true) => (RedundantEncoding::Pcm16, encode_pcm16(samples)),
+            (CodecType::Opus, true) if !self.opus_failed => {
+                match self.encode_opus(samples, channels) {
+                    Ok(data) => (RedundantEncoding::Opus, data),
+                    Err(e) => {
+                        warn!("Reduced Opus copies failed, repeating frames: {}", e);
+                        self.opus_failed = true;
+                        self.opus = None;
+                        (RedundantEncoding::Opus, primary.to_vec())
+                    }
+                }
+            }
+            (CodecType::Opus, _) => (RedundantEncoding::Opus, primary.to_vec()),
+        }
+    }
+
+    fn encode_opus(&mut self, samples: &[f32], channels: u16) -> Result<Vec<u8>, CodecError> {
+        let channels = channels.max(1);
+        let frame_size = samples.len() / channels as usize;
+        if !matches!(&self.opus, Some((c, f, _)) if *c == channels && *f == frame_size) {
+            let encoder = create_codec(&CodecConfig {
+                codec_type: CodecType::Opus,
+                sample_rate: self.sample_rate,
+                channels,
+                frame_size: frame_size as u32,
+                bitrate: REDUCED_OPUS_BITRATE,
+            })?;
+            self.opus = Some((channels, frame_size, encoder));
+        }
+        let (_, _, encoder) = self.opus.as_mut().expect("encoder was just created");
+        encoder.encode(samples)
+    }
+}
+
+/// Split the previous frames off a received audio packet
+///
+/// The packet keeps only its own frame as payload. The previous frames are
+/// returned with their sequence numbers and timestamps, 16-bit PCM copies
+/// widened to the f32 PCM wire format. Returns None if the payload is
+/// malformed.
+pub(crate) fn split_redundancy(packet: &mut Packet) -> Option<Vec<RecoveredAudio>> {
+    if !packet.flags.redundant {
+        return Some(Vec::new());
+    }
+    let payload = RedundancyPayload::from_bytes(&packet.payload)?;
+    packet.payload = payload.primary;
+    packet.flags.redundant = false;
+
+    let frames = payload
+        .frames
+        .into_iter()
+        .filter(|frame| frame.distance > 0)
+        .map(|frame| {
+            let (codec, payload) = match frame.encoding {
+                RedundantEncoding::Pcm => (CodecType::Pcm, frame.data),
+                RedundantEncoding::Opus => (CodecType::Opus, frame.data),
+                RedundantEncoding::Pcm16 => (CodecType::Pcm, decode_pcm16(&frame.data)),
+            };
+            RecoveredAudio {
+                sequence: packet.sequence.wrapping_sub(frame.distance as u32),
+                timestamp: packet.timestamp.wrapping_sub(frame.timestamp_offset as u32),
+                codec,
+                payload,
+            }
+        })
+        .collect();
+    Some(frames)
+}
+
+/// Serialize samples as little-endian 16-bit PCM
+fn encode_pcm16(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|&s| ((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect()
+}
+
+/// Widen little-endian 16-bit PCM to the f32 PCM wire format
+fn decode_pcm16(data: &[u8]) -> Vec<u8> {
+    let samples: Vec<f32> = data
+        .chunks_exact(2)
+        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / i16::MAX as f32)
+        .collect();
+    encode_pcm(&samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: f32) -> Vec<f32> {
+        vec![value; 4]
+    }
+
+    fn audio_packet(sequence: u32, samples: &[f32]) -> Packet {
+        let mut packet = Packet::audio(sequence, sequence * 4, encode_pcm(samples));
+        packet.flags.codec = CodecType::Pcm;
+        packet
+    }
+
+    #[test]
+    fn test_previous_frames_carried() {
+        let mut encoder = RedundancyEncoder::new(RedundancyConfig::with_depth(2), 48000);
+        let mut packets: Vec<Packet> = (0..4)
+            .map(|i| {
+                let samples = frame(i as f32 / 10.0);
+                let mut packet = audio_packet(i, &samples);
+                encoder.protect(&mut packet, &samples, 1);
+                packet
+            })
+            .collect();
+
+        // The first packet has nothing to carry yet
+        assert!(packets[0].flags.redundant);
+        assert!(split_redundancy(&mut packets[0]).unwrap().is_empty());
+        assert_eq!(packets[0].payload, encode_pcm(&frame(0.0)));
+
+        let redundant = split_redundancy(&mut packets[3]).unwrap();
+        assert_eq!(packets[3].payload, encode_pcm(&frame(0.3)));
+        assert!(!packets[3].flags.redundant);
+        assert_eq!(
+            redundant,
+            vec![
+                RecoveredAudio {
+                    sequence: 1,
+                    timestamp: 4,
+                    codec: CodecType::Pcm,
+                    payload: encode_pcm(&frame(0.1)),
+                },
+                RecoveredAudio {
+                    sequence: 2,
+                    timestamp: 8,
+                    codec: CodecType::Pcm,
+                    payload: encode_pcm(&frame(0.2)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduced_pcm_copies() {
+        let mut encoder = RedundancyEncoder::new(RedundancyConfig::reduced(1), 48000);
+        let first = frame(0.5);
+        let mut packet = audio_packet(7, &first);
+        encoder.protect(&mut packet, &first, 1);
+
+        let second = frame(-0.25);
+        let mut packet = audio_packet(8, &second);
+        encoder.protect(&mut packet, &second, 1);
+        let full_size = encode_pcm(&second).len();
+        // Count, one frame header and a 16-bit copy of four samples
+        assert_eq!(
+            packet.payload.len(),
+            1 + RedundancyPayload::FRAME_HEADER_SIZE + 8 + full_size
+        );
+
+        let redundant = split_redundancy(&mut packet).unwrap();
+        assert_eq!(redundant.len(), 1);
+        assert_eq!(redundant[0].sequence, 7);
+        assert_eq!(redundant[0].codec, CodecType::Pcm);
+        let samples: Vec<f32> = redundant[0]
+            .payload
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        assert!(samples.iter().all(|s| (s - 0.5).abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_disabled_leaves_packet_unchanged() {
+        let mut encoder = RedundancyEncoder::new(RedundancyConfig::disabled(), 48000);
+        let samples = frame(0.1);
+        let mut packet = audio_packet(0, &samples);
+        encoder.protect(&mut packet, &samples, 1);
+        assert!(!packet.flags.redundant);
+        assert_eq!(packet.payload, encode_pcm(&samples));
+
+        assert_eq!(RedundancyConfig::with_depth(5).depth, MAX_REDUNDANCY_DEPTH);
+    }
+
+    #[test]
+    fn test_malformed_payload_rejected() {
+        let mut packet = Packet::audio(1, 4, vec![3, 1]);
+        packet.flags.redundant = true;
+        assert!(split_redundancy(&mut packet).is_none());
+    }
+}
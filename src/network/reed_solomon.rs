@@ -0,0 +1,289 @@
+//! Reed-Solomon erasure code over GF(2^8)
+//!
+//! A systematic code built from a Cauchy matrix: `k` data shards are sent
+//! unchanged, followed by `m` parity shards. Every square submatrix of a
+//! Cauchy matrix is invertible, so any `k` of the `k + m` shards rebuild the
+//! data and a group survives any `m` losses (the code is MDS).
+//!
+//! Shards may differ in length; shorter ones count as zero-padded to the
+//! longest, which is also the length of every parity shard.
+
+/// Largest number of data plus parity shards (the Cauchy matrix needs
+/// distinct field elements for every row and column)
+pub const MAX_SHARDS: usize = 256;
+
+/// Reduction polynomial x^8 + x^4 + x^3 + x^2 + 1
+const POLYNOMIAL: u16 = 0x11D;
+
+struct Tables {
+    /// Powers of the generator, repeated so products need no modulo
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+const TABLES: Tables = build_tables();
+
+const fn build_tables() -> Tables {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= POLYNOMIAL;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    Tables { exp, log }
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    TABLES.exp[TABLES.log[a as usize] as usize + TABLES.log[b as usize] as usize]
+}
+
+fn inv(a: u8) -> u8 {
+    debug_assert!(a != 0, "zero has no inverse");
+    TABLES.exp[255 - TABLES.log[a as usize] as usize]
+}
+
+/// `target += coefficient * source` (addition is XOR)
+fn mul_add(target: &mut [u8], source: &[u8], coefficient: u8) {
+    if coefficient == 0 {
+        return;
+    }
+    for (t, &s) in target.iter_mut().zip(source) {
+        *t ^= mul(coefficient, s);
+    }
+}
+
+/// Invert a square matrix by Gauss-Jordan elimination
+fn invert(mut matrix: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<u8>> = (0..n)
+        .map(|row| (0..n).map(|col| (row == col) as u8).collect())
+        .collect();
+
+    for col in 0..n {
+        let pivot = (col..n).find(|&row| matrix[row][col] != 0)?;
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+
+        let scale = inv(matrix[col][col]);
+        for value in matrix[col].iter_mut() {
+            *value = mul(*value, scale);
+        }
+        for value in inverse[col].iter_mut() {
+            *value = mul(*value, scale);
+        }
+
+        for row in 0..n {
+            let factor = matrix[row][col];
+            if row == col || factor == 0 {
+                continue;
+            }
+            let (pivot_row, pivot_inverse) = (matrix[col].clone(), inverse[col].clone());
+            mul_add(&mut matrix[row], &pivot_row, factor);
+            mul_add(&mut inverse[row], &pivot_inverse, factor);
+        }
+    }
+    Some(inverse)
+}
+
+/// Reed-Solomon code with a fixed number of data and parity shards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReedSolomon {
+    data_shards: usize,
+    parity_shards: usize,
+}
+
+impl ReedSolomon {
+    /// Create a code with `data_shards` data and `parity_shards` parity shards
+    ///
+    /// Returns None unless both are at least 1 and together at most
+    /// `MAX_SHARDS`.
+    pub fn new(data_shards: usize, parity_shards: usize) -> Option<Self> {
+        if data_shards == 0 || parity_shards == 0 || data_shards + parity_shards > MAX_SHARDS {
+            return None;
+        }
+        Some(Self {
+            data_shards,
+            parity_shards,
+        })
+    }
+
+    /// Number of parity shards
+    pub fn parity_shards(&self) -> usize {
+        self.parity_shards
+    }
+
+    /// Cauchy matrix entry 1 / (x_parity + y_data) with x = k + parity and
+    /// y = data, all distinct
+    fn coefficient(&self, parity: usize, data: usize) -> u8 {
+        inv(((self.data_shards + parity) ^ data) as u8)
+    }
+
+    /// Compute the parity shards for a group of data shards
+    pub fn encode<T: AsRef<[u8]>>(&self, data: &[T]) -> Vec<Vec<u8>> {
+        debug_assert_eq!(data.len(), self.data_shards);
+        let len = data.iter().map(|d| d.as_ref().len()).max().unwrap_or(0);
+
+        (0..self.parity_shards)
+            .map(|parity| {
+                let mut shard = vec![0u8; len];
+                for (index, d) in data.iter().enumerate() {
+                    mul_add(&mut shard, d.as_ref(), self.coefficient(parity, index));
+                }
+                shard
+            })
+            .collect()
+    }
+
+    /// Rebuild lost data shards in place
+    ///
+    /// `data` holds the received data shards (None where lost) and `parity`
+    /// the received parity shards with their index. Rebuilt shards have the
+    /// parity length; trimming the padding is up to the caller. Returns false
+    /// if fewer than `k` shards were received.
+    pub fn reconstruct(&self, data: &mut [Option<Vec<u8>>], parity: &[(usize, &[u8])]) -> bool {
+        debug_assert_eq!(data.len(), self.data_shards);
+        let missing: Vec<usize> = (0..data.len()).filter(|&i| data[i].is_none()).collect();
+        if missing.is_empty() {
+            return true;
+        }
+        if parity.len() < missing.len() {
+            return false;
+        }
+        let parity = &parity[..missing.len()];
+        let len = parity.iter().map(|(_, p)| p.len()).max().unwrap_or(0);
+
+        // What the missing shards contribute to each parity shard
+        let syndromes: Vec<Vec<u8>> = parity
+            .iter()
+            .map(|&(row, shard)| {
+                let mut syndrome = vec![0u8; len];
+                syndrome[..shard.len()].copy_from_slice(shard);
+                for (index, d) in data.iter().enumerate() {
+                    if let Some(d) = d {
+                        mul_add(&mut syndrome, d, self.coefficient(row, index));
+                    }
+                }
+                syndrome
+            })
+            .collect();
+
+        let matrix = parity
+            .iter()
+            .map(|&(row, _)| {
+                missing
+                    .iter()
+                    .map(|&index| self.coefficient(row, index))
+                    .collect()
+            })
+            .collect();
+        let Some(inverse) = invert(matrix) else {
+            return false;
+        };
+
+        for (i, &index) in missing.iter().enumerate() {
+            let mut shard = vec![0u8; len];
+            for (j, syndrome) in syndromes.iter().enumerate() {
+                mul_add(&mut shard, syndrome, inverse[i][j]);
+            }
+            data[index] = Some(shard);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shards() -> Vec<Vec<u8>> {
+        vec![
+            vec![1, 2, 3, 4],
+            vec![5, 6, 7],
+            vec![9, 10, 11, 12],
+            vec![13, 14, 15, 16],
+            vec![200, 100],
+        ]
+    }
+
+    #[test]
+    fn test_field_arithmetic() {
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inv(a)), 1);
+            assert_eq!(mul(a, 1), a);
+            assert_eq!(mul(a, 0), 0);
+        }
+        assert_eq!(mul(2, 0x80), 0x1D);
+    }
+
+    #[test]
+    fn test_recovers_any_parity_count_losses() {
+        let code = ReedSolomon::new(5, 3).unwrap();
+        let data = shards();
+        let parity = code.encode(&data);
+        assert_eq!(parity.len(), 3);
+        assert!(parity.iter().all(|p| p.len() == 4));
+
+        // Every combination of up to three lost data shards
+        for lost in 0u32..32 {
+            if lost.count_ones() > 3 {
+                continue;
+            }
+            let mut received: Vec<Option<Vec<u8>>> = (0..5)
+                .map(|i| (lost & (1 << i) == 0).then(|| data[i].clone()))
+                .collect();
+            let available: Vec<(usize, &[u8])> = parity
+                .iter()
+                .enumerate()
+                .skip(3 - lost.count_ones() as usize)
+                .map(|(i, p)| (i, p.as_slice()))
+                .collect();
+
+            assert!(code.reconstruct(&mut received, &available));
+            for (i, shard) in received.iter().enumerate() {
+                let shard = shard.as_ref().unwrap();
+                assert_eq!(&shard[..data[i].len()], data[i].as_slice());
+            }
+        }
+    }
+
+    #[test]
+    fn test_too_many_losses() {
+        let code = ReedSolomon::new(5, 2).unwrap();
+        let data = shards();
+        let parity = code.encode(&data);
+
+        let mut received: Vec<Option<Vec<u8>>> = data.into_iter().map(Some).collect();
+        received[0] = None;
+        received[1] = None;
+        received[2] = None;
+        let available: Vec<(usize, &[u8])> = parity
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, p.as_slice()))
+            .collect();
+        assert!(!code.reconstruct(&mut received, &available));
+        assert!(received[0].is_none());
+    }
+
+    #[test]
+    fn test_shard_limits() {
+        assert!(ReedSolomon::new(0, 1).is_none());
+        assert!(ReedSolomon::new(4, 0).is_none());
+        assert!(ReedSolomon::new(250, 7).is_none());
+        assert!(ReedSolomon::new(250, 6).is_some());
+    }
+}
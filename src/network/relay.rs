@@ -0,0 +1,300 @@
+//! Relay server for peers that cannot reach each other directly
+//!
+//! A jamjam-native take on TURN (RFC 5766). A client sends
+//! `RelayMessage::Allocate` to the server and gets back a relayed address: a
+//! UDP port the server opened on its behalf. Anything arriving on that port is
+//! forwarded to the client as `RelayMessage::Data`, and `RelayMessage::Send`
+//! from the client goes out from that port, so remote peers talk to the
+//! relayed address as if it were the client itself.
+//!
+//! Unlike TURN there is no authentication and no per-peer permission list.
+//! An allocation expires when the client has sent nothing to the server for
+//! the allocation lifetime.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::protocol::{Packet, PacketType, RelayMessage};
+
+use super::error::NetworkError;
+
+/// Default allocation lifetime without client traffic
+pub const RELAY_ALLOCATION_LIFETIME: Duration = Duration::from_secs(30);
+
+/// A relayed port owned by one client
+struct Allocation {
+    socket: Arc<UdpSocket>,
+    last_seen: Instant,
+    forward_handle: JoinHandle<()>,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.forward_handle.abort();
+    }
+}
+
+/// Relay server handing out relayed addresses to clients
+pub struct RelayServer {
+    socket: Arc<UdpSocket>,
+    local_addr: SocketAddr,
+    lifetime: Duration,
+    allocations: Mutex<HashMap<SocketAddr, Allocation>>,
+}
+
+impl RelayServer {
+    /// Bind the relay server's control socket
+    pub async fn bind(addr: &str) -> Result<Self, NetworkError> {
+        let addr: SocketAddr = addr.parse()?;
+        let socket = UdpSocket::bind(addr).await?;
+        let local_addr = socket.local_addr()?;
+        info!("Relay server bound to {}", local_addr);
+
+        Ok(Self {
+            socket: Arc::new(socket),
+            local_addr,
+            lifetime: RELAY_ALLOCATION_LIFETIME,
+            allocations: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Get the address clients send relay messages to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Set how long an allocation survives without client traffic
+    pub fn set_allocation_lifetime(&mut self, lifetime: Duration) {
+        self.lifetime = lifetime;
+    }
+
+    /// Number of live allocations
+    pub fn allocation_count(&self) -> usize {
+        self.allocations.lock().len()
+    }
+
+    /// Serve relay messages until the socket fails
+    pub async fn run(&self) -> Result<(), NetworkError> {
+        let mut sweep = tokio::time::interval(self.lifetime / 2);
+        let mut buf = vec![0u8; 2048];
+
+        loop {
+            tokio::select! {
+                result = self.socket.recv_from(&mut buf) => {
+                    let (len, client) = result?;
+                    self.handle_datagram(&buf[..len], client).await;
+                }
+                _ = sweep.tick() => self.expire_allocations(),
+            }
+        }
+    }
+
+    async fn handle_datagram(&self, data: &[u8], client: SocketAddr) {
+        let Some(packet) = Packet::from_bytes(data) else {
+            return;
+        };
+        if packet.packet_type != PacketType::Relay {
+            return;
+        }
+        let Some(message) = RelayMessage::from_bytes(&packet.payload) else {
+            debug!("Invalid relay message from {}", client);
+            return;
+        };
+
+        match message {
+            RelayMessage::Allocate => match self.allocate(client).await {
+                Ok(relayed_addr) => {
+                    let reply = Packet::relay(0, &RelayMessage::AllocateOk { relayed_addr });
+                    if let Err(e) = self.socket.send_to(&reply.to_bytes(), client).await {
+                        warn!("Failed to send allocation to {}: {}", client, e);
+                    }
+                }
+                Err(e) => warn!("Failed to allocate relay for {}: {}", client, e),
+            },
+            RelayMessage::Refresh => {
+                if self.touch(client).is_none() {
+                    debug!("Refresh from {} without allocation", client);
+                }
+            }
+            RelayMessage::Send { peer, data } => {
+                let Some(socket) = self.touch(client) else {
+                    debug!("Send from {} without allocation", client);
+                    return;
+                };
+                if let Err(e) = socket.send_to(&data, peer).await {
+                    debug!("Failed to relay {} bytes to {}: {}", data.len(), peer, e);
+                }
+            }
+            RelayMessage::AllocateOk { .. } | RelayMessage::Data { .. } => {
+                debug!("Ignoring server-side relay message from {}", client);
+            }
+        }
+    }
+
+    /// Get (or create) the client's allocation and return its relayed address
+    async fn allocate(&self, client: SocketAddr) -> Result<SocketAddr, NetworkError> {
+        if let Some(socket) = self.touch(client) {
+            return Ok(socket.local_addr()?);
+        }
+
+        let socket = Arc::new(UdpSocket::bind(SocketAddr::new(self.local_addr.ip(), 0)).await?);
+        let relayed_addr = socket.local_addr()?;
+        let forward_handle = tokio::spawn(forward_to_client(
+            socket.clone(),
+            self.socket.clone(),
+            client,
+        ));
+
+        self.allocations.lock().insert(
+            client,
+            Allocation {
+                socket,
+                last_seen: Instant::now(),
+                forward_handle,
+            },
+        );
+        info!("Allocated relay {} for {}", relayed_addr, client);
+        Ok(relayed_addr)
+    }
+
+    /// Mark client traffic and return the relayed socket
+    fn touch(&self, client: SocketAddr) -> Option<Arc<UdpSocket>> {
+        let mut allocations = self.allocations.lock();
+        let allocation = allocations.get_mut(&client)?;
+        allocation.last_seen = Instant::now();
+        Some(allocation.socket.clone())
+    }
+
+    fn expire_allocations(&self) {
+        let lifetime = self.lifetime;
+        self.allocations.lock().retain(|client, allocation| {
+            let alive = allocation.last_seen.elapsed() < lifetime;
+            if !alive {
+                info!("Relay allocation for {} expired", client);
+            }
+            alive
+        });
+    }
+}
+
+/// Forward everything arriving on a relayed port to its owner
+async fn forward_to_client(relayed: Arc<UdpSocket>, server: Arc<UdpSocket>, client: SocketAddr) {
+    let mut buf = vec![0u8; 2048];
+    loop {
+        let (len, peer) = match relayed.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                debug!("Relayed socket for {} failed: {}", client, e);
+                continue;
+            }
+        };
+        let data = RelayMessage::Data {
+            peer,
+            data: buf[..len].to_vec(),
+        };
+        if let Err(e) = server
+            .send_to(&Packet::relay(0, &data).to_bytes(), client)
+            .await
+        {
+            debug!("Failed to deliver relayed data to {}: {}", client, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::UdpTransport;
+
+    async fn start_relay(lifetime: Duration) -> (Arc<RelayServer>, JoinHandle<()>) {
+        let mut server = RelayServer::bind("127.0.0.1:0").await.unwrap();
+        server.set_allocation_lifetime(lifetime);
+        let server = Arc::new(server);
+        let runner = server.clone();
+        let handle = tokio::spawn(async move {
+            let _ = runner.run().await;
+        });
+        (server, handle)
+    }
+
+    #[tokio::test]
+    async fn test_relay_forwards_both_ways() {
+        let (server, handle) = start_relay(RELAY_ALLOCATION_LIFETIME).await;
+        let client = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let peer = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+
+        let relayed_addr = client.allocate_relay(server.local_addr()).await.unwrap();
+        assert_eq!(client.relayed_addr(), Some(relayed_addr));
+        assert_ne!(relayed_addr, server.local_addr());
+        assert_eq!(server.allocation_count(), 1);
+
+        // Peer -> relayed address -> client, reported as coming from the peer
+        peer.send_to(&Packet::audio(1, 0, vec![1, 2, 3]), relayed_addr)
+            .await
+            .unwrap();
+        let (packet, from) = client.recv_from().await.unwrap();
+        assert_eq!(from, peer.local_addr());
+        assert_eq!(packet.payload, vec![1, 2, 3]);
+        assert!(client.is_relayed(peer.local_addr()));
+
+        // Client -> relay -> peer, arriving from the relayed address
+        client
+            .send_to(&Packet::audio(2, 0, vec![4, 5]), peer.local_addr())
+            .await
+            .unwrap();
+        let (packet, from) = peer.recv_from().await.unwrap();
+        assert_eq!(from, relayed_addr);
+        assert_eq!(packet.sequence, 2);
+        assert_eq!(packet.payload, vec![4, 5]);
+
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_allocate_is_idempotent() {
+        let (server, handle) = start_relay(RELAY_ALLOCATION_LIFETIME).await;
+        let client = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+
+        let first = client.allocate_relay(server.local_addr()).await.unwrap();
+        let second = client.allocate_relay(server.local_addr()).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(server.allocation_count(), 1);
+
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_allocation_expires_without_refresh() {
+        let (server, handle) = start_relay(Duration::from_millis(100)).await;
+        let client = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        client.allocate_relay(server.local_addr()).await.unwrap();
+
+        for _ in 0..6 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.refresh_relay().await.unwrap();
+        }
+        assert_eq!(server.allocation_count(), 1);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(server.allocation_count(), 0);
+
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_allocate_without_server_fails() {
+        let client = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let dead = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+
+        let result = client.allocate_relay(dead.local_addr()).await;
+        assert!(matches!(result, Err(NetworkError::RelayFailed(_))));
+        assert_eq!(client.relayed_addr(), None);
+    }
+}
@@ -0,0 +1,478 @@
+//! Offline replay of packet captures
+//!
+//! Feeds a capture (see `capture`) through the same receive path a
+//! `Connection` uses (FEC recovery, jitter buffer, decoder, PLC) on a
+//! simulated playout clock, so a glitch can be heard and inspected after the
+//! fact. The playout clock starts at the first audio arrival and ticks once
+//! per local frame; ticks with nothing to play are rendered as silence.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::protocol::PacketType;
+
+use super::capture::CapturedPacket;
+use super::error::NetworkError;
+use super::fec::{FecPacket, FecStreamDecoder, RecoveredAudio};
+use super::receive_pipeline::{ReceivePipeline, ReceivePipelineConfig, ReceivePipelineStats};
+use super::redundancy::split_redundancy;
+use super::sequence_tracker::{LossStats, SequenceTracker};
+
+/// Replay settings
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    /// Receive pipeline to replay through (local codec and jitter buffer)
+    pub pipeline: ReceivePipelineConfig,
+    /// Replay audio from this source only (None for the first source that
+    /// sent audio)
+    pub source: Option<SocketAddr>,
+    /// Length of each timeline interval
+    pub interval: Duration,
+}
+
+impl ReplayConfig {
+    /// Replay through a pipeline with one-second timeline intervals
+    pub fn new(pipeline: ReceivePipelineConfig) -> Self {
+        Self {
+            pipeline,
+            source: None,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Receive statistics for one interval of a replay
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayInterval {
+    /// Start of the interval after the first audio arrival (ms)
+    pub start_ms: u64,
+    /// Audio packets received
+    pub packets_received: u64,
+    /// Audio packets lost in transit (before FEC recovery)
+    pub packets_lost: u64,
+    /// Lost audio packets rebuilt from FEC in time for playout
+    pub packets_recovered: u64,
+    /// Frames decoded from received packets
+    pub frames_decoded: u64,
+    /// Frames generated by packet loss concealment
+    pub frames_concealed: u64,
+    /// Playout ticks with nothing to play (rendered as silence)
+    pub underruns: u64,
+    /// Jitter buffer delay at the end of the interval (ms)
+    pub jitter_buffer_delay_ms: f32,
+}
+
+/// Result of a replay
+#[derive(Debug, Clone)]
+pub struct ReplayOutput {
+    /// Source whose audio was replayed (None if the capture has no audio)
+    pub source: Option<SocketAddr>,
+    /// Rendered interleaved audio
+    pub samples: Vec<f32>,
+    /// Statistics per interval
+    pub timeline: Vec<ReplayInterval>,
+    /// Receive pipeline statistics at the end of the replay
+    pub pipeline: ReceivePipelineStats,
+    /// Audio packets received
+    pub packets_received: u64,
+    /// Audio sequence loss statistics
+    pub loss: LossStats,
+    /// Lost audio packets rebuilt from FEC in time for playout
+    pub packets_recovered: u64,
+    /// Playout ticks with nothing to play
+    pub underruns: u64,
+}
+
+/// Replay captured packets through the receive pipeline
+///
+/// Packets must be in arrival order, as a capture file stores them.
+///
+/// # Errors
+/// Returns the first error from `packets`, or a codec error if the pipeline
+/// codec is not available in this build.
+pub fn replay<I>(packets: I, config: &ReplayConfig) -> Result<ReplayOutput, NetworkError>
+where
+    I: IntoIterator<Item = Result<CapturedPacket, NetworkError>>,
+{
+    let mut replayer = Replayer::new(config)?;
+    for captured in packets {
+        replayer.handle(captured?);
+    }
+    Ok(replayer.finish())
+}
+
+/// Cumulative counters the timeline is built from
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    packets_received: u64,
+    packets_lost: u64,
+    packets_recovered: u64,
+    frames_decoded: u64,
+    frames_concealed: u64,
+    underruns: u64,
+}
+
+struct Replayer {
+    pipeline: ReceivePipeline,
+    fec: FecStreamDecoder,
+    tracker: SequenceTracker,
+    source: Option<SocketAddr>,
+    passthrough: bool,
+    frame_duration: Duration,
+    /// Interleaved samples in one local frame (silence on underrun)
+    frame_samples: usize,
+    samples_per_second: u64,
+    /// Arrival time of the first audio (start of the replay clock)
+    start_us: Option<u64>,
+    /// Replay clock at the packet being handled
+    now: Duration,
+    next_tick: Duration,
+    /// Audio played beyond the ticks so far (senders with larger frames)
+    ahead: Duration,
+    samples: Vec<f32>,
+    packets_recovered: u64,
+    underruns: u64,
+    interval: Duration,
+    interval_index: u32,
+    interval_start: Totals,
+    timeline: Vec<ReplayInterval>,
+}
+
+impl Replayer {
+    fn new(config: &ReplayConfig) -> Result<Self, NetworkError> {
+        let codec = &config.pipeline.codec;
+        Ok(Self {
+            pipeline: ReceivePipeline::new(config.pipeline.clone())?,
+            fec: FecStreamDecoder::new(),
+            tracker: SequenceTracker::new(),
+            source: config.source,
+            passthrough: config.pipeline.is_passthrough(),
+            frame_duration: config.pipeline.frame_duration(),
+            frame_samples: codec.frame_size as usize * codec.channels as usize,
+            samples_per_second: (codec.sample_rate as u64 * codec.channels as u64).max(1),
+            start_us: None,
+            now: Duration::ZERO,
+            next_tick: Duration::ZERO,
+            ahead: Duration::ZERO,
+            samples: Vec::new(),
+            packets_recovered: 0,
+            underruns: 0,
+            interval: config.interval.max(Duration::from_millis(1)),
+            interval_index: 0,
+            interval_start: Totals::default(),
+            timeline: Vec::new(),
+        })
+    }
+
+    fn handle(&mut self, captured: CapturedPacket) {
+        let mut packet = captured.packet;
+        // Only the main stream is replayed
+        if !matches!(packet.packet_type, PacketType::Audio | PacketType::Fec)
+            || packet.stream.is_some()
+        {
+            return;
+        }
+        match self.source {
+            Some(source) if source != captured.source => return,
+            Some(_) => {}
+            None if packet.packet_type == PacketType::Audio => {
+                self.source = Some(captured.source);
+            }
+            None => return,
+        }
+
+        let start_us = *self.start_us.get_or_insert(captured.arrival_us);
+        let now = Duration::from_micros(captured.arrival_us.saturating_sub(start_us));
+        self.advance(now);
+        self.roll_timeline(now);
+        self.now = now;
+
+        match packet.packet_type {
+            PacketType::Audio => {
+                self.tracker.record(packet.sequence);
+                let Some(redundant) = split_redundancy(&mut packet) else {
+                    return;
+                };
+                for frame in redundant {
+                    self.pipeline.insert_redundant(
+                        frame.sequence,
+                        frame.timestamp,
+                        frame.codec,
+                        frame.payload,
+                    );
+                }
+                if self.fec.contains(packet.sequence) {
+                    // Already rebuilt from FEC
+                    return;
+                }
+                let recovered = self.fec.add_packet_all(
+                    packet.sequence,
+                    packet.timestamp,
+                    packet.flags.codec,
+                    &packet.payload,
+                );
+                self.deliver(
+                    RecoveredAudio {
+                        sequence: packet.sequence,
+                        timestamp: packet.timestamp,
+                        codec: packet.flags.codec,
+                        payload: packet.payload,
+                    },
+                    false,
+                );
+                for recovered in recovered {
+                    self.deliver(recovered, true);
+                }
+            }
+            PacketType::Fec => {
+                let recovered = FecPacket::from_bytes(&packet.payload)
+                    .map(|fec| self.fec.add_fec_all(fec))
+                    .unwrap_or_default();
+                for recovered in recovered {
+                    self.deliver(recovered, true);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Hand audio to the pipeline (late recovered packets are dropped)
+    fn deliver(&mut self, audio: RecoveredAudio, recovered: bool) {
+        if recovered {
+            if self.pipeline.is_late(audio.sequence) {
+                return;
+            }
+            self.packets_recovered += 1;
+        }
+        self.pipeline.insert_at(
+            audio.sequence,
+            audio.timestamp,
+            audio.codec,
+            audio.payload,
+            self.now.as_micros() as u64,
+        );
+
+        // Passthrough mode plays frames on arrival
+        if self.passthrough {
+            for frame in self.pipeline.drain_ready() {
+                self.samples.extend_from_slice(&frame.samples);
+            }
+        }
+    }
+
+    /// Run the playout clock up to `now`
+    fn advance(&mut self, now: Duration) {
+        if self.passthrough {
+            return;
+        }
+        while self.next_tick <= now {
+            self.tick();
+        }
+    }
+
+    /// Play one local frame
+    fn tick(&mut self) {
+        let now = self.next_tick;
+        self.next_tick += self.frame_duration;
+        self.roll_timeline(now);
+
+        if self.ahead >= self.frame_duration {
+            self.ahead -= self.frame_duration;
+            return;
+        }
+        match self.pipeline.pop_frame_at(now.as_micros() as u64) {
+            Some(frame) => {
+                let nanos = frame.samples.len() as u64 * 1_000_000_000 / self.samples_per_second;
+                self.ahead =
+                    (self.ahead + Duration::from_nanos(nanos)).saturating_sub(self.frame_duration);
+                self.samples.extend_from_slice(&frame.samples);
+            }
+            None => {
+                self.underruns += 1;
+                self.samples
+                    .resize(self.samples.len() + self.frame_samples, 0.0);
+            }
+        }
+    }
+
+    /// Close every timeline interval that ended by `now`
+    fn roll_timeline(&mut self, now: Duration) {
+        while now >= self.interval * (self.interval_index + 1) {
+            self.close_interval();
+        }
+    }
+
+    fn close_interval(&mut self) {
+        let totals = self.totals();
+        let start = self.interval_start;
+        self.timeline.push(ReplayInterval {
+            start_ms: (self.interval * self.interval_index).as_millis() as u64,
+            packets_received: totals.packets_received - start.packets_received,
+            packets_lost: totals.packets_lost - start.packets_lost,
+            packets_recovered: totals.packets_recovered - start.packets_recovered,
+            frames_decoded: totals.frames_decoded - start.frames_decoded,
+            frames_concealed: totals.frames_concealed - start.frames_concealed,
+            underruns: totals.underruns - start.underruns,
+            jitter_buffer_delay_ms: self.pipeline.jitter_buffer_delay_ms(),
+        });
+        self.interval_start = totals;
+        self.interval_index += 1;
+    }
+
+    fn totals(&self) -> Totals {
+        let stats = self.pipeline.stats();
+        Totals {
+            packets_received: self.tracker.packets_received(),
+            packets_lost: self.tracker.packets_lost(),
+            packets_recovered: self.packets_recovered,
+            frames_decoded: stats.frames_decoded,
+            frames_concealed: stats.frames_concealed,
+            underruns: self.underruns,
+        }
+    }
+
+    /// Play out what is still buffered and close the last interval
+    fn finish(mut self) -> ReplayOutput {
+        if self.start_us.is_some() {
+            if !self.passthrough {
+                // One tick per buffered frame plus the playout delay; packets
+                // stranded behind the playout position are never played
+                let buffer = self.pipeline.stats().jitter_buffer;
+                for _ in 0..buffer.current_depth + buffer.current_delay_frames {
+                    if self.pipeline.stats().jitter_buffer.current_depth == 0 {
+                        break;
+                    }
+                    self.tick();
+                }
+            }
+            self.close_interval();
+        }
+
+        ReplayOutput {
+            source: self.source,
+            pipeline: self.pipeline.stats(),
+            packets_received: self.tracker.packets_received(),
+            loss: self.tracker.stats(),
+            packets_recovered: self.packets_recovered,
+            underruns: self.underruns,
+            samples: self.samples,
+            timeline: self.timeline,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{CodecConfig, CodecType};
+    use crate::network::{FecStreamEncoder, JitterBufferMode};
+    use crate::protocol::Packet;
+
+    const FRAME_SIZE: u32 = 48;
+    /// 48 samples at 48 kHz
+    const FRAME_US: u64 = 1000;
+
+    fn pipeline_config(mode: JitterBufferMode) -> ReceivePipelineConfig {
+        let codec = CodecConfig {
+            frame_size: FRAME_SIZE,
+            channels: 1,
+            ..Default::default()
+        };
+        ReceivePipelineConfig::new(mode, codec)
+    }
+
+    fn pcm(value: f32) -> Vec<u8> {
+        (0..FRAME_SIZE).flat_map(|_| value.to_le_bytes()).collect()
+    }
+
+    fn captured(arrival_us: u64, packet: Packet) -> Result<CapturedPacket, NetworkError> {
+        Ok(CapturedPacket {
+            arrival_us,
+            source: "192.0.2.1:5000".parse().unwrap(),
+            packet,
+        })
+    }
+
+    /// Audio packets one frame apart, skipping `lost`, with FEC every `group`
+    fn stream(count: u32, lost: &[u32], group: usize) -> Vec<Result<CapturedPacket, NetworkError>> {
+        let mut encoder = FecStreamEncoder::new(group);
+        let mut packets = Vec::new();
+        for i in 0..count {
+            let arrival = 10_000 + i as u64 * FRAME_US;
+            let timestamp = i * FRAME_SIZE;
+            let payload = pcm(i as f32 / 1000.0);
+            let fec = encoder.add_packet(i, timestamp, CodecType::Pcm, &payload);
+            if !lost.contains(&i) {
+                packets.push(captured(arrival, Packet::audio(i, timestamp, payload)));
+            }
+            if let Some(fec) = fec {
+                let packet = Packet::fec(encoder.next_sequence(), fec.to_bytes());
+                packets.push(captured(arrival, packet));
+            }
+        }
+        packets
+    }
+
+    #[test]
+    fn test_replay_renders_clean_stream() {
+        let config = ReplayConfig::new(pipeline_config(JitterBufferMode::Fixed));
+        let output = replay(stream(100, &[], 4), &config).unwrap();
+
+        assert_eq!(output.source, Some("192.0.2.1:5000".parse().unwrap()));
+        assert_eq!(output.pipeline.frames_decoded, 100);
+        assert_eq!(output.pipeline.frames_concealed, 0);
+        assert_eq!(output.loss.packets_lost, 0);
+        // Decoded frames plus silence while the jitter buffer filled up
+        assert_eq!(
+            output.samples.len(),
+            (100 + output.underruns as usize) * FRAME_SIZE as usize
+        );
+    }
+
+    #[test]
+    fn test_replay_recovers_and_conceals() {
+        let config = ReplayConfig::new(pipeline_config(JitterBufferMode::Fixed));
+        // 10 is rebuilt from FEC; 20 and 21 share a group and are concealed
+        let output = replay(stream(100, &[10, 20, 21], 4), &config).unwrap();
+
+        assert_eq!(output.loss.packets_lost, 3);
+        assert_eq!(output.packets_recovered, 1);
+        assert_eq!(output.pipeline.frames_decoded, 98);
+        assert_eq!(output.pipeline.frames_concealed, 2);
+    }
+
+    #[test]
+    fn test_replay_timeline_intervals() {
+        let mut config = ReplayConfig::new(pipeline_config(JitterBufferMode::Passthrough));
+        config.interval = Duration::from_millis(25);
+        let output = replay(stream(100, &[30], 100), &config).unwrap();
+
+        // 100 frames of 1 ms, the last one arriving at 99 ms
+        assert_eq!(output.timeline.len(), 4);
+        assert_eq!(output.timeline[1].start_ms, 25);
+        assert_eq!(output.timeline[0].packets_received, 25);
+        assert_eq!(output.timeline[1].packets_lost, 1);
+        assert_eq!(output.timeline[1].frames_concealed, 1);
+        let received: u64 = output.timeline.iter().map(|i| i.packets_received).sum();
+        assert_eq!(received, 99);
+    }
+
+    #[test]
+    fn test_replay_filters_source() {
+        let mut packets = stream(10, &[], 4);
+        let other: SocketAddr = "192.0.2.2:5000".parse().unwrap();
+        packets.insert(
+            0,
+            Ok(CapturedPacket {
+                arrival_us: 0,
+                source: other,
+                packet: Packet::audio(500, 0, pcm(0.5)),
+            }),
+        );
+
+        let mut config = ReplayConfig::new(pipeline_config(JitterBufferMode::Passthrough));
+        config.source = Some("192.0.2.1:5000".parse().unwrap());
+        let output = replay(packets, &config).unwrap();
+        assert_eq!(output.packets_received, 10);
+    }
+}
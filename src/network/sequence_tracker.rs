@@ -3,6 +3,11 @@
 //! Tracks received sequence numbers to detect lost packets and calculate
 //! packet loss statistics.
 
+use std::collections::VecDeque;
+
+/// Number of most recent sequence numbers used for the rolling loss rate
+pub const LOSS_WINDOW_PACKETS: usize = 500;
+
 /// Tracks sequence numbers to detect lost packets
 ///
 /// Uses a sliding window approach to handle out-of-order packets
@@ -21,6 +26,40 @@ pub struct SequenceTracker {
     packets_lost: u64,
     /// Window size for out-of-order detection
     window_size: u32,
+    /// Packets that arrived after a later sequence number
+    packets_reordered: u64,
+    /// Packets received more than once
+    packets_duplicated: u64,
+    /// Number of loss bursts (runs of consecutive lost packets)
+    loss_bursts: u64,
+    /// Total length of all loss bursts
+    burst_packets: u64,
+    /// Longest loss burst seen
+    max_burst_length: u32,
+    /// Loss state of the most recent sequence numbers, ending at the highest
+    /// (true = lost)
+    recent: VecDeque<bool>,
+}
+
+/// Snapshot of sequence tracking statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LossStats {
+    /// Overall packet loss rate (0.0 - 1.0)
+    pub loss_rate: f32,
+    /// Loss rate over the last `LOSS_WINDOW_PACKETS` sequence numbers (0.0 - 1.0)
+    pub recent_loss_rate: f32,
+    /// Total packets lost
+    pub packets_lost: u64,
+    /// Packets that arrived out of order
+    pub packets_reordered: u64,
+    /// Packets received more than once
+    pub packets_duplicated: u64,
+    /// Number of loss bursts, counted when the gap is detected
+    pub loss_bursts: u64,
+    /// Average loss burst length in packets
+    pub mean_burst_length: f32,
+    /// Longest loss burst in packets
+    pub max_burst_length: u32,
 }
 
 impl SequenceTracker {
@@ -33,6 +72,12 @@ impl SequenceTracker {
             packets_received: 0,
             packets_lost: 0,
             window_size: 64,
+            packets_reordered: 0,
+            packets_duplicated: 0,
+            loss_bursts: 0,
+            burst_packets: 0,
+            max_burst_length: 0,
+            recent: VecDeque::with_capacity(LOSS_WINDOW_PACKETS),
         }
     }
 
@@ -48,6 +93,7 @@ impl SequenceTracker {
             self.last_sequence = Some(sequence);
             self.highest_sequence = sequence;
             self.received_bitmap = 1; // Mark current as received
+            self.push_recent(false);
             return Vec::new();
         }
 
@@ -64,6 +110,13 @@ impl SequenceTracker {
                     let missed_seq = self.highest_sequence.wrapping_add(i);
                     lost_sequences.push(missed_seq);
                     self.packets_lost += 1;
+                    self.push_recent(true);
+                }
+                if diff > 1 {
+                    let burst = diff as u32 - 1;
+                    self.loss_bursts += 1;
+                    self.burst_packets += burst as u64;
+                    self.max_burst_length = self.max_burst_length.max(burst);
                 }
 
                 // Shift bitmap and mark current as received
@@ -76,7 +129,9 @@ impl SequenceTracker {
             } else {
                 // Large jump - reset tracking
                 self.received_bitmap = 1;
+                self.recent.clear();
             }
+            self.push_recent(false);
 
             self.highest_sequence = sequence;
         } else if diff < 0 && diff > -(self.window_size as i64) {
@@ -90,19 +145,31 @@ impl SequenceTracker {
                     if self.packets_lost > 0 {
                         self.packets_lost -= 1;
                     }
-                    // Remove from lost list if we just reported it
-                    lost_sequences.retain(|&s| s != sequence);
+                    self.packets_reordered += 1;
+                    if let Some(index) = self.recent.len().checked_sub(offset as usize + 1) {
+                        self.recent[index] = false;
+                    }
+                } else {
+                    self.packets_duplicated += 1;
                 }
-                // else: duplicate packet, ignore
             }
+        } else if diff == 0 {
+            self.packets_duplicated += 1;
         }
-        // else: very old packet or duplicate, ignore
+        // else: very old packet, ignore
 
         self.last_sequence = Some(sequence);
 
         lost_sequences
     }
 
+    fn push_recent(&mut self, lost: bool) {
+        if self.recent.len() == LOSS_WINDOW_PACKETS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(lost);
+    }
+
     /// Calculate signed difference between two sequence numbers
     /// Handles wraparound correctly
     fn sequence_diff(&self, a: u32, b: u32) -> i64 {
@@ -120,6 +187,35 @@ impl SequenceTracker {
         }
     }
 
+    /// Get packet loss rate over the last `LOSS_WINDOW_PACKETS` sequence
+    /// numbers (0.0 - 1.0)
+    pub fn recent_loss_rate(&self) -> f32 {
+        if self.recent.is_empty() {
+            0.0
+        } else {
+            let lost = self.recent.iter().filter(|&&lost| lost).count();
+            lost as f32 / self.recent.len() as f32
+        }
+    }
+
+    /// Get a snapshot of all loss statistics
+    pub fn stats(&self) -> LossStats {
+        LossStats {
+            loss_rate: self.loss_rate(),
+            recent_loss_rate: self.recent_loss_rate(),
+            packets_lost: self.packets_lost,
+            packets_reordered: self.packets_reordered,
+            packets_duplicated: self.packets_duplicated,
+            loss_bursts: self.loss_bursts,
+            mean_burst_length: if self.loss_bursts == 0 {
+                0.0
+            } else {
+                self.burst_packets as f32 / self.loss_bursts as f32
+            },
+            max_burst_length: self.max_burst_length,
+        }
+    }
+
     /// Get total packets received
     pub fn packets_received(&self) -> u64 {
         self.packets_received
@@ -154,6 +250,12 @@ impl SequenceTracker {
         self.received_bitmap = 0;
         self.packets_received = 0;
         self.packets_lost = 0;
+        self.packets_reordered = 0;
+        self.packets_duplicated = 0;
+        self.loss_bursts = 0;
+        self.burst_packets = 0;
+        self.max_burst_length = 0;
+        self.recent.clear();
     }
 }
 
@@ -295,5 +397,56 @@ mod tests {
 
         assert_eq!(tracker.packets_received(), 4);
         assert_eq!(tracker.packets_lost(), 0);
+        assert_eq!(tracker.stats().packets_duplicated, 2);
+    }
+
+    #[test]
+    fn test_burst_lengths() {
+        let mut tracker = SequenceTracker::new();
+
+        tracker.record(0);
+        tracker.record(2); // burst of 1
+        tracker.record(6); // burst of 3
+
+        let stats = tracker.stats();
+        assert_eq!(stats.loss_bursts, 2);
+        assert_eq!(stats.max_burst_length, 3);
+        assert_eq!(stats.mean_burst_length, 2.0);
+    }
+
+    #[test]
+    fn test_reordered_packets() {
+        let mut tracker = SequenceTracker::new();
+
+        tracker.record(0);
+        tracker.record(2);
+        tracker.record(1); // Late arrival
+        tracker.record(1); // Duplicate of the late arrival
+
+        let stats = tracker.stats();
+        assert_eq!(stats.packets_reordered, 1);
+        assert_eq!(stats.packets_duplicated, 1);
+        assert_eq!(stats.packets_lost, 0);
+        assert_eq!(stats.recent_loss_rate, 0.0);
+    }
+
+    #[test]
+    fn test_recent_loss_rate_window() {
+        let mut tracker = SequenceTracker::new();
+
+        // Half of the first window lost
+        for i in 0..LOSS_WINDOW_PACKETS as u32 {
+            if i % 2 == 0 {
+                tracker.record(i);
+            }
+        }
+        assert!((tracker.recent_loss_rate() - 0.5).abs() < 0.01);
+
+        // A clean window pushes the old losses out
+        for i in LOSS_WINDOW_PACKETS as u32..2 * LOSS_WINDOW_PACKETS as u32 {
+            tracker.record(i);
+        }
+        assert_eq!(tracker.recent_loss_rate(), 0.0);
+        assert!(tracker.loss_rate() > 0.0);
     }
 }
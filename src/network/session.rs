@@ -1,20 +1,58 @@
 //! Session manager for group P2P audio sessions
 //!
-//! Manages multiple peer connections and audio mixing.
+//! Manages multiple peer connections and audio mixing. Each peer's stream goes
+//! through its own jitter buffer and PLC, and the mixer pulls one frame from
+//! every peer on the local frame clock. Room membership from the signaling
+//! server can be applied directly to build a full mesh.
+//!
+//! Besides its main stream, a peer can send additional named streams (e.g.
+//! vocal mic and guitar DI), announced through signaling. Each one is received,
+//! buffered and mixed on its own so that listeners can balance them.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use parking_lot::Mutex;
 use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, trace, warn};
 use uuid::Uuid;
 
+use super::capabilities::{local_capabilities, CapabilityNegotiation, NegotiatedCapabilities};
+use super::capture::{capture_packet, PacketCapture, SharedCapture};
+use super::clock::{local_clock_us, ClockEstimate, StreamClock};
+use super::codec_negotiation::{AudioDecoders, CodecNegotiation};
+use super::connection::{ConnectionState, ReconnectConfig, RttMeasurement, CONTROL_POLL_INTERVAL};
+use super::control::{ControlChannel, ControlConfig, ControlStats};
+use super::encryption::{
+    send_packet, EncryptedTransport, EncryptionMode, KeyExchangeState, KeyPair,
+};
 use super::error::NetworkError;
-use super::signaling::PeerInfo;
-use super::transport::UdpTransport;
-use crate::protocol::{Packet, PacketType};
+use super::fec::{FecConfig, FecPacket, FecStreamDecoder, FecStreamEncoder, RecoveredAudio};
+use super::ice::{self, IceAgent, IceConfig, IceState};
+use super::jitter_buffer::JitterBufferMode;
+use super::quality::{
+    Packetizer, QualityConfig, QualityController, QualityDecision, QualitySettings,
+};
+use super::receive_pipeline::{ReceivePipeline, ReceivePipelineConfig, ReceivePipelineStats};
+use super::receiver_report::{ReceptionReporter, RemoteReceptionStats};
+use super::redundancy::{split_redundancy, RedundancyConfig, RedundancyEncoder};
+use super::sequence_tracker::{LossStats, SequenceTracker};
+use super::signaling::{AddressCandidate, PeerInfo, SignalingMessage, StreamInfo};
+use super::stun::BindingMessage;
+use super::transport::{StunDatagram, UdpTransport};
+use crate::audio::{CodecConfig, CodecType, SyncedMetronome};
+use crate::protocol::{
+    CodecOfferPayload, ControlMessage, HelloPayload, KeyExchangePayload, LatencyPing, LatencyPong,
+    Packet, PacketType, ReceiverReport, StreamHeader, MAIN_STREAM_ID,
+};
+
+/// Interval for resending our public key, hello and codec offer to peers
+/// that have not answered
+const HANDSHAKE_RETRY_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Session configuration
 #[derive(Debug, Clone)]
@@ -25,6 +63,39 @@ pub struct SessionConfig {
     pub max_peers: usize,
     /// Enable audio mixing (combine all peer audio)
     pub enable_mixing: bool,
+    /// End-to-end encryption policy for all peers
+    pub encryption: EncryptionMode,
+    /// Preferred codec and audio parameters (negotiated per peer)
+    ///
+    /// `frame_size` also sets the mixer's output frame clock.
+    pub codec: CodecConfig,
+    /// Jitter buffer mode for each peer's stream
+    pub jitter_buffer: JitterBufferMode,
+    /// Resample each peer's stream to compensate the drift between its sound
+    /// card and ours
+    pub drift_compensation: bool,
+    /// Channels of the mixed output
+    ///
+    /// With mono peer streams, 2 upmixes each peer with its pan setting.
+    pub mix_channels: u16,
+    /// FEC policy for audio sent to each peer
+    pub fec: FecConfig,
+    /// Redundant audio policy for audio sent to each peer (only used with
+    /// peers that announce redundancy)
+    pub redundancy: RedundancyConfig,
+    /// Timing of ICE connectivity checks to each peer
+    pub ice: IceConfig,
+    /// Per-peer liveness detection and reconnect timing
+    pub reconnect: ReconnectConfig,
+    /// Retransmission policy of each peer's control channel
+    pub control: ControlConfig,
+    /// Bounds for adapting audio sent to each peer to the network (None
+    /// keeps `codec` and `fec` fixed)
+    pub quality: Option<QualityConfig>,
+    /// Additional streams we send with `broadcast_stream_audio`
+    ///
+    /// Announce them to the room with `SignalingMessage::UpdateStreams`.
+    pub streams: Vec<StreamInfo>,
 }
 
 impl Default for SessionConfig {
@@ -33,38 +104,800 @@ impl Default for SessionConfig {
             local_port: 0,
             max_peers: 10,
             enable_mixing: true,
+            encryption: EncryptionMode::default(),
+            codec: CodecConfig::default(),
+            jitter_buffer: JitterBufferMode::default(),
+            drift_compensation: true,
+            mix_channels: 1,
+            fec: FecConfig::default(),
+            redundancy: RedundancyConfig::default(),
+            ice: IceConfig::default(),
+            reconnect: ReconnectConfig::default(),
+            control: ControlConfig::default(),
+            quality: None,
+            streams: Vec::new(),
+        }
+    }
+}
+
+/// Per-peer mix settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerMix {
+    /// Gain applied to the peer's audio (1.0 = unity)
+    pub volume: f32,
+    /// Pan from -1.0 (full left) to 1.0 (full right), used for stereo mixes
+    pub pan: f32,
+    /// Exclude the peer from the mix
+    pub muted: bool,
+}
+
+impl PeerMix {
+    /// Settings for one of the peer's streams with its own `stream` settings
+    fn with_stream(self, stream: PeerMix) -> PeerMix {
+        PeerMix {
+            volume: self.volume * stream.volume,
+            pan: (self.pan + stream.pan).clamp(-1.0, 1.0),
+            muted: self.muted || stream.muted,
+        }
+    }
+}
+
+impl Default for PeerMix {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pan: 0.0,
+            muted: false,
         }
     }
 }
 
+/// Statistics for one peer in the session
+#[derive(Debug, Clone)]
+pub struct PeerStats {
+    /// Audio packets received from the peer
+    pub packets_received: u32,
+    /// Whether audio to and from the peer is end-to-end encrypted
+    pub encrypted: bool,
+    /// Codec negotiated for audio sent to the peer
+    pub send_codec: CodecType,
+    /// Loss statistics of the peer's audio stream
+    pub loss: LossStats,
+    /// Lost audio packets rebuilt from FEC in time for playout
+    pub packets_recovered: u64,
+    /// Jitter buffer and PLC statistics
+    pub receive: ReceivePipelineStats,
+    /// Smoothed round-trip time to the peer (ms)
+    pub rtt_ms: f32,
+    /// Round-trip time variation (ms)
+    pub jitter_ms: f32,
+    /// Packets sent to the peer
+    pub packets_sent: u64,
+    /// Bytes sent to the peer
+    pub bytes_sent: u64,
+    /// Bytes received from the peer
+    pub bytes_received: u64,
+    /// Key exchange packets dropped because they offered a key other than
+    /// the peer's (possibly spoofed)
+    pub key_exchanges_rejected: u64,
+    /// Liveness of the peer's stream
+    pub state: ConnectionState,
+    /// Control channel statistics
+    pub control: ControlStats,
+    /// Peer clock offset and drift (None before the first pong)
+    pub clock: Option<ClockEstimate>,
+    /// Settings chosen by the quality controller (None if it is disabled)
+    pub quality: Option<QualitySettings>,
+    /// How our audio arrives at the peer (None before its first receiver
+    /// report)
+    pub remote_reception: Option<RemoteReceptionStats>,
+    /// Time since the peer was added, in seconds
+    pub uptime_seconds: u64,
+    /// The peer's additional streams received so far, by stream ID
+    pub streams: Vec<StreamStats>,
+    /// Protocol version and capabilities agreed with the peer (None before
+    /// its hello)
+    pub capabilities: Option<NegotiatedCapabilities>,
+    /// Why the peer cannot take part in the session (it is `Failed`)
+    pub incompatibility: Option<String>,
+}
+
+/// Statistics for one of a peer's additional streams
+#[derive(Debug, Clone)]
+pub struct StreamStats {
+    /// Stream ID
+    pub stream_id: u8,
+    /// Interleaved channels of the stream
+    pub channels: u16,
+    /// Audio packets received on the stream
+    pub packets_received: u32,
+    /// Loss statistics of the stream
+    pub loss: LossStats,
+    /// Lost audio packets rebuilt from FEC in time for playout
+    pub packets_recovered: u64,
+    /// Jitter buffer and PLC statistics
+    pub receive: ReceivePipelineStats,
+}
+
 /// Peer state in the session
 struct Peer {
     info: PeerInfo,
     addr: SocketAddr,
     connected: AtomicBool,
     packets_received: AtomicU32,
-    last_audio: Option<Vec<f32>>,
+    key_exchange: KeyExchangeState,
+    /// Codec negotiation and encoder for audio sent to this peer
+    codec: Mutex<CodecNegotiation>,
+    /// Protocol version and capability negotiation
+    capabilities: Mutex<CapabilityNegotiation>,
+    /// Header sequence for every packet to this peer other than audio and
+    /// FEC (one counter per key keeps nonces unique)
+    sequence: AtomicU32,
+    /// Audio sequence numbers for this peer (consecutive per peer for FEC
+    /// grouping and loss tracking)
+    audio_sequence: AtomicU32,
+    /// FEC generator (also serializes audio sequence allocation)
+    fec_encoder: Mutex<FecStreamEncoder>,
+    /// Adds copies of previous frames to audio sent to this peer
+    redundancy: Mutex<RedundancyEncoder>,
+    /// Adapts codec, frame size and FEC to the path to this peer
+    quality: Mutex<Option<QualityController>>,
+    /// Collects captured audio into packets of the adapted frame size
+    packetizer: Mutex<Packetizer>,
+    /// FEC recovery state for audio from this peer
+    fec_decoder: Mutex<FecStreamDecoder>,
+    sequence_tracker: Mutex<SequenceTracker>,
+    /// Jitter and receiver reports for audio from this peer
+    reception: Mutex<ReceptionReporter>,
+    /// Latest receiver report from the peer on our audio
+    remote_reception: Mutex<Option<RemoteReceptionStats>>,
+    packets_recovered: AtomicU64,
+    /// Decoders for the per-peer audio callback
+    decoders: Mutex<AudioDecoders>,
+    /// Jitter buffer and PLC feeding the mixer
+    playout: Mutex<PeerPlayout>,
+    mix: Mutex<PeerMix>,
+    /// RTT from latency pings sent by the handshake loop
+    rtt: Mutex<RttMeasurement>,
+    /// Maps the peer's sample timestamps onto our clock
+    stream_clock: Mutex<StreamClock>,
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    key_exchanges_rejected: AtomicU64,
+    added_at: Instant,
+    /// Connectivity checks over the peer's candidates
+    ice: Mutex<IceAgent>,
+    /// ICE selection `addr` currently follows
+    ice_selected: Option<(SocketAddr, bool)>,
+    /// `Connecting` until the first packet, then `Connected`, `Reconnecting`
+    /// while the peer is silent, and `Failed` once reconnecting times out
+    state: ConnectionState,
+    /// Last authenticated packet from the peer
+    last_received: Mutex<Option<Instant>>,
+    reconnecting_since: Option<Instant>,
+    /// Reliable control messages to and from the peer
+    control: Mutex<ControlChannel>,
+    /// Send state of our additional streams to this peer
+    stream_senders: Mutex<HashMap<u8, StreamSender>>,
+    /// The peer's additional streams, created by their first packet
+    streams: Mutex<HashMap<u8, PeerStream>>,
+    /// Mix settings of the peer's additional streams
+    stream_mix: Mutex<HashMap<u8, PeerMix>>,
+}
+
+impl Peer {
+    /// Packets carrying captured audio to this peer
+    ///
+    /// With the quality controller enabled, audio is collected into packets
+    /// of its frame size and protected with its FEC group size.
+    fn audio_packets(
+        &self,
+        fec: &FecConfig,
+        channels: u16,
+        timestamp: u32,
+        data: &[f32],
+    ) -> Vec<Packet> {
+        let settings = self
+            .quality
+            .lock()
+            .as_ref()
+            .map(QualityController::settings);
+        // FEC only goes to peers that use it, Reed-Solomon only to those
+        // that recover from it
+        let negotiated = self.capabilities.lock().negotiated();
+        let peer_fec = negotiated.fec;
+        let redundant = negotiated.redundancy;
+        self.fec_encoder
+            .lock()
+            .set_scheme(negotiated.fec_scheme(fec.scheme));
+        let Some(settings) = settings else {
+            let fec_group = (fec.enabled && peer_fec).then_some(fec.group_size);
+            return self.frame_packets(fec_group, redundant, channels, timestamp, data);
+        };
+
+        let frames = self
+            .packetizer
+            .lock()
+            .push(data, timestamp, settings.frame_size, channels);
+        frames
+            .into_iter()
+            .flat_map(|(frame, timestamp)| {
+                let fec_group = settings.fec_group_size.filter(|_| peer_fec);
+                self.frame_packets(fec_group, redundant, channels, timestamp, &frame)
+            })
+            .collect()
+    }
+
+    /// Encode a frame for this peer, followed by the FEC packets when a
+    /// group of `fec_group` packets completes
+    ///
+    /// With `redundant`, the audio packet also carries copies of previous
+    /// frames.
+    fn frame_packets(
+        &self,
+        fec_group: Option<usize>,
+        redundant: bool,
+        channels: u16,
+        timestamp: u32,
+        data: &[f32],
+    ) -> Vec<Packet> {
+        let (codec, payload) = self.codec.lock().encode(data);
+
+        let (sequence, fec_packets) = if let Some(group_size) = fec_group {
+            // Hold the encoder while allocating so groups stay consecutive
+            let mut encoder = self.fec_encoder.lock();
+            encoder.set_group_size(group_size);
+            let sequence = self.audio_sequence.fetch_add(1, Ordering::Relaxed);
+            let fec = encoder.add_packet_all(sequence, timestamp, codec, &payload);
+            let fec_packets: Vec<Packet> = fec
+                .into_iter()
+                .map(|fec| Packet::fec(encoder.next_sequence(), fec.to_bytes()))
+                .collect();
+            (sequence, fec_packets)
+        } else {
+            (
+                self.audio_sequence.fetch_add(1, Ordering::Relaxed),
+                Vec::new(),
+            )
+        };
+
+        let mut packet = Packet::audio(sequence, timestamp, payload);
+        packet.flags.has_fec = fec_group.is_some();
+        packet.flags.codec = codec;
+        if redundant {
+            self.redundancy.lock().protect(&mut packet, data, channels);
+        }
+
+        let mut packets = vec![packet];
+        packets.extend(fec_packets);
+        packets
+    }
+
+    /// Encode a frame of one of our additional streams for this peer,
+    /// followed by the FEC packets when a group completes
+    ///
+    /// Additional streams are sent in the frames they are given; only the
+    /// quality controller's FEC group size applies to them.
+    fn stream_packets(
+        &self,
+        stream: &StreamInfo,
+        config: &SessionConfig,
+        timestamp: u32,
+        data: &[f32],
+    ) -> Vec<Packet> {
+        let fec = &config.fec;
+        let negotiated = self.capabilities.lock().negotiated();
+        let peer_fec = negotiated.fec;
+        let scheme = negotiated.fec_scheme(fec.scheme);
+        let fec_group = match self.quality.lock().as_ref() {
+            Some(controller) => controller.settings().fec_group_size,
+            None => fec.enabled.then_some(fec.group_size),
+        }
+        .filter(|_| peer_fec);
+        let (codec, payload) = self
+            .codec
+            .lock()
+            .encode_stream(stream.id, stream.channels, data);
+        let channels = stream.channels.min(u8::MAX as u16) as u8;
+
+        let mut senders = self.stream_senders.lock();
+        let sender = senders.entry(stream.id).or_insert_with(|| StreamSender {
+            sequence: 0,
+            fec_encoder: FecStreamEncoder::with_scheme(fec.group_size, scheme),
+            redundancy: RedundancyEncoder::new(config.redundancy, config.codec.sample_rate),
+        });
+        let sequence = sender.sequence;
+        sender.sequence = sequence.wrapping_add(1);
+        let fec_packets: Vec<Packet> = fec_group.map_or_else(Vec::new, |group_size| {
+            let encoder = &mut sender.fec_encoder;
+            encoder.set_group_size(group_size);
+            encoder.set_scheme(scheme);
+            let fec = encoder.add_packet_all(sequence, timestamp, codec, &payload);
+            fec.into_iter()
+                .map(|fec| {
+                    Packet::fec(encoder.next_sequence(), fec.to_bytes())
+                        .with_stream(stream.id, channels)
+                })
+                .collect()
+        });
+
+        let mut packet =
+            Packet::audio(sequence, timestamp, payload).with_stream(stream.id, channels);
+        packet.flags.has_fec = fec_group.is_some();
+        packet.flags.codec = codec;
+        if negotiated.redundancy {
+            sender
+                .redundancy
+                .protect(&mut packet, data, stream.channels);
+        }
+
+        let mut packets = vec![packet];
+        packets.extend(fec_packets);
+        packets
+    }
+
+    /// Check if audio goes to this peer: ICE consent holds and its hello did
+    /// not show it to be incompatible
+    fn is_sendable(&self) -> bool {
+        self.connected.load(Ordering::SeqCst) && !self.capabilities.lock().is_incompatible()
+    }
+
+    fn next_sequence(&self) -> u32 {
+        self.sequence.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Count a packet sent to this peer
+    fn record_sent(&self, packet: &Packet) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(
+            (packet.header_len() + packet.payload.len()) as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Hand a received or recovered audio packet to the callback and mixer
+    ///
+    /// Recovered packets that missed their playout deadline are dropped.
+    fn deliver_audio(
+        &self,
+        audio: RecoveredAudio,
+        recovered: bool,
+        callbacks: AudioCallbacks,
+        enable_mixing: bool,
+    ) {
+        let mut playout = self.playout.lock();
+        if recovered {
+            if enable_mixing && playout.pipeline.is_late(audio.sequence) {
+                return;
+            }
+            self.packets_recovered.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // Decode according to the codec in the packet header
+        if callbacks.peer.is_some() || callbacks.stream.is_some() {
+            let decoded = self.decoders.lock().decode(audio.codec, &audio.payload);
+            match decoded {
+                Ok(samples) => {
+                    if let Some(callback) = callbacks.peer {
+                        callback(self.info.id, &samples, audio.timestamp);
+                    }
+                    if let Some(callback) = callbacks.stream {
+                        callback(self.info.id, MAIN_STREAM_ID, &samples, audio.timestamp);
+                    }
+                }
+                Err(e) => warn!("Failed to decode audio from {}: {}", self.addr, e),
+            }
+        }
+
+        // The mixer pulls from the jitter buffer on its own clock
+        if enable_mixing {
+            playout.pipeline.insert_with_codec(
+                audio.sequence,
+                audio.timestamp,
+                audio.codec,
+                audio.payload,
+            );
+        }
+    }
+
+    /// Handle an audio or FEC packet of one of the peer's additional streams
+    ///
+    /// The stream is created by its first packet, and re-created if its
+    /// channel count changes.
+    fn receive_stream_packet(
+        &self,
+        header: StreamHeader,
+        mut packet: Packet,
+        pipeline: &ReceivePipelineConfig,
+        callback: Option<&StreamAudioCallback>,
+        enable_mixing: bool,
+    ) {
+        if header.channels == 0 {
+            return;
+        }
+        let channels = header.channels as u16;
+        let id = self.info.id;
+        let mut streams = self.streams.lock();
+        let stream = streams
+            .entry(header.stream_id)
+            .and_modify(|stream| {
+                if stream.channels != channels {
+                    debug!(
+                        "Stream {} of peer {} now has {} channels",
+                        header.stream_id, id, channels
+                    );
+                    *stream = PeerStream::new(channels, pipeline);
+                }
+            })
+            .or_insert_with(|| {
+                debug!("Receiving stream {} from peer {}", header.stream_id, id);
+                PeerStream::new(channels, pipeline)
+            });
+        let deliver = |stream: &mut PeerStream, audio, recovered| {
+            stream.deliver_audio(
+                id,
+                header.stream_id,
+                audio,
+                recovered,
+                callback,
+                enable_mixing,
+            )
+        };
+
+        if packet.packet_type == PacketType::Fec {
+            let Some(fec) = FecPacket::from_bytes(&packet.payload) else {
+                return;
+            };
+            for recovered in stream.fec_decoder.add_fec_all(fec) {
+                deliver(stream, recovered, true);
+            }
+            return;
+        }
+
+        stream.packets_received += 1;
+        stream.sequence_tracker.record(packet.sequence);
+        let Some(redundant) = split_redundancy(&mut packet) else {
+            return;
+        };
+        if enable_mixing {
+            stream.playout.lock().insert_redundant(redundant);
+        }
+        if stream.fec_decoder.contains(packet.sequence) {
+            // Already rebuilt from FEC
+            return;
+        }
+        let recovered = stream.fec_decoder.add_packet_all(
+            packet.sequence,
+            packet.timestamp,
+            packet.flags.codec,
+            &packet.payload,
+        );
+        let audio = RecoveredAudio {
+            sequence: packet.sequence,
+            timestamp: packet.timestamp,
+            codec: packet.flags.codec,
+            payload: packet.payload,
+        };
+        deliver(stream, audio, false);
+        for recovered in recovered {
+            deliver(stream, recovered, true);
+        }
+    }
+
+    /// Follow the peer's liveness, returning the new state on a transition
+    fn update_state(&mut self, config: &ReconnectConfig, now: Instant) -> Option<ConnectionState> {
+        if self.capabilities.lock().is_incompatible() {
+            if self.state == ConnectionState::Failed {
+                return None;
+            }
+            warn!("Peer {} is incompatible, giving up on it", self.info.id);
+            self.state = ConnectionState::Failed;
+            return Some(ConnectionState::Failed);
+        }
+        let heard = self
+            .last_received
+            .lock()
+            .is_some_and(|at| now.duration_since(at) < config.liveness_timeout);
+        let next = match self.state {
+            ConnectionState::Connected if !heard => {
+                warn!("Peer {} went silent, reconnecting", self.info.id);
+                self.reconnecting_since = Some(now);
+                ConnectionState::Reconnecting
+            }
+            ConnectionState::Reconnecting
+                if !heard
+                    && self.reconnecting_since.is_some_and(|since| {
+                        now.duration_since(since) >= config.reconnect_timeout
+                    }) =>
+            {
+                warn!("Reconnecting to peer {} timed out", self.info.id);
+                ConnectionState::Failed
+            }
+            state if state != ConnectionState::Connected && heard => {
+                info!("Peer {} connected at {}", self.info.id, self.addr);
+                ConnectionState::Connected
+            }
+            _ => return None,
+        };
+        self.state = next;
+        Some(next)
+    }
+
+    /// Run the quality controller, applying its decision to the encoder
+    fn update_quality(&self, now: Instant) -> Option<QualityDecision> {
+        let mut quality = self.quality.lock();
+        let controller = quality.as_mut()?;
+        let jitter_buffer = self.playout.lock().pipeline.stats().jitter_buffer;
+        let rtt_ms = self.rtt.lock().rtt_ms;
+        let decision = controller.poll(
+            now,
+            &self.sequence_tracker.lock().stats(),
+            Some(&jitter_buffer),
+            rtt_ms,
+        )?;
+        self.codec.lock().apply_quality(&decision.settings);
+        Some(decision)
+    }
+
+    /// Receiver report on the peer's audio, if one is due
+    fn receiver_report(&self, now: Instant) -> Option<ReceiverReport> {
+        let delay = self.playout.lock().pipeline.stats().jitter_buffer_delay_ms;
+        self.reception
+            .lock()
+            .poll(now, &self.sequence_tracker.lock(), Some(delay))
+    }
+
+    /// One-way delay to the peer: half the best round trip of the clock
+    /// estimate, or half the smoothed RTT until there is one
+    fn one_way(&self) -> Duration {
+        let rtt = self.rtt.lock();
+        match rtt.clock.estimate() {
+            Some(clock) => Duration::from_micros(clock.delay_us / 2),
+            None => Duration::from_secs_f32(rtt.rtt_ms / 2000.0),
+        }
+    }
+
+    fn stats(&self) -> PeerStats {
+        let (capabilities, incompatibility) = {
+            let negotiation = self.capabilities.lock();
+            (negotiation.agreed(), negotiation.incompatibility())
+        };
+        let rtt = self.rtt.lock();
+        PeerStats {
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            encrypted: self.key_exchange.is_established(),
+            send_codec: self.codec.lock().send_codec(),
+            loss: self.sequence_tracker.lock().stats(),
+            packets_recovered: self.packets_recovered.load(Ordering::Relaxed),
+            receive: self.playout.lock().pipeline.stats(),
+            rtt_ms: rtt.rtt_ms,
+            jitter_ms: rtt.jitter_ms,
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            key_exchanges_rejected: self.key_exchanges_rejected.load(Ordering::Relaxed),
+            state: self.state,
+            control: self.control.lock().stats(),
+            clock: rtt.clock.estimate(),
+            quality: self
+                .quality
+                .lock()
+                .as_ref()
+                .map(QualityController::settings),
+            remote_reception: *self.remote_reception.lock(),
+            uptime_seconds: self.added_at.elapsed().as_secs(),
+            streams: self.stream_stats(),
+            capabilities,
+            incompatibility: incompatibility.map(|e| e.to_string()),
+        }
+    }
+
+    fn stream_stats(&self) -> Vec<StreamStats> {
+        let mut stats: Vec<StreamStats> = self
+            .streams
+            .lock()
+            .iter()
+            .map(|(&stream_id, stream)| StreamStats {
+                stream_id,
+                channels: stream.channels,
+                packets_received: stream.packets_received,
+                loss: stream.sequence_tracker.stats(),
+                packets_recovered: stream.packets_recovered,
+                receive: stream.playout.lock().pipeline.stats(),
+            })
+            .collect();
+        stats.sort_by_key(|s| s.stream_id);
+        stats
+    }
+}
+
+/// Send state of one of our additional streams to a peer
+struct StreamSender {
+    /// Consecutive per stream for FEC grouping and loss tracking
+    sequence: u32,
+    fec_encoder: FecStreamEncoder,
+    redundancy: RedundancyEncoder,
+}
+
+/// Receive state of one of a peer's additional streams
+struct PeerStream {
+    channels: u16,
+    packets_received: u32,
+    packets_recovered: u64,
+    fec_decoder: FecStreamDecoder,
+    sequence_tracker: SequenceTracker,
+    /// Decoders for the stream audio callback
+    decoders: AudioDecoders,
+    /// Jitter buffer and PLC feeding the mixer
+    playout: Mutex<PeerPlayout>,
+}
+
+impl PeerStream {
+    /// Receive state for a stream of `channels`, otherwise configured like
+    /// the main stream
+    fn new(channels: u16, pipeline: &ReceivePipelineConfig) -> Self {
+        let config = ReceivePipelineConfig {
+            codec: CodecConfig {
+                channels,
+                ..pipeline.codec.clone()
+            },
+            ..pipeline.clone()
+        };
+        Self {
+            channels,
+            packets_received: 0,
+            packets_recovered: 0,
+            fec_decoder: FecStreamDecoder::new(),
+            sequence_tracker: SequenceTracker::new(),
+            decoders: AudioDecoders::new(config.codec.clone()).expect("PCM is always available"),
+            playout: Mutex::new(PeerPlayout::new(config)),
+        }
+    }
+
+    /// Hand a received or recovered audio packet to the callback and mixer
+    fn deliver_audio(
+        &mut self,
+        peer_id: Uuid,
+        stream_id: u8,
+        audio: RecoveredAudio,
+        recovered: bool,
+        callback: Option<&StreamAudioCallback>,
+        enable_mixing: bool,
+    ) {
+        let mut playout = self.playout.lock();
+        if recovered {
+            if enable_mixing && playout.pipeline.is_late(audio.sequence) {
+                return;
+            }
+            self.packets_recovered += 1;
+        }
+
+        if let Some(callback) = callback {
+            match self.decoders.decode(audio.codec, &audio.payload) {
+                Ok(samples) => callback(peer_id, stream_id, &samples, audio.timestamp),
+                Err(e) => warn!("Failed to decode stream {} audio: {}", stream_id, e),
+            }
+        }
+
+        if enable_mixing {
+            playout.pipeline.insert_with_codec(
+                audio.sequence,
+                audio.timestamp,
+                audio.codec,
+                audio.payload,
+            );
+        }
+    }
+}
+
+/// Receive state feeding one peer's stream into the mixer
+struct PeerPlayout {
+    /// Jitter buffer + decoder + PLC for the peer's stream
+    pipeline: ReceivePipeline,
+    /// Decoded samples not yet mixed (the peer's frame size may differ from ours)
+    pending: VecDeque<f32>,
+}
+
+impl PeerPlayout {
+    fn new(config: ReceivePipelineConfig) -> Self {
+        Self {
+            pipeline: ReceivePipeline::new(config).expect("PCM is always available"),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Keep redundant copies of previous frames for lost slots
+    fn insert_redundant(&mut self, frames: Vec<RecoveredAudio>) {
+        for frame in frames {
+            self.pipeline.insert_redundant(
+                frame.sequence,
+                frame.timestamp,
+                frame.codec,
+                frame.payload,
+            );
+        }
+    }
+
+    /// Take the next `len` samples for the mixer
+    ///
+    /// Pulls as many of the peer's frames as needed. Returns `None` while the
+    /// peer's jitter buffer is still filling up.
+    fn next_frame(&mut self, len: usize) -> Option<Vec<f32>> {
+        while self.pending.len() < len {
+            match self.pipeline.pop_frame() {
+                Some(frame) if !frame.samples.is_empty() => self.pending.extend(frame.samples),
+                _ => break,
+            }
+        }
+
+        if self.pending.is_empty() {
+            return None;
+        }
+        let available = len.min(self.pending.len());
+        let mut frame: Vec<f32> = self.pending.drain(..available).collect();
+        frame.resize(len, 0.0);
+        Some(frame)
+    }
 }
 
 /// Audio callback for received audio from a peer
 pub type PeerAudioCallback = Box<dyn Fn(Uuid, &[f32], u32) + Send + Sync + 'static>;
 
+/// Audio callback for each stream of a peer (peer ID, stream ID, samples,
+/// timestamp)
+pub type StreamAudioCallback = Box<dyn Fn(Uuid, u8, &[f32], u32) + Send + Sync + 'static>;
+
+/// Callbacks for a peer's decoded main stream
+#[derive(Clone, Copy)]
+struct AudioCallbacks<'a> {
+    peer: Option<&'a PeerAudioCallback>,
+    stream: Option<&'a StreamAudioCallback>,
+}
+
 /// Mixed audio callback
 pub type MixedAudioCallback = Box<dyn Fn(&[f32], u32) + Send + Sync + 'static>;
 
+/// Callback for peer state transitions (peer ID, new state)
+pub type PeerStateCallback = Box<dyn Fn(Uuid, ConnectionState) + Send + Sync + 'static>;
+
+/// Callback for control messages from a peer (peer ID, message)
+pub type PeerControlCallback = Box<dyn Fn(Uuid, ControlMessage) + Send + Sync + 'static>;
+
+/// Callback for quality controller decisions (peer ID, decision)
+pub type PeerQualityCallback = Box<dyn Fn(Uuid, QualityDecision) + Send + Sync + 'static>;
+
 /// A multi-peer P2P audio session
 pub struct Session {
     transport: Arc<UdpTransport>,
     peers: Arc<RwLock<HashMap<Uuid, Peer>>>,
     config: SessionConfig,
     running: Arc<AtomicBool>,
-    sequence: AtomicU32,
     local_peer_id: Uuid,
+    /// Key pair for the key exchange with every peer, published through
+    /// signaling so peers can check the key they are offered
+    local_key: Arc<KeyPair>,
     peer_audio_callback: Option<Arc<PeerAudioCallback>>,
+    stream_audio_callback: Option<Arc<StreamAudioCallback>>,
     mixed_audio_callback: Option<Arc<MixedAudioCallback>>,
+    peer_state_callback: Option<Arc<PeerStateCallback>>,
+    control_callback: Option<Arc<PeerControlCallback>>,
+    quality_callback: Option<Arc<PeerQualityCallback>>,
+    /// Metronome shared with every peer
+    metronome: Option<Arc<SyncedMetronome>>,
+    /// Capture file for received packets (opt-in)
+    capture: SharedCapture,
     receive_handle: Option<tokio::task::JoinHandle<()>>,
     /// Inner receive loop handle from UdpTransport (must be aborted to release socket)
     inner_recv_handle: Option<tokio::task::JoinHandle<()>>,
+    handshake_handle: Option<tokio::task::JoinHandle<()>>,
+    mix_handle: Option<tokio::task::JoinHandle<()>>,
+    ice_handle: Option<tokio::task::JoinHandle<()>>,
+    control_handle: Option<tokio::task::JoinHandle<()>>,
+    /// Candidates offered to peers' ICE agents (host is added if missing)
+    local_candidates: Arc<Mutex<Vec<AddressCandidate>>>,
+    /// Our peer ID assigned by the signaling server (ICE username)
+    signaling_id: Arc<Mutex<Option<Uuid>>>,
 }
 
 impl Session {
@@ -78,12 +911,24 @@ impl Session {
             peers: Arc::new(RwLock::new(HashMap::new())),
             config,
             running: Arc::new(AtomicBool::new(false)),
-            sequence: AtomicU32::new(0),
             local_peer_id: Uuid::new_v4(),
+            local_key: Arc::new(KeyPair::generate()),
             peer_audio_callback: None,
+            stream_audio_callback: None,
             mixed_audio_callback: None,
+            peer_state_callback: None,
+            control_callback: None,
+            quality_callback: None,
+            metronome: None,
+            capture: Arc::new(Mutex::new(None)),
             receive_handle: None,
             inner_recv_handle: None,
+            handshake_handle: None,
+            mix_handle: None,
+            ice_handle: None,
+            control_handle: None,
+            local_candidates: Arc::new(Mutex::new(Vec::new())),
+            signaling_id: Arc::new(Mutex::new(None)),
         })
     }
 
@@ -97,6 +942,11 @@ impl Session {
         self.transport.local_addr()
     }
 
+    /// X25519 public key to publish in `UpdatePeerInfo`
+    pub fn public_key(&self) -> [u8; 32] {
+        self.local_key.public_key_bytes()
+    }
+
     /// Add a peer to the session
     pub async fn add_peer(&self, info: PeerInfo, addr: SocketAddr) -> Result<(), NetworkError> {
         let mut peers = self.peers.write().await;
@@ -111,6 +961,46 @@ impl Session {
 
         info!("Adding peer {} ({}) at {}", info.name, info.id, addr);
 
+        let mut key_exchange =
+            KeyExchangeState::with_keypair(self.config.encryption, self.local_key.clone());
+        if let Some(public_key) = info.public_key {
+            key_exchange.expect_peer_key(public_key)?;
+        }
+        let hello = key_exchange
+            .needs_key_exchange()
+            .then(|| key_exchange.key_exchange_packet(false));
+        let capabilities = CapabilityNegotiation::new(
+            local_capabilities(self.config.encryption, u8::MAX),
+            self.config.codec.frame_size,
+        );
+        let sequence = AtomicU32::new(0);
+        let capability_hello =
+            capabilities.hello_packet(sequence.fetch_add(1, Ordering::Relaxed), false);
+        let mut codec = CodecNegotiation::new(self.config.codec.clone());
+        let quality = self.config.quality.clone().map(|config| {
+            let controller = QualityController::new(config, &self.config.codec, &self.config.fec);
+            codec.apply_quality(&controller.settings());
+            controller
+        });
+        let offer = key_exchange.outbound().ok().map(|secure| {
+            let sequence = sequence.fetch_add(1, Ordering::Relaxed);
+            (secure, codec.offer_packet(sequence, false))
+        });
+        let decoders = AudioDecoders::new(CodecConfig {
+            codec_type: CodecType::Pcm,
+            ..self.config.codec.clone()
+        })
+        .expect("PCM is always available");
+        let playout = PeerPlayout::new(self.pipeline_config());
+        let agent = new_ice_agent(
+            &self.transport,
+            &self.local_candidates.lock(),
+            &self.config.ice,
+            *self.signaling_id.lock(),
+            &info,
+            addr,
+        );
+
         peers.insert(
             info.id,
             Peer {
@@ -118,10 +1008,141 @@ impl Session {
                 addr,
                 connected: AtomicBool::new(true),
                 packets_received: AtomicU32::new(0),
-                last_audio: None,
+                key_exchange,
+                codec: Mutex::new(codec),
+                capabilities: Mutex::new(capabilities),
+                sequence,
+                audio_sequence: AtomicU32::new(0),
+                fec_encoder: Mutex::new(FecStreamEncoder::new(self.config.fec.group_size)),
+                redundancy: Mutex::new(RedundancyEncoder::new(
+                    self.config.redundancy,
+                    self.config.codec.sample_rate,
+                )),
+                quality: Mutex::new(quality),
+                packetizer: Mutex::new(Packetizer::default()),
+                fec_decoder: Mutex::new(FecStreamDecoder::new()),
+                sequence_tracker: Mutex::new(SequenceTracker::new()),
+                reception: Mutex::new(ReceptionReporter::new(self.config.codec.sample_rate)),
+                remote_reception: Mutex::new(None),
+                packets_recovered: AtomicU64::new(0),
+                decoders: Mutex::new(decoders),
+                playout: Mutex::new(playout),
+                mix: Mutex::new(PeerMix::default()),
+                rtt: Mutex::new(RttMeasurement::default()),
+                stream_clock: Mutex::new(StreamClock::new(self.config.codec.sample_rate)),
+                packets_sent: AtomicU64::new(0),
+                bytes_sent: AtomicU64::new(0),
+                bytes_received: AtomicU64::new(0),
+                key_exchanges_rejected: AtomicU64::new(0),
+                added_at: Instant::now(),
+                ice: Mutex::new(agent),
+                ice_selected: None,
+                state: ConnectionState::Connecting,
+                last_received: Mutex::new(None),
+                reconnecting_since: None,
+                control: Mutex::new(ControlChannel::new(self.config.control.clone())),
+                stream_senders: Mutex::new(HashMap::new()),
+                streams: Mutex::new(HashMap::new()),
+                stream_mix: Mutex::new(HashMap::new()),
             },
         );
+        drop(peers);
+
+        // Hello first: the peer only accepts it in cleartext until our key
+        // establishes the session keys
+        if let Err(e) = self.transport.send_to(&capability_hello, addr).await {
+            warn!("Failed to send hello to {}: {}", addr, e);
+        }
+        if let Some(hello) = hello {
+            if let Err(e) = self.transport.send_to(&hello, addr).await {
+                warn!("Failed to send key exchange to {}: {}", addr, e);
+            }
+        }
+        if let Some((secure, offer)) = offer {
+            if let Err(e) = send_packet(&self.transport, secure.as_deref(), &offer, addr).await {
+                warn!("Failed to send codec offer to {}: {}", addr, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update a peer's info, re-targeting it if its address changed
+    ///
+    /// New candidates are checked too, so a better path appearing mid-session
+    /// (e.g. the peer joins our LAN) is nominated once it works. Adds the peer
+    /// if it is not in the session yet.
+    pub async fn update_peer(&self, info: PeerInfo, addr: SocketAddr) -> Result<(), NetworkError> {
+        {
+            let mut peers = self.peers.write().await;
+            if let Some(peer) = peers.get_mut(&info.id) {
+                if peer.addr != addr {
+                    info!("Peer {} ({}) moved to {}", info.name, info.id, addr);
+                    peer.addr = addr;
+                }
+                peer.ice
+                    .lock()
+                    .add_remote_candidates(&remote_candidates(&info, addr));
+                // The peer keeps its session keys: signaling cannot undo
+                // an exchange that already happened
+                if let Some(public_key) = info.public_key {
+                    if let Err(e) = peer.key_exchange.expect_peer_key(public_key) {
+                        warn!(
+                            "Peer {} ({}) published a new key: {}",
+                            info.name, info.id, e
+                        );
+                    }
+                }
+                peer.info = info;
+                return Ok(());
+            }
+        }
+        self.add_peer(info, addr).await
+    }
+
+    /// Pick the address used to reach a peer from its signaling info
+    ///
+    /// Returns the highest priority candidate of the same IP family as the
+    /// local socket.
+    pub fn peer_address(&self, info: &PeerInfo) -> Option<SocketAddr> {
+        let ipv4 = self.transport.local_addr().is_ipv4();
+        info.get_sorted_candidates()
+            .into_iter()
+            .find(|addr| addr.is_ipv4() == ipv4)
+    }
 
+    /// Follow room membership from the signaling server
+    ///
+    /// Adds the peers of `RoomJoined` and `PeerJoined`, removes peers on
+    /// `PeerLeft`, and re-targets peers on `PeerUpdated`. Peers that have no
+    /// usable address yet are added once an update provides one. Our own peer
+    /// ID from `RoomCreated` or `RoomJoined` names us in ICE checks. Other
+    /// messages are ignored.
+    pub async fn handle_signaling_message(
+        &self,
+        message: &SignalingMessage,
+    ) -> Result<(), NetworkError> {
+        match message {
+            SignalingMessage::RoomCreated { peer_id, .. } => {
+                *self.signaling_id.lock() = Some(*peer_id);
+            }
+            SignalingMessage::RoomJoined { peer_id, peers, .. } => {
+                *self.signaling_id.lock() = Some(*peer_id);
+                for peer in peers {
+                    if let Some(addr) = self.peer_address(peer) {
+                        self.add_peer(peer.clone(), addr).await?;
+                    }
+                }
+            }
+            SignalingMessage::PeerJoined { peer } | SignalingMessage::PeerUpdated { peer } => {
+                match self.peer_address(peer) {
+                    Some(addr) => self.update_peer(peer.clone(), addr).await?,
+                    None => debug!("Peer {} has no usable address yet", peer.id),
+                }
+            }
+            SignalingMessage::PeerLeft { peer_id } => self.remove_peer(*peer_id).await,
+            _ => {}
+        }
         Ok(())
     }
 
@@ -133,82 +1154,429 @@ impl Session {
         }
     }
 
-    /// Get list of connected peers
-    pub async fn peers(&self) -> Vec<PeerInfo> {
-        let peers = self.peers.read().await;
-        peers.values().map(|p| p.info.clone()).collect()
+    /// Set the candidates offered in ICE checks
+    ///
+    /// These should be the candidates published to the signaling server.
+    /// After a network change, publish re-gathered candidates and set them
+    /// here; they are used for peers added later and for ICE restarts.
+    pub fn set_local_candidates(&self, candidates: Vec<AddressCandidate>) {
+        *self.local_candidates.lock() = candidates;
     }
 
-    /// Set callback for individual peer audio
-    pub fn set_peer_audio_callback<F>(&mut self, callback: F)
+    /// Get a peer's stream state
+    pub async fn peer_state(&self, peer_id: Uuid) -> Option<ConnectionState> {
+        self.peers.read().await.get(&peer_id).map(|p| p.state)
+    }
+
+    /// Set callback for peer state transitions
+    ///
+    /// Must be called before `start`. Reports `Connected` on a peer's first
+    /// packet, `Reconnecting` once it has been silent for the liveness
+    /// timeout (its ICE checks restart), `Connected` when it is heard again,
+    /// and `Failed` when reconnecting times out.
+    pub fn set_peer_state_callback<F>(&mut self, callback: F)
     where
-        F: Fn(Uuid, &[f32], u32) + Send + Sync + 'static,
+        F: Fn(Uuid, ConnectionState) + Send + Sync + 'static,
     {
-        self.peer_audio_callback = Some(Arc::new(Box::new(callback)));
+        self.peer_state_callback = Some(Arc::new(Box::new(callback)));
     }
 
-    /// Set callback for mixed audio from all peers
-    pub fn set_mixed_audio_callback<F>(&mut self, callback: F)
+    /// Set callback for control messages from peers
+    ///
+    /// Must be called before `start`. Each peer's messages are delivered once,
+    /// in the order the peer sent them.
+    pub fn set_control_callback<F>(&mut self, callback: F)
     where
-        F: Fn(&[f32], u32) + Send + Sync + 'static,
+        F: Fn(Uuid, ControlMessage) + Send + Sync + 'static,
     {
-        self.mixed_audio_callback = Some(Arc::new(Box::new(callback)));
+        self.control_callback = Some(Arc::new(Box::new(callback)));
     }
 
-    /// Start the session
+    /// Set callback for quality controller decisions
     ///
-    /// # Thread Safety
-    /// This method takes `&mut self`, ensuring exclusive access.
-    /// The `running` flag is set atomically before spawning the receive loop.
-    pub fn start(&mut self) {
-        if self.running.load(Ordering::SeqCst) {
-            return;
-        }
-
-        self.running.store(true, Ordering::SeqCst);
-        self.start_receive_loop();
-        info!("Session started on {}", self.transport.local_addr());
+    /// Must be called before `start`. Only called if `SessionConfig::quality`
+    /// enables the controller.
+    pub fn set_quality_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(Uuid, QualityDecision) + Send + Sync + 'static,
+    {
+        self.quality_callback = Some(Arc::new(Box::new(callback)));
     }
 
-    /// Stop the session
+    /// Share a metronome with every peer
     ///
-    /// # Thread Safety
-    /// This method takes `&mut self`, ensuring exclusive access.
-    /// The `running` flag is set to false atomically, which signals the
-    /// receive loop to terminate. The abort is a fallback.
-    pub fn stop(&mut self) {
-        self.running.store(false, Ordering::SeqCst);
+    /// Its click is mixed into the mixed audio, so its sample rate must be
+    /// the codec's. Syncs from the tempo owner go out over each peer's control
+    /// channel and are compensated for half the peer's round-trip time.
+    ///
+    /// Must be called before `start()`.
+    pub fn set_metronome(&mut self, metronome: Arc<SyncedMetronome>) {
+        self.metronome = Some(metronome);
+    }
 
-        // Abort inner receive loop first (holds socket reference)
-        if let Some(handle) = self.inner_recv_handle.take() {
-            handle.abort();
-        }
+    /// Get list of connected peers
+    pub async fn peers(&self) -> Vec<PeerInfo> {
+        let peers = self.peers.read().await;
+        peers.values().map(|p| p.info.clone()).collect()
+    }
 
-        // Then abort outer receive loop
-        if let Some(handle) = self.receive_handle.take() {
-            handle.abort();
-        }
+    /// Additional streams a peer has announced through signaling
+    pub async fn peer_streams(&self, peer_id: Uuid) -> Option<Vec<StreamInfo>> {
+        let peers = self.peers.read().await;
+        peers.get(&peer_id).map(|p| p.info.streams.clone())
+    }
 
-        info!("Session stopped");
+    /// Additional streams we send
+    pub fn local_streams(&self) -> &[StreamInfo] {
+        &self.config.streams
     }
 
-    /// Send audio to all peers
-    pub async fn broadcast_audio(&self, data: &[f32], timestamp: u32) -> Result<(), NetworkError> {
-        if !self.running.load(Ordering::SeqCst) {
-            return Err(NetworkError::NotConnected);
+    /// Check if audio to and from a peer is end-to-end encrypted
+    pub async fn is_peer_encrypted(&self, peer_id: Uuid) -> bool {
+        let peers = self.peers.read().await;
+        peers
+            .get(&peer_id)
+            .is_some_and(|p| p.key_exchange.is_established())
+    }
+
+    /// Codec currently used for audio sent to a peer
+    pub async fn peer_send_codec(&self, peer_id: Uuid) -> Option<CodecType> {
+        let peers = self.peers.read().await;
+        peers.get(&peer_id).map(|p| p.codec.lock().send_codec())
+    }
+
+    /// Get jitter buffer and PLC statistics for a peer's stream
+    pub async fn peer_receive_stats(&self, peer_id: Uuid) -> Option<ReceivePipelineStats> {
+        let peers = self.peers.read().await;
+        peers
+            .get(&peer_id)
+            .map(|p| p.playout.lock().pipeline.stats())
+    }
+
+    /// Get statistics for a peer
+    pub async fn peer_stats(&self, peer_id: Uuid) -> Option<PeerStats> {
+        let peers = self.peers.read().await;
+        peers.get(&peer_id).map(Peer::stats)
+    }
+
+    /// Get a peer's clock offset and drift relative to ours
+    ///
+    /// Use it to turn a time on the session timeline (`local_clock_us`) into
+    /// the peer's clock, e.g. for a scheduled start.
+    pub async fn peer_clock(&self, peer_id: Uuid) -> Option<ClockEstimate> {
+        let peers = self.peers.read().await;
+        let estimate = peers.get(&peer_id)?.rtt.lock().clock.estimate();
+        estimate
+    }
+
+    /// Map a peer's audio sample timestamp onto the session timeline
+    ///
+    /// Returns when the sample was sent, in `local_clock_us` microseconds,
+    /// or None before any audio arrived from the peer. Samples from
+    /// different peers with the same session time left them together.
+    pub async fn peer_timeline_us(&self, peer_id: Uuid, timestamp: u32) -> Option<u64> {
+        let peers = self.peers.read().await;
+        let local_us = peers
+            .get(&peer_id)?
+            .stream_clock
+            .lock()
+            .to_local_us(timestamp);
+        local_us
+    }
+
+    /// Get a peer's mix settings
+    pub async fn peer_mix(&self, peer_id: Uuid) -> Option<PeerMix> {
+        let peers = self.peers.read().await;
+        peers.get(&peer_id).map(|p| *p.mix.lock())
+    }
+
+    /// Set a peer's volume (1.0 = unity, clamped to 0.0 - 2.0)
+    pub async fn set_peer_volume(&self, peer_id: Uuid, volume: f32) -> Result<(), NetworkError> {
+        self.update_peer_mix(peer_id, |mix| mix.volume = volume.clamp(0.0, 2.0))
+            .await
+    }
+
+    /// Set a peer's pan (-1.0 = full left, 1.0 = full right)
+    pub async fn set_peer_pan(&self, peer_id: Uuid, pan: f32) -> Result<(), NetworkError> {
+        self.update_peer_mix(peer_id, |mix| mix.pan = pan.clamp(-1.0, 1.0))
+            .await
+    }
+
+    /// Mute or unmute a peer in the mix
+    pub async fn set_peer_muted(&self, peer_id: Uuid, muted: bool) -> Result<(), NetworkError> {
+        self.update_peer_mix(peer_id, |mix| mix.muted = muted).await
+    }
+
+    async fn update_peer_mix(
+        &self,
+        peer_id: Uuid,
+        update: impl FnOnce(&mut PeerMix),
+    ) -> Result<(), NetworkError> {
+        let peers = self.peers.read().await;
+        let peer = peers
+            .get(&peer_id)
+            .ok_or_else(|| NetworkError::PeerNotFound(peer_id.to_string()))?;
+        update(&mut peer.mix.lock());
+        Ok(())
+    }
+
+    /// Get the mix settings of one of a peer's additional streams
+    ///
+    /// They apply on top of the peer's own settings.
+    pub async fn stream_mix(&self, peer_id: Uuid, stream_id: u8) -> Option<PeerMix> {
+        let peers = self.peers.read().await;
+        let mix = peers
+            .get(&peer_id)?
+            .stream_mix
+            .lock()
+            .get(&stream_id)
+            .copied()
+            .unwrap_or_default();
+        Some(mix)
+    }
+
+    /// Set the volume of a peer's stream (1.0 = unity, clamped to 0.0 - 2.0)
+    pub async fn set_stream_volume(
+        &self,
+        peer_id: Uuid,
+        stream_id: u8,
+        volume: f32,
+    ) -> Result<(), NetworkError> {
+        self.update_stream_mix(peer_id, stream_id, |mix| {
+            mix.volume = volume.clamp(0.0, 2.0)
+        })
+        .await
+    }
+
+    /// Set the pan of a peer's stream (-1.0 = full left, 1.0 = full right)
+    pub async fn set_stream_pan(
+        &self,
+        peer_id: Uuid,
+        stream_id: u8,
+        pan: f32,
+    ) -> Result<(), NetworkError> {
+        self.update_stream_mix(peer_id, stream_id, |mix| mix.pan = pan.clamp(-1.0, 1.0))
+            .await
+    }
+
+    /// Mute or unmute a peer's stream in the mix
+    pub async fn set_stream_muted(
+        &self,
+        peer_id: Uuid,
+        stream_id: u8,
+        muted: bool,
+    ) -> Result<(), NetworkError> {
+        self.update_stream_mix(peer_id, stream_id, |mix| mix.muted = muted)
+            .await
+    }
+
+    /// Update a stream's mix settings (the main stream uses the peer's)
+    ///
+    /// Streams that have not arrived yet keep their settings for when they do.
+    async fn update_stream_mix(
+        &self,
+        peer_id: Uuid,
+        stream_id: u8,
+        update: impl FnOnce(&mut PeerMix),
+    ) -> Result<(), NetworkError> {
+        if stream_id == MAIN_STREAM_ID {
+            return self.update_peer_mix(peer_id, update).await;
+        }
+        let peers = self.peers.read().await;
+        let peer = peers
+            .get(&peer_id)
+            .ok_or_else(|| NetworkError::PeerNotFound(peer_id.to_string()))?;
+        update(peer.stream_mix.lock().entry(stream_id).or_default());
+        Ok(())
+    }
+
+    /// Set callback for individual peer audio
+    ///
+    /// Called with each peer's decoded audio as it arrives, without jitter
+    /// buffering.
+    pub fn set_peer_audio_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(Uuid, &[f32], u32) + Send + Sync + 'static,
+    {
+        self.peer_audio_callback = Some(Arc::new(Box::new(callback)));
+    }
+
+    /// Set callback for each stream of each peer
+    ///
+    /// Called with the decoded audio of every stream as it arrives, without
+    /// jitter buffering. The main stream is reported as `MAIN_STREAM_ID`.
+    pub fn set_stream_audio_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(Uuid, u8, &[f32], u32) + Send + Sync + 'static,
+    {
+        self.stream_audio_callback = Some(Arc::new(Box::new(callback)));
+    }
+
+    /// Set callback for mixed audio from all peers
+    ///
+    /// Called once per local frame (`SessionConfig::codec.frame_size`) with
+    /// one jitter-buffered frame from every peer that is playing.
+    pub fn set_mixed_audio_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&[f32], u32) + Send + Sync + 'static,
+    {
+        self.mixed_audio_callback = Some(Arc::new(Box::new(callback)));
+    }
+
+    /// Write every packet received from peers to a capture file
+    ///
+    /// Packets are recorded after decryption, with their arrival time and
+    /// source, so each peer's stream can be replayed on its own. Replaces any
+    /// capture in progress.
+    pub fn start_capture<P: AsRef<Path>>(&self, path: P) -> Result<(), NetworkError> {
+        let capture = PacketCapture::create(path)?;
+        *self.capture.lock() = Some(capture);
+        Ok(())
+    }
+
+    /// Stop capturing and flush the capture file (returns packets captured)
+    pub fn stop_capture(&self) -> Result<u64, NetworkError> {
+        match self.capture.lock().take() {
+            Some(capture) => capture.finish(),
+            None => Ok(0),
+        }
+    }
+
+    /// Start the session
+    ///
+    /// # Thread Safety
+    /// This method takes `&mut self`, ensuring exclusive access.
+    /// The `running` flag is set atomically before spawning the receive loop.
+    pub fn start(&mut self) {
+        if self.running.load(Ordering::SeqCst) {
+            return;
+        }
+
+        self.running.store(true, Ordering::SeqCst);
+        // Subscribe before the receive loop so no early check is lost
+        self.start_ice_loop();
+        self.start_receive_loop();
+        self.start_handshake_loop();
+        self.start_mix_loop();
+        self.start_control_loop();
+        info!("Session started on {}", self.transport.local_addr());
+    }
+
+    /// Stop the session
+    ///
+    /// # Thread Safety
+    /// This method takes `&mut self`, ensuring exclusive access.
+    /// The `running` flag is set to false atomically, which signals the
+    /// receive loop to terminate. The abort is a fallback.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        // Abort inner receive loop first (holds socket reference)
+        if let Some(handle) = self.inner_recv_handle.take() {
+            handle.abort();
+        }
+
+        // Then abort outer receive loop
+        if let Some(handle) = self.receive_handle.take() {
+            handle.abort();
+        }
+
+        if let Some(handle) = self.handshake_handle.take() {
+            handle.abort();
         }
 
-        // Convert f32 samples to bytes
-        let bytes: Vec<u8> = data.iter().flat_map(|&s| s.to_le_bytes()).collect();
-        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
-        let packet = Packet::audio(sequence, timestamp, bytes);
+        if let Some(handle) = self.mix_handle.take() {
+            handle.abort();
+        }
+
+        if let Some(handle) = self.ice_handle.take() {
+            handle.abort();
+        }
+
+        if let Some(handle) = self.control_handle.take() {
+            handle.abort();
+        }
+
+        info!("Session stopped");
+    }
+
+    /// Send audio to all peers
+    pub async fn broadcast_audio(&self, data: &[f32], timestamp: u32) -> Result<(), NetworkError> {
+        if !self.running.load(Ordering::SeqCst) {
+            return Err(NetworkError::NotConnected);
+        }
+
+        // Encode and send to each peer with its negotiated codec
+        let peers = self.peers.read().await;
+        for peer in peers.values() {
+            if peer.is_sendable() {
+                let secure = match peer.key_exchange.outbound() {
+                    Ok(secure) => secure,
+                    Err(e) => {
+                        trace!("Skipping peer {}: {}", peer.info.id, e);
+                        continue;
+                    }
+                };
+                for packet in peer.audio_packets(
+                    &self.config.fec,
+                    self.config.codec.channels,
+                    timestamp,
+                    data,
+                ) {
+                    match send_packet(&self.transport, secure.as_deref(), &packet, peer.addr).await
+                    {
+                        Ok(()) => peer.record_sent(&packet),
+                        Err(e) => warn!("Failed to send to peer {}: {}", peer.info.id, e),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send audio of one of our additional streams to all peers
+    ///
+    /// `data` is interleaved with the stream's channel count from
+    /// `SessionConfig::streams`. `MAIN_STREAM_ID` sends the main stream.
+    pub async fn broadcast_stream_audio(
+        &self,
+        stream_id: u8,
+        data: &[f32],
+        timestamp: u32,
+    ) -> Result<(), NetworkError> {
+        if stream_id == MAIN_STREAM_ID {
+            return self.broadcast_audio(data, timestamp).await;
+        }
+        if !self.running.load(Ordering::SeqCst) {
+            return Err(NetworkError::NotConnected);
+        }
+        let index = self
+            .config
+            .streams
+            .iter()
+            .position(|s| s.id == stream_id)
+            .ok_or(NetworkError::UnknownStream(stream_id))?;
+        let stream = &self.config.streams[index];
 
-        // Send to all peers
         let peers = self.peers.read().await;
         for peer in peers.values() {
-            if peer.connected.load(Ordering::SeqCst) {
-                if let Err(e) = self.transport.send_to(&packet, peer.addr).await {
-                    warn!("Failed to send to peer {}: {}", peer.info.id, e);
+            // Peers only get as many streams as they can receive
+            let max_streams = peer.capabilities.lock().negotiated().max_streams;
+            if !peer.is_sendable() || index >= max_streams as usize {
+                continue;
+            }
+            let secure = match peer.key_exchange.outbound() {
+                Ok(secure) => secure,
+                Err(e) => {
+                    trace!("Skipping peer {}: {}", peer.info.id, e);
+                    continue;
+                }
+            };
+            for packet in peer.stream_packets(stream, &self.config, timestamp, data) {
+                match send_packet(&self.transport, secure.as_deref(), &packet, peer.addr).await {
+                    Ok(()) => peer.record_sent(&packet),
+                    Err(e) => warn!("Failed to send to peer {}: {}", peer.info.id, e),
                 }
             }
         }
@@ -228,21 +1596,69 @@ impl Session {
             .get(&peer_id)
             .ok_or_else(|| NetworkError::PeerNotFound(peer_id.to_string()))?;
 
-        let bytes: Vec<u8> = data.iter().flat_map(|&s| s.to_le_bytes()).collect();
-        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
-        let packet = Packet::audio(sequence, timestamp, bytes);
+        if let Some(e) = peer.capabilities.lock().incompatibility() {
+            return Err(e);
+        }
+        let secure = peer.key_exchange.outbound()?;
+        for packet in peer.audio_packets(
+            &self.config.fec,
+            self.config.codec.channels,
+            timestamp,
+            data,
+        ) {
+            send_packet(&self.transport, secure.as_deref(), &packet, peer.addr).await?;
+            peer.record_sent(&packet);
+        }
+        Ok(())
+    }
 
-        self.transport.send_to(&packet, peer.addr).await?;
+    /// Send a control message to a peer
+    ///
+    /// The message is queued on the peer's control channel and retransmitted
+    /// until acknowledged; if keys are still being exchanged it goes out once
+    /// encryption allows.
+    pub async fn send_control(
+        &self,
+        peer_id: Uuid,
+        message: ControlMessage,
+    ) -> Result<(), NetworkError> {
+        let peers = self.peers.read().await;
+        let peer = peers
+            .get(&peer_id)
+            .ok_or_else(|| NetworkError::PeerNotFound(peer_id.to_string()))?;
+        self.send_control_to(peer, message).await;
         Ok(())
     }
 
+    /// Send a control message to all peers
+    pub async fn broadcast_control(&self, message: ControlMessage) {
+        let peers = self.peers.read().await;
+        for peer in peers.values() {
+            self.send_control_to(peer, message.clone()).await;
+        }
+    }
+
+    async fn send_control_to(&self, peer: &Peer, message: ControlMessage) {
+        let packet = peer.control.lock().send(message, Instant::now());
+        let Ok(secure) = peer.key_exchange.outbound() else {
+            return;
+        };
+        if let Err(e) = send_packet(&self.transport, secure.as_deref(), &packet, peer.addr).await {
+            warn!("Failed to send control message to {}: {}", peer.info.id, e);
+        }
+    }
+
     fn start_receive_loop(&mut self) {
         let transport = self.transport.clone();
         let peers = self.peers.clone();
         let running = self.running.clone();
         let peer_callback = self.peer_audio_callback.clone();
-        let mixed_callback = self.mixed_audio_callback.clone();
+        let stream_callback = self.stream_audio_callback.clone();
+        let control_callback = self.control_callback.clone();
+        let metronome = self.metronome.clone();
+        let capture = self.capture.clone();
         let enable_mixing = self.config.enable_mixing;
+        let stream_pipeline = self.pipeline_config();
 
         // Start inner receive loop and store handle for cleanup
         let (mut rx, inner_handle) = transport.clone().start_receive_loop();
@@ -250,56 +1666,683 @@ impl Session {
 
         let handle = tokio::spawn(async move {
             while let Some((packet, addr)) = rx.recv().await {
+                let received_at_us = local_clock_us();
                 if !running.load(Ordering::SeqCst) {
                     break;
                 }
 
-                if packet.packet_type != PacketType::Audio {
+                if !matches!(
+                    packet.packet_type,
+                    PacketType::Audio
+                        | PacketType::Fec
+                        | PacketType::KeyExchange
+                        | PacketType::Hello
+                        | PacketType::CodecOffer
+                        | PacketType::LatencyPing
+                        | PacketType::LatencyPong
+                        | PacketType::Control
+                        | PacketType::ReceiverReport
+                ) {
                     continue;
                 }
 
-                // Find peer by address
-                let mut peers_guard = peers.write().await;
+                // Find peer by address. Receive state has its own locks, so
+                // only a key exchange needs the map for writing.
+                let peers_guard = peers.read().await;
                 let peer_id = {
                     let peer = peers_guard.values().find(|p| p.addr == addr);
                     peer.map(|p| p.info.id)
                 };
 
-                if let Some(peer_id) = peer_id {
-                    // Convert bytes to f32 samples
-                    let samples: Vec<f32> = packet
-                        .payload
-                        .chunks_exact(4)
-                        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-                        .collect();
+                // Decrypt, or reject cleartext if encryption is required
+                let wire_len = (packet.header_len() + packet.payload.len()) as u64;
+                let mut packet = match peer_id.and_then(|id| peers_guard.get(&id)) {
+                    Some(peer) => match peer.key_exchange.open(packet) {
+                        Ok(packet) => {
+                            peer.bytes_received.fetch_add(wire_len, Ordering::Relaxed);
+                            *peer.last_received.lock() = Some(Instant::now());
+                            packet
+                        }
+                        Err(e) => {
+                            trace!("Dropped packet from {}: {}", addr, e);
+                            continue;
+                        }
+                    },
+                    None => packet,
+                };
+                capture_packet(&capture, received_at_us, addr, &packet);
+
+                if packet.packet_type == PacketType::Hello {
+                    let Some(hello) = HelloPayload::from_bytes(&packet.payload) else {
+                        continue;
+                    };
+                    let Some(peer) = peer_id.and_then(|id| peers_guard.get(&id)) else {
+                        debug!("Received hello from unknown address: {}", addr);
+                        continue;
+                    };
+                    let (reply, negotiated) = {
+                        let mut capabilities = peer.capabilities.lock();
+                        let reply = capabilities
+                            .handle_hello(hello)
+                            .then(|| capabilities.hello_packet(peer.next_sequence(), true));
+                        (reply, capabilities.agreed())
+                    };
+                    // Keep frames within what the peer accepts
+                    if let Some(negotiated) = negotiated {
+                        let limited = peer
+                            .quality
+                            .lock()
+                            .as_mut()
+                            .and_then(|q| q.limit_frame_size(negotiated.max_frame_size));
+                        if let Some(settings) = limited {
+                            peer.codec.lock().apply_quality(&settings);
+                        }
+                    }
+                    // Hellos go out in cleartext until the session keys are
+                    // established, sealed after that
+                    let secure = peer.key_exchange.outbound().ok().flatten();
+                    drop(peers_guard);
+
+                    if let Some(reply) = reply {
+                        if let Err(e) =
+                            send_packet(&transport, secure.as_deref(), &reply, addr).await
+                        {
+                            warn!("Failed to send hello to {}: {}", addr, e);
+                        }
+                    }
+                    continue;
+                }
+
+                // Nothing but hellos from a peer that cannot talk to us
+                if peer_id
+                    .and_then(|id| peers_guard.get(&id))
+                    .is_some_and(|p| p.capabilities.lock().is_incompatible())
+                {
+                    continue;
+                }
+
+                if packet.packet_type == PacketType::KeyExchange {
+                    let Some(payload) = KeyExchangePayload::from_bytes(&packet.payload) else {
+                        continue;
+                    };
+                    drop(peers_guard);
+                    let mut peers_guard = peers.write().await;
+                    let Some(peer) = peer_id.and_then(|id| peers_guard.get_mut(&id)) else {
+                        debug!("Received key exchange from unknown address: {}", addr);
+                        continue;
+                    };
+                    let was_established = peer.key_exchange.is_established();
+                    let reply = match peer.key_exchange.handle_key_exchange(&transport, &payload) {
+                        Ok(reply) => reply,
+                        Err(e) => {
+                            // Anyone can send a cleartext key exchange, so a
+                            // bad one is dropped, not fatal
+                            peer.key_exchanges_rejected.fetch_add(1, Ordering::Relaxed);
+                            debug!("Key exchange from {} rejected: {}", addr, e);
+                            continue;
+                        }
+                    };
+                    if !was_established && peer.key_exchange.is_established() {
+                        info!("End-to-end encryption established with {}", peer.info.id);
+                    }
+                    drop(peers_guard);
+
+                    if let Some(reply) = reply {
+                        if let Err(e) = transport.send_to(&reply, addr).await {
+                            warn!("Failed to send key exchange to {}: {}", addr, e);
+                        }
+                    }
+                    continue;
+                }
+
+                if packet.packet_type == PacketType::CodecOffer {
+                    let Some(offer) = CodecOfferPayload::from_bytes(&packet.payload) else {
+                        continue;
+                    };
+                    let Some(peer) = peer_id.and_then(|id| peers_guard.get(&id)) else {
+                        debug!("Received codec offer from unknown address: {}", addr);
+                        continue;
+                    };
+                    let reply = {
+                        let mut codec = peer.codec.lock();
+                        codec
+                            .handle_offer(offer)
+                            .then(|| codec.offer_packet(peer.next_sequence(), true))
+                    };
+                    let secure = peer.key_exchange.outbound();
+                    drop(peers_guard);
+
+                    if let (Some(reply), Ok(secure)) = (reply, secure) {
+                        if let Err(e) =
+                            send_packet(&transport, secure.as_deref(), &reply, addr).await
+                        {
+                            warn!("Failed to send codec offer to {}: {}", addr, e);
+                        }
+                    }
+                    continue;
+                }
+
+                if packet.packet_type == PacketType::LatencyPing {
+                    let Some(ping) = LatencyPing::from_bytes(&packet.payload) else {
+                        continue;
+                    };
+                    let Some(peer) = peer_id.and_then(|id| peers_guard.get(&id)) else {
+                        continue;
+                    };
+                    let pong = Packet::latency_pong(
+                        peer.next_sequence(),
+                        &LatencyPong::reply(&ping, received_at_us, local_clock_us()),
+                    );
+                    let secure = peer.key_exchange.outbound();
+                    drop(peers_guard);
+
+                    if let Ok(secure) = secure {
+                        if let Err(e) =
+                            send_packet(&transport, secure.as_deref(), &pong, addr).await
+                        {
+                            warn!("Failed to send latency pong to {}: {}", addr, e);
+                        }
+                    }
+                    continue;
+                }
+
+                if packet.packet_type == PacketType::LatencyPong {
+                    if let (Some(pong), Some(peer)) = (
+                        LatencyPong::from_bytes(&packet.payload),
+                        peer_id.and_then(|id| peers_guard.get(&id)),
+                    ) {
+                        peer.rtt.lock().process_pong(&pong);
+                    }
+                    continue;
+                }
+
+                if packet.packet_type == PacketType::ReceiverReport {
+                    if let (Some(report), Some(peer)) = (
+                        ReceiverReport::from_bytes(&packet.payload),
+                        peer_id.and_then(|id| peers_guard.get(&id)),
+                    ) {
+                        *peer.remote_reception.lock() =
+                            Some(RemoteReceptionStats::from_report(&report, received_at_us));
+                    }
+                    continue;
+                }
+
+                if packet.packet_type == PacketType::Control {
+                    let Some(peer) = peer_id.and_then(|id| peers_guard.get(&id)) else {
+                        continue;
+                    };
+                    let id = peer.info.id;
+                    let (ack, messages) = peer.control.lock().handle_packet(&packet);
+                    let secure = peer.key_exchange.outbound();
+                    let one_way = peer.one_way();
+                    drop(peers_guard);
+
+                    if let (Some(ack), Ok(secure)) = (ack, secure) {
+                        if let Err(e) = send_packet(&transport, secure.as_deref(), &ack, addr).await
+                        {
+                            warn!("Failed to acknowledge control message from {}: {}", addr, e);
+                        }
+                    }
+                    for message in messages {
+                        if let (ControlMessage::Metronome(sync), Some(metronome)) =
+                            (&message, &metronome)
+                        {
+                            metronome.handle_sync(sync, one_way);
+                        }
+                        if let Some(callback) = &control_callback {
+                            callback(id, message);
+                        }
+                    }
+                    continue;
+                }
+
+                let Some(peer) = peer_id.and_then(|id| peers_guard.get(&id)) else {
+                    debug!("Received audio from unknown address: {}", addr);
+                    continue;
+                };
+                if let Some(header) = packet.stream {
+                    peer.receive_stream_packet(
+                        header,
+                        packet,
+                        &stream_pipeline,
+                        stream_callback.as_deref(),
+                        enable_mixing,
+                    );
+                    continue;
+                }
+                let callback = AudioCallbacks {
+                    peer: peer_callback.as_deref(),
+                    stream: stream_callback.as_deref(),
+                };
+
+                if packet.packet_type == PacketType::Fec {
+                    let Some(fec) = FecPacket::from_bytes(&packet.payload) else {
+                        continue;
+                    };
+                    let recovered = peer.fec_decoder.lock().add_fec_all(fec);
+                    for recovered in recovered {
+                        trace!("Recovered audio seq={} from FEC", recovered.sequence);
+                        peer.deliver_audio(recovered, true, callback, enable_mixing);
+                    }
+                    continue;
+                }
+
+                peer.packets_received.fetch_add(1, Ordering::Relaxed);
+                peer.sequence_tracker.lock().record(packet.sequence);
+                peer.reception
+                    .lock()
+                    .on_packet(packet.timestamp, received_at_us);
+                let one_way = peer.one_way().as_micros() as u64;
+                peer.stream_clock
+                    .lock()
+                    .on_packet(packet.timestamp, received_at_us, one_way);
+                let Some(redundant) = split_redundancy(&mut packet) else {
+                    trace!("Dropped malformed redundant audio from {}", addr);
+                    continue;
+                };
+                if enable_mixing {
+                    peer.playout.lock().insert_redundant(redundant);
+                }
+                if peer.fec_decoder.lock().contains(packet.sequence) {
+                    // Already rebuilt from FEC
+                    continue;
+                }
+                let recovered = peer.fec_decoder.lock().add_packet_all(
+                    packet.sequence,
+                    packet.timestamp,
+                    packet.flags.codec,
+                    &packet.payload,
+                );
+
+                let audio = RecoveredAudio {
+                    sequence: packet.sequence,
+                    timestamp: packet.timestamp,
+                    codec: packet.flags.codec,
+                    payload: packet.payload,
+                };
+                peer.deliver_audio(audio, false, callback, enable_mixing);
+                for recovered in recovered {
+                    peer.deliver_audio(recovered, true, callback, enable_mixing);
+                }
+            }
+        });
+
+        self.receive_handle = Some(handle);
+    }
+
+    /// Resend our public key, hello and codec offer to peers until theirs
+    /// arrive, and ping every peer for RTT
+    fn start_handshake_loop(&mut self) {
+        let transport = self.transport.clone();
+        let peers = self.peers.clone();
+        let running = self.running.clone();
+        let quality_callback = self.quality_callback.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HANDSHAKE_RETRY_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let (hellos, offers, pings, reports) = {
+                    let peers = peers.read().await;
+                    // Hellos before keys (see `add_peer`), sealed once the
+                    // session keys are established
+                    let mut hellos: Vec<(SocketAddr, Option<Arc<EncryptedTransport>>, Packet)> =
+                        peers
+                            .values()
+                            .filter(|p| p.capabilities.lock().needs_hello())
+                            .map(|p| {
+                                let secure = p.key_exchange.outbound().ok().flatten();
+                                (
+                                    p.addr,
+                                    secure,
+                                    p.capabilities.lock().hello_packet(p.next_sequence(), false),
+                                )
+                            })
+                            .collect();
+                    hellos.extend(
+                        peers
+                            .values()
+                            .filter(|p| p.key_exchange.needs_key_exchange())
+                            .map(|p| (p.addr, None, p.key_exchange.key_exchange_packet(false))),
+                    );
+                    // Codec offers wait until encryption allows them
+                    let offers: Vec<_> = peers
+                        .values()
+                        .filter(|p| p.codec.lock().needs_offer())
+                        .filter_map(|p| {
+                            let secure = p.key_exchange.outbound().ok()?;
+                            let seq = p.next_sequence();
+                            Some((p.addr, secure, p.codec.lock().offer_packet(seq, false)))
+                        })
+                        .collect();
+                    let pings: Vec<_> = peers
+                        .values()
+                        .filter_map(|p| {
+                            let secure = p.key_exchange.outbound().ok()?;
+                            let seq = p.next_sequence();
+                            let ping = p.rtt.lock().create_ping();
+                            Some((p.addr, secure, Packet::latency_ping(seq, &ping)))
+                        })
+                        .collect();
+                    // Tell each peer how its audio arrives
+                    let now = Instant::now();
+                    let reports: Vec<_> = peers
+                        .values()
+                        .filter_map(|p| {
+                            let secure = p.key_exchange.outbound().ok()?;
+                            let report = p.receiver_report(now)?;
+                            let seq = p.next_sequence();
+                            Some((p.addr, secure, Packet::receiver_report(seq, &report)))
+                        })
+                        .collect();
+                    // Adapt audio sent to each peer to its path
+                    for peer in peers.values() {
+                        if let Some(decision) = peer.update_quality(now) {
+                            debug!(
+                                "Audio quality to peer {} changed: {}",
+                                peer.info.id, decision
+                            );
+                            if let Some(callback) = &quality_callback {
+                                callback(peer.info.id, decision);
+                            }
+                        }
+                    }
+                    (hellos, offers, pings, reports)
+                };
+
+                for (addr, secure, hello) in hellos {
+                    if let Err(e) = send_packet(&transport, secure.as_deref(), &hello, addr).await {
+                        warn!("Failed to send handshake to {}: {}", addr, e);
+                    }
+                }
+                for (addr, secure, offer) in offers {
+                    if let Err(e) = send_packet(&transport, secure.as_deref(), &offer, addr).await {
+                        warn!("Failed to send codec offer to {}: {}", addr, e);
+                    }
+                }
+                for (addr, secure, ping) in pings {
+                    if let Err(e) = send_packet(&transport, secure.as_deref(), &ping, addr).await {
+                        warn!("Failed to send latency ping to {}: {}", addr, e);
+                    }
+                }
+                for (addr, secure, report) in reports {
+                    if let Err(e) = send_packet(&transport, secure.as_deref(), &report, addr).await
+                    {
+                        warn!("Failed to send receiver report to {}: {}", addr, e);
+                    }
+                }
+            }
+        });
+
+        self.handshake_handle = Some(handle);
+    }
+}
+
+impl Session {
+    /// Retransmit control messages until each peer acknowledges them, and
+    /// send metronome syncs while we own the tempo
+    fn start_control_loop(&mut self) {
+        let transport = self.transport.clone();
+        let peers = self.peers.clone();
+        let running = self.running.clone();
+        let metronome = self.metronome.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CONTROL_POLL_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let sync = metronome.as_ref().and_then(|m| m.take_sync());
+                let retransmits: Vec<_> = {
+                    let peers = peers.read().await;
+                    let now = Instant::now();
+                    peers
+                        .values()
+                        .filter_map(|p| {
+                            let mut control = p.control.lock();
+                            let sent =
+                                sync.map(|s| control.send(ControlMessage::Metronome(s), now));
+                            // Held back until encryption allows sending
+                            let secure = p.key_exchange.outbound().ok()?;
+                            let mut packets: Vec<_> = sent.into_iter().collect();
+                            packets.extend(control.poll(now));
+                            (!packets.is_empty()).then_some((p.addr, secure, packets))
+                        })
+                        .collect()
+                };
+
+                for (addr, secure, packets) in retransmits {
+                    for packet in packets {
+                        if let Err(e) =
+                            send_packet(&transport, secure.as_deref(), &packet, addr).await
+                        {
+                            warn!("Failed to retransmit control message to {}: {}", addr, e);
+                        }
+                    }
+                }
+            }
+        });
+
+        self.control_handle = Some(handle);
+    }
+
+    /// Run every peer's ICE agent, follow their selected pairs and track
+    /// each peer's liveness
+    ///
+    /// A peer is re-targeted when its agent nominates a different pair, and
+    /// is skipped by the mixer and senders while ICE consent is lost. Peers
+    /// that never answer checks keep their signaled address. A silent peer is
+    /// reconnecting: its ICE checks restart over all of its candidates.
+    fn start_ice_loop(&mut self) {
+        let transport = self.transport.clone();
+        let peers = self.peers.clone();
+        let running = self.running.clone();
+        let ice_config = self.config.ice.clone();
+        let reconnect = self.config.reconnect;
+        let local_candidates = self.local_candidates.clone();
+        let signaling_id = self.signaling_id.clone();
+        let state_callback = self.peer_state_callback.clone();
+        let check_interval = ice_config.check_interval;
+        let mut stun_rx = transport.stun_messages();
+
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(check_interval);
+
+            loop {
+                let transmits = tokio::select! {
+                    datagram = stun_rx.recv() => {
+                        let Some(datagram) = datagram else {
+                            break;
+                        };
+                        let peers = peers.read().await;
+                        match find_ice_peer(&peers, &datagram) {
+                            Some(peer) => {
+                                ice::handle_datagram(&mut peer.ice.lock(), &datagram, Instant::now())
+                            }
+                            None => {
+                                trace!("ICE message from unknown peer {}", datagram.from);
+                                Vec::new()
+                            }
+                        }
+                    }
+                    _ = interval.tick() => {
+                        if !running.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        let mut peers = peers.write().await;
+                        let now = Instant::now();
+                        let mut transmits = Vec::new();
+                        for peer in peers.values_mut() {
+                            if let Some(state) = peer.update_state(&reconnect, now) {
+                                if state == ConnectionState::Reconnecting {
+                                    *peer.ice.lock() = new_ice_agent(
+                                        &transport,
+                                        &local_candidates.lock(),
+                                        &ice_config,
+                                        *signaling_id.lock(),
+                                        &peer.info,
+                                        peer.addr,
+                                    );
+                                    peer.ice_selected = None;
+                                }
+                                if let Some(callback) = &state_callback {
+                                    callback(peer.info.id, state);
+                                }
+                            }
+
+                            let mut agent = peer.ice.lock();
+                            transmits.extend(agent.poll(now));
+                            let selected = agent.selected();
+                            let state = agent.state();
+                            drop(agent);
+
+                            if let Some((addr, relayed)) = selected {
+                                if peer.ice_selected != selected {
+                                    info!("ICE selected {} for peer {}", addr, peer.info.id);
+                                    transport.set_relayed(addr, relayed);
+                                    peer.addr = addr;
+                                    peer.ice_selected = selected;
+                                }
+                            }
+                            match state {
+                                IceState::Connected => peer.connected.store(true, Ordering::SeqCst),
+                                IceState::Disconnected => {
+                                    if peer.connected.swap(false, Ordering::SeqCst) {
+                                        warn!("ICE consent to peer {} expired", peer.info.id);
+                                    }
+                                }
+                                IceState::Checking | IceState::Failed => {}
+                            }
+                        }
+                        transmits
+                    }
+                };
+                ice::send_transmits(&transport, transmits).await;
+            }
+        });
+
+        self.ice_handle = Some(handle);
+    }
+
+    /// Receive pipeline configuration for each peer's stream
+    fn pipeline_config(&self) -> ReceivePipelineConfig {
+        ReceivePipelineConfig {
+            drift_compensation: self.config.drift_compensation,
+            ..ReceivePipelineConfig::new(
+                self.config.jitter_buffer,
+                CodecConfig {
+                    codec_type: CodecType::Pcm,
+                    ..self.config.codec.clone()
+                },
+            )
+        }
+    }
+
+    /// Mix one frame from every peer on the local frame clock
+    ///
+    /// The shared metronome's click is added while it runs, even without
+    /// peers. Does nothing if mixing is disabled or no mixed audio callback is
+    /// set.
+    fn start_mix_loop(&mut self) {
+        if !self.config.enable_mixing {
+            return;
+        }
+        let Some(callback) = self.mixed_audio_callback.clone() else {
+            return;
+        };
+        let peers = self.peers.clone();
+        let running = self.running.clone();
+        let frame_size = self.config.codec.frame_size;
+        let in_channels = self.config.codec.channels.max(1);
+        let out_channels = self.config.mix_channels.max(1);
+        let frame_len = frame_size as usize * in_channels as usize;
+        let metronome = self.metronome.clone();
+        // Peer frames are only upmixed from mono to stereo
+        let mixed_channels = if in_channels == 1 && out_channels == 2 {
+            2
+        } else {
+            in_channels
+        };
+        let frame_duration = self.pipeline_config().frame_duration();
+
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(frame_duration);
+            let mut timestamp: u32 = 0;
+
+            loop {
+                interval.tick().await;
 
-                    // Update peer's last audio
-                    if let Some(peer) = peers_guard.get_mut(&peer_id) {
-                        peer.packets_received.fetch_add(1, Ordering::Relaxed);
-                        peer.last_audio = Some(samples.clone());
-                    }
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
 
-                    // Call per-peer callback
-                    if let Some(ref callback) = peer_callback {
-                        callback(peer_id, &samples, packet.timestamp);
-                    }
+                let frames: Vec<Vec<f32>> = {
+                    let peers = peers.read().await;
+                    let mut frames = Vec::new();
+                    for peer in peers
+                        .values()
+                        .filter(|p| p.connected.load(Ordering::SeqCst))
+                    {
+                        // Muted peers keep playing out so they resume in sync
+                        let mix = *peer.mix.lock();
+                        if let Some(frame) = peer.playout.lock().next_frame(frame_len) {
+                            if !mix.muted {
+                                frames.push(apply_peer_mix(
+                                    &frame,
+                                    mix,
+                                    in_channels,
+                                    mixed_channels,
+                                ));
+                            }
+                        }
 
-                    // Mix audio from all peers if enabled
-                    if enable_mixing {
-                        if let Some(ref callback) = mixed_callback {
-                            let mixed = mix_audio(&peers_guard);
-                            if !mixed.is_empty() {
-                                callback(&mixed, packet.timestamp);
+                        let stream_mix = peer.stream_mix.lock();
+                        for (stream_id, stream) in peer.streams.lock().iter() {
+                            let len = frame_size as usize * stream.channels as usize;
+                            let Some(frame) = stream.playout.lock().next_frame(len) else {
+                                continue;
+                            };
+                            let mix = mix.with_stream(
+                                stream_mix.get(stream_id).copied().unwrap_or_default(),
+                            );
+                            if !mix.muted && can_mix(stream.channels, mixed_channels) {
+                                frames.push(apply_peer_mix(
+                                    &frame,
+                                    mix,
+                                    stream.channels,
+                                    mixed_channels,
+                                ));
                             }
                         }
                     }
-                } else {
-                    debug!("Received audio from unknown address: {}", addr);
+                    frames
+                };
+
+                let mut mixed = mix_audio(&frames);
+                if let Some(metronome) = metronome.as_ref().filter(|m| m.is_running()) {
+                    if mixed.is_empty() {
+                        mixed = vec![0.0; frame_size as usize * mixed_channels as usize];
+                    }
+                    metronome.mix_into(&mut mixed, mixed_channels);
+                }
+                if !mixed.is_empty() {
+                    callback(&mixed, timestamp);
                 }
+                timestamp = timestamp.wrapping_add(frame_size);
             }
         });
 
-        self.receive_handle = Some(handle);
+        self.mix_handle = Some(handle);
     }
 }
 
@@ -309,13 +2352,86 @@ impl Drop for Session {
     }
 }
 
-/// Mix audio from all peers
-fn mix_audio(peers: &HashMap<Uuid, Peer>) -> Vec<f32> {
-    let audio_buffers: Vec<&Vec<f32>> = peers
-        .values()
-        .filter_map(|p| p.last_audio.as_ref())
-        .collect();
+/// ICE agent checking the paths to a peer
+fn new_ice_agent(
+    transport: &UdpTransport,
+    local_candidates: &[AddressCandidate],
+    config: &IceConfig,
+    local_id: Option<Uuid>,
+    info: &PeerInfo,
+    addr: SocketAddr,
+) -> IceAgent {
+    let mut agent = IceAgent::new(
+        ice::local_candidates(transport, local_candidates),
+        config.clone(),
+    );
+    agent.add_remote_candidates(&remote_candidates(info, addr));
+    if let Some(local_id) = local_id {
+        agent.set_usernames(local_id.to_string(), info.id.to_string());
+    }
+    agent
+}
+
+/// Candidates for a peer's ICE agent: its signaled ones plus `addr`
+fn remote_candidates(info: &PeerInfo, addr: SocketAddr) -> Vec<AddressCandidate> {
+    let mut candidates = info.candidates.clone();
+    if !candidates.iter().any(|c| c.address == addr) {
+        candidates.push(AddressCandidate::host(addr));
+    }
+    candidates
+}
+
+/// Find the peer a STUN message is for
+///
+/// Requests name the sender in USERNAME; responses match one of our
+/// transactions. Anything else is matched by the sender's address.
+fn find_ice_peer<'a>(peers: &'a HashMap<Uuid, Peer>, datagram: &StunDatagram) -> Option<&'a Peer> {
+    let message = BindingMessage::from_bytes(&datagram.data)?;
+    let by_username = message
+        .username
+        .as_deref()
+        .and_then(|username| username.split_once(':'))
+        .and_then(|(_, sender)| sender.parse::<Uuid>().ok())
+        .and_then(|sender| peers.get(&sender));
+    by_username
+        .or_else(|| {
+            peers
+                .values()
+                .find(|p| p.ice.lock().owns_transaction(&message.transaction_id))
+        })
+        .or_else(|| {
+            peers
+                .values()
+                .find(|p| p.ice.lock().has_remote(datagram.from))
+        })
+}
+
+/// Apply a peer's volume, and its pan when upmixing mono to stereo
+///
+/// Stereo is averaged down to mono for a mono mix.
+fn apply_peer_mix(frame: &[f32], mix: PeerMix, in_channels: u16, out_channels: u16) -> Vec<f32> {
+    if in_channels == 1 && out_channels == 2 {
+        // Constant power panning: both channels get ~0.707 at center
+        let angle = (mix.pan + 1.0) / 2.0 * std::f32::consts::FRAC_PI_2;
+        let (left, right) = (angle.cos() * mix.volume, angle.sin() * mix.volume);
+        frame.iter().flat_map(|&s| [s * left, s * right]).collect()
+    } else if in_channels == 2 && out_channels == 1 {
+        frame
+            .chunks_exact(2)
+            .map(|pair| (pair[0] + pair[1]) * 0.5 * mix.volume)
+            .collect()
+    } else {
+        frame.iter().map(|&s| s * mix.volume).collect()
+    }
+}
+
+/// Whether a stream of `in_channels` can go into a mix of `out_channels`
+fn can_mix(in_channels: u16, out_channels: u16) -> bool {
+    in_channels == out_channels || matches!((in_channels, out_channels), (1, 2) | (2, 1))
+}
 
+/// Mix one frame from each peer
+fn mix_audio(audio_buffers: &[Vec<f32>]) -> Vec<f32> {
     if audio_buffers.is_empty() {
         return Vec::new();
     }
@@ -331,7 +2447,7 @@ fn mix_audio(peers: &HashMap<Uuid, Peer>) -> Vec<f32> {
     let mut mixed = vec![0.0f32; max_len];
     let num_sources = audio_buffers.len() as f32;
 
-    for buffer in &audio_buffers {
+    for buffer in audio_buffers {
         for (i, &sample) in buffer.iter().enumerate() {
             mixed[i] += sample / num_sources;
         }
@@ -357,6 +2473,7 @@ fn soft_clip(x: f32) -> f32 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::{QualityReason, FEC_GROUP_SIZE};
 
     #[test]
     fn test_soft_clip() {
@@ -372,15 +2489,1168 @@ mod tests {
 
     #[test]
     fn test_mix_audio_empty() {
-        let peers: HashMap<Uuid, Peer> = HashMap::new();
-        let mixed = mix_audio(&peers);
+        let mixed = mix_audio(&[]);
         assert!(mixed.is_empty());
     }
 
+    #[test]
+    fn test_mix_audio_averages_peers() {
+        let mixed = mix_audio(&[vec![0.2; 4], vec![0.4; 4]]);
+        assert_eq!(mixed.len(), 4);
+        assert!((mixed[0] - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_apply_peer_mix() {
+        let left = PeerMix {
+            pan: -1.0,
+            ..Default::default()
+        };
+        let stereo = apply_peer_mix(&[0.5, 0.5], left, 1, 2);
+        assert_eq!(stereo.len(), 4);
+        assert!((stereo[0] - 0.5).abs() < 1e-6);
+        assert!(stereo[1].abs() < 1e-6);
+
+        let half = PeerMix {
+            volume: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(apply_peer_mix(&[0.5, 0.5], half, 1, 1), vec![0.25, 0.25]);
+
+        // Stereo streams are averaged into a mono mix
+        assert_eq!(
+            apply_peer_mix(&[0.5, 0.25, 1.0, 0.0], half, 2, 1),
+            vec![0.1875, 0.25]
+        );
+        assert!(can_mix(2, 1));
+        assert!(!can_mix(4, 2));
+    }
+
+    #[test]
+    fn test_stream_mix_applies_on_top_of_peer() {
+        let peer = PeerMix {
+            volume: 0.5,
+            pan: -0.5,
+            muted: false,
+        };
+        let stream = PeerMix {
+            volume: 1.5,
+            pan: -1.0,
+            muted: false,
+        };
+        let mix = peer.with_stream(stream);
+        assert_eq!(mix.volume, 0.75);
+        assert_eq!(mix.pan, -1.0);
+        assert!(!mix.muted);
+
+        let muted = PeerMix {
+            muted: true,
+            ..Default::default()
+        };
+        assert!(muted.with_stream(PeerMix::default()).muted);
+        assert!(PeerMix::default().with_stream(muted).muted);
+    }
+
+    fn test_playout(frame_size: u32) -> PeerPlayout {
+        let codec = CodecConfig {
+            frame_size,
+            ..Default::default()
+        };
+        let mut config = ReceivePipelineConfig::new(JitterBufferMode::Fixed, codec);
+        config.jitter_buffer.min_delay_frames = 1;
+        config.jitter_buffer.initial_delay_frames = 1;
+        PeerPlayout::new(config)
+    }
+
+    fn pcm_payload(value: f32, len: usize) -> Vec<u8> {
+        (0..len).flat_map(|_| value.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_playout_reframes_smaller_peer_frames() {
+        // Peer sends 4-sample frames, we mix 8-sample frames
+        let mut playout = test_playout(8);
+        for sequence in 0..4 {
+            playout.pipeline.insert(
+                sequence,
+                sequence * 4,
+                pcm_payload(sequence as f32 / 10.0, 4),
+            );
+        }
+
+        let first = playout.next_frame(8).unwrap();
+        assert_eq!(first.len(), 8);
+        assert!((first[0] - 0.0).abs() < 1e-6);
+        assert!((first[4] - 0.1).abs() < 1e-6);
+
+        let second = playout.next_frame(8).unwrap();
+        assert!((second[0] - 0.2).abs() < 1e-6);
+        assert!((second[4] - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_playout_reframes_larger_peer_frames() {
+        // Peer sends 8-sample frames, we mix 4-sample frames
+        let mut playout = test_playout(4);
+        playout.pipeline.insert(0, 0, pcm_payload(0.5, 8));
+
+        assert_eq!(playout.next_frame(4).unwrap(), vec![0.5; 4]);
+        // Second half of the same packet, no new frame pulled
+        assert_eq!(playout.next_frame(4).unwrap(), vec![0.5; 4]);
+        assert_eq!(playout.pipeline.stats().frames_decoded, 1);
+    }
+
+    #[test]
+    fn test_playout_waits_for_jitter_buffer() {
+        let mut playout = test_playout(4);
+        assert!(playout.next_frame(4).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_session_audio() {
+        let config = SessionConfig {
+            encryption: EncryptionMode::Required,
+            ..Default::default()
+        };
+        let mut alice = Session::new(config.clone()).await.unwrap();
+        let mut bob = Session::new(config).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_peer_audio_callback(move |peer_id, samples, _| {
+            let _ = tx.send((peer_id, samples.to_vec()));
+        });
+        alice.start();
+        bob.start();
+
+        // Both publish their key through signaling
+        let peer_info = |id: Uuid, public_key: [u8; 32]| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: Some(public_key),
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        alice
+            .add_peer(
+                peer_info(bob_id, bob.public_key()),
+                loopback(bob.local_addr().port()),
+            )
+            .await
+            .unwrap();
+        bob.add_peer(
+            peer_info(alice_id, alice.public_key()),
+            loopback(alice.local_addr().port()),
+        )
+        .await
+        .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !alice.is_peer_encrypted(bob_id).await || !bob.is_peer_encrypted(alice_id).await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Key exchange did not complete");
+
+        alice.broadcast_audio(&[0.25; 8], 0).await.unwrap();
+
+        let (peer_id, samples) = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("Timed out waiting for audio")
+            .unwrap();
+        assert_eq!(peer_id, alice_id);
+        assert_eq!(samples, vec![0.25; 8]);
+
+        // Encrypted latency pings measure the round trip
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while alice.peer_stats(bob_id).await.unwrap().rtt_ms == 0.0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("No RTT measured");
+        let stats = alice.peer_stats(bob_id).await.unwrap();
+        assert_eq!(stats.packets_sent, 1);
+        assert!(stats.bytes_sent > 0);
+        assert!(stats.bytes_received > 0);
+    }
+
+    #[tokio::test]
+    async fn test_session_additional_streams() {
+        let guitar = StreamInfo {
+            id: 1,
+            name: "Guitar DI".to_string(),
+            channels: 2,
+        };
+        let mut alice = Session::new(SessionConfig {
+            encryption: EncryptionMode::Required,
+            streams: vec![guitar.clone()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut bob = Session::new(SessionConfig {
+            encryption: EncryptionMode::Required,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_stream_audio_callback(move |peer_id, stream_id, samples, _| {
+            let _ = tx.send((peer_id, stream_id, samples.to_vec()));
+        });
+        alice.start();
+        bob.start();
+
+        let peer_info = |id: Uuid, streams: Vec<StreamInfo>| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams,
+            public_key: None,
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        alice
+            .add_peer(peer_info(bob_id, vec![]), loopback(bob.local_addr().port()))
+            .await
+            .unwrap();
+        bob.add_peer(
+            peer_info(alice_id, alice.local_streams().to_vec()),
+            loopback(alice.local_addr().port()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(bob.peer_streams(alice_id).await.unwrap(), vec![guitar]);
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !alice.is_peer_encrypted(bob_id).await || !bob.is_peer_encrypted(alice_id).await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Key exchange did not complete");
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while alice
+                .peer_stats(bob_id)
+                .await
+                .unwrap()
+                .capabilities
+                .is_none()
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Capabilities were not negotiated");
+
+        // Same sequence number on both streams
+        alice.broadcast_audio(&[0.25; 8], 0).await.unwrap();
+        alice
+            .broadcast_stream_audio(1, &[0.5, -0.5, 0.5, -0.5], 0)
+            .await
+            .unwrap();
+        assert!(matches!(
+            alice.broadcast_stream_audio(7, &[0.0; 8], 0).await,
+            Err(NetworkError::UnknownStream(7))
+        ));
+
+        let mut received = Vec::new();
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while received.len() < 2 {
+                received.push(rx.recv().await.unwrap());
+            }
+        })
+        .await
+        .expect("Timed out waiting for stream audio");
+        received.sort_by_key(|(_, stream_id, _)| *stream_id);
+        assert_eq!(received[0], (alice_id, MAIN_STREAM_ID, vec![0.25; 8]));
+        assert_eq!(received[1], (alice_id, 1, vec![0.5, -0.5, 0.5, -0.5]));
+
+        let stats = bob.peer_stats(alice_id).await.unwrap();
+        assert_eq!(stats.packets_received, 1);
+        assert_eq!(stats.streams.len(), 1);
+        assert_eq!(stats.streams[0].stream_id, 1);
+        assert_eq!(stats.streams[0].channels, 2);
+        assert_eq!(stats.streams[0].packets_received, 1);
+
+        bob.set_stream_volume(alice_id, 1, 0.5).await.unwrap();
+        assert_eq!(bob.stream_mix(alice_id, 1).await.unwrap().volume, 0.5);
+        assert_eq!(bob.peer_mix(alice_id).await.unwrap().volume, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_session_control_messages_wait_for_keys() {
+        let config = SessionConfig {
+            encryption: EncryptionMode::Required,
+            ..Default::default()
+        };
+        let mut alice = Session::new(config.clone()).await.unwrap();
+        let mut bob = Session::new(config).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_control_callback(move |peer_id, message| {
+            let _ = tx.send((peer_id, message));
+        });
+        alice.start();
+        bob.start();
+
+        let peer_info = |id: Uuid| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        alice
+            .add_peer(peer_info(bob_id), loopback(bob.local_addr().port()))
+            .await
+            .unwrap();
+        bob.add_peer(peer_info(alice_id), loopback(alice.local_addr().port()))
+            .await
+            .unwrap();
+
+        // Sent before the keys are exchanged: queued, then delivered encrypted
+        let messages = [
+            ControlMessage::MuteState { muted: true },
+            ControlMessage::MuteState { muted: false },
+        ];
+        for message in &messages {
+            alice.broadcast_control(message.clone()).await;
+        }
+        for message in messages {
+            let received = tokio::time::timeout(Duration::from_secs(3), rx.recv())
+                .await
+                .expect("Timed out waiting for control message")
+                .unwrap();
+            assert_eq!(received, (alice_id, message));
+        }
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while alice.peer_stats(bob_id).await.unwrap().control.in_flight > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Control messages were not acknowledged");
+        assert_eq!(
+            bob.peer_stats(alice_id)
+                .await
+                .unwrap()
+                .control
+                .messages_delivered,
+            2
+        );
+        assert!(alice
+            .send_control(Uuid::new_v4(), ControlMessage::MuteState { muted: true })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_metronome_follows_owner() {
+        use crate::audio::{MetronomeConfig, Tempo};
+
+        let mut alice = Session::new(SessionConfig::default()).await.unwrap();
+        let mut bob = Session::new(SessionConfig::default()).await.unwrap();
+        let sample_rate = SessionConfig::default().codec.sample_rate;
+        let alice_metronome = Arc::new(SyncedMetronome::new(
+            MetronomeConfig::default(),
+            sample_rate,
+        ));
+        let bob_metronome = Arc::new(SyncedMetronome::new(
+            MetronomeConfig::default(),
+            sample_rate,
+        ));
+        alice.set_metronome(alice_metronome.clone());
+        bob.set_metronome(bob_metronome.clone());
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_mixed_audio_callback(move |samples, _| {
+            let _ = tx.send(samples.iter().any(|s| s.abs() > 0.01));
+        });
+        alice.set_mixed_audio_callback(|_, _| {});
+        alice.start();
+        bob.start();
+
+        let peer_info = |id: Uuid| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        alice
+            .add_peer(peer_info(Uuid::new_v4()), loopback(bob.local_addr().port()))
+            .await
+            .unwrap();
+        bob.add_peer(
+            peer_info(Uuid::new_v4()),
+            loopback(alice.local_addr().port()),
+        )
+        .await
+        .unwrap();
+
+        let tempo = Tempo {
+            bpm: 90,
+            beats_per_measure: 3,
+            beat_value: 4,
+        };
+        alice_metronome.start(tempo);
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !bob_metronome.is_running() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Follower never started");
+        assert_eq!(bob_metronome.tempo(), tempo);
+        assert!(alice_metronome.is_owner() && !bob_metronome.is_owner());
+
+        // The click plays through the mix without any peer audio
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while !rx.recv().await.unwrap() {}
+        })
+        .await
+        .expect("No click in the mixed audio");
+    }
+
+    #[tokio::test]
+    async fn test_session_mixes_on_frame_clock() {
+        let mut alice = Session::new(SessionConfig::default()).await.unwrap();
+        let mut bob = Session::new(SessionConfig {
+            codec: CodecConfig {
+                frame_size: 240,
+                ..Default::default()
+            },
+            jitter_buffer: JitterBufferMode::Passthrough,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_mixed_audio_callback(move |samples, timestamp| {
+            let _ = tx.send((samples.to_vec(), timestamp));
+        });
+        alice.start();
+        bob.start();
+
+        let peer_info = |id: Uuid| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        alice
+            .add_peer(peer_info(bob_id), loopback(bob.local_addr().port()))
+            .await
+            .unwrap();
+        bob.add_peer(peer_info(alice_id), loopback(alice.local_addr().port()))
+            .await
+            .unwrap();
+
+        // Alice sends one 10ms frame; Bob mixes 5ms frames
+        alice.broadcast_audio(&[0.25; 480], 0).await.unwrap();
+
+        let mut frames = Vec::new();
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while frames.len() < 2 {
+                frames.push(rx.recv().await.unwrap());
+            }
+        })
+        .await
+        .expect("Timed out waiting for mixed audio");
+
+        let (first, first_ts) = &frames[0];
+        let (second, second_ts) = &frames[1];
+        assert_eq!(first.len(), 240);
+        assert_eq!(second.len(), 240);
+        assert_eq!(second_ts.wrapping_sub(*first_ts), 240);
+        assert!(first.iter().all(|&s| (s - 0.25).abs() < 1e-6));
+        assert!(second.iter().all(|&s| (s - 0.25).abs() < 1e-6));
+        assert_eq!(
+            bob.peer_receive_stats(alice_id)
+                .await
+                .unwrap()
+                .frames_decoded,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_follows_room_membership() {
+        let session = Session::new(SessionConfig::default()).await.unwrap();
+        let peer = |port: u16| PeerInfo {
+            id: Uuid::from_u128(1),
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: Some(SocketAddr::from(([127, 0, 0, 1], port))),
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        let peer_id = Uuid::from_u128(1);
+
+        // Joined without an address yet: not added
+        let mut pending = peer(0);
+        pending.public_addr = None;
+        session
+            .handle_signaling_message(&SignalingMessage::PeerJoined { peer: pending })
+            .await
+            .unwrap();
+        assert!(session.peers().await.is_empty());
+
+        // The address arrives with an update
+        session
+            .handle_signaling_message(&SignalingMessage::PeerUpdated { peer: peer(4000) })
+            .await
+            .unwrap();
+        assert_eq!(session.peers().await.len(), 1);
+
+        // A later update re-targets the peer
+        session
+            .handle_signaling_message(&SignalingMessage::PeerUpdated { peer: peer(4001) })
+            .await
+            .unwrap();
+        assert_eq!(session.peers.read().await[&peer_id].addr.port(), 4001);
+
+        session.set_peer_volume(peer_id, 0.5).await.unwrap();
+        session.set_peer_muted(peer_id, true).await.unwrap();
+        let mix = session.peer_mix(peer_id).await.unwrap();
+        assert_eq!(mix.volume, 0.5);
+        assert!(mix.muted);
+
+        session
+            .handle_signaling_message(&SignalingMessage::PeerLeft { peer_id })
+            .await
+            .unwrap();
+        assert!(session.peers().await.is_empty());
+        assert!(session.set_peer_volume(peer_id, 1.0).await.is_err());
+    }
+
+    async fn next_state(
+        rx: &mut tokio::sync::mpsc::UnboundedReceiver<(Uuid, ConnectionState)>,
+        peer_id: Uuid,
+        wanted: ConnectionState,
+    ) {
+        let next = tokio::time::timeout(Duration::from_secs(3), rx.recv())
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for {:?}", wanted))
+            .unwrap();
+        assert_eq!(next, (peer_id, wanted));
+    }
+
+    #[tokio::test]
+    async fn test_session_incompatible_peer_fails() {
+        let mut alice = Session::new(SessionConfig {
+            encryption: EncryptionMode::Required,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut bob = Session::new(SessionConfig {
+            encryption: EncryptionMode::Disabled,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        alice.set_peer_state_callback(move |peer_id, state| {
+            let _ = tx.send((peer_id, state));
+        });
+        alice.start();
+        bob.start();
+
+        let peer_info = |id: Uuid| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        alice
+            .add_peer(peer_info(bob_id), loopback(bob.local_addr().port()))
+            .await
+            .unwrap();
+        bob.add_peer(peer_info(alice_id), loopback(alice.local_addr().port()))
+            .await
+            .unwrap();
+
+        // The hellos may mark the peer connected before they are compared
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while rx.recv().await.unwrap() != (bob_id, ConnectionState::Failed) {}
+        })
+        .await
+        .expect("Incompatible peer was not reported");
+
+        let stats = alice.peer_stats(bob_id).await.unwrap();
+        assert!(stats.capabilities.is_none());
+        assert!(stats
+            .incompatibility
+            .unwrap()
+            .contains("encryption is required"));
+        assert!(matches!(
+            alice.send_audio_to(bob_id, &[0.0; 8], 0).await,
+            Err(NetworkError::IncompatiblePeer(_))
+        ));
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while bob
+                .peer_stats(alice_id)
+                .await
+                .unwrap()
+                .incompatibility
+                .is_none()
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Peer did not learn why it was rejected");
+    }
+
+    #[tokio::test]
+    async fn test_session_peer_key_checked_against_signaling() {
+        let mut alice = Session::new(SessionConfig::default()).await.unwrap();
+        let mut bob = Session::new(SessionConfig::default()).await.unwrap();
+        alice.start();
+        bob.start();
+
+        // Signaling gives bob a key other than the one offered in-band, as
+        // when someone on the path swaps in its own
+        let bob_id = Uuid::new_v4();
+        let bob_info = PeerInfo {
+            id: bob_id,
+            name: "bob".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: Some(KeyPair::generate().public_key_bytes()),
+        };
+        let alice_info = PeerInfo {
+            id: Uuid::new_v4(),
+            name: "alice".to_string(),
+            public_key: Some(alice.public_key()),
+            ..bob_info.clone()
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_addr = loopback(bob.local_addr().port());
+        alice.add_peer(bob_info.clone(), bob_addr).await.unwrap();
+        bob.add_peer(alice_info, loopback(alice.local_addr().port()))
+            .await
+            .unwrap();
+
+        // The offered key is dropped, but does not fail the peer
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while alice
+                .peer_stats(bob_id)
+                .await
+                .unwrap()
+                .key_exchanges_rejected
+                == 0
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Unexpected key was not rejected");
+        let stats = alice.peer_stats(bob_id).await.unwrap();
+        assert!(!stats.encrypted);
+        assert_ne!(stats.state, ConnectionState::Failed);
+        assert!(stats.incompatibility.is_none());
+
+        // With bob's real key the resent exchange goes through
+        let real_info = PeerInfo {
+            public_key: Some(bob.public_key()),
+            ..bob_info.clone()
+        };
+        alice.update_peer(real_info, bob_addr).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while !alice.is_peer_encrypted(bob_id).await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Key exchange did not complete");
+
+        // A key published later does not undo the exchange
+        alice.update_peer(bob_info, bob_addr).await.unwrap();
+        let stats = alice.peer_stats(bob_id).await.unwrap();
+        assert!(stats.encrypted);
+        assert_ne!(stats.state, ConnectionState::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_session_peer_reconnects() {
+        let config = SessionConfig {
+            reconnect: ReconnectConfig {
+                liveness_timeout: Duration::from_millis(300),
+                reconnect_timeout: Duration::from_millis(600),
+            },
+            ..Default::default()
+        };
+        let mut alice = Session::new(config.clone()).await.unwrap();
+        let mut bob = Session::new(config).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        alice.set_peer_state_callback(move |peer_id, state| {
+            let _ = tx.send((peer_id, state));
+        });
+        alice.start();
+        bob.start();
+
+        let peer_info = |id: Uuid| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_id = Uuid::new_v4();
+        alice
+            .add_peer(peer_info(bob_id), loopback(bob.local_addr().port()))
+            .await
+            .unwrap();
+        bob.add_peer(
+            peer_info(Uuid::new_v4()),
+            loopback(alice.local_addr().port()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            alice.peer_state(bob_id).await,
+            Some(ConnectionState::Connecting)
+        );
+
+        next_state(&mut rx, bob_id, ConnectionState::Connected).await;
+
+        // Bob drops off the network for a moment
+        bob.stop();
+        next_state(&mut rx, bob_id, ConnectionState::Reconnecting).await;
+        bob.start();
+        next_state(&mut rx, bob_id, ConnectionState::Connected).await;
+
+        // ...and then for good
+        bob.stop();
+        next_state(&mut rx, bob_id, ConnectionState::Reconnecting).await;
+        next_state(&mut rx, bob_id, ConnectionState::Failed).await;
+        assert_eq!(
+            alice.peer_stats(bob_id).await.unwrap().state,
+            ConnectionState::Failed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_ice_retargets_peer() {
+        let mut alice = Session::new(SessionConfig::default()).await.unwrap();
+        let mut bob = Session::new(SessionConfig::default()).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_peer_audio_callback(move |peer_id, _, _| {
+            let _ = tx.send(peer_id);
+        });
+        alice.start();
+        bob.start();
+
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let alice_id = Uuid::new_v4();
+        let bob_id = Uuid::new_v4();
+        let alice_info = PeerInfo {
+            id: alice_id,
+            name: "alice".to_string(),
+            candidates: vec![AddressCandidate::host(loopback(alice.local_addr().port()))],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        // Bob's best-ranked candidate is unreachable
+        let dead = AddressCandidate {
+            priority: u32::MAX,
+            ..AddressCandidate::host(loopback(9))
+        };
+        let bob_info = PeerInfo {
+            id: bob_id,
+            name: "bob".to_string(),
+            candidates: vec![
+                dead,
+                AddressCandidate::host(loopback(bob.local_addr().port())),
+            ],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+
+        alice
+            .handle_signaling_message(&SignalingMessage::RoomCreated {
+                room_id: "room".to_string(),
+                peer_id: alice_id,
+                invite_code: "ABC123".to_string(),
+            })
+            .await
+            .unwrap();
+        bob.handle_signaling_message(&SignalingMessage::RoomJoined {
+            room_id: "room".to_string(),
+            peer_id: bob_id,
+            peers: vec![alice_info],
+            require_encryption: false,
+        })
+        .await
+        .unwrap();
+        alice
+            .handle_signaling_message(&SignalingMessage::PeerJoined { peer: bob_info })
+            .await
+            .unwrap();
+        assert_eq!(alice.peers.read().await[&bob_id].addr.port(), 9);
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while alice.peers.read().await[&bob_id].addr.port() != bob.local_addr().port() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("ICE did not re-target Bob");
+
+        alice.broadcast_audio(&[0.25; 8], 0).await.unwrap();
+        let peer_id = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("Timed out waiting for audio")
+            .unwrap();
+        assert_eq!(peer_id, alice_id);
+    }
+
+    #[tokio::test]
+    async fn test_session_fec_recovers_lost_audio() {
+        let mut bob = Session::new(SessionConfig::default()).await.unwrap();
+        let alice = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let alice_id = Uuid::new_v4();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_peer_audio_callback(move |_, samples, timestamp| {
+            let _ = tx.send((timestamp, samples.to_vec()));
+        });
+        bob.start();
+        bob.add_peer(
+            PeerInfo {
+                id: alice_id,
+                name: "alice".to_string(),
+                candidates: vec![],
+                public_addr: None,
+                local_addr: None,
+                streams: vec![],
+                public_key: None,
+            },
+            alice.local_addr(),
+        )
+        .await
+        .unwrap();
+        let bob_addr = SocketAddr::from(([127, 0, 0, 1], bob.local_addr().port()));
+
+        let mut encoder = FecStreamEncoder::new(3);
+        let payloads: Vec<Vec<u8>> = (1..=3u8).map(|v| vec![v; 8]).collect();
+        let mut fec = None;
+        for (i, payload) in payloads.iter().enumerate() {
+            let timestamp = i as u32 * 2;
+            fec = encoder.add_packet(i as u32, timestamp, CodecType::Pcm, payload);
+            // Packet 1 is lost on the wire
+            if i != 1 {
+                let packet = Packet::audio(i as u32, timestamp, payload.clone());
+                alice.send_to(&packet, bob_addr).await.unwrap();
+            }
+        }
+        let fec = fec.unwrap();
+        alice
+            .send_to(
+                &Packet::fec(encoder.next_sequence(), fec.to_bytes()),
+                bob_addr,
+            )
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let audio = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for audio")
+                .unwrap();
+            received.push(audio);
+        }
+        received.sort_by_key(|(timestamp, _)| *timestamp);
+        assert_eq!(received[1].0, 2);
+
+        let stats = bob.peer_stats(alice_id).await.unwrap();
+        assert_eq!(stats.packets_recovered, 1);
+        assert_eq!(stats.loss.packets_lost, 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_quality_reacts_to_loss() {
+        let mut bob = Session::new(SessionConfig {
+            fec: FecConfig::disabled(),
+            quality: Some(QualityConfig::default()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let alice = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let alice_id = Uuid::new_v4();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_quality_callback(move |peer, decision| {
+            let _ = tx.send((peer, decision));
+        });
+        bob.start();
+        bob.add_peer(
+            PeerInfo {
+                id: alice_id,
+                name: "alice".to_string(),
+                candidates: vec![],
+                public_addr: None,
+                local_addr: None,
+                streams: vec![],
+                public_key: None,
+            },
+            alice.local_addr(),
+        )
+        .await
+        .unwrap();
+        let bob_addr = SocketAddr::from(([127, 0, 0, 1], bob.local_addr().port()));
+
+        // Every tenth packet from alice is lost
+        for sequence in (0..100u32).filter(|s| s % 10 != 5) {
+            let packet = Packet::audio(sequence, sequence * 120, pcm_payload(0.0, 120));
+            alice.send_to(&packet, bob_addr).await.unwrap();
+        }
+
+        let (peer, decision) = tokio::time::timeout(Duration::from_secs(3), rx.recv())
+            .await
+            .expect("No quality decision")
+            .unwrap();
+        assert_eq!(peer, alice_id);
+        assert_eq!(decision.reason, QualityReason::PacketLoss);
+        assert_eq!(decision.settings.fec_group_size, Some(FEC_GROUP_SIZE));
+        let stats = bob.peer_stats(alice_id).await.unwrap();
+        assert_eq!(stats.quality, Some(decision.settings));
+
+        // Audio to alice is now protected
+        for i in 0..FEC_GROUP_SIZE as u32 {
+            bob.send_audio_to(alice_id, &[0.1; 120], i * 120)
+                .await
+                .unwrap();
+        }
+        let fec = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let (bytes, _) = alice.recv_raw().await.unwrap();
+                let Some(packet) = Packet::from_bytes(&bytes) else {
+                    continue;
+                };
+                if packet.packet_type == PacketType::Audio {
+                    assert!(packet.flags.has_fec);
+                }
+                if packet.packet_type == PacketType::Fec {
+                    return packet;
+                }
+            }
+        })
+        .await
+        .expect("No FEC packet");
+        assert_eq!(FecPacket::from_bytes(&fec.payload).unwrap().packet_count, 4);
+    }
+
+    #[tokio::test]
+    async fn test_session_receiver_reports() {
+        let mut bob = Session::new(SessionConfig::default()).await.unwrap();
+        let alice = UdpTransport::bind("127.0.0.1:0").await.unwrap();
+        let alice_id = Uuid::new_v4();
+        bob.start();
+        bob.add_peer(
+            PeerInfo {
+                id: alice_id,
+                name: "alice".to_string(),
+                candidates: vec![],
+                public_addr: None,
+                local_addr: None,
+                streams: vec![],
+                public_key: None,
+            },
+            alice.local_addr(),
+        )
+        .await
+        .unwrap();
+        let bob_addr = SocketAddr::from(([127, 0, 0, 1], bob.local_addr().port()));
+
+        // Every tenth packet from alice is lost
+        for sequence in (0..100u32).filter(|s| s % 10 != 5) {
+            let packet = Packet::audio(sequence, sequence * 120, pcm_payload(0.0, 120));
+            alice.send_to(&packet, bob_addr).await.unwrap();
+        }
+
+        let report = tokio::time::timeout(Duration::from_secs(3), async {
+            loop {
+                let (bytes, _) = alice.recv_raw().await.unwrap();
+                let Some(packet) = Packet::from_bytes(&bytes) else {
+                    continue;
+                };
+                if packet.packet_type == PacketType::ReceiverReport {
+                    return ReceiverReport::from_bytes(&packet.payload).unwrap();
+                }
+            }
+        })
+        .await
+        .expect("No receiver report");
+        assert_eq!(report.highest_sequence, 99);
+        assert_eq!(report.cumulative_lost, 10);
+        assert_eq!(report.fraction_lost, (10 * 256 / 100) as u8);
+        assert!(report.jitter_buffer_delay_us.is_some());
+        assert!(bob
+            .peer_stats(alice_id)
+            .await
+            .unwrap()
+            .remote_reception
+            .is_none());
+
+        // Alice reports back on bob's audio
+        let back = ReceiverReport {
+            highest_sequence: 7,
+            cumulative_lost: 1,
+            fraction_lost: 32,
+            jitter_us: 1_500,
+            jitter_buffer_delay_us: None,
+        };
+        alice
+            .send_to(&Packet::receiver_report(0, &back), bob_addr)
+            .await
+            .unwrap();
+        let reception = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let stats = bob.peer_stats(alice_id).await.unwrap();
+                if let Some(reception) = stats.remote_reception {
+                    return reception;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Report not stored");
+        assert_eq!(reception.highest_sequence, 7);
+        assert_eq!(reception.packets_lost, 1);
+        assert_eq!(reception.fraction_lost, 0.125);
+        assert_eq!(reception.jitter_ms, 1.5);
+        assert_eq!(reception.jitter_buffer_delay_ms, None);
+    }
+
     #[tokio::test]
     async fn test_session_creation() {
         let config = SessionConfig::default();
         let session = Session::new(config).await.unwrap();
         assert!(session.local_addr().port() > 0);
     }
+
+    #[tokio::test]
+    async fn test_session_codec_negotiation() {
+        let mut alice = Session::new(SessionConfig {
+            codec: CodecConfig {
+                codec_type: CodecType::Opus,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut bob = Session::new(SessionConfig::default()).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        bob.set_peer_audio_callback(move |_, samples, _| {
+            let _ = tx.send(samples.to_vec());
+        });
+        alice.start();
+        bob.start();
+
+        let peer_info = |id: Uuid| PeerInfo {
+            id,
+            name: "peer".to_string(),
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            streams: vec![],
+            public_key: None,
+        };
+        let loopback = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let bob_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        alice
+            .add_peer(peer_info(bob_id), loopback(bob.local_addr().port()))
+            .await
+            .unwrap();
+        bob.add_peer(peer_info(alice_id), loopback(alice.local_addr().port()))
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            loop {
+                let negotiated = {
+                    let peers = bob.peers.read().await;
+                    let needs_offer = peers[&alice_id].codec.lock().needs_offer();
+                    !needs_offer
+                };
+                if negotiated {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Codec offers were not exchanged");
+
+        let expected = if CodecType::Opus.is_available() {
+            CodecType::Opus
+        } else {
+            CodecType::Pcm
+        };
+        assert_eq!(bob.peer_send_codec(alice_id).await, Some(expected));
+
+        if expected == CodecType::Pcm {
+            alice.broadcast_audio(&[0.25; 8], 0).await.unwrap();
+            let samples = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("Timed out waiting for audio")
+                .unwrap();
+            assert_eq!(samples, vec![0.25; 8]);
+        }
+    }
 }
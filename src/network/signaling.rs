@@ -19,6 +19,31 @@ use super::error::NetworkError;
 /// Maximum peers per room
 pub const MAX_PEERS_PER_ROOM: usize = 10;
 
+/// Version of the signaling messages sent by this build
+pub const SIGNALING_VERSION: u8 = 1;
+
+/// Oldest signaling version the server accepts
+pub const MIN_SIGNALING_VERSION: u8 = 1;
+
+/// Signaling version of clients that predate versioning
+fn legacy_signaling_version() -> u8 {
+    1
+}
+
+/// Error to send a client whose signaling version the server does not
+/// accept (None if it is accepted)
+pub fn signaling_version_error(version: u8) -> Option<SignalingMessage> {
+    if (MIN_SIGNALING_VERSION..=SIGNALING_VERSION).contains(&version) {
+        return None;
+    }
+    Some(SignalingMessage::Error {
+        message: format!(
+            "Unsupported signaling version {} (server supports {}-{}), please update",
+            version, MIN_SIGNALING_VERSION, SIGNALING_VERSION
+        ),
+    })
+}
+
 /// Address candidate type for ICE-like connection establishment
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CandidateType {
@@ -26,6 +51,10 @@ pub enum CandidateType {
     Host,
     /// Server reflexive address (public IP via STUN)
     ServerReflexive,
+    /// Relayed address on a relay server (last resort when hole punching fails)
+    Relay,
+    /// Address learned from a connectivity check (not signaled)
+    PeerReflexive,
 }
 
 /// A single address candidate for connection
@@ -68,6 +97,45 @@ impl AddressCandidate {
             priority,
         }
     }
+
+    /// Create a new peer reflexive candidate (from an ICE connectivity check)
+    ///
+    /// The priority is the one the peer advertised in its check.
+    pub fn peer_reflexive(address: SocketAddr, priority: u32) -> Self {
+        Self {
+            address,
+            candidate_type: CandidateType::PeerReflexive,
+            priority,
+        }
+    }
+
+    /// Create a new relay candidate (from a relay allocation)
+    pub fn relay(address: SocketAddr) -> Self {
+        // Relay has the lowest priority: only used when direct paths fail
+        let type_pref: u32 = 0;
+        let local_pref: u32 = if address.is_ipv6() { 65535 } else { 65534 };
+        let priority = (type_pref << 24) | (local_pref << 8) | 255;
+
+        Self {
+            address,
+            candidate_type: CandidateType::Relay,
+            priority,
+        }
+    }
+}
+
+/// An audio stream a peer sends, announced through signaling
+///
+/// Every peer sends its main stream (`MAIN_STREAM_ID`) in the session's audio
+/// format; only additional streams are announced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamInfo {
+    /// Stream ID carried in the packets' stream extension (1-255)
+    pub id: u8,
+    /// Display name (e.g. "Vocal", "Guitar DI")
+    pub name: String,
+    /// Interleaved channels of the stream's audio
+    pub channels: u16,
 }
 
 /// Peer information with multiple address candidates
@@ -84,6 +152,13 @@ pub struct PeerInfo {
     /// Legacy: single local address (for backward compatibility)
     #[serde(default)]
     pub local_addr: Option<SocketAddr>,
+    /// Additional audio streams the peer sends
+    #[serde(default)]
+    pub streams: Vec<StreamInfo>,
+    /// X25519 public key the peer uses for end-to-end encryption; its
+    /// in-band key exchange must offer the same key
+    #[serde(default)]
+    pub public_key: Option<[u8; 32]>,
 }
 
 impl PeerInfo {
@@ -120,6 +195,9 @@ pub struct RoomInfo {
     pub has_password: bool,
     /// 6-character invite code for easy room sharing
     pub invite_code: String,
+    /// Peers must encrypt all audio in this room
+    #[serde(default)]
+    pub requires_encryption: bool,
 }
 
 /// Signaling message types
@@ -131,11 +209,20 @@ pub enum SignalingMessage {
         room_name: String,
         password: Option<String>,
         peer_name: String,
+        /// Peers must encrypt all audio in this room
+        #[serde(default)]
+        require_encryption: bool,
+        /// Signaling version of the client (`SIGNALING_VERSION`)
+        #[serde(default = "legacy_signaling_version")]
+        signaling_version: u8,
     },
     JoinRoom {
         room_id: String,
         password: Option<String>,
         peer_name: String,
+        /// Signaling version of the client (`SIGNALING_VERSION`)
+        #[serde(default = "legacy_signaling_version")]
+        signaling_version: u8,
     },
     LeaveRoom,
     /// Update peer connection information with multiple candidates
@@ -149,6 +236,13 @@ pub enum SignalingMessage {
         /// Legacy: single local address
         #[serde(default)]
         local_addr: Option<SocketAddr>,
+        /// X25519 public key for end-to-end encryption (kept if None)
+        #[serde(default)]
+        public_key: Option<[u8; 32]>,
+    },
+    /// Announce the additional audio streams we send (replaces earlier ones)
+    UpdateStreams {
+        streams: Vec<StreamInfo>,
     },
     ListRooms,
 
@@ -163,6 +257,9 @@ pub enum SignalingMessage {
         room_id: String,
         peer_id: Uuid,
         peers: Vec<PeerInfo>,
+        /// Peers must encrypt all audio in this room
+        #[serde(default)]
+        require_encryption: bool,
     },
     PeerJoined {
         peer: PeerInfo,
@@ -199,6 +296,8 @@ struct Room {
     broadcast_tx: broadcast::Sender<SignalingMessage>,
     /// 6-character invite code for easy room sharing
     invite_code: String,
+    /// Peers must encrypt all audio in this room
+    require_encryption: bool,
 }
 
 /// Signaling server state
@@ -252,6 +351,7 @@ impl SignalingServer {
                 max_peers: MAX_PEERS_PER_ROOM,
                 has_password: room.password.is_some(),
                 invite_code: room.invite_code.clone(),
+                requires_encryption: room.require_encryption,
             })
             .collect()
     }
@@ -376,7 +476,12 @@ async fn process_message(
             room_name,
             password,
             peer_name,
+            require_encryption,
+            signaling_version,
         } => {
+            if let Some(error) = signaling_version_error(signaling_version) {
+                return Some(error);
+            }
             let room_id = generate_room_id();
             let peer_id = Uuid::new_v4();
             let (tx, rx) = broadcast::channel(100);
@@ -401,6 +506,8 @@ async fn process_message(
                 candidates: vec![],
                 public_addr: None,
                 local_addr: None,
+                streams: vec![],
+                public_key: None,
             };
 
             let mut peers = HashMap::new();
@@ -413,6 +520,7 @@ async fn process_message(
                 peers,
                 broadcast_tx: tx,
                 invite_code: invite_code.clone(),
+                require_encryption,
             };
 
             rooms.write().await.insert(room_id.clone(), room);
@@ -436,7 +544,11 @@ async fn process_message(
             room_id,
             password,
             peer_name,
+            signaling_version,
         } => {
+            if let Some(error) = signaling_version_error(signaling_version) {
+                return Some(error);
+            }
             let mut rooms_guard = rooms.write().await;
 
             // Look up room by ID or invite code
@@ -478,6 +590,8 @@ async fn process_message(
                         candidates: vec![],
                         public_addr: None,
                         local_addr: None,
+                        streams: vec![],
+                        public_key: None,
                     };
 
                     let peers: Vec<PeerInfo> = room.peers.values().cloned().collect();
@@ -499,6 +613,7 @@ async fn process_message(
                         room_id: actual_room_id,
                         peer_id,
                         peers,
+                        require_encryption: room.require_encryption,
                     })
                 }
                 None => Some(SignalingMessage::Error {
@@ -530,6 +645,7 @@ async fn process_message(
             candidates,
             public_addr,
             local_addr,
+            public_key,
         } => {
             if let (Some(room_id), Some(peer_id)) =
                 (current_room.as_ref(), current_peer_id.as_ref())
@@ -544,6 +660,27 @@ async fn process_message(
                         // Also update legacy fields for backward compatibility
                         peer.public_addr = public_addr;
                         peer.local_addr = local_addr;
+                        if public_key.is_some() {
+                            peer.public_key = public_key;
+                        }
+
+                        let _ = room
+                            .broadcast_tx
+                            .send(SignalingMessage::PeerUpdated { peer: peer.clone() });
+                    }
+                }
+            }
+            None
+        }
+
+        SignalingMessage::UpdateStreams { streams } => {
+            if let (Some(room_id), Some(peer_id)) =
+                (current_room.as_ref(), current_peer_id.as_ref())
+            {
+                let mut rooms_guard = rooms.write().await;
+                if let Some(room) = rooms_guard.get_mut(room_id) {
+                    if let Some(peer) = room.peers.get_mut(peer_id) {
+                        peer.streams = streams;
 
                         let _ = room
                             .broadcast_tx
@@ -565,6 +702,7 @@ async fn process_message(
                     max_peers: MAX_PEERS_PER_ROOM,
                     has_password: room.password.is_some(),
                     invite_code: room.invite_code.clone(),
+                    requires_encryption: room.require_encryption,
                 })
                 .collect();
 
@@ -790,6 +928,8 @@ mod tests {
             room_name: "Test Room".to_string(),
             password: None,
             peer_name: "Alice".to_string(),
+            require_encryption: false,
+            signaling_version: SIGNALING_VERSION,
         };
 
         let json = serde_json::to_string(&msg).unwrap();
@@ -800,15 +940,72 @@ mod tests {
                 room_name,
                 password,
                 peer_name,
+                require_encryption,
+                signaling_version,
             } => {
                 assert_eq!(room_name, "Test Room");
                 assert!(password.is_none());
                 assert_eq!(peer_name, "Alice");
+                assert!(!require_encryption);
+                assert_eq!(signaling_version, SIGNALING_VERSION);
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_require_encryption_defaults_to_false() {
+        // Messages from older clients omit the field
+        let json =
+            r#"{"type":"CreateRoom","data":{"room_name":"Old","password":null,"peer_name":"Bob"}}"#;
+        match serde_json::from_str::<SignalingMessage>(json).unwrap() {
+            SignalingMessage::CreateRoom {
+                require_encryption,
+                signaling_version,
+                ..
+            } => {
+                assert!(!require_encryption);
+                assert_eq!(signaling_version, 1);
             }
             _ => panic!("Wrong message type"),
         }
     }
 
+    #[test]
+    fn test_update_peer_info_public_key() {
+        // Older clients publish no key
+        let json = r#"{"type":"UpdatePeerInfo","data":{"public_addr":"203.0.113.1:5000"}}"#;
+        match serde_json::from_str::<SignalingMessage>(json).unwrap() {
+            SignalingMessage::UpdatePeerInfo { public_key, .. } => assert!(public_key.is_none()),
+            _ => panic!("Wrong message type"),
+        }
+
+        let message = SignalingMessage::UpdatePeerInfo {
+            candidates: vec![],
+            public_addr: None,
+            local_addr: None,
+            public_key: Some([9; 32]),
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        match serde_json::from_str::<SignalingMessage>(&json).unwrap() {
+            SignalingMessage::UpdatePeerInfo { public_key, .. } => {
+                assert_eq!(public_key, Some([9; 32]))
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_signaling_version_check() {
+        assert!(signaling_version_error(SIGNALING_VERSION).is_none());
+        match signaling_version_error(SIGNALING_VERSION + 1) {
+            Some(SignalingMessage::Error { message }) => {
+                assert!(message.contains("Unsupported signaling version"))
+            }
+            other => panic!("Expected an error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_generate_invite_code_length() {
         let code = generate_invite_code();
@@ -938,6 +1135,11 @@ mod tests {
         // IPv6 slightly higher than IPv4 within same type
         assert!(host_v6.priority > host_v4.priority);
         assert!(srflx_v6.priority > srflx_v4.priority);
+
+        // ServerReflexive > Relay
+        let relay_v6 = AddressCandidate::relay("[2001:db8::3]:40000".parse().unwrap());
+        assert_eq!(relay_v6.candidate_type, CandidateType::Relay);
+        assert!(srflx_v4.priority > relay_v6.priority);
     }
 
     #[test]
@@ -971,6 +1173,8 @@ mod tests {
             )],
             public_addr: None,
             local_addr: None,
+            streams: vec![],
+            public_key: None,
         };
 
         assert_eq!(peer.candidates.len(), 1);
@@ -986,5 +1190,27 @@ mod tests {
         assert_eq!(peer.name, "OldPeer");
         assert!(peer.candidates.is_empty()); // Default empty
         assert!(peer.public_addr.is_some());
+        assert!(peer.streams.is_empty());
+    }
+
+    #[test]
+    fn test_update_streams_serialize() {
+        let msg = SignalingMessage::UpdateStreams {
+            streams: vec![StreamInfo {
+                id: 1,
+                name: "Guitar DI".to_string(),
+                channels: 1,
+            }],
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("UpdateStreams"));
+
+        match serde_json::from_str::<SignalingMessage>(&json).unwrap() {
+            SignalingMessage::UpdateStreams { streams } => {
+                assert_eq!(streams.len(), 1);
+                assert_eq!(streams[0].name, "Guitar DI");
+            }
+            _ => panic!("Wrong message type"),
+        }
     }
 }
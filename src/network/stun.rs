@@ -1,7 +1,8 @@
 //! STUN client for NAT traversal
 //!
 //! Implements RFC 5389 STUN (Session Traversal Utilities for NAT)
-//! to discover public IP address and port mapping.
+//! to discover public IP address and port mapping, and the binding
+//! messages used for ICE connectivity checks (see `ice`).
 
 use std::net::SocketAddr;
 use tokio::net::UdpSocket;
@@ -13,10 +14,20 @@ use super::error::NetworkError;
 /// STUN message types
 const BINDING_REQUEST: u16 = 0x0001;
 const BINDING_RESPONSE: u16 = 0x0101;
+const BINDING_ERROR_RESPONSE: u16 = 0x0111;
 
 /// STUN attribute types
 const MAPPED_ADDRESS: u16 = 0x0001;
+const USERNAME: u16 = 0x0006;
+const ERROR_CODE: u16 = 0x0009;
 const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const PRIORITY: u16 = 0x0024;
+const USE_CANDIDATE: u16 = 0x0025;
+const ICE_CONTROLLED: u16 = 0x8029;
+const ICE_CONTROLLING: u16 = 0x802A;
+
+/// ICE role conflict error code (RFC 8445)
+pub(crate) const ROLE_CONFLICT: u16 = 487;
 
 /// STUN magic cookie (RFC 5389)
 const MAGIC_COOKIE: u32 = 0x2112A442;
@@ -264,6 +275,207 @@ fn parse_xor_mapped_address(
     }
 }
 
+/// Check if a datagram is a STUN message (RFC 7983 demultiplexing)
+///
+/// jamjam packets also start with a byte below 4, so the magic cookie and a
+/// message length matching the datagram are required as well.
+pub(crate) fn is_stun_message(data: &[u8]) -> bool {
+    if data.len() < 20 || data[0] > 3 {
+        return false;
+    }
+    let msg_len = u16::from_be_bytes([data[2], data[3]]) as usize;
+    let cookie = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    cookie == MAGIC_COOKIE && msg_len.is_multiple_of(4) && msg_len == data.len() - 20
+}
+
+/// Class of a binding message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StunClass {
+    Request,
+    Success,
+    /// Error response with its error code
+    Error(u16),
+}
+
+/// Binding message used for ICE connectivity checks
+///
+/// Messages are not authenticated (no MESSAGE-INTEGRITY); the media itself is
+/// protected by the end-to-end encryption.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BindingMessage {
+    pub class: StunClass,
+    pub transaction_id: [u8; 12],
+    /// USERNAME ("receiver:sender")
+    pub username: Option<String>,
+    /// PRIORITY of the sender's candidate (requests)
+    pub priority: Option<u32>,
+    /// USE-CANDIDATE (nomination by the controlling agent)
+    pub use_candidate: bool,
+    /// ICE-CONTROLLING tie-breaker
+    pub controlling: Option<u64>,
+    /// ICE-CONTROLLED tie-breaker
+    pub controlled: Option<u64>,
+    /// XOR-MAPPED-ADDRESS (success responses)
+    pub mapped_address: Option<SocketAddr>,
+}
+
+impl BindingMessage {
+    /// Create a binding request with a random transaction ID
+    pub fn request() -> Self {
+        Self {
+            class: StunClass::Request,
+            transaction_id: rand::random(),
+            username: None,
+            priority: None,
+            use_candidate: false,
+            controlling: None,
+            controlled: None,
+            mapped_address: None,
+        }
+    }
+
+    /// Create a response to a request
+    pub fn response(request: &BindingMessage, class: StunClass) -> Self {
+        Self {
+            class,
+            transaction_id: request.transaction_id,
+            ..Self::request()
+        }
+    }
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut attrs = Vec::new();
+        if let Some(username) = &self.username {
+            write_attribute(&mut attrs, USERNAME, username.as_bytes());
+        }
+        if let StunClass::Error(code) = self.class {
+            let reason: &[u8] = if code == ROLE_CONFLICT {
+                b"Role Conflict"
+            } else {
+                b""
+            };
+            let mut value = vec![0, 0, (code / 100) as u8, (code % 100) as u8];
+            value.extend_from_slice(reason);
+            write_attribute(&mut attrs, ERROR_CODE, &value);
+        }
+        if let Some(addr) = self.mapped_address {
+            write_attribute(
+                &mut attrs,
+                XOR_MAPPED_ADDRESS,
+                &encode_xor_mapped_address(addr, &self.transaction_id),
+            );
+        }
+        if let Some(priority) = self.priority {
+            write_attribute(&mut attrs, PRIORITY, &priority.to_be_bytes());
+        }
+        if self.use_candidate {
+            write_attribute(&mut attrs, USE_CANDIDATE, &[]);
+        }
+        if let Some(tiebreaker) = self.controlled {
+            write_attribute(&mut attrs, ICE_CONTROLLED, &tiebreaker.to_be_bytes());
+        }
+        if let Some(tiebreaker) = self.controlling {
+            write_attribute(&mut attrs, ICE_CONTROLLING, &tiebreaker.to_be_bytes());
+        }
+
+        let msg_type = match self.class {
+            StunClass::Request => BINDING_REQUEST,
+            StunClass::Success => BINDING_RESPONSE,
+            StunClass::Error(_) => BINDING_ERROR_RESPONSE,
+        };
+        let mut msg = Vec::with_capacity(20 + attrs.len());
+        msg.extend_from_slice(&msg_type.to_be_bytes());
+        msg.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        msg.extend_from_slice(&self.transaction_id);
+        msg.extend_from_slice(&attrs);
+        msg
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if !is_stun_message(data) {
+            return None;
+        }
+        let mut transaction_id = [0u8; 12];
+        transaction_id.copy_from_slice(&data[8..20]);
+        let mut message = Self {
+            class: match u16::from_be_bytes([data[0], data[1]]) {
+                BINDING_REQUEST => StunClass::Request,
+                BINDING_RESPONSE => StunClass::Success,
+                BINDING_ERROR_RESPONSE => StunClass::Error(0),
+                _ => return None,
+            },
+            transaction_id,
+            ..Self::request()
+        };
+
+        let mut offset = 20;
+        while offset + 4 <= data.len() {
+            let attr_type = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            let attr_len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+            let value = data.get(offset + 4..offset + 4 + attr_len)?;
+
+            match attr_type {
+                USERNAME => message.username = String::from_utf8(value.to_vec()).ok(),
+                ERROR_CODE if value.len() >= 4 => {
+                    message.class = StunClass::Error(value[2] as u16 * 100 + value[3] as u16);
+                }
+                XOR_MAPPED_ADDRESS => {
+                    message.mapped_address = parse_xor_mapped_address(value, &transaction_id).ok();
+                }
+                PRIORITY if value.len() == 4 => {
+                    message.priority = Some(u32::from_be_bytes(value.try_into().ok()?));
+                }
+                USE_CANDIDATE => message.use_candidate = true,
+                ICE_CONTROLLED if value.len() == 8 => {
+                    message.controlled = Some(u64::from_be_bytes(value.try_into().ok()?));
+                }
+                ICE_CONTROLLING if value.len() == 8 => {
+                    message.controlling = Some(u64::from_be_bytes(value.try_into().ok()?));
+                }
+                _ => {}
+            }
+
+            // Align to 4-byte boundary
+            offset += 4 + ((attr_len + 3) & !3);
+        }
+
+        Some(message)
+    }
+}
+
+/// Append an attribute, padded to a 4-byte boundary
+fn write_attribute(buf: &mut Vec<u8>, attr_type: u16, value: &[u8]) {
+    buf.extend_from_slice(&attr_type.to_be_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len() + (4 - value.len() % 4) % 4, 0);
+}
+
+/// Encode an XOR-MAPPED-ADDRESS attribute value (RFC 5389)
+fn encode_xor_mapped_address(addr: SocketAddr, transaction_id: &[u8; 12]) -> Vec<u8> {
+    let xor_port = addr.port() ^ ((MAGIC_COOKIE >> 16) as u16);
+    let mut value = Vec::with_capacity(20);
+    match addr.ip() {
+        std::net::IpAddr::V4(ip) => {
+            value.extend_from_slice(&[0x00, 0x01]);
+            value.extend_from_slice(&xor_port.to_be_bytes());
+            value.extend_from_slice(&(u32::from(ip) ^ MAGIC_COOKIE).to_be_bytes());
+        }
+        std::net::IpAddr::V6(ip) => {
+            let mut xor_mask = [0u8; 16];
+            xor_mask[0..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+            xor_mask[4..16].copy_from_slice(transaction_id);
+            value.extend_from_slice(&[0x00, 0x02]);
+            value.extend_from_slice(&xor_port.to_be_bytes());
+            value.extend(ip.octets().iter().zip(xor_mask).map(|(b, m)| b ^ m));
+        }
+    }
+    value
+}
+
 /// Parse MAPPED-ADDRESS attribute (legacy, non-XOR)
 fn parse_mapped_address(data: &[u8]) -> Result<SocketAddr, NetworkError> {
     if data.len() < 8 {
@@ -380,6 +592,48 @@ mod tests {
         assert_eq!(result.ip().to_string(), "2001:db8::1");
     }
 
+    #[test]
+    fn test_binding_message_roundtrip() {
+        let mut request = BindingMessage::request();
+        request.username = Some("receiver:sender".to_string());
+        request.priority = Some(0x6E00_FFFF);
+        request.use_candidate = true;
+        request.controlling = Some(0x0123_4567_89AB_CDEF);
+
+        let bytes = request.to_bytes();
+        assert!(is_stun_message(&bytes));
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(BindingMessage::from_bytes(&bytes), Some(request.clone()));
+
+        for addr in ["192.168.1.100:5000", "[2001:db8::1]:5000"] {
+            let mut response = BindingMessage::response(&request, StunClass::Success);
+            response.mapped_address = Some(addr.parse().unwrap());
+            let parsed = BindingMessage::from_bytes(&response.to_bytes()).unwrap();
+            assert_eq!(parsed, response);
+            // Public STUN parsing understands our responses too
+            assert_eq!(
+                parse_binding_response(&response.to_bytes(), &request.transaction_id).unwrap(),
+                addr.parse().unwrap()
+            );
+        }
+
+        let conflict = BindingMessage::response(&request, StunClass::Error(ROLE_CONFLICT));
+        let parsed = BindingMessage::from_bytes(&conflict.to_bytes()).unwrap();
+        assert_eq!(parsed.class, StunClass::Error(ROLE_CONFLICT));
+    }
+
+    #[test]
+    fn test_stun_demux_rejects_jamjam_packets() {
+        use crate::protocol::Packet;
+
+        assert!(is_stun_message(&build_binding_request(&[7; 12])));
+        // A jamjam packet whose header happens to contain the magic cookie;
+        // its payload does not match the STUN length field
+        let packet = Packet::audio(0x0000_2112, 0xA442_0000, vec![0; 10]);
+        assert!(!is_stun_message(&packet.to_bytes()));
+        assert!(!is_stun_message(&Packet::keep_alive(1).to_bytes()));
+    }
+
     #[test]
     fn test_parse_mapped_address_ipv6() {
         // MAPPED-ADDRESS for 2001:db8::1:8080
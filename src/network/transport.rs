@@ -1,21 +1,61 @@
 //! UDP transport layer
-
+//!
+//! A transport can hold a relay allocation (see `relay`). Packets to peers
+//! marked as relayed are wrapped in `RelayMessage::Send` and sent to the relay
+//! server; `RelayMessage::Data` from the server is unwrapped on receive and
+//! reported as coming from the original peer, which marks that peer as relayed
+//! so replies take the same path.
+//!
+//! STUN messages (ICE connectivity checks) share the socket with jamjam
+//! packets. They are handed to the channel from `stun_messages` instead of
+//! being returned by `recv_from`.
+
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use parking_lot::Mutex;
 use socket2::{Domain, Protocol, Socket, Type};
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, trace};
 
-use crate::protocol::Packet;
+use crate::protocol::{Packet, PacketType, RelayMessage};
 
 use super::error::NetworkError;
+use super::stun::is_stun_message;
+
+/// Time to wait for a relay allocation reply before retrying
+const RELAY_ALLOCATE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Number of allocation requests sent before giving up
+const RELAY_ALLOCATE_ATTEMPTS: usize = 3;
+
+/// Relay allocation held by this transport
+struct RelayRoute {
+    /// Relay server address
+    server: SocketAddr,
+    /// Address the relay server opened for us
+    relayed_addr: SocketAddr,
+    /// Peers reached through the relay
+    peers: HashSet<SocketAddr>,
+}
+
+/// STUN message received on the transport
+pub(crate) struct StunDatagram {
+    pub data: Vec<u8>,
+    pub from: SocketAddr,
+    /// Arrived through our relay allocation
+    pub relayed: bool,
+}
 
 /// UDP transport for sending and receiving packets
 pub struct UdpTransport {
     socket: Arc<UdpSocket>,
     local_addr: SocketAddr,
+    relay: Mutex<Option<RelayRoute>>,
+    stun_tx: Mutex<Option<mpsc::UnboundedSender<StunDatagram>>>,
 }
 
 impl UdpTransport {
@@ -53,6 +93,8 @@ impl UdpTransport {
         Ok(Self {
             socket: Arc::new(socket),
             local_addr,
+            relay: Mutex::new(None),
+            stun_tx: Mutex::new(None),
         })
     }
 
@@ -61,35 +103,215 @@ impl UdpTransport {
         self.local_addr
     }
 
+    /// Allocate a relayed address on a relay server
+    ///
+    /// Must be called before a receive loop is started on this transport,
+    /// since the server's reply is read directly from the socket. Datagrams
+    /// from other senders that arrive meanwhile are dropped.
+    pub async fn allocate_relay(&self, server: SocketAddr) -> Result<SocketAddr, NetworkError> {
+        let request = Packet::relay(0, &RelayMessage::Allocate).to_bytes();
+
+        for attempt in 1..=RELAY_ALLOCATE_ATTEMPTS {
+            self.socket.send_to(&request, server).await?;
+
+            let reply = tokio::time::timeout(RELAY_ALLOCATE_TIMEOUT, async {
+                let mut buf = vec![0u8; 2048];
+                loop {
+                    let (len, addr) = self.socket.recv_from(&mut buf).await?;
+                    if addr != server {
+                        continue;
+                    }
+                    if let Some(RelayMessage::AllocateOk { relayed_addr }) =
+                        parse_relay(&buf[..len])
+                    {
+                        return Ok::<_, NetworkError>(relayed_addr);
+                    }
+                }
+            })
+            .await;
+
+            match reply {
+                Ok(Ok(mut relayed_addr)) => {
+                    // A relay bound to a wildcard address reports it as such
+                    if relayed_addr.ip().is_unspecified() {
+                        relayed_addr.set_ip(server.ip());
+                    }
+                    info!("Relay {} allocated {}", server, relayed_addr);
+                    *self.relay.lock() = Some(RelayRoute {
+                        server,
+                        relayed_addr,
+                        peers: HashSet::new(),
+                    });
+                    return Ok(relayed_addr);
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => debug!("Relay allocation attempt {} timed out", attempt),
+            }
+        }
+
+        Err(NetworkError::RelayFailed(format!(
+            "No allocation from relay server {}",
+            server
+        )))
+    }
+
+    /// Get the relayed address, if a relay allocation is held
+    pub fn relayed_addr(&self) -> Option<SocketAddr> {
+        self.relay.lock().as_ref().map(|relay| relay.relayed_addr)
+    }
+
+    /// Keep the relay allocation alive (no-op without an allocation)
+    pub async fn refresh_relay(&self) -> Result<(), NetworkError> {
+        let server = self.relay.lock().as_ref().map(|relay| relay.server);
+        if let Some(server) = server {
+            let packet = Packet::relay(0, &RelayMessage::Refresh);
+            self.socket.send_to(&packet.to_bytes(), server).await?;
+        }
+        Ok(())
+    }
+
+    /// Route packets to a peer through the relay (or directly again)
+    ///
+    /// Has no effect without a relay allocation.
+    pub fn set_relayed(&self, peer: SocketAddr, relayed: bool) {
+        if let Some(relay) = self.relay.lock().as_mut() {
+            if relayed {
+                relay.peers.insert(peer);
+            } else {
+                relay.peers.remove(&peer);
+            }
+        }
+    }
+
+    /// Check if packets to a peer go through the relay
+    pub fn is_relayed(&self, peer: SocketAddr) -> bool {
+        self.relay
+            .lock()
+            .as_ref()
+            .is_some_and(|relay| relay.peers.contains(&peer))
+    }
+
+    /// Receive STUN messages from now on (replaces any previous receiver)
+    ///
+    /// Without a receiver, STUN messages are dropped.
+    pub(crate) fn stun_messages(&self) -> mpsc::UnboundedReceiver<StunDatagram> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.stun_tx.lock() = Some(tx);
+        rx
+    }
+
     /// Send a packet to a remote address
     pub async fn send_to(&self, packet: &Packet, addr: SocketAddr) -> Result<(), NetworkError> {
-        let data = packet.to_bytes();
-        self.socket.send_to(&data, addr).await?;
-        trace!("Sent {} bytes to {}", data.len(), addr);
+        let relayed = self.is_relayed(addr);
+        self.send_raw(&packet.to_bytes(), addr, relayed).await
+    }
+
+    /// Send raw bytes, either directly or through the relay
+    ///
+    /// `relayed` is ignored without a relay allocation.
+    pub(crate) async fn send_raw(
+        &self,
+        data: &[u8],
+        addr: SocketAddr,
+        relayed: bool,
+    ) -> Result<(), NetworkError> {
+        let server = self
+            .relay
+            .lock()
+            .as_ref()
+            .filter(|_| relayed)
+            .map(|relay| relay.server);
+
+        match server {
+            Some(server) => {
+                let wrapped = Packet::relay(
+                    0,
+                    &RelayMessage::Send {
+                        peer: addr,
+                        data: data.to_vec(),
+                    },
+                );
+                self.socket.send_to(&wrapped.to_bytes(), server).await?;
+                trace!("Sent {} bytes to {} via relay {}", data.len(), addr, server);
+            }
+            None => {
+                self.socket.send_to(data, addr).await?;
+                trace!("Sent {} bytes to {}", data.len(), addr);
+            }
+        }
         Ok(())
     }
 
     /// Receive a packet (returns packet and sender address)
     pub async fn recv_from(&self) -> Result<(Packet, SocketAddr), NetworkError> {
-        let mut buf = vec![0u8; 2048];
-        let (len, addr) = self.socket.recv_from(&mut buf).await?;
-        buf.truncate(len);
+        let (buf, addr) = self.recv_datagram().await?;
 
         let packet = Packet::from_bytes(&buf).ok_or(NetworkError::InvalidPacket)?;
-        trace!("Received {} bytes from {}", len, addr);
+        trace!("Received {} bytes from {}", buf.len(), addr);
 
         Ok((packet, addr))
     }
 
     /// Receive raw bytes (for connectivity probing without packet parsing)
     pub async fn recv_raw(&self) -> Result<(Vec<u8>, SocketAddr), NetworkError> {
-        let mut buf = vec![0u8; 2048];
-        let (len, addr) = self.socket.recv_from(&mut buf).await?;
-        buf.truncate(len);
-        trace!("Received {} raw bytes from {}", len, addr);
+        let (buf, addr) = self.recv_datagram().await?;
+        trace!("Received {} raw bytes from {}", buf.len(), addr);
         Ok((buf, addr))
     }
 
+    /// Receive the next jamjam datagram
+    ///
+    /// Unwraps data delivered by the relay and diverts STUN messages.
+    async fn recv_datagram(&self) -> Result<(Vec<u8>, SocketAddr), NetworkError> {
+        loop {
+            let mut buf = vec![0u8; 2048];
+            let (len, addr) = self.socket.recv_from(&mut buf).await?;
+            buf.truncate(len);
+
+            let Some((data, from, relayed)) = self.unwrap_relayed(buf, addr) else {
+                continue;
+            };
+            if !is_stun_message(&data) {
+                return Ok((data, from));
+            }
+            if let Some(tx) = self.stun_tx.lock().as_ref() {
+                let _ = tx.send(StunDatagram {
+                    data,
+                    from,
+                    relayed,
+                });
+            }
+        }
+    }
+
+    /// Unwrap relay server traffic (returns data, sender and whether relayed)
+    ///
+    /// Returns `None` for relay control messages.
+    fn unwrap_relayed(
+        &self,
+        buf: Vec<u8>,
+        addr: SocketAddr,
+    ) -> Option<(Vec<u8>, SocketAddr, bool)> {
+        let mut relay = self.relay.lock();
+        let Some(route) = relay.as_mut().filter(|route| route.server == addr) else {
+            return Some((buf, addr, false));
+        };
+        match parse_relay(&buf) {
+            Some(RelayMessage::Data { peer, data }) => {
+                // STUN checks choose their path themselves
+                if !is_stun_message(&data) {
+                    route.peers.insert(peer);
+                }
+                Some((data, peer, true))
+            }
+            Some(message) => {
+                debug!("Ignoring relay message {:?}", message);
+                None
+            }
+            None => Some((buf, addr, false)),
+        }
+    }
+
     /// Start a receive loop that sends packets to a channel
     pub fn start_receive_loop(
         self: Arc<Self>,
@@ -120,6 +342,15 @@ impl UdpTransport {
     }
 }
 
+/// Parse a datagram as a relay message
+fn parse_relay(data: &[u8]) -> Option<RelayMessage> {
+    let packet = Packet::from_bytes(data)?;
+    if packet.packet_type != PacketType::Relay {
+        return None;
+    }
+    RelayMessage::from_bytes(&packet.payload)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,5 +5,9 @@
 mod packet;
 
 pub use packet::{
-    LatencyInfoMessage, LatencyPing, LatencyPong, Packet, PacketType, HEADER_SIZE, PROTOCOL_VERSION,
+    Capabilities, CodecOfferPayload, ControlMessage, ControlPayload, HelloPayload,
+    KeyExchangePayload, LatencyInfoMessage, LatencyPing, LatencyPong, Packet, PacketType,
+    PathValidationPayload, ReceiverReport, RedundancyPayload, RedundantEncoding, RedundantFrame,
+    RelayMessage, StreamHeader, HEADER_SIZE, MAIN_STREAM_ID, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION, STREAM_EXTENSION_SIZE,
 };
@@ -5,16 +5,45 @@
 //! - type: 1 byte
 //! - sequence: 4 bytes (big-endian)
 //! - timestamp: 4 bytes (big-endian, in samples)
-//! - flags: 2 bytes
+//! - flags: 2 bytes (bit 0: encrypted, bit 1: has FEC, bits 2-3: audio codec,
+//!   bit 4: stream extension, bit 5: redundant audio)
+//!
+//! Audio and FEC packets of a peer's additional streams carry a 2-byte stream
+//! extension after the header:
+//! - stream_id: 1 byte
+//! - channels: 1 byte
+//!
+//! Packets of the main stream have no extension, so they are unchanged from
+//! peers that send a single stream.
+//!
+//! The version and type bytes and the `Hello` packet are kept the same in
+//! every protocol version, so peers speaking different versions can still
+//! tell each other which versions they speak.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 use serde::{Deserialize, Serialize};
 
-/// Protocol version
+use crate::audio::{CodecType, MetronomeSync};
+
+/// Protocol version (the highest version we speak)
 pub const PROTOCOL_VERSION: u8 = 1;
 
+/// Oldest protocol version we still speak
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
 /// Header size in bytes
 pub const HEADER_SIZE: usize = 12;
 
+/// Stream extension size in bytes
+pub const STREAM_EXTENSION_SIZE: usize = 2;
+
+/// Stream ID of a peer's main audio stream (sent without a stream extension)
+pub const MAIN_STREAM_ID: u8 = 0;
+
+/// Flag bit set when the stream extension follows the header
+const STREAM_EXTENSION_FLAG: u16 = 0x0010;
+
 /// Maximum payload size (MTU - IP header - UDP header - our header)
 /// 1500 - 20 - 8 - 12 = 1460 bytes
 #[allow(dead_code)]
@@ -38,6 +67,21 @@ pub enum PacketType {
     LatencyPong = 0x06,
     /// Latency configuration info exchange
     LatencyInfo = 0x07,
+    /// Public key exchange for end-to-end encryption
+    KeyExchange = 0x08,
+    /// Audio codec capabilities and preference
+    CodecOffer = 0x09,
+    /// Relay allocation and relayed data (see `RelayMessage`)
+    Relay = 0x0A,
+    /// How the sender's audio arrives here (see `ReceiverReport`)
+    ReceiverReport = 0x0B,
+    /// Protocol versions and capabilities (see `HelloPayload`)
+    Hello = 0x0C,
+    /// Cookie sent to a new peer address before accepting it (see
+    /// `PathValidationPayload`)
+    PathChallenge = 0x0D,
+    /// Cookie of a path challenge echoed from the new address
+    PathResponse = 0x0E,
 }
 
 impl TryFrom<u8> for PacketType {
@@ -52,6 +96,13 @@ impl TryFrom<u8> for PacketType {
             0x05 => Ok(PacketType::LatencyPing),
             0x06 => Ok(PacketType::LatencyPong),
             0x07 => Ok(PacketType::LatencyInfo),
+            0x08 => Ok(PacketType::KeyExchange),
+            0x09 => Ok(PacketType::CodecOffer),
+            0x0A => Ok(PacketType::Relay),
+            0x0B => Ok(PacketType::ReceiverReport),
+            0x0C => Ok(PacketType::Hello),
+            0x0D => Ok(PacketType::PathChallenge),
+            0x0E => Ok(PacketType::PathResponse),
             _ => Err(()),
         }
     }
@@ -64,6 +115,10 @@ pub struct PacketFlags {
     pub encrypted: bool,
     /// Packet contains FEC info
     pub has_fec: bool,
+    /// Codec of the audio payload
+    pub codec: CodecType,
+    /// Audio payload is a `RedundancyPayload` carrying previous frames
+    pub redundant: bool,
 }
 
 impl PacketFlags {
@@ -75,6 +130,10 @@ impl PacketFlags {
         if self.has_fec {
             flags |= 0x0002;
         }
+        flags |= (self.codec.to_flags() as u16) << 2;
+        if self.redundant {
+            flags |= 0x0020;
+        }
         flags
     }
 
@@ -82,10 +141,21 @@ impl PacketFlags {
         Self {
             encrypted: (value & 0x0001) != 0,
             has_fec: (value & 0x0002) != 0,
+            codec: CodecType::from_flags((value >> 2) as u8),
+            redundant: (value & 0x0020) != 0,
         }
     }
 }
 
+/// Stream extension of an audio or FEC packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamHeader {
+    /// Stream the packet belongs to (never `MAIN_STREAM_ID`)
+    pub stream_id: u8,
+    /// Interleaved channels of the stream's audio
+    pub channels: u8,
+}
+
 /// A network packet
 #[derive(Debug, Clone)]
 pub struct Packet {
@@ -94,6 +164,8 @@ pub struct Packet {
     pub sequence: u32,
     pub timestamp: u32,
     pub flags: PacketFlags,
+    /// Additional stream the packet belongs to (None for the main stream)
+    pub stream: Option<StreamHeader>,
     pub payload: Vec<u8>,
 }
 
@@ -106,6 +178,7 @@ impl Packet {
             sequence,
             timestamp,
             flags: PacketFlags::default(),
+            stream: None,
             payload,
         }
     }
@@ -118,6 +191,7 @@ impl Packet {
             sequence,
             timestamp: 0,
             flags: PacketFlags::default(),
+            stream: None,
             payload: Vec::new(),
         }
     }
@@ -130,6 +204,7 @@ impl Packet {
             sequence,
             timestamp: 0,
             flags: PacketFlags::default(),
+            stream: None,
             payload: ping.to_bytes(),
         }
     }
@@ -142,6 +217,7 @@ impl Packet {
             sequence,
             timestamp: 0,
             flags: PacketFlags::default(),
+            stream: None,
             payload: pong.to_bytes(),
         }
     }
@@ -154,40 +230,215 @@ impl Packet {
             sequence,
             timestamp: 0,
             flags: PacketFlags::default(),
+            stream: None,
             payload: info.to_bytes(),
         }
     }
 
-    /// Serialize the packet to bytes
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+    /// Create a new FEC packet (payload is a serialized FEC group)
+    pub fn fec(sequence: u32, payload: Vec<u8>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::Fec,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload,
+        }
+    }
+
+    /// Create a new codec offer packet
+    pub fn codec_offer(sequence: u32, offer: &CodecOfferPayload) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::CodecOffer,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: offer.to_bytes(),
+        }
+    }
+
+    /// Create a new key exchange packet
+    pub fn key_exchange(sequence: u32, key_exchange: &KeyExchangePayload) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::KeyExchange,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: key_exchange.to_bytes(),
+        }
+    }
+
+    /// Create a new control channel packet
+    ///
+    /// `sequence` numbers the transmission (see `ControlPayload`) and `floor`
+    /// is the oldest message sequence the sender still retransmits.
+    pub fn control(sequence: u32, floor: u32, payload: &ControlPayload) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::Control,
+            sequence,
+            timestamp: floor,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: payload.to_bytes(),
+        }
+    }
+
+    /// Create a new relay packet
+    pub fn relay(sequence: u32, message: &RelayMessage) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::Relay,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: message.to_bytes(),
+        }
+    }
+
+    /// Create a new receiver report packet
+    pub fn receiver_report(sequence: u32, report: &ReceiverReport) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::ReceiverReport,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: report.to_bytes(),
+        }
+    }
+
+    /// Create a new hello packet
+    pub fn hello(sequence: u32, hello: &HelloPayload) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::Hello,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: hello.to_bytes(),
+        }
+    }
+
+    /// Create a path challenge packet
+    pub fn path_challenge(sequence: u32, challenge: &PathValidationPayload) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::PathChallenge,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: challenge.to_bytes(),
+        }
+    }
+
+    /// Create a path response packet echoing a challenge
+    pub fn path_response(sequence: u32, challenge: &PathValidationPayload) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            packet_type: PacketType::PathResponse,
+            sequence,
+            timestamp: 0,
+            flags: PacketFlags::default(),
+            stream: None,
+            payload: challenge.to_bytes(),
+        }
+    }
+
+    /// Put the packet on an additional stream
+    pub fn with_stream(mut self, stream_id: u8, channels: u8) -> Self {
+        self.stream = (stream_id != MAIN_STREAM_ID).then_some(StreamHeader {
+            stream_id,
+            channels,
+        });
+        self
+    }
+
+    /// Stream the packet belongs to
+    pub fn stream_id(&self) -> u8 {
+        self.stream.map_or(MAIN_STREAM_ID, |s| s.stream_id)
+    }
+
+    /// Size of the header including the stream extension
+    pub fn header_len(&self) -> usize {
+        match self.stream {
+            Some(_) => HEADER_SIZE + STREAM_EXTENSION_SIZE,
+            None => HEADER_SIZE,
+        }
+    }
+
+    /// Serialize the header (with the stream extension) to bytes
+    pub fn header_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.header_len());
+        let mut flags = self.flags.to_u16();
+        if self.stream.is_some() {
+            flags |= STREAM_EXTENSION_FLAG;
+        }
 
         buf.push(self.version);
         buf.push(self.packet_type as u8);
         buf.extend_from_slice(&self.sequence.to_be_bytes());
         buf.extend_from_slice(&self.timestamp.to_be_bytes());
-        buf.extend_from_slice(&self.flags.to_u16().to_be_bytes());
-        buf.extend_from_slice(&self.payload);
+        buf.extend_from_slice(&flags.to_be_bytes());
+        if let Some(stream) = self.stream {
+            buf.push(stream.stream_id);
+            buf.push(stream.channels);
+        }
 
         buf
     }
 
+    /// Serialize the packet to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.header_bytes();
+        buf.extend_from_slice(&self.payload);
+        buf
+    }
+
     /// Deserialize a packet from bytes
+    ///
+    /// Packets of versions we do not speak are rejected, except for hello
+    /// packets, which tell us the versions the peer speaks.
     pub fn from_bytes(data: &[u8]) -> Option<Self> {
         if data.len() < HEADER_SIZE {
             return None;
         }
 
         let version = data[0];
-        if version != PROTOCOL_VERSION {
+        let packet_type = PacketType::try_from(data[1]).ok()?;
+        let supported = (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version);
+        if !supported && packet_type != PacketType::Hello {
             return None;
         }
 
-        let packet_type = PacketType::try_from(data[1]).ok()?;
         let sequence = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
         let timestamp = u32::from_be_bytes([data[6], data[7], data[8], data[9]]);
-        let flags = PacketFlags::from_u16(u16::from_be_bytes([data[10], data[11]]));
-        let payload = data[HEADER_SIZE..].to_vec();
+        let raw_flags = u16::from_be_bytes([data[10], data[11]]);
+        let flags = PacketFlags::from_u16(raw_flags);
+
+        let (stream, header_len) = if raw_flags & STREAM_EXTENSION_FLAG != 0 {
+            if data.len() < HEADER_SIZE + STREAM_EXTENSION_SIZE {
+                return None;
+            }
+            let stream = StreamHeader {
+                stream_id: data[HEADER_SIZE],
+                channels: data[HEADER_SIZE + 1],
+            };
+            (Some(stream), HEADER_SIZE + STREAM_EXTENSION_SIZE)
+        } else {
+            (None, HEADER_SIZE)
+        };
+        let payload = data[header_len..].to_vec();
 
         Some(Self {
             version,
@@ -195,11 +446,126 @@ impl Packet {
             sequence,
             timestamp,
             flags,
+            stream,
             payload,
         })
     }
 }
 
+// ============================================================================
+// Redundant audio message types
+// ============================================================================
+
+/// How a redundant frame is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RedundantEncoding {
+    /// Little-endian f32 PCM, like a PCM audio payload
+    Pcm = 0,
+    /// An Opus packet
+    Opus = 1,
+    /// Little-endian 16-bit PCM (half the size of `Pcm`)
+    Pcm16 = 2,
+}
+
+impl TryFrom<u8> for RedundantEncoding {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RedundantEncoding::Pcm),
+            1 => Ok(RedundantEncoding::Opus),
+            2 => Ok(RedundantEncoding::Pcm16),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A previous frame carried along with an audio packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedundantFrame {
+    /// Sequence numbers before the packet's own (1 = the previous packet)
+    pub distance: u8,
+    /// Samples per channel before the packet's timestamp
+    pub timestamp_offset: u16,
+    /// How `data` is encoded
+    pub encoding: RedundantEncoding,
+    /// Encoded frame
+    pub data: Vec<u8>,
+}
+
+/// Audio payload with redundant copies of previous frames (after RFC 2198)
+///
+/// Sent when the redundant flag is set. Binary format:
+/// - count: 1 byte (number of redundant frames)
+/// - per redundant frame: distance (1 byte), encoding (1 byte),
+///   timestamp_offset (2 bytes, big-endian), length (2 bytes, big-endian)
+/// - the redundant frames' data, in the same order
+/// - the packet's own frame (the rest of the payload)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedundancyPayload {
+    /// Previous frames, oldest first
+    pub frames: Vec<RedundantFrame>,
+    /// The packet's own frame, encoded with the codec in the header flags
+    pub primary: Vec<u8>,
+}
+
+impl RedundancyPayload {
+    /// Size of each redundant frame's header in bytes
+    pub const FRAME_HEADER_SIZE: usize = 6;
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data_len: usize = self.frames.iter().map(|f| f.data.len()).sum();
+        let mut buf = Vec::with_capacity(
+            1 + self.frames.len() * Self::FRAME_HEADER_SIZE + data_len + self.primary.len(),
+        );
+        buf.push(self.frames.len() as u8);
+        for frame in &self.frames {
+            buf.push(frame.distance);
+            buf.push(frame.encoding as u8);
+            buf.extend_from_slice(&frame.timestamp_offset.to_be_bytes());
+            buf.extend_from_slice(&(frame.data.len() as u16).to_be_bytes());
+        }
+        for frame in &self.frames {
+            buf.extend_from_slice(&frame.data);
+        }
+        buf.extend_from_slice(&self.primary);
+        buf
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let (&count, rest) = data.split_first()?;
+        let headers_len = count as usize * Self::FRAME_HEADER_SIZE;
+        if rest.len() < headers_len {
+            return None;
+        }
+        let (headers, mut body) = rest.split_at(headers_len);
+
+        let mut frames = Vec::with_capacity(count as usize);
+        for header in headers.chunks_exact(Self::FRAME_HEADER_SIZE) {
+            let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+            if body.len() < len {
+                return None;
+            }
+            let (frame, remaining) = body.split_at(len);
+            body = remaining;
+            frames.push(RedundantFrame {
+                distance: header[0],
+                encoding: RedundantEncoding::try_from(header[1]).ok()?,
+                timestamp_offset: u16::from_be_bytes([header[2], header[3]]),
+                data: frame.to_vec(),
+            });
+        }
+
+        Some(Self {
+            frames,
+            primary: body.to_vec(),
+        })
+    }
+}
+
 // ============================================================================
 // Latency measurement message types
 // ============================================================================
@@ -243,28 +609,46 @@ impl LatencyPing {
     }
 }
 
-/// Latency pong payload for RTT measurement
+/// Latency pong payload for RTT measurement and clock sync
 ///
-/// Binary format (12 bytes):
+/// Binary format (28 bytes):
 /// - original_sent_time_us: 8 bytes (big-endian)
 /// - ping_sequence: 4 bytes (big-endian)
+/// - receive_time_us: 8 bytes (big-endian, responder's clock)
+/// - send_time_us: 8 bytes (big-endian, responder's clock)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LatencyPong {
     /// Original sent timestamp from ping
     pub original_sent_time_us: u64,
     /// Ping sequence number (echoed back)
     pub ping_sequence: u32,
+    /// When the ping arrived, on the responder's clock (microseconds)
+    pub receive_time_us: u64,
+    /// When this pong was sent, on the responder's clock (microseconds)
+    pub send_time_us: u64,
 }
 
 impl LatencyPong {
     /// Size of serialized LatencyPong in bytes
-    pub const SIZE: usize = 12;
+    pub const SIZE: usize = 28;
+
+    /// Answer a ping received at `receive_time_us`, sending at `send_time_us`
+    pub fn reply(ping: &LatencyPing, receive_time_us: u64, send_time_us: u64) -> Self {
+        Self {
+            original_sent_time_us: ping.sent_time_us,
+            ping_sequence: ping.ping_sequence,
+            receive_time_us,
+            send_time_us,
+        }
+    }
 
     /// Serialize to bytes
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(Self::SIZE);
         buf.extend_from_slice(&self.original_sent_time_us.to_be_bytes());
         buf.extend_from_slice(&self.ping_sequence.to_be_bytes());
+        buf.extend_from_slice(&self.receive_time_us.to_be_bytes());
+        buf.extend_from_slice(&self.send_time_us.to_be_bytes());
         buf
     }
 
@@ -278,6 +662,12 @@ impl LatencyPong {
                 data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
             ]),
             ping_sequence: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            receive_time_us: u64::from_be_bytes([
+                data[12], data[13], data[14], data[15], data[16], data[17], data[18], data[19],
+            ]),
+            send_time_us: u64::from_be_bytes([
+                data[20], data[21], data[22], data[23], data[24], data[25], data[26], data[27],
+            ]),
         })
     }
 }
@@ -371,6 +761,575 @@ impl LatencyInfoMessage {
     }
 }
 
+// ============================================================================
+// Key exchange message types
+// ============================================================================
+
+/// Key exchange payload for end-to-end encryption
+///
+/// Binary format (33 or 49 bytes):
+/// - public_key: 32 bytes (X25519 public key)
+/// - ack: 1 byte (1 if the sender already has the receiver's public key)
+/// - nonce: 16 bytes, optional (absent from older peers)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyExchangePayload {
+    /// Sender's X25519 public key
+    pub public_key: [u8; 32],
+    /// Sender has already received the receiver's public key
+    pub ack: bool,
+    /// Random value of this exchange, mixed into the session keys so a key
+    /// pair used more than once still yields fresh session keys
+    pub nonce: Option<[u8; 16]>,
+}
+
+impl KeyExchangePayload {
+    /// Size of serialized KeyExchangePayload in bytes (without nonce)
+    pub const SIZE: usize = 33;
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE + 16);
+        buf.extend_from_slice(&self.public_key);
+        buf.push(self.ack as u8);
+        if let Some(nonce) = &self.nonce {
+            buf.extend_from_slice(nonce);
+        }
+        buf
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::SIZE {
+            return None;
+        }
+        let mut public_key = [0u8; 32];
+        public_key.copy_from_slice(&data[..32]);
+        let nonce = data
+            .get(Self::SIZE..Self::SIZE + 16)
+            .map(|nonce| nonce.try_into().expect("slice of 16 bytes"));
+        Some(Self {
+            public_key,
+            ack: data[32] != 0,
+            nonce,
+        })
+    }
+}
+
+// ============================================================================
+// Codec negotiation message types
+// ============================================================================
+
+/// Codec offer payload for per-peer codec negotiation
+///
+/// Binary format (3 bytes):
+/// - supported: 1 byte (bit N set if the codec with flags value N can be decoded)
+/// - preferred: 1 byte (codec flags value)
+/// - ack: 1 byte (1 if the sender already has the receiver's offer)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecOfferPayload {
+    /// Codecs the sender can decode
+    pub supported: Vec<CodecType>,
+    /// Codec the sender would like to use
+    pub preferred: CodecType,
+    /// Sender has already received the receiver's offer
+    pub ack: bool,
+}
+
+impl CodecOfferPayload {
+    /// Size of serialized CodecOfferPayload in bytes
+    pub const SIZE: usize = 3;
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let supported = self
+            .supported
+            .iter()
+            .fold(0u8, |mask, codec| mask | (1 << codec.to_flags()));
+        vec![supported, self.preferred.to_flags(), self.ack as u8]
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::SIZE {
+            return None;
+        }
+        let supported = [CodecType::Pcm, CodecType::Opus]
+            .into_iter()
+            .filter(|codec| data[0] & (1 << codec.to_flags()) != 0)
+            .collect();
+        Some(Self {
+            supported,
+            preferred: CodecType::from_flags(data[1]),
+            ack: data[2] != 0,
+        })
+    }
+}
+
+// ============================================================================
+// Version and capability handshake message types
+// ============================================================================
+
+/// What a peer supports, as announced in its hello
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Codecs the peer can decode
+    pub codecs: Vec<CodecType>,
+    /// The peer sends and recovers from FEC packets
+    pub fec: bool,
+    /// The peer recovers from Reed-Solomon FEC packets (not just XOR)
+    pub reed_solomon: bool,
+    /// The peer plays previous frames carried in audio packets (see
+    /// `RedundancyPayload`)
+    pub redundancy: bool,
+    /// The peer answers path challenges, so it can move to a new address
+    pub path_validation: bool,
+    /// The peer can encrypt its audio
+    pub encryption: bool,
+    /// The peer only accepts encrypted audio
+    pub requires_encryption: bool,
+    /// Additional streams the peer can receive (0 if it only plays the main
+    /// stream)
+    pub max_streams: u8,
+    /// Largest audio frame the peer accepts, in samples per channel
+    pub max_frame_size: u32,
+}
+
+/// Hello payload for protocol version and capability negotiation
+///
+/// Binary format (10 bytes, later versions may append fields):
+/// - min_version: 1 byte (oldest protocol version the sender speaks)
+/// - max_version: 1 byte (newest protocol version the sender speaks)
+/// - codecs: 1 byte (bit N set if the codec with flags value N can be decoded)
+/// - features: 1 byte (bit 0: FEC, bit 1: encryption, bit 2: encryption
+///   required, bit 3: Reed-Solomon FEC, bit 4: redundant audio, bit 5: path
+///   validation)
+/// - max_streams: 1 byte
+/// - max_frame_size: 4 bytes (big-endian)
+/// - ack: 1 byte (1 if the sender already has the receiver's hello)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelloPayload {
+    /// Oldest protocol version the sender speaks
+    pub min_version: u8,
+    /// Newest protocol version the sender speaks
+    pub max_version: u8,
+    /// What the sender supports
+    pub capabilities: Capabilities,
+    /// Sender has already received the receiver's hello
+    pub ack: bool,
+}
+
+impl HelloPayload {
+    /// Size of serialized HelloPayload in bytes
+    pub const SIZE: usize = 10;
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let caps = &self.capabilities;
+        let codecs = caps
+            .codecs
+            .iter()
+            .fold(0u8, |mask, codec| mask | (1 << codec.to_flags()));
+        let features = caps.fec as u8
+            | (caps.encryption as u8) << 1
+            | (caps.requires_encryption as u8) << 2
+            | (caps.reed_solomon as u8) << 3
+            | (caps.redundancy as u8) << 4
+            | (caps.path_validation as u8) << 5;
+
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        buf.push(self.min_version);
+        buf.push(self.max_version);
+        buf.push(codecs);
+        buf.push(features);
+        buf.push(caps.max_streams);
+        buf.extend_from_slice(&caps.max_frame_size.to_be_bytes());
+        buf.push(self.ack as u8);
+        buf
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::SIZE {
+            return None;
+        }
+        let codecs = [CodecType::Pcm, CodecType::Opus]
+            .into_iter()
+            .filter(|codec| data[2] & (1 << codec.to_flags()) != 0)
+            .collect();
+        let features = data[3];
+        Some(Self {
+            min_version: data[0],
+            max_version: data[1],
+            capabilities: Capabilities {
+                codecs,
+                fec: features & 0x01 != 0,
+                encryption: features & 0x02 != 0,
+                requires_encryption: features & 0x04 != 0,
+                reed_solomon: features & 0x08 != 0,
+                redundancy: features & 0x10 != 0,
+                path_validation: features & 0x20 != 0,
+                max_streams: data[4],
+                max_frame_size: u32::from_be_bytes([data[5], data[6], data[7], data[8]]),
+            },
+            ack: data[9] != 0,
+        })
+    }
+}
+
+// ============================================================================
+// Path validation message types
+// ============================================================================
+
+/// Path challenge and response payload
+///
+/// Binary format (8 bytes):
+/// - cookie: 8 bytes (big-endian, random, echoed unchanged in the response)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathValidationPayload {
+    /// Random value only a receiver at the challenged address learns
+    pub cookie: u64,
+}
+
+impl PathValidationPayload {
+    /// Size of serialized PathValidationPayload in bytes
+    pub const SIZE: usize = 8;
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.cookie.to_be_bytes().to_vec()
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let cookie = data.get(..Self::SIZE)?.try_into().ok()?;
+        Some(Self {
+            cookie: u64::from_be_bytes(cookie),
+        })
+    }
+}
+
+// ============================================================================
+// Receiver report message types
+// ============================================================================
+
+/// Receiver report on one incoming audio stream, modelled on RTCP report
+/// blocks (RFC 3550 §6.4.1)
+///
+/// Binary format (17 bytes):
+/// - highest_sequence: 4 bytes (big-endian)
+/// - cumulative_lost: 4 bytes (big-endian)
+/// - fraction_lost: 1 byte (lost / expected since the previous report, × 256)
+/// - jitter_us: 4 bytes (big-endian, interarrival jitter in microseconds)
+/// - jitter_buffer_delay_us: 4 bytes (big-endian, 0xFFFFFFFF if none)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiverReport {
+    /// Highest audio sequence number received
+    pub highest_sequence: u32,
+    /// Audio packets lost since the stream started
+    pub cumulative_lost: u32,
+    /// Fraction of audio packets lost since the previous report (1/256 units)
+    pub fraction_lost: u8,
+    /// Interarrival jitter (microseconds)
+    pub jitter_us: u32,
+    /// Receiver's jitter buffer delay (microseconds, None without a buffer)
+    pub jitter_buffer_delay_us: Option<u32>,
+}
+
+impl ReceiverReport {
+    /// Size of serialized ReceiverReport in bytes
+    pub const SIZE: usize = 17;
+
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        buf.extend_from_slice(&self.highest_sequence.to_be_bytes());
+        buf.extend_from_slice(&self.cumulative_lost.to_be_bytes());
+        buf.push(self.fraction_lost);
+        buf.extend_from_slice(&self.jitter_us.to_be_bytes());
+        let delay = self
+            .jitter_buffer_delay_us
+            .map_or(u32::MAX, |delay| delay.min(u32::MAX - 1));
+        buf.extend_from_slice(&delay.to_be_bytes());
+        buf
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::SIZE {
+            return None;
+        }
+        let delay = u32::from_be_bytes([data[13], data[14], data[15], data[16]]);
+        Some(Self {
+            highest_sequence: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            cumulative_lost: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            fraction_lost: data[8],
+            jitter_us: u32::from_be_bytes([data[9], data[10], data[11], data[12]]),
+            jitter_buffer_delay_us: (delay != u32::MAX).then_some(delay),
+        })
+    }
+}
+
+// ============================================================================
+// Relay message types
+// ============================================================================
+
+/// Relay message exchanged between a client and a relay server
+///
+/// Binary format:
+/// - kind: 1 byte (0: Allocate, 1: AllocateOk, 2: Refresh, 3: Send, 4: Data)
+/// - address (AllocateOk, Send, Data): family 1 byte (4 or 6), IP 4/16 bytes,
+///   port 2 bytes (big-endian)
+/// - data (Send, Data): remaining bytes, a complete jamjam packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayMessage {
+    /// Client requests a relayed address
+    Allocate,
+    /// Server granted a relayed address to the client
+    AllocateOk { relayed_addr: SocketAddr },
+    /// Client keeps its allocation alive
+    Refresh,
+    /// Client asks the server to send `data` to `peer` from the relayed address
+    Send { peer: SocketAddr, data: Vec<u8> },
+    /// Server delivers `data` that `peer` sent to the relayed address
+    Data { peer: SocketAddr, data: Vec<u8> },
+}
+
+impl RelayMessage {
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RelayMessage::Allocate => vec![0],
+            RelayMessage::AllocateOk { relayed_addr } => {
+                let mut buf = vec![1];
+                write_socket_addr(&mut buf, relayed_addr);
+                buf
+            }
+            RelayMessage::Refresh => vec![2],
+            RelayMessage::Send { peer, data } | RelayMessage::Data { peer, data } => {
+                let kind = if matches!(self, RelayMessage::Send { .. }) {
+                    3
+                } else {
+                    4
+                };
+                let mut buf = Vec::with_capacity(1 + 19 + data.len());
+                buf.push(kind);
+                write_socket_addr(&mut buf, peer);
+                buf.extend_from_slice(data);
+                buf
+            }
+        }
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let (&kind, rest) = data.split_first()?;
+        match kind {
+            0 => Some(RelayMessage::Allocate),
+            1 => {
+                let (relayed_addr, _) = read_socket_addr(rest)?;
+                Some(RelayMessage::AllocateOk { relayed_addr })
+            }
+            2 => Some(RelayMessage::Refresh),
+            3 => {
+                let (peer, data) = read_socket_addr(rest)?;
+                Some(RelayMessage::Send {
+                    peer,
+                    data: data.to_vec(),
+                })
+            }
+            4 => {
+                let (peer, data) = read_socket_addr(rest)?;
+                Some(RelayMessage::Data {
+                    peer,
+                    data: data.to_vec(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn write_socket_addr(buf: &mut Vec<u8>, addr: &SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            buf.push(4);
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.push(6);
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+    buf.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+fn read_socket_addr(data: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    let (&family, rest) = data.split_first()?;
+    let (ip, rest) = match family {
+        4 if rest.len() >= 4 => {
+            let octets: [u8; 4] = rest[..4].try_into().ok()?;
+            (IpAddr::V4(Ipv4Addr::from(octets)), &rest[4..])
+        }
+        6 if rest.len() >= 16 => {
+            let octets: [u8; 16] = rest[..16].try_into().ok()?;
+            (IpAddr::V6(Ipv6Addr::from(octets)), &rest[16..])
+        }
+        _ => return None,
+    };
+    if rest.len() < 2 {
+        return None;
+    }
+    let port = u16::from_be_bytes([rest[0], rest[1]]);
+    Some((SocketAddr::new(ip, port), &rest[2..]))
+}
+
+// ============================================================================
+// Control channel message types
+// ============================================================================
+
+/// Control channel payload
+///
+/// Binary format:
+/// - kind: 1 byte (0: Message, 1: Ack)
+/// - sequence: 4 bytes (big-endian), the message's or the acknowledged one's
+/// - message (Message): remaining bytes, see `ControlMessage`
+///
+/// The header sequence numbers transmissions, not messages: every packet
+/// the channel sends (retransmissions and acks included) gets a new one, so
+/// no two share a nonce. The header timestamp is the sender's retransmit
+/// floor.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlPayload {
+    /// Message `sequence`; `message` is `None` if its type is unknown to us
+    /// (it is still acknowledged and keeps its place in the order)
+    Message {
+        sequence: u32,
+        message: Option<ControlMessage>,
+    },
+    /// Acknowledges message `sequence`
+    Ack { sequence: u32 },
+}
+
+impl ControlPayload {
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ControlPayload::Message { sequence, message } => {
+                let mut buf = vec![0];
+                buf.extend_from_slice(&sequence.to_be_bytes());
+                if let Some(message) = message {
+                    buf.extend_from_slice(&message.to_bytes());
+                }
+                buf
+            }
+            ControlPayload::Ack { sequence } => {
+                let mut buf = vec![1];
+                buf.extend_from_slice(&sequence.to_be_bytes());
+                buf
+            }
+        }
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < 5 {
+            return None;
+        }
+        let sequence = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+        match data[0] {
+            0 => Some(ControlPayload::Message {
+                sequence,
+                message: ControlMessage::from_bytes(&data[5..]),
+            }),
+            1 => Some(ControlPayload::Ack { sequence }),
+            _ => None,
+        }
+    }
+}
+
+/// Message sent reliably and in order between peers over the media path
+///
+/// Binary format:
+/// - type: 1 byte
+/// - MuteState (1): muted 1 byte
+/// - StreamConfig (2): codec flags 1 byte, sample_rate 4 bytes, frame_size
+///   4 bytes, channels 2 bytes (big-endian)
+/// - Metronome (3): see `MetronomeSync`
+/// - Custom (255): kind 2 bytes (big-endian), data remaining bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlMessage {
+    /// Sender muted or unmuted its microphone
+    MuteState { muted: bool },
+    /// Audio format the sender is sending
+    StreamConfig {
+        codec: CodecType,
+        sample_rate: u32,
+        frame_size: u32,
+        channels: u16,
+    },
+    /// Shared metronome tempo and position from its owner
+    Metronome(MetronomeSync),
+    /// Application-defined command
+    Custom { kind: u16, data: Vec<u8> },
+}
+
+impl ControlMessage {
+    /// Serialize to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ControlMessage::MuteState { muted } => vec![1, *muted as u8],
+            ControlMessage::StreamConfig {
+                codec,
+                sample_rate,
+                frame_size,
+                channels,
+            } => {
+                let mut buf = Vec::with_capacity(12);
+                buf.push(2);
+                buf.push(codec.to_flags());
+                buf.extend_from_slice(&sample_rate.to_be_bytes());
+                buf.extend_from_slice(&frame_size.to_be_bytes());
+                buf.extend_from_slice(&channels.to_be_bytes());
+                buf
+            }
+            ControlMessage::Metronome(sync) => {
+                let mut buf = vec![3];
+                buf.extend_from_slice(&sync.to_bytes());
+                buf
+            }
+            ControlMessage::Custom { kind, data } => {
+                let mut buf = Vec::with_capacity(3 + data.len());
+                buf.push(255);
+                buf.extend_from_slice(&kind.to_be_bytes());
+                buf.extend_from_slice(data);
+                buf
+            }
+        }
+    }
+
+    /// Deserialize from bytes
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let (&kind, rest) = data.split_first()?;
+        match kind {
+            1 => Some(ControlMessage::MuteState {
+                muted: *rest.first()? != 0,
+            }),
+            2 if rest.len() >= 11 => Some(ControlMessage::StreamConfig {
+                codec: CodecType::from_flags(rest[0]),
+                sample_rate: u32::from_be_bytes([rest[1], rest[2], rest[3], rest[4]]),
+                frame_size: u32::from_be_bytes([rest[5], rest[6], rest[7], rest[8]]),
+                channels: u16::from_be_bytes([rest[9], rest[10]]),
+            }),
+            3 => MetronomeSync::from_bytes(rest).map(ControlMessage::Metronome),
+            255 if rest.len() >= 2 => Some(ControlMessage::Custom {
+                kind: u16::from_be_bytes([rest[0], rest[1]]),
+                data: rest[2..].to_vec(),
+            }),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -397,6 +1356,13 @@ mod tests {
         assert_eq!(PacketType::try_from(0x05), Ok(PacketType::LatencyPing));
         assert_eq!(PacketType::try_from(0x06), Ok(PacketType::LatencyPong));
         assert_eq!(PacketType::try_from(0x07), Ok(PacketType::LatencyInfo));
+        assert_eq!(PacketType::try_from(0x08), Ok(PacketType::KeyExchange));
+        assert_eq!(PacketType::try_from(0x09), Ok(PacketType::CodecOffer));
+        assert_eq!(PacketType::try_from(0x0A), Ok(PacketType::Relay));
+        assert_eq!(PacketType::try_from(0x0B), Ok(PacketType::ReceiverReport));
+        assert_eq!(PacketType::try_from(0x0C), Ok(PacketType::Hello));
+        assert_eq!(PacketType::try_from(0x0D), Ok(PacketType::PathChallenge));
+        assert_eq!(PacketType::try_from(0x0E), Ok(PacketType::PathResponse));
         assert_eq!(PacketType::try_from(0xFF), Err(()));
     }
 
@@ -416,6 +1382,8 @@ mod tests {
         let pong = LatencyPong {
             original_sent_time_us: 1234567890123,
             ping_sequence: 42,
+            receive_time_us: 987654321,
+            send_time_us: 987654400,
         };
         let bytes = pong.to_bytes();
         let decoded = LatencyPong::from_bytes(&bytes).expect("Failed to decode");
@@ -457,17 +1425,243 @@ mod tests {
         assert_eq!(decoded_ping, ping);
     }
 
+    #[test]
+    fn test_key_exchange_roundtrip() {
+        let key_exchange = KeyExchangePayload {
+            public_key: [7u8; 32],
+            ack: true,
+            nonce: Some([3u8; 16]),
+        };
+        let packet = Packet::key_exchange(0, &key_exchange);
+        let decoded = Packet::from_bytes(&packet.to_bytes()).expect("Failed to decode packet");
+        assert_eq!(decoded.packet_type, PacketType::KeyExchange);
+
+        let decoded_key_exchange =
+            KeyExchangePayload::from_bytes(&decoded.payload).expect("Failed to decode");
+        assert_eq!(decoded_key_exchange, key_exchange);
+
+        // Older peers send no nonce
+        let legacy = KeyExchangePayload {
+            nonce: None,
+            ..key_exchange
+        };
+        assert_eq!(legacy.to_bytes().len(), KeyExchangePayload::SIZE);
+        assert_eq!(
+            KeyExchangePayload::from_bytes(&legacy.to_bytes()),
+            Some(legacy)
+        );
+    }
+
     #[test]
     fn test_flags_roundtrip() {
         let flags = PacketFlags {
             encrypted: true,
             has_fec: true,
+            codec: CodecType::Opus,
+            redundant: true,
         };
         let encoded = flags.to_u16();
         let decoded = PacketFlags::from_u16(encoded);
 
         assert_eq!(decoded.encrypted, flags.encrypted);
         assert_eq!(decoded.has_fec, flags.has_fec);
+        assert_eq!(decoded.codec, CodecType::Opus);
+        assert!(decoded.redundant);
+        assert_eq!(PacketFlags::from_u16(0).codec, CodecType::Pcm);
+    }
+
+    #[test]
+    fn test_codec_offer_roundtrip() {
+        let offer = CodecOfferPayload {
+            supported: vec![CodecType::Pcm, CodecType::Opus],
+            preferred: CodecType::Opus,
+            ack: false,
+        };
+        let packet = Packet::codec_offer(0, &offer);
+        let decoded = Packet::from_bytes(&packet.to_bytes()).expect("Failed to decode packet");
+        assert_eq!(decoded.packet_type, PacketType::CodecOffer);
+        assert_eq!(CodecOfferPayload::from_bytes(&decoded.payload), Some(offer));
+    }
+
+    #[test]
+    fn test_hello_roundtrip() {
+        let hello = HelloPayload {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            capabilities: Capabilities {
+                codecs: vec![CodecType::Pcm, CodecType::Opus],
+                fec: true,
+                reed_solomon: true,
+                redundancy: true,
+                path_validation: true,
+                encryption: true,
+                requires_encryption: false,
+                max_streams: 4,
+                max_frame_size: 5760,
+            },
+            ack: true,
+        };
+        let packet = Packet::hello(3, &hello);
+        let decoded = Packet::from_bytes(&packet.to_bytes()).expect("Failed to decode packet");
+        assert_eq!(decoded.packet_type, PacketType::Hello);
+        assert_eq!(HelloPayload::from_bytes(&decoded.payload), Some(hello));
+    }
+
+    #[test]
+    fn test_hello_of_any_version_is_accepted() {
+        let hello = HelloPayload {
+            min_version: 7,
+            max_version: 9,
+            capabilities: Capabilities {
+                codecs: vec![CodecType::Pcm],
+                fec: false,
+                reed_solomon: false,
+                redundancy: false,
+                path_validation: false,
+                encryption: false,
+                requires_encryption: false,
+                max_streams: 0,
+                max_frame_size: 480,
+            },
+            ack: false,
+        };
+        let mut packet = Packet::hello(0, &hello);
+        packet.version = 9;
+        let decoded = Packet::from_bytes(&packet.to_bytes()).expect("Hello should decode");
+        assert_eq!(decoded.version, 9);
+
+        // Other packets of that version are not understood
+        let mut audio = Packet::audio(0, 0, vec![1, 2]);
+        audio.version = 9;
+        assert!(Packet::from_bytes(&audio.to_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_path_validation_roundtrip() {
+        let challenge = PathValidationPayload {
+            cookie: 0x0123_4567_89AB_CDEF,
+        };
+        for packet in [
+            Packet::path_challenge(5, &challenge),
+            Packet::path_response(6, &challenge),
+        ] {
+            let decoded = Packet::from_bytes(&packet.to_bytes()).expect("Failed to decode packet");
+            assert_eq!(decoded.packet_type, packet.packet_type);
+            assert_eq!(
+                PathValidationPayload::from_bytes(&decoded.payload),
+                Some(challenge)
+            );
+        }
+        assert_eq!(PathValidationPayload::from_bytes(&[1; 7]), None);
+    }
+
+    #[test]
+    fn test_receiver_report_roundtrip() {
+        let report = ReceiverReport {
+            highest_sequence: u32::MAX - 3,
+            cumulative_lost: 17,
+            fraction_lost: 64,
+            jitter_us: 1_250,
+            jitter_buffer_delay_us: Some(8_000),
+        };
+        let packet = Packet::receiver_report(0, &report);
+        let decoded = Packet::from_bytes(&packet.to_bytes()).expect("Failed to decode packet");
+        assert_eq!(decoded.packet_type, PacketType::ReceiverReport);
+        assert_eq!(ReceiverReport::from_bytes(&decoded.payload), Some(report));
+
+        let without_buffer = ReceiverReport {
+            jitter_buffer_delay_us: None,
+            ..report
+        };
+        let bytes = without_buffer.to_bytes();
+        assert_eq!(bytes.len(), ReceiverReport::SIZE);
+        assert_eq!(ReceiverReport::from_bytes(&bytes), Some(without_buffer));
+        assert_eq!(ReceiverReport::from_bytes(&bytes[..16]), None);
+    }
+
+    #[test]
+    fn test_relay_message_roundtrip() {
+        let messages = [
+            RelayMessage::Allocate,
+            RelayMessage::AllocateOk {
+                relayed_addr: "203.0.113.10:40000".parse().unwrap(),
+            },
+            RelayMessage::Refresh,
+            RelayMessage::Send {
+                peer: "[2001:db8::1]:5000".parse().unwrap(),
+                data: vec![1, 2, 3],
+            },
+            RelayMessage::Data {
+                peer: "192.168.1.2:5000".parse().unwrap(),
+                data: Packet::audio(7, 480, vec![9; 16]).to_bytes(),
+            },
+        ];
+        for message in messages {
+            let packet = Packet::relay(0, &message);
+            let restored = Packet::from_bytes(&packet.to_bytes()).unwrap();
+            assert_eq!(restored.packet_type, PacketType::Relay);
+            assert_eq!(RelayMessage::from_bytes(&restored.payload), Some(message));
+        }
+
+        assert_eq!(RelayMessage::from_bytes(&[]), None);
+        assert_eq!(RelayMessage::from_bytes(&[3, 4, 127, 0]), None);
+    }
+
+    #[test]
+    fn test_control_payload_roundtrip() {
+        let messages = [
+            ControlMessage::MuteState { muted: true },
+            ControlMessage::StreamConfig {
+                codec: CodecType::Opus,
+                sample_rate: 48000,
+                frame_size: 128,
+                channels: 2,
+            },
+            ControlMessage::Metronome(MetronomeSync {
+                epoch: 2,
+                owner_token: 99,
+                running: true,
+                bpm: 120,
+                beats_per_measure: 4,
+                beat_value: 4,
+                current_beat: 1,
+                measure: 8,
+                sample_position: 4800,
+                next: None,
+            }),
+            ControlMessage::Custom {
+                kind: 7,
+                data: vec![1, 2, 3],
+            },
+        ];
+        for message in messages {
+            let payload = ControlPayload::Message {
+                sequence: 9,
+                message: Some(message),
+            };
+            assert_eq!(
+                ControlPayload::from_bytes(&payload.to_bytes()),
+                Some(payload)
+            );
+        }
+        let ack = ControlPayload::Ack { sequence: 9 };
+        assert_eq!(ControlPayload::from_bytes(&ack.to_bytes()), Some(ack));
+
+        // Unknown message types still parse as a message to acknowledge
+        assert_eq!(
+            ControlPayload::from_bytes(&[0, 0, 0, 0, 9, 42, 1]),
+            Some(ControlPayload::Message {
+                sequence: 9,
+                message: None
+            })
+        );
+        assert_eq!(ControlPayload::from_bytes(&[9, 0, 0, 0, 0]), None);
+        assert_eq!(ControlPayload::from_bytes(&[1, 0, 0]), None);
+
+        let packet = Packet::control(5, 3, &ControlPayload::Ack { sequence: 3 });
+        let parsed = Packet::from_bytes(&packet.to_bytes()).unwrap();
+        assert_eq!(parsed.packet_type, PacketType::Control);
+        assert_eq!((parsed.sequence, parsed.timestamp), (5, 3));
     }
 
     #[test]
@@ -477,6 +1671,73 @@ mod tests {
         assert_eq!(bytes.len(), HEADER_SIZE);
     }
 
+    #[test]
+    fn test_stream_extension_roundtrip() {
+        let packet = Packet::audio(3, 384, vec![9, 8, 7]).with_stream(2, 2);
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes.len(), HEADER_SIZE + STREAM_EXTENSION_SIZE + 3);
+
+        let decoded = Packet::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            decoded.stream,
+            Some(StreamHeader {
+                stream_id: 2,
+                channels: 2
+            })
+        );
+        assert_eq!(decoded.stream_id(), 2);
+        assert_eq!(decoded.payload, vec![9, 8, 7]);
+
+        // The main stream is sent without an extension
+        let main = Packet::audio(3, 384, vec![]).with_stream(MAIN_STREAM_ID, 2);
+        assert!(main.stream.is_none());
+        assert_eq!(main.to_bytes().len(), HEADER_SIZE);
+
+        // The extension flag promises two more bytes
+        assert!(Packet::from_bytes(&bytes[..HEADER_SIZE + 1]).is_none());
+    }
+
+    #[test]
+    fn test_redundancy_payload_roundtrip() {
+        let payload = RedundancyPayload {
+            frames: vec![
+                RedundantFrame {
+                    distance: 2,
+                    timestamp_offset: 240,
+                    encoding: RedundantEncoding::Pcm16,
+                    data: vec![1, 2, 3, 4],
+                },
+                RedundantFrame {
+                    distance: 1,
+                    timestamp_offset: 120,
+                    encoding: RedundantEncoding::Opus,
+                    data: vec![5],
+                },
+            ],
+            primary: vec![6, 7, 8],
+        };
+        let bytes = payload.to_bytes();
+        assert_eq!(
+            bytes.len(),
+            1 + 2 * RedundancyPayload::FRAME_HEADER_SIZE + 5 + 3
+        );
+        assert_eq!(RedundancyPayload::from_bytes(&bytes), Some(payload));
+
+        // Without previous frames only the count byte is added
+        let empty = RedundancyPayload {
+            frames: Vec::new(),
+            primary: vec![9],
+        };
+        assert_eq!(empty.to_bytes(), vec![0, 9]);
+
+        // Truncated frame data and unknown encodings are rejected
+        assert!(RedundancyPayload::from_bytes(&bytes[..10]).is_none());
+        let mut unknown = bytes.clone();
+        unknown[2] = 7;
+        assert!(RedundancyPayload::from_bytes(&unknown).is_none());
+        assert!(RedundancyPayload::from_bytes(&[]).is_none());
+    }
+
     #[test]
     fn test_invalid_packet_too_short() {
         let data = vec![0u8; HEADER_SIZE - 1];
@@ -487,6 +1748,7 @@ mod tests {
     fn test_invalid_protocol_version() {
         let mut data = vec![0u8; HEADER_SIZE];
         data[0] = 99; // Invalid version
+        data[1] = PacketType::Audio as u8;
         assert!(Packet::from_bytes(&data).is_none());
     }
 }
@@ -0,0 +1,85 @@
+//! Peer clock and session timeline tests
+//!
+//! Both sessions run in one process and share its clock, so the true offset
+//! between them is zero.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use jamjam::network::{local_clock_us, PeerInfo, Session, SessionConfig};
+use uuid::Uuid;
+
+fn peer_info(id: Uuid) -> PeerInfo {
+    PeerInfo {
+        id,
+        name: "peer".to_string(),
+        candidates: vec![],
+        public_addr: None,
+        local_addr: None,
+        streams: vec![],
+        public_key: None,
+    }
+}
+
+fn loopback(session: &Session) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], session.local_addr().port()))
+}
+
+/// Test: Peer clocks and sample timestamps land on one timeline
+/// Given two sessions connected over loopback
+/// When latency pings have been exchanged
+/// Then the estimated clock offset is within the measured round trip
+/// When one session sends audio
+/// Then the other maps its sample timestamps to when they were sent
+#[tokio::test]
+async fn test_peer_timeline_on_loopback() {
+    let mut alice = Session::new(SessionConfig::default()).await.unwrap();
+    let mut bob = Session::new(SessionConfig::default()).await.unwrap();
+    alice.start();
+    bob.start();
+
+    let alice_id = Uuid::new_v4();
+    let bob_id = Uuid::new_v4();
+    alice
+        .add_peer(peer_info(bob_id), loopback(&bob))
+        .await
+        .unwrap();
+    bob.add_peer(peer_info(alice_id), loopback(&alice))
+        .await
+        .unwrap();
+
+    let clock = tokio::time::timeout(Duration::from_secs(3), async {
+        loop {
+            if let Some(clock) = bob.peer_clock(alice_id).await {
+                return clock;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("No clock estimate");
+    assert!(clock.offset_us.unsigned_abs() <= clock.delay_us / 2 + 1);
+    assert_eq!(bob.peer_stats(alice_id).await.unwrap().clock, Some(clock));
+
+    // 48 kHz: 4800 samples take 100 ms
+    let sent_at = local_clock_us();
+    alice.broadcast_audio(&[0.1; 480], 48_000).await.unwrap();
+    let start = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            if let Some(at) = bob.peer_timeline_us(alice_id, 48_000).await {
+                return at;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("Audio never arrived");
+
+    let error = start as i64 - sent_at as i64;
+    assert!(error.abs() < 20_000, "mapped {} µs off", error);
+    assert_eq!(
+        bob.peer_timeline_us(alice_id, 52_800).await,
+        Some(start + 100_000)
+    );
+    assert_eq!(bob.peer_timeline_us(Uuid::new_v4(), 0).await, None);
+}
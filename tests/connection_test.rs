@@ -45,6 +45,7 @@ async fn test_custom_session_config() {
         local_port: 0,
         max_peers: 5,
         enable_mixing: true,
+        ..Default::default()
     };
 
     let session = Session::new(config)
@@ -116,6 +117,7 @@ async fn test_session_recreate_same_port() {
         local_port: port,
         max_peers: 10,
         enable_mixing: true,
+        ..Default::default()
     };
 
     let session2 = Session::new(config2).await;
@@ -146,6 +148,7 @@ async fn test_session_repeated_recreate() {
             local_port: port,
             max_peers: 10,
             enable_mixing: true,
+            ..Default::default()
         };
 
         let session = Session::new(config).await;
@@ -0,0 +1,88 @@
+//! Control channel tests
+//!
+//! Control messages travel on the media socket next to audio.
+
+use std::time::Duration;
+
+use jamjam::audio::CodecType;
+use jamjam::network::{Connection, EncryptionMode};
+use jamjam::protocol::ControlMessage;
+
+/// Test: Control messages arrive once and in order
+/// Given two peers connected with encryption
+/// When A sends several control messages
+/// Then B receives each of them once, in the order they were sent
+/// And A sees every message acknowledged
+#[tokio::test]
+async fn test_control_messages_in_order() {
+    let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+    let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+    a.set_encryption_mode(EncryptionMode::Required);
+    b.set_encryption_mode(EncryptionMode::Required);
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    b.set_control_callback(move |message| {
+        let _ = tx.send(message);
+    });
+
+    let (addr_a, addr_b) = (a.local_addr(), b.local_addr());
+    let (result_a, result_b) = tokio::join!(a.connect(addr_b), b.connect(addr_a));
+    result_a.unwrap();
+    result_b.unwrap();
+    a.wait_for_encryption(Duration::from_secs(3)).await.unwrap();
+    b.wait_for_encryption(Duration::from_secs(3)).await.unwrap();
+
+    let messages = vec![
+        ControlMessage::MuteState { muted: true },
+        ControlMessage::StreamConfig {
+            codec: CodecType::Pcm,
+            sample_rate: 48000,
+            frame_size: 128,
+            channels: 1,
+        },
+        ControlMessage::Custom {
+            kind: 1,
+            data: b"tempo".to_vec(),
+        },
+    ];
+    for message in &messages {
+        a.send_control(message.clone()).await.unwrap();
+    }
+
+    let mut received = Vec::new();
+    while received.len() < messages.len() {
+        let message = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .expect("Timed out waiting for control messages")
+            .unwrap();
+        received.push(message);
+    }
+    assert_eq!(received, messages);
+
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while a.control_stats().in_flight > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Control messages were not acknowledged");
+    assert_eq!(a.control_stats().messages_sent, 3);
+    assert_eq!(b.control_stats().messages_delivered, 3);
+
+    // Nothing is delivered twice
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(rx.try_recv().is_err());
+}
+
+/// Test: Control messages need a connection
+/// Given a connection that is not connected
+/// When a control message is sent
+/// Then it fails with NotConnected
+#[tokio::test]
+async fn test_control_requires_connection() {
+    let conn = Connection::new("127.0.0.1:0").await.unwrap();
+    assert!(conn
+        .send_control(ControlMessage::MuteState { muted: false })
+        .await
+        .is_err());
+}
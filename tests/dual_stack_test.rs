@@ -1,13 +1,14 @@
 //! Dual-stack IPv4/IPv6 connectivity tests
 //!
 //! Tests for the Happy Eyeballs-style connection establishment
-//! with multiple address candidates.
+//! with multiple address candidates, and for ICE connectivity checks.
 
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
 use jamjam::network::{
     candidates_to_addrs, gather_candidates, AddressCandidate, CandidateType, Connection,
-    NetworkError, PeerInfo,
+    ConnectionState, IceConfig, NetworkError, PeerInfo,
 };
 use uuid::Uuid;
 
@@ -181,6 +182,8 @@ fn test_peer_info_with_candidates_roundtrip() {
         ],
         public_addr: Some("203.0.113.50:5000".parse().unwrap()),
         local_addr: Some("192.168.1.100:5000".parse().unwrap()),
+        streams: vec![],
+        public_key: None,
     };
 
     let json = serde_json::to_string(&original).expect("Should serialize");
@@ -275,3 +278,91 @@ async fn test_connect_with_candidates_prevents_double_connect() {
         other => panic!("Expected AlreadyConnected error, got: {:?}", other),
     }
 }
+
+/// Host candidate nobody listens on, ranked above every real candidate
+fn dead_candidate(port: u16) -> AddressCandidate {
+    AddressCandidate {
+        priority: u32::MAX,
+        ..AddressCandidate::host(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+}
+
+/// Test: Both peers run ICE checks at the same time
+/// Given each peer's best-ranked candidate is unreachable
+/// When both call connect_ice with each other's candidates
+/// Then both nominate the working pair well before the sequential timeout
+/// And audio flows over it
+#[tokio::test]
+async fn test_connect_ice_both_sides_skip_dead_candidates() {
+    let mut conn1 = Connection::new("127.0.0.1:0")
+        .await
+        .expect("Failed to create connection 1");
+    let mut conn2 = Connection::new("127.0.0.1:0")
+        .await
+        .expect("Failed to create connection 2");
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    conn2.set_audio_callback(move |_, timestamp| {
+        let _ = tx.send(timestamp);
+    });
+
+    let candidates1 = [AddressCandidate::host(conn1.local_addr())];
+    let candidates2 = [AddressCandidate::host(conn2.local_addr())];
+    let remote1 = [dead_candidate(59998), candidates2[0].clone()];
+    let remote2 = [dead_candidate(59999), candidates1[0].clone()];
+
+    let start = Instant::now();
+    let (result1, result2) = tokio::join!(
+        conn1.connect_ice(&candidates1, &remote1),
+        conn2.connect_ice(&candidates2, &remote2),
+    );
+    result1.expect("Peer 1 should connect");
+    result2.expect("Peer 2 should connect");
+    assert!(
+        start.elapsed() < Duration::from_secs(2),
+        "ICE took {:?}",
+        start.elapsed()
+    );
+
+    assert_eq!(conn1.remote_addr(), conn2.local_addr());
+    assert_eq!(conn2.remote_addr(), conn1.local_addr());
+
+    conn1.send_audio(&[0.5; 4], 480).await.unwrap();
+    let timestamp = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+        .await
+        .expect("Timed out waiting for audio")
+        .unwrap();
+    assert_eq!(timestamp, 480);
+}
+
+/// Test: ICE checks fail when nothing answers
+/// Given the only remote candidate is unreachable
+/// When connect_ice runs
+/// Then it fails and the connection is marked failed
+#[tokio::test]
+async fn test_connect_ice_fails_without_answer() {
+    let mut conn = Connection::new("127.0.0.1:0")
+        .await
+        .expect("Failed to create connection");
+    conn.set_ice_config(IceConfig {
+        max_attempts: 2,
+        ..Default::default()
+    });
+
+    let local = [AddressCandidate::host(conn.local_addr())];
+    let result = conn.connect_ice(&local, &[dead_candidate(59997)]).await;
+
+    assert!(matches!(result, Err(NetworkError::ConnectionFailed(_))));
+    assert_eq!(conn.state(), ConnectionState::Failed);
+}
+
+/// Test: connect_ice rejects an empty candidate list
+#[tokio::test]
+async fn test_connect_ice_empty_fails() {
+    let mut conn = Connection::new("127.0.0.1:0")
+        .await
+        .expect("Failed to create connection");
+
+    let result = conn.connect_ice(&[], &[]).await;
+    assert!(matches!(result, Err(NetworkError::NoCandidates)));
+}
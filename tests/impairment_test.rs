@@ -0,0 +1,121 @@
+//! Impairment proxy tests
+//!
+//! Two connections talk through an `ImpairmentProxy` so loss, reordering and
+//! duplication can be asserted on one machine.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use jamjam::network::{
+    Connection, Direction, FecConfig, ImpairmentConfig, ImpairmentProxy, LossModel,
+};
+
+/// Connect A and B through a proxy running `config` in both directions
+async fn connect_through_proxy(
+    config: ImpairmentConfig,
+) -> (Connection, Connection, Arc<ImpairmentProxy>) {
+    let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+    let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+
+    let proxy = Arc::new(
+        ImpairmentProxy::bind(
+            "127.0.0.1:0",
+            "127.0.0.1:0",
+            a.local_addr(),
+            b.local_addr(),
+            config,
+        )
+        .await
+        .unwrap(),
+    );
+    let runner = proxy.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    a.connect(proxy.a_side_addr()).await.unwrap();
+    b.connect(proxy.b_side_addr()).await.unwrap();
+    (a, b, proxy)
+}
+
+/// Send `count` audio frames from A, 2 ms apart
+async fn send_frames(a: &Connection, count: u32) {
+    for i in 0..count {
+        a.send_audio(&[0.1; 32], i * 32).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+}
+
+/// Test: FEC rebuilds audio lost on an impaired link
+/// Given A sends audio with FEC to B over a link losing 5% of packets
+/// When A sends 400 frames
+/// Then B counts lost audio packets
+/// And rebuilds some of them from FEC
+#[tokio::test]
+async fn test_fec_recovers_random_loss() {
+    let config = ImpairmentConfig {
+        loss: LossModel::Random { probability: 0.05 },
+        seed: 1,
+        ..Default::default()
+    };
+    let (mut a, b, proxy) = connect_through_proxy(config).await;
+    a.set_fec_config(FecConfig::with_group_size(4));
+
+    send_frames(&a, 400).await;
+
+    let stats = b.stats();
+    assert!(stats.packets_lost > 0);
+    assert!(stats.packets_recovered > 0);
+    assert!(stats.packets_recovered <= stats.packets_lost);
+    assert!(stats.packets_lost <= proxy.stats(Direction::AToB).packets_lost);
+}
+
+/// Test: Bursty loss is reported as bursts
+/// Given a Gilbert-Elliott link with a mean burst of 4 packets
+/// When A sends 400 frames without FEC
+/// Then B sees loss bursts longer than a single packet
+#[tokio::test]
+async fn test_gilbert_elliott_loss_arrives_in_bursts() {
+    let config = ImpairmentConfig {
+        loss: LossModel::GilbertElliott {
+            to_bad: 0.02,
+            to_good: 0.25,
+            loss_good: 0.0,
+            loss_bad: 1.0,
+        },
+        seed: 2,
+        ..Default::default()
+    };
+    let (mut a, b, _proxy) = connect_through_proxy(config).await;
+    a.set_fec_config(FecConfig::disabled());
+
+    send_frames(&a, 400).await;
+
+    let stats = b.stats();
+    assert!(stats.loss_bursts > 0);
+    assert!(stats.max_burst_length > 1);
+}
+
+/// Test: Reordering and duplication show up in receive stats
+/// Given a link that holds back 10% of packets and duplicates 5%
+/// When A sends 200 frames
+/// Then B counts reordered and duplicated audio packets
+/// And loses nothing
+#[tokio::test]
+async fn test_reorder_and_duplicates_are_counted() {
+    let config = ImpairmentConfig {
+        reorder_probability: 0.1,
+        reorder_delay: Duration::from_millis(10),
+        duplicate_probability: 0.05,
+        seed: 3,
+        ..Default::default()
+    };
+    let (mut a, b, _proxy) = connect_through_proxy(config).await;
+    a.set_fec_config(FecConfig::disabled());
+
+    send_frames(&a, 200).await;
+
+    let stats = b.stats();
+    assert!(stats.packets_reordered > 0);
+    assert!(stats.packets_duplicated > 0);
+    assert_eq!(stats.packets_lost, 0);
+}
@@ -2,7 +2,7 @@
 //!
 //! Tests for latency management and jitter buffer functionality.
 
-use jamjam::network::{FecDecoder, FecEncoder, FecPacket};
+use jamjam::network::{FecDecoder, FecEncoder, FecPacket, FecScheme};
 
 /// Test: Jitter buffer adapts automatically
 /// Given jitter buffer is set to "adaptive"
@@ -110,6 +110,42 @@ fn test_fec_multiple_packet_loss() {
     );
 }
 
+/// Test: Reed-Solomon FEC recovers a burst loss
+/// Given Reed-Solomon FEC with 2 parity packets per 4 audio packets
+/// When 2 consecutive packets of a group are lost
+/// Then both packets are recovered
+#[test]
+fn test_reed_solomon_burst_recovery() {
+    let mut encoder = FecEncoder::with_scheme(4, FecScheme::ReedSolomon { parity: 2 });
+    let mut decoder = FecDecoder::with_group_size(4);
+
+    let packets = vec![
+        vec![1, 2, 3, 4],
+        vec![5, 6, 7, 8],
+        vec![9, 10, 11, 12],
+        vec![13, 14, 15, 16],
+    ];
+
+    let mut fec_packets: Vec<FecPacket> = Vec::new();
+    for packet in &packets {
+        fec_packets = encoder.add_packet_all(packet);
+    }
+    assert_eq!(fec_packets.len(), 2);
+
+    // Lose packets 1 and 2 in a burst
+    decoder.add_packet(0, 0, &packets[0]);
+    decoder.add_packet(0, 3, &packets[3]);
+
+    let mut recovered = Vec::new();
+    for fec in fec_packets {
+        recovered.extend(decoder.add_fec_all(fec));
+    }
+    recovered.sort_by_key(|r| r.packet_index);
+    assert_eq!(recovered.len(), 2, "Both lost packets should be recovered");
+    assert_eq!(recovered[0].data, packets[1]);
+    assert_eq!(recovered[1].data, packets[2]);
+}
+
 /// Test: Latency in LAN environment
 /// Given two machines connected within the same LAN
 /// And using "ultra-low-latency" preset
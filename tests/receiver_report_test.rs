@@ -0,0 +1,51 @@
+//! Receiver report tests
+//!
+//! Senders learn how their audio arrives from the peer's receiver reports.
+
+use std::time::Duration;
+
+use jamjam::network::{Connection, EncryptionMode};
+
+/// Test: The sender sees how its audio arrives
+/// Given two peers connected with encryption
+/// When A sends audio to B
+/// Then A's stats carry B's receiver report on that audio
+/// And B, which sent no audio, has no report about its own stream
+#[tokio::test]
+async fn test_sender_learns_reception_from_report() {
+    let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+    let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+    a.set_encryption_mode(EncryptionMode::Required);
+    b.set_encryption_mode(EncryptionMode::Required);
+
+    let (addr_a, addr_b) = (a.local_addr(), b.local_addr());
+    let (result_a, result_b) = tokio::join!(a.connect(addr_b), b.connect(addr_a));
+    result_a.unwrap();
+    result_b.unwrap();
+    a.wait_for_encryption(Duration::from_secs(3)).await.unwrap();
+    b.wait_for_encryption(Duration::from_secs(3)).await.unwrap();
+
+    for i in 0..50u32 {
+        a.send_audio(&[0.1; 128], i * 128).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+
+    let reception = tokio::time::timeout(Duration::from_secs(4), async {
+        loop {
+            if let Some(reception) = a.stats().remote_reception {
+                if reception.highest_sequence == 49 {
+                    return reception;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("No receiver report for our audio");
+    assert_eq!(reception.packets_lost, 0);
+    assert_eq!(reception.fraction_lost, 0.0);
+    assert!(reception.jitter_ms >= 0.0);
+    assert_eq!(reception.jitter_buffer_delay_ms, None);
+
+    assert!(b.stats().remote_reception.is_none());
+}
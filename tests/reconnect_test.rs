@@ -0,0 +1,212 @@
+//! Automatic reconnection tests
+//!
+//! Network loss is simulated by silencing the peer or by tearing down a
+//! forwarder that stood between the two peers.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use jamjam::network::{
+    AddressCandidate, Connection, ConnectionState, EncryptionMode, ReconnectConfig, UdpTransport,
+};
+use jamjam::protocol::{Packet, PacketType};
+use parking_lot::Mutex;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+fn fast_reconnect() -> ReconnectConfig {
+    ReconnectConfig {
+        liveness_timeout: Duration::from_secs(1),
+        reconnect_timeout: Duration::from_secs(2),
+    }
+}
+
+/// Record state transitions of a connection
+fn watch_states(conn: &mut Connection) -> UnboundedReceiver<ConnectionState> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    conn.set_state_callback(move |state| {
+        let _ = tx.send(state);
+    });
+    rx
+}
+
+async fn wait_for_state(states: &mut UnboundedReceiver<ConnectionState>, wanted: ConnectionState) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while states.recv().await.unwrap() != wanted {}
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out waiting for {:?}", wanted));
+}
+
+/// Peer that answers keep-alives while `answering` is set
+async fn start_responder(answering: Arc<AtomicBool>) -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    let peer = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+    let addr = peer.local_addr();
+    let handle = tokio::spawn(async move {
+        while let Ok((packet, from)) = peer.recv_from().await {
+            if packet.packet_type == PacketType::KeepAlive && answering.load(Ordering::SeqCst) {
+                let _ = peer.send_to(&Packet::keep_alive(0), from).await;
+            }
+        }
+    });
+    (addr, handle)
+}
+
+/// Last address seen on one side of the forwarder
+type Learned = Arc<Mutex<Option<SocketAddr>>>;
+
+/// Relay datagrams arriving on `from` out of `to`, learning the sender
+async fn forward(from: Arc<UdpSocket>, to: Arc<UdpSocket>, sender: Learned, target: Learned) {
+    let mut buf = vec![0u8; 2048];
+    while let Ok((len, addr)) = from.recv_from(&mut buf).await {
+        *sender.lock() = Some(addr);
+        let target = *target.lock();
+        if let Some(target) = target {
+            let _ = to.send_to(&buf[..len], target).await;
+        }
+    }
+}
+
+/// Forward datagrams between two peers through a pair of sockets
+///
+/// Returns the address each peer should connect to.
+async fn start_forwarder() -> (SocketAddr, SocketAddr, tokio::task::JoinHandle<()>) {
+    let to_a = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let to_b = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let (addr_for_a, addr_for_b) = (to_a.local_addr().unwrap(), to_b.local_addr().unwrap());
+
+    let a: Learned = Arc::default();
+    let b: Learned = Arc::default();
+    let handle = tokio::spawn(async move {
+        tokio::join!(
+            forward(to_a.clone(), to_b.clone(), a.clone(), b.clone()),
+            forward(to_b, to_a, b, a),
+        );
+    });
+    (addr_for_a, addr_for_b, handle)
+}
+
+/// Test: Connection survives a short outage
+/// Given a connected peer
+/// When the peer goes silent
+/// Then the connection is reconnecting
+/// And audio can still be sent
+/// When the peer answers again
+/// Then the connection is connected again
+#[tokio::test]
+async fn test_reconnecting_on_silence_and_resume() {
+    let answering = Arc::new(AtomicBool::new(true));
+    let (peer_addr, responder) = start_responder(answering.clone()).await;
+
+    let mut conn = Connection::new("127.0.0.1:0").await.unwrap();
+    conn.set_reconnect_config(fast_reconnect());
+    let mut states = watch_states(&mut conn);
+    conn.connect(peer_addr).await.unwrap();
+    wait_for_state(&mut states, ConnectionState::Connected).await;
+
+    answering.store(false, Ordering::SeqCst);
+    wait_for_state(&mut states, ConnectionState::Reconnecting).await;
+    assert!(!conn.is_connected());
+    conn.send_audio(&[0.0; 4], 0).await.unwrap();
+
+    answering.store(true, Ordering::SeqCst);
+    wait_for_state(&mut states, ConnectionState::Connected).await;
+    assert!(conn.is_connected());
+
+    responder.abort();
+}
+
+/// Test: Reconnecting gives up after the reconnect timeout
+/// Given a connected peer that goes silent for good
+/// When the reconnect timeout passes
+/// Then the connection has failed with an error
+#[tokio::test]
+async fn test_reconnect_times_out() {
+    let answering = Arc::new(AtomicBool::new(true));
+    let (peer_addr, responder) = start_responder(answering.clone()).await;
+
+    let mut conn = Connection::new("127.0.0.1:0").await.unwrap();
+    conn.set_reconnect_config(fast_reconnect());
+    let mut states = watch_states(&mut conn);
+    conn.connect(peer_addr).await.unwrap();
+
+    answering.store(false, Ordering::SeqCst);
+    wait_for_state(&mut states, ConnectionState::Reconnecting).await;
+    wait_for_state(&mut states, ConnectionState::Failed).await;
+    assert_eq!(conn.last_error().as_deref(), Some("Reconnect timed out"));
+    assert!(conn.send_audio(&[0.0; 4], 0).await.is_err());
+
+    responder.abort();
+}
+
+/// Test: ICE restart moves a live stream to a new path
+/// Given two peers connected through a forwarder with encryption
+/// When the forwarder disappears
+/// Then both peers are reconnecting
+/// When both restart ICE with their candidates
+/// Then they reach each other directly
+/// And the encrypted stream continues without a new key exchange
+#[tokio::test]
+async fn test_restart_ice_resumes_stream() {
+    let (addr_for_a, addr_for_b, forwarder) = start_forwarder().await;
+
+    let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+    let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+    for conn in [&mut a, &mut b] {
+        conn.set_encryption_mode(EncryptionMode::Required);
+        conn.set_reconnect_config(ReconnectConfig {
+            reconnect_timeout: Duration::from_secs(30),
+            ..fast_reconnect()
+        });
+    }
+    let mut states_a = watch_states(&mut a);
+    let mut states_b = watch_states(&mut b);
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    b.set_audio_callback(move |_, timestamp| {
+        let _ = tx.send(timestamp);
+    });
+
+    // B first so the forwarder learns where B is
+    b.connect(addr_for_b).await.unwrap();
+    a.connect(addr_for_a).await.unwrap();
+    a.wait_for_encryption(Duration::from_secs(5)).await.unwrap();
+    b.wait_for_encryption(Duration::from_secs(5)).await.unwrap();
+
+    a.send_audio(&[0.25; 4], 480).await.unwrap();
+    let timestamp = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+        .await
+        .expect("Timed out waiting for audio via the forwarder")
+        .unwrap();
+    assert_eq!(timestamp, 480);
+
+    // The path goes away
+    forwarder.abort();
+    wait_for_state(&mut states_a, ConnectionState::Reconnecting).await;
+    wait_for_state(&mut states_b, ConnectionState::Reconnecting).await;
+
+    let candidates_a = [AddressCandidate::host(a.local_addr())];
+    let candidates_b = [AddressCandidate::host(b.local_addr())];
+    let (result_a, result_b) = tokio::join!(
+        a.restart_ice(&candidates_a, &candidates_b),
+        b.restart_ice(&candidates_b, &candidates_a),
+    );
+    result_a.unwrap();
+    result_b.unwrap();
+    assert_eq!(a.remote_addr(), b.local_addr());
+    assert_eq!(b.remote_addr(), a.local_addr());
+
+    wait_for_state(&mut states_a, ConnectionState::Connected).await;
+    wait_for_state(&mut states_b, ConnectionState::Connected).await;
+    assert!(a.is_encrypted() && b.is_encrypted());
+
+    a.send_audio(&[0.5; 4], 960).await.unwrap();
+    let timestamp = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+        .await
+        .expect("Timed out waiting for audio after the restart")
+        .unwrap();
+    assert_eq!(timestamp, 960);
+    assert_eq!(a.stats().packets_sent, 2);
+}
@@ -0,0 +1,122 @@
+//! Relay fallback tests
+//!
+//! A local `RelayServer` stands in for the relay a peer falls back to when
+//! hole punching fails.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use jamjam::network::{Connection, EncryptionMode, RelayServer, UdpTransport};
+use jamjam::protocol::{Packet, PacketType};
+
+/// Address nobody listens on (a candidate that cannot be reached)
+const DEAD_CANDIDATE: &str = "127.0.0.1:9";
+
+async fn start_relay() -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    let relay = RelayServer::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind relay");
+    let addr = relay.local_addr();
+    let handle = tokio::spawn(async move {
+        let _ = relay.run().await;
+    });
+    (addr, handle)
+}
+
+/// Test: Peer reaches us through our relay candidate
+/// Given peer A holds a relay allocation
+/// And peer B can only reach A's relay candidate
+/// When both connect with their candidates
+/// Then B connects to A's relayed address
+/// And encrypted audio flows both ways through the relay
+#[tokio::test]
+async fn test_connect_via_relay_candidate() {
+    let (relay_addr, relay_handle) = start_relay().await;
+
+    let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+    let mut b = Connection::new("127.0.0.1:0").await.unwrap();
+    a.set_encryption_mode(EncryptionMode::Required);
+    b.set_encryption_mode(EncryptionMode::Required);
+
+    let relayed_addr = a.allocate_relay(relay_addr).await.unwrap();
+    assert_eq!(a.relayed_addr(), Some(relayed_addr));
+
+    let (tx_a, mut rx_a) = tokio::sync::mpsc::unbounded_channel();
+    a.set_audio_callback(move |data, timestamp| {
+        let _ = tx_a.send((data.to_vec(), timestamp));
+    });
+    let (tx_b, mut rx_b) = tokio::sync::mpsc::unbounded_channel();
+    b.set_audio_callback(move |data, timestamp| {
+        let _ = tx_b.send((data.to_vec(), timestamp));
+    });
+
+    let a_candidates = [DEAD_CANDIDATE.parse().unwrap(), b.local_addr()];
+    let b_candidates = [DEAD_CANDIDATE.parse().unwrap(), relayed_addr];
+    let (result_a, result_b) = tokio::join!(
+        a.connect_with_candidates(&a_candidates),
+        b.connect_with_candidates(&b_candidates),
+    );
+    result_a.unwrap();
+    result_b.unwrap();
+
+    assert!(a.is_relayed(), "A should answer B through the relay");
+    assert!(!b.is_relayed(), "B talks to the relayed address directly");
+    assert_eq!(b.remote_addr(), relayed_addr);
+
+    a.wait_for_encryption(Duration::from_secs(3)).await.unwrap();
+    b.wait_for_encryption(Duration::from_secs(3)).await.unwrap();
+
+    a.send_audio(&[0.25; 4], 480).await.unwrap();
+    let (_, timestamp) = tokio::time::timeout(Duration::from_secs(2), rx_b.recv())
+        .await
+        .expect("Timed out waiting for audio at B")
+        .unwrap();
+    assert_eq!(timestamp, 480);
+
+    b.send_audio(&[0.5; 4], 960).await.unwrap();
+    let (_, timestamp) = tokio::time::timeout(Duration::from_secs(2), rx_a.recv())
+        .await
+        .expect("Timed out waiting for audio at A")
+        .unwrap();
+    assert_eq!(timestamp, 960);
+
+    relay_handle.abort();
+}
+
+/// Test: Automatic fallback to our own relay
+/// Given peer A holds a relay allocation
+/// And peer B only accepts traffic from A's relayed address
+/// When A connects to B
+/// Then A's direct probes go unanswered
+/// And A connects to B through the relay
+#[tokio::test]
+async fn test_fallback_to_relay_when_direct_fails() {
+    let (relay_addr, relay_handle) = start_relay().await;
+
+    let mut a = Connection::new("127.0.0.1:0").await.unwrap();
+    let relayed_addr = a.allocate_relay(relay_addr).await.unwrap();
+
+    // B behaves like a peer behind a firewall that drops A's direct path
+    let b = Arc::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+    let b_addr = b.local_addr();
+    let responder = tokio::spawn({
+        let b = b.clone();
+        async move {
+            while let Ok((packet, from)) = b.recv_from().await {
+                if packet.packet_type == PacketType::KeepAlive && from == relayed_addr {
+                    let _ = b.send_to(&Packet::keep_alive(0), from).await;
+                }
+            }
+        }
+    });
+
+    a.connect_with_candidates(&[b_addr]).await.unwrap();
+
+    assert!(a.is_connected());
+    assert!(a.is_relayed(), "A should have fallen back to the relay");
+    assert_eq!(a.remote_addr(), b_addr);
+
+    responder.abort();
+    relay_handle.abort();
+}
@@ -7,7 +7,8 @@ use std::net::TcpListener;
 use std::time::Duration;
 
 use jamjam::network::{
-    generate_invite_code, is_invite_code_format, SignalingClient, SignalingMessage, SignalingServer,
+    generate_invite_code, is_invite_code_format, SignalingClient, SignalingMessage,
+    SignalingServer, SIGNALING_VERSION,
 };
 
 /// Find an available port for testing
@@ -69,6 +70,8 @@ async fn test_create_room() {
         room_name: "Test Room".to_string(),
         password: None,
         peer_name: "Host".to_string(),
+        require_encryption: false,
+        signaling_version: SIGNALING_VERSION,
     })
     .await
     .expect("Failed to send create room");
@@ -116,6 +119,8 @@ async fn test_join_room_via_invite_code() {
             room_name: "Test Room".to_string(),
             password: None,
             peer_name: "Host".to_string(),
+            require_encryption: false,
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .expect("Failed to send create room");
@@ -138,6 +143,7 @@ async fn test_join_room_via_invite_code() {
             room_id: invite_code.clone(), // Use invite code as room_id
             password: None,
             peer_name: "Guest".to_string(),
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .expect("Failed to send join room");
@@ -154,8 +160,10 @@ async fn test_join_room_via_invite_code() {
             room_id: _,
             peer_id,
             peers,
+            require_encryption,
         } => {
             assert!(!peer_id.is_nil(), "Peer ID should not be nil");
+            assert!(!require_encryption, "Room should not require encryption");
             assert_eq!(peers.len(), 1, "Should see host peer");
             assert_eq!(peers[0].name, "Host", "Host name should match");
         }
@@ -163,6 +171,86 @@ async fn test_join_room_via_invite_code() {
     }
 }
 
+/// Test: Room requiring encryption
+/// Given a room created with encryption required
+/// When another client lists and joins the room
+/// Then the requirement is reported in both responses
+#[tokio::test]
+async fn test_room_requires_encryption() {
+    let port = find_available_port();
+    let server_handle = start_test_server(port).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client1 = SignalingClient::new(&format!("ws://127.0.0.1:{}", port));
+    let mut conn1 = client1.connect().await.expect("Failed to connect client 1");
+
+    conn1
+        .send(SignalingMessage::CreateRoom {
+            room_name: "Private Room".to_string(),
+            password: None,
+            peer_name: "Host".to_string(),
+            require_encryption: true,
+            signaling_version: SIGNALING_VERSION,
+        })
+        .await
+        .expect("Failed to send create room");
+    let room_id = match conn1
+        .recv()
+        .await
+        .expect("Failed to receive create response")
+    {
+        SignalingMessage::RoomCreated { room_id, .. } => room_id,
+        other => panic!("Expected RoomCreated, got {:?}", other),
+    };
+
+    let client2 = SignalingClient::new(&format!("ws://127.0.0.1:{}", port));
+    let mut conn2 = client2.connect().await.expect("Failed to connect client 2");
+
+    conn2
+        .send(SignalingMessage::ListRooms)
+        .await
+        .expect("Failed to list rooms");
+    let list_response = conn2.recv().await.expect("Failed to receive room list");
+
+    conn2
+        .send(SignalingMessage::JoinRoom {
+            room_id,
+            password: None,
+            peer_name: "Guest".to_string(),
+            signaling_version: SIGNALING_VERSION,
+        })
+        .await
+        .expect("Failed to send join room");
+    let join_response = conn2.recv().await.expect("Failed to receive join response");
+
+    // Clean up
+    let _ = conn1.close().await;
+    let _ = conn2.close().await;
+    server_handle.abort();
+
+    match list_response {
+        SignalingMessage::RoomList { rooms } => {
+            assert_eq!(rooms.len(), 1, "Should have 1 room");
+            assert!(
+                rooms[0].requires_encryption,
+                "Room should require encryption"
+            );
+        }
+        other => panic!("Expected RoomList, got {:?}", other),
+    }
+    match join_response {
+        SignalingMessage::RoomJoined {
+            require_encryption, ..
+        } => {
+            assert!(
+                require_encryption,
+                "Join should report encryption requirement"
+            );
+        }
+        other => panic!("Expected RoomJoined, got {:?}", other),
+    }
+}
+
 /// Test: Full flow - create room, join, leave, disconnect
 /// Given two clients
 /// When they go through full session flow
@@ -182,6 +270,8 @@ async fn test_full_session_flow() {
             room_name: "Session Room".to_string(),
             password: None,
             peer_name: "Host".to_string(),
+            require_encryption: false,
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .expect("Failed to create room");
@@ -204,6 +294,7 @@ async fn test_full_session_flow() {
             room_id: invite_code,
             password: None,
             peer_name: "Guest".to_string(),
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .expect("Failed to join room");
@@ -288,6 +379,7 @@ async fn test_join_nonexistent_room() {
         room_id: "NONEXISTENT".to_string(),
         password: None,
         peer_name: "Guest".to_string(),
+        signaling_version: SIGNALING_VERSION,
     })
     .await
     .expect("Failed to send join room");
@@ -328,6 +420,8 @@ async fn test_join_room_wrong_password() {
             room_name: "Secure Room".to_string(),
             password: Some("secret123".to_string()),
             peer_name: "Host".to_string(),
+            require_encryption: false,
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .expect("Failed to create room");
@@ -350,6 +444,7 @@ async fn test_join_room_wrong_password() {
             room_id: invite_code,
             password: Some("wrongpassword".to_string()),
             peer_name: "Guest".to_string(),
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .expect("Failed to send join room");
@@ -391,6 +486,8 @@ async fn test_list_rooms() {
             room_name: "Room 1".to_string(),
             password: None,
             peer_name: "Host1".to_string(),
+            require_encryption: false,
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .unwrap();
@@ -404,6 +501,8 @@ async fn test_list_rooms() {
             room_name: "Room 2".to_string(),
             password: Some("secret".to_string()),
             peer_name: "Host2".to_string(),
+            require_encryption: false,
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .unwrap();
@@ -464,6 +563,8 @@ async fn test_room_cleanup_on_disconnect() {
             room_name: "Temp Room".to_string(),
             password: None,
             peer_name: "Host".to_string(),
+            require_encryption: false,
+            signaling_version: SIGNALING_VERSION,
         })
         .await
         .unwrap();
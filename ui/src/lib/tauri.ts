@@ -16,6 +16,8 @@ export interface RoomInfo {
   max_peers: number;
   has_password: boolean;
   invite_code: string;
+  /** Peers must encrypt all audio in this room */
+  requires_encryption: boolean;
 }
 
 /**
@@ -36,6 +38,8 @@ export interface JoinResult {
   peer_id: string;
   invite_code: string;
   peers: PeerInfo[];
+  /** Peers must encrypt all audio in this room */
+  require_encryption: boolean;
 }
 
 /**
@@ -57,7 +61,28 @@ export type SignalingEvent =
   | { type: "PeerJoined"; peer: PeerInfo }
   | { type: "PeerLeft"; peer_id: string }
   | { type: "PeerUpdated"; peer: PeerInfo }
-  | { type: "ChatMessageReceived"; message: ChatMessage };
+  | { type: "ChatMessageReceived"; message: ChatMessage }
+  | { type: "PeerConnectionState"; peer_id: string; state: ConnectionState }
+  | {
+      type: "PeerQualityChanged";
+      peer_id: string;
+      /** congestion, packet loss, jitter or recovery */
+      reason: string;
+      /** Old and new settings with the conditions behind the change */
+      description: string;
+    };
+
+/**
+ * State of the audio stream to a peer
+ */
+export type ConnectionState =
+  | "disconnected"
+  | "connecting"
+  | "gathering_candidates"
+  | "checking_connectivity"
+  | "connected"
+  | "reconnecting"
+  | "failed";
 
 /**
  * Connect to a signaling server
@@ -113,14 +138,33 @@ export async function signalingLeaveRoom(connId: number): Promise<void> {
  * @param connId Connection ID from signalingConnect
  * @param roomName Name for the new room
  * @param peerName Display name for this peer (room creator)
+ * @param requireEncryption Require end-to-end encryption for all peers. Default: false
  * @returns Join result with the new room info
  */
 export async function signalingCreateRoom(
   connId: number,
   roomName: string,
-  peerName: string
+  peerName: string,
+  requireEncryption?: boolean
 ): Promise<JoinResult> {
-  return invoke("signaling_create_room", { connId, roomName, peerName });
+  return invoke("signaling_create_room", {
+    connId,
+    roomName,
+    peerName,
+    requireEncryption: requireEncryption ?? false,
+  });
+}
+
+/**
+ * Publish the local streaming port to the room so peers can reach us
+ * @param connId Connection ID from signalingConnect
+ * @param port Local UDP port returned by streamingStart
+ */
+export async function signalingUpdatePeerInfo(
+  connId: number,
+  port: number
+): Promise<void> {
+  return invoke("signaling_update_peer_info", { connId, port });
 }
 
 /**
@@ -232,8 +276,14 @@ export interface NetworkStats {
   rtt_ms: number;
   /** Jitter in milliseconds */
   jitter_ms: number;
-  /** Packet loss percentage (0-100) */
+  /** Packet loss percentage over recent packets (0-100) */
   packet_loss_percent: number;
+  /** Packet loss percentage since connecting (0-100) */
+  total_packet_loss_percent: number;
+  /** Audio packets lost in transit */
+  packets_lost: number;
+  /** Longest run of consecutive lost packets */
+  max_burst_length: number;
   /** Connection uptime in seconds */
   uptime_seconds: number;
   /** Total packets sent */
@@ -244,6 +294,10 @@ export interface NetworkStats {
   bytes_sent: number;
   /** Total bytes received */
   bytes_received: number;
+  /** Whether audio is end-to-end encrypted */
+  encrypted: boolean;
+  /** Lost packets recovered by FEC */
+  packets_recovered: number;
 }
 
 /**
@@ -282,44 +336,97 @@ export interface AudioQuality {
   underrun_count: number;
 }
 
+/**
+ * Per-peer streaming status
+ */
+export interface PeerStreamStatus {
+  peer_id: string;
+  name: string;
+  /** Volume from 0 to 200 (100 = unity) */
+  volume: number;
+  /** Pan from -100 (full left) to 100 (full right) */
+  pan: number;
+  /** Whether the peer is muted in the mix */
+  muted: boolean;
+  /** Round-trip time in milliseconds */
+  rtt_ms: number;
+  /** Packet loss percentage over recent packets (0-100) */
+  packet_loss_percent: number;
+  /** Whether audio is end-to-end encrypted */
+  encrypted: boolean;
+  /** Stream state; "reconnecting" while the peer is being reached again */
+  state: ConnectionState;
+  /** Whether the peer muted their microphone */
+  remote_muted: boolean;
+  /** One-way network delay in ms (half the best round trip), null until measured */
+  one_way_ms: number | null;
+  /** Peer clock minus local clock in ms, null until measured */
+  clock_offset_ms: number | null;
+  /** Settings chosen by adaptive quality, null if it is off */
+  quality: string | null;
+}
+
 /**
  * Streaming status information
  */
+/**
+ * Shared metronome status
+ */
+export interface MetronomeStatus {
+  /** Whether the click is running */
+  running: boolean;
+  bpm: number;
+  beats_per_measure: number;
+  /** Current beat within the measure (0-indexed) */
+  current_beat: number;
+  /** Whether we set the tempo (otherwise we follow a peer) */
+  is_owner: boolean;
+}
+
 export interface StreamingStatus {
   is_active: boolean;
-  remote_addr: string | null;
+  /** Local UDP port peers reach us at */
+  local_port: number | null;
+  /** Peers in the session */
+  peers: PeerStreamStatus[];
   /** Whether microphone is muted */
   is_muted: boolean;
   /** Current input audio level (0-100) */
   input_level: number;
   /** Current output audio level (0-100, for master meter) */
   output_level: number;
-  /** Network statistics */
+  /** Network statistics (worst peer for RTT, jitter and loss; totals otherwise) */
   network: NetworkStats | null;
   /** Detailed latency breakdown */
   latency: DetailedLatency | null;
   /** Audio quality metrics */
   audio_quality: AudioQuality | null;
+  /** Shared metronome (null if not streaming) */
+  metronome: MetronomeStatus | null;
 }
 
 /**
- * Start audio streaming to a remote peer
- * @param remoteAddr Remote address in format "ip:port"
+ * Start audio streaming to every peer in the current room
  * @param inputDeviceId Optional input device ID
  * @param outputDeviceId Optional output device ID
  * @param bufferSize Buffer size in samples (32, 64, 128, or 256). Default: 64
+ * @param requireEncryption Refuse to stream without end-to-end encryption. Default: false
+ * @param adaptiveQuality Adapt bitrate, frame size, FEC and codec to the network. Default: false
+ * @returns Local UDP port, to publish with signalingUpdatePeerInfo
  */
 export async function streamingStart(
-  remoteAddr: string,
   inputDeviceId?: string,
   outputDeviceId?: string,
-  bufferSize?: number
-): Promise<void> {
+  bufferSize?: number,
+  requireEncryption?: boolean,
+  adaptiveQuality?: boolean
+): Promise<number> {
   return invoke("streaming_start", {
-    remoteAddr,
     inputDeviceId: inputDeviceId ?? null,
     outputDeviceId: outputDeviceId ?? null,
     bufferSize: bufferSize ?? 64,
+    requireEncryption: requireEncryption ?? false,
+    adaptiveQuality: adaptiveQuality ?? false,
   });
 }
 
@@ -383,19 +490,27 @@ export async function streamingGetInputLevel(): Promise<number> {
 }
 
 /**
- * Set peer (received audio) volume
+ * Set a peer's (received audio) volume
+ * @param peerId Peer ID
  * @param volume Volume from 0 to 200 (100 = unity gain, 200 = 2x)
  */
-export async function streamingSetPeerVolume(volume: number): Promise<void> {
-  return invoke("streaming_set_peer_volume", { volume: Math.round(volume) });
+export async function streamingSetPeerVolume(
+  peerId: string,
+  volume: number
+): Promise<void> {
+  return invoke("streaming_set_peer_volume", {
+    peerId,
+    volume: Math.round(volume),
+  });
 }
 
 /**
- * Get peer volume
+ * Get a peer's volume
+ * @param peerId Peer ID
  * @returns Volume from 0 to 200 (100 = unity)
  */
-export async function streamingGetPeerVolume(): Promise<number> {
-  return invoke("streaming_get_peer_volume");
+export async function streamingGetPeerVolume(peerId: string): Promise<number> {
+  return invoke("streaming_get_peer_volume", { peerId });
 }
 
 /**
@@ -415,19 +530,51 @@ export async function streamingGetMasterVolume(): Promise<number> {
 }
 
 /**
- * Set peer (received audio) pan
+ * Set a peer's (received audio) pan
+ * @param peerId Peer ID
  * @param pan Pan from -100 (full left) to 100 (full right), 0 = center
  */
-export async function streamingSetPeerPan(pan: number): Promise<void> {
-  return invoke("streaming_set_peer_pan", { pan: Math.round(pan) });
+export async function streamingSetPeerPan(
+  peerId: string,
+  pan: number
+): Promise<void> {
+  return invoke("streaming_set_peer_pan", { peerId, pan: Math.round(pan) });
 }
 
 /**
- * Get peer pan
+ * Get a peer's pan
+ * @param peerId Peer ID
  * @returns Pan from -100 to 100 (0 = center)
  */
-export async function streamingGetPeerPan(): Promise<number> {
-  return invoke("streaming_get_peer_pan");
+export async function streamingGetPeerPan(peerId: string): Promise<number> {
+  return invoke("streaming_get_peer_pan", { peerId });
+}
+
+/**
+ * Mute or unmute a peer in the mix
+ * @param peerId Peer ID
+ * @param muted Whether to mute the peer
+ */
+export async function streamingSetPeerMute(
+  peerId: string,
+  muted: boolean
+): Promise<void> {
+  return invoke("streaming_set_peer_mute", { peerId, muted });
+}
+
+/**
+ * Start, retempo or stop the metronome for everyone in the room
+ * A running metronome changes tempo on the next bar.
+ * @param enabled Whether the click should run
+ * @param bpm Tempo (20-300)
+ * @param beatsPerMeasure Beats per bar
+ */
+export async function streamingSetMetronome(
+  enabled: boolean,
+  bpm: number,
+  beatsPerMeasure: number
+): Promise<void> {
+  return invoke("streaming_set_metronome", { enabled, bpm, beatsPerMeasure });
 }
 
 // ============================================================================
@@ -20,6 +20,7 @@ import {
   signalingLeaveRoom,
   signalingCreateRoom,
   signalingPollEvents,
+  signalingUpdatePeerInfo,
   streamingStart,
   streamingStop,
   streamingStatus,
@@ -29,6 +30,7 @@ import {
   streamingSetPeerVolume,
   streamingSetMasterVolume,
   streamingSetPeerPan,
+  streamingSetPeerMute,
   configGetConnectionHistory,
   configAddConnectionHistory,
   configRemoveConnectionHistory,
@@ -51,7 +53,7 @@ type SessionState =
   | { status: "server_connected"; rooms: RoomInfo[] }
   | { status: "creating" }
   | { status: "joining"; code: string }
-  | { status: "connected"; roomCode: string; participants: string[] }
+  | { status: "connected"; roomCode: string; participants: PeerInfo[] }
   | { status: "error"; message: string };
 
 export interface MainScreenProps {
@@ -66,6 +68,8 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
   const [connectionId, setConnectionId] = useState<number | null>(null);
   const [peerName, setPeerName] = useState("User");
   const hasAutoConnected = useRef(false);
+  // Peers muted in the mixer (the toggle callback only carries the ID)
+  const mutedPeers = useRef(new Set<string>());
   const [roomName, setRoomName] = useState("");
   const [inviteCode, setInviteCode] = useState("");
   const [currentInviteCode, setCurrentInviteCode] = useState("");
@@ -220,25 +224,30 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
       try {
         const events = await signalingPollEvents(connectionId);
         for (const event of events) {
-          if (event.type === "PeerJoined") {
+          // Audio streams follow these events in the backend
+          if (event.type === "PeerJoined" || event.type === "PeerUpdated") {
+            const peer = event.peer;
             setSessionState((prev) => {
               if (prev.status !== "connected") return prev;
-              // Avoid duplicate
-              if (prev.participants.includes(event.peer.name)) return prev;
+              const known = prev.participants.some((p) => p.id === peer.id);
               return {
                 ...prev,
-                participants: [...prev.participants, event.peer.name],
+                participants: known
+                  ? prev.participants.map((p) => (p.id === peer.id ? peer : p))
+                  : [...prev.participants, peer],
               };
             });
           } else if (event.type === "PeerLeft") {
             setSessionState((prev) => {
               if (prev.status !== "connected") return prev;
-              // Note: we don't have peer name, but can filter by peer_id if needed
-              // For now, we'll rely on the participant list refresh
-              return prev;
+              return {
+                ...prev,
+                participants: prev.participants.filter((p) => p.id !== event.peer_id),
+              };
             });
           }
-          // ChatMessageReceived events are handled by ChatPanel's own polling
+          // ChatMessageReceived events are handled by ChatPanel's own polling;
+          // PeerConnectionState is also reflected in each peer's stream status
         }
       } catch (e) {
         console.error("Failed to poll signaling events:", e);
@@ -279,6 +288,27 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
     }
   };
 
+  // Start streaming to everyone in the room and tell the room where to reach us
+  const startStreaming = async (connId: number, requireEncryption: boolean) => {
+    try {
+      // Get currently selected devices and buffer size from settings
+      const [devices, bufferSize] = await Promise.all([
+        audioGetCurrentDevices(),
+        audioGetBufferSize(),
+      ]);
+      const port = await streamingStart(
+        devices.input_device_id ?? undefined,
+        devices.output_device_id ?? undefined,
+        bufferSize,
+        requireEncryption
+      );
+      await signalingUpdatePeerInfo(connId, port);
+      console.log("Streaming started on port:", port, "with devices:", devices, "bufferSize:", bufferSize);
+    } catch (streamErr) {
+      console.error("Failed to start streaming:", streamErr);
+    }
+  };
+
   // Handle room creation
   const handleCreateRoom = async () => {
     if (connectionId === null) return;
@@ -296,8 +326,10 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
       setSessionState({
         status: "connected",
         roomCode: result.room_id,
-        participants: result.peers.map((p) => p.name),
+        participants: result.peers,
       });
+
+      await startStreaming(connectionId, result.require_encryption);
     } catch (e) {
       setSessionState({
         status: "error",
@@ -325,7 +357,7 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
       setSessionState({
         status: "connected",
         roomCode: result.room_id,
-        participants: result.peers.map((p) => p.name),
+        participants: result.peers,
       });
 
       // Save to connection history
@@ -339,31 +371,8 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
         console.error("Failed to save to history:", historyErr);
       }
 
-      // Start streaming if there's a peer with an address (e.g., echobot)
-      const peerWithAddr = result.peers.find(
-        (p: PeerInfo) => p.public_addr || p.local_addr
-      );
-      if (peerWithAddr) {
-        const addr = peerWithAddr.public_addr || peerWithAddr.local_addr;
-        if (addr) {
-          try {
-            // Get currently selected devices and buffer size from settings
-            const [devices, bufferSize] = await Promise.all([
-              audioGetCurrentDevices(),
-              audioGetBufferSize(),
-            ]);
-            await streamingStart(
-              addr,
-              devices.input_device_id ?? undefined,
-              devices.output_device_id ?? undefined,
-              bufferSize
-            );
-            console.log("Streaming started to:", addr, "with devices:", devices, "bufferSize:", bufferSize);
-          } catch (streamErr) {
-            console.error("Failed to start streaming:", streamErr);
-          }
-        }
-      }
+      // Stream to every peer in the room (full mesh)
+      await startStreaming(connectionId, result.require_encryption);
     } catch (e) {
       setSessionState({
         status: "error",
@@ -386,6 +395,7 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
       }
 
       await signalingLeaveRoom(connectionId);
+      mutedPeers.current.clear();
       setCurrentInviteCode("");
       setInviteCode("");
       setMyPeerId(null);
@@ -432,11 +442,11 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
   }, []);
 
   // Handle peer volume change from mixer
-  const handlePeerVolumeChange = useCallback(async (_participantId: string, volume: number) => {
+  const handlePeerVolumeChange = useCallback(async (participantId: string, volume: number) => {
     try {
       // Convert 0-100 fader range to 0-200 backend range (100 = unity)
       const backendVolume = Math.round(volume * 2);
-      await streamingSetPeerVolume(backendVolume);
+      await streamingSetPeerVolume(participantId, backendVolume);
     } catch (e) {
       console.error("Failed to set peer volume:", e);
     }
@@ -454,15 +464,30 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
   }, []);
 
   // Handle peer pan change from mixer
-  const handlePeerPanChange = useCallback(async (_participantId: string, pan: number) => {
+  const handlePeerPanChange = useCallback(async (participantId: string, pan: number) => {
     try {
       // Pan is already -100 to 100, send directly to backend
-      await streamingSetPeerPan(pan);
+      await streamingSetPeerPan(participantId, pan);
     } catch (e) {
       console.error("Failed to set peer pan:", e);
     }
   }, []);
 
+  // Handle peer mute toggle from mixer
+  const handlePeerMuteToggle = useCallback(async (participantId: string) => {
+    const muted = !mutedPeers.current.has(participantId);
+    if (muted) {
+      mutedPeers.current.add(participantId);
+    } else {
+      mutedPeers.current.delete(participantId);
+    }
+    try {
+      await streamingSetPeerMute(participantId, muted);
+    } catch (e) {
+      console.error("Failed to set peer mute:", e);
+    }
+  }, []);
+
   // Render server connected state (room list)
   const renderServerConnectedState = () => {
     if (sessionState.status !== "server_connected") return null;
@@ -644,9 +669,9 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
               >
                 {peerName} ({t("mixer.channel.you")})
               </li>
-              {sessionState.participants.map((participant, index) => (
+              {sessionState.participants.map((participant) => (
                 <li
-                  key={index}
+                  key={participant.id}
                   style={{
                     padding: "var(--space-sm)",
                     backgroundColor: "var(--color-bg-tertiary)",
@@ -654,7 +679,7 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
                     marginBottom: "var(--space-xs)",
                   }}
                 >
-                  {participant}
+                  {participant.name}
                 </li>
               ))}
             </ul>
@@ -674,14 +699,15 @@ export function MainScreen({ onSettingsClick, settingsVersion }: MainScreenProps
           <MixerConsole
             inputLevel={inputLevel}
             isInputMuted={isMuted}
-            participants={sessionState.participants.map((name, index): Participant => ({
-              id: `peer-${index}`,
-              name: name,
+            participants={sessionState.participants.map((peer): Participant => ({
+              id: peer.id,
+              name: peer.name,
               level: outputLevel,
             }))}
             masterLevel={outputLevel}
             onInputMuteToggle={handleToggleMute}
             onParticipantVolumeChange={handlePeerVolumeChange}
+            onParticipantMuteToggle={handlePeerMuteToggle}
             onParticipantPanChange={handlePeerPanChange}
             onMasterVolumeChange={handleMasterVolumeChange}
           />
@@ -175,12 +175,12 @@ export function SettingsPanel({ onSettingsChange }: SettingsPanelProps) {
   const restartStreamingWithBufferSize = async (newBufferSize: number) => {
     try {
       const status = await streamingStatus();
-      if (status.is_active && status.remote_addr) {
+      if (status.is_active) {
         await streamingStop();
         // Small delay to let audio resources release
         await new Promise(resolve => setTimeout(resolve, 100));
+        // The session reuses its port, so peers keep reaching us
         await streamingStart(
-          status.remote_addr,
           selectedInputId ?? undefined,
           selectedOutputId ?? undefined,
           newBufferSize